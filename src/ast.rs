@@ -1,52 +1,55 @@
+pub(crate) mod arena;
 pub(crate) mod expression;
 pub(crate) mod meta;
 pub(crate) mod misc;
 pub(crate) mod node;
 pub(crate) mod statement;
+pub mod visit;
 
 pub use self::{
-    expression::{BinaryOp, BoolLit, Ident, NumLit, UnaryOp},
+    arena::{Ast, Kind, NodeKind, SideTable},
+    expression::{BinaryOp, BoolLit, Call, Field, Ident, Match, NumLit, Tuple, UnaryOp},
     meta::Meta,
-    misc::Param,
-    node::Node,
-    statement::{Assign, Block, Decl, DoWhileLoop, Func, IfStmt, Return, WhileLoop},
+    misc::{Annot, Arm, Param, Path, Pattern, Signature, TypeParam, Variant, Visibility},
+    node::{Node, NodeId},
+    statement::{
+        Assign, Block, Decl, DoWhileLoop, Enum, ExprStmt, Func, IfLet, IfStmt, Impl, Module,
+        Return, Struct, Trait, Use, WhileLoop,
+    },
 };
 
-#[derive(Clone)]
+#[derive(Debug, Clone)]
 pub enum Stmt {
-    Block(Block),
+    Block(Node<Block>),
     Decl(Decl),
     Assign(Assign),
     IfStmt(IfStmt),
+    IfLet(IfLet),
     WhileLoop(WhileLoop),
     DoWhileLoop(DoWhileLoop),
     Func(Func),
+    Enum(Enum),
+    Struct(Struct),
+    Trait(Trait),
+    Impl(Impl),
     Continue,
     Return(Return),
-}
-
-impl std::fmt::Debug for Stmt {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        // I also hate this implementation...
-        match self {
-            Stmt::Block(x) => write!(f, "{:#?}", x),
-            Stmt::Decl(x) => write!(f, "{:#?}", x),
-            Stmt::Assign(x) => write!(f, "{:#?}", x),
-            Stmt::IfStmt(x) => write!(f, "{:#?}", x),
-            Stmt::WhileLoop(x) => write!(f, "{:#?}", x),
-            Stmt::DoWhileLoop(x) => write!(f, "{:#?}", x),
-            Stmt::Func(x) => write!(f, "{:#?}", x),
-            Stmt::Continue => write!(f, "Continue"),
-            Stmt::Return(x) => write!(f, "{:#?}", x),
-        }
-    }
+    Module(Module),
+    Use(Use),
+    ExprStmt(ExprStmt),
 }
 
 #[derive(Debug, Clone)]
 pub enum Expr {
-    Ident(Ident),
+    Ident(Node<Ident>),
     NumLit(NumLit),
     BoolLit(BoolLit),
+    NoneLit,
     BinaryOp(BinaryOp),
     UnaryOp(UnaryOp),
+    Path(Node<Path>),
+    Call(Call),
+    Field(Field),
+    Tuple(Tuple),
+    Match(Match),
 }
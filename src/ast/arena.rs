@@ -0,0 +1,139 @@
+use std::ops::{Index, IndexMut};
+
+use crate::location::Span;
+
+use super::{
+    node::{Node, NodeId},
+    Annot, Arm, Block, Expr, Ident, Param, Path, Pattern, Signature, Stmt, TypeParam, Variant,
+};
+
+// Anything that can be allocated, the arena keeps them all in one list
+#[derive(Debug, Clone)]
+pub enum NodeKind {
+    Stmt(Stmt),
+    Expr(Expr),
+    Ident(Ident),
+    Block(Block),
+    Param(Param),
+    Path(Path),
+    Variant(Variant),
+    Pattern(Pattern),
+    Arm(Arm),
+    TypeParam(TypeParam),
+    Signature(Signature),
+    Annot(Annot),
+}
+
+pub trait Kind: Sized {
+    fn wrap(self) -> NodeKind;
+    fn get(node: &NodeKind) -> &Self;
+    fn get_mut(node: &mut NodeKind) -> &mut Self;
+}
+
+macro_rules! kinds {
+    ($($kind:ident),*) => {
+        $(
+            impl Kind for $kind {
+                fn wrap(self) -> NodeKind {
+                    NodeKind::$kind(self)
+                }
+
+                // A Node<T> can only come from allocating a T, so the kind always matches
+                fn get(node: &NodeKind) -> &Self {
+                    match node {
+                        NodeKind::$kind(x) => x,
+                        _ => unreachable!("expected a {} node", stringify!($kind)),
+                    }
+                }
+
+                fn get_mut(node: &mut NodeKind) -> &mut Self {
+                    match node {
+                        NodeKind::$kind(x) => x,
+                        _ => unreachable!("expected a {} node", stringify!($kind)),
+                    }
+                }
+            }
+        )*
+    };
+}
+
+kinds!(Stmt, Expr, Ident, Block, Param, Path, Variant, Pattern, Arm, TypeParam, Signature, Annot);
+
+// Every node of a file, children are always allocated before their parents
+#[derive(Debug, Clone, Default)]
+pub struct Ast {
+    nodes: Vec<NodeKind>,
+    spans: Vec<Span>,
+}
+
+impl Ast {
+    pub fn alloc<T: Kind>(&mut self, src: T, span: Span) -> Node<T> {
+        self.nodes.push(src.wrap());
+        self.spans.push(span);
+        Node::new(NodeId(self.nodes.len() as u32 - 1))
+    }
+
+    pub fn span(&self, id: impl Into<NodeId>) -> Span {
+        self.spans[id.into().0 as usize]
+    }
+
+    pub fn kind(&self, id: NodeId) -> &NodeKind {
+        &self.nodes[id.0 as usize]
+    }
+
+    pub fn ids(&self) -> impl Iterator<Item = NodeId> {
+        (0..self.nodes.len() as u32).map(NodeId)
+    }
+
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+}
+
+impl<T: Kind> Index<Node<T>> for Ast {
+    type Output = T;
+
+    fn index(&self, node: Node<T>) -> &T {
+        T::get(&self.nodes[node.id.0 as usize])
+    }
+}
+
+impl<T: Kind> IndexMut<Node<T>> for Ast {
+    fn index_mut(&mut self, node: Node<T>) -> &mut T {
+        T::get_mut(&mut self.nodes[node.id.0 as usize])
+    }
+}
+
+// Whatever a pass works out about nodes, without the nodes having to know about it
+#[derive(Debug, Clone)]
+pub struct SideTable<T> {
+    vals: Vec<Option<T>>,
+}
+
+impl<T> Default for SideTable<T> {
+    fn default() -> Self {
+        SideTable { vals: Vec::new() }
+    }
+}
+
+impl<T> SideTable<T> {
+    pub fn insert(&mut self, id: impl Into<NodeId>, val: T) {
+        let idx = id.into().0 as usize;
+        if idx >= self.vals.len() {
+            self.vals.resize_with(idx + 1, || None);
+        }
+        self.vals[idx] = Some(val);
+    }
+
+    pub fn get(&self, id: impl Into<NodeId>) -> Option<&T> {
+        self.vals.get(id.into().0 as usize).and_then(Option::as_ref)
+    }
+
+    pub fn contains(&self, id: impl Into<NodeId>) -> bool {
+        self.get(id).is_some()
+    }
+}
@@ -1,11 +1,10 @@
 use crate::tokens::Type;
 
-use super::{meta::Meta, node::Node, Expr};
+use super::{meta::Meta, misc::Arm, node::Node, Expr};
 
 #[derive(Debug, Clone)]
 pub struct Ident {
     pub name: String,
-    // TODO: Add ID
 }
 
 #[derive(Debug, Clone)]
@@ -30,3 +29,28 @@ pub struct UnaryOp {
     pub op: Meta<Type>,
     pub val: Node<Expr>,
 }
+
+#[derive(Debug, Clone)]
+pub struct Call {
+    pub callee: Node<Expr>,
+    pub args: Vec<Node<Expr>>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Tuple {
+    pub items: Vec<Node<Expr>>,
+}
+
+// A tuple field like pair.0, or pair?.0 which is none when pair is
+#[derive(Debug, Clone)]
+pub struct Field {
+    pub val: Node<Expr>,
+    pub name: Node<Ident>,
+    pub optional: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct Match {
+    pub val: Node<Expr>,
+    pub arms: Vec<Node<Arm>>,
+}
@@ -1,21 +1,15 @@
-use crate::{location::Location, types::DataType};
+use crate::location::Span;
 
+// Something small with a span that isn't worth a node of its own, like an operator
 #[derive(Clone)]
 pub struct Meta<T> {
     pub src: T,
-    pub start: Location,
-    pub end: Location,
-    pub typ: Option<DataType>,
+    pub span: Span,
 }
 
 impl<T> Meta<T> {
-    pub fn new(src: T, start: Location, end: Location) -> Meta<T> {
-        Meta {
-            src,
-            start,
-            end,
-            typ: None,
-        }
+    pub fn new(src: T, span: Span) -> Meta<T> {
+        Meta { src, span }
     }
 }
 
@@ -24,10 +18,6 @@ where
     T: std::fmt::Debug,
 {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(
-            f,
-            "({:#?}) {:#?}, {{ start: {}, end: {} }}",
-            self.typ, self.src, self.start, self.end
-        )
+        write!(f, "{:?} {{ span: {} }}", self.src, self.span)
     }
 }
@@ -1,7 +1,116 @@
-use super::{expression::Ident, node::Node};
+use std::{cell::RefCell, rc::Rc};
 
-#[derive(Debug, Clone)]
+use crate::scope::Scope;
+
+use super::{arena::Ast, expression::Ident, node::Node, Expr, Stmt};
+
+#[derive(Debug, Clone, Copy)]
 pub struct Param {
     pub name: Node<Ident>,
-    pub annot: Node<Ident>,
+    pub annot: Node<Annot>,
+}
+
+impl Param {
+    // A method's first parameter, which gets Self as its type without one written
+    pub fn is_self(&self, ast: &Ast) -> bool {
+        ast[self.name].name == "self"
+            && matches!(ast[self.annot], Annot::Named(typ) if ast[typ].name == "Self")
+    }
+}
+
+// A type as it's written, every part of it has its own span
+#[derive(Debug, Clone)]
+pub enum Annot {
+    // A built in type, an enum, a struct without type parameters or a type parameter
+    Named(Node<Ident>),
+    Tuple(Vec<Node<Annot>>),
+    Optional(Node<Annot>),
+    // A struct with its types, like Pair<int32, bool>
+    Generic(Node<Ident>, Vec<Node<Annot>>),
+    // Any type with the trait
+    Dyn(Node<Ident>),
+}
+
+impl Annot {
+    // Written the way the checker shows types, like (int32, Pair<Light, bool>)?
+    pub fn display(&self, ast: &Ast) -> String {
+        let list = |items: &[Node<Annot>]| {
+            let items: Vec<String> = items.iter().map(|x| ast[*x].display(ast)).collect();
+            items.join(", ")
+        };
+        match self {
+            Annot::Named(name) => ast[*name].name.clone(),
+            Annot::Tuple(items) if items.len() == 1 => format!("({},)", list(items)),
+            Annot::Tuple(items) => format!("({})", list(items)),
+            Annot::Optional(inner) => format!("{}?", ast[*inner].display(ast)),
+            Annot::Generic(name, args) => format!("{}<{}>", ast[*name].name, list(args)),
+            Annot::Dyn(name) => format!("dyn {}", ast[*name].name),
+        }
+    }
+}
+
+// T or T: Ord + Num in func max<T: Ord>, the bounds say which operators a T has
+#[derive(Debug, Clone)]
+pub struct TypeParam {
+    pub name: Node<Ident>,
+    pub bounds: Vec<Node<Ident>>,
+}
+
+// A method in a trait, which every impl of it has to have. Its first parameter is self
+#[derive(Debug, Clone)]
+pub struct Signature {
+    pub name: Node<Ident>,
+    pub params: Vec<Node<Param>>,
+    pub ret: Option<Node<Annot>>,
+}
+
+// One of an enum's variants, with the types of its fields
+#[derive(Debug, Clone)]
+pub struct Variant {
+    pub name: Node<Ident>,
+    pub fields: Vec<Node<Annot>>,
+}
+
+#[derive(Debug, Clone)]
+pub enum Pattern {
+    Wildcard,
+    Binding(Node<Ident>),
+    // A number, a negative number or a bool
+    Literal(Node<Expr>),
+    Tuple(Vec<Node<Pattern>>),
+    // Variants without fields have no parentheses, like Color.Red
+    Variant(Node<Path>, Vec<Node<Pattern>>),
+}
+
+// The body is either a block or an expression statement, only the second one has a value
+#[derive(Debug, Clone)]
+pub struct Arm {
+    pub pat: Node<Pattern>,
+    pub guard: Option<Node<Expr>>,
+    pub body: Node<Stmt>,
+    // Holds the bindings, empty until the resolver gets to it
+    pub scope: Rc<RefCell<Scope>>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Path {
+    pub segments: Vec<Node<Ident>>,
+}
+
+impl Path {
+    // The segments live in the arena, so there's no Display
+    pub fn display(&self, ast: &Ast) -> String {
+        let names: Vec<&str> = self
+            .segments
+            .iter()
+            .map(|x| ast[*x].name.as_str())
+            .collect();
+        names.join(".")
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Visibility {
+    Public,
+    Private,
 }
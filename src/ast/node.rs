@@ -1,35 +1,61 @@
-use crate::location::Location;
+use std::{fmt, marker::PhantomData};
 
-use super::meta::Meta;
+// Unique within the file it was parsed from, the side tables are keyed by it
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct NodeId(pub u32);
 
-#[derive(Clone)]
-pub struct Node<T>(pub Box<Meta<T>>);
+impl fmt::Display for NodeId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "#{}", self.0)
+    }
+}
+
+// A handle to a node of a known kind, the node itself lives in the Ast
+pub struct Node<T> {
+    pub id: NodeId,
+    kind: PhantomData<fn() -> T>,
+}
 
 impl<T> Node<T> {
-    pub fn new(src: T, start: Location, end: Location) -> Node<T> {
-        Node(Box::new(Meta::new(src, start, end)))
+    pub(crate) fn new(id: NodeId) -> Node<T> {
+        Node {
+            id,
+            kind: PhantomData,
+        }
+    }
+}
+
+// Derives would want T to be Copy as well
+impl<T> Clone for Node<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Copy for Node<T> {}
+
+impl<T> PartialEq for Node<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.id == other.id
     }
 }
 
-impl<T> std::ops::Deref for Node<T> {
-    type Target = Box<Meta<T>>;
+impl<T> Eq for Node<T> {}
 
-    fn deref(&self) -> &Self::Target {
-        &self.0
+impl<T> std::hash::Hash for Node<T> {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        self.id.hash(state);
     }
 }
 
-impl<T> std::ops::DerefMut for Node<T> {
-    fn deref_mut(&mut self) -> &mut Self::Target {
-        &mut self.0
+impl<T> From<Node<T>> for NodeId {
+    fn from(node: Node<T>) -> NodeId {
+        node.id
     }
 }
 
-impl<T> std::fmt::Debug for Node<T>
-where
-    T: std::fmt::Debug,
-{
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{:#?}", self.0)
+impl<T> fmt::Debug for Node<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.id)
     }
 }
@@ -2,18 +2,26 @@ use std::{cell::RefCell, rc::Rc};
 
 use crate::{scope::Scope, tokens::Type};
 
-use super::{expression::Ident, meta::Meta, misc::Param, node::Node, Expr, Stmt};
+use super::{
+    expression::Ident,
+    meta::Meta,
+    misc::{Annot, Param, Path, Signature, TypeParam, Variant, Visibility},
+    node::Node,
+    Expr, Stmt,
+};
 
 #[derive(Debug, Clone)]
 pub struct Block {
     pub stmts: Vec<Node<Stmt>>,
+    // Empty until the resolver gets to it
     pub scope: Rc<RefCell<Scope>>,
 }
 
 #[derive(Debug, Clone)]
 pub struct Decl {
+    pub vis: Visibility,
     pub name: Node<Ident>,
-    pub annot: Option<Node<Ident>>,
+    pub annot: Option<Node<Annot>>,
     pub val: Node<Expr>,
 }
 
@@ -30,6 +38,14 @@ pub struct IfStmt {
     pub body: Node<Block>,
 }
 
+// The name only exists in the body, which only runs when the value isn't none
+#[derive(Debug, Clone)]
+pub struct IfLet {
+    pub name: Node<Ident>,
+    pub val: Node<Expr>,
+    pub body: Node<Block>,
+}
+
 #[derive(Debug, Clone)]
 pub struct WhileLoop {
     pub cond: Node<Expr>,
@@ -44,14 +60,68 @@ pub struct DoWhileLoop {
 
 #[derive(Debug, Clone)]
 pub struct Func {
+    pub vis: Visibility,
     pub name: Node<Ident>,
+    pub generics: Vec<Node<TypeParam>>,
     pub params: Vec<Node<Param>>,
-    pub ret: Option<Node<Ident>>,
+    pub ret: Option<Node<Annot>>,
     pub body: Node<Block>,
-    // TODO: Add ID, like Expr::Ident
+}
+
+#[derive(Debug, Clone)]
+pub struct Enum {
+    pub vis: Visibility,
+    pub name: Node<Ident>,
+    pub variants: Vec<Node<Variant>>,
+}
+
+// The fields are written like parameters, and a struct is made by calling it with them in order
+#[derive(Debug, Clone)]
+pub struct Struct {
+    pub vis: Visibility,
+    pub name: Node<Ident>,
+    pub generics: Vec<Node<TypeParam>>,
+    pub fields: Vec<Node<Param>>,
+    // Holds the type parameters, empty until the resolver gets to it
+    pub scope: Rc<RefCell<Scope>>,
+}
+
+// Holds Self, empty until the resolver gets to it
+#[derive(Debug, Clone)]
+pub struct Trait {
+    pub vis: Visibility,
+    pub name: Node<Ident>,
+    pub methods: Vec<Node<Signature>>,
+    pub scope: Rc<RefCell<Scope>>,
+}
+
+// The methods are functions, only the trait has to be in scope. Without a trait they're the type's
+// own. The scope holds Self and the methods, empty until the resolver gets to it
+#[derive(Debug, Clone)]
+pub struct Impl {
+    pub trait_name: Option<Node<Ident>>,
+    pub typ: Node<Annot>,
+    pub methods: Vec<Node<Stmt>>,
+    pub scope: Rc<RefCell<Scope>>,
 }
 
 #[derive(Debug, Clone)]
 pub struct Return {
     pub val: Option<Node<Expr>>,
 }
+
+#[derive(Debug, Clone)]
+pub struct Module {
+    pub vis: Visibility,
+    pub name: Node<Ident>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Use {
+    pub path: Node<Path>,
+}
+
+#[derive(Debug, Clone)]
+pub struct ExprStmt {
+    pub expr: Node<Expr>,
+}
@@ -0,0 +1,657 @@
+// Nodes are handed around as handles into the Ast. Visitor only reads it, so the hooks get
+// the node itself next to its handle. VisitorMut can change anything, so it gets the handles
+// and the whole arena. A new Stmt or Expr only needs a hook and a case in the walks below
+use crate::ast::{
+    Annot, Arm, Assign, Ast, BinaryOp, Block, BoolLit, Call, Decl, DoWhileLoop, Enum, Expr,
+    ExprStmt, Field, Func, Ident, IfLet, IfStmt, Impl, Match, Module, Node, NumLit, Param, Path,
+    Pattern, Return, Signature, Stmt, Struct, Trait, Tuple, TypeParam, UnaryOp, Use, Variant,
+    WhileLoop,
+};
+
+pub mod walk {
+    use super::*;
+
+    // Every hook walks into its children by default, so only the interesting ones need
+    // overriding. Anything a pass works out goes into its own side tables
+    pub trait Visitor<'a>: Sized {
+        fn ast(&self) -> &'a Ast;
+
+        fn visit_block(&mut self, block: Node<Block>) {
+            walk_block(self, block);
+        }
+
+        fn visit_stmt(&mut self, stmt: Node<Stmt>) {
+            walk_stmt(self, stmt);
+        }
+
+        fn visit_expr(&mut self, expr: Node<Expr>) {
+            walk_expr(self, expr);
+        }
+
+        fn visit_param(&mut self, param: Node<Param>) {
+            walk_param(self, param);
+        }
+
+        // Names in declarations, annotations and paths, not the ones used as expressions
+        fn visit_ident(&mut self, _ident: Node<Ident>) {}
+
+        fn visit_path(&mut self, path: Node<Path>) {
+            walk_path(self, path);
+        }
+
+        fn visit_annot(&mut self, annot: Node<Annot>) {
+            walk_annot(self, annot);
+        }
+
+        fn visit_variant(&mut self, variant: Node<Variant>) {
+            walk_variant(self, variant);
+        }
+
+        fn visit_type_param(&mut self, type_param: Node<TypeParam>) {
+            walk_type_param(self, type_param);
+        }
+
+        fn visit_signature(&mut self, signature: Node<Signature>) {
+            walk_signature(self, signature);
+        }
+
+        fn visit_pattern(&mut self, pattern: Node<Pattern>) {
+            walk_pattern(self, pattern);
+        }
+
+        fn visit_arm(&mut self, arm: Node<Arm>) {
+            walk_arm(self, arm);
+        }
+
+        // Statements
+        fn visit_decl(&mut self, decl: &'a Decl, _stmt: Node<Stmt>) {
+            walk_decl(self, decl);
+        }
+
+        fn visit_assign(&mut self, assign: &'a Assign, _stmt: Node<Stmt>) {
+            walk_assign(self, assign);
+        }
+
+        fn visit_if_stmt(&mut self, if_stmt: &'a IfStmt, _stmt: Node<Stmt>) {
+            walk_if_stmt(self, if_stmt);
+        }
+
+        fn visit_if_let(&mut self, if_let: &'a IfLet, _stmt: Node<Stmt>) {
+            walk_if_let(self, if_let);
+        }
+
+        fn visit_while_loop(&mut self, while_loop: &'a WhileLoop, _stmt: Node<Stmt>) {
+            walk_while_loop(self, while_loop);
+        }
+
+        fn visit_do_while_loop(&mut self, do_while_loop: &'a DoWhileLoop, _stmt: Node<Stmt>) {
+            walk_do_while_loop(self, do_while_loop);
+        }
+
+        fn visit_continue(&mut self, _stmt: Node<Stmt>) {}
+
+        fn visit_return(&mut self, ret: &'a Return, _stmt: Node<Stmt>) {
+            walk_return(self, ret);
+        }
+
+        fn visit_func(&mut self, func: &'a Func, _stmt: Node<Stmt>) {
+            walk_func(self, func);
+        }
+
+        fn visit_enum(&mut self, enum_stmt: &'a Enum, _stmt: Node<Stmt>) {
+            walk_enum(self, enum_stmt);
+        }
+
+        fn visit_struct(&mut self, struct_stmt: &'a Struct, _stmt: Node<Stmt>) {
+            walk_struct(self, struct_stmt);
+        }
+
+        fn visit_trait(&mut self, trait_stmt: &'a Trait, _stmt: Node<Stmt>) {
+            walk_trait(self, trait_stmt);
+        }
+
+        fn visit_impl(&mut self, impl_stmt: &'a Impl, _stmt: Node<Stmt>) {
+            walk_impl(self, impl_stmt);
+        }
+
+        fn visit_module(&mut self, module: &'a Module, _stmt: Node<Stmt>) {
+            self.visit_ident(module.name);
+        }
+
+        fn visit_use(&mut self, use_stmt: &'a Use, _stmt: Node<Stmt>) {
+            self.visit_path(use_stmt.path);
+        }
+
+        fn visit_expr_stmt(&mut self, expr_stmt: &'a ExprStmt, _stmt: Node<Stmt>) {
+            self.visit_expr(expr_stmt.expr);
+        }
+
+        // Expressions
+        fn visit_ident_expr(&mut self, _ident: Node<Ident>, _expr: Node<Expr>) {}
+
+        fn visit_num_lit(&mut self, _num_lit: &'a NumLit, _expr: Node<Expr>) {}
+
+        fn visit_bool_lit(&mut self, _bool_lit: &'a BoolLit, _expr: Node<Expr>) {}
+
+        fn visit_none_lit(&mut self, _expr: Node<Expr>) {}
+
+        fn visit_binary_op(&mut self, binop: &'a BinaryOp, _expr: Node<Expr>) {
+            self.visit_expr(binop.lhs);
+            self.visit_expr(binop.rhs);
+        }
+
+        fn visit_unary_op(&mut self, unop: &'a UnaryOp, _expr: Node<Expr>) {
+            self.visit_expr(unop.val);
+        }
+
+        fn visit_path_expr(&mut self, path: Node<Path>, _expr: Node<Expr>) {
+            self.visit_path(path);
+        }
+
+        fn visit_call(&mut self, call: &'a Call, _expr: Node<Expr>) {
+            walk_call(self, call);
+        }
+
+        fn visit_field(&mut self, field: &'a Field, _expr: Node<Expr>) {
+            self.visit_expr(field.val);
+            self.visit_ident(field.name);
+        }
+
+        fn visit_tuple(&mut self, tuple: &'a Tuple, _expr: Node<Expr>) {
+            for &item in &tuple.items {
+                self.visit_expr(item);
+            }
+        }
+
+        fn visit_match(&mut self, match_expr: &'a Match, _expr: Node<Expr>) {
+            walk_match(self, match_expr);
+        }
+    }
+
+    pub fn walk_block<'a, V: Visitor<'a>>(visitor: &mut V, block: Node<Block>) {
+        for &stmt in &visitor.ast()[block].stmts {
+            visitor.visit_stmt(stmt);
+        }
+    }
+
+    pub fn walk_stmt<'a, V: Visitor<'a>>(visitor: &mut V, stmt: Node<Stmt>) {
+        match &visitor.ast()[stmt] {
+            Stmt::Block(x) => visitor.visit_block(*x),
+            Stmt::Decl(x) => visitor.visit_decl(x, stmt),
+            Stmt::Assign(x) => visitor.visit_assign(x, stmt),
+            Stmt::IfStmt(x) => visitor.visit_if_stmt(x, stmt),
+            Stmt::IfLet(x) => visitor.visit_if_let(x, stmt),
+            Stmt::WhileLoop(x) => visitor.visit_while_loop(x, stmt),
+            Stmt::DoWhileLoop(x) => visitor.visit_do_while_loop(x, stmt),
+            Stmt::Continue => visitor.visit_continue(stmt),
+            Stmt::Return(x) => visitor.visit_return(x, stmt),
+            Stmt::Func(x) => visitor.visit_func(x, stmt),
+            Stmt::Enum(x) => visitor.visit_enum(x, stmt),
+            Stmt::Struct(x) => visitor.visit_struct(x, stmt),
+            Stmt::Trait(x) => visitor.visit_trait(x, stmt),
+            Stmt::Impl(x) => visitor.visit_impl(x, stmt),
+            Stmt::Module(x) => visitor.visit_module(x, stmt),
+            Stmt::Use(x) => visitor.visit_use(x, stmt),
+            Stmt::ExprStmt(x) => visitor.visit_expr_stmt(x, stmt),
+        }
+    }
+
+    pub fn walk_expr<'a, V: Visitor<'a>>(visitor: &mut V, expr: Node<Expr>) {
+        match &visitor.ast()[expr] {
+            Expr::Ident(x) => visitor.visit_ident_expr(*x, expr),
+            Expr::NumLit(x) => visitor.visit_num_lit(x, expr),
+            Expr::BoolLit(x) => visitor.visit_bool_lit(x, expr),
+            Expr::NoneLit => visitor.visit_none_lit(expr),
+            Expr::BinaryOp(x) => visitor.visit_binary_op(x, expr),
+            Expr::UnaryOp(x) => visitor.visit_unary_op(x, expr),
+            Expr::Path(x) => visitor.visit_path_expr(*x, expr),
+            Expr::Call(x) => visitor.visit_call(x, expr),
+            Expr::Field(x) => visitor.visit_field(x, expr),
+            Expr::Tuple(x) => visitor.visit_tuple(x, expr),
+            Expr::Match(x) => visitor.visit_match(x, expr),
+        }
+    }
+
+    pub fn walk_param<'a, V: Visitor<'a>>(visitor: &mut V, param: Node<Param>) {
+        let param = &visitor.ast()[param];
+        visitor.visit_ident(param.name);
+        visitor.visit_annot(param.annot);
+    }
+
+    pub fn walk_path<'a, V: Visitor<'a>>(visitor: &mut V, path: Node<Path>) {
+        for &seg in &visitor.ast()[path].segments {
+            visitor.visit_ident(seg);
+        }
+    }
+
+    pub fn walk_annot<'a, V: Visitor<'a>>(visitor: &mut V, annot: Node<Annot>) {
+        match &visitor.ast()[annot] {
+            Annot::Named(name) | Annot::Dyn(name) => visitor.visit_ident(*name),
+            Annot::Tuple(items) => {
+                for &item in items {
+                    visitor.visit_annot(item);
+                }
+            }
+            Annot::Optional(inner) => visitor.visit_annot(*inner),
+            Annot::Generic(name, args) => {
+                visitor.visit_ident(*name);
+                for &arg in args {
+                    visitor.visit_annot(arg);
+                }
+            }
+        }
+    }
+
+    pub fn walk_variant<'a, V: Visitor<'a>>(visitor: &mut V, variant: Node<Variant>) {
+        let variant = &visitor.ast()[variant];
+        visitor.visit_ident(variant.name);
+        for &field in &variant.fields {
+            visitor.visit_annot(field);
+        }
+    }
+
+    pub fn walk_type_param<'a, V: Visitor<'a>>(visitor: &mut V, type_param: Node<TypeParam>) {
+        let type_param = &visitor.ast()[type_param];
+        visitor.visit_ident(type_param.name);
+        for &bound in &type_param.bounds {
+            visitor.visit_ident(bound);
+        }
+    }
+
+    pub fn walk_signature<'a, V: Visitor<'a>>(visitor: &mut V, signature: Node<Signature>) {
+        let signature = &visitor.ast()[signature];
+        visitor.visit_ident(signature.name);
+        for &param in &signature.params {
+            visitor.visit_param(param);
+        }
+        if let Some(ret) = signature.ret {
+            visitor.visit_annot(ret);
+        }
+    }
+
+    pub fn walk_pattern<'a, V: Visitor<'a>>(visitor: &mut V, pattern: Node<Pattern>) {
+        match &visitor.ast()[pattern] {
+            Pattern::Wildcard => (),
+            Pattern::Binding(x) => visitor.visit_ident(*x),
+            Pattern::Literal(x) => visitor.visit_expr(*x),
+            Pattern::Tuple(items) => {
+                for &item in items {
+                    visitor.visit_pattern(item);
+                }
+            }
+            Pattern::Variant(path, fields) => {
+                visitor.visit_path(*path);
+                for &field in fields {
+                    visitor.visit_pattern(field);
+                }
+            }
+        }
+    }
+
+    pub fn walk_arm<'a, V: Visitor<'a>>(visitor: &mut V, arm: Node<Arm>) {
+        let arm = &visitor.ast()[arm];
+        visitor.visit_pattern(arm.pat);
+        if let Some(guard) = arm.guard {
+            visitor.visit_expr(guard);
+        }
+        visitor.visit_stmt(arm.body);
+    }
+
+    pub fn walk_decl<'a, V: Visitor<'a>>(visitor: &mut V, decl: &'a Decl) {
+        visitor.visit_ident(decl.name);
+        if let Some(annot) = decl.annot {
+            visitor.visit_annot(annot);
+        }
+        visitor.visit_expr(decl.val);
+    }
+
+    pub fn walk_assign<'a, V: Visitor<'a>>(visitor: &mut V, assign: &'a Assign) {
+        visitor.visit_ident(assign.name);
+        visitor.visit_expr(assign.val);
+    }
+
+    pub fn walk_if_stmt<'a, V: Visitor<'a>>(visitor: &mut V, if_stmt: &'a IfStmt) {
+        visitor.visit_expr(if_stmt.cond);
+        visitor.visit_block(if_stmt.body);
+    }
+
+    pub fn walk_if_let<'a, V: Visitor<'a>>(visitor: &mut V, if_let: &'a IfLet) {
+        visitor.visit_ident(if_let.name);
+        visitor.visit_expr(if_let.val);
+        visitor.visit_block(if_let.body);
+    }
+
+    pub fn walk_while_loop<'a, V: Visitor<'a>>(visitor: &mut V, while_loop: &'a WhileLoop) {
+        visitor.visit_expr(while_loop.cond);
+        visitor.visit_block(while_loop.body);
+    }
+
+    pub fn walk_do_while_loop<'a, V: Visitor<'a>>(visitor: &mut V, do_while_loop: &'a DoWhileLoop) {
+        visitor.visit_block(do_while_loop.body);
+        visitor.visit_expr(do_while_loop.cond);
+    }
+
+    pub fn walk_return<'a, V: Visitor<'a>>(visitor: &mut V, ret: &'a Return) {
+        if let Some(val) = ret.val {
+            visitor.visit_expr(val);
+        }
+    }
+
+    pub fn walk_func<'a, V: Visitor<'a>>(visitor: &mut V, func: &'a Func) {
+        visitor.visit_ident(func.name);
+        for &type_param in &func.generics {
+            visitor.visit_type_param(type_param);
+        }
+        for &param in &func.params {
+            visitor.visit_param(param);
+        }
+        if let Some(ret) = func.ret {
+            visitor.visit_annot(ret);
+        }
+        visitor.visit_block(func.body);
+    }
+
+    pub fn walk_enum<'a, V: Visitor<'a>>(visitor: &mut V, enum_stmt: &'a Enum) {
+        visitor.visit_ident(enum_stmt.name);
+        for &variant in &enum_stmt.variants {
+            visitor.visit_variant(variant);
+        }
+    }
+
+    pub fn walk_struct<'a, V: Visitor<'a>>(visitor: &mut V, struct_stmt: &'a Struct) {
+        visitor.visit_ident(struct_stmt.name);
+        for &type_param in &struct_stmt.generics {
+            visitor.visit_type_param(type_param);
+        }
+        for &field in &struct_stmt.fields {
+            visitor.visit_param(field);
+        }
+    }
+
+    pub fn walk_trait<'a, V: Visitor<'a>>(visitor: &mut V, trait_stmt: &'a Trait) {
+        visitor.visit_ident(trait_stmt.name);
+        for &method in &trait_stmt.methods {
+            visitor.visit_signature(method);
+        }
+    }
+
+    pub fn walk_impl<'a, V: Visitor<'a>>(visitor: &mut V, impl_stmt: &'a Impl) {
+        if let Some(trait_name) = impl_stmt.trait_name {
+            visitor.visit_ident(trait_name);
+        }
+        visitor.visit_annot(impl_stmt.typ);
+        for &method in &impl_stmt.methods {
+            visitor.visit_stmt(method);
+        }
+    }
+
+    pub fn walk_call<'a, V: Visitor<'a>>(visitor: &mut V, call: &'a Call) {
+        visitor.visit_expr(call.callee);
+        for &arg in &call.args {
+            visitor.visit_expr(arg);
+        }
+    }
+
+    pub fn walk_match<'a, V: Visitor<'a>>(visitor: &mut V, match_expr: &'a Match) {
+        visitor.visit_expr(match_expr.val);
+        for &arm in &match_expr.arms {
+            visitor.visit_arm(arm);
+        }
+    }
+}
+
+pub mod walk_mut {
+    use super::*;
+
+    // For passes that rewrite nodes in place. The children are read before walking into them,
+    // so a hook is free to replace the node it was given
+    pub trait VisitorMut: Sized {
+        fn visit_block(&mut self, ast: &mut Ast, block: Node<Block>) {
+            walk_block(self, ast, block);
+        }
+
+        fn visit_stmt(&mut self, ast: &mut Ast, stmt: Node<Stmt>) {
+            walk_stmt(self, ast, stmt);
+        }
+
+        fn visit_expr(&mut self, ast: &mut Ast, expr: Node<Expr>) {
+            walk_expr(self, ast, expr);
+        }
+
+        fn visit_param(&mut self, ast: &mut Ast, param: Node<Param>) {
+            walk_param(self, ast, param);
+        }
+
+        fn visit_ident(&mut self, _ast: &mut Ast, _ident: Node<Ident>) {}
+
+        fn visit_type_param(&mut self, ast: &mut Ast, type_param: Node<TypeParam>) {
+            walk_type_param(self, ast, type_param);
+        }
+
+        fn visit_path(&mut self, ast: &mut Ast, path: Node<Path>) {
+            walk_path(self, ast, path);
+        }
+
+        fn visit_annot(&mut self, ast: &mut Ast, annot: Node<Annot>) {
+            walk_annot(self, ast, annot);
+        }
+
+        fn visit_pattern(&mut self, ast: &mut Ast, pattern: Node<Pattern>) {
+            walk_pattern(self, ast, pattern);
+        }
+
+        fn visit_arm(&mut self, ast: &mut Ast, arm: Node<Arm>) {
+            walk_arm(self, ast, arm);
+        }
+    }
+
+    pub fn walk_block<V: VisitorMut>(visitor: &mut V, ast: &mut Ast, block: Node<Block>) {
+        for stmt in ast[block].stmts.clone() {
+            visitor.visit_stmt(ast, stmt);
+        }
+    }
+
+    pub fn walk_stmt<V: VisitorMut>(visitor: &mut V, ast: &mut Ast, stmt: Node<Stmt>) {
+        match ast[stmt].clone() {
+            Stmt::Block(x) => visitor.visit_block(ast, x),
+            Stmt::Decl(x) => {
+                visitor.visit_ident(ast, x.name);
+                if let Some(annot) = x.annot {
+                    visitor.visit_annot(ast, annot);
+                }
+                visitor.visit_expr(ast, x.val);
+            }
+            Stmt::Assign(x) => {
+                visitor.visit_ident(ast, x.name);
+                visitor.visit_expr(ast, x.val);
+            }
+            Stmt::IfStmt(x) => {
+                visitor.visit_expr(ast, x.cond);
+                visitor.visit_block(ast, x.body);
+            }
+            Stmt::IfLet(x) => {
+                visitor.visit_ident(ast, x.name);
+                visitor.visit_expr(ast, x.val);
+                visitor.visit_block(ast, x.body);
+            }
+            Stmt::WhileLoop(x) => {
+                visitor.visit_expr(ast, x.cond);
+                visitor.visit_block(ast, x.body);
+            }
+            Stmt::DoWhileLoop(x) => {
+                visitor.visit_block(ast, x.body);
+                visitor.visit_expr(ast, x.cond);
+            }
+            Stmt::Continue => (),
+            Stmt::Return(x) => {
+                if let Some(val) = x.val {
+                    visitor.visit_expr(ast, val);
+                }
+            }
+            Stmt::Func(x) => {
+                visitor.visit_ident(ast, x.name);
+                for type_param in x.generics {
+                    visitor.visit_type_param(ast, type_param);
+                }
+                for param in x.params {
+                    visitor.visit_param(ast, param);
+                }
+                if let Some(ret) = x.ret {
+                    visitor.visit_annot(ast, ret);
+                }
+                visitor.visit_block(ast, x.body);
+            }
+            Stmt::Enum(x) => {
+                visitor.visit_ident(ast, x.name);
+                for variant in x.variants {
+                    let Variant { name, fields } = ast[variant].clone();
+                    visitor.visit_ident(ast, name);
+                    for field in fields {
+                        visitor.visit_annot(ast, field);
+                    }
+                }
+            }
+            Stmt::Struct(x) => {
+                visitor.visit_ident(ast, x.name);
+                for type_param in x.generics {
+                    visitor.visit_type_param(ast, type_param);
+                }
+                for field in x.fields {
+                    visitor.visit_param(ast, field);
+                }
+            }
+            Stmt::Trait(x) => {
+                visitor.visit_ident(ast, x.name);
+                for method in x.methods {
+                    let Signature { name, params, ret } = ast[method].clone();
+                    visitor.visit_ident(ast, name);
+                    for param in params {
+                        visitor.visit_param(ast, param);
+                    }
+                    if let Some(ret) = ret {
+                        visitor.visit_annot(ast, ret);
+                    }
+                }
+            }
+            Stmt::Impl(x) => {
+                if let Some(trait_name) = x.trait_name {
+                    visitor.visit_ident(ast, trait_name);
+                }
+                visitor.visit_annot(ast, x.typ);
+                for method in x.methods {
+                    visitor.visit_stmt(ast, method);
+                }
+            }
+            Stmt::Module(x) => visitor.visit_ident(ast, x.name),
+            Stmt::Use(x) => visitor.visit_path(ast, x.path),
+            Stmt::ExprStmt(x) => visitor.visit_expr(ast, x.expr),
+        }
+    }
+
+    pub fn walk_expr<V: VisitorMut>(visitor: &mut V, ast: &mut Ast, expr: Node<Expr>) {
+        match ast[expr].clone() {
+            Expr::Ident(_) | Expr::NumLit(_) | Expr::BoolLit(_) | Expr::NoneLit => (),
+            Expr::BinaryOp(x) => {
+                visitor.visit_expr(ast, x.lhs);
+                visitor.visit_expr(ast, x.rhs);
+            }
+            Expr::UnaryOp(x) => visitor.visit_expr(ast, x.val),
+            Expr::Path(x) => visitor.visit_path(ast, x),
+            Expr::Call(x) => {
+                visitor.visit_expr(ast, x.callee);
+                for arg in x.args {
+                    visitor.visit_expr(ast, arg);
+                }
+            }
+            Expr::Field(x) => {
+                visitor.visit_expr(ast, x.val);
+                visitor.visit_ident(ast, x.name);
+            }
+            Expr::Tuple(x) => {
+                for item in x.items {
+                    visitor.visit_expr(ast, item);
+                }
+            }
+            Expr::Match(x) => {
+                visitor.visit_expr(ast, x.val);
+                for arm in x.arms {
+                    visitor.visit_arm(ast, arm);
+                }
+            }
+        }
+    }
+
+    pub fn walk_param<V: VisitorMut>(visitor: &mut V, ast: &mut Ast, param: Node<Param>) {
+        let Param { name, annot } = ast[param];
+        visitor.visit_ident(ast, name);
+        visitor.visit_annot(ast, annot);
+    }
+
+    pub fn walk_type_param<V: VisitorMut>(
+        visitor: &mut V,
+        ast: &mut Ast,
+        type_param: Node<TypeParam>,
+    ) {
+        let TypeParam { name, bounds } = ast[type_param].clone();
+        visitor.visit_ident(ast, name);
+        for bound in bounds {
+            visitor.visit_ident(ast, bound);
+        }
+    }
+
+    pub fn walk_path<V: VisitorMut>(visitor: &mut V, ast: &mut Ast, path: Node<Path>) {
+        for seg in ast[path].segments.clone() {
+            visitor.visit_ident(ast, seg);
+        }
+    }
+
+    pub fn walk_annot<V: VisitorMut>(visitor: &mut V, ast: &mut Ast, annot: Node<Annot>) {
+        match ast[annot].clone() {
+            Annot::Named(name) | Annot::Dyn(name) => visitor.visit_ident(ast, name),
+            Annot::Tuple(items) => {
+                for item in items {
+                    visitor.visit_annot(ast, item);
+                }
+            }
+            Annot::Optional(inner) => visitor.visit_annot(ast, inner),
+            Annot::Generic(name, args) => {
+                visitor.visit_ident(ast, name);
+                for arg in args {
+                    visitor.visit_annot(ast, arg);
+                }
+            }
+        }
+    }
+
+    pub fn walk_pattern<V: VisitorMut>(visitor: &mut V, ast: &mut Ast, pattern: Node<Pattern>) {
+        match ast[pattern].clone() {
+            Pattern::Wildcard => (),
+            Pattern::Binding(x) => visitor.visit_ident(ast, x),
+            Pattern::Literal(x) => visitor.visit_expr(ast, x),
+            Pattern::Tuple(items) => {
+                for item in items {
+                    visitor.visit_pattern(ast, item);
+                }
+            }
+            Pattern::Variant(path, fields) => {
+                visitor.visit_path(ast, path);
+                for field in fields {
+                    visitor.visit_pattern(ast, field);
+                }
+            }
+        }
+    }
+
+    pub fn walk_arm<V: VisitorMut>(visitor: &mut V, ast: &mut Ast, arm: Node<Arm>) {
+        let Arm {
+            pat, guard, body, ..
+        } = ast[arm].clone();
+        visitor.visit_pattern(ast, pat);
+        if let Some(guard) = guard {
+            visitor.visit_expr(ast, guard);
+        }
+        visitor.visit_stmt(ast, body);
+    }
+}
+
+pub use self::{walk::Visitor, walk_mut::VisitorMut};
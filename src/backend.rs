@@ -0,0 +1,10 @@
+// Everything that turns the IR into something that runs outside of fusion
+pub(crate) mod c;
+pub mod wasm;
+pub mod x86;
+
+pub use self::{
+    c::emit_c,
+    wasm::emit_wasm,
+    x86::{emit_asm, emit_object},
+};
@@ -0,0 +1,570 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::ir::{
+    print::quote, BinOp, Block, Callee, Const, Function, Inst, Module, Op, Terminator, Type, UnOp,
+    Value, ENTRY,
+};
+
+// Portable C99 for the whole module, with the entry as main. Values become locals, blocks
+// become labels and phis get copied into on the way in. Anything that can trap goes through a
+// small helper that says where it happened. #line directives point those, C compiler errors
+// and debuggers back at the .fn files
+pub fn emit_c(module: &Module) -> String {
+    // The helpers are only known once the functions are written
+    let mut emitter = Emitter::default();
+    for func in &module.funcs {
+        emitter.raw("");
+        emitter.function(func);
+    }
+
+    let mut out = PRELUDE.to_owned();
+    if emitter.traps {
+        out += "\n";
+        out += TRAP;
+    }
+    for (_, code) in &emitter.helpers {
+        out += "\n";
+        out += code;
+    }
+    out += "\n";
+    for global in &module.globals {
+        out += &format!(
+            "static {} {};\n",
+            ctype(global.typ),
+            global_name(&global.name)
+        );
+    }
+    if !module.globals.is_empty() {
+        out += "\n";
+    }
+    for func in module.funcs.iter().filter(|x| x.name != ENTRY) {
+        out += &format!("{};\n", signature(func));
+    }
+
+    // Nothing before the functions has a directive, so they go in as they were written
+    out + &emitter.out
+}
+
+const PRELUDE: &str = "// Generated by fusion
+#include <math.h>
+#include <stdbool.h>
+#include <stdint.h>
+#include <stdio.h>
+#include <stdlib.h>
+
+// Every function value has this type, calls cast it back to what it really is
+typedef void (*fusion_func)(void);
+";
+
+const TRAP: &str = "static void fusion_trap(const char *msg, const char *file, int line) {
+    fprintf(stderr, \"error: %s (%s:%d)\\n\", msg, file, line);
+    exit(1);
+}
+";
+
+#[derive(Default)]
+struct Emitter {
+    // Static functions the code calls, in the order they were first needed
+    helpers: Vec<(String, String)>,
+    // Whether any of them can trap
+    traps: bool,
+    out: String,
+    // Which line the next line of C counts as, from the last directive
+    at: Option<(String, u32)>,
+    // And which line it should be
+    want: Option<(String, u32)>,
+    // What the last line of C was for, when it was code
+    last: Option<(String, u32)>,
+    // The values the function reads, the rest don't get a variable
+    used: HashSet<Value>,
+}
+
+impl Emitter {
+    // A line of code, with a directive first if it would count as the wrong line. Code from the
+    // same line of fusion all goes on one line of C, so everything in it points at the right place
+    fn line(&mut self, text: &str) {
+        if self.want.is_some() && self.want == self.last {
+            self.out.pop();
+            self.out += &format!(" {}\n", text.trim_start());
+            return;
+        }
+
+        if self.want.is_some() && self.want != self.at {
+            let (file, line) = self.want.clone().unwrap();
+            self.out += &format!("#line {} {}\n", line, quote(&file));
+            self.at = self.want.clone();
+        }
+        self.raw(text);
+        self.last = self.want.clone();
+    }
+
+    // A line that doesn't need to point anywhere, like a declaration
+    fn raw(&mut self, text: &str) {
+        self.out += text;
+        self.out.push('\n');
+        if let Some((_, line)) = &mut self.at {
+            *line += 1;
+        }
+        self.last = None;
+    }
+
+    fn function(&mut self, func: &Function) {
+        let types = func.types();
+        let entry = func.name == ENTRY;
+
+        // The signature points at the first code in the function
+        self.want = func.blocks[0].insts.iter().find_map(|x| match &x.op {
+            Op::Line(file, line) => Some((file.clone(), *line)),
+            _ => None,
+        });
+        match entry {
+            true => self.line("int main(void) {"),
+            false => self.line(&format!("{} {{", signature(func))),
+        }
+
+        self.used = func
+            .blocks
+            .iter()
+            .flat_map(|x| {
+                x.insts
+                    .iter()
+                    .flat_map(|x| x.op.operands())
+                    .chain(x.term.operands())
+            })
+            .collect();
+
+        // C wants everything declared before the gotos jump over it
+        for block in &func.blocks {
+            for inst in &block.insts {
+                if let Some((val, typ)) = inst.dest.filter(|x| self.used.contains(&x.0)) {
+                    self.raw(&format!("    {} {};", ctype(typ), val_name(val)));
+                    if let Op::Phi(_) = inst.op {
+                        self.raw(&format!("    {} {};", ctype(typ), phi_name(val)));
+                    }
+                }
+            }
+        }
+
+        let targets: HashSet<Block> = func
+            .blocks
+            .iter()
+            .flat_map(|x| x.term.successors())
+            .collect();
+        for (idx, block) in func.blocks.iter().enumerate() {
+            let this = Block(idx as u32);
+            if targets.contains(&this) {
+                self.raw(&format!("{}:", this));
+            }
+
+            for inst in &block.insts {
+                let dest = inst.dest.map(|x| x.0).filter(|x| self.used.contains(x));
+                let code = match &inst.op {
+                    Op::Line(file, line) => {
+                        self.want = Some((file.clone(), *line));
+                        continue;
+                    }
+                    Op::Phi(_) => {
+                        if let Some(dest) = dest {
+                            self.line(&format!("    {} = {};", val_name(dest), phi_name(dest)));
+                        }
+                        continue;
+                    }
+                    // Nothing needs what it gives, so it only matters if it does something
+                    _ if dest.is_none() && inst.dest.is_some() && !has_effects(inst) => continue,
+                    Op::Store(name, val) => format!("{} = {}", global_name(name), val_name(*val)),
+                    _ => self.expr(inst, &types),
+                };
+                match dest {
+                    Some(dest) => self.line(&format!("    {} = {};", val_name(dest), code)),
+                    None => self.line(&format!("    {};", code)),
+                }
+            }
+
+            match block.term {
+                Terminator::Jump(to) => {
+                    self.copies(func, this, to, "    ");
+                    self.line(&format!("    goto {};", to));
+                }
+                Terminator::Branch(cond, yes, no) => {
+                    let copies = |to: Block| self.phis(func, to).next().is_some();
+                    if copies(yes) {
+                        self.line(&format!("    if ({}) {{", val_name(cond)));
+                        self.copies(func, this, yes, "        ");
+                        self.line(&format!("        goto {};", yes));
+                        self.line("    }");
+                    } else {
+                        self.line(&format!("    if ({}) goto {};", val_name(cond), yes));
+                    }
+                    self.copies(func, this, no, "    ");
+                    self.line(&format!("    goto {};", no));
+                }
+                Terminator::Return(_) if entry => self.line("    return 0;"),
+                Terminator::Return(Some(val)) => {
+                    self.line(&format!("    return {};", val_name(val)))
+                }
+                Terminator::Return(None) => self.line("    return;"),
+            }
+        }
+        self.raw("}");
+    }
+
+    // The phis in the block something reads
+    fn phis<'a>(
+        &'a self,
+        func: &'a Function,
+        block: Block,
+    ) -> impl Iterator<Item = (Value, &'a Vec<(Block, Value)>)> + 'a {
+        func[block]
+            .insts
+            .iter()
+            .filter_map(|inst| match (&inst.op, inst.dest) {
+                (Op::Phi(incoming), Some((dest, _))) if self.used.contains(&dest) => {
+                    Some((dest, incoming))
+                }
+                _ => None,
+            })
+    }
+
+    // What the phis in to get when control comes from block
+    fn copies(&mut self, func: &Function, block: Block, to: Block, indent: &str) {
+        let copies: Vec<String> = self
+            .phis(func, to)
+            .map(|(dest, incoming)| {
+                let (_, val) = incoming.iter().find(|(from, _)| *from == block).unwrap();
+                format!("{}{} = {};", indent, phi_name(dest), val_name(*val))
+            })
+            .collect();
+        for copy in copies {
+            self.line(&copy);
+        }
+    }
+
+    fn expr(&mut self, inst: &Inst, types: &HashMap<Value, Type>) -> String {
+        let typ = inst.dest.map(|x| x.1);
+        match &inst.op {
+            Op::Const(val) => constant(*val, typ.unwrap()),
+            Op::Func(name) => format!("(fusion_func){}", func_name(name)),
+            Op::Binary(op, lhs, rhs) => self.binary(*op, types[lhs], *lhs, *rhs),
+            Op::Unary(op, val) => self.unary(*op, types[val], *val),
+            Op::Call(callee, args) => {
+                let names: Vec<String> = args.iter().map(|&x| val_name(x)).collect();
+                let callee = match callee {
+                    Callee::Direct(name) => func_name(name),
+                    Callee::Indirect(func) => {
+                        let params: Vec<&str> = args.iter().map(|x| ctype(types[x])).collect();
+                        let params = match params.is_empty() {
+                            true => "void".to_owned(),
+                            false => params.join(", "),
+                        };
+                        let ret = typ.map_or("void", ctype);
+                        format!("(({} (*)({})){})", ret, params, val_name(*func))
+                    }
+                };
+                format!("{}({})", callee, names.join(", "))
+            }
+            Op::Load(name) => global_name(name),
+            op => unreachable!("{} isn't an expression", op),
+        }
+    }
+
+    fn binary(&mut self, op: BinOp, typ: Type, lhs: Value, rhs: Value) -> String {
+        let (a, b) = (val_name(lhs), val_name(rhs));
+        let t = ctype(typ);
+        match typ {
+            Type::Float(_) => match op {
+                BinOp::Rem => format!("fmod{}({}, {})", math_suffix(typ), a, b),
+                BinOp::Pow => format!("pow{}({}, {})", math_suffix(typ), a, b),
+                op => format!("{} {} {}", a, c_op(op), b),
+            },
+            Type::Bool | Type::Func => match op {
+                BinOp::Nand => format!("!({} & {})", a, b),
+                BinOp::Nor => format!("!({} | {})", a, b),
+                BinOp::Xand => format!("{} == {}", a, b),
+                op => format!("{} {} {}", a, c_op(op), b),
+            },
+            // Signed overflow is undefined in C, so anything that can wrap is done unsigned.
+            // At least 32 bits wide, or the operands get promoted back to int
+            _ => match op {
+                BinOp::Add | BinOp::Sub | BinOp::Mul => {
+                    let w = wide(typ);
+                    format!("({})(({}){} {} ({}){})", t, w, a, c_op(op), w, b)
+                }
+                BinOp::Div | BinOp::Rem | BinOp::Pow | BinOp::Shl | BinOp::Shr | BinOp::Ushr => {
+                    let name = self.helper(op, typ);
+                    format!("{}({}, {}, __FILE__, __LINE__)", name, a, b)
+                }
+                BinOp::Nand => format!("({})~({} & {})", t, a, b),
+                BinOp::Nor => format!("({})~({} | {})", t, a, b),
+                BinOp::Xand => format!("({})~({} ^ {})", t, a, b),
+                BinOp::And | BinOp::Or | BinOp::Xor => format!("({})({} {} {})", t, a, c_op(op), b),
+                op => format!("{} {} {}", a, c_op(op), b),
+            },
+        }
+    }
+
+    fn unary(&mut self, op: UnOp, typ: Type, val: Value) -> String {
+        let a = val_name(val);
+        match (op, typ) {
+            (UnOp::Neg, Type::Float(_)) => format!("-{}", a),
+            (UnOp::Neg, _) => format!("({})(0u - ({}){})", ctype(typ), wide(typ), a),
+            (UnOp::Not, Type::Bool) => format!("!{}", a),
+            (UnOp::Not, _) => format!("({})~{}", ctype(typ), a),
+            (UnOp::Clz | UnOp::Ctz, _) => {
+                let bits = typ.bits();
+                let (name, test) = match op {
+                    UnOp::Clz => ("clz", format!("(x >> ({} - n)) & 1", bits - 1)),
+                    _ => ("ctz", "(x >> n) & 1".to_owned()),
+                };
+                let name = format!("fusion_{}_{}", name, typ);
+                let code = format!(
+                    "static {t} {name}({t} a) {{
+    {u} x = ({u})a;
+    {t} n = 0;
+    while (n < {bits} && !({test})) {{
+        n++;
+    }}
+    return n;
+}}
+",
+                    t = ctype(typ),
+                    u = unsigned(typ),
+                );
+                self.add_helper(&name, code);
+                format!("{}({})", name, a)
+            }
+        }
+    }
+
+    // The static function for an integer operation that can trap
+    fn helper(&mut self, op: BinOp, typ: Type) -> String {
+        let name = format!("fusion_{}_{}", op, typ);
+        let (t, w, u) = (ctype(typ), wide(typ), unsigned(typ));
+        let signed = matches!(typ, Type::Int(_));
+        let bits = typ.bits();
+
+        let mut code = format!(
+            "static {} {}({} a, {} b, const char *file, int line) {{\n",
+            t, name, t, t
+        );
+        match op {
+            BinOp::Div | BinOp::Rem => {
+                code += "    if (b == 0) {\n";
+                code += "        fusion_trap(\"Division by zero\", file, line);\n";
+                code += "    }\n";
+                // The one division that overflows
+                if signed {
+                    let overflow = match op {
+                        BinOp::Div => format!("({})(0u - ({})a)", t, w),
+                        _ => "0".to_owned(),
+                    };
+                    code += &format!(
+                        "    if (b == -1) {{\n        return {};\n    }}\n",
+                        overflow
+                    );
+                }
+                code += &format!("    return a {} b;\n", c_op(op));
+            }
+            BinOp::Pow => {
+                if signed {
+                    code += "    if (b < 0) {\n";
+                    code += "        fusion_trap(\"Negative exponent\", file, line);\n";
+                    code += "    }\n";
+                }
+                // The interpreter stops counting there too
+                if bits > 32 {
+                    code += &format!("    if (b > ({})0xffffffff) {{\n", t);
+                    code += &format!("        b = ({})0xffffffff;\n", t);
+                    code += "    }\n";
+                }
+                code += &format!("    {} result = 1, base = ({})a;\n", w, w);
+                code += "    while (b) {\n";
+                code += "        if (b & 1) {\n";
+                code += "            result *= base;\n";
+                code += "        }\n";
+                code += "        base *= base;\n";
+                code += "        b >>= 1;\n";
+                code += "    }\n";
+                code += &format!("    return ({})result;\n", t);
+            }
+            _ => {
+                let negative = if signed { "b < 0 || " } else { "" };
+                code += &format!("    if ({}b >= {}) {{\n", negative, bits);
+                code += "        fusion_trap(\"Shifted by more than the width of the type\", file, line);\n";
+                code += "    }\n";
+                // Right shifts of negative numbers are up to the compiler, this isn't
+                let shifted = match op {
+                    BinOp::Shl => format!("({})(({})a << b)", t, w),
+                    BinOp::Shr if signed => format!("a < 0 ? ({})~(~a >> b) : a >> b", t),
+                    BinOp::Shr | BinOp::Ushr if !signed => "a >> b".to_owned(),
+                    _ => format!("({})(({})a >> b)", t, u),
+                };
+                code += &format!("    return {};\n", shifted);
+            }
+        }
+        code += "}\n";
+
+        self.traps = true;
+        self.add_helper(&name, code);
+        name
+    }
+
+    fn add_helper(&mut self, name: &str, code: String) {
+        if !self.helpers.iter().any(|(x, _)| x == name) {
+            self.helpers.push((name.to_owned(), code));
+        }
+    }
+}
+
+// Calls and whatever might trap
+fn has_effects(inst: &Inst) -> bool {
+    match inst.op {
+        Op::Call(..) | Op::Store(..) => true,
+        Op::Binary(op, ..) => matches!(
+            (op, inst.dest),
+            (
+                BinOp::Div | BinOp::Rem | BinOp::Pow | BinOp::Shl | BinOp::Shr | BinOp::Ushr,
+                Some((_, Type::Int(_) | Type::Uint(_)))
+            )
+        ),
+        _ => false,
+    }
+}
+
+fn signature(func: &Function) -> String {
+    let params: Vec<String> = func
+        .params
+        .iter()
+        .map(|&(val, typ)| format!("{} {}", ctype(typ), val_name(val)))
+        .collect();
+    let params = match params.is_empty() {
+        true => "void".to_owned(),
+        false => params.join(", "),
+    };
+    let ret = func.ret.map_or("void", ctype);
+    format!("{} {}({})", ret, func_name(&func.name), params)
+}
+
+// float16 has nothing smaller to go in, and float128 gets whatever long double is
+fn ctype(typ: Type) -> &'static str {
+    match (typ, typ.bits()) {
+        (Type::Int(_), 8) => "int8_t",
+        (Type::Int(_), 16) => "int16_t",
+        (Type::Int(_), 32) => "int32_t",
+        (Type::Int(_), 64) => "int64_t",
+        (Type::Int(_), _) => "__int128",
+        (Type::Uint(_), 8) => "uint8_t",
+        (Type::Uint(_), 16) => "uint16_t",
+        (Type::Uint(_), 32) => "uint32_t",
+        (Type::Uint(_), 64) => "uint64_t",
+        (Type::Uint(_), _) => "unsigned __int128",
+        (Type::Float(_), 16 | 32) => "float",
+        (Type::Float(_), 64) => "double",
+        (Type::Float(_), _) => "long double",
+        (Type::Bool, _) => "bool",
+        (Type::Func, _) => "fusion_func",
+    }
+}
+
+// The unsigned type with the same width
+fn unsigned(typ: Type) -> &'static str {
+    match typ.bits() {
+        8 => "uint8_t",
+        16 => "uint16_t",
+        32 => "uint32_t",
+        64 => "uint64_t",
+        _ => "unsigned __int128",
+    }
+}
+
+// The unsigned type arithmetic wraps in
+fn wide(typ: Type) -> &'static str {
+    match typ.bits() {
+        8 | 16 | 32 => "uint32_t",
+        64 => "uint64_t",
+        _ => "unsigned __int128",
+    }
+}
+
+fn math_suffix(typ: Type) -> &'static str {
+    match typ.bits() {
+        16 | 32 => "f",
+        64 => "",
+        _ => "l",
+    }
+}
+
+fn constant(val: Const, typ: Type) -> String {
+    match val {
+        Const::Bool(x) => x.to_string(),
+        Const::Float(x) if x.is_nan() => "NAN".to_owned(),
+        Const::Float(x) if x.is_infinite() => {
+            format!("{}INFINITY", if x < 0.0 { "-" } else { "" })
+        }
+        Const::Float(x) => format!("({}){:?}", ctype(typ), x),
+        // i64::MIN doesn't have a literal, its negation is too big for one
+        Const::Int(x) if x == i64::MIN as i128 => {
+            format!("({})(-9223372036854775807ll - 1)", ctype(typ))
+        }
+        Const::Int(x) if i64::try_from(x).is_ok() => format!("({}){}", ctype(typ), x),
+        Const::Int(x) if u64::try_from(x).is_ok() => format!("({}){}ull", ctype(typ), x),
+        Const::Int(x) => {
+            let x = x as u128;
+            format!(
+                "({})(((unsigned __int128)0x{:x}ull << 64) | 0x{:x}ull)",
+                ctype(typ),
+                x >> 64,
+                x as u64
+            )
+        }
+    }
+}
+
+fn c_op(op: BinOp) -> &'static str {
+    match op {
+        BinOp::Add => "+",
+        BinOp::Sub => "-",
+        BinOp::Mul => "*",
+        BinOp::Div => "/",
+        BinOp::Rem => "%",
+        BinOp::And => "&",
+        BinOp::Or => "|",
+        BinOp::Xor => "^",
+        BinOp::Eq => "==",
+        BinOp::Ne => "!=",
+        BinOp::Lt => "<",
+        BinOp::Le => "<=",
+        BinOp::Gt => ">",
+        BinOp::Ge => ">=",
+        _ => unreachable!("{} doesn't have an operator in C", op),
+    }
+}
+
+// Every part of the name gets its length in front, so no two names can come out the same and
+// none of them can be a keyword
+pub(super) fn mangle(prefix: &str, name: &str) -> String {
+    let mut out = prefix.to_owned();
+    for part in name.split('.') {
+        out += &format!("{}{}", part.len(), part);
+    }
+    out
+}
+
+fn func_name(name: &str) -> String {
+    match name == ENTRY {
+        true => "main".to_owned(),
+        false => mangle("f", name),
+    }
+}
+
+fn global_name(name: &str) -> String {
+    mangle("g", name)
+}
+
+fn val_name(val: Value) -> String {
+    format!("v{}", val.0)
+}
+
+// Where control coming into the block leaves the value for the phi
+fn phi_name(val: Value) -> String {
+    format!("p{}", val.0)
+}
@@ -0,0 +1,187 @@
+mod emit;
+mod encode;
+mod validate;
+mod wat;
+
+pub use self::{emit::emit_wasm, validate::validate};
+
+// A WebAssembly module, close to how the binary format lays it out. Functions are numbered
+// with the imports first, and function values are indices into the one table
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Module {
+    pub types: Vec<FuncType>,
+    pub imports: Vec<Import>,
+    pub funcs: Vec<Func>,
+    pub globals: Vec<Global>,
+    // The functions in the table, in order
+    pub table: Vec<u32>,
+}
+
+impl Module {
+    // The index of the type, adding it if it isn't there yet
+    pub fn type_index(&mut self, typ: FuncType) -> u32 {
+        match self.types.iter().position(|x| *x == typ) {
+            Some(idx) => idx as u32,
+            None => {
+                self.types.push(typ);
+                self.types.len() as u32 - 1
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ValType {
+    I32,
+    I64,
+    F32,
+    F64,
+}
+
+impl ValType {
+    pub fn code(self) -> u8 {
+        match self {
+            ValType::I32 => 0x7f,
+            ValType::I64 => 0x7e,
+            ValType::F32 => 0x7d,
+            ValType::F64 => 0x7c,
+        }
+    }
+
+    pub fn from_code(code: u8) -> Option<ValType> {
+        Some(match code {
+            0x7f => ValType::I32,
+            0x7e => ValType::I64,
+            0x7d => ValType::F32,
+            0x7c => ValType::F64,
+            _ => return None,
+        })
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            ValType::I32 => "i32",
+            ValType::I64 => "i64",
+            ValType::F32 => "f32",
+            ValType::F64 => "f64",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FuncType {
+    pub params: Vec<ValType>,
+    pub results: Vec<ValType>,
+}
+
+// Only functions get imported, for the float math wasm doesn't have
+#[derive(Debug, Clone, PartialEq)]
+pub struct Import {
+    pub module: String,
+    pub name: String,
+    pub typ: u32,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Func {
+    pub name: String,
+    pub typ: u32,
+    // Not counting the parameters, which come first
+    pub locals: Vec<ValType>,
+    pub body: Vec<Instr>,
+    pub export: bool,
+}
+
+// Always mutable and starting out zeroed, like the IR's globals
+#[derive(Debug, Clone, PartialEq)]
+pub struct Global {
+    pub name: String,
+    pub typ: ValType,
+}
+
+// The instructions the backend uses. Blocks say what they leave on the stack, and a br counts
+// how many blocks out it goes
+#[derive(Debug, Clone, PartialEq)]
+pub enum Instr {
+    Unreachable,
+    Block(Option<ValType>),
+    Loop(Option<ValType>),
+    If(Option<ValType>),
+    Else,
+    End,
+    Br(u32),
+    BrIf(u32),
+    Return,
+    Call(u32),
+    // With the type of the function, the table index is on top of the stack
+    CallIndirect(u32),
+    Drop,
+    LocalGet(u32),
+    LocalSet(u32),
+    GlobalGet(u32),
+    GlobalSet(u32),
+    I32Const(i32),
+    I64Const(i64),
+    F32Const(f32),
+    F64Const(f64),
+    // Everything without immediates, by opcode
+    Num(u8),
+}
+
+// What a numeric instruction is called and what it takes and gives
+pub fn numeric(code: u8) -> Option<(String, Vec<ValType>, ValType)> {
+    use ValType::*;
+
+    const COMPARE_INT: [&str; 10] = [
+        "eq", "ne", "lt_s", "lt_u", "gt_s", "gt_u", "le_s", "le_u", "ge_s", "ge_u",
+    ];
+    const COMPARE_FLOAT: [&str; 6] = ["eq", "ne", "lt", "gt", "le", "ge"];
+    const UNARY_INT: [&str; 3] = ["clz", "ctz", "popcnt"];
+    const BINARY_INT: [&str; 15] = [
+        "add", "sub", "mul", "div_s", "div_u", "rem_s", "rem_u", "and", "or", "xor", "shl",
+        "shr_s", "shr_u", "rotl", "rotr",
+    ];
+    const UNARY_FLOAT: [&str; 7] = ["abs", "neg", "ceil", "floor", "trunc", "nearest", "sqrt"];
+    const BINARY_FLOAT: [&str; 7] = ["add", "sub", "mul", "div", "min", "max", "copysign"];
+
+    // Each run of opcodes is one kind of instruction on one type
+    let runs: [(u8, &[&str], ValType, usize, ValType); 14] = [
+        (0x46, &COMPARE_INT, I32, 2, I32),
+        (0x51, &COMPARE_INT, I64, 2, I32),
+        (0x5b, &COMPARE_FLOAT, F32, 2, I32),
+        (0x61, &COMPARE_FLOAT, F64, 2, I32),
+        (0x67, &UNARY_INT, I32, 1, I32),
+        (0x6a, &BINARY_INT, I32, 2, I32),
+        (0x79, &UNARY_INT, I64, 1, I64),
+        (0x7c, &BINARY_INT, I64, 2, I64),
+        (0x8b, &UNARY_FLOAT, F32, 1, F32),
+        (0x92, &BINARY_FLOAT, F32, 2, F32),
+        (0x99, &UNARY_FLOAT, F64, 1, F64),
+        (0xa0, &BINARY_FLOAT, F64, 2, F64),
+        (0x45, &["eqz"], I32, 1, I32),
+        (0x50, &["eqz"], I64, 1, I32),
+    ];
+    for (start, names, typ, arity, result) in runs {
+        if let Some(name) = code.checked_sub(start).and_then(|x| names.get(x as usize)) {
+            let name = format!("{}.{}", typ.name(), name);
+            return Some((name, vec![typ; arity], result));
+        }
+    }
+
+    let (name, from, to) = match code {
+        0xa7 => ("i32.wrap_i64", I64, I32),
+        0xac => ("i64.extend_i32_s", I32, I64),
+        0xad => ("i64.extend_i32_u", I32, I64),
+        0xb6 => ("f32.demote_f64", F64, F32),
+        0xbb => ("f64.promote_f32", F32, F64),
+        _ => return None,
+    };
+    Some((name.to_owned(), vec![from], to))
+}
+
+// The opcode of a numeric instruction from its name
+pub fn opcode(name: &str) -> u8 {
+    (0x45..=0xbb)
+        .find(|&x| numeric(x).is_some_and(|(x, _, _)| x == name))
+        .unwrap_or_else(|| panic!("{} isn't a numeric instruction", name))
+}
@@ -0,0 +1,716 @@
+use std::collections::HashMap;
+
+use super::{opcode, Func, FuncType, Global, Import, Instr, Module, ValType};
+use crate::ir::{self, BinOp, Block, Callee, Cfg, Const, Op, Terminator, Type, UnOp, Value};
+
+// The module as wasm, with every function exported under its own name. Wasm only has blocks,
+// loops and ifs, so the control flow gets put back into those with the algorithm from "Beyond
+// Relooper" by Norman Ramsey. Traps are just unreachable, and float % and ^ come from the host
+// as fusion.fmod and fusion.pow, which should do what C's fmod and pow do. Nothing 128 bits
+// wide fits in a wasm value, so those are an error
+pub fn emit_wasm(module: &ir::Module) -> Result<Module, String> {
+    for global in &module.globals {
+        valtype(global.typ).map_err(|err| format!("@{}: {}", global.name, err))?;
+    }
+    for func in &module.funcs {
+        let types = func.types().into_values().chain(func.ret);
+        if let Some(Err(err)) = types.map(valtype).find(|x| x.is_err()) {
+            return Err(format!("@{}: {}", func.name, err));
+        }
+    }
+
+    let mut out = Module::default();
+    // Imports come first in the function numbering, so they have to be known up front
+    let mut imports = HashMap::new();
+    for (op, name) in [(BinOp::Rem, "fmod"), (BinOp::Pow, "pow")] {
+        let used = module.funcs.iter().flat_map(|x| &x.blocks).any(|block| {
+            block.insts.iter().any(|inst| match (&inst.op, inst.dest) {
+                (Op::Binary(x, ..), Some((_, Type::Float(_)))) => *x == op,
+                _ => false,
+            })
+        });
+        if used {
+            let typ = out.type_index(FuncType {
+                params: vec![ValType::F64; 2],
+                results: vec![ValType::F64],
+            });
+            imports.insert(op, out.imports.len() as u32);
+            out.imports.push(Import {
+                module: "fusion".to_owned(),
+                name: name.to_owned(),
+                typ,
+            });
+        }
+    }
+
+    let first = out.imports.len() as u32;
+    let mut emitter = Emitter {
+        module,
+        funcs: (first..)
+            .zip(&module.funcs)
+            .map(|(idx, x)| (x.name.as_str(), idx))
+            .collect(),
+        globals: (0..)
+            .zip(&module.globals)
+            .map(|(idx, x)| (x.name.as_str(), idx))
+            .collect(),
+        imports,
+        helpers: Vec::new(),
+        first_helper: first + module.funcs.len() as u32,
+        out,
+    };
+    emitter.out.globals = module
+        .globals
+        .iter()
+        .map(|x| Global {
+            name: x.name.clone(),
+            typ: valtype(x.typ).unwrap(),
+        })
+        .collect();
+
+    for func in &module.funcs {
+        let func = emitter.function(func)?;
+        emitter.out.funcs.push(func);
+    }
+    let helpers = std::mem::take(&mut emitter.helpers);
+    emitter
+        .out
+        .funcs
+        .extend(helpers.into_iter().map(|(_, func)| func));
+    Ok(emitter.out)
+}
+
+// Integers up to 32 bits, bools and function values all go in an i32
+fn valtype(typ: Type) -> Result<ValType, String> {
+    Ok(match (typ, typ.bits()) {
+        (Type::Bool | Type::Func, _) => ValType::I32,
+        (_, 128) => return Err(format!("{} doesn't fit in any wasm type", typ)),
+        (Type::Float(_), 64) => ValType::F64,
+        (Type::Float(_), _) => ValType::F32,
+        (_, 64) => ValType::I64,
+        _ => ValType::I32,
+    })
+}
+
+fn num(name: &str) -> Instr {
+    Instr::Num(opcode(name))
+}
+
+struct Emitter<'a> {
+    module: &'a ir::Module,
+    out: Module,
+    funcs: HashMap<&'a str, u32>,
+    globals: HashMap<&'a str, u32>,
+    // The float math from the host, by the operation
+    imports: HashMap<BinOp, u32>,
+    // Functions the code needs that aren't in the IR, they go after the rest
+    helpers: Vec<(String, Func)>,
+    first_helper: u32,
+}
+
+// Where a br can go, innermost last
+#[derive(Clone, Copy, PartialEq)]
+enum Frame {
+    If,
+    // Going out of it runs the IR block
+    BlockFollowedBy(Block),
+    // Going back to its start runs the IR block again
+    LoopHeadedBy(Block),
+}
+
+// What a function needs while its body is put together
+struct Body<'a> {
+    func: &'a ir::Function,
+    cfg: Cfg,
+    // Where each block is in reverse postorder, edges going to an earlier block are back edges
+    index: Vec<usize>,
+    // The blocks each block immediately dominates
+    children: Vec<Vec<Block>>,
+    types: HashMap<Value, Type>,
+    locals: HashMap<Value, u32>,
+    // Where control coming into a block leaves the value for each of its phis
+    incoming: HashMap<Value, u32>,
+    code: Vec<Instr>,
+}
+
+impl<'a> Emitter<'a> {
+    fn function(&mut self, func: &'a ir::Function) -> Result<Func, String> {
+        let params: Vec<ValType> = func.params.iter().map(|x| valtype(x.1).unwrap()).collect();
+        let results: Vec<ValType> = func.ret.map(|x| valtype(x).unwrap()).into_iter().collect();
+        let typ = self.out.type_index(FuncType {
+            params: params.clone(),
+            results,
+        });
+
+        let cfg = Cfg::new(func);
+        let mut index = vec![usize::MAX; func.blocks.len()];
+        for (idx, block) in cfg.order.iter().enumerate() {
+            index[block.0 as usize] = idx;
+        }
+        let mut children = vec![Vec::new(); func.blocks.len()];
+        for &block in &cfg.order {
+            if let Some(idom) = cfg.idom[block.0 as usize] {
+                children[idom.0 as usize].push(block);
+            }
+        }
+
+        // Every value gets a local, with the parameters first
+        let mut locals: HashMap<Value, u32> = (0..)
+            .zip(func.params.iter().map(|x| x.0))
+            .map(|(idx, val)| (val, idx))
+            .collect();
+        let mut incoming = HashMap::new();
+        let mut types = Vec::new();
+        for inst in func.blocks.iter().flat_map(|x| &x.insts) {
+            let Some((val, typ)) = inst.dest else {
+                continue;
+            };
+            let next = |types: &mut Vec<ValType>| {
+                types.push(valtype(typ).unwrap());
+                (params.len() + types.len() - 1) as u32
+            };
+            locals.insert(val, next(&mut types));
+            if let Op::Phi(_) = inst.op {
+                incoming.insert(val, next(&mut types));
+            }
+        }
+
+        let mut body = Body {
+            func,
+            cfg,
+            index,
+            children,
+            types: func.types(),
+            locals,
+            incoming,
+            code: Vec::new(),
+        };
+        for block in body.cfg.order.clone() {
+            for &pred in body.cfg.preds(block) {
+                if body.cfg.is_reachable(pred)
+                    && body.is_back_edge(pred, block)
+                    && !body.cfg.dominates(block, pred)
+                {
+                    return Err(format!(
+                        "@{}: control flow can't be made structured",
+                        func.name
+                    ));
+                }
+            }
+        }
+
+        self.tree(&mut body, Block(0), &mut Vec::new());
+        // Every path ends in a br or a return, but the validator doesn't know that
+        if func.ret.is_some() {
+            body.code.push(Instr::Unreachable);
+        }
+
+        Ok(Func {
+            name: func.name.clone(),
+            typ,
+            locals: types,
+            body: body.code,
+            export: true,
+        })
+    }
+
+    // The block and everything it dominates, in a loop if something jumps back to it
+    fn tree(&mut self, body: &mut Body, block: Block, ctx: &mut Vec<Frame>) {
+        let mut merges: Vec<Block> = body.children[block.0 as usize]
+            .iter()
+            .copied()
+            .filter(|&x| body.is_merge(x))
+            .collect();
+        merges.sort_by_key(|x| std::cmp::Reverse(body.index[x.0 as usize]));
+
+        if body.is_loop_header(block) {
+            body.code.push(Instr::Loop(None));
+            ctx.push(Frame::LoopHeadedBy(block));
+            self.within(body, block, &merges, ctx);
+            ctx.pop();
+            body.code.push(Instr::End);
+        } else {
+            self.within(body, block, &merges, ctx);
+        }
+    }
+
+    // The block's own code inside one block for each of the merges, which come after it with
+    // the last one outermost
+    fn within(&mut self, body: &mut Body, block: Block, merges: &[Block], ctx: &mut Vec<Frame>) {
+        if let Some((&last, rest)) = merges.split_first() {
+            body.code.push(Instr::Block(None));
+            ctx.push(Frame::BlockFollowedBy(last));
+            self.within(body, block, rest, ctx);
+            ctx.pop();
+            body.code.push(Instr::End);
+            self.tree(body, last, ctx);
+            return;
+        }
+
+        let data = &body.func[block];
+        for inst in &data.insts {
+            self.inst(body, inst);
+        }
+        match data.term {
+            Terminator::Jump(to) => self.branch(body, block, to, ctx),
+            Terminator::Branch(_, yes, no) if yes == no => self.branch(body, block, yes, ctx),
+            Terminator::Branch(cond, yes, no) => {
+                body.get(cond);
+                body.code.push(Instr::If(None));
+                ctx.push(Frame::If);
+                self.branch(body, block, yes, ctx);
+                body.code.push(Instr::Else);
+                self.branch(body, block, no, ctx);
+                ctx.pop();
+                body.code.push(Instr::End);
+            }
+            Terminator::Return(val) => {
+                if let Some(val) = val {
+                    body.get(val);
+                }
+                body.code.push(Instr::Return);
+            }
+        }
+    }
+
+    // Back edges and merges are somewhere a br can go, anything else only has this one way in
+    // so it goes right here
+    fn branch(&mut self, body: &mut Body, from: Block, to: Block, ctx: &mut Vec<Frame>) {
+        let copies: Vec<(Value, Value)> = body.func[to]
+            .insts
+            .iter()
+            .filter_map(|inst| match (&inst.op, inst.dest) {
+                (Op::Phi(incoming), Some((dest, _))) => {
+                    let (_, val) = incoming.iter().find(|(x, _)| *x == from).unwrap();
+                    Some((dest, *val))
+                }
+                _ => None,
+            })
+            .collect();
+        for (dest, val) in copies {
+            body.get(val);
+            body.code.push(Instr::LocalSet(body.incoming[&dest]));
+        }
+
+        let target = match body.is_back_edge(from, to) {
+            true => Frame::LoopHeadedBy(to),
+            false if body.is_merge(to) => Frame::BlockFollowedBy(to),
+            false => return self.tree(body, to, ctx),
+        };
+        let pos = ctx.iter().rposition(|x| *x == target).unwrap();
+        body.code.push(Instr::Br((ctx.len() - 1 - pos) as u32));
+    }
+
+    fn inst(&mut self, body: &mut Body, inst: &ir::Inst) {
+        let code = &mut body.code;
+        match &inst.op {
+            Op::Line(..) => return,
+            Op::Phi(_) => {
+                let dest = inst.dest.unwrap().0;
+                code.push(Instr::LocalGet(body.incoming[&dest]));
+            }
+            &Op::Const(val) => code.push(constant(val, inst.dest.unwrap().1)),
+            Op::Func(name) => {
+                let func = self.funcs[name.as_str()];
+                let idx = match self.out.table.iter().position(|&x| x == func) {
+                    Some(idx) => idx,
+                    None => {
+                        self.out.table.push(func);
+                        self.out.table.len() - 1
+                    }
+                };
+                code.push(Instr::I32Const(idx as i32));
+            }
+            &Op::Binary(op, lhs, rhs) => self.binary(body, op, lhs, rhs),
+            &Op::Unary(op, val) => unary(body, op, val),
+            Op::Call(callee, args) => {
+                for &arg in args {
+                    body.get(arg);
+                }
+                match callee {
+                    Callee::Direct(name) => {
+                        body.code.push(Instr::Call(self.funcs[name.as_str()]));
+                        let ret = self.module.func(name).unwrap().ret;
+                        if inst.dest.is_none() && ret.is_some() {
+                            body.code.push(Instr::Drop);
+                        }
+                    }
+                    &Callee::Indirect(func) => {
+                        let typ = FuncType {
+                            params: args
+                                .iter()
+                                .map(|x| valtype(body.types[x]).unwrap())
+                                .collect(),
+                            results: inst
+                                .dest
+                                .map(|x| valtype(x.1).unwrap())
+                                .into_iter()
+                                .collect(),
+                        };
+                        let typ = self.out.type_index(typ);
+                        body.get(func);
+                        body.code.push(Instr::CallIndirect(typ));
+                    }
+                }
+            }
+            Op::Load(name) => code.push(Instr::GlobalGet(self.globals[name.as_str()])),
+            Op::Store(name, val) => {
+                body.get(*val);
+                body.code
+                    .push(Instr::GlobalSet(self.globals[name.as_str()]));
+            }
+        }
+        if let Some((dest, _)) = inst.dest {
+            body.code.push(Instr::LocalSet(body.locals[&dest]));
+        }
+    }
+
+    fn binary(&mut self, body: &mut Body, op: BinOp, lhs: Value, rhs: Value) {
+        let typ = body.types[&lhs];
+        let vt = valtype(typ).unwrap();
+        let p = vt.name();
+        let bits = typ.bits();
+        let signed = matches!(typ, Type::Int(_));
+        let s = if signed { "_s" } else { "_u" };
+
+        let name = match (typ, op) {
+            (Type::Float(_), BinOp::Rem | BinOp::Pow) => {
+                // The host only has them for f64
+                let import = self.imports[&op];
+                for val in [lhs, rhs] {
+                    body.get(val);
+                    if vt == ValType::F32 {
+                        body.code.push(num("f64.promote_f32"));
+                    }
+                }
+                body.code.push(Instr::Call(import));
+                if vt == ValType::F32 {
+                    body.code.push(num("f32.demote_f64"));
+                }
+                return;
+            }
+            (Type::Float(_), _) => match op {
+                BinOp::Add => "add",
+                BinOp::Sub => "sub",
+                BinOp::Mul => "mul",
+                BinOp::Div => "div",
+                op => compare(op, ""),
+            },
+            (Type::Bool | Type::Func, _) => match op {
+                BinOp::And => "and",
+                BinOp::Or => "or",
+                BinOp::Xor => "xor",
+                BinOp::Nand | BinOp::Nor => {
+                    body.get(lhs);
+                    body.get(rhs);
+                    body.code.push(num(if op == BinOp::Nand {
+                        "i32.and"
+                    } else {
+                        "i32.or"
+                    }));
+                    body.code.push(num("i32.eqz"));
+                    return;
+                }
+                BinOp::Xand => "eq",
+                op => compare(op, "_u"),
+            },
+            _ => match op {
+                BinOp::Add | BinOp::Sub | BinOp::Mul => {
+                    let name = match op {
+                        BinOp::Add => "add",
+                        BinOp::Sub => "sub",
+                        _ => "mul",
+                    };
+                    body.get(lhs);
+                    body.get(rhs);
+                    body.code.push(num(&format!("{}.{}", p, name)));
+                    body.code.extend(wrap(typ));
+                    return;
+                }
+                BinOp::Div => {
+                    trap_if(body, rhs, &[num(&format!("{}.eqz", p))]);
+                    // The one division that overflows, and traps in wasm
+                    if signed && bits >= 32 {
+                        body.get(rhs);
+                        body.code.push(int(vt, -1));
+                        body.code.push(num(&format!("{}.eq", p)));
+                        body.code.push(Instr::If(Some(vt)));
+                        body.code.push(int(vt, 0));
+                        body.get(lhs);
+                        body.code.push(num(&format!("{}.sub", p)));
+                        body.code.push(Instr::Else);
+                        body.get(lhs);
+                        body.get(rhs);
+                        body.code.push(num(&format!("{}.div_s", p)));
+                        body.code.push(Instr::End);
+                    } else {
+                        body.get(lhs);
+                        body.get(rhs);
+                        body.code.push(num(&format!("{}.div{}", p, s)));
+                        body.code.extend(wrap(typ));
+                    }
+                    return;
+                }
+                BinOp::Rem => {
+                    trap_if(body, rhs, &[num(&format!("{}.eqz", p))]);
+                    if signed {
+                        "rem_s"
+                    } else {
+                        "rem_u"
+                    }
+                }
+                BinOp::Pow => {
+                    let helper = self.pow(vt, signed);
+                    body.get(lhs);
+                    body.get(rhs);
+                    body.code.push(Instr::Call(helper));
+                    body.code.extend(wrap(typ));
+                    return;
+                }
+                BinOp::Shl | BinOp::Shr | BinOp::Ushr => {
+                    // Negative amounts are huge when they're unsigned
+                    let check = [int(vt, bits as i64), num(&format!("{}.ge_u", p))];
+                    trap_if(body, rhs, &check);
+                    body.get(lhs);
+                    if op == BinOp::Ushr && signed && bits < 32 {
+                        body.code.push(int(vt, (1 << bits) - 1));
+                        body.code.push(num("i32.and"));
+                    }
+                    body.get(rhs);
+                    let name = match op {
+                        BinOp::Shl => "shl".to_owned(),
+                        BinOp::Shr => format!("shr{}", s),
+                        _ => "shr_u".to_owned(),
+                    };
+                    body.code.push(num(&format!("{}.{}", p, name)));
+                    body.code.extend(wrap(typ));
+                    return;
+                }
+                BinOp::And => "and",
+                BinOp::Or => "or",
+                BinOp::Xor => "xor",
+                BinOp::Nand | BinOp::Nor | BinOp::Xand => {
+                    let name = match op {
+                        BinOp::Nand => "and",
+                        BinOp::Nor => "or",
+                        _ => "xor",
+                    };
+                    body.get(lhs);
+                    body.get(rhs);
+                    body.code.push(num(&format!("{}.{}", p, name)));
+                    body.code.push(int(vt, -1));
+                    body.code.push(num(&format!("{}.xor", p)));
+                    body.code.extend(wrap(typ));
+                    return;
+                }
+                op => compare(op, s),
+            },
+        };
+
+        body.get(lhs);
+        body.get(rhs);
+        body.code.push(num(&format!("{}.{}", p, name)));
+    }
+
+    // Integer powers by squaring, one for each type and signedness
+    fn pow(&mut self, vt: ValType, signed: bool) -> u32 {
+        let p = vt.name();
+        let name = format!("fusion.pow.{}{}", if signed { "s" } else { "u" }, &p[1..]);
+        if let Some(pos) = self.helpers.iter().position(|(x, _)| *x == name) {
+            return self.first_helper + pos as u32;
+        }
+
+        let (base, exp, result) = (0, 1, 2);
+        let mut code = Vec::new();
+        if signed {
+            code.extend([
+                Instr::LocalGet(exp),
+                int(vt, 0),
+                num(&format!("{}.lt_s", p)),
+            ]);
+            code.extend([Instr::If(None), Instr::Unreachable, Instr::End]);
+        }
+        // The interpreter stops counting there too
+        if vt == ValType::I64 {
+            let max = [Instr::I64Const(u32::MAX as i64)];
+            code.push(Instr::LocalGet(exp));
+            code.extend(max.clone());
+            code.push(num("i64.gt_u"));
+            code.push(Instr::If(None));
+            code.extend(max);
+            code.extend([Instr::LocalSet(exp), Instr::End]);
+        }
+        code.extend([int(vt, 1), Instr::LocalSet(result)]);
+        code.extend([Instr::Block(None), Instr::Loop(None)]);
+        code.extend([
+            Instr::LocalGet(exp),
+            num(&format!("{}.eqz", p)),
+            Instr::BrIf(1),
+        ]);
+        code.extend([Instr::LocalGet(exp), int(vt, 1), num(&format!("{}.and", p))]);
+        code.extend([num(&format!("{}.eqz", p)), num("i32.eqz"), Instr::If(None)]);
+        code.extend([Instr::LocalGet(result), Instr::LocalGet(base)]);
+        code.extend([
+            num(&format!("{}.mul", p)),
+            Instr::LocalSet(result),
+            Instr::End,
+        ]);
+        code.extend([Instr::LocalGet(base), Instr::LocalGet(base)]);
+        code.extend([num(&format!("{}.mul", p)), Instr::LocalSet(base)]);
+        code.extend([
+            Instr::LocalGet(exp),
+            int(vt, 1),
+            num(&format!("{}.shr_u", p)),
+        ]);
+        code.extend([Instr::LocalSet(exp), Instr::Br(0), Instr::End, Instr::End]);
+        code.push(Instr::LocalGet(result));
+
+        let typ = self.out.type_index(FuncType {
+            params: vec![vt; 2],
+            results: vec![vt],
+        });
+        self.helpers.push((
+            name.clone(),
+            Func {
+                name,
+                typ,
+                locals: vec![vt],
+                body: code,
+                export: false,
+            },
+        ));
+        self.first_helper + self.helpers.len() as u32 - 1
+    }
+}
+
+impl Body<'_> {
+    fn get(&mut self, val: Value) {
+        self.code.push(Instr::LocalGet(self.locals[&val]));
+    }
+
+    fn is_back_edge(&self, from: Block, to: Block) -> bool {
+        self.index[to.0 as usize] <= self.index[from.0 as usize]
+    }
+
+    fn is_loop_header(&self, block: Block) -> bool {
+        let preds = self.cfg.preds(block);
+        preds
+            .iter()
+            .any(|&x| self.cfg.is_reachable(x) && self.is_back_edge(x, block))
+    }
+
+    // More than one way in that isn't a back edge
+    fn is_merge(&self, block: Block) -> bool {
+        let preds = self.cfg.preds(block);
+        let forward = preds
+            .iter()
+            .filter(|&&x| self.cfg.is_reachable(x) && !self.is_back_edge(x, block));
+        forward.count() > 1
+    }
+}
+
+fn compare(op: BinOp, s: &str) -> &'static str {
+    match (op, s) {
+        (BinOp::Eq, _) => "eq",
+        (BinOp::Ne, _) => "ne",
+        (BinOp::Lt, "") => "lt",
+        (BinOp::Le, "") => "le",
+        (BinOp::Gt, "") => "gt",
+        (BinOp::Ge, "") => "ge",
+        (BinOp::Lt, "_s") => "lt_s",
+        (BinOp::Le, "_s") => "le_s",
+        (BinOp::Gt, "_s") => "gt_s",
+        (BinOp::Ge, "_s") => "ge_s",
+        (BinOp::Lt, _) => "lt_u",
+        (BinOp::Le, _) => "le_u",
+        (BinOp::Gt, _) => "gt_u",
+        (BinOp::Ge, _) => "ge_u",
+        _ => unreachable!("{} isn't a comparison", op),
+    }
+}
+
+fn unary(body: &mut Body, op: UnOp, val: Value) {
+    let typ = body.types[&val];
+    let vt = valtype(typ).unwrap();
+    let p = vt.name();
+    let bits = typ.bits();
+    match (op, typ) {
+        (UnOp::Neg, Type::Float(_)) => {
+            body.get(val);
+            body.code.push(num(&format!("{}.neg", p)));
+        }
+        (UnOp::Neg, _) => {
+            body.code.push(int(vt, 0));
+            body.get(val);
+            body.code.push(num(&format!("{}.sub", p)));
+            body.code.extend(wrap(typ));
+        }
+        (UnOp::Not, Type::Bool) => {
+            body.get(val);
+            body.code.push(num("i32.eqz"));
+        }
+        (UnOp::Not, _) => {
+            body.get(val);
+            body.code.push(int(vt, -1));
+            body.code.push(num(&format!("{}.xor", p)));
+            body.code.extend(wrap(typ));
+        }
+        // Only the bits that are part of the type count
+        (UnOp::Clz, _) if bits < 32 => {
+            body.get(val);
+            body.code.push(int(vt, (1 << bits) - 1));
+            body.code.extend([num("i32.and"), num("i32.clz")]);
+            body.code
+                .extend([int(vt, 32 - bits as i64), num("i32.sub")]);
+        }
+        (UnOp::Ctz, _) if bits < 32 => {
+            body.get(val);
+            body.code
+                .extend([int(vt, 1 << bits), num("i32.or"), num("i32.ctz")]);
+        }
+        (UnOp::Clz | UnOp::Ctz, _) => {
+            body.get(val);
+            body.code.push(num(&format!("{}.{}", p, op)));
+        }
+    }
+}
+
+// Traps if the value does what the check says
+fn trap_if(body: &mut Body, val: Value, check: &[Instr]) {
+    body.get(val);
+    body.code.extend(check.iter().cloned());
+    body.code
+        .extend([Instr::If(None), Instr::Unreachable, Instr::End]);
+}
+
+// Integers narrower than 32 bits are kept sign or zero extended in an i32, the same as the IR
+fn wrap(typ: Type) -> Vec<Instr> {
+    let bits = typ.bits() as i32;
+    match typ {
+        _ if bits >= 32 => Vec::new(),
+        Type::Int(_) => vec![
+            Instr::I32Const(32 - bits),
+            num("i32.shl"),
+            Instr::I32Const(32 - bits),
+            num("i32.shr_s"),
+        ],
+        _ => vec![Instr::I32Const((1 << bits) - 1), num("i32.and")],
+    }
+}
+
+fn int(vt: ValType, val: i64) -> Instr {
+    match vt {
+        ValType::I64 => Instr::I64Const(val),
+        _ => Instr::I32Const(val as i32),
+    }
+}
+
+fn constant(val: Const, typ: Type) -> Instr {
+    match (val, valtype(typ).unwrap()) {
+        (Const::Int(x), ValType::I64) => Instr::I64Const(x as i64),
+        (Const::Int(x), _) => Instr::I32Const(x as i32),
+        (Const::Float(x), ValType::F32) => Instr::F32Const(x as f32),
+        (Const::Float(x), _) => Instr::F64Const(x),
+        (Const::Bool(x), _) => Instr::I32Const(x as i32),
+    }
+}
@@ -0,0 +1,223 @@
+use super::{Instr, Module, ValType};
+
+const MAGIC: &[u8] = b"\0asm";
+const VERSION: [u8; 4] = [1, 0, 0, 0];
+
+impl Module {
+    // The binary format, every section in the order the spec wants them
+    pub fn encode(&self) -> Vec<u8> {
+        let mut out = MAGIC.to_vec();
+        out.extend(VERSION);
+
+        section(&mut out, 1, &self.types, |out, typ| {
+            out.push(0x60);
+            vec_of(out, &typ.params, |out, x| out.push(x.code()));
+            vec_of(out, &typ.results, |out, x| out.push(x.code()));
+        });
+        section(&mut out, 2, &self.imports, |out, import| {
+            name(out, &import.module);
+            name(out, &import.name);
+            out.push(0x00);
+            unsigned(out, import.typ as u64);
+        });
+        section(&mut out, 3, &self.funcs, |out, func| {
+            unsigned(out, func.typ as u64)
+        });
+        if !self.table.is_empty() {
+            let tables = [self.table.len()];
+            section(&mut out, 4, &tables, |out, &len| {
+                out.push(0x70);
+                out.push(0x01);
+                unsigned(out, len as u64);
+                unsigned(out, len as u64);
+            });
+        }
+        section(&mut out, 6, &self.globals, |out, global| {
+            out.push(global.typ.code());
+            out.push(0x01);
+            instr(out, &zero(global.typ));
+            instr(out, &Instr::End);
+        });
+
+        let first = self.imports.len() as u32;
+        let exports: Vec<(u32, &str)> = (first..)
+            .zip(&self.funcs)
+            .filter(|(_, func)| func.export)
+            .map(|(idx, func)| (idx, func.name.as_str()))
+            .collect();
+        section(&mut out, 7, &exports, |out, &(idx, export)| {
+            name(out, export);
+            out.push(0x00);
+            unsigned(out, idx as u64);
+        });
+
+        if !self.table.is_empty() {
+            let elems = [&self.table];
+            section(&mut out, 9, &elems, |out, funcs| {
+                unsigned(out, 0);
+                instr(out, &Instr::I32Const(0));
+                instr(out, &Instr::End);
+                vec_of(out, funcs, |out, &x| unsigned(out, x as u64));
+            });
+        }
+        section(&mut out, 10, &self.funcs, |out, func| {
+            let mut body = Vec::new();
+            // Locals are written as runs of the same type
+            let mut runs: Vec<(u32, ValType)> = Vec::new();
+            for &typ in &func.locals {
+                match runs.last_mut() {
+                    Some((count, last)) if *last == typ => *count += 1,
+                    _ => runs.push((1, typ)),
+                }
+            }
+            vec_of(&mut body, &runs, |out, &(count, typ)| {
+                unsigned(out, count as u64);
+                out.push(typ.code());
+            });
+            for x in &func.body {
+                instr(&mut body, x);
+            }
+            instr(&mut body, &Instr::End);
+
+            unsigned(out, body.len() as u64);
+            out.extend(body);
+        });
+        out
+    }
+}
+
+pub(super) fn zero(typ: ValType) -> Instr {
+    match typ {
+        ValType::I32 => Instr::I32Const(0),
+        ValType::I64 => Instr::I64Const(0),
+        ValType::F32 => Instr::F32Const(0.0),
+        ValType::F64 => Instr::F64Const(0.0),
+    }
+}
+
+// Empty sections are left out altogether
+fn section<T>(out: &mut Vec<u8>, id: u8, items: &[T], item: impl FnMut(&mut Vec<u8>, &T)) {
+    if items.is_empty() {
+        return;
+    }
+    let mut content = Vec::new();
+    vec_of(&mut content, items, item);
+    out.push(id);
+    unsigned(out, content.len() as u64);
+    out.extend(content);
+}
+
+fn vec_of<T>(out: &mut Vec<u8>, items: &[T], mut item: impl FnMut(&mut Vec<u8>, &T)) {
+    unsigned(out, items.len() as u64);
+    for x in items {
+        item(out, x);
+    }
+}
+
+fn name(out: &mut Vec<u8>, text: &str) {
+    unsigned(out, text.len() as u64);
+    out.extend(text.as_bytes());
+}
+
+fn block_type(out: &mut Vec<u8>, typ: Option<ValType>) {
+    out.push(typ.map_or(0x40, |x| x.code()));
+}
+
+fn instr(out: &mut Vec<u8>, instr: &Instr) {
+    match *instr {
+        Instr::Unreachable => out.push(0x00),
+        Instr::Block(typ) => {
+            out.push(0x02);
+            block_type(out, typ);
+        }
+        Instr::Loop(typ) => {
+            out.push(0x03);
+            block_type(out, typ);
+        }
+        Instr::If(typ) => {
+            out.push(0x04);
+            block_type(out, typ);
+        }
+        Instr::Else => out.push(0x05),
+        Instr::End => out.push(0x0b),
+        Instr::Br(depth) => {
+            out.push(0x0c);
+            unsigned(out, depth as u64);
+        }
+        Instr::BrIf(depth) => {
+            out.push(0x0d);
+            unsigned(out, depth as u64);
+        }
+        Instr::Return => out.push(0x0f),
+        Instr::Call(func) => {
+            out.push(0x10);
+            unsigned(out, func as u64);
+        }
+        Instr::CallIndirect(typ) => {
+            out.push(0x11);
+            unsigned(out, typ as u64);
+            out.push(0x00);
+        }
+        Instr::Drop => out.push(0x1a),
+        Instr::LocalGet(idx) => {
+            out.push(0x20);
+            unsigned(out, idx as u64);
+        }
+        Instr::LocalSet(idx) => {
+            out.push(0x21);
+            unsigned(out, idx as u64);
+        }
+        Instr::GlobalGet(idx) => {
+            out.push(0x23);
+            unsigned(out, idx as u64);
+        }
+        Instr::GlobalSet(idx) => {
+            out.push(0x24);
+            unsigned(out, idx as u64);
+        }
+        Instr::I32Const(x) => {
+            out.push(0x41);
+            signed(out, x as i64);
+        }
+        Instr::I64Const(x) => {
+            out.push(0x42);
+            signed(out, x);
+        }
+        Instr::F32Const(x) => {
+            out.push(0x43);
+            out.extend(x.to_le_bytes());
+        }
+        Instr::F64Const(x) => {
+            out.push(0x44);
+            out.extend(x.to_le_bytes());
+        }
+        Instr::Num(code) => out.push(code),
+    }
+}
+
+// LEB128, seven bits at a time with the high bit saying more are coming
+fn unsigned(out: &mut Vec<u8>, mut val: u64) {
+    loop {
+        let byte = (val & 0x7f) as u8;
+        val >>= 7;
+        if val == 0 {
+            out.push(byte);
+            return;
+        }
+        out.push(byte | 0x80);
+    }
+}
+
+// Stops once the rest is all sign bits and the last byte's sign bit agrees
+fn signed(out: &mut Vec<u8>, mut val: i64) {
+    loop {
+        let byte = (val & 0x7f) as u8;
+        val >>= 7;
+        let done = (val == 0 && byte & 0x40 == 0) || (val == -1 && byte & 0x40 != 0);
+        if done {
+            out.push(byte);
+            return;
+        }
+        out.push(byte | 0x80);
+    }
+}
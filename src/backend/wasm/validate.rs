@@ -0,0 +1,564 @@
+use std::collections::HashSet;
+
+use super::{numeric, FuncType, ValType};
+
+// Decodes a binary module and checks it the way an engine would before running it: sections in
+// order and the right size, indices in range, and every function body type checking with the
+// stack machine from the spec. It only knows what the backend uses, so no memories
+pub fn validate(bytes: &[u8]) -> Result<(), String> {
+    let mut reader = Reader { bytes, pos: 0 };
+    let mut module = Decoded::default();
+    module
+        .read(&mut reader)
+        .map_err(|msg| format!("at byte {:#x}: {}", reader.pos, msg))
+}
+
+#[derive(Default)]
+struct Decoded {
+    types: Vec<FuncType>,
+    // The type of every function, imports first
+    funcs: Vec<u32>,
+    imported: usize,
+    globals: Vec<ValType>,
+    table: Option<u32>,
+    bodies: usize,
+}
+
+impl Decoded {
+    fn read(&mut self, reader: &mut Reader) -> Result<(), String> {
+        if reader.take(4)? != b"\0asm" {
+            return Err("not a wasm module".to_owned());
+        }
+        if reader.take(4)? != [1, 0, 0, 0] {
+            return Err("only version 1 is supported".to_owned());
+        }
+
+        let mut last = 0;
+        while !reader.done() {
+            let id = reader.byte()?;
+            let size = reader.u32()? as usize;
+            let end = reader.pos + size;
+            if end > reader.bytes.len() {
+                return Err(format!("section {} goes past the end", id));
+            }
+            // Custom sections can go anywhere, the rest have an order
+            if id != 0 {
+                if id <= last {
+                    return Err(format!("section {} is out of order", id));
+                }
+                last = id;
+            }
+
+            let mut section = Reader {
+                bytes: &reader.bytes[..end],
+                pos: reader.pos,
+            };
+            let res = self.section(id, &mut section);
+            reader.pos = section.pos;
+            res?;
+            if reader.pos != end {
+                return Err(format!("section {} is the wrong size", id));
+            }
+        }
+
+        if self.bodies != self.funcs.len() - self.imported {
+            return Err("there are functions without bodies".to_owned());
+        }
+        Ok(())
+    }
+
+    fn section(&mut self, id: u8, reader: &mut Reader) -> Result<(), String> {
+        match id {
+            0 => {
+                reader.name()?;
+                reader.pos = reader.bytes.len();
+            }
+            1 => {
+                for _ in 0..reader.u32()? {
+                    if reader.byte()? != 0x60 {
+                        return Err("expected a function type".to_owned());
+                    }
+                    let params = reader.vec(Reader::valtype)?;
+                    let results = reader.vec(Reader::valtype)?;
+                    if results.len() > 1 {
+                        return Err("functions give at most one value".to_owned());
+                    }
+                    self.types.push(FuncType { params, results });
+                }
+            }
+            2 => {
+                for _ in 0..reader.u32()? {
+                    reader.name()?;
+                    reader.name()?;
+                    if reader.byte()? != 0x00 {
+                        return Err("only functions can be imported".to_owned());
+                    }
+                    let typ = self.type_index(reader)?;
+                    self.funcs.push(typ);
+                    self.imported += 1;
+                }
+            }
+            3 => {
+                for _ in 0..reader.u32()? {
+                    let typ = self.type_index(reader)?;
+                    self.funcs.push(typ);
+                }
+            }
+            4 => {
+                if reader.u32()? != 1 {
+                    return Err("expected one table".to_owned());
+                }
+                if reader.byte()? != 0x70 {
+                    return Err("tables have to hold functions".to_owned());
+                }
+                let min = match reader.byte()? {
+                    0 => reader.u32()?,
+                    1 => {
+                        let min = reader.u32()?;
+                        if reader.u32()? < min {
+                            return Err("the table's maximum is below its minimum".to_owned());
+                        }
+                        min
+                    }
+                    _ => return Err("expected the table's limits".to_owned()),
+                };
+                self.table = Some(min);
+            }
+            6 => {
+                for _ in 0..reader.u32()? {
+                    let typ = reader.valtype()?;
+                    if reader.byte()? > 1 {
+                        return Err("expected whether the global is mutable".to_owned());
+                    }
+                    self.const_expr(reader, typ)?;
+                    self.globals.push(typ);
+                }
+            }
+            7 => {
+                let mut names = HashSet::new();
+                for _ in 0..reader.u32()? {
+                    let name = reader.name()?;
+                    if !names.insert(name.clone()) {
+                        return Err(format!("'{}' is exported twice", name));
+                    }
+                    let (kind, idx) = (reader.byte()?, reader.u32()? as usize);
+                    let count = match kind {
+                        0x00 => self.funcs.len(),
+                        0x03 => self.globals.len(),
+                        _ => return Err("only functions and globals can be exported".to_owned()),
+                    };
+                    if idx >= count {
+                        return Err(format!("'{}' exports something that isn't there", name));
+                    }
+                }
+            }
+            9 => {
+                for _ in 0..reader.u32()? {
+                    if reader.u32()? != 0 {
+                        return Err("only active elements for the table are supported".to_owned());
+                    }
+                    let Some(len) = self.table else {
+                        return Err("elements without a table".to_owned());
+                    };
+                    let offset = self.const_expr(reader, ValType::I32)?;
+                    let funcs = reader.vec(Reader::u32)?;
+                    if funcs.iter().any(|&x| x as usize >= self.funcs.len()) {
+                        return Err("an element isn't a function".to_owned());
+                    }
+                    if offset < 0 || offset as u64 + funcs.len() as u64 > len as u64 {
+                        return Err("the elements don't fit in the table".to_owned());
+                    }
+                }
+            }
+            10 => {
+                let count = reader.u32()? as usize;
+                if count != self.funcs.len() - self.imported {
+                    return Err("the number of bodies doesn't match the functions".to_owned());
+                }
+                for idx in self.imported..self.imported + count {
+                    let size = reader.u32()? as usize;
+                    let end = reader.pos + size;
+                    if end > reader.bytes.len() {
+                        return Err("the function body goes past the end".to_owned());
+                    }
+                    let mut body = Reader {
+                        bytes: &reader.bytes[..end],
+                        pos: reader.pos,
+                    };
+                    let res = self.body(idx, &mut body);
+                    reader.pos = body.pos;
+                    res.map_err(|msg| format!("function {}: {}", idx, msg))?;
+                    self.bodies += 1;
+                }
+            }
+            _ => return Err(format!("section {} isn't supported", id)),
+        }
+        Ok(())
+    }
+
+    fn type_index(&self, reader: &mut Reader) -> Result<u32, String> {
+        let typ = reader.u32()?;
+        match (typ as usize) < self.types.len() {
+            true => Ok(typ),
+            false => Err(format!("type {} doesn't exist", typ)),
+        }
+    }
+
+    // A constant and then end, which is all an initializer can be here. Gives integers back
+    fn const_expr(&self, reader: &mut Reader, typ: ValType) -> Result<i64, String> {
+        let (got, val) = match reader.byte()? {
+            0x41 => (ValType::I32, reader.signed(32)?),
+            0x42 => (ValType::I64, reader.signed(64)?),
+            0x43 => (ValType::F32, reader.take(4).map(|_| 0)?),
+            0x44 => (ValType::F64, reader.take(8).map(|_| 0)?),
+            _ => return Err("expected a constant".to_owned()),
+        };
+        if got != typ {
+            return Err(format!("expected {}, got {}", typ.name(), got.name()));
+        }
+        if reader.byte()? != 0x0b {
+            return Err("expected the end of the constant".to_owned());
+        }
+        Ok(val)
+    }
+
+    fn body(&self, idx: usize, reader: &mut Reader) -> Result<(), String> {
+        let typ = &self.types[self.funcs[idx] as usize];
+        let mut locals = typ.params.clone();
+        for _ in 0..reader.u32()? {
+            let count = reader.u32()? as usize;
+            let typ = reader.valtype()?;
+            if locals.len() + count > 50_000 {
+                return Err("too many locals".to_owned());
+            }
+            locals.extend(std::iter::repeat_n(typ, count));
+        }
+
+        let mut stack = Stack {
+            values: Vec::new(),
+            frames: vec![Frame {
+                kind: Kind::Block,
+                result: typ.results.first().copied(),
+                height: 0,
+                unreachable: false,
+            }],
+        };
+        while !stack.frames.is_empty() {
+            self.instr(reader, &mut stack, &locals, typ)?;
+        }
+        match reader.done() {
+            true => Ok(()),
+            false => Err("there's code after the end".to_owned()),
+        }
+    }
+
+    fn instr(
+        &self,
+        reader: &mut Reader,
+        stack: &mut Stack,
+        locals: &[ValType],
+        func: &FuncType,
+    ) -> Result<(), String> {
+        use ValType::*;
+
+        let local = |reader: &mut Reader| {
+            let idx = reader.u32()? as usize;
+            locals
+                .get(idx)
+                .copied()
+                .ok_or_else(|| format!("local {} doesn't exist", idx))
+        };
+        let global = |reader: &mut Reader| {
+            let idx = reader.u32()? as usize;
+            self.globals
+                .get(idx)
+                .copied()
+                .ok_or_else(|| format!("global {} doesn't exist", idx))
+        };
+        let block_type = |reader: &mut Reader| match reader.byte()? {
+            0x40 => Ok(None),
+            code => match ValType::from_code(code) {
+                Some(typ) => Ok(Some(typ)),
+                None => Err("expected a block type".to_owned()),
+            },
+        };
+
+        let code = reader.byte()?;
+        match code {
+            0x00 => stack.unreachable(),
+            0x01 => {}
+            0x02 | 0x03 => {
+                let result = block_type(reader)?;
+                let kind = if code == 0x02 {
+                    Kind::Block
+                } else {
+                    Kind::Loop
+                };
+                stack.push_frame(kind, result);
+            }
+            0x04 => {
+                let result = block_type(reader)?;
+                stack.pop(Some(I32))?;
+                stack.push_frame(Kind::If, result);
+            }
+            0x05 => {
+                let frame = stack.pop_frame()?;
+                if frame.kind != Kind::If {
+                    return Err("else without an if".to_owned());
+                }
+                stack.push_frame(Kind::Else, frame.result);
+            }
+            0x0b => {
+                let frame = stack.pop_frame()?;
+                if frame.kind == Kind::If && frame.result.is_some() {
+                    return Err("an if without an else can't give a value".to_owned());
+                }
+                stack.values.extend(frame.result.map(Some));
+            }
+            0x0c | 0x0d => {
+                let depth = reader.u32()? as usize;
+                if depth >= stack.frames.len() {
+                    return Err(format!("br {} goes past the function", depth));
+                }
+                let target = &stack.frames[stack.frames.len() - 1 - depth];
+                // Going to a loop starts it again, which takes nothing
+                let label = match target.kind {
+                    Kind::Loop => None,
+                    _ => target.result,
+                };
+                if code == 0x0d {
+                    stack.pop(Some(I32))?;
+                }
+                if let Some(label) = label {
+                    stack.pop(Some(label))?;
+                }
+                match code {
+                    0x0c => stack.unreachable(),
+                    _ => stack.values.extend(label.map(Some)),
+                }
+            }
+            0x0f => {
+                if let Some(&typ) = func.results.first() {
+                    stack.pop(Some(typ))?;
+                }
+                stack.unreachable();
+            }
+            0x10 => {
+                let idx = reader.u32()? as usize;
+                let Some(&typ) = self.funcs.get(idx) else {
+                    return Err(format!("function {} doesn't exist", idx));
+                };
+                stack.call(&self.types[typ as usize])?;
+            }
+            0x11 => {
+                let typ = self.type_index(reader)?;
+                if reader.byte()? != 0x00 || self.table.is_none() {
+                    return Err("call_indirect without a table".to_owned());
+                }
+                stack.pop(Some(I32))?;
+                stack.call(&self.types[typ as usize])?;
+            }
+            0x1a => {
+                stack.pop(None)?;
+            }
+            0x20 => stack.values.push(Some(local(reader)?)),
+            0x21 => {
+                let typ = local(reader)?;
+                stack.pop(Some(typ))?;
+            }
+            0x23 => stack.values.push(Some(global(reader)?)),
+            0x24 => {
+                let typ = global(reader)?;
+                stack.pop(Some(typ))?;
+            }
+            0x41 => {
+                reader.signed(32)?;
+                stack.values.push(Some(I32));
+            }
+            0x42 => {
+                reader.signed(64)?;
+                stack.values.push(Some(I64));
+            }
+            0x43 => {
+                reader.take(4)?;
+                stack.values.push(Some(F32));
+            }
+            0x44 => {
+                reader.take(8)?;
+                stack.values.push(Some(F64));
+            }
+            _ => {
+                let Some((_, params, result)) = numeric(code) else {
+                    return Err(format!("unknown instruction {:#04x}", code));
+                };
+                for &typ in params.iter().rev() {
+                    stack.pop(Some(typ))?;
+                }
+                stack.values.push(Some(result));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Kind {
+    Block,
+    Loop,
+    If,
+    Else,
+}
+
+struct Frame {
+    kind: Kind,
+    result: Option<ValType>,
+    // How many values were on the stack when it started
+    height: usize,
+    // After a br or a return anything can be on the stack
+    unreachable: bool,
+}
+
+// The types on the operand stack, None is one that could be anything
+struct Stack {
+    values: Vec<Option<ValType>>,
+    frames: Vec<Frame>,
+}
+
+impl Stack {
+    fn pop(&mut self, expected: Option<ValType>) -> Result<Option<ValType>, String> {
+        let frame = self.frames.last().unwrap();
+        if self.values.len() == frame.height {
+            return match frame.unreachable {
+                true => Ok(expected),
+                false => Err(format!(
+                    "expected {} on the stack, but it's empty",
+                    expected.map_or("a value", |x| x.name())
+                )),
+            };
+        }
+
+        let got = self.values.pop().unwrap();
+        match (got, expected) {
+            (Some(got), Some(expected)) if got != expected => {
+                Err(format!("expected {}, got {}", expected.name(), got.name()))
+            }
+            _ => Ok(got.or(expected)),
+        }
+    }
+
+    fn push_frame(&mut self, kind: Kind, result: Option<ValType>) {
+        self.frames.push(Frame {
+            kind,
+            result,
+            height: self.values.len(),
+            unreachable: false,
+        });
+    }
+
+    // The frame's result has to be all that's left of it
+    fn pop_frame(&mut self) -> Result<Frame, String> {
+        let Some(frame) = self.frames.last() else {
+            return Err("end without a block".to_owned());
+        };
+        if let Some(result) = frame.result {
+            self.pop(Some(result))?;
+        }
+        let frame = self.frames.pop().unwrap();
+        if self.values.len() != frame.height {
+            return Err("values are left on the stack at the end of a block".to_owned());
+        }
+        Ok(frame)
+    }
+
+    fn unreachable(&mut self) {
+        let frame = self.frames.last_mut().unwrap();
+        self.values.truncate(frame.height);
+        frame.unreachable = true;
+    }
+
+    fn call(&mut self, typ: &FuncType) -> Result<(), String> {
+        for &param in typ.params.iter().rev() {
+            self.pop(Some(param))?;
+        }
+        self.values.extend(typ.results.iter().map(|&x| Some(x)));
+        Ok(())
+    }
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn done(&self) -> bool {
+        self.pos >= self.bytes.len()
+    }
+
+    fn byte(&mut self) -> Result<u8, String> {
+        let byte = *self.bytes.get(self.pos).ok_or("unexpected end")?;
+        self.pos += 1;
+        Ok(byte)
+    }
+
+    fn take(&mut self, count: usize) -> Result<&'a [u8], String> {
+        if self.pos + count > self.bytes.len() {
+            return Err("unexpected end".to_owned());
+        }
+        self.pos += count;
+        Ok(&self.bytes[self.pos - count..self.pos])
+    }
+
+    fn u32(&mut self) -> Result<u32, String> {
+        let mut val: u64 = 0;
+        for shift in (0..35).step_by(7) {
+            let byte = self.byte()?;
+            val |= ((byte & 0x7f) as u64) << shift;
+            if byte & 0x80 == 0 {
+                return u32::try_from(val).map_err(|_| "the number is too big".to_owned());
+            }
+        }
+        Err("the number is too long".to_owned())
+    }
+
+    // An integer with at most this many bits
+    fn signed(&mut self, bits: u32) -> Result<i64, String> {
+        let mut val: i64 = 0;
+        let mut shift = 0;
+        loop {
+            let byte = self.byte()?;
+            if shift >= bits + 7 {
+                return Err("the number is too long".to_owned());
+            }
+            if shift < 64 {
+                val |= ((byte & 0x7f) as i64) << shift;
+            }
+            shift += 7;
+            if byte & 0x80 == 0 {
+                if shift < 64 && byte & 0x40 != 0 {
+                    val |= -1 << shift;
+                }
+                return Ok(val);
+            }
+        }
+    }
+
+    fn vec<T>(
+        &mut self,
+        mut item: impl FnMut(&mut Self) -> Result<T, String>,
+    ) -> Result<Vec<T>, String> {
+        let count = self.u32()?;
+        (0..count).map(|_| item(self)).collect()
+    }
+
+    fn name(&mut self) -> Result<String, String> {
+        let len = self.u32()? as usize;
+        let bytes = self.take(len)?;
+        String::from_utf8(bytes.to_vec()).map_err(|_| "a name isn't UTF-8".to_owned())
+    }
+
+    fn valtype(&mut self) -> Result<ValType, String> {
+        let code = self.byte()?;
+        ValType::from_code(code).ok_or_else(|| format!("{:#04x} isn't a value type", code))
+    }
+}
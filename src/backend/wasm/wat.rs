@@ -0,0 +1,140 @@
+use std::fmt;
+
+use super::{encode::zero, numeric, FuncType, Instr, Module, ValType};
+
+// The text format, with the instructions flat and indented by how deep in blocks they are
+impl fmt::Display for Module {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // Everything refers to functions by name, imports included
+        let names: Vec<String> = self
+            .imports
+            .iter()
+            .map(|x| format!("{}.{}", x.module, x.name))
+            .chain(self.funcs.iter().map(|x| x.name.clone()))
+            .collect();
+
+        writeln!(f, "(module")?;
+        for (idx, typ) in self.types.iter().enumerate() {
+            writeln!(f, "  (type (;{};) (func{}))", idx, signature(typ))?;
+        }
+        for (idx, import) in self.imports.iter().enumerate() {
+            writeln!(
+                f,
+                "  (import \"{}\" \"{}\" (func ${} (type {})))",
+                import.module, import.name, names[idx], import.typ
+            )?;
+        }
+        if !self.table.is_empty() {
+            writeln!(f, "  (table {} funcref)", self.table.len())?;
+        }
+        for global in &self.globals {
+            writeln!(
+                f,
+                "  (global ${} (mut {}) ({}))",
+                global.name,
+                global.typ.name(),
+                Show(&zero(global.typ), &names)
+            )?;
+        }
+        for func in self.funcs.iter().filter(|x| x.export) {
+            writeln!(f, "  (export \"{}\" (func ${}))", func.name, func.name)?;
+        }
+        if !self.table.is_empty() {
+            let funcs: Vec<String> = self
+                .table
+                .iter()
+                .map(|&x| format!("${}", names[x as usize]))
+                .collect();
+            writeln!(f, "  (elem (i32.const 0) func {})", funcs.join(" "))?;
+        }
+
+        for func in &self.funcs {
+            let typ = &self.types[func.typ as usize];
+            writeln!(
+                f,
+                "  (func ${} (type {}){}",
+                func.name,
+                func.typ,
+                signature(typ)
+            )?;
+            if !func.locals.is_empty() {
+                let locals: Vec<&str> = func.locals.iter().map(|x| x.name()).collect();
+                writeln!(f, "    (local {})", locals.join(" "))?;
+            }
+
+            let mut depth = 2;
+            for instr in &func.body {
+                if matches!(instr, Instr::End | Instr::Else) {
+                    depth -= 1;
+                }
+                writeln!(f, "{}{}", "  ".repeat(depth), Show(instr, &names))?;
+                if matches!(
+                    instr,
+                    Instr::Block(_) | Instr::Loop(_) | Instr::If(_) | Instr::Else
+                ) {
+                    depth += 1;
+                }
+            }
+            writeln!(f, "  )")?;
+        }
+        writeln!(f, ")")
+    }
+}
+
+fn signature(typ: &FuncType) -> String {
+    let mut out = String::new();
+    if !typ.params.is_empty() {
+        let params: Vec<&str> = typ.params.iter().map(|x| x.name()).collect();
+        out += &format!(" (param {})", params.join(" "));
+    }
+    if !typ.results.is_empty() {
+        let results: Vec<&str> = typ.results.iter().map(|x| x.name()).collect();
+        out += &format!(" (result {})", results.join(" "));
+    }
+    out
+}
+
+// An instruction, which needs the function names for calls
+struct Show<'a>(&'a Instr, &'a [String]);
+
+impl fmt::Display for Show<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let block = |f: &mut fmt::Formatter<'_>, name: &str, typ: Option<ValType>| match typ {
+            Some(typ) => write!(f, "{} (result {})", name, typ.name()),
+            None => write!(f, "{}", name),
+        };
+        match *self.0 {
+            Instr::Unreachable => write!(f, "unreachable"),
+            Instr::Block(typ) => block(f, "block", typ),
+            Instr::Loop(typ) => block(f, "loop", typ),
+            Instr::If(typ) => block(f, "if", typ),
+            Instr::Else => write!(f, "else"),
+            Instr::End => write!(f, "end"),
+            Instr::Br(depth) => write!(f, "br {}", depth),
+            Instr::BrIf(depth) => write!(f, "br_if {}", depth),
+            Instr::Return => write!(f, "return"),
+            Instr::Call(func) => write!(f, "call ${}", self.1[func as usize]),
+            Instr::CallIndirect(typ) => write!(f, "call_indirect (type {})", typ),
+            Instr::Drop => write!(f, "drop"),
+            Instr::LocalGet(idx) => write!(f, "local.get {}", idx),
+            Instr::LocalSet(idx) => write!(f, "local.set {}", idx),
+            Instr::GlobalGet(idx) => write!(f, "global.get {}", idx),
+            Instr::GlobalSet(idx) => write!(f, "global.set {}", idx),
+            Instr::I32Const(x) => write!(f, "i32.const {}", x),
+            Instr::I64Const(x) => write!(f, "i64.const {}", x),
+            Instr::F32Const(x) => write!(f, "f32.const {}", float(x as f64)),
+            Instr::F64Const(x) => write!(f, "f64.const {}", float(x)),
+            Instr::Num(code) => write!(f, "{}", numeric(code).unwrap().0),
+        }
+    }
+}
+
+// Debug always reads back exactly, it just spells the special values differently
+fn float(x: f64) -> String {
+    match x {
+        _ if x.is_nan() => "nan".to_owned(),
+        f64::INFINITY => "inf".to_owned(),
+        f64::NEG_INFINITY => "-inf".to_owned(),
+        _ => format!("{:?}", x),
+    }
+}
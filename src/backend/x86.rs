@@ -0,0 +1,310 @@
+mod asm;
+mod elf;
+mod encode;
+mod isel;
+mod jit;
+mod regalloc;
+
+use crate::ir::{self, Type};
+
+pub use self::{
+    encode::{Object, Reloc, RelocKind, RelocTarget},
+    jit::Jit,
+};
+
+// x86-64 for the System V ABI, so the functions can be called from C and linked with cc. Each
+// function gets its instructions picked with virtual registers, then a linear scan puts those in
+// real registers or stack slots. Every float is a double in a register, the same as the
+// interpreter, and only goes in and out as a float at calls when its type is float32. float16
+// and float128 go in and out as doubles. Traps print where they happened and exit(1) without
+// needing libc, float % and ^ call fmod and pow. Nothing 128 bits wide fits in a register, so
+// those are an error
+pub fn emit_asm(module: &ir::Module) -> Result<String, String> {
+    Ok(compile(module, Runtime::Process)?.to_string())
+}
+
+// A relocatable ELF object, the same code as emit_asm gives
+pub fn emit_object(module: &ir::Module) -> Result<Vec<u8>, String> {
+    Ok(elf::write(&compile(module, Runtime::Process)?.encode()))
+}
+
+// The symbol C sees a function as
+pub fn symbol(name: &str) -> String {
+    super::c::mangle("f", name)
+}
+
+// Where the code is going to run
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Runtime {
+    // A process of its own, called from C. Traps print and exit
+    Process,
+    // Inside fusion, only ever entered through the JIT's stubs. Traps go back to the stub, calls
+    // are counted the way the IR machine counts them and float32 stays a double even at calls
+    Jit,
+}
+
+pub fn compile(module: &ir::Module, runtime: Runtime) -> Result<Program, String> {
+    for global in &module.globals {
+        check(global.typ).map_err(|err| format!("@{}: {}", global.name, err))?;
+    }
+    for func in &module.funcs {
+        let types = func.types().into_values().chain(func.ret);
+        if let Some(Err(err)) = types.map(check).find(|x| x.is_err()) {
+            return Err(format!("@{}: {}", func.name, err));
+        }
+    }
+
+    let mut program = Program {
+        funcs: Vec::new(),
+        msgs: Vec::new(),
+        globals: module
+            .globals
+            .iter()
+            .map(|x| super::c::mangle("g", &x.name))
+            .collect(),
+    };
+    // The JIT's own globals go after the module's
+    let base = program.globals.len();
+    if runtime == Runtime::Jit {
+        program.globals.extend(jit::GLOBALS.map(str::to_owned));
+    }
+    for func in &module.funcs {
+        let code = isel::select(module, func, runtime, base, &mut program.msgs);
+        program.funcs.push(Func {
+            name: symbol(&func.name),
+            code: regalloc::allocate(code),
+            global: true,
+        });
+    }
+    program.funcs.push(Func {
+        name: "fusion_trap".to_owned(),
+        code: runtime.trap(base),
+        global: false,
+    });
+    Ok(program)
+}
+
+fn check(typ: Type) -> Result<(), String> {
+    match typ.bits() {
+        128 => Err(format!("{} doesn't fit in a register", typ)),
+        _ => Ok(()),
+    }
+}
+
+impl Runtime {
+    // The routine every trap calls, with the message in rdi and its length in rsi
+    fn trap(self, base: usize) -> Vec<Inst> {
+        match self {
+            Runtime::Process => vec![
+                Inst::Mov(Opnd::R(RDX), Opnd::R(RSI)),
+                Inst::Mov(Opnd::R(RSI), Opnd::R(RDI)),
+                // write(2, msg, len) then exit(1)
+                Inst::Mov(Opnd::R(RDI), Opnd::Imm(2)),
+                Inst::Mov(Opnd::R(RAX), Opnd::Imm(1)),
+                Inst::Syscall,
+                Inst::Mov(Opnd::R(RDI), Opnd::Imm(1)),
+                Inst::Mov(Opnd::R(RAX), Opnd::Imm(231)),
+                Inst::Syscall,
+            ],
+            Runtime::Jit => jit::trap(base),
+        }
+    }
+}
+
+// Everything in the object. The trap routine is the last function
+#[derive(Debug, Clone, PartialEq)]
+pub struct Program {
+    pub funcs: Vec<Func>,
+    // What traps print, in .rodata
+    pub msgs: Vec<String>,
+    // Eight bytes each in .bss
+    pub globals: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Func {
+    pub name: String,
+    pub code: Vec<Inst>,
+    pub global: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Reg {
+    Gpr(u8),
+    Xmm(u8),
+}
+
+pub const RAX: Reg = Reg::Gpr(0);
+pub const RCX: Reg = Reg::Gpr(1);
+pub const RDX: Reg = Reg::Gpr(2);
+pub const RBX: Reg = Reg::Gpr(3);
+pub const RSP: Reg = Reg::Gpr(4);
+pub const RBP: Reg = Reg::Gpr(5);
+pub const RSI: Reg = Reg::Gpr(6);
+pub const RDI: Reg = Reg::Gpr(7);
+pub const R8: Reg = Reg::Gpr(8);
+pub const R9: Reg = Reg::Gpr(9);
+pub const R10: Reg = Reg::Gpr(10);
+pub const R11: Reg = Reg::Gpr(11);
+pub const R12: Reg = Reg::Gpr(12);
+pub const R13: Reg = Reg::Gpr(13);
+pub const R14: Reg = Reg::Gpr(14);
+pub const R15: Reg = Reg::Gpr(15);
+
+impl Reg {
+    fn num(self) -> u8 {
+        match self {
+            Reg::Gpr(x) | Reg::Xmm(x) => x,
+        }
+    }
+}
+
+// Which kind of register a value needs
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Class {
+    Int,
+    Float,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Mem {
+    // A register plus an offset, for the frame
+    Base(Reg, i32),
+    // One of the globals, relative to rip
+    Global(usize),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Opnd {
+    // A virtual register, only until registers are allocated
+    V(u32),
+    R(Reg),
+    Imm(i64),
+    M(Mem),
+}
+
+// Somewhere code can point at outside of its own function
+#[derive(Debug, Clone, PartialEq)]
+pub enum Target {
+    Func(usize),
+    Msg(usize),
+    Extern(&'static str),
+}
+
+// The condition codes, numbered like the encoding numbers them
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Cond {
+    O,
+    No,
+    B,
+    Ae,
+    E,
+    Ne,
+    Be,
+    A,
+    S,
+    Ns,
+    P,
+    Np,
+    L,
+    Ge,
+    Le,
+    G,
+}
+
+impl Cond {
+    fn name(self) -> &'static str {
+        use Cond::*;
+        match self {
+            O => "o",
+            No => "no",
+            B => "b",
+            Ae => "ae",
+            E => "e",
+            Ne => "ne",
+            Be => "be",
+            A => "a",
+            S => "s",
+            Ns => "ns",
+            P => "p",
+            Np => "np",
+            L => "l",
+            Ge => "ge",
+            Le => "le",
+            G => "g",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Alu {
+    Add,
+    Or,
+    And,
+    Sub,
+    Xor,
+    Cmp,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Shift {
+    Shl,
+    Shr,
+    Sar,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Sse {
+    Addsd,
+    Subsd,
+    Mulsd,
+    Divsd,
+    Ucomisd,
+    Xorpd,
+    Cvtss2sd,
+    Cvtsd2ss,
+}
+
+// Integer instructions are all 64 bits wide. The destination comes first, like Intel syntax
+#[derive(Debug, Clone, PartialEq)]
+pub enum Inst {
+    Mov(Opnd, Opnd),
+    MovAbs(Opnd, i64),
+    Movsd(Opnd, Opnd),
+    // The bits of an integer register into a float one and back
+    ToXmm(Opnd, Opnd),
+    FromXmm(Opnd, Opnd),
+    Alu(Alu, Opnd, Opnd),
+    Test(Opnd, Opnd),
+    Imul(Opnd, Opnd),
+    Neg(Opnd),
+    Not(Opnd),
+    // By cl when there's no count
+    Shift(Shift, Opnd, Option<u8>),
+    // Sign or zero extends the low bits of the source
+    Extend(u32, bool, Opnd, Opnd),
+    Cqo,
+    Idiv(Opnd),
+    Div(Opnd),
+    // One if the condition holds, otherwise zero. Goes through al
+    Set(Cond, Opnd),
+    Bsr(Opnd, Opnd),
+    Bsf(Opnd, Opnd),
+    Sse(Sse, Opnd, Opnd),
+    Lea(Opnd, Target),
+    Label(usize),
+    Jmp(usize),
+    Jcc(Cond, usize),
+    Call(Target),
+    CallInd(Opnd),
+    // All at once, so they can read what the others write. Only until registers are allocated
+    Moves(Vec<(Opnd, Opnd, Class)>),
+    // The epilogue and a ret, until the frame is known
+    Return,
+    Push(Reg),
+    Pop(Reg),
+    Ret,
+    Syscall,
+    // Where the code after it came from, only kept as a comment
+    Line(String, u32),
+}
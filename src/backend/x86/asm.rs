@@ -0,0 +1,187 @@
+use std::fmt::{self, Write};
+
+use super::{Alu, Inst, Mem, Opnd, Program, Reg, Shift, Sse, Target};
+
+const NAMES: [&str; 16] = [
+    "ax", "cx", "dx", "bx", "sp", "bp", "si", "di", "8", "9", "10", "11", "12", "13", "14", "15",
+];
+
+// GNU assembler in AT&T syntax, the default for as and what cc -S writes
+impl fmt::Display for Program {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "# Generated by fusion")?;
+        writeln!(f, "\t.text")?;
+        for (idx, func) in self.funcs.iter().enumerate() {
+            writeln!(f)?;
+            if func.global {
+                writeln!(f, "\t.globl {}", func.name)?;
+            }
+            writeln!(f, "\t.type {}, @function", func.name)?;
+            writeln!(f, "{}:", func.name)?;
+            for inst in &func.code {
+                let mut out = String::new();
+                self.inst(&mut out, idx, inst);
+                write!(f, "{}", out)?;
+            }
+            writeln!(f, "\t.size {}, .-{}", func.name, func.name)?;
+        }
+
+        if !self.msgs.is_empty() {
+            writeln!(f, "\n\t.section .rodata")?;
+            for (idx, msg) in self.msgs.iter().enumerate() {
+                writeln!(f, ".Lmsg{}:\n\t.ascii {}", idx, ascii(msg))?;
+            }
+        }
+        if !self.globals.is_empty() {
+            writeln!(f, "\n\t.bss\n\t.align 8")?;
+            for global in &self.globals {
+                writeln!(f, "{}:\n\t.zero 8", global)?;
+            }
+        }
+        // Nothing needs an executable stack
+        writeln!(f, "\n\t.section .note.GNU-stack,\"\",@progbits")
+    }
+}
+
+// Quoted for .ascii, which takes octal escapes
+fn ascii(text: &str) -> String {
+    let mut out = "\"".to_owned();
+    for &byte in text.as_bytes() {
+        match byte {
+            b'"' | b'\\' => out += &format!("\\{}", byte as char),
+            b' '..=b'~' => out.push(byte as char),
+            _ => out += &format!("\\{:03o}", byte),
+        }
+    }
+    out + "\""
+}
+
+// A general register by how many of its bits are used
+fn gpr(num: u8, bits: u32) -> String {
+    let name = NAMES[num as usize];
+    match (num < 8, bits) {
+        (true, 64) => format!("%r{}", name),
+        (true, 32) => format!("%e{}", name),
+        (true, 16) => format!("%{}", name),
+        (true, _) if num < 4 => format!("%{}l", &name[..1]),
+        (true, _) => format!("%{}l", name),
+        (false, 64) => format!("%r{}", name),
+        (false, 32) => format!("%r{}d", name),
+        (false, 16) => format!("%r{}w", name),
+        (false, _) => format!("%r{}b", name),
+    }
+}
+
+impl Program {
+    fn target(&self, target: &Target) -> String {
+        match target {
+            Target::Func(idx) => self.funcs[*idx].name.clone(),
+            Target::Msg(idx) => format!(".Lmsg{}", idx),
+            Target::Extern(name) => format!("{}@PLT", name),
+        }
+    }
+
+    fn opnd(&self, opnd: &Opnd, bits: u32) -> String {
+        match opnd {
+            Opnd::R(Reg::Gpr(num)) => gpr(*num, bits),
+            Opnd::R(Reg::Xmm(num)) => format!("%xmm{}", num),
+            Opnd::Imm(x) => format!("${}", x),
+            Opnd::M(Mem::Base(reg, 0)) => format!("({})", gpr(reg.num(), 64)),
+            Opnd::M(Mem::Base(reg, off)) => format!("{}({})", off, gpr(reg.num(), 64)),
+            Opnd::M(Mem::Global(idx)) => format!("{}(%rip)", self.globals[*idx]),
+            Opnd::V(x) => unreachable!("v{} wasn't allocated", x),
+        }
+    }
+
+    fn inst(&self, out: &mut String, func: usize, inst: &Inst) {
+        let op = |x: &Opnd| self.opnd(x, 64);
+        let label = |block: &usize| format!(".L{}_{}", func, block);
+        if let Inst::Label(block) = inst {
+            return writeln!(out, "{}:", label(block)).unwrap();
+        }
+        let mut line = |text: String| writeln!(out, "\t{}", text).unwrap();
+        match inst {
+            Inst::Mov(d, s) => line(format!("movq {}, {}", op(s), op(d))),
+            Inst::MovAbs(d, x) => line(format!("movabsq ${}, {}", x, op(d))),
+            Inst::Movsd(d, s) => line(format!("movsd {}, {}", op(s), op(d))),
+            Inst::ToXmm(d, s) | Inst::FromXmm(d, s) => line(format!("movq {}, {}", op(s), op(d))),
+            Inst::Alu(alu, d, s) => {
+                let name = match alu {
+                    Alu::Add => "addq",
+                    Alu::Or => "orq",
+                    Alu::And => "andq",
+                    Alu::Sub => "subq",
+                    Alu::Xor => "xorq",
+                    Alu::Cmp => "cmpq",
+                };
+                line(format!("{} {}, {}", name, op(s), op(d)))
+            }
+            Inst::Test(x, y) => line(format!("testq {}, {}", op(y), op(x))),
+            Inst::Imul(d, s) => line(format!("imulq {}, {}", op(s), op(d))),
+            Inst::Neg(d) => line(format!("negq {}", op(d))),
+            Inst::Not(d) => line(format!("notq {}", op(d))),
+            Inst::Shift(shift, d, count) => {
+                let name = match shift {
+                    Shift::Shl => "shlq",
+                    Shift::Shr => "shrq",
+                    Shift::Sar => "sarq",
+                };
+                let count = match count {
+                    Some(x) => format!("${}", x),
+                    None => "%cl".to_owned(),
+                };
+                line(format!("{} {}, {}", name, count, op(d)))
+            }
+            // Writing the low half of a register clears the top, which zero extends
+            Inst::Extend(32, false, d, s) => {
+                line(format!("movl {}, {}", self.opnd(s, 32), self.opnd(d, 32)))
+            }
+            Inst::Extend(bits, signed, d, s) => {
+                let name = match (bits, signed) {
+                    (8, true) => "movsbq",
+                    (16, true) => "movswq",
+                    (32, _) => "movslq",
+                    (8, false) => "movzbq",
+                    _ => "movzwq",
+                };
+                line(format!("{} {}, {}", name, self.opnd(s, *bits), op(d)))
+            }
+            Inst::Cqo => line("cqto".to_owned()),
+            Inst::Idiv(s) => line(format!("idivq {}", op(s))),
+            Inst::Div(s) => line(format!("divq {}", op(s))),
+            Inst::Set(cond, d) => {
+                line(format!("set{} %al", cond.name()));
+                line("movzbl %al, %eax".to_owned());
+                line(format!("movq %rax, {}", op(d)));
+            }
+            Inst::Bsr(d, s) => line(format!("bsrq {}, {}", op(s), op(d))),
+            Inst::Bsf(d, s) => line(format!("bsfq {}, {}", op(s), op(d))),
+            Inst::Sse(sse, d, s) => {
+                let name = match sse {
+                    Sse::Addsd => "addsd",
+                    Sse::Subsd => "subsd",
+                    Sse::Mulsd => "mulsd",
+                    Sse::Divsd => "divsd",
+                    Sse::Ucomisd => "ucomisd",
+                    Sse::Xorpd => "xorpd",
+                    Sse::Cvtss2sd => "cvtss2sd",
+                    Sse::Cvtsd2ss => "cvtsd2ss",
+                };
+                line(format!("{} {}, {}", name, op(s), op(d)))
+            }
+            Inst::Lea(d, target) => line(format!("leaq {}(%rip), {}", self.target(target), op(d))),
+            Inst::Jmp(block) => line(format!("jmp {}", label(block))),
+            Inst::Jcc(cond, block) => line(format!("j{} {}", cond.name(), label(block))),
+            Inst::Call(target) => line(format!("call {}", self.target(target))),
+            Inst::CallInd(s) => line(format!("call *{}", op(s))),
+            Inst::Push(reg) => line(format!("pushq {}", gpr(reg.num(), 64))),
+            Inst::Pop(reg) => line(format!("popq {}", gpr(reg.num(), 64))),
+            Inst::Ret => line("ret".to_owned()),
+            Inst::Syscall => line("syscall".to_owned()),
+            Inst::Line(file, num) => line(format!("# {}:{}", file, num)),
+            Inst::Label(_) | Inst::Moves(_) | Inst::Return => {
+                unreachable!("left over from before allocation")
+            }
+        }
+    }
+}
@@ -0,0 +1,226 @@
+use super::{Object, RelocKind, RelocTarget};
+
+// Section indices, in the order they're written
+const TEXT: u16 = 1;
+const RODATA: u16 = 2;
+const BSS: u16 = 3;
+const SYMTAB: u32 = 5;
+const STRTAB: u32 = 6;
+const NAMES: [&str; 9] = [
+    "",
+    ".text",
+    ".rodata",
+    ".bss",
+    ".rela.text",
+    ".symtab",
+    ".strtab",
+    ".shstrtab",
+    ".note.GNU-stack",
+];
+
+// The type, flags, contents (or the size for .bss), link, info, alignment and entry size
+type Section<'a> = (u32, u64, Result<&'a [u8], usize>, u32, u32, u64, u64);
+
+struct Strings(Vec<u8>);
+
+impl Strings {
+    fn add(&mut self, name: &str) -> u32 {
+        let at = self.0.len() as u32;
+        self.0.extend(name.as_bytes());
+        self.0.push(0);
+        at
+    }
+}
+
+struct Symbol {
+    name: u32,
+    // The binding in the top four bits and the type in the bottom
+    info: u8,
+    section: u16,
+    value: usize,
+    size: usize,
+}
+
+const LOCAL: u8 = 0;
+const GLOBAL: u8 = 1 << 4;
+const NOTYPE: u8 = 0;
+const OBJECT: u8 = 1;
+const FUNC: u8 = 2;
+const SECTION: u8 = 3;
+
+// A relocatable ELF64 object for x86-64, what cc -c would give for the same assembly
+pub(super) fn write(obj: &Object) -> Vec<u8> {
+    let mut strtab = Strings(vec![0]);
+    let section = |section| Symbol {
+        name: 0,
+        info: LOCAL | SECTION,
+        section,
+        value: 0,
+        size: 0,
+    };
+    let mut symbols = vec![
+        Symbol {
+            name: 0,
+            info: 0,
+            section: 0,
+            value: 0,
+            size: 0,
+        },
+        section(TEXT),
+        section(RODATA),
+        section(BSS),
+    ];
+    // Locals have to come before globals
+    for &(ref name, start, size, global) in &obj.funcs {
+        if !global {
+            let name = strtab.add(name);
+            symbols.push(Symbol {
+                name,
+                info: LOCAL | FUNC,
+                section: TEXT,
+                value: start,
+                size,
+            });
+        }
+    }
+    for (name, off) in &obj.globals {
+        let name = strtab.add(name);
+        symbols.push(Symbol {
+            name,
+            info: LOCAL | OBJECT,
+            section: BSS,
+            value: *off,
+            size: 8,
+        });
+    }
+    let first_global = symbols.len();
+    for &(ref name, start, size, global) in &obj.funcs {
+        if global {
+            let name = strtab.add(name);
+            symbols.push(Symbol {
+                name,
+                info: GLOBAL | FUNC,
+                section: TEXT,
+                value: start,
+                size,
+            });
+        }
+    }
+    let mut externs: Vec<&str> = Vec::new();
+    for reloc in &obj.relocs {
+        if let RelocTarget::Extern(name) = reloc.target {
+            if !externs.contains(&name) {
+                externs.push(name);
+            }
+        }
+    }
+    let extern_start = symbols.len();
+    for name in &externs {
+        let name = strtab.add(name);
+        symbols.push(Symbol {
+            name,
+            info: GLOBAL | NOTYPE,
+            section: 0,
+            value: 0,
+            size: 0,
+        });
+    }
+
+    let mut rela = Vec::new();
+    for reloc in &obj.relocs {
+        let symbol = match reloc.target {
+            RelocTarget::Rodata => 2,
+            RelocTarget::Bss => 3,
+            RelocTarget::Extern(name) => {
+                extern_start + externs.iter().position(|&x| x == name).unwrap()
+            }
+        };
+        let kind: u64 = match reloc.kind {
+            RelocKind::Pc32 => 2,
+            RelocKind::Plt32 => 4,
+        };
+        rela.extend((reloc.offset as u64).to_le_bytes());
+        rela.extend(((symbol as u64) << 32 | kind).to_le_bytes());
+        rela.extend(reloc.addend.to_le_bytes());
+    }
+
+    let mut symtab = Vec::new();
+    for symbol in &symbols {
+        symtab.extend(symbol.name.to_le_bytes());
+        symtab.push(symbol.info);
+        symtab.push(0);
+        symtab.extend(symbol.section.to_le_bytes());
+        symtab.extend((symbol.value as u64).to_le_bytes());
+        symtab.extend((symbol.size as u64).to_le_bytes());
+    }
+
+    let mut shstrtab = Strings(vec![0]);
+    let names: Vec<u32> = NAMES.iter().map(|x| shstrtab.add(x)).collect();
+    let sections: [Section; 9] = [
+        (0, 0, Ok(&[]), 0, 0, 0, 0),
+        (1, 0x6, Ok(&obj.text), 0, 0, 16, 0),
+        (1, 0x2, Ok(&obj.rodata), 0, 0, 1, 0),
+        (8, 0x3, Err(obj.bss), 0, 0, 8, 0),
+        (4, 0x40, Ok(&rela), SYMTAB, TEXT as u32, 8, 24),
+        (2, 0, Ok(&symtab), STRTAB, first_global as u32, 8, 24),
+        (3, 0, Ok(&strtab.0), 0, 0, 1, 0),
+        (3, 0, Ok(&shstrtab.0), 0, 0, 1, 0),
+        (1, 0, Ok(&[]), 0, 0, 1, 0),
+    ];
+
+    // The header, then every section's contents, then the section headers
+    let mut out = Vec::new();
+    out.extend(b"\x7fELF");
+    // 64 bits, little endian, version 1, System V
+    out.extend([2, 1, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+    out.extend(1u16.to_le_bytes()); // relocatable
+    out.extend(62u16.to_le_bytes()); // x86-64
+    out.extend(1u32.to_le_bytes());
+    out.extend(0u64.to_le_bytes()); // entry
+    out.extend(0u64.to_le_bytes()); // program headers
+    let shoff_at = out.len();
+    out.extend(0u64.to_le_bytes());
+    out.extend(0u32.to_le_bytes()); // flags
+    out.extend(64u16.to_le_bytes());
+    out.extend(0u16.to_le_bytes());
+    out.extend(0u16.to_le_bytes());
+    out.extend(64u16.to_le_bytes());
+    out.extend((sections.len() as u16).to_le_bytes());
+    out.extend(((sections.len() - 2) as u16).to_le_bytes()); // .shstrtab
+
+    let mut placed = Vec::new();
+    for &(_, _, contents, _, _, align, _) in &sections {
+        let contents = match contents {
+            Ok(bytes) => bytes,
+            Err(size) => {
+                placed.push((out.len(), size));
+                continue;
+            }
+        };
+        while !out.len().is_multiple_of(align.max(1) as usize) {
+            out.push(0);
+        }
+        placed.push((out.len(), contents.len()));
+        out.extend(contents);
+    }
+
+    while !out.len().is_multiple_of(8) {
+        out.push(0);
+    }
+    let shoff = out.len() as u64;
+    out[shoff_at..shoff_at + 8].copy_from_slice(&shoff.to_le_bytes());
+    for (idx, &(typ, flags, _, link, info, align, entsize)) in sections.iter().enumerate() {
+        let (offset, size) = if idx == 0 { (0, 0) } else { placed[idx] };
+        out.extend(names[idx].to_le_bytes());
+        out.extend(typ.to_le_bytes());
+        out.extend(flags.to_le_bytes());
+        out.extend(0u64.to_le_bytes()); // address
+        out.extend((offset as u64).to_le_bytes());
+        out.extend((size as u64).to_le_bytes());
+        out.extend(link.to_le_bytes());
+        out.extend(info.to_le_bytes());
+        out.extend(align.to_le_bytes());
+        out.extend(entsize.to_le_bytes());
+    }
+    out
+}
@@ -0,0 +1,356 @@
+use std::collections::HashMap;
+
+use super::{Alu, Inst, Mem, Opnd, Program, Shift, Sse, Target, RAX};
+
+// Machine code for the program, with what a linker or loader still has to fill in
+#[derive(Debug, Clone, PartialEq)]
+pub struct Object {
+    pub text: Vec<u8>,
+    pub rodata: Vec<u8>,
+    pub bss: usize,
+    // The name, where it starts and how long it is, and whether it's global
+    pub funcs: Vec<(String, usize, usize, bool)>,
+    // Where each global is in .bss
+    pub globals: Vec<(String, usize)>,
+    pub relocs: Vec<Reloc>,
+}
+
+// Four bytes in .text that get the target plus the addend, less where the four bytes are
+#[derive(Debug, Clone, PartialEq)]
+pub struct Reloc {
+    pub offset: usize,
+    pub kind: RelocKind,
+    pub target: RelocTarget,
+    pub addend: i64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RelocKind {
+    Pc32,
+    // A call, which can go through the PLT
+    Plt32,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum RelocTarget {
+    Rodata,
+    Bss,
+    Extern(&'static str),
+}
+
+impl Program {
+    // Every jump and call within .text is already resolved, only data and externs are left
+    pub fn encode(&self) -> Object {
+        let mut msgs = Vec::new();
+        let mut rodata = Vec::new();
+        for msg in &self.msgs {
+            msgs.push(rodata.len());
+            rodata.extend(msg.as_bytes());
+        }
+
+        let mut enc = Encoder {
+            text: Vec::new(),
+            relocs: Vec::new(),
+            msgs,
+            labels: HashMap::new(),
+            jumps: Vec::new(),
+            calls: Vec::new(),
+        };
+        let mut funcs = Vec::new();
+        for func in &self.funcs {
+            // Functions start sixteen byte aligned, padded with int3
+            while !enc.text.len().is_multiple_of(16) {
+                enc.text.push(0xcc);
+            }
+            let start = enc.text.len();
+            for inst in &func.code {
+                enc.inst(inst);
+            }
+            for (pos, label) in std::mem::take(&mut enc.jumps) {
+                let to = enc.labels[&label];
+                enc.patch(pos, to);
+            }
+            enc.labels.clear();
+            funcs.push((
+                func.name.clone(),
+                start,
+                enc.text.len() - start,
+                func.global,
+            ));
+        }
+        for (pos, func) in std::mem::take(&mut enc.calls) {
+            enc.patch(pos, funcs[func].1);
+        }
+
+        Object {
+            text: enc.text,
+            rodata,
+            bss: 8 * self.globals.len(),
+            funcs,
+            globals: (0..)
+                .step_by(8)
+                .zip(&self.globals)
+                .map(|(off, name)| (name.clone(), off))
+                .collect(),
+            relocs: enc.relocs,
+        }
+    }
+}
+
+struct Encoder {
+    text: Vec<u8>,
+    relocs: Vec<Reloc>,
+    // Where each message starts in .rodata
+    msgs: Vec<usize>,
+    // Blocks of the current function, and the jumps to them
+    labels: HashMap<usize, usize>,
+    jumps: Vec<(usize, usize)>,
+    // Calls and addresses of functions, fixed up once they've all been placed
+    calls: Vec<(usize, usize)>,
+}
+
+fn num(opnd: &Opnd) -> u8 {
+    match opnd {
+        Opnd::R(reg) => reg.num(),
+        _ => unreachable!("{:?} isn't a register", opnd),
+    }
+}
+
+fn imm(opnd: &Opnd) -> Option<i32> {
+    match *opnd {
+        Opnd::Imm(x) => Some(i32::try_from(x).expect("immediates are 32 bits")),
+        _ => None,
+    }
+}
+
+impl Encoder {
+    fn patch(&mut self, pos: usize, to: usize) {
+        let rel = to as i64 - (pos as i64 + 4);
+        self.text[pos..pos + 4].copy_from_slice(&(rel as i32).to_le_bytes());
+    }
+
+    fn imm32(&mut self, x: i32) {
+        self.text.extend(x.to_le_bytes());
+    }
+
+    // An instruction with a ModRM byte: any prefix, then REX when it's needed, the opcode, and
+    // the register and register or memory operands. trailing is how many immediate bytes come
+    // after, which rip relative addresses have to skip over
+    fn modrm(
+        &mut self,
+        prefix: &[u8],
+        wide: bool,
+        opcode: &[u8],
+        reg: u8,
+        rm: &Opnd,
+        trailing: i64,
+    ) {
+        let base = match rm {
+            Opnd::R(x) | Opnd::M(Mem::Base(x, _)) => x.num(),
+            _ => 0,
+        };
+        self.text.extend(prefix);
+        let rex = 0x40 | (wide as u8) << 3 | (reg >> 3) << 2 | (base >> 3);
+        if rex != 0x40 {
+            self.text.push(rex);
+        }
+        self.text.extend(opcode);
+
+        let reg = (reg & 7) << 3;
+        match rm {
+            Opnd::R(x) => self.text.push(0xc0 | reg | (x.num() & 7)),
+            Opnd::M(Mem::Base(x, disp)) => {
+                let low = x.num() & 7;
+                // rbp and r13 with no displacement mean something else, so they get a zero one
+                let mode = match *disp {
+                    0 if low != 5 => 0,
+                    -128..=127 => 1,
+                    _ => 2,
+                };
+                self.text.push(mode << 6 | reg | low);
+                // So do rsp and r12 without an index
+                if low == 4 {
+                    self.text.push(0x24);
+                }
+                match mode {
+                    1 => self.text.push(*disp as i8 as u8),
+                    2 => self.imm32(*disp),
+                    _ => {}
+                }
+            }
+            Opnd::M(Mem::Global(idx)) => {
+                self.text.push(reg | 5);
+                self.relocs.push(Reloc {
+                    offset: self.text.len(),
+                    kind: RelocKind::Pc32,
+                    target: RelocTarget::Bss,
+                    addend: 8 * *idx as i64 - 4 - trailing,
+                });
+                self.imm32(0);
+            }
+            _ => unreachable!("{:?} can't be an operand", rm),
+        }
+    }
+
+    // Four bytes relative to the end of the instruction, which they're at the end of
+    fn rel32(&mut self, target: &Target) {
+        let pos = self.text.len();
+        match *target {
+            Target::Func(idx) => self.calls.push((pos, idx)),
+            Target::Msg(idx) => self.relocs.push(Reloc {
+                offset: pos,
+                kind: RelocKind::Pc32,
+                target: RelocTarget::Rodata,
+                addend: self.msgs[idx] as i64 - 4,
+            }),
+            Target::Extern(name) => self.relocs.push(Reloc {
+                offset: pos,
+                kind: RelocKind::Plt32,
+                target: RelocTarget::Extern(name),
+                addend: -4,
+            }),
+        }
+        self.imm32(0);
+    }
+
+    fn inst(&mut self, inst: &Inst) {
+        match inst {
+            Inst::Mov(d, s) => match (d, imm(s)) {
+                (_, Some(x)) => {
+                    self.modrm(&[], true, &[0xc7], 0, d, 4);
+                    self.imm32(x);
+                }
+                (Opnd::R(_), None) => self.modrm(&[], true, &[0x8b], num(d), s, 0),
+                _ => self.modrm(&[], true, &[0x89], num(s), d, 0),
+            },
+            Inst::MovAbs(d, x) => {
+                let reg = num(d);
+                self.text.extend([0x48 | reg >> 3, 0xb8 | (reg & 7)]);
+                self.text.extend(x.to_le_bytes());
+            }
+            Inst::Movsd(d, s) => match d {
+                Opnd::R(_) => self.modrm(&[0xf2], false, &[0x0f, 0x10], num(d), s, 0),
+                _ => self.modrm(&[0xf2], false, &[0x0f, 0x11], num(s), d, 0),
+            },
+            Inst::ToXmm(d, s) => self.modrm(&[0x66], true, &[0x0f, 0x6e], num(d), s, 0),
+            Inst::FromXmm(d, s) => self.modrm(&[0x66], true, &[0x0f, 0x7e], num(s), d, 0),
+            Inst::Alu(alu, d, s) => {
+                let code = match alu {
+                    Alu::Add => 0,
+                    Alu::Or => 1,
+                    Alu::And => 4,
+                    Alu::Sub => 5,
+                    Alu::Xor => 6,
+                    Alu::Cmp => 7,
+                };
+                match (d, imm(s)) {
+                    (_, Some(x)) => {
+                        self.modrm(&[], true, &[0x81], code, d, 4);
+                        self.imm32(x);
+                    }
+                    (Opnd::R(_), None) => self.modrm(&[], true, &[code << 3 | 3], num(d), s, 0),
+                    _ => self.modrm(&[], true, &[code << 3 | 1], num(s), d, 0),
+                }
+            }
+            Inst::Test(x, y) => match (imm(y), y) {
+                (Some(val), _) => {
+                    self.modrm(&[], true, &[0xf7], 0, x, 4);
+                    self.imm32(val);
+                }
+                (None, Opnd::R(_)) => self.modrm(&[], true, &[0x85], num(y), x, 0),
+                _ => self.modrm(&[], true, &[0x85], num(x), y, 0),
+            },
+            Inst::Imul(d, s) => self.modrm(&[], true, &[0x0f, 0xaf], num(d), s, 0),
+            Inst::Not(d) => self.modrm(&[], true, &[0xf7], 2, d, 0),
+            Inst::Neg(d) => self.modrm(&[], true, &[0xf7], 3, d, 0),
+            Inst::Div(s) => self.modrm(&[], true, &[0xf7], 6, s, 0),
+            Inst::Idiv(s) => self.modrm(&[], true, &[0xf7], 7, s, 0),
+            Inst::Shift(shift, d, count) => {
+                let code = match shift {
+                    Shift::Shl => 4,
+                    Shift::Shr => 5,
+                    Shift::Sar => 7,
+                };
+                match count {
+                    Some(x) => {
+                        self.modrm(&[], true, &[0xc1], code, d, 1);
+                        self.text.push(*x);
+                    }
+                    None => self.modrm(&[], true, &[0xd3], code, d, 0),
+                }
+            }
+            Inst::Extend(bits, signed, d, s) => {
+                let (wide, opcode): (bool, &[u8]) = match (bits, signed) {
+                    (8, true) => (true, &[0x0f, 0xbe]),
+                    (16, true) => (true, &[0x0f, 0xbf]),
+                    (32, true) => (true, &[0x63]),
+                    (8, false) => (true, &[0x0f, 0xb6]),
+                    (16, false) => (true, &[0x0f, 0xb7]),
+                    _ => (false, &[0x8b]),
+                };
+                self.modrm(&[], wide, opcode, num(d), s, 0);
+            }
+            Inst::Cqo => self.text.extend([0x48, 0x99]),
+            Inst::Set(cond, d) => {
+                // setcc al, movzx eax, al
+                self.text
+                    .extend([0x0f, 0x90 | *cond as u8, 0xc0, 0x0f, 0xb6, 0xc0]);
+                self.inst(&Inst::Mov(d.clone(), Opnd::R(RAX)));
+            }
+            Inst::Bsr(d, s) => self.modrm(&[], true, &[0x0f, 0xbd], num(d), s, 0),
+            Inst::Bsf(d, s) => self.modrm(&[], true, &[0x0f, 0xbc], num(d), s, 0),
+            Inst::Sse(sse, d, s) => {
+                let (prefix, opcode) = match sse {
+                    Sse::Addsd => (0xf2, 0x58),
+                    Sse::Mulsd => (0xf2, 0x59),
+                    Sse::Subsd => (0xf2, 0x5c),
+                    Sse::Divsd => (0xf2, 0x5e),
+                    Sse::Ucomisd => (0x66, 0x2e),
+                    Sse::Xorpd => (0x66, 0x57),
+                    Sse::Cvtss2sd => (0xf3, 0x5a),
+                    Sse::Cvtsd2ss => (0xf2, 0x5a),
+                };
+                self.modrm(&[prefix], false, &[0x0f, opcode], num(d), s, 0);
+            }
+            Inst::Lea(d, target) => {
+                let reg = num(d);
+                self.text
+                    .extend([0x48 | (reg >> 3) << 2, 0x8d, (reg & 7) << 3 | 5]);
+                self.rel32(target);
+            }
+            Inst::Label(block) => {
+                self.labels.insert(*block, self.text.len());
+            }
+            Inst::Jmp(block) => {
+                self.text.push(0xe9);
+                self.jumps.push((self.text.len(), *block));
+                self.imm32(0);
+            }
+            Inst::Jcc(cond, block) => {
+                self.text.extend([0x0f, 0x80 | *cond as u8]);
+                self.jumps.push((self.text.len(), *block));
+                self.imm32(0);
+            }
+            Inst::Call(target) => {
+                self.text.push(0xe8);
+                self.rel32(target);
+            }
+            Inst::CallInd(s) => self.modrm(&[], false, &[0xff], 2, s, 0),
+            Inst::Push(reg) | Inst::Pop(reg) => {
+                if reg.num() >= 8 {
+                    self.text.push(0x41);
+                }
+                let base = if matches!(inst, Inst::Push(_)) {
+                    0x50
+                } else {
+                    0x58
+                };
+                self.text.push(base | (reg.num() & 7));
+            }
+            Inst::Ret => self.text.push(0xc3),
+            Inst::Syscall => self.text.extend([0x0f, 0x05]),
+            Inst::Line(..) => {}
+            Inst::Moves(_) | Inst::Return => unreachable!("left over from before allocation"),
+        }
+    }
+}
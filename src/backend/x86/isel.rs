@@ -0,0 +1,679 @@
+use std::collections::HashMap;
+
+use super::{
+    jit, Alu, Class, Cond, Inst, Mem, Opnd, Reg, Runtime, Shift, Sse, Target, R8, R9, RAX, RBP,
+    RCX, RDI, RDX, RSI, RSP,
+};
+use crate::{
+    ir::{
+        BinOp, Block, Callee, Cfg, Const, Function, Module, Op, Terminator, Type, UnOp, Value,
+        MAX_DEPTH,
+    },
+    types::FloatingSize,
+};
+
+// Integer arguments go in these, in order, then on the stack
+const ARGS: [Reg; 6] = [RDI, RSI, RDX, RCX, R8, R9];
+const XMM0: Reg = Reg::Xmm(0);
+const XMM1: Reg = Reg::Xmm(1);
+
+// A function with virtual registers. Blocks only jump at their end, apart from going to a trap,
+// which needs nothing
+#[derive(Debug)]
+pub(super) struct Code {
+    pub blocks: Vec<MBlock>,
+    // The class of every virtual register, the IR's values keep their numbers
+    pub vregs: Vec<Class>,
+    // How many eight byte slots calls need for arguments on the stack
+    pub out_slots: u32,
+}
+
+#[derive(Debug, Default)]
+pub(super) struct MBlock {
+    pub insts: Vec<Inst>,
+    pub succs: Vec<usize>,
+}
+
+// The first block takes the parameters, then come the IR's blocks in reverse postorder. Anything
+// a single IR instruction needs its own control flow for goes after those. The JIT's globals
+// start at base
+pub(super) fn select(
+    module: &Module,
+    func: &Function,
+    runtime: Runtime,
+    base: usize,
+    msgs: &mut Vec<String>,
+) -> Code {
+    let types = func.types();
+    let mut vregs = vec![Class::Int; func.next_value as usize];
+    for (val, &typ) in &types {
+        vregs[val.0 as usize] = class(typ);
+    }
+
+    let cfg = Cfg::new(func);
+    let mut selector = Selector {
+        module,
+        func,
+        runtime,
+        base,
+        types,
+        code: Code {
+            blocks: vec![MBlock::default()],
+            vregs,
+            out_slots: 0,
+        },
+        at: 0,
+        blocks: HashMap::new(),
+        traps: HashMap::new(),
+        line: None,
+        msgs,
+    };
+    for &block in &cfg.order {
+        let idx = selector.new_block();
+        selector.blocks.insert(block, idx);
+    }
+
+    selector.params();
+    for &block in &cfg.order {
+        selector.at = selector.blocks[&block];
+        for inst in &func[block].insts {
+            selector.inst(inst.dest, &inst.op);
+        }
+        selector.term(block, &func[block].term);
+    }
+    selector.code
+}
+
+struct Selector<'a> {
+    module: &'a Module,
+    func: &'a Function,
+    runtime: Runtime,
+    base: usize,
+    types: HashMap<Value, Type>,
+    code: Code,
+    // The block instructions go in
+    at: usize,
+    blocks: HashMap<Block, usize>,
+    // One block per message, they can all jump to the same one
+    traps: HashMap<String, usize>,
+    line: Option<(String, u32)>,
+    msgs: &'a mut Vec<String>,
+}
+
+fn class(typ: Type) -> Class {
+    match typ {
+        Type::Float(_) => Class::Float,
+        _ => Class::Int,
+    }
+}
+
+// Where each argument goes, and how many go on the stack. Stack slots are eight bytes each,
+// counted from where the caller's rsp was
+pub(super) fn abi(types: &[Type], stack: impl Fn(i32) -> Mem) -> (Vec<Opnd>, u32) {
+    let (mut ints, mut floats, mut slots) = (0, 0, 0);
+    let locs = types
+        .iter()
+        .map(|&typ| match class(typ) {
+            Class::Int if ints < ARGS.len() => {
+                ints += 1;
+                Opnd::R(ARGS[ints - 1])
+            }
+            Class::Float if floats < 8 => {
+                floats += 1;
+                Opnd::R(Reg::Xmm(floats - 1))
+            }
+            _ => {
+                slots += 1;
+                Opnd::M(stack(8 * (slots as i32 - 1)))
+            }
+        })
+        .collect();
+    (locs, slots)
+}
+
+fn v(val: Value) -> Opnd {
+    Opnd::V(val.0)
+}
+
+impl Selector<'_> {
+    // Whether the type goes in and out of calls as a float instead of a double
+    fn narrow(&self, typ: Type) -> bool {
+        self.runtime == Runtime::Process && typ == Type::Float(FloatingSize::Float32)
+    }
+
+    fn new_block(&mut self) -> usize {
+        self.code.blocks.push(MBlock::default());
+        self.code.blocks.len() - 1
+    }
+
+    fn vreg(&mut self, class: Class) -> Opnd {
+        self.code.vregs.push(class);
+        Opnd::V(self.code.vregs.len() as u32 - 1)
+    }
+
+    fn emit(&mut self, inst: Inst) {
+        self.code.blocks[self.at].insts.push(inst);
+    }
+
+    fn jump(&mut self, to: usize) {
+        self.emit(Inst::Jmp(to));
+        self.code.blocks[self.at].succs.push(to);
+    }
+
+    fn branch(&mut self, cond: Cond, yes: usize, no: usize) {
+        self.emit(Inst::Jcc(cond, yes));
+        self.jump(no);
+        self.code.blocks[self.at].succs.push(yes);
+    }
+
+    // Goes to a block that prints the message, with the line the code came from
+    fn trap_if(&mut self, cond: Cond, msg: &str) {
+        let text = match &self.line {
+            Some((file, line)) => format!("error: {} ({}:{})\n", msg, file, line),
+            None => format!("error: {}\n", msg),
+        };
+        let block = match self.traps.get(&text) {
+            Some(&block) => block,
+            None => {
+                let idx = match self.msgs.iter().position(|x| *x == text) {
+                    Some(idx) => idx,
+                    None => {
+                        self.msgs.push(text.clone());
+                        self.msgs.len() - 1
+                    }
+                };
+                let block = self.new_block();
+                self.code.blocks[block].insts = vec![
+                    Inst::Lea(Opnd::R(RDI), Target::Msg(idx)),
+                    Inst::Mov(Opnd::R(RSI), Opnd::Imm(text.len() as i64)),
+                    // The trap routine comes after every function
+                    Inst::Call(Target::Func(self.module.funcs.len())),
+                ];
+                self.traps.insert(text, block);
+                block
+            }
+        };
+        self.emit(Inst::Jcc(cond, block));
+        self.code.blocks[self.at].succs.push(block);
+    }
+
+    // Keeps integers sign or zero extended from their own width
+    fn wrap(&mut self, dest: &Opnd, typ: Type) {
+        let bits = typ.bits();
+        if typ.is_int() && bits < 64 {
+            let signed = matches!(typ, Type::Int(_));
+            self.emit(Inst::Extend(bits, signed, dest.clone(), dest.clone()));
+        }
+    }
+
+    fn int(&mut self, dest: Opnd, val: i64) {
+        match i32::try_from(val) {
+            Ok(_) => self.emit(Inst::Mov(dest, Opnd::Imm(val))),
+            Err(_) => self.emit(Inst::MovAbs(dest, val)),
+        }
+    }
+
+    // C doesn't promise anything about the bits above a small argument
+    fn params(&mut self) {
+        let types: Vec<Type> = self.func.params.iter().map(|&(_, typ)| typ).collect();
+        let (locs, _) = abi(&types, |off| Mem::Base(RBP, 16 + off));
+        let moves = self
+            .func
+            .params
+            .iter()
+            .zip(locs)
+            .map(|(&(val, typ), loc)| (v(val), loc, class(typ)))
+            .collect();
+        self.emit(Inst::Moves(moves));
+
+        for &(val, typ) in &self.func.params {
+            match typ {
+                Type::Bool => self.emit(Inst::Extend(8, false, v(val), v(val))),
+                _ if self.narrow(typ) => self.emit(Inst::Sse(Sse::Cvtss2sd, v(val), v(val))),
+                _ => self.wrap(&v(val), typ),
+            }
+        }
+        // Running out of stack would take fusion down with it
+        if self.runtime == Runtime::Jit {
+            let depth = Opnd::M(Mem::Global(self.base + jit::DEPTH));
+            self.emit(Inst::Alu(Alu::Add, depth.clone(), Opnd::Imm(1)));
+            self.emit(Inst::Alu(Alu::Cmp, depth, Opnd::Imm(MAX_DEPTH as i64)));
+            let msg = format!("Recursed too deep, after {} calls", MAX_DEPTH);
+            self.trap_if(Cond::G, &msg);
+        }
+        let first = self.blocks[&Block(0)];
+        self.jump(first);
+    }
+
+    fn inst(&mut self, dest: Option<(Value, Type)>, op: &Op) {
+        let d = dest.map(|(val, _)| v(val));
+        match op {
+            Op::Phi(_) => {}
+            Op::Const(Const::Int(x)) => self.int(d.unwrap(), *x as i64),
+            Op::Const(Const::Bool(x)) => self.emit(Inst::Mov(d.unwrap(), Opnd::Imm(*x as i64))),
+            Op::Const(Const::Float(x)) => {
+                let bits = self.vreg(Class::Int);
+                self.int(bits.clone(), x.to_bits() as i64);
+                self.emit(Inst::ToXmm(d.unwrap(), bits));
+            }
+            Op::Func(name) => {
+                let idx = self.module.funcs.iter().position(|x| x.name == *name);
+                self.emit(Inst::Lea(d.unwrap(), Target::Func(idx.unwrap())));
+            }
+            Op::Binary(op, lhs, rhs) => {
+                let typ = self.types[lhs];
+                match typ {
+                    Type::Float(_) => self.float_binary(d.unwrap(), *op, v(*lhs), v(*rhs)),
+                    _ => self.binary(d.unwrap(), *op, typ, v(*lhs), v(*rhs)),
+                }
+            }
+            Op::Unary(op, val) => self.unary(d.unwrap(), *op, self.types[val], v(*val)),
+            Op::Call(callee, args) => self.call(dest, callee, args),
+            Op::Load(name) => {
+                let (idx, typ) = self.global(name);
+                match class(typ) {
+                    Class::Int => self.emit(Inst::Mov(d.unwrap(), Opnd::M(Mem::Global(idx)))),
+                    Class::Float => self.emit(Inst::Movsd(d.unwrap(), Opnd::M(Mem::Global(idx)))),
+                }
+            }
+            Op::Store(name, val) => {
+                let (idx, typ) = self.global(name);
+                match class(typ) {
+                    Class::Int => self.emit(Inst::Mov(Opnd::M(Mem::Global(idx)), v(*val))),
+                    Class::Float => self.emit(Inst::Movsd(Opnd::M(Mem::Global(idx)), v(*val))),
+                }
+            }
+            Op::Line(file, line) => {
+                self.line = Some((file.clone(), *line));
+                self.emit(Inst::Line(file.clone(), *line));
+            }
+        }
+    }
+
+    fn global(&self, name: &str) -> (usize, Type) {
+        let idx = self.module.globals.iter().position(|x| x.name == name);
+        let idx = idx.expect("the verifier checks globals exist");
+        (idx, self.module.globals[idx].typ)
+    }
+
+    fn float_binary(&mut self, d: Opnd, op: BinOp, lhs: Opnd, rhs: Opnd) {
+        let arith = |op| match op {
+            BinOp::Add => Some(Sse::Addsd),
+            BinOp::Sub => Some(Sse::Subsd),
+            BinOp::Mul => Some(Sse::Mulsd),
+            BinOp::Div => Some(Sse::Divsd),
+            _ => None,
+        };
+        if let Some(sse) = arith(op) {
+            self.emit(Inst::Movsd(d.clone(), lhs));
+            self.emit(Inst::Sse(sse, d, rhs));
+            return;
+        }
+
+        match op {
+            BinOp::Rem | BinOp::Pow => {
+                let name = if op == BinOp::Rem { "fmod" } else { "pow" };
+                self.emit(Inst::Moves(vec![
+                    (Opnd::R(XMM0), lhs, Class::Float),
+                    (Opnd::R(XMM1), rhs, Class::Float),
+                ]));
+                self.emit(Inst::Call(Target::Extern(name)));
+                self.emit(Inst::Moves(vec![(d, Opnd::R(XMM0), Class::Float)]));
+            }
+            // Unordered sets the parity flag, and NaN is only ever not equal
+            BinOp::Eq | BinOp::Ne => {
+                let (cond, parity, alu) = match op {
+                    BinOp::Eq => (Cond::E, Cond::Np, Alu::And),
+                    _ => (Cond::Ne, Cond::P, Alu::Or),
+                };
+                let other = self.vreg(Class::Int);
+                self.emit(Inst::Sse(Sse::Ucomisd, lhs, rhs));
+                self.emit(Inst::Set(cond, d.clone()));
+                self.emit(Inst::Set(parity, other.clone()));
+                self.emit(Inst::Alu(alu, d, other));
+            }
+            // Above is false when unordered, so less than compares the other way around
+            _ => {
+                let (lhs, rhs, cond) = match op {
+                    BinOp::Lt => (rhs, lhs, Cond::A),
+                    BinOp::Le => (rhs, lhs, Cond::Ae),
+                    BinOp::Gt => (lhs, rhs, Cond::A),
+                    _ => (lhs, rhs, Cond::Ae),
+                };
+                self.emit(Inst::Sse(Sse::Ucomisd, lhs, rhs));
+                self.emit(Inst::Set(cond, d));
+            }
+        }
+    }
+
+    // Integers, bools and functions
+    fn binary(&mut self, d: Opnd, op: BinOp, typ: Type, lhs: Opnd, rhs: Opnd) {
+        let signed = matches!(typ, Type::Int(_));
+        let alu = match op {
+            BinOp::Add => Some(Alu::Add),
+            BinOp::Sub => Some(Alu::Sub),
+            BinOp::And | BinOp::Nand => Some(Alu::And),
+            BinOp::Or | BinOp::Nor => Some(Alu::Or),
+            BinOp::Xor | BinOp::Xand => Some(Alu::Xor),
+            _ => None,
+        };
+        if let Some(alu) = alu {
+            self.emit(Inst::Mov(d.clone(), lhs));
+            self.emit(Inst::Alu(alu, d.clone(), rhs));
+            if matches!(op, BinOp::Nand | BinOp::Nor | BinOp::Xand) {
+                match typ {
+                    Type::Bool => self.emit(Inst::Alu(Alu::Xor, d.clone(), Opnd::Imm(1))),
+                    _ => self.emit(Inst::Not(d.clone())),
+                }
+            }
+            self.wrap(&d, typ);
+            return;
+        }
+
+        let (lt, le, gt, ge) = match signed {
+            true => (Cond::L, Cond::Le, Cond::G, Cond::Ge),
+            false => (Cond::B, Cond::Be, Cond::A, Cond::Ae),
+        };
+        let cond = match op {
+            BinOp::Eq => Cond::E,
+            BinOp::Ne => Cond::Ne,
+            BinOp::Lt => lt,
+            BinOp::Le => le,
+            BinOp::Gt => gt,
+            BinOp::Ge => ge,
+            BinOp::Mul => {
+                self.emit(Inst::Mov(d.clone(), lhs));
+                self.emit(Inst::Imul(d.clone(), rhs));
+                return self.wrap(&d, typ);
+            }
+            BinOp::Div | BinOp::Rem => return self.divide(d, op, typ, lhs, rhs),
+            BinOp::Pow => return self.power(d, typ, lhs, rhs),
+            _ => return self.shift(d, op, typ, lhs, rhs),
+        };
+        self.emit(Inst::Alu(Alu::Cmp, lhs, rhs));
+        self.emit(Inst::Set(cond, d));
+    }
+
+    fn divide(&mut self, d: Opnd, op: BinOp, typ: Type, lhs: Opnd, rhs: Opnd) {
+        self.emit(Inst::Alu(Alu::Cmp, rhs.clone(), Opnd::Imm(0)));
+        self.trap_if(Cond::E, "Division by zero");
+        let res = Opnd::R(if op == BinOp::Div { RAX } else { RDX });
+
+        if !matches!(typ, Type::Int(_)) {
+            self.emit(Inst::Mov(Opnd::R(RAX), lhs));
+            self.emit(Inst::Alu(Alu::Xor, Opnd::R(RDX), Opnd::R(RDX)));
+            self.emit(Inst::Div(rhs));
+            self.emit(Inst::Mov(d, res));
+            return;
+        }
+        // Smaller types are sign extended, so their minimum over -1 fits. The widest one
+        // faults instead of wrapping, so -1 goes its own way
+        let wide = typ.bits() == 64;
+        let done = self.new_block();
+        if wide {
+            let (minus, normal) = (self.new_block(), self.new_block());
+            self.emit(Inst::Alu(Alu::Cmp, rhs.clone(), Opnd::Imm(-1)));
+            self.branch(Cond::E, minus, normal);
+
+            self.at = minus;
+            match op {
+                BinOp::Div => {
+                    self.emit(Inst::Mov(d.clone(), lhs.clone()));
+                    self.emit(Inst::Neg(d.clone()));
+                }
+                _ => self.emit(Inst::Mov(d.clone(), Opnd::Imm(0))),
+            }
+            self.jump(done);
+            self.at = normal;
+        }
+        self.emit(Inst::Mov(Opnd::R(RAX), lhs));
+        self.emit(Inst::Cqo);
+        self.emit(Inst::Idiv(rhs));
+        self.emit(Inst::Mov(d.clone(), res));
+        self.wrap(&d, typ);
+        self.jump(done);
+        self.at = done;
+    }
+
+    // Squaring and multiplying, over at most the 32 bits the interpreter clamps exponents to
+    fn power(&mut self, d: Opnd, typ: Type, lhs: Opnd, rhs: Opnd) {
+        if matches!(typ, Type::Int(_)) {
+            self.emit(Inst::Alu(Alu::Cmp, rhs.clone(), Opnd::Imm(0)));
+            self.trap_if(Cond::L, "Negative exponent");
+        }
+        let (exp, high, base) = (
+            self.vreg(Class::Int),
+            self.vreg(Class::Int),
+            self.vreg(Class::Int),
+        );
+        let (clamp, test, odd, mul, square, done) = (
+            self.new_block(),
+            self.new_block(),
+            self.new_block(),
+            self.new_block(),
+            self.new_block(),
+            self.new_block(),
+        );
+        self.emit(Inst::Mov(d.clone(), Opnd::Imm(1)));
+        self.emit(Inst::Mov(base.clone(), lhs));
+        self.emit(Inst::Mov(exp.clone(), rhs.clone()));
+        self.emit(Inst::Mov(high.clone(), rhs));
+        self.emit(Inst::Shift(Shift::Shr, high.clone(), Some(32)));
+        self.emit(Inst::Alu(Alu::Cmp, high, Opnd::Imm(0)));
+        self.branch(Cond::Ne, clamp, test);
+
+        self.at = clamp;
+        self.emit(Inst::Mov(exp.clone(), Opnd::Imm(-1)));
+        self.emit(Inst::Extend(32, false, exp.clone(), exp.clone()));
+        self.jump(test);
+
+        self.at = test;
+        self.emit(Inst::Alu(Alu::Cmp, exp.clone(), Opnd::Imm(0)));
+        self.branch(Cond::E, done, odd);
+
+        self.at = odd;
+        self.emit(Inst::Test(exp.clone(), Opnd::Imm(1)));
+        self.branch(Cond::E, square, mul);
+
+        self.at = mul;
+        self.emit(Inst::Imul(d.clone(), base.clone()));
+        self.jump(square);
+
+        self.at = square;
+        self.emit(Inst::Imul(base.clone(), base));
+        self.emit(Inst::Shift(Shift::Shr, exp, Some(1)));
+        self.jump(test);
+
+        self.at = done;
+        self.wrap(&d, typ);
+    }
+
+    fn shift(&mut self, d: Opnd, op: BinOp, typ: Type, lhs: Opnd, rhs: Opnd) {
+        let bits = typ.bits();
+        let signed = matches!(typ, Type::Int(_));
+        // Negative amounts are huge unsigned
+        self.emit(Inst::Alu(Alu::Cmp, rhs.clone(), Opnd::Imm(bits as i64)));
+        self.trap_if(Cond::Ae, "Shifted by more than the width of the type");
+        self.emit(Inst::Mov(Opnd::R(RCX), rhs));
+        self.emit(Inst::Mov(d.clone(), lhs));
+        match op {
+            BinOp::Shl => {
+                self.emit(Inst::Shift(Shift::Shl, d.clone(), None));
+                self.wrap(&d, typ);
+            }
+            BinOp::Shr if signed => self.emit(Inst::Shift(Shift::Sar, d, None)),
+            BinOp::Shr => self.emit(Inst::Shift(Shift::Shr, d, None)),
+            // The same bits read as unsigned
+            _ => {
+                if signed && bits < 64 {
+                    self.emit(Inst::Extend(bits, false, d.clone(), d.clone()));
+                }
+                self.emit(Inst::Shift(Shift::Shr, d.clone(), None));
+                self.wrap(&d, typ);
+            }
+        }
+    }
+
+    fn unary(&mut self, d: Opnd, op: UnOp, typ: Type, val: Opnd) {
+        let bits = typ.bits();
+        match (op, typ) {
+            (UnOp::Neg, Type::Float(_)) => {
+                let (sign, mask) = (self.vreg(Class::Int), self.vreg(Class::Float));
+                self.emit(Inst::MovAbs(sign.clone(), i64::MIN));
+                self.emit(Inst::ToXmm(mask.clone(), sign));
+                self.emit(Inst::Movsd(d.clone(), val));
+                self.emit(Inst::Sse(Sse::Xorpd, d, mask));
+            }
+            (UnOp::Not, Type::Bool) => {
+                self.emit(Inst::Mov(d.clone(), val));
+                self.emit(Inst::Alu(Alu::Xor, d, Opnd::Imm(1)));
+            }
+            (UnOp::Neg | UnOp::Not, _) => {
+                self.emit(Inst::Mov(d.clone(), val));
+                match op {
+                    UnOp::Neg => self.emit(Inst::Neg(d.clone())),
+                    _ => self.emit(Inst::Not(d.clone())),
+                }
+                self.wrap(&d, typ);
+            }
+            // Only the bits of the type count, and bsr and bsf don't do zero
+            _ => {
+                let src = match op {
+                    UnOp::Clz if bits < 64 => {
+                        let src = self.vreg(Class::Int);
+                        self.emit(Inst::Extend(bits, false, src.clone(), val));
+                        src
+                    }
+                    _ => val,
+                };
+                let (zero, some, done) = (self.new_block(), self.new_block(), self.new_block());
+                self.emit(Inst::Alu(Alu::Cmp, src.clone(), Opnd::Imm(0)));
+                self.branch(Cond::E, zero, some);
+
+                self.at = zero;
+                self.emit(Inst::Mov(d.clone(), Opnd::Imm(bits as i64)));
+                self.jump(done);
+
+                self.at = some;
+                match op {
+                    // The highest set bit counted from the top, bits is a power of two
+                    UnOp::Clz => {
+                        self.emit(Inst::Bsr(d.clone(), src));
+                        self.emit(Inst::Alu(Alu::Xor, d, Opnd::Imm(bits as i64 - 1)));
+                    }
+                    _ => self.emit(Inst::Bsf(d, src)),
+                }
+                self.jump(done);
+                self.at = done;
+            }
+        }
+    }
+
+    fn call(&mut self, dest: Option<(Value, Type)>, callee: &Callee, args: &[Value]) {
+        let types: Vec<Type> = args.iter().map(|x| self.types[x]).collect();
+        let (locs, slots) = abi(&types, |off| Mem::Base(RSP, off));
+        self.code.out_slots = self.code.out_slots.max(slots);
+
+        let mut moves = Vec::new();
+        for ((&arg, &typ), loc) in args.iter().zip(&types).zip(locs) {
+            let mut src = v(arg);
+            if self.narrow(typ) {
+                let narrow = self.vreg(Class::Float);
+                self.emit(Inst::Sse(Sse::Cvtsd2ss, narrow.clone(), src));
+                src = narrow;
+            }
+            moves.push((loc, src, class(typ)));
+        }
+        // Nothing the moves write is rax
+        if let Callee::Indirect(func) = callee {
+            self.emit(Inst::Mov(Opnd::R(RAX), v(*func)));
+        }
+        self.emit(Inst::Moves(moves));
+        match callee {
+            Callee::Direct(name) => {
+                let idx = self.module.funcs.iter().position(|x| x.name == *name);
+                self.emit(Inst::Call(Target::Func(idx.unwrap())));
+            }
+            Callee::Indirect(_) => self.emit(Inst::CallInd(Opnd::R(RAX))),
+        }
+
+        if let Some((val, typ)) = dest {
+            let (src, class) = match class(typ) {
+                Class::Int => (Opnd::R(RAX), Class::Int),
+                Class::Float => (Opnd::R(XMM0), Class::Float),
+            };
+            self.emit(Inst::Moves(vec![(v(val), src, class)]));
+            if self.narrow(typ) {
+                self.emit(Inst::Sse(Sse::Cvtss2sd, v(val), v(val)));
+            }
+        }
+    }
+
+    // What the phis in the block get, coming from another one
+    fn phi_moves(&self, from: Block, to: Block) -> Vec<(Opnd, Opnd, Class)> {
+        let mut moves = Vec::new();
+        for inst in &self.func[to].insts {
+            if let (Op::Phi(incoming), Some((dest, typ))) = (&inst.op, inst.dest) {
+                if let Some(&(_, val)) = incoming.iter().find(|(pred, _)| *pred == from) {
+                    moves.push((v(dest), v(val), class(typ)));
+                }
+            }
+        }
+        moves
+    }
+
+    // Where a branch goes, a block of its own when there are phis to fill in on the way
+    fn edge(&mut self, from: Block, to: Block) -> usize {
+        let moves = self.phi_moves(from, to);
+        let target = self.blocks[&to];
+        if moves.is_empty() {
+            return target;
+        }
+        let block = self.new_block();
+        self.code.blocks[block] = MBlock {
+            insts: vec![Inst::Moves(moves), Inst::Jmp(target)],
+            succs: vec![target],
+        };
+        block
+    }
+
+    fn term(&mut self, block: Block, term: &Terminator) {
+        match *term {
+            Terminator::Jump(to) | Terminator::Branch(_, to, _)
+                if term.successors().iter().all(|&x| x == to) =>
+            {
+                let moves = self.phi_moves(block, to);
+                if !moves.is_empty() {
+                    self.emit(Inst::Moves(moves));
+                }
+                self.jump(self.blocks[&to]);
+            }
+            Terminator::Jump(_) => unreachable!("jumps only go one place"),
+            Terminator::Branch(cond, yes, no) => {
+                let (yes, no) = (self.edge(block, yes), self.edge(block, no));
+                self.emit(Inst::Alu(Alu::Cmp, v(cond), Opnd::Imm(0)));
+                self.branch(Cond::Ne, yes, no);
+            }
+            Terminator::Return(val) => {
+                if let (Some(val), Some(typ)) = (val, self.func.ret) {
+                    let mut src = v(val);
+                    if self.narrow(typ) {
+                        let narrow = self.vreg(Class::Float);
+                        self.emit(Inst::Sse(Sse::Cvtsd2ss, narrow.clone(), src));
+                        src = narrow;
+                    }
+                    let dest = match class(typ) {
+                        Class::Int => RAX,
+                        Class::Float => XMM0,
+                    };
+                    self.emit(Inst::Moves(vec![(Opnd::R(dest), src, class(typ))]));
+                }
+                if self.runtime == Runtime::Jit {
+                    let depth = Opnd::M(Mem::Global(self.base + jit::DEPTH));
+                    self.emit(Inst::Alu(Alu::Sub, depth, Opnd::Imm(1)));
+                }
+                self.emit(Inst::Return);
+            }
+        }
+    }
+}
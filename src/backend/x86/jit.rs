@@ -0,0 +1,384 @@
+use std::mem;
+
+use super::{
+    isel::abi, Alu, Func, Inst, Mem, Opnd, RelocTarget, Runtime, Target, R12, R13, R14, R15, RAX,
+    RBP, RBX, RDI, RSI, RSP,
+};
+use crate::ir::{Function, Machine, Module, Type, Val};
+
+// Where the stub left rsp, the message and its length once something traps, and how deep calls
+// are, in that order after the module's own globals
+pub(super) const GLOBALS: [&str; 4] = ["fusion_sp", "fusion_msg", "fusion_len", "fusion_depth"];
+const SP: usize = 0;
+const MSG: usize = 1;
+const LEN: usize = 2;
+pub(super) const DEPTH: usize = 3;
+
+// Runs a module as x86-64 in memory of fusion's own, and in the IR machine when it can't. Only
+// the whole module moves over, so the globals are always in one place. Every function gets a
+// stub that takes its arguments from an array and puts the result in another, so one function
+// pointer type calls all of them, and traps unwind back to it instead of exiting
+pub struct Jit<'a> {
+    module: &'a Module,
+    run: Run<'a>,
+}
+
+enum Run<'a> {
+    Native(Native),
+    // With why it isn't native
+    Machine(Machine<'a>, String),
+}
+
+impl<'a> Jit<'a> {
+    pub fn new(module: &'a Module) -> Jit<'a> {
+        let run = match super::compile(module, Runtime::Jit).and_then(|x| load(module, x)) {
+            Ok(native) => Run::Native(native),
+            Err(err) => Run::Machine(Machine::new(module), err),
+        };
+        Jit { module, run }
+    }
+
+    // Why the module runs in the machine, if it does
+    pub fn fallback(&self) -> Option<&str> {
+        match &self.run {
+            Run::Native(_) => None,
+            Run::Machine(_, err) => Some(err),
+        }
+    }
+
+    // The same as the machine's call, down to the errors. Arguments that don't fit the function
+    // are an error either way, native code would take their bits as they are
+    pub fn call(&mut self, name: &str, args: Vec<Val>) -> Result<Option<Val>, String> {
+        let Some(idx) = self.module.funcs.iter().position(|x| x.name == name) else {
+            return Err(format!("@{} isn't a function", name));
+        };
+        check_args(self.module, &self.module.funcs[idx], &args)?;
+
+        let native = match &mut self.run {
+            Run::Native(native) => native,
+            Run::Machine(machine, _) => return machine.call(name, args),
+        };
+        let args: Vec<u64> = args.iter().map(|x| native.bits(x)).collect();
+        let bits = native.call(idx, &args)?;
+        Ok(self.module.funcs[idx].ret.map(|typ| native.val(bits, typ)))
+    }
+}
+
+fn check_args(module: &Module, func: &Function, args: &[Val]) -> Result<(), String> {
+    if args.len() != func.params.len() {
+        return Err(format!(
+            "@{} takes {} arguments, but got {}",
+            func.name,
+            func.params.len(),
+            args.len()
+        ));
+    }
+    for (arg, &(_, typ)) in args.iter().zip(&func.params) {
+        let fits = match (arg, typ) {
+            // Native code would just use the low bits, where the machine keeps the whole thing
+            (&Val::Int(x), Type::Int(_) | Type::Uint(_)) => typ.holds(x),
+            (Val::Float(_), Type::Float(_)) => true,
+            (Val::Bool(_), Type::Bool) => true,
+            (Val::Func(name), Type::Func) => module.func(name).is_some(),
+            _ => false,
+        };
+        if !fits {
+            return Err(format!("@{} can't take {} as a {}", func.name, arg, typ));
+        }
+    }
+    Ok(())
+}
+
+// Everything a stub pushes, rbp first. One more slot keeps calls sixteen byte aligned
+const PUSHED: [super::Reg; 6] = [RBP, RBX, R12, R13, R14, R15];
+
+// Back out of the stub's frame to whoever called it, from anywhere below it
+fn leave(base: usize) -> Vec<Inst> {
+    let mut code = vec![
+        Inst::Mov(Opnd::R(RSP), Opnd::M(Mem::Global(base + SP))),
+        Inst::Alu(Alu::Add, Opnd::R(RSP), Opnd::Imm(8)),
+    ];
+    code.extend(PUSHED.iter().rev().map(|&x| Inst::Pop(x)));
+    code.push(Inst::Ret);
+    code
+}
+
+// Keeps the message for the stub to find, then returns one from it
+pub(super) fn trap(base: usize) -> Vec<Inst> {
+    let mut code = vec![
+        Inst::Mov(Opnd::M(Mem::Global(base + MSG)), Opnd::R(RDI)),
+        Inst::Mov(Opnd::M(Mem::Global(base + LEN)), Opnd::R(RSI)),
+        Inst::Mov(Opnd::R(RAX), Opnd::Imm(1)),
+    ];
+    code.extend(leave(base));
+    code
+}
+
+// u64 stub(const u64 *args, u64 *result), which gives zero unless the function trapped
+fn stub(func: &Function, idx: usize, base: usize) -> Vec<Inst> {
+    let mut code: Vec<Inst> = PUSHED.iter().map(|&x| Inst::Push(x)).collect();
+    code.extend([
+        Inst::Alu(Alu::Sub, Opnd::R(RSP), Opnd::Imm(8)),
+        Inst::Mov(Opnd::M(Mem::Global(base + SP)), Opnd::R(RSP)),
+        Inst::Mov(Opnd::M(Mem::Global(base + DEPTH)), Opnd::Imm(0)),
+        Inst::Mov(Opnd::R(R12), Opnd::R(RDI)),
+        Inst::Mov(Opnd::R(R13), Opnd::R(RSI)),
+    ]);
+
+    let types: Vec<Type> = func.params.iter().map(|&(_, typ)| typ).collect();
+    let (locs, slots) = abi(&types, |off| Mem::Base(RSP, off));
+    if slots > 0 {
+        let size = (8 * slots as i64 + 15) / 16 * 16;
+        code.push(Inst::Alu(Alu::Sub, Opnd::R(RSP), Opnd::Imm(size)));
+    }
+    for (idx, loc) in locs.into_iter().enumerate() {
+        let arg = Opnd::M(Mem::Base(R12, 8 * idx as i32));
+        match loc {
+            Opnd::R(super::Reg::Xmm(_)) => code.push(Inst::Movsd(loc, arg)),
+            Opnd::R(_) => code.push(Inst::Mov(loc, arg)),
+            _ => code.extend([Inst::Mov(Opnd::R(RAX), arg), Inst::Mov(loc, Opnd::R(RAX))]),
+        }
+    }
+    code.push(Inst::Call(Target::Func(idx)));
+
+    let result = Opnd::M(Mem::Base(R13, 0));
+    match func.ret {
+        Some(Type::Float(_)) => code.push(Inst::Movsd(result, Opnd::R(super::Reg::Xmm(0)))),
+        Some(_) => code.push(Inst::Mov(result, Opnd::R(RAX))),
+        None => {}
+    }
+    code.push(Inst::Mov(Opnd::R(RAX), Opnd::Imm(0)));
+    code.extend(leave(base));
+    code
+}
+
+// What float % and ^ call, the same as the machine works them out
+extern "C" fn fmod(x: f64, y: f64) -> f64 {
+    x % y
+}
+
+extern "C" fn pow(x: f64, y: f64) -> f64 {
+    x.powf(y)
+}
+
+const PAGE: usize = 4096;
+
+fn round_up(len: usize, to: usize) -> usize {
+    len.div_ceil(to) * to
+}
+
+fn load(module: &Module, mut program: super::Program) -> Result<Native, String> {
+    let base = module.globals.len();
+    let first = program.funcs.len();
+    for (idx, func) in module.funcs.iter().enumerate() {
+        program.funcs.push(Func {
+            name: format!("fusion_stub{}", idx),
+            code: stub(func, idx, base),
+            global: false,
+        });
+    }
+    let obj = program.encode();
+
+    // The code, a jump for each extern since they can be anywhere, then the messages. The
+    // globals get pages of their own after that, since they're the only thing written
+    let mut image = obj.text.clone();
+    let mut externs: Vec<(&str, usize)> = Vec::new();
+    for reloc in &obj.relocs {
+        if let RelocTarget::Extern(name) = reloc.target {
+            if externs.iter().all(|&(x, _)| x != name) {
+                let addr = match name {
+                    "fmod" => fmod as *const () as usize,
+                    "pow" => pow as *const () as usize,
+                    _ => return Err(format!("nothing to call for {}", name)),
+                };
+                externs.push((name, image.len()));
+                // jmp *0(%rip), then the address it reads
+                image.extend([0xff, 0x25, 0, 0, 0, 0]);
+                image.extend((addr as u64).to_le_bytes());
+            }
+        }
+    }
+    let rodata = image.len();
+    image.extend(&obj.rodata);
+    let exec = round_up(image.len(), PAGE);
+    let len = exec + round_up(obj.bss, PAGE);
+
+    let mem = sys::map(len)?;
+    let start = mem as usize;
+    // Safe since the mapping is len bytes and nothing else has it yet
+    let bytes = unsafe { std::slice::from_raw_parts_mut(mem, len) };
+    bytes[..image.len()].copy_from_slice(&image);
+    for reloc in &obj.relocs {
+        let target = match reloc.target {
+            RelocTarget::Rodata => rodata,
+            RelocTarget::Bss => exec,
+            RelocTarget::Extern(name) => externs.iter().find(|x| x.0 == name).unwrap().1,
+        };
+        let rel = target as i64 + reloc.addend - reloc.offset as i64;
+        bytes[reloc.offset..reloc.offset + 4].copy_from_slice(&(rel as i32).to_le_bytes());
+    }
+    let native = Native {
+        mem,
+        len,
+        funcs: obj.funcs[..module.funcs.len()]
+            .iter()
+            .map(|x| start + x.1)
+            .collect(),
+        stubs: obj.funcs[first..].iter().map(|x| start + x.1).collect(),
+        names: module.funcs.iter().map(|x| x.name.clone()).collect(),
+        globals: (start + exec) as *const u64,
+        base,
+    };
+    // Never writable and executable at once
+    sys::protect(mem, exec)?;
+    Ok(native)
+}
+
+struct Native {
+    mem: *mut u8,
+    len: usize,
+    // Where each function and its stub start
+    funcs: Vec<usize>,
+    stubs: Vec<usize>,
+    names: Vec<String>,
+    globals: *const u64,
+    // Where the JIT's globals start
+    base: usize,
+}
+
+impl Native {
+    fn call(&mut self, idx: usize, args: &[u64]) -> Result<u64, String> {
+        // Safe since Jit::call checked there's an argument for every parameter the stub reads, and
+        // the stub writes one result
+        let stub: extern "C" fn(*const u64, *mut u64) -> u64 =
+            unsafe { mem::transmute(self.stubs[idx]) };
+        let mut result = 0;
+        if stub(args.as_ptr(), &mut result) == 0 {
+            return Ok(result);
+        }
+
+        // Safe since the trap left where its message is in .rodata
+        let msg = unsafe {
+            let ptr = *self.globals.add(self.base + MSG) as *const u8;
+            let len = *self.globals.add(self.base + LEN) as usize;
+            std::slice::from_raw_parts(ptr, len)
+        };
+        // Only the message, like the machine gives
+        let msg = String::from_utf8_lossy(msg);
+        let msg = msg.trim_end().strip_prefix("error: ").unwrap_or(&msg);
+        let msg = match msg.ends_with(')') {
+            true => &msg[..msg.rfind(" (").unwrap_or(msg.len())],
+            false => msg,
+        };
+        Err(msg.to_owned())
+    }
+
+    fn bits(&self, val: &Val) -> u64 {
+        match val {
+            Val::Int(x) => *x as u64,
+            Val::Float(x) => x.to_bits(),
+            Val::Bool(x) => *x as u64,
+            Val::Func(name) => {
+                let idx = self.names.iter().position(|x| x == name);
+                self.funcs[idx.expect("checked by Jit::call")] as u64
+            }
+        }
+    }
+
+    fn val(&self, bits: u64, typ: Type) -> Val {
+        match typ {
+            Type::Int(_) => Val::Int(bits as i64 as i128),
+            Type::Uint(_) => Val::Int(bits as i128),
+            Type::Float(_) => Val::Float(f64::from_bits(bits)),
+            Type::Bool => Val::Bool(bits != 0),
+            Type::Func => match self.funcs.iter().position(|&x| x as u64 == bits) {
+                Some(idx) => Val::Func(self.names[idx].clone()),
+                None => Val::Func(String::new()),
+            },
+        }
+    }
+}
+
+impl Drop for Native {
+    fn drop(&mut self) {
+        sys::unmap(self.mem, self.len);
+    }
+}
+
+#[cfg(all(target_os = "linux", target_arch = "x86_64"))]
+mod sys {
+    use std::{ffi::c_void, io};
+
+    const PROT_READ: i32 = 1;
+    const PROT_WRITE: i32 = 2;
+    const PROT_EXEC: i32 = 4;
+    const MAP_PRIVATE: i32 = 2;
+    const MAP_ANONYMOUS: i32 = 0x20;
+
+    extern "C" {
+        fn mmap(
+            addr: *mut c_void,
+            len: usize,
+            prot: i32,
+            flags: i32,
+            fd: i32,
+            off: i64,
+        ) -> *mut c_void;
+        fn mprotect(addr: *mut c_void, len: usize, prot: i32) -> i32;
+        fn munmap(addr: *mut c_void, len: usize) -> i32;
+    }
+
+    // Zeroed, readable and writable
+    pub fn map(len: usize) -> Result<*mut u8, String> {
+        let flags = MAP_PRIVATE | MAP_ANONYMOUS;
+        // Safe since it's a new mapping, nothing else is at the address it picks
+        let mem = unsafe {
+            mmap(
+                std::ptr::null_mut(),
+                len,
+                PROT_READ | PROT_WRITE,
+                flags,
+                -1,
+                0,
+            )
+        };
+        match mem as isize {
+            -1 => Err(format!(
+                "Couldn't map memory: {}",
+                io::Error::last_os_error()
+            )),
+            _ => Ok(mem as *mut u8),
+        }
+    }
+
+    // Makes the first len bytes executable and not writable
+    pub fn protect(mem: *mut u8, len: usize) -> Result<(), String> {
+        // Safe since the code is all written by now
+        match unsafe { mprotect(mem as *mut c_void, len, PROT_READ | PROT_EXEC) } {
+            0 => Ok(()),
+            _ => Err(format!(
+                "Couldn't make code executable: {}",
+                io::Error::last_os_error()
+            )),
+        }
+    }
+
+    pub fn unmap(mem: *mut u8, len: usize) {
+        // Safe since nothing points into it once the JIT is gone
+        unsafe { munmap(mem as *mut c_void, len) };
+    }
+}
+
+// Anywhere else the code can't run, so everything goes to the machine
+#[cfg(not(all(target_os = "linux", target_arch = "x86_64")))]
+mod sys {
+    pub fn map(_: usize) -> Result<*mut u8, String> {
+        Err("Native code only runs on x86-64 Linux".to_owned())
+    }
+
+    pub fn protect(_: *mut u8, _: usize) -> Result<(), String> {
+        unreachable!("nothing gets mapped")
+    }
+
+    pub fn unmap(_: *mut u8, _: usize) {}
+}
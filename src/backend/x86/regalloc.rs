@@ -0,0 +1,401 @@
+use std::collections::HashSet;
+
+use super::{
+    isel::Code, Alu, Class, Inst, Mem, Opnd, Reg, Sse, R10, R11, R12, R13, R14, R15, R8, R9, RBP,
+    RBX, RDI, RSI, RSP,
+};
+
+// Calls leave these alone, so they're the only ones a value can be in across one
+const SAVED: [Reg; 5] = [RBX, R12, R13, R14, R15];
+// Fixing up instructions after allocation needs a few registers of its own. rax, rcx and rdx are
+// taken by division, shifts and setcc, so none of these are ever allocated
+const X14: Reg = Reg::Xmm(14);
+const X15: Reg = Reg::Xmm(15);
+
+// Ones calls clobber come first, so values that don't live across calls leave the rest free
+fn pool(class: Class) -> Vec<Reg> {
+    match class {
+        Class::Int => [RSI, RDI, R8, R9].into_iter().chain(SAVED).collect(),
+        Class::Float => (0..14).map(Reg::Xmm).collect(),
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Loc {
+    Reg(Reg),
+    Slot(u32),
+}
+
+// Linear scan over one interval per virtual register, from the first place it's live to the
+// last, after Poletto and Sarkar. When registers run out, whichever interval ends last goes on
+// the stack. Then the parallel moves get put in order, anything an instruction can't take gets
+// fixed up and the frame goes around it all
+pub(super) fn allocate(code: Code) -> Vec<Inst> {
+    let intervals = intervals(&code);
+    let calls = calls(&code);
+    let crosses = |start: u32, end: u32| {
+        let idx = calls.partition_point(|&x| x <= start);
+        idx < calls.len() && calls[idx] < end
+    };
+
+    let mut order: Vec<u32> = (0..code.vregs.len() as u32)
+        .filter(|&x| intervals[x as usize].0 != u32::MAX)
+        .collect();
+    order.sort_by_key(|&x| intervals[x as usize].0);
+
+    let mut locs: Vec<Option<Loc>> = vec![None; code.vregs.len()];
+    let mut slots = 0;
+    // The end, the register and which one is in it
+    let mut active: Vec<(u32, Reg, u32)> = Vec::new();
+    for vreg in order {
+        let (start, end) = intervals[vreg as usize];
+        active.retain(|&(until, _, _)| until >= start);
+
+        let candidates: Vec<Reg> = pool(code.vregs[vreg as usize])
+            .into_iter()
+            .filter(|x| !crosses(start, end) || SAVED.contains(x))
+            .collect();
+        let free = candidates
+            .iter()
+            .find(|&&reg| active.iter().all(|&(_, x, _)| x != reg));
+        if let Some(&reg) = free {
+            locs[vreg as usize] = Some(Loc::Reg(reg));
+            active.push((end, reg, vreg));
+            continue;
+        }
+
+        let victim = (0..active.len())
+            .filter(|&idx| candidates.contains(&active[idx].1))
+            .max_by_key(|&idx| active[idx].0);
+        match victim {
+            Some(idx) if active[idx].0 > end => {
+                let (_, reg, other) = active[idx];
+                locs[other as usize] = Some(Loc::Slot(slots));
+                locs[vreg as usize] = Some(Loc::Reg(reg));
+                active[idx] = (end, reg, vreg);
+            }
+            _ => locs[vreg as usize] = Some(Loc::Slot(slots)),
+        }
+        slots += 1;
+    }
+
+    // Spill slots go under the saved registers, outgoing arguments at the bottom
+    let saved: Vec<Reg> = SAVED
+        .into_iter()
+        .filter(|x| locs.contains(&Some(Loc::Reg(*x))))
+        .collect();
+    let pushed = 8 * saved.len() as i32;
+    let size = 8 * (slots as i32 + code.out_slots as i32);
+    let size = (pushed + size + 15) / 16 * 16 - pushed;
+    let place = |loc: Loc| match loc {
+        Loc::Reg(reg) => Opnd::R(reg),
+        Loc::Slot(slot) => Opnd::M(Mem::Base(RBP, -pushed - 8 * (slot as i32 + 1))),
+    };
+
+    let mut out = vec![Inst::Push(RBP), Inst::Mov(Opnd::R(RBP), Opnd::R(RSP))];
+    out.extend(saved.iter().map(|&x| Inst::Push(x)));
+    if size > 0 {
+        out.push(Inst::Alu(Alu::Sub, Opnd::R(RSP), Opnd::Imm(size as i64)));
+    }
+
+    let targets: HashSet<usize> = code
+        .blocks
+        .iter()
+        .enumerate()
+        .flat_map(|(idx, block)| {
+            block.insts.iter().filter_map(move |inst| match *inst {
+                Inst::Jmp(to) if to != idx + 1 => Some(to),
+                Inst::Jcc(_, to) => Some(to),
+                _ => None,
+            })
+        })
+        .collect();
+    for (idx, block) in code.blocks.into_iter().enumerate() {
+        if targets.contains(&idx) {
+            out.push(Inst::Label(idx));
+        }
+        for mut inst in block.insts {
+            match inst {
+                Inst::Jmp(to) if to == idx + 1 => continue,
+                Inst::Return => {
+                    out.push(Inst::Mov(Opnd::R(RSP), Opnd::R(RBP)));
+                    if pushed > 0 {
+                        out.push(Inst::Alu(Alu::Sub, Opnd::R(RSP), Opnd::Imm(pushed as i64)));
+                    }
+                    out.extend(saved.iter().rev().map(|&x| Inst::Pop(x)));
+                    out.push(Inst::Pop(RBP));
+                    out.push(Inst::Ret);
+                    continue;
+                }
+                _ => {}
+            }
+            for opnd in operands_mut(&mut inst) {
+                if let Opnd::V(vreg) = *opnd {
+                    *opnd = place(locs[vreg as usize].expect("every vreg is live somewhere"));
+                }
+            }
+            legalize(inst, &mut out);
+        }
+    }
+    out
+}
+
+// Where each virtual register is first and last live, counting instructions in order
+fn intervals(code: &Code) -> Vec<(u32, u32)> {
+    let count = code.blocks.len();
+    let mut gens = vec![HashSet::new(); count];
+    let mut kills = vec![HashSet::new(); count];
+    for (idx, block) in code.blocks.iter().enumerate() {
+        for inst in &block.insts {
+            let (uses, defs) = uses_defs(inst);
+            for vreg in uses {
+                if !kills[idx].contains(&vreg) {
+                    gens[idx].insert(vreg);
+                }
+            }
+            kills[idx].extend(defs);
+        }
+    }
+
+    let mut live_in: Vec<HashSet<u32>> = vec![HashSet::new(); count];
+    let mut live_out: Vec<HashSet<u32>> = vec![HashSet::new(); count];
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for idx in (0..count).rev() {
+            let out: HashSet<u32> = code.blocks[idx]
+                .succs
+                .iter()
+                .flat_map(|&x| live_in[x].iter().copied())
+                .collect();
+            let mut live: HashSet<u32> = out.difference(&kills[idx]).copied().collect();
+            live.extend(&gens[idx]);
+            if live != live_in[idx] || out != live_out[idx] {
+                changed = true;
+                live_in[idx] = live;
+                live_out[idx] = out;
+            }
+        }
+    }
+
+    let mut intervals = vec![(u32::MAX, 0); code.vregs.len()];
+    let mut extend = |vreg: u32, pos: u32| {
+        let (start, end) = &mut intervals[vreg as usize];
+        *start = (*start).min(pos);
+        *end = (*end).max(pos);
+    };
+    let mut pos = 0;
+    for (idx, block) in code.blocks.iter().enumerate() {
+        for &vreg in &live_in[idx] {
+            extend(vreg, pos);
+        }
+        for inst in &block.insts {
+            let (uses, defs) = uses_defs(inst);
+            for vreg in uses.into_iter().chain(defs) {
+                extend(vreg, pos);
+            }
+            pos += 1;
+        }
+        for &vreg in &live_out[idx] {
+            extend(vreg, pos - 1);
+        }
+    }
+    intervals
+}
+
+// Where the calls that come back are
+fn calls(code: &Code) -> Vec<u32> {
+    let mut calls = Vec::new();
+    let mut pos = 0;
+    for block in &code.blocks {
+        // Traps don't have anywhere to go after
+        let trap = block.succs.is_empty() && !matches!(block.insts.last(), Some(Inst::Return));
+        for inst in &block.insts {
+            if matches!(inst, Inst::Call(_) | Inst::CallInd(_)) && !trap {
+                calls.push(pos);
+            }
+            pos += 1;
+        }
+    }
+    calls
+}
+
+fn vreg(opnd: &Opnd) -> Option<u32> {
+    match *opnd {
+        Opnd::V(x) => Some(x),
+        _ => None,
+    }
+}
+
+// The virtual registers the instruction reads and writes
+fn uses_defs(inst: &Inst) -> (Vec<u32>, Vec<u32>) {
+    let (uses, defs): (Vec<&Opnd>, Vec<&Opnd>) = match inst {
+        Inst::Mov(d, s)
+        | Inst::Movsd(d, s)
+        | Inst::ToXmm(d, s)
+        | Inst::FromXmm(d, s)
+        | Inst::Extend(_, _, d, s)
+        | Inst::Bsr(d, s)
+        | Inst::Bsf(d, s)
+        | Inst::Sse(Sse::Cvtss2sd | Sse::Cvtsd2ss, d, s) => (vec![s], vec![d]),
+        Inst::Alu(Alu::Cmp, x, y) | Inst::Test(x, y) | Inst::Sse(Sse::Ucomisd, x, y) => {
+            (vec![x, y], Vec::new())
+        }
+        Inst::Alu(_, d, s) | Inst::Imul(d, s) | Inst::Sse(_, d, s) => (vec![d, s], vec![d]),
+        Inst::Neg(d) | Inst::Not(d) | Inst::Shift(_, d, _) => (vec![d], vec![d]),
+        Inst::MovAbs(d, _) | Inst::Lea(d, _) | Inst::Set(_, d) => (Vec::new(), vec![d]),
+        Inst::Idiv(s) | Inst::Div(s) | Inst::CallInd(s) => (vec![s], Vec::new()),
+        Inst::Moves(moves) => (
+            moves.iter().map(|(_, s, _)| s).collect(),
+            moves.iter().map(|(d, _, _)| d).collect(),
+        ),
+        _ => (Vec::new(), Vec::new()),
+    };
+    (
+        uses.into_iter().filter_map(vreg).collect(),
+        defs.into_iter().filter_map(vreg).collect(),
+    )
+}
+
+fn operands_mut(inst: &mut Inst) -> Vec<&mut Opnd> {
+    match inst {
+        Inst::Mov(x, y)
+        | Inst::Movsd(x, y)
+        | Inst::ToXmm(x, y)
+        | Inst::FromXmm(x, y)
+        | Inst::Alu(_, x, y)
+        | Inst::Test(x, y)
+        | Inst::Imul(x, y)
+        | Inst::Extend(_, _, x, y)
+        | Inst::Bsr(x, y)
+        | Inst::Bsf(x, y)
+        | Inst::Sse(_, x, y) => vec![x, y],
+        Inst::MovAbs(x, _)
+        | Inst::Neg(x)
+        | Inst::Not(x)
+        | Inst::Shift(_, x, _)
+        | Inst::Idiv(x)
+        | Inst::Div(x)
+        | Inst::Set(_, x)
+        | Inst::Lea(x, _)
+        | Inst::CallInd(x) => vec![x],
+        Inst::Moves(moves) => moves.iter_mut().flat_map(|(d, s, _)| [d, s]).collect(),
+        _ => Vec::new(),
+    }
+}
+
+fn is_mem(opnd: &Opnd) -> bool {
+    matches!(opnd, Opnd::M(_))
+}
+
+// Puts things that can't be in memory in r10 or xmm14 for a destination, r11 or xmm15 for a
+// source
+fn legalize(inst: Inst, out: &mut Vec<Inst>) {
+    let (r10, r11, x14, x15) = (Opnd::R(R10), Opnd::R(R11), Opnd::R(X14), Opnd::R(X15));
+    match inst {
+        Inst::Mov(d, s) if is_mem(&d) && is_mem(&s) => {
+            out.push(Inst::Mov(r10.clone(), s));
+            out.push(Inst::Mov(d, r10));
+        }
+        Inst::MovAbs(d, x) if is_mem(&d) => {
+            out.push(Inst::MovAbs(r10.clone(), x));
+            out.push(Inst::Mov(d, r10));
+        }
+        Inst::Movsd(d, s) if is_mem(&d) && is_mem(&s) => {
+            out.push(Inst::Movsd(x14.clone(), s));
+            out.push(Inst::Movsd(d, x14));
+        }
+        // Memory holds the same bits either way
+        Inst::ToXmm(d, s) if is_mem(&d) => legalize(Inst::Mov(d, s), out),
+        Inst::FromXmm(d, s) if is_mem(&s) => legalize(Inst::Mov(d, s), out),
+        Inst::Alu(op, d, s) if is_mem(&d) && is_mem(&s) => {
+            out.push(Inst::Mov(r11.clone(), s));
+            out.push(Inst::Alu(op, d, r11));
+        }
+        Inst::Test(x, y) if is_mem(&x) && is_mem(&y) => {
+            out.push(Inst::Mov(r11.clone(), y));
+            out.push(Inst::Test(x, r11));
+        }
+        Inst::Imul(d, s) if is_mem(&d) => {
+            out.push(Inst::Mov(r10.clone(), d.clone()));
+            out.push(Inst::Imul(r10.clone(), s));
+            out.push(Inst::Mov(d, r10));
+        }
+        Inst::Extend(bits, signed, d, s) if is_mem(&d) => {
+            out.push(Inst::Extend(bits, signed, r10.clone(), s));
+            out.push(Inst::Mov(d, r10));
+        }
+        Inst::Bsr(d, s) if is_mem(&d) => {
+            out.push(Inst::Bsr(r10.clone(), s));
+            out.push(Inst::Mov(d, r10));
+        }
+        Inst::Bsf(d, s) if is_mem(&d) => {
+            out.push(Inst::Bsf(r10.clone(), s));
+            out.push(Inst::Mov(d, r10));
+        }
+        Inst::Lea(d, target) if is_mem(&d) => {
+            out.push(Inst::Lea(r10.clone(), target));
+            out.push(Inst::Mov(d, r10));
+        }
+        Inst::Sse(op, d, mut s) => {
+            // xorpd wants sixteen aligned bytes from memory, the slots only have eight
+            if op == Sse::Xorpd && is_mem(&s) {
+                out.push(Inst::Movsd(x15.clone(), s));
+                s = x15;
+            }
+            if !is_mem(&d) {
+                return out.push(Inst::Sse(op, d, s));
+            }
+            match op {
+                Sse::Ucomisd => {
+                    out.push(Inst::Movsd(x14.clone(), d));
+                    out.push(Inst::Sse(op, x14, s));
+                }
+                Sse::Cvtss2sd | Sse::Cvtsd2ss => {
+                    out.push(Inst::Sse(op, x14.clone(), s));
+                    out.push(Inst::Movsd(d, x14));
+                }
+                _ => {
+                    out.push(Inst::Movsd(x14.clone(), d.clone()));
+                    out.push(Inst::Sse(op, x14.clone(), s));
+                    out.push(Inst::Movsd(d, x14));
+                }
+            }
+        }
+        Inst::Moves(moves) => resolve(moves, out),
+        inst => out.push(inst),
+    }
+}
+
+fn copy(dest: Opnd, src: Opnd, class: Class) -> Inst {
+    match class {
+        Class::Int => Inst::Mov(dest, src),
+        Class::Float => Inst::Movsd(dest, src),
+    }
+}
+
+// Does a move once nothing still needs what it overwrites. When only cycles are left one of
+// them gets broken by saving a destination in r11 or xmm15
+fn resolve(mut moves: Vec<(Opnd, Opnd, Class)>, out: &mut Vec<Inst>) {
+    moves.retain(|(d, s, _)| d != s);
+    while !moves.is_empty() {
+        let ready = (0..moves.len()).find(|&idx| moves.iter().all(|(_, s, _)| *s != moves[idx].0));
+        if let Some(idx) = ready {
+            let (d, s, class) = moves.remove(idx);
+            legalize(copy(d, s, class), out);
+            continue;
+        }
+
+        let (d, _, class) = moves[0].clone();
+        let tmp = Opnd::R(match class {
+            Class::Int => R11,
+            Class::Float => X15,
+        });
+        legalize(copy(tmp.clone(), d.clone(), class), out);
+        for (_, s, _) in &mut moves {
+            if *s == d {
+                *s = tmp.clone();
+            }
+        }
+    }
+}
@@ -0,0 +1,9 @@
+use std::{io, process};
+
+use fusion::lsp;
+
+// Talks to the editor over stdin and stdout, so nothing else may print to stdout
+fn main() {
+    let code = lsp::run(io::stdin().lock(), io::stdout().lock());
+    process::exit(code);
+}
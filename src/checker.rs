@@ -1,56 +1,444 @@
-use std::{cell::RefCell, rc::Rc};
+use std::{
+    cell::{OnceCell, RefCell},
+    collections::{HashMap, HashSet},
+    rc::Rc,
+};
 
 pub(crate) mod expressions;
+pub(crate) mod generics;
+pub(crate) mod modules;
+pub(crate) mod narrowing;
+pub(crate) mod patterns;
 pub(crate) mod statements;
+pub(crate) mod traits;
 
+use self::{generics::Link, narrowing::Narrowed};
 use crate::{
-    ast::{Expr, Meta, Node},
-    error::{Error, ErrorCode},
-    program::Program,
+    ast::{self, visit::Visitor, Annot, Ast, Expr, Ident, Meta, Node, NodeId, SideTable, Stmt},
+    error::{Error, ErrorCode, Reporter},
+    location::Span,
+    program::{Instance, Method, Program},
     scope::Scope,
-    types::{self, DataType},
+    suggest,
+    symbols::{SymbolId, SymbolKind, SymbolTable},
+    types::{self, DataType, IntegralSize},
 };
 
-pub struct Checker {
-    pub lines: Rc<Vec<String>>,
-    pub prog: Program,
+pub struct Checker<'a> {
+    pub reporter: Rc<Reporter>,
+    pub symbols: Rc<RefCell<SymbolTable>>,
+    pub types: SideTable<DataType>,
+    pub calls: SideTable<usize>,
+    pub methods: SideTable<Method>,
+    pub vtables: SideTable<Vec<SymbolId>>,
+    pub instances: Rc<RefCell<Vec<Instance>>>,
+    // The other programs a generic function might come from, so its body can be checked again
+    pub others: Vec<&'a Program>,
+    prog: &'a Program,
     top: Rc<RefCell<Scope>>,
-    fn_ret: Option<DataType>,
+    fn_ret: Option<Meta<DataType>>,
+    narrowed: Narrowed,
+    // Variables no condition can narrow, found the first time one is needed
+    escaping: OnceCell<HashSet<SymbolId>>,
+    // Whether a continue leaves the code after it for good, it doesn't in top level code
+    can_continue: bool,
+    // What each type parameter is while an instance is checked
+    subst: HashMap<SymbolId, DataType>,
+    // The calls that led to the instance being checked, outermost first
+    chain: Vec<Link>,
 }
 
-impl Checker {
-    pub fn new(lines: Rc<Vec<String>>, prog: Program) -> Checker {
-        let top = Rc::clone(&prog.block.scope);
+impl<'a> Checker<'a> {
+    pub fn new(
+        reporter: Rc<Reporter>,
+        symbols: Rc<RefCell<SymbolTable>>,
+        prog: &'a Program,
+    ) -> Checker<'a> {
         Checker {
-            lines,
+            reporter,
+            symbols,
+            types: SideTable::default(),
+            calls: SideTable::default(),
+            methods: SideTable::default(),
+            vtables: SideTable::default(),
+            instances: Rc::default(),
+            others: Vec::new(),
             prog,
-            top,
+            top: prog.scope(),
             fn_ret: None,
+            narrowed: Narrowed::new(),
+            escaping: OnceCell::new(),
+            can_continue: false,
+            subst: HashMap::new(),
+            chain: Vec::new(),
+        }
+    }
+
+    fn panic(&self, message: String, span: Span, id: ErrorCode) {
+        self.raise(Error::new(message, span, id));
+    }
+
+    // Errors in an instance point back at the calls that asked for it
+    fn raise(&self, err: Error) {
+        self.reporter.panic(&self.trace(err));
+    }
+
+    // The same code checked again for other types would only warn about the same things again
+    fn lint(&self, err: Error) {
+        if self.chain.is_empty() {
+            self.reporter.lint(err);
+        }
+    }
+
+    fn at_top_level(&self) -> bool {
+        Rc::ptr_eq(&self.top, &self.prog.ast[self.prog.root].scope)
+    }
+
+    // Every name has been resolved by the time checking starts
+    fn symbol(&self, node: impl Into<NodeId>) -> SymbolId {
+        *self
+            .prog
+            .names
+            .get(node)
+            .expect("identifier wasn't resolved")
+    }
+
+    fn set_type(&mut self, name: Node<Ident>, typ: DataType) {
+        self.symbols.borrow_mut().get_mut(self.symbol(name)).typ = Some(typ.clone());
+        self.types.insert(name, typ);
+    }
+
+    // Enums and type parameters are only types, and generic functions and structs need a call to
+    // say what their types are. Every other name has a value
+    fn value_type(&self, name: Node<Ident>) -> DataType {
+        let symbols = self.symbols.borrow();
+        let symbol = symbols.get(self.symbol(name));
+        let msg = match symbol.kind {
+            SymbolKind::Enum | SymbolKind::TypeParam => "is a type, not a value",
+            SymbolKind::Trait => "is a trait, not a value",
+            _ if !symbol.generics.is_empty() => "is generic, so it can only be called",
+            _ => {
+                drop(symbols);
+                return self.symbol_type(name);
+            }
+        };
+        self.panic(
+            format!("'{}' {}", self.ast()[name].name, msg),
+            self.ast().span(name),
+            ErrorCode::InvalidExpression,
+        );
+        panic!()
+    }
+
+    fn ident_type(&self, ident: Node<Ident>) -> DataType {
+        match self.narrowed.get(&self.symbol(ident)) {
+            Some(typ) => typ.clone(),
+            None => self.value_type(ident),
+        }
+    }
+
+    fn symbol_type(&self, name: Node<Ident>) -> DataType {
+        let symbols = self.symbols.borrow();
+        let symbol = symbols.get(self.symbol(name));
+        match &symbol.typ {
+            Some(typ) => typ.clone(),
+            // The resolver makes sure names are declared before they're used
+            None => panic!("'{}' was used before it got a type", symbol.name),
+        }
+    }
+
+    // Enums, structs and type parameters get declared, everything else is built in. A generic
+    // struct needs its types, which come after its name
+    fn declared_type(&self, name: &str, args: Vec<DataType>) -> Option<DataType> {
+        let id = self.top.borrow().get(name)?;
+        let symbols = self.symbols.borrow();
+        let symbol = symbols.get(id);
+        match symbol.kind {
+            SymbolKind::Struct if args.len() == symbol.generics.len() => {
+                Some(types::Struct::new(symbol.name.clone(), id, args))
+            }
+            _ if !args.is_empty() => None,
+            SymbolKind::Enum => Some(types::Enum::new(symbol.name.clone(), id)),
+            SymbolKind::TypeParam => match self.subst.get(&id) {
+                Some(typ) => Some(typ.clone()),
+                None => symbol.typ.clone(),
+            },
+            _ => None,
+        }
+    }
+
+    // The types visible from here can be used in annotations too
+    fn type_names(&self) -> Vec<String> {
+        let mut names = types::names();
+        let mut enums: Vec<String> = self
+            .top
+            .borrow()
+            .names()
+            .into_iter()
+            .filter(|x| {
+                let id = self.top.borrow().get(x);
+                id.is_some_and(|id| {
+                    matches!(
+                        self.symbols.borrow().get(id).kind,
+                        SymbolKind::Enum | SymbolKind::Struct | SymbolKind::TypeParam
+                    )
+                })
+            })
+            .collect();
+        enums.sort();
+        enums.dedup();
+        names.extend(enums);
+        names
+    }
+
+    // Only the names in an annotation need looking up, the rest is already the type's shape. Every
+    // part gets its type. When a name isn't a type, the part of the annotation it's in comes back
+    fn named_type(&mut self, annot: Node<Annot>) -> Result<DataType, Node<Annot>> {
+        let ast = self.ast();
+        let typ = match &ast[annot] {
+            Annot::Named(name) => {
+                let name = &ast[*name].name;
+                DataType::from(name).or_else(|| self.declared_type(name, Vec::new()))
+            }
+            Annot::Tuple(items) => Some(types::Tuple::new(self.named_types(items)?)),
+            Annot::Optional(inner) => Some(types::Optional::new(self.named_type(*inner)?)),
+            Annot::Generic(name, args) => {
+                let args = self.named_types(args)?;
+                self.declared_type(&ast[*name].name, args)
+            }
+            Annot::Dyn(name) => {
+                let name = &ast[*name].name;
+                self.find_trait(name)
+                    .map(|id| types::Dyn::new(name.clone(), id))
+            }
+        };
+
+        let typ = typ.ok_or(annot)?;
+        self.types.insert(annot, typ.clone());
+        Ok(typ)
+    }
+
+    fn named_types(&mut self, annots: &[Node<Annot>]) -> Result<Vec<DataType>, Node<Annot>> {
+        annots.iter().map(|&x| self.named_type(x)).collect()
+    }
+
+    fn resolve_annot(&mut self, annot: Node<Annot>) -> DataType {
+        match self.named_type(annot) {
+            Ok(typ) => typ,
+            Err(part) => {
+                self.unknown_type(part);
+                panic!()
+            }
         }
     }
 
-    fn panic<T>(&self, message: String, node: &Meta<T>, id: ErrorCode) {
-        Error::new(Rc::clone(&self.lines), message, node.start, node.end, id).panic();
+    // Suggestions only replace the name, so the types after it stay where they are
+    fn unknown_type(&self, part: Node<Annot>) {
+        let ast = self.ast();
+        let span = ast.span(part);
+        let err = Error::new(
+            format!("Unknown type '{}'", ast[part].display(ast)),
+            span,
+            ErrorCode::UnknownType,
+        );
+
+        let name = match ast[part] {
+            Annot::Named(x) | Annot::Generic(x, _) | Annot::Dyn(x) => x,
+            Annot::Tuple(_) | Annot::Optional(_) => unreachable!("only names can be unknown"),
+        };
+        let base = ast[name].name.as_str();
+        let names = match ast[part] {
+            Annot::Dyn(_) => self.trait_names(),
+            _ => self.type_names(),
+        };
+        let err = match suggest::closest(base, names.iter().map(String::as_str)) {
+            Some(best) => err.suggest(
+                ast.span(name),
+                best.to_owned(),
+                "a type with a similar name exists".to_owned(),
+            ),
+            None => err,
+        };
+        let err = match (&ast[part], self.find_trait(base)) {
+            (Annot::Named(_), Some(_)) => err.suggest(
+                span,
+                format!("dyn {}", base),
+                "a trait is only a type with dyn in front of it".to_owned(),
+            ),
+            _ if base == "Self" => {
+                err.help("Self is only a type inside of traits and impls".to_owned())
+            }
+            _ => err,
+        };
+        let err = match self.struct_params(base) {
+            Some(params) => err.help(format!(
+                "{} needs a type for each of its parameters, like {}<{}>",
+                base,
+                base,
+                params.join(", ")
+            )),
+            None => err,
+        };
+        self.raise(err);
+    }
+
+    // The names of a generic struct's type parameters
+    fn struct_params(&self, name: &str) -> Option<Vec<String>> {
+        let id = self.top.borrow().get(name)?;
+        let symbols = self.symbols.borrow();
+        let symbol = symbols.get(id);
+        match symbol.kind {
+            SymbolKind::Struct if !symbol.generics.is_empty() => Some(
+                symbol
+                    .generics
+                    .iter()
+                    .map(|&x| symbols.get(x).name.clone())
+                    .collect(),
+            ),
+            _ => None,
+        }
     }
 
-    fn verify_cond(&mut self, cond: &mut Node<Expr>) {
+    fn verify_cond(&mut self, cond: Node<Expr>) {
         let cond_typ = self.check_expr(cond);
-        if !cond_typ.eq(&types::Bool::new()) {
-            self.panic(
-                format!("Expected bool, but instead found {}", cond_typ.to_string()),
-                &cond,
+        if cond_typ != types::Bool::new() {
+            let err = Error::new(
+                format!("Expected bool, but instead found {}", cond_typ),
+                self.ast().span(cond),
                 ErrorCode::TypeMismatch,
             );
+            self.raise(self.unwrap_help(err, &cond_typ));
         }
     }
 
     pub fn check(&mut self) {
-        // I really wish there was a better way of doing this...
-        let mut prog = self.prog.clone();
-        for stmt in &mut prog.block.stmts {
-            self.check_stmt(stmt);
-            self.prog.block.stmts.push(stmt.clone());
+        self.visit_block(self.prog.root);
+    }
+}
+
+// Statements are checked by the visitor's walk, the hooks only hand them to the check functions
+impl<'a> Visitor<'a> for Checker<'a> {
+    fn ast(&self) -> &'a Ast {
+        &self.prog.ast
+    }
+
+    fn visit_block(&mut self, block: Node<ast::Block>) {
+        self.check_block(block);
+    }
+
+    fn visit_decl(&mut self, decl: &'a ast::Decl, _stmt: Node<Stmt>) {
+        self.check_decl(decl);
+    }
+
+    fn visit_assign(&mut self, assign: &'a ast::Assign, _stmt: Node<Stmt>) {
+        self.check_assign(assign);
+    }
+
+    fn visit_if_stmt(&mut self, if_stmt: &'a ast::IfStmt, _stmt: Node<Stmt>) {
+        self.check_if(if_stmt);
+    }
+
+    fn visit_if_let(&mut self, if_let: &'a ast::IfLet, _stmt: Node<Stmt>) {
+        self.check_if_let(if_let);
+    }
+
+    fn visit_while_loop(&mut self, while_loop: &'a ast::WhileLoop, _stmt: Node<Stmt>) {
+        self.check_while(while_loop);
+    }
+
+    fn visit_do_while_loop(&mut self, do_while_loop: &'a ast::DoWhileLoop, _stmt: Node<Stmt>) {
+        self.check_do_while(do_while_loop);
+    }
+
+    fn visit_return(&mut self, ret: &'a ast::Return, stmt: Node<Stmt>) {
+        self.check_return(ret, self.ast().span(stmt));
+    }
+
+    fn visit_func(&mut self, func: &'a ast::Func, _stmt: Node<Stmt>) {
+        self.check_func(func);
+    }
+
+    fn visit_enum(&mut self, enum_stmt: &'a ast::Enum, _stmt: Node<Stmt>) {
+        // Already declared when the block started
+        self.verify_vis(enum_stmt.vis, enum_stmt.name);
+    }
+
+    fn visit_struct(&mut self, struct_stmt: &'a ast::Struct, _stmt: Node<Stmt>) {
+        self.verify_vis(struct_stmt.vis, struct_stmt.name);
+    }
+
+    fn visit_trait(&mut self, trait_stmt: &'a ast::Trait, _stmt: Node<Stmt>) {
+        // Already declared when the block started
+        self.verify_vis(trait_stmt.vis, trait_stmt.name);
+    }
+
+    fn visit_impl(&mut self, impl_stmt: &'a ast::Impl, _stmt: Node<Stmt>) {
+        self.check_impl(impl_stmt);
+    }
+
+    fn visit_use(&mut self, use_stmt: &'a ast::Use, _stmt: Node<Stmt>) {
+        self.check_use(use_stmt);
+    }
+
+    fn visit_expr_stmt(&mut self, expr_stmt: &'a ast::ExprStmt, _stmt: Node<Stmt>) {
+        // Calls are the only expressions allowed to not have a value, and those don't get a type
+        self.visit_expr(expr_stmt.expr);
+    }
+
+    fn visit_ident_expr(&mut self, ident: Node<Ident>, expr: Node<Expr>) {
+        let typ = self.ident_type(ident);
+        self.types.insert(expr, typ);
+    }
+
+    fn visit_num_lit(&mut self, _: &'a ast::NumLit, expr: Node<Expr>) {
+        // Floats aren't real, they can't hurt you
+        self.types
+            .insert(expr, types::Int::new(IntegralSize::Int32));
+    }
+
+    fn visit_bool_lit(&mut self, _: &'a ast::BoolLit, expr: Node<Expr>) {
+        self.types.insert(expr, types::Bool::new());
+    }
+
+    fn visit_none_lit(&mut self, expr: Node<Expr>) {
+        self.types.insert(expr, DataType::None);
+    }
+
+    fn visit_binary_op(&mut self, binop: &'a ast::BinaryOp, expr: Node<Expr>) {
+        let typ = self.check_binop(binop);
+        self.types.insert(expr, typ);
+    }
+
+    fn visit_unary_op(&mut self, unop: &'a ast::UnaryOp, expr: Node<Expr>) {
+        let typ = self.check_unop(unop);
+        self.types.insert(expr, typ);
+    }
+
+    fn visit_path_expr(&mut self, path: Node<ast::Path>, expr: Node<Expr>) {
+        let typ = self.check_path(path);
+        self.types.insert(expr, typ);
+    }
+
+    fn visit_call(&mut self, call: &'a ast::Call, expr: Node<Expr>) {
+        if let Some(typ) = self.check_call(expr, call) {
+            self.types.insert(expr, typ);
+        }
+    }
+
+    fn visit_field(&mut self, field: &'a ast::Field, expr: Node<Expr>) {
+        let typ = self.check_field(field);
+        self.types.insert(expr, typ);
+    }
+
+    fn visit_tuple(&mut self, tuple: &'a ast::Tuple, expr: Node<Expr>) {
+        let items = tuple.items.iter().map(|&x| self.check_expr(x)).collect();
+        self.types.insert(expr, types::Tuple::new(items));
+    }
+
+    // Like calls, a match only has a value when all of its arms do
+    fn visit_match(&mut self, match_expr: &'a ast::Match, expr: Node<Expr>) {
+        if let Some(typ) = self.check_match(match_expr) {
+            self.types.insert(expr, typ);
         }
-        self.prog = prog;
     }
 }
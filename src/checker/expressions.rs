@@ -1,77 +1,310 @@
 use crate::{
-    ast::{self, Expr, Node},
-    error::ErrorCode,
-    types::{self, DataType, IntegralSize},
+    ast::{self, visit::Visitor, Expr, Node},
+    error::{Error, ErrorCode},
+    location::Span,
+    suggest,
+    tokens::{Type, ORDERED_BINARY_OPERATORS},
+    types::{self, DataType},
 };
 
 use super::Checker;
 
-impl Checker {
-    pub(crate) fn check_expr(&mut self, node: &mut Node<Expr>) -> DataType {
-        let copy = node.clone();
-        let typ = match &mut node.src {
-            Expr::Ident(i) => self.check_ident(copy, i),
-            Expr::NumLit(_) => types::Int::new(IntegralSize::Int32), // Floats aren't real, they can't hurt you
-            Expr::BoolLit(_) => types::Bool::new(),
-            Expr::BinaryOp(binop) => self.check_binop(binop),
-            Expr::UnaryOp(unop) => self.check_unop(unop),
-
-            // In case any other expressions are added
-            #[allow(unreachable_patterns)]
-            _ => {
+impl Checker<'_> {
+    // Somewhere a value is needed, so calls without a return type aren't allowed
+    pub(crate) fn check_expr(&mut self, expr: Node<Expr>) -> DataType {
+        self.visit_expr(expr);
+        match self.types.get(expr) {
+            Some(typ) => typ.clone(),
+            None => {
+                let what = match self.ast()[expr] {
+                    Expr::Match(_) => "match",
+                    _ => "call",
+                };
                 self.panic(
-                    "Invalid expression".to_owned(),
-                    node,
-                    ErrorCode::InvalidExpression,
+                    format!("This {} does not return a value", what),
+                    self.ast().span(expr),
+                    ErrorCode::TypeMismatch,
                 );
                 panic!()
             }
-        };
+        }
+    }
+
+    pub(crate) fn check_binop(&mut self, binop: &ast::BinaryOp) -> DataType {
+        let left_typ = self.check_expr(binop.lhs);
+        let right_typ = self.check_expr(binop.rhs);
+
+        let op = &binop.op.src;
+        if *op == Type::Coalesce {
+            return self.check_coalesce(binop, &left_typ, &right_typ);
+        }
+
+        // Anything that might be none can be checked against none, or against what it holds
+        let is_eq = matches!(op, Type::Equal | Type::NotEqual);
+        let comparable = is_eq && (left_typ.fits(&right_typ) || right_typ.fits(&left_typ));
+        if left_typ != right_typ && !comparable {
+            let err = Error::new(
+                format!(
+                    "Cannot use the {} operator on {} and {}",
+                    op.src_strings().first().unwrap(),
+                    left_typ,
+                    right_typ,
+                ),
+                binop.op.span,
+                ErrorCode::TypeMismatch,
+            )
+            .label(self.ast().span(binop.lhs), format!("this is {}", left_typ))
+            .label(self.ast().span(binop.rhs), format!("this is {}", right_typ));
+            let optional = match left_typ {
+                DataType::Optional(_) | DataType::None => &left_typ,
+                _ => &right_typ,
+            };
+            self.raise(self.unwrap_help(err, optional));
+        }
+
+        if !comparable {
+            for (side, typ) in [(binop.lhs, &left_typ), (binop.rhs, &right_typ)] {
+                self.verify_present(side, typ, op);
+            }
+        }
+        // Checking against none works for anything, what it holds doesn't need to be comparable
+        if left_typ != DataType::None && right_typ != DataType::None {
+            self.verify_op(op, &left_typ, binop.op.span);
+        }
+
+        // Comparisons are the third row of the precedence table
+        match op.is(ORDERED_BINARY_OPERATORS[2]) {
+            true => types::Bool::new(),
+            false => left_typ, // Since left_typ == right_typ
+        }
+    }
+
+    // Only == and != can see past a none, every other operator needs a value
+    fn verify_present(&self, expr: Node<Expr>, typ: &DataType, op: &Type) {
+        if let DataType::Optional(_) | DataType::None = typ {
+            let err = Error::new(
+                format!(
+                    "Cannot use the {} operator on {}, it might be none",
+                    op.src_strings().first().unwrap(),
+                    typ,
+                ),
+                self.ast().span(expr),
+                ErrorCode::TypeMismatch,
+            );
+            self.raise(self.unwrap_help(err, typ));
+        }
+    }
 
-        node.typ = Some(typ.clone());
+    pub(crate) fn check_unop(&mut self, unop: &ast::UnaryOp) -> DataType {
+        let typ = self.check_expr(unop.val);
+        self.verify_present(unop.val, &typ, &unop.op.src);
+        self.verify_op(&unop.op.src, &typ, unop.op.span);
         typ
     }
 
-    pub(crate) fn check_ident(&mut self, node: Node<Expr>, ident: &mut ast::Ident) -> DataType {
-        match self.top.borrow().get(&ident.name) {
-            Ok(vari) => match vari.borrow().typ.as_ref() {
-                Some(val) => return val.clone(),
-                None => self.panic(
-                    format!("The variable '{}' does not exist", ident.name),
-                    &node,
-                    ErrorCode::VariableNotFound,
+    // x ?? y is x when it isn't none and y when it is, so y has to be able to stand in for x.
+    // It's only optional if y is too
+    pub(crate) fn coalesce_type(&self, left: &DataType, right: &DataType) -> Option<DataType> {
+        match left {
+            DataType::Optional(x) if *right == *x.inner => Some(right.clone()),
+            DataType::Optional(_) if right.fits(left) => Some(left.clone()),
+            _ => None,
+        }
+    }
+
+    fn check_coalesce(
+        &self,
+        binop: &ast::BinaryOp,
+        left_typ: &DataType,
+        right_typ: &DataType,
+    ) -> DataType {
+        if let Some(typ) = self.coalesce_type(left_typ, right_typ) {
+            return typ;
+        }
+
+        let err = match left_typ {
+            DataType::Optional(x) => Error::new(
+                format!(
+                    "Expected a default of type {}, but got {}",
+                    x.inner, right_typ
                 ),
-            },
-            Err(err) => self.panic(
-                format!("The variable '{}' does not exist", ident.name),
-                &node,
-                err,
+                self.ast().span(binop.rhs),
+                ErrorCode::TypeMismatch,
+            )
+            .label(self.ast().span(binop.lhs), format!("this is {}", left_typ)),
+            _ => Error::new(
+                format!("?? needs an optional on its left, but this is {}", left_typ),
+                self.ast().span(binop.lhs),
+                ErrorCode::TypeMismatch,
             ),
         };
-        DataType::Bool(types::Bool {})
+        self.raise(err);
+        panic!()
     }
 
-    pub(crate) fn check_binop(&mut self, binop: &mut ast::BinaryOp) -> DataType {
-        let left_typ = self.check_expr(&mut binop.lhs);
-        let right_typ = self.check_expr(&mut binop.rhs);
+    // a?.0 is none when a is
+    pub(crate) fn check_field(&mut self, field: &ast::Field) -> DataType {
+        let typ = self.check_expr(field.val);
+        let (typ, optional) = match typ {
+            DataType::Optional(x) if field.optional => (*x.inner, true),
+            typ => (typ, false),
+        };
 
-        if !left_typ.eq(&right_typ) {
-            self.panic(
-                format!(
-                    "Cannot use the {} operator on {} and {}",
-                    binop.op.src.src_strings().get(0).unwrap(),
-                    left_typ.to_string(),
-                    right_typ.to_string(),
-                ),
-                &binop.op,
+        let item = self.field_type(&typ, self.ast().span(field.val), field.name);
+        match optional {
+            true => types::Optional::new(item),
+            false => item,
+        }
+    }
+
+    // Tuple fields are numbered, struct fields are named. A generic struct's fields have its
+    // types filled in
+    pub(crate) fn field_type(
+        &self,
+        typ: &DataType,
+        span: Span,
+        name: Node<ast::Ident>,
+    ) -> DataType {
+        let field = &self.ast()[name].name;
+        let field_span = self.ast().span(name);
+        let err = match typ {
+            DataType::Tuple(x) => {
+                if let Some(item) = field.parse::<usize>().ok().and_then(|idx| x.items.get(idx)) {
+                    return item.clone();
+                }
+                let err = Error::new(
+                    format!("{} has no field {}", typ, field),
+                    field_span,
+                    ErrorCode::InvalidExpression,
+                );
+                match x.items.len() {
+                    0 => err,
+                    len => err.help(format!("its fields go from 0 to {}", len - 1)),
+                }
+            }
+            DataType::Struct(x) => {
+                let symbols = self.symbols.borrow();
+                let symbol = symbols.get(x.id);
+                let members = symbol.members.as_ref().unwrap().borrow();
+                if let Some(id) = members.get(field) {
+                    let map = symbol
+                        .generics
+                        .iter()
+                        .copied()
+                        .zip(x.args.clone())
+                        .collect();
+                    return symbols.get(id).typ.as_ref().unwrap().subst(&map);
+                }
+
+                let err = Error::new(
+                    format!("{} has no field '{}'", typ, field),
+                    field_span,
+                    ErrorCode::InvalidExpression,
+                )
+                .label(symbol.span, "struct declared here".to_owned());
+                let names = members.names();
+                match suggest::closest(field, names.iter().map(String::as_str)) {
+                    Some(best) => err.suggest(
+                        field_span,
+                        best.to_owned(),
+                        "a field with a similar name exists".to_owned(),
+                    ),
+                    None => err,
+                }
+            }
+            DataType::Optional(_) | DataType::None => Error::new(
+                format!("This is {}, so it might not have any fields", typ),
+                span,
                 ErrorCode::TypeMismatch,
-            );
+            )
+            .help("use ?. to only get the field when there is one".to_owned()),
+            _ => Error::new(
+                format!("{} doesn't have fields", typ),
+                field_span,
+                ErrorCode::InvalidExpression,
+            ),
+        };
+        let err = match self.is_method(typ, field) {
+            true => err.help(format!("'{}' is a method, so it has to be called", field)),
+            false => err,
+        };
+        self.raise(err);
+        panic!()
+    }
+
+    // Points at the function being called, when it has a name
+    pub(crate) fn label_callee(&self, err: Error, callee: Node<Expr>) -> Error {
+        let names = &self.prog.names;
+        let id = match &self.ast()[callee] {
+            Expr::Ident(ident) => names.get(*ident).copied(),
+            Expr::Path(path) => names.get(*path).copied(),
+            _ => None,
+        };
+
+        match id {
+            Some(id) => {
+                let span = self.symbols.borrow().get(id).span;
+                err.label(span, "function declared here".to_owned())
+            }
+            None => err,
         }
+    }
+
+    pub(crate) fn check_call(&mut self, expr: Node<Expr>, call: &ast::Call) -> Option<DataType> {
+        if let Some(typ) = self.check_method_call(expr, call) {
+            return typ;
+        }
+        if let Some((id, generics)) = self.generic_callee(call.callee) {
+            return self.check_generic_call(expr, call, id, &generics);
+        }
+
+        let func = match self.check_expr(call.callee) {
+            DataType::Function(func) => func,
+            typ => {
+                self.panic(
+                    format!("Cannot call a value of type {}", typ),
+                    self.ast().span(call.callee),
+                    ErrorCode::InvalidCall,
+                );
+                panic!()
+            }
+        };
 
-        left_typ // Since left_typ == right_typ
+        self.verify_arity(self.ast().span(expr), call, func.params.len());
+        for (&arg, param) in call.args.iter().zip(&func.params) {
+            let typ = self.check_expr(arg);
+            self.verify_arg(call, arg, &typ, param);
+        }
+
+        func.ret.map(|ret| *ret)
     }
 
-    pub(crate) fn check_unop(&mut self, unop: &mut ast::UnaryOp) -> DataType {
-        self.check_expr(&mut unop.val)
+    pub(crate) fn verify_arity(&self, span: Span, call: &ast::Call, params: usize) {
+        if params != call.args.len() {
+            let err = Error::new(
+                format!("Expected {} arguments, but got {}", params, call.args.len()),
+                span,
+                ErrorCode::InvalidCall,
+            );
+            self.raise(self.label_callee(err, call.callee));
+        }
+    }
+
+    pub(crate) fn verify_arg(
+        &mut self,
+        call: &ast::Call,
+        arg: Node<Expr>,
+        typ: &DataType,
+        param: &DataType,
+    ) {
+        if !self.coerce(arg, typ, param) {
+            let err = Error::new(
+                format!("Expected an argument of type {}, but got {}", param, typ),
+                self.ast().span(arg),
+                ErrorCode::TypeMismatch,
+            );
+            let err = self.dyn_help(self.unwrap_help(err, typ), typ, param);
+            self.raise(self.label_callee(err, call.callee));
+        }
     }
 }
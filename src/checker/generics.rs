@@ -0,0 +1,434 @@
+use std::{
+    cell::OnceCell,
+    collections::{HashMap, HashSet},
+    rc::Rc,
+};
+
+use crate::{
+    ast::{self, visit::Visitor, Expr, Ident, Node, NodeKind, SideTable},
+    error::{Error, ErrorCode},
+    location::Span,
+    program::{Instance, Program},
+    suggest,
+    symbols::{SymbolId, SymbolKind},
+    tokens::Type,
+    types::{self, Bound, DataType},
+};
+
+use super::{narrowing::Narrowed, Checker};
+
+// One of the calls on the way to the instance being checked, like max<int32>
+#[derive(Debug, Clone)]
+pub(crate) struct Link {
+    func: SymbolId,
+    name: String,
+    span: Span,
+}
+
+// A function with this many instances inside of each other is taken to never stop needing more,
+// like one that calls itself with a tuple of what it got
+const MAX_DEPTH: usize = 8;
+
+// Works out type parameters from what was passed for them. The first argument that says what one
+// is wins, a later one that disagrees is a mismatch once the types are filled in
+fn infer(param: &DataType, arg: &DataType, found: &mut HashMap<SymbolId, DataType>) {
+    match (param, arg) {
+        // none doesn't say what it's missing
+        (DataType::Param(_), DataType::None) => (),
+        (DataType::Param(x), arg) => {
+            found.entry(x.id).or_insert_with(|| arg.clone());
+        }
+        (DataType::Optional(x), DataType::Optional(y)) => infer(&x.inner, &y.inner, found),
+        (DataType::Optional(x), arg) => infer(&x.inner, arg, found),
+        (DataType::Array(x), DataType::Array(y)) => infer(&x.typ, &y.typ, found),
+        (DataType::Tuple(x), DataType::Tuple(y)) => {
+            for (param, arg) in x.items.iter().zip(&y.items) {
+                infer(param, arg, found);
+            }
+        }
+        (DataType::Function(x), DataType::Function(y)) => {
+            for (param, arg) in x.params.iter().zip(&y.params) {
+                infer(param, arg, found);
+            }
+            if let (Some(param), Some(arg)) = (&x.ret, &y.ret) {
+                infer(param, arg, found);
+            }
+        }
+        (DataType::Struct(x), DataType::Struct(y)) if x.id == y.id => {
+            for (param, arg) in x.args.iter().zip(&y.args) {
+                infer(param, arg, found);
+            }
+        }
+        _ => (),
+    }
+}
+
+impl<'a> Checker<'a> {
+    // Every call that led here gets pointed at. A function that keeps calling itself only gets
+    // its first one, the types only get bigger after that
+    pub(crate) fn trace(&self, mut err: Error) -> Error {
+        let mut seen = HashSet::new();
+        for link in &self.chain {
+            if seen.insert(link.span) {
+                err = err.label(
+                    link.span,
+                    format!("while checking {} for this call", link.name),
+                );
+            }
+        }
+        err
+    }
+
+    // A type parameter is its own type, which can only do what its bounds say. Traits are bounds
+    // too, for methods
+    pub(crate) fn declare_generics(&mut self, generics: &[Node<ast::TypeParam>]) {
+        for &param in generics {
+            let ast::TypeParam { name, ref bounds } = self.ast()[param];
+            let (mut found, mut traits) = (Vec::new(), Vec::new());
+            for &bound in bounds {
+                self.resolve_bound(bound, &mut found, &mut traits);
+            }
+            let typ = types::Param::new(
+                self.ast()[name].name.clone(),
+                self.symbol(name),
+                found,
+                traits,
+            );
+            self.set_type(name, typ);
+        }
+    }
+
+    fn resolve_bound(
+        &self,
+        bound: Node<Ident>,
+        bounds: &mut Vec<Bound>,
+        traits: &mut Vec<SymbolId>,
+    ) {
+        let name = &self.ast()[bound].name;
+        if let Some(bound) = Bound::from(name) {
+            bounds.push(bound);
+            return;
+        }
+        if let Some(id) = self.find_trait(name) {
+            traits.push(id);
+            return;
+        }
+
+        let span = self.ast().span(bound);
+        let mut names: Vec<String> = Bound::VALUES.iter().map(|x| x.to_string()).collect();
+        let err = Error::new(
+            format!("Unknown bound '{}'", name),
+            span,
+            ErrorCode::UnknownType,
+        )
+        .help(format!(
+            "the bounds are {}, and any trait can be one too",
+            names.join(", ")
+        ));
+        names.extend(self.trait_names());
+        let err = match suggest::closest(name, names.iter().map(String::as_str)) {
+            Some(best) => err.suggest(
+                span,
+                best.to_owned(),
+                "a bound with a similar name exists".to_owned(),
+            ),
+            None => err,
+        };
+        self.raise(err);
+        panic!()
+    }
+
+    // Every operator is in a row of the table in types, and only works on types with its bound
+    pub(crate) fn verify_op(&self, op: &Type, typ: &DataType, span: Span) {
+        let Some(bound) = Bound::of(op) else {
+            return;
+        };
+        if typ.has(bound) {
+            return;
+        }
+
+        let op = op.src_strings().remove(0);
+        let err = match typ {
+            DataType::Param(param) => {
+                let err = Error::new(
+                    format!(
+                        "Cannot use the {} operator on {}, it doesn't have the {} bound",
+                        op, typ, bound
+                    ),
+                    span,
+                    ErrorCode::UnsatisfiedBound,
+                )
+                .label(
+                    self.symbols.borrow().get(param.id).span,
+                    format!("{} declared here", param.name),
+                );
+                self.suggest_bound(err, param, bound)
+            }
+            _ => Error::new(
+                format!("Cannot use the {} operator on {}", op, typ),
+                span,
+                ErrorCode::TypeMismatch,
+            ),
+        };
+        self.raise(err);
+    }
+
+    // Adds the missing bound to where the type parameter was declared
+    fn suggest_bound(&self, err: Error, param: &types::Param, bound: Bound) -> Error {
+        let ast = self.ast();
+        let decl = ast.ids().find_map(|id| match ast.kind(id) {
+            NodeKind::TypeParam(x) if self.prog.names.get(x.name) == Some(&param.id) => Some(x),
+            _ => None,
+        });
+        let Some(decl) = decl else {
+            return err;
+        };
+
+        let (span, replacement) = match decl.bounds.last() {
+            Some(&last) => (ast.span(last), format!("{} + {}", ast[last].name, bound)),
+            None => (ast.span(decl.name), format!("{}: {}", param.name, bound)),
+        };
+        err.suggest(
+            span,
+            replacement,
+            format!("give {} the {} bound", param.name, bound),
+        )
+    }
+
+    // The function or struct a call names, when it has type parameters to fill in
+    pub(crate) fn generic_callee(&self, callee: Node<Expr>) -> Option<(SymbolId, Vec<SymbolId>)> {
+        let id = match &self.ast()[callee] {
+            Expr::Ident(x) => *self.prog.names.get(*x)?,
+            Expr::Path(x) => *self.prog.names.get(*x)?,
+            _ => return None,
+        };
+        let generics = self.symbols.borrow().get(id).generics.clone();
+        match generics.is_empty() {
+            true => None,
+            false => Some((id, generics)),
+        }
+    }
+
+    // The type parameters come from the arguments. A call with all of them known runs its own
+    // instance, calls inside a generic function get one when that function does
+    pub(crate) fn check_generic_call(
+        &mut self,
+        expr: Node<Expr>,
+        call: &ast::Call,
+        id: SymbolId,
+        generics: &[SymbolId],
+    ) -> Option<DataType> {
+        let span = self.ast().span(expr);
+        let (kind, func) = {
+            let symbols = self.symbols.borrow();
+            let symbol = symbols.get(id);
+            match &symbol.typ {
+                Some(DataType::Function(func)) => (symbol.kind, func.clone()),
+                _ => unreachable!("generic functions and structs are always called"),
+            }
+        };
+
+        self.verify_arity(span, call, func.params.len());
+        let args: Vec<DataType> = call.args.iter().map(|&x| self.check_expr(x)).collect();
+        let mut found = HashMap::new();
+        for (param, arg) in func.params.iter().zip(&args) {
+            infer(param, arg, &mut found);
+        }
+
+        let mut types = Vec::new();
+        for &param in generics {
+            let Some(typ) = found.get(&param) else {
+                let symbols = self.symbols.borrow();
+                let name = &symbols.get(param).name;
+                let err = Error::new(
+                    format!(
+                        "Can't work out what {} is for this call to '{}'",
+                        name,
+                        symbols.get(id).name
+                    ),
+                    span,
+                    ErrorCode::UnknownType,
+                )
+                .label(symbols.get(param).span, format!("{} declared here", name))
+                .help(format!(
+                    "only the arguments say what {} is, so one of them needs a type with {} in it",
+                    name, name
+                ));
+                self.raise(err);
+                panic!()
+            };
+            self.verify_bounds(param, typ, span);
+            types.push(typ.clone());
+        }
+
+        let found = generics.iter().copied().zip(types.clone()).collect();
+        let params: Vec<DataType> = func.params.iter().map(|x| x.subst(&found)).collect();
+        let ret = func.ret.map(|x| x.subst(&found));
+        for ((&arg, typ), param) in call.args.iter().zip(&args).zip(&params) {
+            self.verify_arg(call, arg, typ, param);
+        }
+        self.types
+            .insert(call.callee, types::Function::new(params, ret.clone()));
+
+        if kind == SymbolKind::Function && !types.iter().any(DataType::is_generic) {
+            let idx = self.instantiate(id, types, span);
+            self.calls.insert(expr, idx);
+        }
+        ret
+    }
+
+    fn verify_bounds(&self, param: SymbolId, typ: &DataType, span: Span) {
+        let symbols = self.symbols.borrow();
+        let decl = symbols.get(param);
+        let Some(DataType::Param(decl_typ)) = &decl.typ else {
+            unreachable!("type parameters get their type when they're declared");
+        };
+
+        for &bound in &decl_typ.bounds {
+            if typ.has(bound) {
+                continue;
+            }
+            let err = Error::new(
+                format!(
+                    "{} doesn't have the {} bound that {} needs",
+                    typ, bound, decl.name
+                ),
+                span,
+                ErrorCode::UnsatisfiedBound,
+            )
+            .label(decl.span, format!("{} declared here", decl.name))
+            .note(format!(
+                "{} lets a type use {}",
+                bound,
+                bound
+                    .ops()
+                    .iter()
+                    .map(|x| x.src_strings().remove(0))
+                    .collect::<Vec<_>>()
+                    .join(" ")
+            ));
+            let err = match typ {
+                DataType::Param(x) => self.suggest_bound(err, x, bound),
+                _ => err,
+            };
+            self.raise(err);
+        }
+
+        for &trait_id in &decl_typ.traits {
+            if self.implements(typ, trait_id) {
+                continue;
+            }
+            let trait_name = &symbols.get(trait_id).name;
+            let err = Error::new(
+                format!(
+                    "{} doesn't implement {}, which {} needs",
+                    typ, trait_name, decl.name
+                ),
+                span,
+                ErrorCode::UnsatisfiedBound,
+            )
+            .label(decl.span, format!("{} declared here", decl.name));
+            let err = match typ {
+                DataType::Param(x) => err.help(format!(
+                    "give {} the {} bound, like {}: {}",
+                    x.name, trait_name, x.name, trait_name
+                )),
+                _ => err.help(format!(
+                    "it needs an impl {} for {} {{ }} with every method of {}",
+                    trait_name, typ, trait_name
+                )),
+            };
+            self.raise(err);
+        }
+    }
+
+    // Where a generic function is declared, which might be in another module or an earlier input
+    fn find_func(&self, id: SymbolId) -> (&'a Program, &'a ast::Func) {
+        std::iter::once(self.prog)
+            .chain(self.others.iter().copied())
+            .find_map(|prog| prog.func(id).map(|func| (prog, func)))
+            .expect("a function that has a symbol was declared somewhere")
+    }
+
+    // Checks a generic function's body again with its type parameters filled in, once for every
+    // set of types it gets called with. The instance keeps those types for the interpreter
+    fn instantiate(&mut self, func: SymbolId, args: Vec<DataType>, span: Span) -> usize {
+        let found = self
+            .instances
+            .borrow()
+            .iter()
+            .position(|x| x.func == func && x.args == args);
+        if let Some(idx) = found {
+            return idx;
+        }
+
+        let generics = self.symbols.borrow().get(func).generics.clone();
+        let names: Vec<String> = args.iter().map(|x| x.to_string()).collect();
+        let name = format!(
+            "{}<{}>",
+            self.symbols.borrow().get(func).name,
+            names.join(", ")
+        );
+        if self.chain.iter().filter(|x| x.func == func).count() >= MAX_DEPTH {
+            let err = Error::new(
+                format!(
+                    "'{}' keeps getting called with bigger types",
+                    self.symbols.borrow().get(func).name
+                ),
+                span,
+                ErrorCode::InvalidCall,
+            )
+            .help("a generic function can't call itself with a bigger type every time".to_owned());
+            self.raise(err);
+        }
+
+        let idx = self.instances.borrow().len();
+        self.instances.borrow_mut().push(Instance {
+            func,
+            args: args.clone(),
+            types: SideTable::default(),
+            calls: SideTable::default(),
+            methods: SideTable::default(),
+            vtables: SideTable::default(),
+        });
+
+        let (prog, decl) = self.find_func(func);
+        let mut chain = self.chain.clone();
+        chain.push(Link { func, name, span });
+        let mut others = self.others.clone();
+        others.push(self.prog);
+
+        let mut checker = Checker {
+            reporter: Rc::clone(&self.reporter),
+            symbols: Rc::clone(&self.symbols),
+            types: SideTable::default(),
+            calls: SideTable::default(),
+            methods: SideTable::default(),
+            vtables: SideTable::default(),
+            instances: Rc::clone(&self.instances),
+            others,
+            prog,
+            top: prog.scope(),
+            fn_ret: None,
+            narrowed: Narrowed::new(),
+            escaping: OnceCell::new(),
+            can_continue: false,
+            subst: generics.into_iter().zip(args).collect(),
+            chain,
+        };
+
+        // The body sets the types of what it declares, and the generic version of it might still
+        // be getting checked
+        let saved = self.symbols.borrow().clone();
+        checker.func_type(decl);
+        checker.check_func(decl);
+        *self.symbols.borrow_mut() = saved;
+
+        let mut instances = self.instances.borrow_mut();
+        instances[idx].types = checker.types;
+        instances[idx].calls = checker.calls;
+        instances[idx].methods = checker.methods;
+        instances[idx].vtables = checker.vtables;
+        idx
+    }
+}
@@ -0,0 +1,42 @@
+use crate::{
+    ast::{self, visit::Visitor, Node},
+    location::Span,
+    symbols::SymbolKind,
+    types::DataType,
+};
+
+use super::Checker;
+
+impl Checker<'_> {
+    // The resolver already found the item, all that's left is its type. Paths that start with a
+    // variable only had the variable resolved, the rest are its fields
+    pub(crate) fn check_path(&mut self, path: Node<ast::Path>) -> DataType {
+        let segments = &self.ast()[path].segments;
+        if self.prog.names.contains(path) {
+            return self.value_type(*segments.last().unwrap());
+        }
+
+        self.chain_type(segments)
+    }
+
+    // A variable and the fields after it
+    pub(crate) fn chain_type(&self, segments: &[Node<ast::Ident>]) -> DataType {
+        let start = self.ast().span(segments[0]);
+        let mut typ = self.ident_type(segments[0]);
+        for pair in segments.windows(2) {
+            let span = Span::new(start.file, start.lo, self.ast().span(pair[0]).hi);
+            typ = self.field_type(&typ, span, pair[1]);
+        }
+        typ
+    }
+
+    // Imported enums don't have a value, but they still have a type. Traits have neither
+    pub(crate) fn check_use(&mut self, use_stmt: &ast::Use) {
+        let item = *self.ast()[use_stmt.path].segments.last().unwrap();
+        if self.symbols.borrow().get(self.symbol(item)).kind == SymbolKind::Trait {
+            return;
+        }
+        let typ = self.symbol_type(item);
+        self.types.insert(use_stmt.path, typ);
+    }
+}
@@ -0,0 +1,258 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::{
+    ast::{
+        self,
+        visit::{walk, Visitor},
+        Ast, Expr, Node, Stmt,
+    },
+    error::{Error, ErrorCode},
+    program::Program,
+    symbols::SymbolId,
+    tokens::Type,
+    types::DataType,
+};
+
+use super::Checker;
+
+// Variables declared as T? that are known to hold a T at this point. Conditions like x != none
+// add to it for the code they guard, assigning something that might be none takes away from it.
+// Calls aren't looked into, so anything a function assigns without declaring it is never narrowed
+pub(crate) type Narrowed = HashMap<SymbolId, DataType>;
+
+// Every variable assigned somewhere in a loop, those can't stay narrowed going into it
+struct Assigned<'a> {
+    prog: &'a Program,
+    ids: HashSet<SymbolId>,
+}
+
+impl<'a> Visitor<'a> for Assigned<'a> {
+    fn ast(&self) -> &'a Ast {
+        &self.prog.ast
+    }
+
+    fn visit_assign(&mut self, assign: &'a ast::Assign, _stmt: Node<Stmt>) {
+        if let Some(&id) = self.prog.names.get(assign.name) {
+            self.ids.insert(id);
+        }
+        walk::walk_assign(self, assign);
+    }
+}
+
+// Variables assigned by a function that didn't declare them, like a global or a local of the
+// function around it. Any call could be the one that changes them
+struct Escaping<'a> {
+    prog: &'a Program,
+    // The function being walked, none at the top level
+    func: Option<SymbolId>,
+    owners: HashMap<SymbolId, Option<SymbolId>>,
+    assigns: Vec<(SymbolId, Option<SymbolId>)>,
+}
+
+impl Escaping<'_> {
+    fn declare(&mut self, name: Node<ast::Ident>) {
+        if let Some(&id) = self.prog.names.get(name) {
+            self.owners.insert(id, self.func);
+        }
+    }
+}
+
+impl<'a> Visitor<'a> for Escaping<'a> {
+    fn ast(&self) -> &'a Ast {
+        &self.prog.ast
+    }
+
+    fn visit_decl(&mut self, decl: &'a ast::Decl, _stmt: Node<Stmt>) {
+        self.declare(decl.name);
+        walk::walk_decl(self, decl);
+    }
+
+    fn visit_param(&mut self, param: Node<ast::Param>) {
+        self.declare(self.prog.ast[param].name);
+    }
+
+    fn visit_if_let(&mut self, if_let: &'a ast::IfLet, _stmt: Node<Stmt>) {
+        self.declare(if_let.name);
+        walk::walk_if_let(self, if_let);
+    }
+
+    fn visit_assign(&mut self, assign: &'a ast::Assign, _stmt: Node<Stmt>) {
+        if let Some(&id) = self.prog.names.get(assign.name) {
+            self.assigns.push((id, self.func));
+        }
+        walk::walk_assign(self, assign);
+    }
+
+    fn visit_func(&mut self, func: &'a ast::Func, _stmt: Node<Stmt>) {
+        let outer = std::mem::replace(&mut self.func, self.prog.names.get(func.name).copied());
+        walk::walk_func(self, func);
+        self.func = outer;
+    }
+}
+
+// What was narrowed before and still is, anything a block changed doesn't last past it
+pub(crate) fn keep(before: Narrowed, after: &Narrowed) -> Narrowed {
+    before
+        .into_iter()
+        .filter(|(id, typ)| after.get(id) == Some(typ))
+        .collect()
+}
+
+impl Checker<'_> {
+    // Every program with a function that could be called from here gets looked at once
+    fn escaping(&self) -> &HashSet<SymbolId> {
+        self.escaping.get_or_init(|| {
+            let mut escaping = Escaping {
+                prog: self.prog,
+                func: None,
+                owners: HashMap::new(),
+                assigns: Vec::new(),
+            };
+            for &prog in self.others.iter().chain([&self.prog]) {
+                escaping.prog = prog;
+                escaping.visit_block(prog.root);
+            }
+            let Escaping {
+                owners, assigns, ..
+            } = escaping;
+            assigns
+                .into_iter()
+                .filter(|(id, func)| owners.get(id) != Some(func))
+                .map(|(id, _)| id)
+                .collect()
+        })
+    }
+
+    // What a condition coming out as `when` says about which optionals aren't none
+    fn facts(&self, cond: Node<Expr>, when: bool) -> Vec<(SymbolId, DataType)> {
+        let ast = self.ast();
+        match &ast[cond] {
+            Expr::UnaryOp(x) if x.op.src == Type::Not => self.facts(x.val, !when),
+            Expr::BinaryOp(x) => match (&x.op.src, when) {
+                // Both sides have to be true for an & to be, and both false for an |
+                (Type::And, true) | (Type::Or, false) => {
+                    let mut facts = self.facts(x.lhs, when);
+                    facts.extend(self.facts(x.rhs, when));
+                    facts
+                }
+                (Type::NotEqual, true) | (Type::Equal, false) => {
+                    let name = match (&ast[x.lhs], &ast[x.rhs]) {
+                        (Expr::Ident(name), Expr::NoneLit) | (Expr::NoneLit, Expr::Ident(name)) => {
+                            *name
+                        }
+                        _ => return Vec::new(),
+                    };
+                    let id = self.symbol(name);
+                    if self.escaping().contains(&id) {
+                        return Vec::new();
+                    }
+                    match &self.symbols.borrow().get(id).typ {
+                        Some(DataType::Optional(typ)) => vec![(id, *typ.inner.clone())],
+                        _ => Vec::new(),
+                    }
+                }
+                _ => Vec::new(),
+            },
+            _ => Vec::new(),
+        }
+    }
+
+    // Whether the code after a block only runs when the block wasn't entered
+    fn exits(&self, block: Node<ast::Block>) -> bool {
+        self.ast()[block]
+            .stmts
+            .iter()
+            .any(|&stmt| match &self.ast()[stmt] {
+                Stmt::Return(_) => true,
+                Stmt::Continue => self.can_continue,
+                Stmt::Block(x) => self.exits(*x),
+                _ => false,
+            })
+    }
+
+    fn forget_assigned(&mut self, block: Node<ast::Block>) {
+        let mut assigned = Assigned {
+            prog: self.prog,
+            ids: HashSet::new(),
+        };
+        assigned.visit_block(block);
+        self.narrowed.retain(|id, _| !assigned.ids.contains(id));
+    }
+
+    // Assigning a T keeps a narrowed variable narrowed, only something that might be none undoes it
+    pub(crate) fn assigned(&mut self, id: SymbolId, val: &DataType) {
+        if let DataType::Optional(_) | DataType::None = val {
+            self.narrowed.remove(&id);
+        }
+    }
+
+    // Code that only runs when the condition is true knows what it knows, and code after an if
+    // that always leaves knows the opposite
+    pub(crate) fn check_if(&mut self, if_stmt: &ast::IfStmt) {
+        self.verify_cond(if_stmt.cond);
+
+        let before = self.narrowed.clone();
+        self.narrowed.extend(self.facts(if_stmt.cond, true));
+        self.visit_block(if_stmt.body);
+        self.narrowed = keep(before, &self.narrowed);
+
+        if self.exits(if_stmt.body) {
+            self.narrowed.extend(self.facts(if_stmt.cond, false));
+        }
+    }
+
+    pub(crate) fn check_if_let(&mut self, if_let: &ast::IfLet) {
+        let typ = match self.check_expr(if_let.val) {
+            DataType::Optional(x) => *x.inner,
+            typ => {
+                self.panic(
+                    format!("if let unwraps an optional, but this is {}", typ),
+                    self.ast().span(if_let.val),
+                    ErrorCode::TypeMismatch,
+                );
+                panic!()
+            }
+        };
+
+        self.set_type(if_let.name, typ);
+        self.visit_block(if_let.body);
+    }
+
+    // The condition is checked before every run of the body, so what it says holds at the start
+    // of each one. Loops can only end by the condition coming out false
+    pub(crate) fn check_while(&mut self, while_loop: &ast::WhileLoop) {
+        self.forget_assigned(while_loop.body);
+        self.verify_cond(while_loop.cond);
+
+        let before = self.narrowed.clone();
+        self.narrowed.extend(self.facts(while_loop.cond, true));
+        self.in_loop(while_loop.body);
+        self.narrowed = keep(before, &self.narrowed);
+
+        self.narrowed.extend(self.facts(while_loop.cond, false));
+    }
+
+    pub(crate) fn check_do_while(&mut self, do_while_loop: &ast::DoWhileLoop) {
+        self.forget_assigned(do_while_loop.body);
+        self.in_loop(do_while_loop.body);
+        self.verify_cond(do_while_loop.cond);
+
+        self.narrowed.extend(self.facts(do_while_loop.cond, false));
+    }
+
+    fn in_loop(&mut self, body: Node<ast::Block>) {
+        let can_continue = std::mem::replace(&mut self.can_continue, true);
+        self.visit_block(body);
+        self.can_continue = can_continue;
+    }
+
+    // Optionals have to be checked against none or given a default before they can be used
+    pub(crate) fn unwrap_help(&self, err: Error, typ: &DataType) -> Error {
+        match typ {
+            DataType::Optional(_) | DataType::None => {
+                err.help("check that it isn't none first, or give it a default with ??".to_owned())
+            }
+            _ => err,
+        }
+    }
+}
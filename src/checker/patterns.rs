@@ -0,0 +1,451 @@
+use std::rc::Rc;
+
+use crate::{
+    ast::{self, visit::Visitor, Expr, Node, Pattern, Stmt},
+    error::{Error, ErrorCode},
+    interpreter,
+    lint::Lint,
+    symbols::{SymbolId, SymbolKind, SymbolTable},
+    tokens::Type,
+    types::{self, DataType},
+};
+
+use super::Checker;
+
+// How many missing patterns an error lists before it only counts the rest
+const SHOWN: usize = 3;
+
+impl Checker<'_> {
+    // Arms with a value all need the same type, the same way every branch of an if would
+    pub(crate) fn check_match(&mut self, match_expr: &ast::Match) -> Option<DataType> {
+        let typ = self.check_expr(match_expr.val);
+
+        let mut res: Option<(DataType, Node<Stmt>)> = None;
+        let mut valued = true;
+        for &arm in &match_expr.arms {
+            let body = self.ast()[arm].body;
+            let Some(val) = self.check_arm(arm, &typ) else {
+                valued = false;
+                continue;
+            };
+
+            match &res {
+                Some((first, span)) if *first != val => self.raise(
+                    Error::new(
+                        format!("Expected this arm to be {}, but it is {}", first, val),
+                        self.ast().span(body),
+                        ErrorCode::TypeMismatch,
+                    )
+                    .label(self.ast().span(*span), format!("this is {}", first)),
+                ),
+                Some(_) => (),
+                None => res = Some((val, body)),
+            }
+        }
+
+        self.check_exhaustive(match_expr, &typ);
+        res.filter(|_| valued).map(|(typ, _)| typ)
+    }
+
+    fn check_arm(&mut self, arm: Node<ast::Arm>, typ: &DataType) -> Option<DataType> {
+        let arm = &self.ast()[arm];
+        let top = std::mem::replace(&mut self.top, Rc::clone(&arm.scope));
+
+        self.check_pattern(arm.pat, typ);
+        if let Some(guard) = arm.guard {
+            self.verify_cond(guard);
+        }
+
+        let val = match &self.ast()[arm.body] {
+            Stmt::ExprStmt(x) => {
+                self.visit_expr(x.expr);
+                self.types.get(x.expr).cloned()
+            }
+            _ => {
+                self.visit_stmt(arm.body);
+                None
+            }
+        };
+
+        self.top = top;
+        val
+    }
+
+    fn check_pattern(&mut self, pat: Node<Pattern>, typ: &DataType) {
+        let ast = self.ast();
+        let span = ast.span(pat);
+        let mismatch = |checker: &Self, found: &str| {
+            checker.panic(
+                format!("Expected a pattern of type {}, but found {}", typ, found),
+                span,
+                ErrorCode::TypeMismatch,
+            );
+        };
+
+        match &ast[pat] {
+            Pattern::Wildcard => (),
+            Pattern::Binding(name) => self.set_type(*name, typ.clone()),
+            Pattern::Literal(lit) => match (&ast[*lit], typ) {
+                (Expr::BoolLit(_), DataType::Bool(_)) => {
+                    self.types.insert(*lit, typ.clone());
+                }
+                (Expr::BoolLit(_), _) => mismatch(self, "a bool"),
+                // Numbers take the type they're matched against, like they would in a comparison
+                (_, DataType::Int(_) | DataType::Uint(_)) => {
+                    if let Expr::UnaryOp(x) = &ast[*lit] {
+                        self.types.insert(x.val, typ.clone());
+                    }
+                    self.types.insert(*lit, typ.clone());
+                }
+                _ => mismatch(self, "a number"),
+            },
+            Pattern::Tuple(items) => match typ {
+                DataType::Tuple(tuple) if tuple.items.len() == items.len() => {
+                    for (&item, typ) in items.iter().zip(&tuple.items) {
+                        self.check_pattern(item, typ);
+                    }
+                }
+                _ => mismatch(self, &format!("a tuple of {}", items.len())),
+            },
+            Pattern::Variant(path, fields) => {
+                let item = *ast[*path].segments.last().unwrap();
+                let id = self.symbol(item);
+                let (kind, variant_typ) = {
+                    let symbols = self.symbols.borrow();
+                    let symbol = symbols.get(id);
+                    (symbol.kind, symbol.typ.clone())
+                };
+                if kind != SymbolKind::Variant {
+                    self.panic(
+                        format!("'{}' is not an enum variant", ast[*path].display(ast)),
+                        ast.span(*path),
+                        ErrorCode::InvalidExpression,
+                    );
+                }
+
+                let (enum_typ, params) = match variant_typ {
+                    Some(DataType::Function(func)) => (*func.ret.unwrap(), func.params),
+                    Some(typ) => (typ, Vec::new()),
+                    None => panic!("variants get their type when their block starts"),
+                };
+                if enum_typ != *typ {
+                    mismatch(self, &enum_typ.to_string());
+                }
+                if params.len() != fields.len() {
+                    self.panic(
+                        format!(
+                            "'{}' has {} fields, but the pattern has {}",
+                            ast[*path].display(ast),
+                            params.len(),
+                            fields.len()
+                        ),
+                        span,
+                        ErrorCode::TypeMismatch,
+                    );
+                }
+
+                for (&field, typ) in fields.iter().zip(&params) {
+                    self.check_pattern(field, typ);
+                }
+            }
+        }
+
+        self.types.insert(pat, typ.clone());
+    }
+
+    // An arm is unreachable when the ones before it already match everything it does. Arms
+    // with a guard might not match at all, so they don't cover anything
+    fn check_exhaustive(&self, match_expr: &ast::Match, typ: &DataType) {
+        let symbols = Rc::clone(&self.symbols);
+        let symbols = symbols.borrow();
+        let space = Space {
+            symbols: &symbols,
+            checker: self,
+        };
+
+        let types = [typ.clone()];
+        let mut rows = Vec::new();
+        for &arm in &match_expr.arms {
+            let arm = &self.ast()[arm];
+            let pat = space.lower(arm.pat);
+            if !space.useful(&rows, std::slice::from_ref(&pat), &types) {
+                self.lint(Error::lint(
+                    Lint::UnreachablePatterns,
+                    "This arm is unreachable, the arms before it match everything it does"
+                        .to_owned(),
+                    self.ast().span(arm.pat),
+                ));
+            }
+            if arm.guard.is_none() {
+                rows.push(vec![pat]);
+            }
+        }
+
+        let missing: Vec<String> = space
+            .missing(&rows, &types)
+            .into_iter()
+            .map(|x| space.show(&x[0], typ))
+            .collect();
+        if missing.is_empty() {
+            return;
+        }
+
+        let mut listed = missing[..missing.len().min(SHOWN)].join(", ");
+        if missing.len() > SHOWN {
+            listed = format!("{} and {} more", listed, missing.len() - SHOWN);
+        }
+        self.raise(
+            Error::new(
+                format!("This match doesn't cover {}", listed),
+                self.ast().span(match_expr.val),
+                ErrorCode::NonExhaustiveMatch,
+            )
+            .help(
+                "add an arm for every missing pattern, or a _ arm to match everything else"
+                    .to_owned(),
+            ),
+        );
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Ctor {
+    Bool(bool),
+    Int(i128),
+    Tuple,
+    // The variant's position in its enum
+    Variant(usize),
+}
+
+// A pattern with everything that doesn't decide what it matches taken out, bindings are
+// wildcards and every value is built by a constructor
+#[derive(Debug, Clone)]
+enum Pat {
+    Wild,
+    Ctor(Ctor, Vec<Pat>),
+}
+
+impl Pat {
+    fn ctor(&self) -> Option<&Ctor> {
+        match self {
+            Pat::Wild => None,
+            Pat::Ctor(ctor, _) => Some(ctor),
+        }
+    }
+}
+
+// Rows are the arms so far, each one a list of patterns for the values still left to match.
+// This is the usefulness check from Maranget's "Warnings for pattern matching"
+struct Space<'a, 'b> {
+    symbols: &'a SymbolTable,
+    checker: &'a Checker<'b>,
+}
+
+impl Space<'_, '_> {
+    fn lower(&self, pat: Node<Pattern>) -> Pat {
+        let ast = self.checker.ast();
+        let typ = self.checker.types.get(pat);
+        match &ast[pat] {
+            Pattern::Wildcard | Pattern::Binding(_) => Pat::Wild,
+            Pattern::Literal(lit) => {
+                let ctor = match &ast[*lit] {
+                    Expr::BoolLit(x) => Ctor::Bool(x.val),
+                    Expr::NumLit(x) => Ctor::Int(interpreter::wrap(x.val as i128, typ)),
+                    Expr::UnaryOp(x) => match (&x.op.src, &ast[x.val]) {
+                        (Type::Subtraction, Expr::NumLit(num)) => {
+                            Ctor::Int(interpreter::wrap(-(num.val as i128), typ))
+                        }
+                        _ => unreachable!("the parser only makes negative numbers"),
+                    },
+                    _ => unreachable!("the parser only makes number and bool literals"),
+                };
+                Pat::Ctor(ctor, Vec::new())
+            }
+            Pattern::Tuple(items) => {
+                Pat::Ctor(Ctor::Tuple, items.iter().map(|&x| self.lower(x)).collect())
+            }
+            Pattern::Variant(path, fields) => {
+                let item = *ast[*path].segments.last().unwrap();
+                let id = self.checker.symbol(item);
+                let idx = match typ {
+                    Some(DataType::Enum(typ)) => self
+                        .variants(typ)
+                        .iter()
+                        .position(|(_, x)| *x == id)
+                        .unwrap(),
+                    _ => unreachable!("variant patterns are checked against an enum"),
+                };
+                let fields = fields.iter().map(|&x| self.lower(x)).collect();
+                Pat::Ctor(Ctor::Variant(idx), fields)
+            }
+        }
+    }
+
+    // In the order they were declared
+    fn variants(&self, typ: &types::Enum) -> Vec<(String, SymbolId)> {
+        let members = self.symbols.get(typ.id).members.as_ref().unwrap();
+        members.borrow().locals()
+    }
+
+    // Numbers have too many values to list, so only a wildcard can cover all of them
+    fn ctors(&self, typ: &DataType) -> Option<Vec<Ctor>> {
+        match typ {
+            DataType::Bool(_) => Some(vec![Ctor::Bool(false), Ctor::Bool(true)]),
+            DataType::Tuple(_) => Some(vec![Ctor::Tuple]),
+            DataType::Enum(typ) => Some((0..self.variants(typ).len()).map(Ctor::Variant).collect()),
+            _ => None,
+        }
+    }
+
+    fn fields(&self, ctor: &Ctor, typ: &DataType) -> Vec<DataType> {
+        match (ctor, typ) {
+            (Ctor::Tuple, DataType::Tuple(x)) => x.items.clone(),
+            (Ctor::Variant(idx), DataType::Enum(x)) => {
+                let (_, id) = self.variants(x)[*idx];
+                match &self.symbols.get(id).typ {
+                    Some(DataType::Function(func)) => func.params.clone(),
+                    _ => Vec::new(),
+                }
+            }
+            _ => Vec::new(),
+        }
+    }
+
+    // The rows that can match a value made by ctor, with its fields in place of the first column
+    fn specialize(&self, rows: &[Vec<Pat>], ctor: &Ctor, arity: usize) -> Vec<Vec<Pat>> {
+        rows.iter()
+            .filter_map(|row| {
+                let mut res = match &row[0] {
+                    Pat::Wild => vec![Pat::Wild; arity],
+                    Pat::Ctor(x, fields) if x == ctor => fields.clone(),
+                    Pat::Ctor(..) => return None,
+                };
+                res.extend_from_slice(&row[1..]);
+                Some(res)
+            })
+            .collect()
+    }
+
+    // The rows that match whatever a constructor none of them name makes
+    fn default(&self, rows: &[Vec<Pat>]) -> Vec<Vec<Pat>> {
+        rows.iter()
+            .filter(|row| matches!(row[0], Pat::Wild))
+            .map(|row| row[1..].to_vec())
+            .collect()
+    }
+
+    // The constructors that show up in the first column, and whether they're all of them
+    fn used(&self, rows: &[Vec<Pat>], typ: &DataType) -> (Vec<Ctor>, Option<Vec<Ctor>>) {
+        let mut used: Vec<Ctor> = Vec::new();
+        for ctor in rows.iter().filter_map(|row| row[0].ctor()) {
+            if !used.contains(ctor) {
+                used.push(ctor.clone());
+            }
+        }
+
+        let all = self.ctors(typ);
+        let complete = all.filter(|all| all.iter().all(|x| used.contains(x)));
+        (used, complete)
+    }
+
+    // Whether some value matches q but none of the rows
+    fn useful(&self, rows: &[Vec<Pat>], q: &[Pat], types: &[DataType]) -> bool {
+        let Some((head, rest)) = q.split_first() else {
+            return rows.is_empty();
+        };
+
+        let with_fields = |ctor: &Ctor, fields: &[Pat]| {
+            let typs = self.fields(ctor, &types[0]);
+            let rows = self.specialize(rows, ctor, typs.len());
+            let q = [fields, rest].concat();
+            self.useful(&rows, &q, &[typs.as_slice(), &types[1..]].concat())
+        };
+
+        match head {
+            Pat::Ctor(ctor, fields) => with_fields(ctor, fields),
+            Pat::Wild => match self.used(rows, &types[0]) {
+                (_, Some(all)) => all.iter().any(|ctor| {
+                    let arity = self.fields(ctor, &types[0]).len();
+                    with_fields(ctor, &vec![Pat::Wild; arity])
+                }),
+                _ => self.useful(&self.default(rows), rest, &types[1..]),
+            },
+        }
+    }
+
+    // Values none of the rows match, one pattern per column
+    fn missing(&self, rows: &[Vec<Pat>], types: &[DataType]) -> Vec<Vec<Pat>> {
+        let Some((typ, rest)) = types.split_first() else {
+            return match rows.is_empty() {
+                true => vec![Vec::new()],
+                false => Vec::new(),
+            };
+        };
+
+        match self.used(rows, typ) {
+            (_, Some(all)) => {
+                let mut res = Vec::new();
+                for ctor in all {
+                    let typs = self.fields(&ctor, typ);
+                    let arity = typs.len();
+                    let rows = self.specialize(rows, &ctor, arity);
+                    for mut pats in self.missing(&rows, &[typs, rest.to_vec()].concat()) {
+                        let tail = pats.split_off(arity);
+                        res.push([vec![Pat::Ctor(ctor.clone(), pats)], tail].concat());
+                    }
+                }
+                res
+            }
+            (used, None) => {
+                let tails = self.missing(&self.default(rows), rest);
+                // Naming the missing constructors reads better than _, when there's a list of them
+                let heads = match self.ctors(typ) {
+                    Some(all) if !used.is_empty() => all
+                        .into_iter()
+                        .filter(|x| !used.contains(x))
+                        .map(|ctor| {
+                            let arity = self.fields(&ctor, typ).len();
+                            Pat::Ctor(ctor, vec![Pat::Wild; arity])
+                        })
+                        .collect(),
+                    _ => vec![Pat::Wild],
+                };
+
+                let mut res = Vec::new();
+                for head in &heads {
+                    for tail in &tails {
+                        res.push([vec![head.clone()], tail.clone()].concat());
+                    }
+                }
+                res
+            }
+        }
+    }
+
+    fn show(&self, pat: &Pat, typ: &DataType) -> String {
+        let Pat::Ctor(ctor, fields) = pat else {
+            return "_".to_owned();
+        };
+
+        let typs = self.fields(ctor, typ);
+        let fields: Vec<String> = fields
+            .iter()
+            .zip(&typs)
+            .map(|(pat, typ)| self.show(pat, typ))
+            .collect();
+        match (ctor, typ) {
+            (Ctor::Bool(x), _) => x.to_string(),
+            (Ctor::Int(x), _) => x.to_string(),
+            (Ctor::Tuple, _) if fields.len() == 1 => format!("({},)", fields[0]),
+            (Ctor::Tuple, _) => format!("({})", fields.join(", ")),
+            (Ctor::Variant(idx), DataType::Enum(x)) => {
+                let (name, _) = &self.variants(x)[*idx];
+                match fields.is_empty() {
+                    true => format!("{}.{}", x.name, name),
+                    false => format!("{}.{}({})", x.name, name, fields.join(", ")),
+                }
+            }
+            _ => unreachable!("variants only come from enums"),
+        }
+    }
+}
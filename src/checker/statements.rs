@@ -1,181 +1,298 @@
 use std::rc::Rc;
 
 use crate::{
-    ast::{self, Node, Stmt},
-    error::ErrorCode,
-    types::DataType,
+    ast::{
+        self,
+        visit::{walk, Visitor},
+        Meta, Node, Stmt, Visibility,
+    },
+    error::{Error, ErrorCode},
+    location::Span,
+    tokens::Type,
+    types::{self, DataType},
 };
 
-use super::Checker;
-
-impl Checker {
-    pub(crate) fn check_stmt(&mut self, node: &mut Node<Stmt>) {
-        let copy = node.clone();
-        match &mut node.src {
-            Stmt::Block(ref mut x) => self.check_block(x),
-            Stmt::Decl(ref mut x) => self.check_decl(x),
-            Stmt::Assign(ref mut x) => self.check_assign(x),
-            Stmt::IfStmt(ref mut x) => self.check_if_stmt(x),
-            Stmt::WhileLoop(ref mut x) => self.check_while_loop(x),
-            Stmt::DoWhileLoop(ref mut x) => self.check_do_while_loop(x),
-            Stmt::Continue => (),
-            Stmt::Return(ref mut x) => self.check_return(copy, x),
-            Stmt::Func(ref mut x) => self.check_func(x),
-
-            // In case any other statements are added
-            #[allow(unreachable_patterns)]
-            _ => self.panic(
-                "Invalid statement".to_owned(),
-                node,
-                ErrorCode::InvalidStatement,
-            ),
+use super::{narrowing, Checker};
+
+impl Checker<'_> {
+    pub(crate) fn check_block(&mut self, block: Node<ast::Block>) {
+        let top = Rc::clone(&self.top);
+        self.top = Rc::clone(&self.ast()[block].scope);
+        let narrowed = self.narrowed.clone();
+
+        self.declare_enums(&self.ast()[block].stmts);
+        self.declare_structs(&self.ast()[block].stmts);
+        self.declare_traits(&self.ast()[block].stmts);
+        self.declare_impls(&self.ast()[block].stmts);
+        self.declare_funcs(&self.ast()[block].stmts);
+        walk::walk_block(self, block);
+
+        self.top = top;
+        self.narrowed = narrowing::keep(narrowed, &self.narrowed);
+    }
+
+    // Every enum is a type before any variant gets its fields, so enums can hold each other.
+    // Variants with fields are functions that make the enum, the rest are already one
+    pub(crate) fn declare_enums(&mut self, stmts: &[Node<Stmt>]) {
+        let enums: Vec<&ast::Enum> = stmts
+            .iter()
+            .filter_map(|&stmt| match &self.ast()[stmt] {
+                Stmt::Enum(x) => Some(x),
+                _ => None,
+            })
+            .collect();
+
+        for enum_stmt in &enums {
+            let id = self.symbol(enum_stmt.name);
+            let typ = types::Enum::new(self.ast()[enum_stmt.name].name.clone(), id);
+            self.symbols.borrow_mut().get_mut(id).typ = Some(typ);
+        }
+
+        for enum_stmt in &enums {
+            let typ = self.symbol_type(enum_stmt.name);
+            for &variant in &enum_stmt.variants {
+                let ast::Variant { name, ref fields } = self.ast()[variant];
+                let fields: Vec<DataType> = fields.iter().map(|&x| self.resolve_annot(x)).collect();
+                let variant_typ = match fields.is_empty() {
+                    true => typ.clone(),
+                    false => types::Function::new(fields, Some(typ.clone())),
+                };
+                self.symbols.borrow_mut().get_mut(self.symbol(name)).typ = Some(variant_typ);
+            }
         }
     }
 
-    pub(crate) fn check_block(&mut self, block: &mut ast::Block) {
-        let top = Rc::clone(&self.top);
-        self.top = Rc::clone(&block.scope);
+    // Struct types don't need anything from their symbol, so the fields can use any of them right
+    // away. Calling a struct makes one, so its symbol is a function from its fields
+    pub(crate) fn declare_structs(&mut self, stmts: &[Node<Stmt>]) {
+        for &stmt in stmts {
+            let Stmt::Struct(struct_stmt) = &self.ast()[stmt] else {
+                continue;
+            };
 
-        for stmt in &mut block.stmts {
-            self.check_stmt(stmt);
+            let top = std::mem::replace(&mut self.top, Rc::clone(&struct_stmt.scope));
+            self.declare_generics(&struct_stmt.generics);
+            let fields = struct_stmt
+                .fields
+                .iter()
+                .map(|&field| {
+                    let ast::Param { name, annot } = self.ast()[field];
+                    let typ = self.resolve_annot(annot);
+                    self.set_type(name, typ.clone());
+                    typ
+                })
+                .collect();
+            let args = struct_stmt
+                .generics
+                .iter()
+                .map(|&x| self.symbol_type(self.ast()[x].name))
+                .collect();
+            self.top = top;
+
+            let id = self.symbol(struct_stmt.name);
+            let name = self.ast()[struct_stmt.name].name.clone();
+            let typ = types::Struct::new(name, id, args);
+            self.set_type(struct_stmt.name, types::Function::new(fields, Some(typ)));
         }
+    }
 
-        self.top = top;
+    // Functions can be called before they are declared, so their types are known up front
+    pub(crate) fn declare_funcs(&mut self, stmts: &[Node<Stmt>]) {
+        for &stmt in stmts {
+            if let Stmt::Func(func) = &self.ast()[stmt] {
+                let typ = self.func_type(func);
+                self.set_type(func.name, typ);
+            }
+        }
     }
 
-    pub(crate) fn check_decl(&mut self, decl: &mut ast::Decl) {
-        let val = self.check_expr(&mut decl.val);
-        match &mut decl.annot {
+    pub(crate) fn verify_vis(&self, vis: Visibility, name: Node<ast::Ident>) {
+        if vis == Visibility::Public && !self.at_top_level() {
+            self.panic(
+                format!(
+                    "'{}' cannot be public, only top level items can be",
+                    self.ast()[name].name
+                ),
+                self.ast().span(name),
+                ErrorCode::InvalidStatement,
+            );
+        }
+    }
+
+    pub(crate) fn check_decl(&mut self, decl: &ast::Decl) {
+        self.verify_vis(decl.vis, decl.name);
+
+        let val = self.check_expr(decl.val);
+        let typ = match decl.annot {
             Some(annot) => {
-                let (annot_typ, val_typ) = (annot.src.name.clone(), val.to_string());
-                if annot_typ != val_typ {
-                    self.panic(
+                let annot_typ = self.resolve_annot(annot);
+                if !self.coerce(decl.val, &val, &annot_typ) {
+                    let err = Error::new(
                         format!(
                             "'{}' is defined to be type {}, but assigned {}",
-                            decl.name.src.name, annot_typ, val_typ,
+                            self.ast()[decl.name].name,
+                            annot_typ,
+                            val,
                         ),
-                        &decl.val,
+                        self.ast().span(decl.val),
                         ErrorCode::TypeMismatch,
+                    )
+                    .label(
+                        self.ast().span(annot),
+                        "expected because of this annotation".to_owned(),
                     );
+                    let err = self.unwrap_help(err, &val);
+                    self.raise(self.dyn_help(err, &val, &annot_typ));
                 }
+                annot_typ
             }
-            None => (),
+            // none alone doesn't say what it's standing in for
+            None if val == DataType::None => {
+                let name = &self.ast()[decl.name].name;
+                self.raise(
+                    Error::new(
+                        format!("The type of '{}' can't be worked out from none", name),
+                        self.ast().span(decl.name),
+                        ErrorCode::UnknownType,
+                    )
+                    .help(format!(
+                        "give it an optional type, like let {}: int32? = none",
+                        name
+                    )),
+                );
+                panic!()
+            }
+            None => val.clone(),
         };
 
-        // Set type in scope
-        let name = &decl.name.src.name;
-        if let Some(err) = self.top.borrow_mut().set(&name, val) {
-            self.panic(
-                format!("The variable '{}' does not exist", name),
-                &decl.name,
-                err,
-            );
-        }
+        self.set_type(decl.name, typ);
     }
 
-    pub(crate) fn check_assign(&mut self, assign: &mut ast::Assign) {
-        let val = self.check_expr(&mut assign.val);
-        // TODO: Check if operator is legal for the type
-
-        let name = &assign.name.src.name;
+    pub(crate) fn check_assign(&mut self, assign: &ast::Assign) {
+        let val = self.check_expr(assign.val);
 
-        match self.top.borrow().get(&name) {
-            Ok(vari) => {
-                let typ = vari.borrow().typ.as_ref().unwrap().clone();
-                if typ != val {
-                    self.panic(
+        let id = self.symbol(assign.name);
+        let typ = self.symbol_type(assign.name);
+        // x ??= y can always be done to an optional, other operators need what x holds now
+        let val = match assign.op.as_ref().map(|x| &x.src) {
+            Some(Type::Coalesce) => self.coalesce_type(&typ, &val).unwrap_or(val),
+            Some(op) => {
+                let cur = self.narrowed.get(&id).unwrap_or(&typ).clone();
+                if let DataType::Optional(_) = cur {
+                    let err = Error::new(
                         format!(
-                            "Tried to assign type {}, expected type {}",
-                            val.to_string(),
-                            typ.to_string()
+                            "Cannot use the {} operator on {}, it might be none",
+                            op.src_strings().first().unwrap(),
+                            cur,
                         ),
-                        &assign.name,
+                        self.ast().span(assign.name),
                         ErrorCode::TypeMismatch,
                     );
+                    self.raise(self.unwrap_help(err, &cur));
                 }
+                self.verify_op(op, &cur, assign.op.as_ref().unwrap().span);
+                val
             }
-            Err(err) => self.panic(
-                format!("The variable '{}' does not exist", name),
-                &assign.name,
-                err,
-            ),
-        }
-    }
-
-    pub(crate) fn check_if_stmt(&mut self, if_stmt: &mut ast::IfStmt) {
-        self.verify_cond(&mut if_stmt.cond);
-        self.check_block(&mut if_stmt.body.src);
-    }
+            None => val,
+        };
 
-    pub(crate) fn check_while_loop(&mut self, while_loop: &mut ast::WhileLoop) {
-        self.verify_cond(&mut while_loop.cond);
-        self.check_block(&mut while_loop.body.src);
-    }
+        if !self.coerce(assign.val, &val, &typ) {
+            let decl = self.symbols.borrow().get(id).span;
+            let err = Error::new(
+                format!("Tried to assign type {}, expected type {}", val, typ),
+                self.ast().span(assign.name),
+                ErrorCode::TypeMismatch,
+            )
+            .label(self.ast().span(assign.val), format!("this is {}", val))
+            .label(decl, "variable declared here".to_owned());
+            let err = self.unwrap_help(err, &val);
+            self.raise(self.dyn_help(err, &val, &typ));
+        }
 
-    pub(crate) fn check_do_while_loop(&mut self, do_while_loop: &mut ast::DoWhileLoop) {
-        self.check_block(&mut do_while_loop.body.src);
-        self.verify_cond(&mut do_while_loop.cond);
+        self.assigned(id, &val);
     }
 
-    pub(crate) fn check_return(&mut self, node: Node<Stmt>, ret: &mut ast::Return) {
-        let val = match ret.val {
-            Some(ref mut x) => Some(self.check_expr(x)),
-            None => None,
-        };
+    pub(crate) fn check_return(&mut self, ret: &ast::Return, span: Span) {
+        let val = ret.val.map(|x| self.check_expr(x));
 
-        match (&val, &self.fn_ret) {
-            (Some(x), Some(y)) => {
-                if x != y {
-                    self.panic(
-                        format!(
-                            "Expected a return type of {}, but got type {} instead",
-                            x.to_string(),
-                            y.to_string(),
-                        ),
-                        &node,
-                        ErrorCode::TypeMismatch,
-                    );
-                }
+        let fn_ret = self.fn_ret.clone();
+        match (&val, &fn_ret) {
+            (Some(x), Some(y)) if !self.coerce(ret.val.unwrap(), x, &y.src) => {
+                let err = Error::new(
+                    format!(
+                        "Expected a return type of {}, but got type {} instead",
+                        y.src, x,
+                    ),
+                    span,
+                    ErrorCode::TypeMismatch,
+                )
+                .label(y.span, "return type declared here".to_owned());
+                let err = self.unwrap_help(err, x);
+                self.raise(self.dyn_help(err, x, &y.src));
             }
-            (Some(x), None) => self.panic(
-                format!(
-                    "Expected no return type, but got type {} instead",
-                    x.to_string()
-                ),
-                &node,
-                ErrorCode::TypeMismatch,
-            ),
-            (None, Some(y)) => self.panic(
-                format!(
-                    "Expected a return type of {}, but got no type instead",
-                    y.to_string()
-                ),
-                &node,
+            (Some(x), None) => self.raise(Error::new(
+                format!("Expected no return type, but got type {} instead", x),
+                span,
                 ErrorCode::TypeMismatch,
+            )),
+            (None, Some(y)) => self.raise(
+                Error::new(
+                    format!(
+                        "Expected a return type of {}, but got no type instead",
+                        y.src
+                    ),
+                    span,
+                    ErrorCode::TypeMismatch,
+                )
+                .label(y.span, "return type declared here".to_owned()),
             ),
             _ => (),
         };
     }
 
-    pub(crate) fn check_func(&mut self, func: &mut ast::Func) {
-        let prev_ret = self.fn_ret.clone();
-
-        let ret_typ = match func.ret {
-            Some(ref mut ret) => {
-                ret.typ = DataType::from(&ret.src.name);
-                ret.typ.clone()
+    pub(crate) fn func_type(&mut self, func: &ast::Func) -> DataType {
+        // The type parameters are declared in the body's scope, where the annotations can see them
+        let top = match func.generics.is_empty() {
+            true => Rc::clone(&self.top),
+            false => {
+                let body = Rc::clone(&self.ast()[func.body].scope);
+                std::mem::replace(&mut self.top, body)
             }
-            None => None,
         };
-        self.fn_ret = ret_typ;
+        self.declare_generics(&func.generics);
+
+        let params = func
+            .params
+            .iter()
+            .map(|&param| self.resolve_annot(self.ast()[param].annot))
+            .collect();
+        let ret = func.ret.map(|ret| self.resolve_annot(ret));
+        self.top = top;
+
+        types::Function::new(params, ret)
+    }
+
+    pub(crate) fn check_func(&mut self, func: &ast::Func) {
+        self.verify_vis(func.vis, func.name);
+
+        // The annotations were already resolved when the function got declared
+        let prev_ret = self.fn_ret.take();
+        self.fn_ret = func
+            .ret
+            .map(|ret| Meta::new(self.types.get(ret).unwrap().clone(), self.ast().span(ret)));
 
-        for param in &func.params {
-            let name = param.src.name.src.name.clone();
-            let typ = DataType::from(&param.src.annot.src.name).unwrap();
-            func.body.src.scope.borrow_mut().param(name, typ);
+        for &param in &func.params {
+            let ast::Param { name, annot } = self.ast()[param];
+            let typ = self.types.get(annot).unwrap().clone();
+            self.set_type(name, typ);
         }
 
-        self.check_block(&mut func.body.src);
+        // It could be called after anything narrowed out here has changed
+        let narrowed = std::mem::take(&mut self.narrowed);
+        let can_continue = std::mem::replace(&mut self.can_continue, true);
+        self.visit_block(func.body);
+        self.narrowed = narrowed;
+        self.can_continue = can_continue;
 
         self.fn_ret = prev_ret;
     }
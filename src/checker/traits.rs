@@ -0,0 +1,657 @@
+use std::{collections::HashMap, rc::Rc};
+
+use crate::{
+    ast::{self, visit::Visitor, Expr, Ident, Node, Stmt},
+    error::{Error, ErrorCode},
+    location::Span,
+    program::Method,
+    suggest,
+    symbols::{Impl, SymbolId, SymbolKind},
+    types::{self, DataType},
+};
+
+use super::Checker;
+
+// What calling a method runs, with the type it has for the receiver it's called on
+enum Found {
+    // The receiver's type is known, so there's only one function it can be
+    Static(SymbolId, types::Function),
+    // Whatever value is behind the dyn brought its own, this is where it is in the vtable
+    Dyn(usize, types::Function),
+    // A type parameter's trait says it's there, each instance finds the real one
+    Generic(types::Function),
+}
+
+impl Checker<'_> {
+    // Self stands for whatever type implements the trait, so inside it, it's a type parameter
+    // that only has the trait's methods
+    pub(crate) fn declare_traits(&mut self, stmts: &[Node<Stmt>]) {
+        for &stmt in stmts {
+            let Stmt::Trait(trait_stmt) = &self.ast()[stmt] else {
+                continue;
+            };
+
+            let id = self.symbol(trait_stmt.name);
+            let self_id = self.trait_self(id);
+            let typ = types::Param::new("Self".to_owned(), self_id, Vec::new(), vec![id]);
+            self.symbols.borrow_mut().get_mut(self_id).typ = Some(typ);
+
+            let top = std::mem::replace(&mut self.top, Rc::clone(&trait_stmt.scope));
+            for &sig in &trait_stmt.methods {
+                let ast::Signature {
+                    name,
+                    ref params,
+                    ret,
+                } = self.ast()[sig];
+                self.verify_self(name, params);
+                let params = params
+                    .iter()
+                    .map(|&x| self.resolve_annot(self.ast()[x].annot))
+                    .collect();
+                let ret = ret.map(|x| self.resolve_annot(x));
+                self.set_type(name, types::Function::new(params, ret));
+            }
+            self.top = top;
+        }
+    }
+
+    // The methods are checked against the trait before anything can call them, wherever the
+    // impl is
+    pub(crate) fn declare_impls(&mut self, stmts: &[Node<Stmt>]) {
+        for &stmt in stmts {
+            let Stmt::Impl(impl_stmt) = &self.ast()[stmt] else {
+                continue;
+            };
+
+            let typ = self.resolve_annot(impl_stmt.typ);
+            let typ_span = self.ast().span(impl_stmt.typ);
+            if let DataType::Dyn(x) = &typ {
+                self.raise(
+                    Error::new(
+                        format!("Methods can't be added to {}", typ),
+                        typ_span,
+                        ErrorCode::InvalidStatement,
+                    )
+                    .help(format!(
+                        "a {} already has the methods of {}, the types behind it have the rest",
+                        typ, x.name
+                    )),
+                );
+            }
+            let self_id = impl_stmt.scope.borrow().local("Self").unwrap();
+            self.symbols.borrow_mut().get_mut(self_id).typ = Some(typ.clone());
+            let trait_id = impl_stmt.trait_name.map(|x| self.resolve_trait(x));
+
+            let top = std::mem::replace(&mut self.top, Rc::clone(&impl_stmt.scope));
+            let mut methods = Vec::new();
+            for &method in &impl_stmt.methods {
+                let Stmt::Func(func) = &self.ast()[method] else {
+                    unreachable!("impls only hold functions");
+                };
+                if !func.generics.is_empty() {
+                    self.raise(
+                        Error::new(
+                            "Methods can't have type parameters".to_owned(),
+                            self.ast().span(func.name),
+                            ErrorCode::InvalidStatement,
+                        )
+                        .help("a generic function can take the value instead".to_owned()),
+                    );
+                }
+                self.verify_self(func.name, &func.params);
+                let method_typ = self.func_type(func);
+                self.set_type(func.name, method_typ);
+                methods.push(func.name);
+            }
+            self.top = top;
+
+            let start = self.ast().span(stmt);
+            let span = Span::new(start.file, start.lo, typ_span.hi);
+            if let Some(trait_id) = trait_id {
+                self.verify_impl(trait_id, &typ, &methods, span);
+            }
+            let methods: Vec<(String, SymbolId)> = methods
+                .iter()
+                .map(|&x| (self.ast()[x].name.clone(), self.symbol(x)))
+                .collect();
+            self.verify_unique(trait_id, &typ, &methods, span);
+            self.symbols.borrow_mut().add_impl(Impl {
+                trait_id,
+                typ,
+                span,
+                methods,
+            });
+        }
+    }
+
+    // Methods get the value they're called on as their first parameter. The error goes on that
+    // parameter when it's there but isn't self
+    fn verify_self(&self, name: Node<Ident>, params: &[Node<ast::Param>]) {
+        let first = params.first().map(|&x| &self.ast()[x]);
+        let span = match first {
+            Some(x) if x.is_self(self.ast()) => return,
+            Some(x) => self.ast().span(x.name),
+            None => self.ast().span(name),
+        };
+        let name = &self.ast()[name].name;
+        self.raise(
+            Error::new(
+                format!("'{}' needs self as its first parameter", name),
+                span,
+                ErrorCode::InvalidStatement,
+            )
+            .help(format!(
+                "it gets the value it's called on as self, like func {}(self)",
+                name
+            )),
+        );
+    }
+
+    fn resolve_trait(&self, name: Node<Ident>) -> SymbolId {
+        let text = &self.ast()[name].name;
+        let span = self.ast().span(name);
+        if let Some(id) = self.find_trait(text) {
+            return id;
+        }
+
+        let found = self.top.borrow().get(text);
+        let err = match found {
+            Some(id) => Error::new(
+                format!("'{}' is not a trait", text),
+                span,
+                ErrorCode::UnknownType,
+            )
+            .label(
+                self.symbols.borrow().get(id).span,
+                format!("'{}' declared here", text),
+            ),
+            None => {
+                let err = Error::new(
+                    format!("Unknown trait '{}'", text),
+                    span,
+                    ErrorCode::UnknownType,
+                );
+                let names = self.trait_names();
+                match suggest::closest(text, names.iter().map(String::as_str)) {
+                    Some(best) => err.suggest(
+                        span,
+                        best.to_owned(),
+                        "a trait with a similar name exists".to_owned(),
+                    ),
+                    None => err,
+                }
+            }
+        };
+        self.raise(err);
+        panic!()
+    }
+
+    pub(crate) fn find_trait(&self, name: &str) -> Option<SymbolId> {
+        let id = self.top.borrow().get(name)?;
+        match self.symbols.borrow().get(id).kind {
+            SymbolKind::Trait => Some(id),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn trait_names(&self) -> Vec<String> {
+        let mut names: Vec<String> = self
+            .top
+            .borrow()
+            .names()
+            .into_iter()
+            .filter(|x| self.find_trait(x).is_some())
+            .collect();
+        names.sort();
+        names.dedup();
+        names
+    }
+
+    fn trait_self(&self, id: SymbolId) -> SymbolId {
+        let symbols = self.symbols.borrow();
+        let members = symbols.get(id).members.as_ref().unwrap().borrow();
+        members
+            .local("Self")
+            .expect("traits declare Self as a member")
+    }
+
+    // In the order they were declared, which is the order of every vtable for the trait
+    fn trait_methods(&self, id: SymbolId) -> Vec<(String, SymbolId)> {
+        let symbols = self.symbols.borrow();
+        let members = symbols.get(id).members.as_ref().unwrap().borrow();
+        members
+            .locals()
+            .into_iter()
+            .filter(|(_, id)| symbols.get(*id).kind == SymbolKind::Method)
+            .collect()
+    }
+
+    // A trait's method as the type implementing it sees it, with Self filled in
+    fn trait_method(&self, id: SymbolId, method: SymbolId, typ: &DataType) -> types::Function {
+        let map = HashMap::from([(self.trait_self(id), typ.clone())]);
+        match self.method_type(method).subst(&map) {
+            DataType::Function(func) => func,
+            _ => unreachable!("methods are functions"),
+        }
+    }
+
+    fn method_type(&self, method: SymbolId) -> DataType {
+        self.symbols
+            .borrow()
+            .get(method)
+            .typ
+            .clone()
+            .expect("methods get their type before any code is checked")
+    }
+
+    // Every method of the trait is there with the same types, and nothing else is
+    fn verify_impl(&self, trait_id: SymbolId, typ: &DataType, methods: &[Node<Ident>], span: Span) {
+        let trait_name = self.symbols.borrow().get(trait_id).name.clone();
+        let wanted = self.trait_methods(trait_id);
+
+        for &method in methods {
+            let name = &self.ast()[method].name;
+            let method_span = self.ast().span(method);
+            let Some(&(_, sig)) = wanted.iter().find(|(x, _)| x == name) else {
+                let err = Error::new(
+                    format!("'{}' is not a method of {}", name, trait_name),
+                    method_span,
+                    ErrorCode::InvalidStatement,
+                )
+                .label(
+                    self.symbols.borrow().get(trait_id).span,
+                    format!("{} declared here", trait_name),
+                )
+                .help(format!(
+                    "methods that aren't in the trait go in their own impl {} {{ }}",
+                    typ
+                ));
+                let err = match suggest::closest(name, wanted.iter().map(|x| x.0.as_str())) {
+                    Some(best) => err.suggest(
+                        method_span,
+                        best.to_owned(),
+                        "a method with a similar name is in the trait".to_owned(),
+                    ),
+                    None => err,
+                };
+                self.raise(err);
+                panic!()
+            };
+
+            let expected = DataType::Function(self.trait_method(trait_id, sig, typ));
+            let found = self.method_type(self.symbol(method));
+            if found != expected {
+                self.raise(
+                    Error::new(
+                        format!(
+                            "Expected '{}' to be {} like in {}, but it's {}",
+                            name, expected, trait_name, found
+                        ),
+                        method_span,
+                        ErrorCode::TypeMismatch,
+                    )
+                    .label(
+                        self.symbols.borrow().get(sig).span,
+                        "declared in the trait here".to_owned(),
+                    ),
+                );
+            }
+        }
+
+        let missing: Vec<&(String, SymbolId)> = wanted
+            .iter()
+            .filter(|(name, _)| !methods.iter().any(|&x| self.ast()[x].name == *name))
+            .collect();
+        if missing.is_empty() {
+            return;
+        }
+        let names: Vec<String> = missing.iter().map(|(x, _)| format!("'{}'", x)).collect();
+        let mut err = Error::new(
+            format!(
+                "The impl of {} for {} is missing {}",
+                trait_name,
+                typ,
+                names.join(", ")
+            ),
+            span,
+            ErrorCode::InvalidStatement,
+        );
+        for (name, id) in missing {
+            err = err.label(
+                self.symbols.borrow().get(*id).span,
+                format!("'{}' declared here", name),
+            );
+        }
+        self.raise(err.help(format!(
+            "{} needs every method of {} to implement it",
+            typ, trait_name
+        )));
+    }
+
+    // A type implements each trait once, and its own methods have different names
+    fn verify_unique(
+        &self,
+        trait_id: Option<SymbolId>,
+        typ: &DataType,
+        methods: &[(String, SymbolId)],
+        span: Span,
+    ) {
+        let symbols = self.symbols.borrow();
+        for found in symbols.impls().iter().filter(|x| x.typ == *typ) {
+            if let Some(id) = trait_id.filter(|&x| found.trait_id == Some(x)) {
+                self.raise(
+                    Error::new(
+                        format!(
+                            "{} is already implemented for {}",
+                            symbols.get(id).name,
+                            typ
+                        ),
+                        span,
+                        ErrorCode::DuplicateDeclaration,
+                    )
+                    .label(found.span, "first implemented here".to_owned()),
+                );
+            }
+            if trait_id.is_some() || found.trait_id.is_some() {
+                continue;
+            }
+            for (name, id) in methods {
+                if let Some((_, prev)) = found.methods.iter().find(|(x, _)| x == name) {
+                    self.raise(
+                        Error::new(
+                            format!("'{}' is already a method of {}", name, typ),
+                            symbols.get(*id).span,
+                            ErrorCode::DuplicateDeclaration,
+                        )
+                        .label(symbols.get(*prev).span, "first declared here".to_owned()),
+                    );
+                }
+            }
+        }
+    }
+
+    // A type parameter has a trait when it's one of its bounds, and a dyn has its own
+    pub(crate) fn implements(&self, typ: &DataType, trait_id: SymbolId) -> bool {
+        match typ {
+            DataType::Param(x) => x.traits.contains(&trait_id),
+            DataType::Dyn(x) => x.id == trait_id,
+            _ => self.find_impl(typ, trait_id).is_some(),
+        }
+    }
+
+    fn find_impl(&self, typ: &DataType, trait_id: SymbolId) -> Option<Impl> {
+        self.symbols
+            .borrow()
+            .impls()
+            .iter()
+            .find(|x| x.trait_id == Some(trait_id) && x.typ == *typ)
+            .cloned()
+    }
+
+    // Whether a value can go where the expected type is needed. A dyn takes any value whose type
+    // has the trait, and the value picks up that type's methods on the way in
+    pub(crate) fn coerce(&mut self, expr: Node<Expr>, typ: &DataType, expected: &DataType) -> bool {
+        if typ.fits(expected) {
+            return true;
+        }
+        let trait_id = match expected {
+            DataType::Dyn(x) => x.id,
+            DataType::Optional(x) => match &*x.inner {
+                DataType::Dyn(x) => x.id,
+                _ => return false,
+            },
+            _ => return false,
+        };
+
+        match typ {
+            // The instances know what it is
+            DataType::Param(x) => x.traits.contains(&trait_id),
+            _ => match self.find_impl(typ, trait_id) {
+                Some(found) => {
+                    let vtable = self
+                        .trait_methods(trait_id)
+                        .iter()
+                        .map(|(name, _)| {
+                            let (_, id) = found.methods.iter().find(|(x, _)| x == name).unwrap();
+                            *id
+                        })
+                        .collect();
+                    self.vtables.insert(expr, vtable);
+                    true
+                }
+                None => false,
+            },
+        }
+    }
+
+    // Says why a value didn't go into a dyn
+    pub(crate) fn dyn_help(&self, err: Error, typ: &DataType, expected: &DataType) -> Error {
+        let inner = match expected {
+            DataType::Optional(x) => &*x.inner,
+            x => x,
+        };
+        match inner {
+            DataType::Dyn(x) => err.help(format!(
+                "{} doesn't implement {}, so it can't be a {}",
+                typ, x.name, inner
+            )),
+            _ => err,
+        }
+    }
+
+    // x.area() calls a method when x doesn't have a field called area. The receiver becomes the
+    // method's self, the arguments go after it. Like a field, x?.area() is none when x is
+    pub(crate) fn check_method_call(
+        &mut self,
+        expr: Node<Expr>,
+        call: &ast::Call,
+    ) -> Option<Option<DataType>> {
+        let (recv, name, optional) = match &self.ast()[call.callee] {
+            Expr::Field(x) => match self.check_expr(x.val) {
+                DataType::Optional(inner) if x.optional => (*inner.inner, x.name, true),
+                typ => (typ, x.name, false),
+            },
+            // Only a path through a value, module paths have a name
+            Expr::Path(x) if !self.prog.names.contains(*x) => {
+                let (&name, rest) = self.ast()[*x].segments.split_last().unwrap();
+                (self.chain_type(rest), name, false)
+            }
+            _ => return None,
+        };
+        if self.has_field(&recv, &self.ast()[name].name) {
+            return None;
+        }
+
+        let func = match self.find_method(&recv, name) {
+            Found::Static(id, func) => {
+                self.methods.insert(expr, Method::Static(id));
+                func
+            }
+            Found::Dyn(idx, func) => {
+                self.methods.insert(expr, Method::Dyn(idx));
+                func
+            }
+            Found::Generic(func) => func,
+        };
+
+        let params = &func.params[1..];
+        self.verify_arity(self.ast().span(expr), call, params.len());
+        for (&arg, param) in call.args.iter().zip(params) {
+            let typ = self.check_expr(arg);
+            self.verify_arg(call, arg, &typ, param);
+        }
+        let ret = func.ret.map(|x| *x);
+        self.types.insert(
+            call.callee,
+            types::Function::new(params.to_vec(), ret.clone()),
+        );
+        match optional {
+            true => Some(ret.map(types::Optional::new)),
+            false => Some(ret),
+        }
+    }
+
+    fn has_field(&self, typ: &DataType, name: &str) -> bool {
+        match typ {
+            DataType::Tuple(x) => name.parse::<usize>().is_ok_and(|idx| idx < x.items.len()),
+            DataType::Struct(x) => {
+                let symbols = self.symbols.borrow();
+                let members = symbols.get(x.id).members.as_ref().unwrap().borrow();
+                members.local(name).is_some()
+            }
+            _ => false,
+        }
+    }
+
+    // A type's own methods come before the ones from its traits, and two traits with the same
+    // method can't be told apart
+    fn find_method(&self, recv: &DataType, name: Node<Ident>) -> Found {
+        let text = &self.ast()[name].name;
+        match recv {
+            DataType::Param(x) => {
+                for &trait_id in &x.traits {
+                    if let Some((_, id)) =
+                        self.trait_methods(trait_id).iter().find(|x| x.0 == *text)
+                    {
+                        return Found::Generic(self.trait_method(trait_id, *id, recv));
+                    }
+                }
+            }
+            DataType::Dyn(x) => {
+                let methods = self.trait_methods(x.id);
+                if let Some(idx) = methods.iter().position(|x| x.0 == *text) {
+                    self.verify_dyn_method(x, methods[idx].1, name);
+                    return Found::Dyn(idx, self.trait_method(x.id, methods[idx].1, recv));
+                }
+            }
+            _ => {
+                let symbols = self.symbols.borrow();
+                let mut found: Vec<(Option<SymbolId>, SymbolId)> = symbols
+                    .impls()
+                    .iter()
+                    .filter(|x| x.typ == *recv)
+                    .filter_map(|x| {
+                        let (_, id) = x.methods.iter().find(|x| x.0 == *text)?;
+                        Some((x.trait_id, *id))
+                    })
+                    .collect();
+                // Sorting puts the type's own method first, if there is one
+                found.sort();
+                if let [(Some(_), _), (Some(_), _), ..] = found[..] {
+                    let names: Vec<&str> = found
+                        .iter()
+                        .map(|x| symbols.get(x.0.unwrap()).name.as_str())
+                        .collect();
+                    self.raise(Error::new(
+                        format!(
+                            "'{}' could be the method from any of {}, for {}",
+                            text,
+                            names.join(", "),
+                            recv
+                        ),
+                        self.ast().span(name),
+                        ErrorCode::InvalidCall,
+                    ));
+                }
+                if let Some(&(_, id)) = found.first() {
+                    match &symbols.get(id).typ {
+                        Some(DataType::Function(func)) => return Found::Static(id, func.clone()),
+                        _ => unreachable!("methods are functions"),
+                    }
+                }
+            }
+        }
+
+        let span = self.ast().span(name);
+        let err = Error::new(
+            format!("{} has no field or method '{}'", recv, text),
+            span,
+            ErrorCode::InvalidExpression,
+        );
+        let names = self.method_names(recv);
+        let err = match suggest::closest(text, names.iter().map(String::as_str)) {
+            Some(best) => err.suggest(
+                span,
+                best.to_owned(),
+                "a method with a similar name exists".to_owned(),
+            ),
+            None => err,
+        };
+        let err = match recv {
+            DataType::Param(x) => err.help(format!(
+                "{} only has the methods of the traits it's bound by",
+                x.name
+            )),
+            _ => self.unwrap_help(err, recv),
+        };
+        self.raise(err);
+        panic!()
+    }
+
+    // A dyn doesn't know what Self is, so it can only pass a method the value it's called on
+    fn verify_dyn_method(&self, dyn_typ: &types::Dyn, method: SymbolId, name: Node<Ident>) {
+        let DataType::Function(func) = self.method_type(method) else {
+            unreachable!("methods are functions");
+        };
+        let map = HashMap::from([(self.trait_self(dyn_typ.id), DataType::None)]);
+        let rest = func.params[1..].iter().chain(func.ret.as_deref());
+        if rest.into_iter().all(|x| x.subst(&map) == *x) {
+            return;
+        }
+        self.raise(
+            Error::new(
+                format!(
+                    "'{}' can't be called on a dyn {}, its signature uses Self",
+                    self.ast()[name].name,
+                    dyn_typ.name
+                ),
+                self.ast().span(name),
+                ErrorCode::InvalidCall,
+            )
+            .label(
+                self.symbols.borrow().get(method).span,
+                "declared here".to_owned(),
+            )
+            .help(format!(
+                "a generic function with a {} bound can call it",
+                dyn_typ.name
+            )),
+        );
+    }
+
+    fn method_names(&self, typ: &DataType) -> Vec<String> {
+        let traits = match typ {
+            DataType::Param(x) => x.traits.clone(),
+            DataType::Dyn(x) => vec![x.id],
+            _ => {
+                let symbols = self.symbols.borrow();
+                return symbols
+                    .impls()
+                    .iter()
+                    .filter(|x| x.typ == *typ)
+                    .flat_map(|x| x.methods.iter().map(|x| x.0.clone()))
+                    .collect();
+            }
+        };
+        traits
+            .into_iter()
+            .flat_map(|x| self.trait_methods(x))
+            .map(|x| x.0)
+            .collect()
+    }
+
+    // Whether a field that doesn't exist is a method someone forgot to call
+    pub(crate) fn is_method(&self, typ: &DataType, name: &str) -> bool {
+        self.method_names(typ).iter().any(|x| x == name)
+    }
+
+    pub(crate) fn check_impl(&mut self, impl_stmt: &ast::Impl) {
+        let top = std::mem::replace(&mut self.top, Rc::clone(&impl_stmt.scope));
+        for &method in &impl_stmt.methods {
+            if let Stmt::Func(func) = &self.ast()[method] {
+                self.check_func(func);
+            }
+        }
+        self.top = top;
+    }
+}
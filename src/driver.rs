@@ -0,0 +1,84 @@
+use std::{
+    io,
+    panic::{self, AssertUnwindSafe},
+    path::Path,
+    rc::Rc,
+};
+
+use crate::{
+    error::{FatalError, Reporter},
+    ir,
+    module::ModuleGraph,
+};
+
+// Runs a compiler stage, turning the unwind from a fatal error back into a value
+pub fn catch_fatal<T>(stage: impl FnOnce() -> T) -> Result<T, FatalError> {
+    match panic::catch_unwind(AssertUnwindSafe(stage)) {
+        Ok(val) => Ok(val),
+        Err(err) if err.is::<FatalError>() => Err(FatalError),
+        Err(err) => panic::resume_unwind(err),
+    }
+}
+
+// Loads and checks a program, the error itself will have gone through the reporter
+pub fn compile(
+    reporter: Rc<Reporter>,
+    root: &Path,
+    read: &dyn Fn(&Path) -> io::Result<String>,
+) -> Result<ModuleGraph, FatalError> {
+    let graph = catch_fatal(|| {
+        let mut graph = ModuleGraph::load_with(Rc::clone(&reporter), root, read);
+        graph.resolve();
+        graph.check();
+        graph
+    })?;
+
+    // Denied lints don't stop checking, but they do stop the program from compiling
+    match reporter.has_errors() {
+        true => Err(FatalError),
+        false => Ok(graph),
+    }
+}
+
+// Gets as far through loading, resolving and checking as it can, for tools that want whatever
+// is known about a program even when it has errors. Only a failed load, like a file that doesn't
+// parse, gives nothing
+pub fn analyze(
+    reporter: Rc<Reporter>,
+    root: &Path,
+    read: &dyn Fn(&Path) -> io::Result<String>,
+) -> Option<ModuleGraph> {
+    let mut graph =
+        catch_fatal(|| ModuleGraph::load_with(Rc::clone(&reporter), root, read)).ok()?;
+    if catch_fatal(|| graph.resolve()).is_ok() {
+        let _ = catch_fatal(|| graph.check());
+    }
+
+    Some(graph)
+}
+
+// A checked program as SSA, unless it uses something only the interpreter can run. Anything
+// the verifier finds is a bug in the lowering
+pub fn lower(graph: &ModuleGraph) -> Result<ir::Module, String> {
+    let module = ir::lower(graph)?;
+    if let Err(err) = ir::verify(&module) {
+        panic!("The lowered IR doesn't verify: {}\n{}", err, module);
+    }
+    Ok(module)
+}
+
+// Runs the passes, checking the module still verifies after each one. A pass named by
+// print_after gets what it left printed every time it runs
+pub fn optimize(module: &mut ir::Module, print_after: Option<&str>) -> ir::Stats {
+    ir::optimize(module, |pass, module| {
+        if let Err(err) = ir::verify(module) {
+            panic!(
+                "The IR doesn't verify after {}: {}\n{}",
+                pass.name, err, module
+            );
+        }
+        if print_after == Some(pass.name) {
+            eprintln!("; after {}\n{}", pass.name, module);
+        }
+    })
+}
@@ -1,18 +1,81 @@
 use core::fmt;
-use std::rc::Rc;
+use std::{cell::RefCell, panic, rc::Rc};
 
-use crate::location::Location;
+pub(crate) mod explain;
+pub(crate) mod json;
+pub(crate) mod render;
 
+pub use self::explain::{explain, Explanation};
+
+use crate::{
+    lint::{Level, Lint, LintLevels},
+    location::Span,
+    source::SourceMap,
+};
+
+#[derive(Debug, Clone)]
 pub struct Error {
-    lines: Rc<Vec<String>>,
-    msg: String,
-    start: Location,
-    end: Location,
-    id: ErrorCode,
+    pub msg: String,
+    pub span: Span,
+    pub id: ErrorCode,
+    pub severity: Severity,
+    pub lint: Option<Lint>,
+    pub labels: Vec<Label>,
+    pub notes: Vec<Note>,
+    pub suggestions: Vec<Suggestion>,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+    Note,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Severity::Error => write!(f, "error"),
+            Severity::Warning => write!(f, "warning"),
+            Severity::Note => write!(f, "note"),
+        }
+    }
+}
+
+// A secondary span, pointing at something that explains the error
+#[derive(Debug, Clone)]
+pub struct Label {
+    pub span: Span,
+    pub msg: String,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum NoteKind {
+    Note,
+    Help,
 }
 
-fn size(num: u32) -> usize {
-    num.to_string().len()
+impl fmt::Display for NoteKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NoteKind::Note => write!(f, "note"),
+            NoteKind::Help => write!(f, "help"),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Note {
+    pub kind: NoteKind,
+    pub msg: String,
+}
+
+// A fix that can be applied without a human, by replacing the span with the replacement
+#[derive(Debug, Clone)]
+pub struct Suggestion {
+    pub span: Span,
+    pub replacement: String,
+    pub msg: String,
 }
 
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
@@ -25,6 +88,51 @@ pub enum ErrorCode {
     ReservedNameUsed,
     TypeMismatch,
     VariableNotFound,
+    UnknownType,
+    ModuleNotFound,
+    ImportCycle,
+    PrivateItem,
+    InvalidCall,
+    Lint,
+    UseBeforeDeclaration,
+    DuplicateDeclaration,
+    NonExhaustiveMatch,
+    UnsatisfiedBound,
+}
+
+impl ErrorCode {
+    pub const VALUES: [ErrorCode; 18] = [
+        ErrorCode::Unknown,
+        ErrorCode::UnexpectedToken,
+        ErrorCode::IncorrectParsingType,
+        ErrorCode::InvalidStatement,
+        ErrorCode::InvalidExpression,
+        ErrorCode::ReservedNameUsed,
+        ErrorCode::TypeMismatch,
+        ErrorCode::VariableNotFound,
+        ErrorCode::UnknownType,
+        ErrorCode::ModuleNotFound,
+        ErrorCode::ImportCycle,
+        ErrorCode::PrivateItem,
+        ErrorCode::InvalidCall,
+        ErrorCode::Lint,
+        ErrorCode::UseBeforeDeclaration,
+        ErrorCode::DuplicateDeclaration,
+        ErrorCode::NonExhaustiveMatch,
+        ErrorCode::UnsatisfiedBound,
+    ];
+
+    // Takes E0006, 6 or TypeMismatch
+    pub fn from(src: &str) -> Option<ErrorCode> {
+        let num = src.strip_prefix('E').unwrap_or(src);
+        ErrorCode::VALUES
+            .into_iter()
+            .find(|code| num.parse::<u32>() == Ok(*code as u32) || code.to_string() == src)
+    }
+
+    pub fn code(&self) -> String {
+        format!("E{:0>4}", *self as u32)
+    }
 }
 
 impl fmt::Display for ErrorCode {
@@ -34,60 +142,135 @@ impl fmt::Display for ErrorCode {
 }
 
 impl Error {
-    pub fn new(
-        lines: Rc<Vec<String>>,
-        msg: String,
-        start: Location,
-        end: Location,
-        id: ErrorCode,
-    ) -> Error {
+    pub fn new(msg: String, span: Span, id: ErrorCode) -> Error {
         Error {
-            lines,
             msg,
-            start,
-            end,
+            span,
             id,
+            severity: Severity::Error,
+            lint: None,
+            labels: Vec::new(),
+            notes: Vec::new(),
+            suggestions: Vec::new(),
         }
     }
 
-    pub fn panic(&self) {
-        let Location { row, col, idx } = self.start;
-        let Location {
-            row: end_row,
-            idx: ending,
-            ..
-        } = self.end;
-
-        let end_idx = if end_row == row {
-            ending
-        } else {
-            let mut sum = 0;
-            for row in &self.lines[0..=row as usize] {
-                // +1 for the new line
-                sum += row.len() as u32 + 1;
-            }
-            sum - 1
-        };
-
-        let num_size = size(row + 1);
-
-        let mut err = String::new();
-        err += &format!("E{:0>4} {}:\n", self.id as u32, self.id);
-        for idx in (i32::max(row as i32 - 4, 0) as u32)..=row {
-            let line_num = idx + 1;
-            err += &format!("{}. {}", line_num, " ".repeat(num_size - size(line_num)));
-            err += &format!("{}\n", self.lines.get(idx as usize).unwrap());
+    // Only a warning, until the lint's level says otherwise
+    pub fn lint(lint: Lint, msg: String, span: Span) -> Error {
+        Error {
+            severity: Severity::Warning,
+            lint: Some(lint),
+            ..Error::new(msg, span, ErrorCode::Lint)
         }
+    }
 
-        err += &format!(
-            "{}{}\n",
-            " ".repeat(usize::max(num_size + col as usize + 2, 0)),
-            "^".repeat(u32::max(end_idx - idx, 1) as usize),
-        );
+    pub fn label(mut self, span: Span, msg: String) -> Error {
+        self.labels.push(Label { span, msg });
+        self
+    }
+
+    pub fn note(mut self, msg: String) -> Error {
+        self.notes.push(Note {
+            kind: NoteKind::Note,
+            msg,
+        });
+        self
+    }
+
+    pub fn help(mut self, msg: String) -> Error {
+        self.notes.push(Note {
+            kind: NoteKind::Help,
+            msg,
+        });
+        self
+    }
+
+    pub fn suggest(mut self, span: Span, replacement: String, msg: String) -> Error {
+        self.suggestions.push(Suggestion {
+            span,
+            replacement,
+            msg,
+        });
+        self
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ErrorFormat {
+    Human,
+    Json,
+    // Only collected, for tools that show errors themselves
+    Silent,
+}
+
+// Unwound with once an error has been shown, so the driver can stop without a panic message
+#[derive(Debug)]
+pub struct FatalError;
 
-        err += &format!("{} ({}:{})\n", self.msg, row + 1, col + 1);
+// Knows how to show errors, since that needs every loaded file and the output settings
+pub struct Reporter {
+    pub map: Rc<SourceMap>,
+    pub color: bool,
+    pub format: ErrorFormat,
+    pub lints: RefCell<LintLevels>,
+    errors: RefCell<Vec<Error>>,
+}
+
+impl Reporter {
+    pub fn new(map: Rc<SourceMap>, color: bool, format: ErrorFormat) -> Rc<Reporter> {
+        Rc::new(Reporter {
+            map,
+            color,
+            format,
+            lints: RefCell::new(LintLevels::default()),
+            errors: RefCell::new(Vec::new()),
+        })
+    }
+
+    // Every diagnostic emitted so far, warnings included
+    pub fn diagnostics(&self) -> Vec<Error> {
+        self.errors.borrow().clone()
+    }
+
+    pub fn errors(&self) -> Vec<Error> {
+        self.diagnostics()
+            .into_iter()
+            .filter(|err| err.severity == Severity::Error)
+            .collect()
+    }
+
+    pub fn has_errors(&self) -> bool {
+        self.errors
+            .borrow()
+            .iter()
+            .any(|err| err.severity == Severity::Error)
+    }
+
+    pub fn emit(&self, err: &Error) {
+        match self.format {
+            // On stderr so they never mix with what's being built
+            ErrorFormat::Human => eprintln!("{}", self.render(err)),
+            // One object per line
+            ErrorFormat::Json => eprintln!("{}", self.to_json(err)),
+            ErrorFormat::Silent => (),
+        }
+        self.errors.borrow_mut().push(err.clone());
+    }
+
+    // Denied lints are errors, but checking still goes on so every problem gets shown
+    pub fn lint(&self, mut err: Error) {
+        let lint = err.lint.unwrap();
+        let level = self.lints.borrow().level(lint, err.span);
+        match level {
+            Level::Allow => return,
+            Level::Warn => err.severity = Severity::Warning,
+            Level::Deny => err.severity = Severity::Error,
+        }
+        self.emit(&err);
+    }
 
-        println!("{}", err);
-        panic!("");
+    pub fn panic(&self, err: &Error) {
+        self.emit(err);
+        panic::resume_unwind(Box::new(FatalError));
     }
 }
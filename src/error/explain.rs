@@ -0,0 +1,229 @@
+use super::ErrorCode;
+
+// Examples are lists of files, the first one being the file that gets compiled
+pub type Example = &'static [(&'static str, &'static str)];
+
+pub struct Explanation {
+    pub code: ErrorCode,
+    pub text: &'static str,
+    pub erroneous: Example,
+    pub corrected: Example,
+}
+
+impl Explanation {
+    fn example(files: Example) -> String {
+        let mut out = String::new();
+        for (path, code) in files {
+            if files.len() > 1 {
+                out += &format!("    // {}\n", path);
+            }
+            for line in code.lines() {
+                let line = format!("    {}", line);
+                out += line.trim_end();
+                out += "\n";
+            }
+        }
+        out
+    }
+
+    pub fn render(&self) -> String {
+        let mut out = format!("{} {}\n\n{}\n", self.code.code(), self.code, self.text);
+        if !self.erroneous.is_empty() {
+            out += "\nErroneous code example:\n\n";
+            out += &Explanation::example(self.erroneous);
+        }
+        if !self.corrected.is_empty() {
+            out += "\nCorrected:\n\n";
+            out += &Explanation::example(self.corrected);
+        }
+        out
+    }
+}
+
+pub fn explain(code: ErrorCode) -> &'static Explanation {
+    EXPLANATIONS.iter().find(|x| x.code == code).unwrap()
+}
+
+pub const EXPLANATIONS: &[Explanation] = &[
+    Explanation {
+        code: ErrorCode::Unknown,
+        text: "Something went wrong inside of the compiler itself. This is never caused by
+the code being compiled, so it's always a bug worth reporting.",
+        erroneous: &[],
+        corrected: &[],
+    },
+    Explanation {
+        code: ErrorCode::UnexpectedToken,
+        text: "The parser needed a specific token, like '=' after the name in a 'let'
+statement, but found something else.",
+        erroneous: &[("main.fn", "let x 5\n")],
+        corrected: &[("main.fn", "let x = 5\n")],
+    },
+    Explanation {
+        code: ErrorCode::IncorrectParsingType,
+        text: "The parser tried to read a token as the wrong kind of literal, like reading
+an identifier as a number. The parser always checks the token first, so this
+is a bug in the compiler.",
+        erroneous: &[],
+        corrected: &[],
+    },
+    Explanation {
+        code: ErrorCode::InvalidStatement,
+        text: "A line doesn't start with anything that can begin a statement. Statements
+start with a keyword like 'let', 'if' or 'func', a name being assigned to or
+called, or a block. A bare expression can't be used as a statement, since its
+value would be thrown away.",
+        erroneous: &[("main.fn", "5 + 5\n")],
+        corrected: &[("main.fn", "let ten = 5 + 5\n")],
+    },
+    Explanation {
+        code: ErrorCode::InvalidExpression,
+        text: "An expression was expected, but the token found can't start one. This
+usually means a value is missing, like on the right side of an '='.",
+        erroneous: &[("main.fn", "let x =\n")],
+        corrected: &[("main.fn", "let x = 1\n")],
+    },
+    Explanation {
+        code: ErrorCode::ReservedNameUsed,
+        text: "Keywords like 'if', 'let' and 'func' are reserved, so they can't be used as
+the name of a variable, function, parameter or module.",
+        erroneous: &[("main.fn", "let if = 5\n")],
+        corrected: &[("main.fn", "let value = 5\n")],
+    },
+    Explanation {
+        code: ErrorCode::TypeMismatch,
+        text: "A value has a different type than the one it needs to have. This happens
+when a value doesn't match a variable's annotation, a variable is reassigned
+with a different type, an operator is used on two different types, a
+condition isn't a bool, or a return value or argument doesn't match the
+function's signature. Values are never converted between types implicitly,
+except that a T or none can go where a T? is expected. A T? can only be used
+as a T once an if like 'if x != none' has made sure it isn't none, through
+'if let', or with a default from '??'.",
+        erroneous: &[("main.fn", "let x: bool = 5\n")],
+        corrected: &[("main.fn", "let x: int32 = 5\n")],
+    },
+    Explanation {
+        code: ErrorCode::VariableNotFound,
+        text: "A name was used that isn't declared anywhere it can be seen from. Variables
+have to be declared with 'let' before they are used, and can only be used in
+the block they are declared in and the blocks inside of it.",
+        erroneous: &[("main.fn", "let x = y\n")],
+        corrected: &[("main.fn", "let y = 1\nlet x = y\n")],
+    },
+    Explanation {
+        code: ErrorCode::UnknownType,
+        text: "A type annotation names a type that doesn't exist. The built in types are
+int8 to int128, uint8 to uint128, float16 to float128 and bool, and any of
+them followed by [] for an array or ? for an optional. A variable can't be
+declared as none without an annotation, since none doesn't say what type it
+stands in for.",
+        erroneous: &[("main.fn", "let x: int23 = 5\n")],
+        corrected: &[("main.fn", "let x: int32 = 5\n")],
+    },
+    Explanation {
+        code: ErrorCode::ModuleNotFound,
+        text: "A module couldn't be found. 'mod name' loads the file name.fn from the same
+folder as the file declaring it, and paths like 'a.b.c' can only go through
+modules that have been declared.",
+        erroneous: &[("main.fn", "mod math\n")],
+        corrected: &[("main.fn", "mod math\n"), ("math.fn", "pub let pi = 3\n")],
+    },
+    Explanation {
+        code: ErrorCode::ImportCycle,
+        text: "Modules declare each other in a loop. Each module is checked after every
+module it declares, so a module can't end up declaring itself, directly or
+through other modules. Moving the shared items into a new module that both
+declare fixes the loop.",
+        erroneous: &[
+            ("main.fn", "mod a\n"),
+            ("a.fn", "mod b\n"),
+            ("b.fn", "mod a\n"),
+        ],
+        corrected: &[
+            ("main.fn", "mod a\n"),
+            ("a.fn", "mod b\n"),
+            ("b.fn", "pub let x = 1\n"),
+        ],
+    },
+    Explanation {
+        code: ErrorCode::PrivateItem,
+        text: "An item was used from outside of the module it is declared in, but it isn't
+public. Top level items and modules are private unless they start with 'pub'.",
+        erroneous: &[
+            ("main.fn", "mod math\nlet x = math.secret\n"),
+            ("math.fn", "let secret = 4\n"),
+        ],
+        corrected: &[
+            ("main.fn", "mod math\nlet x = math.secret\n"),
+            ("math.fn", "pub let secret = 4\n"),
+        ],
+    },
+    Explanation {
+        code: ErrorCode::InvalidCall,
+        text: "Something that isn't a function was called, or a function was called with
+the wrong number of arguments.",
+        erroneous: &[("main.fn", "let x = 5\nlet y = x(1)\n")],
+        corrected: &[(
+            "main.fn",
+            "func x(a: int32): int32 {\n    return a\n}\n\nlet y = x(1)\n",
+        )],
+    },
+    Explanation {
+        code: ErrorCode::Lint,
+        text: "A lint found something, and its level was set to deny. Lints are warnings
+by default, they can be set to allow, warn or deny with --allow=, --warn= and
+--deny= or with an attribute. #[level(lint)] only applies to the statement
+after it, #![level(lint)] to the whole file.",
+        erroneous: &[("main.fn", "#![deny(unused_variables)]\nlet x = 5\n")],
+        corrected: &[(
+            "main.fn",
+            "#![deny(unused_variables)]\nlet x = 5\nlet y = x\n\n#[allow(unused_variables)]\nlet z = y\n",
+        )],
+    },
+    Explanation {
+        code: ErrorCode::UseBeforeDeclaration,
+        text: "A name was used above the line that declares it. Variables only exist from
+their declaration onwards, functions can be used anywhere in the block they are
+declared in.",
+        erroneous: &[("main.fn", "let total = count + 1\nlet count = 1\n")],
+        corrected: &[("main.fn", "let count = 1\nlet total = count + 1\n")],
+    },
+    Explanation {
+        code: ErrorCode::DuplicateDeclaration,
+        text: "The same name was declared twice in one scope. Declaring it again in an
+inner block is allowed, but that shadows the outer one.",
+        erroneous: &[("main.fn", "let x = 1\nlet x = 2\n")],
+        corrected: &[("main.fn", "let x = 1\nx = 2\n")],
+    },
+    Explanation {
+        code: ErrorCode::NonExhaustiveMatch,
+        text: "A match has no arm for some of the values it could get. The error lists
+patterns that aren't covered, adding an arm for each of them or a _ arm at the
+end fixes it. Arms with a guard might not match, so they don't count.",
+        erroneous: &[(
+            "main.fn",
+            "enum Light {\n    Red\n    Green\n}\n\nlet go = match Light.Red {\n    Light.Green => true\n}\n",
+        )],
+        corrected: &[(
+            "main.fn",
+            "enum Light {\n    Red\n    Green\n}\n\nlet go = match Light.Red {\n    Light.Green => true\n    Light.Red => false\n}\n",
+        )],
+    },
+    Explanation {
+        code: ErrorCode::UnsatisfiedBound,
+        text: "A type parameter was used in a way its bounds don't allow, or a call gave it
+a type without one of its bounds. A T can only use the operators its bounds
+give it: Eq for == and !=, Ord for the comparisons, Num for arithmetic, Logic
+for the logic operators and Bits for shifts. Adding the bound fixes the first
+case, callers then have to pass types that have it.",
+        erroneous: &[(
+            "main.fn",
+            "func max<T>(a: T, b: T): T {\n    if a > b {\n        return a\n    }\n    return b\n}\n",
+        )],
+        corrected: &[(
+            "main.fn",
+            "func max<T: Ord>(a: T, b: T): T {\n    if a > b {\n        return a\n    }\n    return b\n}\n",
+        )],
+    },
+];
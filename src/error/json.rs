@@ -0,0 +1,83 @@
+use crate::{json::Json, location::Span};
+
+use super::{Error, Reporter};
+
+impl Reporter {
+    fn position(&self, span: Span, end: bool) -> Json {
+        let loc = if end {
+            self.map.end(span)
+        } else {
+            self.map.start(span)
+        };
+
+        Json::object(vec![
+            ("row", Json::num(loc.row + 1)),
+            ("col", Json::num(loc.col + 1)),
+            ("byte", Json::num(loc.idx)),
+        ])
+    }
+
+    // The file and both ends of a span, spread into an object's fields
+    fn span_fields(&self, span: Span) -> Vec<(&'static str, Json)> {
+        vec![
+            ("file", Json::str(&self.map.get(span.file).path)),
+            ("start", self.position(span, false)),
+            ("end", self.position(span, true)),
+        ]
+    }
+
+    pub fn to_json(&self, err: &Error) -> Json {
+        let labels = err
+            .labels
+            .iter()
+            .map(|label| {
+                let mut fields = vec![("message", Json::str(&label.msg))];
+                fields.extend(self.span_fields(label.span));
+                Json::object(fields)
+            })
+            .collect();
+
+        let notes = err
+            .notes
+            .iter()
+            .map(|note| {
+                Json::object(vec![
+                    ("kind", Json::str(&note.kind.to_string())),
+                    ("message", Json::str(&note.msg)),
+                ])
+            })
+            .collect();
+
+        let suggestions = err
+            .suggestions
+            .iter()
+            .map(|suggestion| {
+                let mut fields = vec![
+                    ("message", Json::str(&suggestion.msg)),
+                    ("replacement", Json::str(&suggestion.replacement)),
+                ];
+                fields.extend(self.span_fields(suggestion.span));
+                Json::object(fields)
+            })
+            .collect();
+
+        let mut fields = vec![
+            ("code", Json::num(err.id as u32)),
+            ("name", Json::str(&err.id.to_string())),
+            ("severity", Json::str(&err.severity.to_string())),
+            (
+                "lint",
+                err.lint.map_or(Json::Null, |lint| Json::str(lint.name())),
+            ),
+            ("message", Json::str(&err.msg)),
+        ];
+        fields.extend(self.span_fields(err.span));
+        fields.extend([
+            ("labels", Json::Array(labels)),
+            ("notes", Json::Array(notes)),
+            ("suggestions", Json::Array(suggestions)),
+        ]);
+
+        Json::object(fields)
+    }
+}
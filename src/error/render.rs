@@ -0,0 +1,272 @@
+use std::collections::BTreeSet;
+
+use crate::{
+    location::{Location, Span},
+    source::{FileId, SourceFile},
+};
+
+use super::{Error, Reporter, Severity};
+
+const TAB_WIDTH: usize = 4;
+// Lines shown before the primary span
+const CONTEXT: u32 = 4;
+
+// Characters that take up two columns in a terminal, mostly CJK and emoji
+const WIDE: &[(u32, u32)] = &[
+    (0x1100, 0x115F),
+    (0x2E80, 0x303E),
+    (0x3041, 0x33FF),
+    (0x3400, 0x4DBF),
+    (0x4E00, 0x9FFF),
+    (0xA000, 0xA4CF),
+    (0xAC00, 0xD7A3),
+    (0xF900, 0xFAFF),
+    (0xFE30, 0xFE4F),
+    (0xFF00, 0xFF60),
+    (0xFFE0, 0xFFE6),
+    (0x1F300, 0x1F64F),
+    (0x1F900, 0x1F9FF),
+    (0x20000, 0x3FFFD),
+];
+
+// Combining marks and invisible characters, which don't move the cursor
+const ZERO_WIDTH: &[(u32, u32)] = &[
+    (0x0300, 0x036F),
+    (0x200B, 0x200F),
+    (0x20D0, 0x20FF),
+    (0xFE00, 0xFE0F),
+];
+
+fn in_ranges(chr: char, ranges: &[(u32, u32)]) -> bool {
+    let code = chr as u32;
+    ranges.iter().any(|(lo, hi)| (*lo..=*hi).contains(&code))
+}
+
+pub fn char_width(chr: char) -> usize {
+    if chr == '\t' {
+        TAB_WIDTH
+    } else if chr.is_control() || in_ranges(chr, ZERO_WIDTH) {
+        0
+    } else if in_ranges(chr, WIDE) {
+        2
+    } else {
+        1
+    }
+}
+
+pub fn str_width(src: &str) -> usize {
+    src.chars().map(char_width).sum()
+}
+
+fn expand_tabs(line: &str) -> String {
+    line.replace('\t', &" ".repeat(TAB_WIDTH))
+}
+
+// Everything before the given column
+fn prefix(line: &str, col: u32) -> &str {
+    match line.char_indices().nth(col as usize) {
+        Some((idx, _)) => &line[..idx],
+        None => line,
+    }
+}
+
+fn indentation(line: &str) -> &str {
+    &line[..line.len() - line.trim_start().len()]
+}
+
+#[derive(Clone, Copy)]
+enum Style {
+    Error,
+    Warning,
+    Secondary,
+    Gutter,
+    Note,
+}
+
+impl Style {
+    fn code(&self) -> &str {
+        match self {
+            Style::Error => "1;31",
+            Style::Warning => "1;33",
+            Style::Secondary | Style::Gutter => "1;34",
+            Style::Note => "1",
+        }
+    }
+}
+
+impl From<Severity> for Style {
+    fn from(severity: Severity) -> Style {
+        match severity {
+            Severity::Error => Style::Error,
+            Severity::Warning => Style::Warning,
+            Severity::Note => Style::Note,
+        }
+    }
+}
+
+struct Mark<'a> {
+    span: Span,
+    primary: bool,
+    msg: Option<&'a str>,
+}
+
+impl Reporter {
+    fn paint(&self, text: &str, style: Style) -> String {
+        if self.color && !text.is_empty() {
+            format!("\x1b[{}m{}\x1b[0m", style.code(), text)
+        } else {
+            text.to_owned()
+        }
+    }
+
+    // A span ending right after a newline really ends on the line before it
+    fn bounds(&self, file: &SourceFile, span: Span) -> (Location, Location) {
+        let start = file.location(span.lo);
+        let mut end = file.location(span.hi);
+        if end.row > start.row && end.col == 0 {
+            let row = end.row - 1;
+            end = Location::new(file.id, row, file.line(row).chars().count() as u32, span.hi);
+        }
+
+        (start, end)
+    }
+
+    pub fn render(&self, err: &Error) -> String {
+        let mut marks = vec![Mark {
+            span: err.span,
+            primary: true,
+            msg: None,
+        }];
+        marks.extend(err.labels.iter().map(|label| Mark {
+            span: label.span,
+            primary: false,
+            msg: Some(&label.msg),
+        }));
+
+        let mut files: Vec<FileId> = Vec::new();
+        for mark in &marks {
+            if !files.contains(&mark.span.file) {
+                files.push(mark.span.file);
+            }
+        }
+
+        // Every snippet shares a gutter width, so the code lines up between files
+        let width = marks
+            .iter()
+            .map(|mark| {
+                let file = self.map.get(mark.span.file);
+                (self.bounds(&file, mark.span).1.row + 1).to_string().len()
+            })
+            .max()
+            .unwrap_or(1);
+
+        let header = match err.lint {
+            Some(lint) => format!("{} {}:", err.severity, lint),
+            None => format!("{} {}:", err.id.code(), err.id),
+        };
+        let mut out = format!("{}\n", self.paint(&header, err.severity.into()));
+        for file in files {
+            let file = self.map.get(file);
+            let file_marks: Vec<&Mark> = marks.iter().filter(|x| x.span.file == file.id).collect();
+            if file.id != err.span.file {
+                out += &format!(
+                    "{} {}\n",
+                    self.paint("-->", Style::Gutter),
+                    self.map.format(file_marks[0].span)
+                );
+            }
+
+            out += &self.render_snippet(&file, &file_marks, width, err.severity);
+        }
+
+        out += &format!("{} ({})\n", err.msg, self.map.format(err.span));
+        for note in &err.notes {
+            let kind = self.paint(&note.kind.to_string(), Style::Note);
+            out += &format!("{}: {}\n", kind, note.msg);
+        }
+        for suggestion in &err.suggestions {
+            out += &format!(
+                "{}: {}: `{}` ({})\n",
+                self.paint("help", Style::Note),
+                suggestion.msg,
+                suggestion.replacement,
+                self.map.format(suggestion.span)
+            );
+        }
+
+        out
+    }
+
+    fn render_snippet(
+        &self,
+        file: &SourceFile,
+        marks: &[&Mark],
+        width: usize,
+        severity: Severity,
+    ) -> String {
+        let mut rows = BTreeSet::new();
+        for mark in marks {
+            let (start, end) = self.bounds(file, mark.span);
+            let first = if mark.primary {
+                start.row.saturating_sub(CONTEXT)
+            } else {
+                start.row
+            };
+            rows.extend(first..=end.row);
+        }
+
+        let mut out = String::new();
+        let mut prev: Option<u32> = None;
+        for row in rows {
+            if prev.is_some_and(|x| x + 1 < row) {
+                out += &format!("{}\n", self.paint("...", Style::Gutter));
+            }
+            prev = Some(row);
+
+            let line = file.line(row);
+            let num = (row + 1).to_string();
+            out += &format!(
+                "{}{}{}\n",
+                self.paint(&format!("{}.", num), Style::Gutter),
+                " ".repeat(width - num.len() + 1),
+                expand_tabs(line)
+            );
+
+            for mark in marks {
+                let (start, end) = self.bounds(file, mark.span);
+                if row < start.row || row > end.row {
+                    continue;
+                }
+
+                // Lines in the middle of a span are underlined from their indentation onwards
+                let from = if row == start.row {
+                    str_width(prefix(line, start.col))
+                } else {
+                    str_width(indentation(line))
+                };
+                let to = if row == end.row {
+                    str_width(prefix(line, end.col))
+                } else {
+                    str_width(line)
+                };
+
+                let (chr, style) = if mark.primary {
+                    ("^", Style::from(severity))
+                } else {
+                    ("-", Style::Secondary)
+                };
+
+                let mut underline = " ".repeat(width + 2 + from);
+                underline +=
+                    &self.paint(&chr.repeat(usize::max(to.saturating_sub(from), 1)), style);
+                if let (true, Some(msg)) = (row == end.row, mark.msg) {
+                    underline += &format!(" {}", self.paint(msg, style));
+                }
+
+                out += &format!("{}\n", underline);
+            }
+        }
+
+        out
+    }
+}
@@ -0,0 +1,355 @@
+use std::{collections::HashSet, path::Path, rc::Rc};
+
+use crate::{
+    error::Reporter,
+    lexer::Lexer,
+    location::Span,
+    parser::Parser,
+    tokens::{Token, Type, ORDERED_BINARY_OPERATORS, ORDERED_UNARY_OPERATORS},
+};
+
+const INDENT: &str = "    ";
+
+// One blank line is enough to split things up, the parser lints anything more
+const MAX_BLANK_LINES: usize = 1;
+
+fn is_opener(typ: &Type) -> bool {
+    typ.is(&[Type::LeftParen, Type::LeftBrace, Type::LeftBracket])
+}
+
+fn is_closer(typ: &Type) -> bool {
+    typ.is(&[Type::RightParen, Type::RightBrace, Type::RightBracket])
+}
+
+fn is_binop(typ: &Type) -> bool {
+    ORDERED_BINARY_OPERATORS.iter().any(|ops| typ.is(ops))
+}
+
+// Whatever comes right before a binary operator
+fn ends_operand(typ: &Type) -> bool {
+    matches!(
+        typ,
+        Type::Identifier(_)
+            | Type::Number(_)
+            | Type::Boolean(_)
+            | Type::RightParen
+            | Type::RightBracket
+    )
+}
+
+// A minus is only unary when there's nothing for it to subtract from
+fn is_unary(prev: Option<&Type>, typ: &Type) -> bool {
+    match typ {
+        Type::Subtraction => !prev.is_some_and(ends_operand),
+        _ => typ.is(ORDERED_UNARY_OPERATORS),
+    }
+}
+
+// Lists are the parentheses right after something that can be called or declared
+fn opens_list(prev: Option<&Type>) -> bool {
+    prev.is_some_and(|x| matches!(x, Type::Identifier(_) | Type::RightParen))
+}
+
+// The < and > of type lists, like in func max<T: Ord> or a: Pair<int32, bool>. Those aren't
+// comparisons, so they don't get spaces. Annotations start at a colon and go on for as long as
+// there's more type to them, and both sides of an impl's for are types too
+fn type_lists(line: &[Token]) -> HashSet<usize> {
+    let mut found = HashSet::new();
+    let (mut depth, mut parens, mut annot) = (0, 0, false);
+    for (idx, tok) in line.iter().enumerate() {
+        let typ = &tok.typ;
+        let more_type = matches!(
+            typ,
+            Type::Identifier(_)
+                | Type::Question
+                | Type::LeftParen
+                | Type::LessThan
+                | Type::Colon
+                | Type::Dyn
+        );
+        if annot && depth == 0 && parens == 0 && !more_type {
+            annot = false;
+        }
+
+        let after_name = idx > 0 && matches!(line[idx - 1].typ, Type::Identifier(_));
+        let declares = idx > 1 && line[idx - 2].typ.is(&[Type::Function, Type::Struct]);
+        match typ {
+            Type::Colon | Type::Impl => annot = true,
+            Type::For if line[0].typ == Type::Impl => annot = true,
+            Type::LeftParen if annot => parens += 1,
+            Type::RightParen if annot => parens -= 1,
+            Type::LessThan if after_name && (depth > 0 || annot || declares) => {
+                depth += 1;
+                found.insert(idx);
+            }
+            Type::GreaterThan if depth > 0 => {
+                depth -= 1;
+                found.insert(idx);
+            }
+            Type::RightShift if depth > 1 => {
+                depth -= 2;
+                found.insert(idx);
+            }
+            _ => (),
+        }
+    }
+    found
+}
+
+// Only files that parse get formatted, so a typo can't get formatted into something else
+pub fn format_file(reporter: &Rc<Reporter>, path: &Path, code: String) -> String {
+    let code = Rc::new(code);
+    let file = reporter
+        .map
+        .add(path.display().to_string(), Rc::clone(&code));
+
+    let mut lexer = Lexer::new(Rc::clone(&code), file);
+    let tokens = lexer.lex();
+    Parser::new(Rc::clone(reporter), &lexer.filter()).parse();
+
+    format(&code, &tokens)
+}
+
+// Reformats a file from its tokens, which have to include the whitespace and comments
+pub fn format(src: &str, tokens: &[Token]) -> String {
+    let tokens: Vec<Token> = tokens
+        .iter()
+        .filter(|x| !matches!(x.typ, Type::Whitespace | Type::EOF))
+        .cloned()
+        .collect();
+
+    let tokens = fix_trailing_commas(tokens);
+    let tokens = fix_block_lines(tokens);
+    Formatter::new(src).lines(&tokens)
+}
+
+// Lists spread over lines end with a comma, lists on one line don't
+fn fix_trailing_commas(tokens: Vec<Token>) -> Vec<Token> {
+    let mut add = HashSet::new();
+    let mut remove = HashSet::new();
+
+    let mut stack = Vec::new();
+    let mut prev = None;
+    for (idx, tok) in tokens.iter().enumerate() {
+        match tok.typ {
+            Type::LeftParen => stack.push((idx, opens_list(prev))),
+            Type::RightParen => {
+                if let Some((open, true)) = stack.pop() {
+                    let last = (open..idx)
+                        .rev()
+                        .find(|x| !matches!(tokens[*x].typ, Type::NewLine | Type::Comment(_)))
+                        .unwrap();
+                    let multiline = tokens[last..idx].iter().any(|x| x.typ == Type::NewLine);
+
+                    match tokens[last].typ {
+                        Type::LeftParen => (),
+                        Type::Comma if !multiline => {
+                            remove.insert(last);
+                        }
+                        Type::Comma => (),
+                        _ if multiline => {
+                            add.insert(last);
+                        }
+                        _ => (),
+                    }
+                }
+            }
+            _ => (),
+        }
+
+        if !matches!(tok.typ, Type::NewLine | Type::Comment(_)) {
+            prev = Some(&tok.typ);
+        }
+    }
+
+    let mut fixed = Vec::new();
+    for (idx, tok) in tokens.iter().enumerate() {
+        if remove.contains(&idx) {
+            continue;
+        }
+
+        fixed.push(tok.clone());
+        if add.contains(&idx) {
+            let span = Span::new(tok.span.file, tok.span.hi, tok.span.hi);
+            fixed.push(Token::new(span, Type::Comma));
+        }
+    }
+
+    fixed
+}
+
+// Blocks either fit on one line or have their braces on lines of their own, like the printer
+// writes them. Empty ones are always {}
+fn fix_block_lines(tokens: Vec<Token>) -> Vec<Token> {
+    let mut after = HashSet::new();
+    let mut before = HashSet::new();
+    let mut remove = HashSet::new();
+
+    let mut stack = Vec::new();
+    for (idx, tok) in tokens.iter().enumerate() {
+        match tok.typ {
+            Type::LeftBrace => stack.push(idx),
+            Type::RightBrace => {
+                let Some(open) = stack.pop() else {
+                    continue;
+                };
+                let inside = &tokens[open + 1..idx];
+                if inside.iter().all(|x| x.typ == Type::NewLine) {
+                    remove.extend(open + 1..idx);
+                } else if inside.iter().any(|x| x.typ == Type::NewLine) {
+                    // A comment after the brace stays on its line
+                    if !matches!(inside[0].typ, Type::NewLine | Type::Comment(_)) {
+                        after.insert(open);
+                    }
+                    if tokens[idx - 1].typ != Type::NewLine {
+                        before.insert(idx);
+                    }
+                }
+            }
+            _ => (),
+        }
+    }
+
+    let mut fixed = Vec::new();
+    for (idx, tok) in tokens.iter().enumerate() {
+        if remove.contains(&idx) {
+            continue;
+        }
+
+        let span = Span::new(tok.span.file, tok.span.lo, tok.span.lo);
+        if before.contains(&idx) {
+            fixed.push(Token::new(span, Type::NewLine));
+        }
+        fixed.push(tok.clone());
+        if after.contains(&idx) {
+            let span = Span::new(tok.span.file, tok.span.hi, tok.span.hi);
+            fixed.push(Token::new(span, Type::NewLine));
+        }
+    }
+
+    fixed
+}
+
+struct Formatter<'a> {
+    src: &'a str,
+    out: String,
+    // The indentation of the line each open bracket is on
+    stack: Vec<usize>,
+}
+
+impl<'a> Formatter<'a> {
+    fn new(src: &'a str) -> Formatter<'a> {
+        Formatter {
+            src,
+            out: String::new(),
+            stack: Vec::new(),
+        }
+    }
+
+    // Numbers are written the way they were, everything else has one way to write it
+    fn text(&self, tok: &Token) -> String {
+        match &tok.typ {
+            Type::Identifier(name) => name.clone(),
+            Type::Comment(text) => text.trim_end().to_owned(),
+            Type::Number(_) => self.src[tok.span.lo as usize..tok.span.hi as usize].to_owned(),
+            typ => typ.src_strings().remove(0),
+        }
+    }
+
+    fn lines(mut self, tokens: &[Token]) -> String {
+        let lines: Vec<&[Token]> = tokens.split(|x| x.typ == Type::NewLine).collect();
+
+        let mut blank = 0;
+        let mut prev: Option<&[Token]> = None;
+        for line in lines {
+            if line.is_empty() {
+                blank += 1;
+                continue;
+            }
+
+            // No blank lines at the start of the file or right inside of brackets
+            if let Some(prev) = prev {
+                let after_open = is_opener(&prev.last().unwrap().typ);
+                let before_close = is_closer(&line[0].typ);
+                if !after_open && !before_close {
+                    self.out.push_str(&"\n".repeat(blank.min(MAX_BLANK_LINES)));
+                }
+            }
+
+            self.line(line);
+            self.out.push('\n');
+
+            blank = 0;
+            prev = Some(line);
+        }
+
+        self.out
+    }
+
+    fn line(&mut self, line: &[Token]) {
+        // A line closing a bracket lines up with the line that opened it
+        let indent = match self.stack.last() {
+            Some(&open) if is_closer(&line[0].typ) => open,
+            Some(&open) => open + 1,
+            None => 0,
+        };
+        self.out.push_str(&INDENT.repeat(indent));
+
+        let angles = type_lists(line);
+        let mut prev: Option<&Type> = None;
+        let mut prev_unary = false;
+        for (idx, tok) in line.iter().enumerate() {
+            let typ = &tok.typ;
+            if let Some(prev) = prev {
+                let space = match (angles.contains(&(idx - 1)), angles.contains(&idx)) {
+                    (_, true) => false,
+                    (true, false) if *prev == Type::LessThan => false,
+                    // A closing one is spaced like a ), it isn't an operator that = could join
+                    (true, false) => self.space(&Type::RightParen, false, typ),
+                    _ => self.space(prev, prev_unary, typ),
+                };
+                if space {
+                    self.out.push(' ');
+                }
+            }
+
+            if is_opener(typ) {
+                self.stack.push(indent);
+            } else if is_closer(typ) {
+                self.stack.pop();
+            }
+
+            self.out.push_str(&self.text(tok));
+            prev_unary = is_unary(prev, typ);
+            prev = Some(typ);
+        }
+    }
+
+    fn space(&self, prev: &Type, prev_unary: bool, typ: &Type) -> bool {
+        match (prev, typ) {
+            _ if prev_unary => false,
+            (_, Type::Comment(_)) => true,
+            (
+                Type::LeftParen | Type::LeftBracket | Type::Dot | Type::QuestionDot | Type::Hash,
+                _,
+            ) => false,
+            (
+                _,
+                Type::Comma
+                | Type::Semicolon
+                | Type::Colon
+                | Type::Dot
+                | Type::QuestionDot
+                | Type::Question
+                | Type::RightParen
+                | Type::RightBracket,
+            ) => false,
+            (Type::LeftBrace, Type::RightBrace) => false,
+            // Calls, parameter lists and attributes
+            (Type::Identifier(_) | Type::RightParen, Type::LeftParen) => false,
+            // Operator assignments like +=
+            (prev, Type::Assignment) if is_binop(prev) => false,
+            _ => true,
+        }
+    }
+}
@@ -0,0 +1,818 @@
+use core::fmt;
+use std::{collections::HashMap, rc::Rc};
+
+use crate::{
+    ast::{self, Block, Expr, Node, NodeId, Stmt},
+    ir::MAX_DEPTH,
+    location::Span,
+    program::{Method, Program},
+    symbols::SymbolId,
+    tokens::Type,
+    types::DataType,
+};
+
+// Calls nest on the real stack and are a lot bigger in debug builds, so whatever runs a program
+// should do it on a thread this big to reach MAX_DEPTH calls
+pub const STACK_SIZE: usize = 64 << 20;
+
+#[derive(Debug, Clone)]
+pub enum Value {
+    Int(i128),
+    Float(f64),
+    Bool(bool),
+    Func(Func),
+    Tuple(Vec<Value>),
+    // Structs too, their fields are in the order they were declared
+    Variant(Variant),
+    // A variant with fields or a struct, before it's been called with them
+    Ctor(Variant),
+    // Optionals that aren't none are only the value they hold
+    None,
+    Dyn(Dyn),
+}
+
+// A value that could be any type with the trait, it keeps that type's methods with it
+#[derive(Debug, Clone)]
+pub struct Dyn {
+    pub val: Box<Value>,
+    pub vtable: Rc<Vec<Func>>,
+}
+
+// Variants are told apart by their symbol, the name is only for showing them
+#[derive(Debug, Clone, PartialEq)]
+pub struct Variant {
+    pub id: SymbolId,
+    pub name: String,
+    pub fields: Vec<Value>,
+}
+
+impl fmt::Display for Variant {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name)?;
+        if !self.fields.is_empty() {
+            write!(f, "({})", list(&self.fields))?;
+        }
+        Ok(())
+    }
+}
+
+fn list(vals: &[Value]) -> String {
+    let vals: Vec<String> = vals.iter().map(|x| x.to_string()).collect();
+    vals.join(", ")
+}
+
+// A function is wherever its statement is, the program has to stay around to call it. One
+// declared inside a generic function keeps the instance it was declared in
+#[derive(Clone)]
+pub struct Func {
+    pub prog: Rc<Program>,
+    pub stmt: Node<Stmt>,
+    pub view: Option<View>,
+}
+
+// The instance of a generic function being run, its types come from there instead of the program.
+// The owner is the program whose list of instances it's in
+#[derive(Clone)]
+pub struct View {
+    pub owner: Rc<Program>,
+    pub idx: usize,
+}
+
+impl Func {
+    fn get(&self) -> &ast::Func {
+        match &self.prog.ast[self.stmt] {
+            Stmt::Func(func) => func,
+            _ => unreachable!("functions always point at a func statement"),
+        }
+    }
+}
+
+impl fmt::Debug for Func {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Func({:?})", self.stmt)
+    }
+}
+
+impl PartialEq for Value {
+    fn eq(&self, other: &Value) -> bool {
+        match (self, other) {
+            (Value::Int(x), Value::Int(y)) => x == y,
+            (Value::Float(x), Value::Float(y)) => x == y,
+            (Value::Bool(x), Value::Bool(y)) => x == y,
+            (Value::Func(x), Value::Func(y)) => Rc::ptr_eq(&x.prog, &y.prog) && x.stmt == y.stmt,
+            (Value::Tuple(x), Value::Tuple(y)) => x == y,
+            (Value::Variant(x), Value::Variant(y)) | (Value::Ctor(x), Value::Ctor(y)) => x == y,
+            (Value::None, Value::None) => true,
+            (Value::Dyn(x), y) => *x.val == *y,
+            (x, Value::Dyn(y)) => *x == *y.val,
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Int(x) => write!(f, "{}", x),
+            Value::Float(x) => write!(f, "{}", x),
+            Value::Bool(x) => write!(f, "{}", x),
+            Value::Func(x) => write!(f, "func {}", x.prog.ast[x.get().name].name),
+            Value::Tuple(x) if x.len() == 1 => write!(f, "({},)", x[0]),
+            Value::Tuple(x) => write!(f, "({})", list(x)),
+            Value::Variant(x) => write!(f, "{}", x),
+            Value::Ctor(x) => write!(f, "func {}", x.name),
+            Value::None => write!(f, "none"),
+            Value::Dyn(x) => write!(f, "{}", x.val),
+        }
+    }
+}
+
+// Something that went wrong while running, like dividing by zero
+#[derive(Debug, Clone)]
+pub struct Trap {
+    pub msg: String,
+    pub span: Span,
+}
+
+// How a statement finished, everything but Normal skips the rest of the block
+enum Flow {
+    Normal,
+    Continue,
+    Return(Option<Value>),
+}
+
+// Runs checked programs straight from the AST. Every variable is keyed by its symbol, so
+// declarations from earlier programs stay visible to later ones
+#[derive(Default)]
+pub struct Interpreter {
+    globals: HashMap<SymbolId, Value>,
+    // One per call, a name is looked up from the innermost call outwards
+    frames: Vec<HashMap<SymbolId, Value>>,
+    view: Option<View>,
+    // The names of every struct's fields, in order
+    fields: HashMap<SymbolId, Vec<String>>,
+}
+
+type Result<T> = std::result::Result<T, Trap>;
+
+impl Interpreter {
+    pub fn new() -> Interpreter {
+        Interpreter::default()
+    }
+
+    // The value of the last statement, when that's an expression with one
+    pub fn run(&mut self, prog: &Rc<Program>) -> Result<Option<Value>> {
+        let stmts = &prog.ast[prog.root].stmts;
+        self.declare_funcs(prog, stmts);
+
+        let mut last = None;
+        for &stmt in stmts {
+            last = None;
+            match &prog.ast[stmt] {
+                // A match without a value can still return from the program
+                Stmt::ExprStmt(x) if prog.types.contains(x.expr) => {
+                    last = self.eval(prog, x.expr)?
+                }
+                _ => {
+                    if let Flow::Return(_) = self.exec(prog, stmt)? {
+                        break;
+                    }
+                }
+            }
+        }
+
+        Ok(last)
+    }
+
+    // Calls a function with values that already match its parameters
+    pub fn call(&mut self, func: &Func, args: Vec<Value>, span: Span) -> Result<Option<Value>> {
+        self.call_in(func, func.view.clone(), args, span)
+    }
+
+    fn call_in(
+        &mut self,
+        func: &Func,
+        view: Option<View>,
+        args: Vec<Value>,
+        span: Span,
+    ) -> Result<Option<Value>> {
+        if self.frames.len() == MAX_DEPTH {
+            return Err(Trap {
+                msg: format!("Recursed too deep, after {} calls", MAX_DEPTH),
+                span,
+            });
+        }
+
+        let prog = Rc::clone(&func.prog);
+        let decl = func.get();
+        let mut frame = HashMap::new();
+        for (&param, val) in decl.params.iter().zip(args) {
+            frame.insert(self.symbol(&prog, prog.ast[param].name), val);
+        }
+
+        self.frames.push(frame);
+        let outer = std::mem::replace(&mut self.view, view);
+        let res = self.block(&prog, decl.body);
+        self.view = outer;
+        self.frames.pop();
+
+        match res? {
+            Flow::Return(val) => Ok(val),
+            _ => Ok(None),
+        }
+    }
+
+    fn symbol(&self, prog: &Program, node: impl Into<NodeId>) -> SymbolId {
+        *prog.names.get(node).expect("the program wasn't resolved")
+    }
+
+    // Inside an instance the program's types still have the type parameters in them
+    fn typ(&self, prog: &Program, node: impl Into<NodeId>) -> Option<DataType> {
+        match &self.view {
+            Some(view) => view.owner.instances[view.idx].types.get(node).cloned(),
+            None => prog.types.get(node).cloned(),
+        }
+    }
+
+    // The instance a call to a generic function runs, calls to anything else don't have one
+    fn instance(&self, prog: &Rc<Program>, call: Node<Expr>) -> Option<View> {
+        let (owner, calls) = match &self.view {
+            Some(view) => (&view.owner, &view.owner.instances[view.idx].calls),
+            None => (prog, &prog.calls),
+        };
+        calls.get(call).map(|&idx| View {
+            owner: Rc::clone(owner),
+            idx,
+        })
+    }
+
+    // Whichever of the program or the instance being run has what the checker worked out
+    fn method(&self, prog: &Program, call: Node<Expr>) -> Option<Method> {
+        match &self.view {
+            Some(view) => view.owner.instances[view.idx].methods.get(call).copied(),
+            None => prog.methods.get(call).copied(),
+        }
+    }
+
+    fn vtable(&self, prog: &Program, expr: Node<Expr>) -> Option<Vec<SymbolId>> {
+        match &self.view {
+            Some(view) => view.owner.instances[view.idx].vtables.get(expr).cloned(),
+            None => prog.vtables.get(expr).cloned(),
+        }
+    }
+
+    // The receiver goes first, a dyn gets unwrapped and picks the method from its vtable. Only
+    // x?.f() can have a none to call it on, which skips the call
+    fn call_method(
+        &mut self,
+        prog: &Rc<Program>,
+        call: &ast::Call,
+        method: Method,
+        span: Span,
+    ) -> Result<Option<Value>> {
+        let recv = match &prog.ast[call.callee] {
+            Expr::Field(x) => match self.value(prog, x.val)? {
+                Value::None if x.optional => return Ok(Some(Value::None)),
+                val => val,
+            },
+            Expr::Path(x) => {
+                let segments = &prog.ast[*x].segments;
+                let mut val = self.get(self.symbol(prog, segments[0]));
+                for &seg in &segments[1..segments.len() - 1] {
+                    val = self.field(val, &prog.ast[seg].name);
+                }
+                val
+            }
+            _ => unreachable!("methods are only called through a field or a path"),
+        };
+        let (func, recv) = match (method, recv) {
+            (Method::Static(id), recv) => match self.get(id) {
+                Value::Func(func) => (func, recv),
+                val => unreachable!("methods are functions, got {}", val),
+            },
+            (Method::Dyn(idx), Value::Dyn(x)) => (x.vtable[idx].clone(), *x.val),
+            (_, val) => unreachable!("only a dyn has a vtable, got {}", val),
+        };
+
+        let mut args = vec![recv];
+        for &arg in &call.args {
+            args.push(self.value(prog, arg)?);
+        }
+        self.call(&func, args, span)
+    }
+
+    // Tuple fields are numbered, struct fields go by name
+    fn field(&self, val: Value, name: &str) -> Value {
+        match val {
+            Value::Tuple(items) => items[name.parse::<usize>().unwrap()].clone(),
+            Value::Variant(x) => {
+                let idx = self.fields[&x.id].iter().position(|x| x == name).unwrap();
+                x.fields[idx].clone()
+            }
+            // Only a?.0 can get here with a none
+            Value::None => Value::None,
+            val => unreachable!(
+                "the checker only allows fields of tuples and structs, got {}",
+                val
+            ),
+        }
+    }
+
+    fn get(&self, id: SymbolId) -> Value {
+        self.frames
+            .iter()
+            .rev()
+            .chain(std::iter::once(&self.globals))
+            .find_map(|vars| vars.get(&id))
+            .cloned()
+            .expect("the resolver makes sure variables are declared before they're used")
+    }
+
+    fn declare(&mut self, id: SymbolId, val: Value) {
+        match self.frames.last_mut() {
+            Some(frame) => frame.insert(id, val),
+            None => self.globals.insert(id, val),
+        };
+    }
+
+    fn set(&mut self, id: SymbolId, val: Value) {
+        let vars = self
+            .frames
+            .iter_mut()
+            .rev()
+            .chain(std::iter::once(&mut self.globals))
+            .find(|vars| vars.contains_key(&id));
+        vars.expect("assigned to a variable that was never declared")
+            .insert(id, val);
+    }
+
+    // Functions can be called before they're declared, so they exist as soon as their block does.
+    // The same goes for variants, which are values or make them
+    fn declare_funcs(&mut self, prog: &Rc<Program>, stmts: &[Node<Stmt>]) {
+        for &stmt in stmts {
+            match &prog.ast[stmt] {
+                Stmt::Func(func) => {
+                    let func_val = Func {
+                        prog: Rc::clone(prog),
+                        stmt,
+                        view: self.view.clone(),
+                    };
+                    self.declare(self.symbol(prog, func.name), Value::Func(func_val));
+                }
+                Stmt::Enum(enum_stmt) => {
+                    for &variant in &enum_stmt.variants {
+                        let variant = &prog.ast[variant];
+                        let id = self.symbol(prog, variant.name);
+                        let val = Variant {
+                            id,
+                            name: format!(
+                                "{}.{}",
+                                prog.ast[enum_stmt.name].name, prog.ast[variant.name].name
+                            ),
+                            fields: Vec::new(),
+                        };
+                        let val = match variant.fields.is_empty() {
+                            true => Value::Variant(val),
+                            false => Value::Ctor(val),
+                        };
+                        self.declare(id, val);
+                    }
+                }
+                Stmt::Struct(struct_stmt) => {
+                    let id = self.symbol(prog, struct_stmt.name);
+                    let names = struct_stmt
+                        .fields
+                        .iter()
+                        .map(|&x| prog.ast[prog.ast[x].name].name.clone())
+                        .collect();
+                    self.fields.insert(id, names);
+                    let val = Variant {
+                        id,
+                        name: prog.ast[struct_stmt.name].name.clone(),
+                        fields: Vec::new(),
+                    };
+                    self.declare(id, Value::Ctor(val));
+                }
+                // Methods are only found through the calls the checker pointed at them
+                Stmt::Impl(impl_stmt) => {
+                    for &method in &impl_stmt.methods {
+                        let Stmt::Func(func) = &prog.ast[method] else {
+                            continue;
+                        };
+                        let func_val = Func {
+                            prog: Rc::clone(prog),
+                            stmt: method,
+                            view: None,
+                        };
+                        self.declare(self.symbol(prog, func.name), Value::Func(func_val));
+                    }
+                }
+                _ => (),
+            }
+        }
+    }
+
+    // The first arm that matches, with its bindings declared. The checker makes sure there is one
+    fn pick(&mut self, prog: &Rc<Program>, match_expr: &ast::Match) -> Result<Node<Stmt>> {
+        let val = self.value(prog, match_expr.val)?;
+        for &arm in &match_expr.arms {
+            let arm = &prog.ast[arm];
+            if !self.matches(prog, arm.pat, &val)? {
+                continue;
+            }
+            match arm.guard {
+                Some(guard) if !self.cond(prog, guard)? => (),
+                _ => return Ok(arm.body),
+            }
+        }
+        unreachable!("the checker only allows matches that cover every value")
+    }
+
+    fn matches(
+        &mut self,
+        prog: &Rc<Program>,
+        pat: Node<ast::Pattern>,
+        val: &Value,
+    ) -> Result<bool> {
+        let res = match (&prog.ast[pat], val) {
+            (ast::Pattern::Wildcard, _) => true,
+            (ast::Pattern::Binding(name), val) => {
+                self.declare(self.symbol(prog, *name), val.clone());
+                true
+            }
+            (ast::Pattern::Literal(lit), val) => self.value(prog, *lit)? == *val,
+            (ast::Pattern::Tuple(pats), Value::Tuple(vals)) => {
+                for (&pat, val) in pats.iter().zip(vals) {
+                    if !self.matches(prog, pat, val)? {
+                        return Ok(false);
+                    }
+                }
+                true
+            }
+            (ast::Pattern::Variant(path, pats), Value::Variant(variant)) => {
+                if self.symbol(prog, *path) != variant.id {
+                    return Ok(false);
+                }
+                for (&pat, val) in pats.iter().zip(&variant.fields) {
+                    if !self.matches(prog, pat, val)? {
+                        return Ok(false);
+                    }
+                }
+                true
+            }
+            (_, val) => unreachable!(
+                "the checker only allows patterns of the right type, got {}",
+                val
+            ),
+        };
+        Ok(res)
+    }
+
+    fn block(&mut self, prog: &Rc<Program>, block: Node<Block>) -> Result<Flow> {
+        let stmts = &prog.ast[block].stmts;
+        self.declare_funcs(prog, stmts);
+
+        for &stmt in stmts {
+            match self.exec(prog, stmt)? {
+                Flow::Normal => (),
+                flow => return Ok(flow),
+            }
+        }
+        Ok(Flow::Normal)
+    }
+
+    fn cond(&mut self, prog: &Rc<Program>, cond: Node<Expr>) -> Result<bool> {
+        match self.value(prog, cond)? {
+            Value::Bool(x) => Ok(x),
+            val => unreachable!("the checker only allows bool conditions, got {}", val),
+        }
+    }
+
+    fn exec(&mut self, prog: &Rc<Program>, stmt: Node<Stmt>) -> Result<Flow> {
+        match &prog.ast[stmt] {
+            Stmt::Block(x) => return self.block(prog, *x),
+            Stmt::Decl(x) => {
+                let val = self.value(prog, x.val)?;
+                self.declare(self.symbol(prog, x.name), val);
+            }
+            Stmt::Assign(x) => {
+                let id = self.symbol(prog, x.name);
+                let mut val = self.value(prog, x.val)?;
+                if let Some(op) = &x.op {
+                    let typ = self.typ(prog, x.name).or(self.typ(prog, x.val));
+                    val = binop(&op.src, self.get(id), val, typ.as_ref(), op.span)?;
+                }
+                self.set(id, val);
+            }
+            Stmt::IfStmt(x) => {
+                if self.cond(prog, x.cond)? {
+                    return self.block(prog, x.body);
+                }
+            }
+            Stmt::IfLet(x) => {
+                let val = self.value(prog, x.val)?;
+                if val != Value::None {
+                    self.declare(self.symbol(prog, x.name), val);
+                    return self.block(prog, x.body);
+                }
+            }
+            Stmt::WhileLoop(x) => {
+                while self.cond(prog, x.cond)? {
+                    if let flow @ Flow::Return(_) = self.block(prog, x.body)? {
+                        return Ok(flow);
+                    }
+                }
+            }
+            Stmt::DoWhileLoop(x) => loop {
+                if let flow @ Flow::Return(_) = self.block(prog, x.body)? {
+                    return Ok(flow);
+                }
+                if !self.cond(prog, x.cond)? {
+                    break;
+                }
+            },
+            Stmt::Continue => return Ok(Flow::Continue),
+            Stmt::Return(x) => {
+                let val = match x.val {
+                    Some(val) => Some(self.value(prog, val)?),
+                    None => None,
+                };
+                return Ok(Flow::Return(val));
+            }
+            // Arms can be blocks, which can return or continue
+            Stmt::ExprStmt(x) => match &prog.ast[x.expr] {
+                Expr::Match(m) => {
+                    let body = self.pick(prog, m)?;
+                    return self.exec(prog, body);
+                }
+                _ => {
+                    self.eval(prog, x.expr)?;
+                }
+            },
+            // Already declared when the block started, modules and imports are only names
+            Stmt::Func(_)
+            | Stmt::Enum(_)
+            | Stmt::Struct(_)
+            | Stmt::Trait(_)
+            | Stmt::Impl(_)
+            | Stmt::Module(_)
+            | Stmt::Use(_) => (),
+        }
+
+        Ok(Flow::Normal)
+    }
+
+    // Somewhere the checker made sure there's a value. One that goes into a dyn takes its type's
+    // methods with it
+    fn value(&mut self, prog: &Rc<Program>, expr: Node<Expr>) -> Result<Value> {
+        let val = self
+            .eval(prog, expr)?
+            .expect("the checker only allows calls with a return type here");
+        match self.vtable(prog, expr) {
+            Some(ids) if val != Value::None => {
+                let vtable = ids
+                    .into_iter()
+                    .map(|id| match self.get(id) {
+                        Value::Func(func) => func,
+                        val => unreachable!("methods are functions, got {}", val),
+                    })
+                    .collect();
+                Ok(Value::Dyn(Dyn {
+                    val: Box::new(val),
+                    vtable: Rc::new(vtable),
+                }))
+            }
+            _ => Ok(val),
+        }
+    }
+
+    pub fn eval(&mut self, prog: &Rc<Program>, expr: Node<Expr>) -> Result<Option<Value>> {
+        let span = prog.ast.span(expr);
+        let typ = self.typ(prog, expr);
+        let typ = typ.as_ref();
+        let val = match &prog.ast[expr] {
+            Expr::Ident(x) => self.get(self.symbol(prog, *x)),
+            Expr::Path(x) if prog.names.contains(*x) => self.get(self.symbol(prog, *x)),
+            // A variable and its fields
+            Expr::Path(x) => {
+                let segments = &prog.ast[*x].segments;
+                let mut val = self.get(self.symbol(prog, segments[0]));
+                for &seg in &segments[1..] {
+                    val = self.field(val, &prog.ast[seg].name);
+                }
+                val
+            }
+            Expr::NumLit(x) => match typ {
+                Some(DataType::Float(_)) => Value::Float(x.val as f64),
+                _ => Value::Int(wrap(x.val as i128, typ)),
+            },
+            Expr::BoolLit(x) => Value::Bool(x.val),
+            Expr::NoneLit => Value::None,
+            // The default is only worked out when it's needed
+            Expr::BinaryOp(x) if x.op.src == Type::Coalesce => match self.value(prog, x.lhs)? {
+                Value::None => self.value(prog, x.rhs)?,
+                val => val,
+            },
+            Expr::BinaryOp(x) => {
+                let lhs = self.value(prog, x.lhs)?;
+                let rhs = self.value(prog, x.rhs)?;
+                // Comparisons are typed bool, the operands know how wide the numbers are
+                binop(
+                    &x.op.src,
+                    lhs,
+                    rhs,
+                    self.typ(prog, x.lhs).as_ref(),
+                    x.op.span,
+                )?
+            }
+            Expr::UnaryOp(x) => {
+                let val = self.value(prog, x.val)?;
+                unop(&x.op.src, val, typ)
+            }
+            Expr::Call(x) => {
+                if let Some(method) = self.method(prog, expr) {
+                    return self.call_method(prog, x, method, span);
+                }
+                let callee = self.value(prog, x.callee)?;
+                let mut args = Vec::new();
+                for &arg in &x.args {
+                    args.push(self.value(prog, arg)?);
+                }
+                match callee {
+                    Value::Func(func) => {
+                        let view = self.instance(prog, expr).or_else(|| func.view.clone());
+                        return self.call_in(&func, view, args, span);
+                    }
+                    Value::Ctor(variant) => Value::Variant(Variant {
+                        fields: args,
+                        ..variant
+                    }),
+                    val => unreachable!("the checker only allows calling functions, got {}", val),
+                }
+            }
+            Expr::Field(x) => {
+                let val = self.value(prog, x.val)?;
+                self.field(val, &prog.ast[x.name].name)
+            }
+            Expr::Tuple(x) => {
+                let mut items = Vec::new();
+                for &item in &x.items {
+                    items.push(self.value(prog, item)?);
+                }
+                Value::Tuple(items)
+            }
+            // Only matches with a value get here, so every arm is an expression
+            Expr::Match(x) => {
+                let body = self.pick(prog, x)?;
+                return match &prog.ast[body] {
+                    Stmt::ExprStmt(body) => self.eval(prog, body.expr),
+                    _ => unreachable!("matches without a value are run as statements"),
+                };
+            }
+        };
+
+        Ok(Some(val))
+    }
+}
+
+// Integers are kept sign or zero extended from their own width, so they can be compared directly
+pub(crate) fn wrap(val: i128, typ: Option<&DataType>) -> i128 {
+    let (bits, signed) = match typ {
+        Some(DataType::Int(x)) => (x.size as u32, true),
+        Some(DataType::Uint(x)) => (x.size as u32, false),
+        _ => (128, true),
+    };
+
+    match (bits, signed) {
+        (128, _) => val,
+        (_, true) => (val << (128 - bits)) >> (128 - bits),
+        (_, false) => val & ((1 << bits) - 1),
+    }
+}
+
+fn bits(typ: Option<&DataType>) -> u32 {
+    match typ {
+        Some(DataType::Int(x)) => x.size as u32,
+        Some(DataType::Uint(x)) => x.size as u32,
+        _ => 128,
+    }
+}
+
+pub fn binop(
+    op: &Type,
+    lhs: Value,
+    rhs: Value,
+    typ: Option<&DataType>,
+    span: Span,
+) -> Result<Value> {
+    let trap = |msg: &str| {
+        Err(Trap {
+            msg: msg.to_owned(),
+            span,
+        })
+    };
+
+    let val = match (lhs, rhs) {
+        // Only x ??= y gets here, x ?? y doesn't work out y unless it has to
+        (lhs, rhs) if *op == Type::Coalesce => match lhs {
+            Value::None => rhs,
+            lhs => lhs,
+        },
+        (Value::Int(x), Value::Int(y)) => match op {
+            Type::Addition => Value::Int(wrap(x.wrapping_add(y), typ)),
+            Type::Subtraction => Value::Int(wrap(x.wrapping_sub(y), typ)),
+            Type::Multiplication => Value::Int(wrap(x.wrapping_mul(y), typ)),
+            Type::Division | Type::Modulo if y == 0 => return trap("Division by zero"),
+            Type::Division => Value::Int(wrap(x.wrapping_div(y), typ)),
+            Type::Modulo => Value::Int(wrap(x.wrapping_rem(y), typ)),
+            Type::Exponentiation if y < 0 => return trap("Negative exponent"),
+            Type::Exponentiation => {
+                let exp = u32::try_from(y).unwrap_or(u32::MAX);
+                Value::Int(wrap(x.wrapping_pow(exp), typ))
+            }
+            Type::LeftShift | Type::RightShift | Type::ZeroFillRightShift
+                if y < 0 || y >= bits(typ) as i128 =>
+            {
+                return trap("Shifted by more than the width of the type")
+            }
+            Type::LeftShift => Value::Int(wrap(x << y, typ)),
+            Type::RightShift => Value::Int(x >> y),
+            // The same bits read as unsigned, then shifted and read back
+            Type::ZeroFillRightShift => {
+                let bits = bits(typ);
+                let unsigned = match bits {
+                    128 => x as u128,
+                    _ => (x as u128) & ((1 << bits) - 1),
+                };
+                Value::Int(wrap((unsigned >> y) as i128, typ))
+            }
+            Type::And => Value::Int(x & y),
+            Type::Or => Value::Int(x | y),
+            Type::Xor => Value::Int(x ^ y),
+            Type::Nand => Value::Int(wrap(!(x & y), typ)),
+            Type::Nor => Value::Int(wrap(!(x | y), typ)),
+            Type::Xand => Value::Int(wrap(!(x ^ y), typ)),
+            _ => Value::Bool(compare(op, x.cmp(&y))),
+        },
+        (Value::Float(x), Value::Float(y)) => match op {
+            Type::Addition => Value::Float(x + y),
+            Type::Subtraction => Value::Float(x - y),
+            Type::Multiplication => Value::Float(x * y),
+            Type::Division => Value::Float(x / y),
+            Type::Modulo => Value::Float(x % y),
+            Type::Exponentiation => Value::Float(x.powf(y)),
+            _ => match x.partial_cmp(&y) {
+                Some(ord) => Value::Bool(compare(op, ord)),
+                // NaN is only ever not equal
+                None => Value::Bool(*op == Type::NotEqual),
+            },
+        },
+        (Value::Bool(x), Value::Bool(y)) => match op {
+            Type::And => Value::Bool(x & y),
+            Type::Or => Value::Bool(x | y),
+            Type::Xor => Value::Bool(x ^ y),
+            Type::Nand => Value::Bool(!(x & y)),
+            Type::Nor => Value::Bool(!(x | y)),
+            Type::Xand => Value::Bool(x == y),
+            _ => Value::Bool(compare(op, x.cmp(&y))),
+        },
+        (x, y) => match op {
+            Type::Equal => Value::Bool(x == y),
+            Type::NotEqual => Value::Bool(x != y),
+            _ => return trap(&format!("Cannot use {} on {} and {}", op, x, y)),
+        },
+    };
+
+    Ok(val)
+}
+
+fn compare(op: &Type, ord: std::cmp::Ordering) -> bool {
+    match op {
+        Type::Equal => ord.is_eq(),
+        Type::NotEqual => ord.is_ne(),
+        Type::LessThan => ord.is_lt(),
+        Type::LessThanOrEqual => ord.is_le(),
+        Type::GreaterThan => ord.is_gt(),
+        Type::GreaterThanOrEqual => ord.is_ge(),
+        _ => unreachable!("{} is not a comparison", op),
+    }
+}
+
+pub fn unop(op: &Type, val: Value, typ: Option<&DataType>) -> Value {
+    match (op, val) {
+        (Type::Subtraction, Value::Int(x)) => Value::Int(wrap(x.wrapping_neg(), typ)),
+        (Type::Subtraction, Value::Float(x)) => Value::Float(-x),
+        (Type::Not, Value::Int(x)) => Value::Int(wrap(!x, typ)),
+        (Type::Not, Value::Bool(x)) => Value::Bool(!x),
+        // Only the bits that are part of the type get counted
+        (Type::CountLeadingZeros, Value::Int(x)) => {
+            let bits = bits(typ);
+            let unsigned = (x as u128) << (128 - bits);
+            Value::Int(unsigned.leading_zeros().min(bits) as i128)
+        }
+        (Type::CountTrailingZeros, Value::Int(x)) => {
+            Value::Int((x as u128).trailing_zeros().min(bits(typ)) as i128)
+        }
+        (op, val) => unreachable!("the checker doesn't allow {} on {}", op, val),
+    }
+}
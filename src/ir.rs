@@ -0,0 +1,625 @@
+use std::collections::HashMap;
+
+use crate::{
+    tokens,
+    types::{DataType, FloatingSize, IntegralSize},
+};
+
+pub(crate) mod cfg;
+pub(crate) mod eval;
+pub(crate) mod lower;
+pub(crate) mod opt;
+pub(crate) mod parse;
+pub(crate) mod print;
+pub(crate) mod verify;
+
+pub use self::{
+    cfg::Cfg,
+    eval::{Machine, Val, MAX_DEPTH},
+    lower::{lower, ENTRY},
+    opt::{optimize, Pass, Stats, PASSES},
+    parse::parse,
+    verify::{verify, verify_func},
+};
+
+// What the backends see of the program. Every function is in SSA form, values only ever get
+// assigned once and phis pick between them where control flow joins. Variables that other
+// functions or modules can see live in globals instead, and go through loads and stores
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Module {
+    pub globals: Vec<Global>,
+    pub funcs: Vec<Function>,
+}
+
+impl Module {
+    pub fn func(&self, name: &str) -> Option<&Function> {
+        self.funcs.iter().find(|x| x.name == name)
+    }
+
+    pub fn global(&self, name: &str) -> Option<&Global> {
+        self.globals.iter().find(|x| x.name == name)
+    }
+}
+
+// Starts out zeroed, the code that declares the variable stores its first value
+#[derive(Debug, Clone, PartialEq)]
+pub struct Global {
+    pub name: String,
+    pub typ: Type,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Type {
+    Int(IntegralSize),
+    Uint(IntegralSize),
+    Float(FloatingSize),
+    Bool,
+    // Points at a function, calling it needs the types of the arguments and the result
+    Func,
+}
+
+impl Type {
+    // Arrays can't be made yet, and tuples, enums and structs don't fit in one value, so they're
+    // only interpreted for now
+    pub fn from(typ: &DataType) -> Option<Type> {
+        match typ {
+            DataType::Int(x) => Some(Type::Int(x.size)),
+            DataType::Uint(x) => Some(Type::Uint(x.size)),
+            DataType::Float(x) => Some(Type::Float(x.size)),
+            DataType::Bool(_) => Some(Type::Bool),
+            DataType::Function(_) => Some(Type::Func),
+            DataType::Array(_)
+            | DataType::Tuple(_)
+            | DataType::Enum(_)
+            | DataType::Optional(_)
+            | DataType::None
+            | DataType::Param(_)
+            | DataType::Struct(_)
+            | DataType::Dyn(_) => None,
+        }
+    }
+
+    pub fn is_int(self) -> bool {
+        matches!(self, Type::Int(_) | Type::Uint(_))
+    }
+
+    pub fn bits(self) -> u32 {
+        match self {
+            Type::Int(x) | Type::Uint(x) => x as u32,
+            Type::Float(x) => x as u32,
+            Type::Bool => 1,
+            Type::Func => 64,
+        }
+    }
+
+    // Whether an integer is in range for this type as it is, without wrapping it
+    pub fn holds(self, val: i128) -> bool {
+        let bits = self.bits();
+        match self {
+            _ if bits == 128 => self.is_int() && (val >= 0 || matches!(self, Type::Int(_))),
+            Type::Int(_) => (-(1 << (bits - 1))..1 << (bits - 1)).contains(&val),
+            Type::Uint(_) => (0..1 << bits).contains(&val),
+            _ => false,
+        }
+    }
+
+    // What a variable holds before anything has been assigned to it
+    pub fn zero(self) -> Op {
+        match self {
+            Type::Int(_) | Type::Uint(_) => Op::Const(Const::Int(0)),
+            Type::Float(_) => Op::Const(Const::Float(0.0)),
+            Type::Bool => Op::Const(Const::Bool(false)),
+            Type::Func => unreachable!("functions don't have a zero value"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Value(pub u32);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Block(pub u32);
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Function {
+    pub name: String,
+    pub params: Vec<(Value, Type)>,
+    pub ret: Option<Type>,
+    // The first block is where the function starts
+    pub blocks: Vec<BlockData>,
+    // One more than the highest value, so passes can make new ones
+    pub next_value: u32,
+}
+
+impl Function {
+    pub fn new(name: String, params: Vec<Type>, ret: Option<Type>) -> Function {
+        let params: Vec<(Value, Type)> = params
+            .into_iter()
+            .enumerate()
+            .map(|(idx, typ)| (Value(idx as u32), typ))
+            .collect();
+        Function {
+            name,
+            next_value: params.len() as u32,
+            params,
+            ret,
+            blocks: Vec::new(),
+        }
+    }
+
+    pub fn new_value(&mut self) -> Value {
+        self.next_value += 1;
+        Value(self.next_value - 1)
+    }
+
+    pub fn add_block(&mut self) -> Block {
+        self.blocks.push(BlockData::default());
+        Block(self.blocks.len() as u32 - 1)
+    }
+
+    // Every value with the type it was defined with
+    pub fn types(&self) -> HashMap<Value, Type> {
+        let mut types: HashMap<Value, Type> = self.params.iter().copied().collect();
+        for block in &self.blocks {
+            types.extend(block.insts.iter().filter_map(|x| x.dest));
+        }
+        types
+    }
+
+    // Points every use of a value in the map at what it maps to, following chains of them
+    pub fn replace_uses(&mut self, map: &HashMap<Value, Value>) {
+        let resolve = |mut val: Value| {
+            while let Some(&to) = map.get(&val) {
+                val = to;
+            }
+            val
+        };
+        for block in &mut self.blocks {
+            for inst in &mut block.insts {
+                for val in inst.op.operands_mut() {
+                    *val = resolve(*val);
+                }
+            }
+            for val in block.term.operands_mut() {
+                *val = resolve(*val);
+            }
+        }
+    }
+
+    // Drops the blocks that can't be reached from the entry, and numbers the rest in order.
+    // Gives how many were dropped
+    pub fn remove_dead_blocks(&mut self) -> usize {
+        let cfg = Cfg::new(self);
+        let mut map = HashMap::new();
+        for idx in 0..self.blocks.len() {
+            let block = Block(idx as u32);
+            if cfg.is_reachable(block) {
+                map.insert(block, Block(map.len() as u32));
+            }
+        }
+
+        let removed = self.blocks.len() - map.len();
+        let blocks = std::mem::take(&mut self.blocks);
+        for (idx, mut data) in blocks.into_iter().enumerate() {
+            if !map.contains_key(&Block(idx as u32)) {
+                continue;
+            }
+            for inst in &mut data.insts {
+                if let Op::Phi(incoming) = &mut inst.op {
+                    incoming.retain(|(pred, _)| map.contains_key(pred));
+                    for (pred, _) in incoming {
+                        *pred = map[pred];
+                    }
+                }
+            }
+            data.term = match data.term {
+                Terminator::Jump(to) => Terminator::Jump(map[&to]),
+                Terminator::Branch(cond, yes, no) => Terminator::Branch(cond, map[&yes], map[&no]),
+                term => term,
+            };
+            self.blocks.push(data);
+        }
+        removed
+    }
+
+    // A phi whose operands are all the same value, or itself, is just that value. Gives how
+    // many were removed
+    pub fn remove_trivial_phis(&mut self) -> usize {
+        let mut aliases: HashMap<Value, Value> = HashMap::new();
+        let resolve = |aliases: &HashMap<Value, Value>, mut val: Value| {
+            while let Some(&to) = aliases.get(&val) {
+                val = to;
+            }
+            val
+        };
+
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for block in &self.blocks {
+                for inst in &block.insts {
+                    let (Op::Phi(incoming), Some((phi, _))) = (&inst.op, inst.dest) else {
+                        continue;
+                    };
+                    if aliases.contains_key(&phi) {
+                        continue;
+                    }
+
+                    let mut same = None;
+                    let mut trivial = true;
+                    for &(_, val) in incoming {
+                        let val = resolve(&aliases, val);
+                        if val == phi || Some(val) == same {
+                            continue;
+                        }
+                        if same.is_some() {
+                            trivial = false;
+                            break;
+                        }
+                        same = Some(val);
+                    }
+
+                    if let (true, Some(same)) = (trivial, same) {
+                        aliases.insert(phi, same);
+                        changed = true;
+                    }
+                }
+            }
+        }
+
+        for block in &mut self.blocks {
+            block
+                .insts
+                .retain(|x| !x.dest.is_some_and(|(val, _)| aliases.contains_key(&val)));
+        }
+        self.replace_uses(&aliases);
+        aliases.len()
+    }
+
+    // Parameters first, then everything else in the order it's written
+    pub fn renumber(&mut self) {
+        let mut map: HashMap<Value, Value> = HashMap::new();
+        let mut next = 0;
+        let mut number = |map: &mut HashMap<Value, Value>, val: Value| {
+            *map.entry(val).or_insert_with(|| {
+                next += 1;
+                Value(next - 1)
+            })
+        };
+
+        for (val, _) in &mut self.params {
+            *val = number(&mut map, *val);
+        }
+        for block in &mut self.blocks {
+            for inst in &mut block.insts {
+                if let Some((val, _)) = &mut inst.dest {
+                    *val = number(&mut map, *val);
+                }
+            }
+        }
+        // Anything left over was never defined, the verifier will say so
+        for block in &mut self.blocks {
+            for inst in &mut block.insts {
+                for val in inst.op.operands_mut() {
+                    *val = number(&mut map, *val);
+                }
+            }
+            for val in block.term.operands_mut() {
+                *val = number(&mut map, *val);
+            }
+        }
+
+        self.next_value = next;
+    }
+}
+
+impl std::ops::Index<Block> for Function {
+    type Output = BlockData;
+
+    fn index(&self, block: Block) -> &BlockData {
+        &self.blocks[block.0 as usize]
+    }
+}
+
+impl std::ops::IndexMut<Block> for Function {
+    fn index_mut(&mut self, block: Block) -> &mut BlockData {
+        &mut self.blocks[block.0 as usize]
+    }
+}
+
+// Phis always come first, and every block ends by going somewhere else or returning
+#[derive(Debug, Clone, PartialEq)]
+pub struct BlockData {
+    pub insts: Vec<Inst>,
+    pub term: Terminator,
+}
+
+impl Default for BlockData {
+    fn default() -> BlockData {
+        BlockData {
+            insts: Vec::new(),
+            term: Terminator::Return(None),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Inst {
+    pub dest: Option<(Value, Type)>,
+    pub op: Op,
+}
+
+impl Inst {
+    pub fn new(dest: Option<(Value, Type)>, op: Op) -> Inst {
+        Inst { dest, op }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Op {
+    Const(Const),
+    // The address of a function, so it can be called later
+    Func(String),
+    Binary(BinOp, Value, Value),
+    Unary(UnOp, Value),
+    // The value from whichever block control came from
+    Phi(Vec<(Block, Value)>),
+    Call(Callee, Vec<Value>),
+    Load(String),
+    Store(String, Value),
+    // Where the code after it came from, a file and a line counted from one. Running it does
+    // nothing, it's there for backends that can point back at the source
+    Line(String, u32),
+}
+
+impl Op {
+    pub fn operands(&self) -> Vec<Value> {
+        match self {
+            Op::Const(_) | Op::Func(_) | Op::Load(_) | Op::Line(..) => Vec::new(),
+            Op::Binary(_, lhs, rhs) => vec![*lhs, *rhs],
+            Op::Unary(_, val) | Op::Store(_, val) => vec![*val],
+            Op::Phi(incoming) => incoming.iter().map(|(_, val)| *val).collect(),
+            Op::Call(callee, args) => match callee {
+                Callee::Direct(_) => args.clone(),
+                Callee::Indirect(func) => std::iter::once(*func).chain(args.clone()).collect(),
+            },
+        }
+    }
+
+    pub fn operands_mut(&mut self) -> Vec<&mut Value> {
+        match self {
+            Op::Const(_) | Op::Func(_) | Op::Load(_) | Op::Line(..) => Vec::new(),
+            Op::Binary(_, lhs, rhs) => vec![lhs, rhs],
+            Op::Unary(_, val) | Op::Store(_, val) => vec![val],
+            Op::Phi(incoming) => incoming.iter_mut().map(|(_, val)| val).collect(),
+            Op::Call(callee, args) => match callee {
+                Callee::Direct(_) => args.iter_mut().collect(),
+                Callee::Indirect(func) => std::iter::once(func).chain(args.iter_mut()).collect(),
+            },
+        }
+    }
+}
+
+// Integers are sign or zero extended from their own width, the type says which
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Const {
+    Int(i128),
+    Float(f64),
+    Bool(bool),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Callee {
+    Direct(String),
+    Indirect(Value),
+}
+
+// These mean the same as in the interpreter, traps included. Comparisons give a bool, the
+// rest give the type of their operands
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum BinOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Rem,
+    Pow,
+    Shl,
+    Shr,
+    Ushr,
+    And,
+    Or,
+    Xor,
+    Nand,
+    Nor,
+    Xand,
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+impl BinOp {
+    pub const VALUES: [BinOp; 21] = [
+        BinOp::Add,
+        BinOp::Sub,
+        BinOp::Mul,
+        BinOp::Div,
+        BinOp::Rem,
+        BinOp::Pow,
+        BinOp::Shl,
+        BinOp::Shr,
+        BinOp::Ushr,
+        BinOp::And,
+        BinOp::Or,
+        BinOp::Xor,
+        BinOp::Nand,
+        BinOp::Nor,
+        BinOp::Xand,
+        BinOp::Eq,
+        BinOp::Ne,
+        BinOp::Lt,
+        BinOp::Le,
+        BinOp::Gt,
+        BinOp::Ge,
+    ];
+
+    pub fn from(op: &tokens::Type) -> Option<BinOp> {
+        use tokens::Type as T;
+        Some(match op {
+            T::Addition => BinOp::Add,
+            T::Subtraction => BinOp::Sub,
+            T::Multiplication => BinOp::Mul,
+            T::Division => BinOp::Div,
+            T::Modulo => BinOp::Rem,
+            T::Exponentiation => BinOp::Pow,
+            T::LeftShift => BinOp::Shl,
+            T::RightShift => BinOp::Shr,
+            T::ZeroFillRightShift => BinOp::Ushr,
+            T::And => BinOp::And,
+            T::Or => BinOp::Or,
+            T::Xor => BinOp::Xor,
+            T::Nand => BinOp::Nand,
+            T::Nor => BinOp::Nor,
+            T::Xand => BinOp::Xand,
+            T::Equal => BinOp::Eq,
+            T::NotEqual => BinOp::Ne,
+            T::LessThan => BinOp::Lt,
+            T::LessThanOrEqual => BinOp::Le,
+            T::GreaterThan => BinOp::Gt,
+            T::GreaterThanOrEqual => BinOp::Ge,
+            _ => return None,
+        })
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            BinOp::Add => "add",
+            BinOp::Sub => "sub",
+            BinOp::Mul => "mul",
+            BinOp::Div => "div",
+            BinOp::Rem => "rem",
+            BinOp::Pow => "pow",
+            BinOp::Shl => "shl",
+            BinOp::Shr => "shr",
+            BinOp::Ushr => "ushr",
+            BinOp::And => "and",
+            BinOp::Or => "or",
+            BinOp::Xor => "xor",
+            BinOp::Nand => "nand",
+            BinOp::Nor => "nor",
+            BinOp::Xand => "xand",
+            BinOp::Eq => "eq",
+            BinOp::Ne => "ne",
+            BinOp::Lt => "lt",
+            BinOp::Le => "le",
+            BinOp::Gt => "gt",
+            BinOp::Ge => "ge",
+        }
+    }
+
+    pub fn is_comparison(self) -> bool {
+        matches!(
+            self,
+            BinOp::Eq | BinOp::Ne | BinOp::Lt | BinOp::Le | BinOp::Gt | BinOp::Ge
+        )
+    }
+
+    // Whether the operands can have this type
+    pub fn allows(self, typ: Type) -> bool {
+        match self {
+            BinOp::Eq | BinOp::Ne => true,
+            BinOp::Lt | BinOp::Le | BinOp::Gt | BinOp::Ge => typ != Type::Func,
+            BinOp::Add | BinOp::Sub | BinOp::Mul | BinOp::Div | BinOp::Rem | BinOp::Pow => {
+                typ.is_int() || matches!(typ, Type::Float(_))
+            }
+            BinOp::Shl | BinOp::Shr | BinOp::Ushr => typ.is_int(),
+            BinOp::And | BinOp::Or | BinOp::Xor | BinOp::Nand | BinOp::Nor | BinOp::Xand => {
+                typ.is_int() || typ == Type::Bool
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum UnOp {
+    Neg,
+    Not,
+    Clz,
+    Ctz,
+}
+
+impl UnOp {
+    pub const VALUES: [UnOp; 4] = [UnOp::Neg, UnOp::Not, UnOp::Clz, UnOp::Ctz];
+
+    pub fn from(op: &tokens::Type) -> Option<UnOp> {
+        use tokens::Type as T;
+        Some(match op {
+            T::Subtraction => UnOp::Neg,
+            T::Not => UnOp::Not,
+            T::CountLeadingZeros => UnOp::Clz,
+            T::CountTrailingZeros => UnOp::Ctz,
+            _ => return None,
+        })
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            UnOp::Neg => "neg",
+            UnOp::Not => "not",
+            UnOp::Clz => "clz",
+            UnOp::Ctz => "ctz",
+        }
+    }
+
+    pub fn allows(self, typ: Type) -> bool {
+        match self {
+            UnOp::Neg => typ.is_int() || matches!(typ, Type::Float(_)),
+            UnOp::Not => typ.is_int() || typ == Type::Bool,
+            UnOp::Clz | UnOp::Ctz => typ.is_int(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Terminator {
+    Jump(Block),
+    Branch(Value, Block, Block),
+    Return(Option<Value>),
+}
+
+impl Terminator {
+    pub fn successors(&self) -> Vec<Block> {
+        match self {
+            Terminator::Jump(to) => vec![*to],
+            Terminator::Branch(_, yes, no) => vec![*yes, *no],
+            Terminator::Return(_) => Vec::new(),
+        }
+    }
+
+    pub fn operands(&self) -> Vec<Value> {
+        match self {
+            Terminator::Branch(cond, _, _) => vec![*cond],
+            Terminator::Return(Some(val)) => vec![*val],
+            _ => Vec::new(),
+        }
+    }
+
+    pub fn operands_mut(&mut self) -> Vec<&mut Value> {
+        match self {
+            Terminator::Branch(cond, _, _) => vec![cond],
+            Terminator::Return(Some(val)) => vec![val],
+            _ => Vec::new(),
+        }
+    }
+
+    pub fn successors_mut(&mut self) -> Vec<&mut Block> {
+        match self {
+            Terminator::Jump(to) => vec![to],
+            Terminator::Branch(_, yes, no) => vec![yes, no],
+            Terminator::Return(_) => Vec::new(),
+        }
+    }
+}
@@ -0,0 +1,142 @@
+use super::{Block, Function};
+
+// How the blocks of a function connect. Blocks that can't be reached from the entry have no
+// dominator and are left out of the order
+#[derive(Debug, Clone)]
+pub struct Cfg {
+    pub preds: Vec<Vec<Block>>,
+    pub succs: Vec<Vec<Block>>,
+    // Reverse postorder, so a block comes before everything it dominates
+    pub order: Vec<Block>,
+    // The closest block every path from the entry goes through, the entry has none
+    pub idom: Vec<Option<Block>>,
+}
+
+impl Cfg {
+    pub fn new(func: &Function) -> Cfg {
+        let count = func.blocks.len();
+        let mut preds: Vec<Vec<Block>> = vec![Vec::new(); count];
+        let mut succs: Vec<Vec<Block>> = vec![Vec::new(); count];
+        for (idx, block) in func.blocks.iter().enumerate() {
+            for to in block.term.successors() {
+                // A branch with both sides going to the same block is still only one edge
+                if (to.0 as usize) < count && !succs[idx].contains(&to) {
+                    succs[idx].push(to);
+                    preds[to.0 as usize].push(Block(idx as u32));
+                }
+            }
+        }
+
+        let order = match count {
+            0 => Vec::new(),
+            _ => reverse_postorder(&succs),
+        };
+        let idom = dominators(&preds, &order, count);
+
+        Cfg {
+            preds,
+            succs,
+            order,
+            idom,
+        }
+    }
+
+    pub fn preds(&self, block: Block) -> &[Block] {
+        &self.preds[block.0 as usize]
+    }
+
+    pub fn succs(&self, block: Block) -> &[Block] {
+        &self.succs[block.0 as usize]
+    }
+
+    pub fn is_reachable(&self, block: Block) -> bool {
+        block.0 == 0 || self.idom[block.0 as usize].is_some()
+    }
+
+    // Every block dominates itself
+    pub fn dominates(&self, block: Block, other: Block) -> bool {
+        let mut cur = Some(other);
+        while let Some(at) = cur {
+            if at == block {
+                return true;
+            }
+            cur = self.idom[at.0 as usize];
+        }
+        false
+    }
+}
+
+fn reverse_postorder(succs: &[Vec<Block>]) -> Vec<Block> {
+    let mut seen = vec![false; succs.len()];
+    let mut order = Vec::new();
+    // Each entry is a block and how many of its successors have been looked at
+    let mut stack = vec![(Block(0), 0)];
+    seen[0] = true;
+    while let Some((block, next)) = stack.pop() {
+        match succs[block.0 as usize].get(next) {
+            Some(&to) => {
+                stack.push((block, next + 1));
+                if !seen[to.0 as usize] {
+                    seen[to.0 as usize] = true;
+                    stack.push((to, 0));
+                }
+            }
+            None => order.push(block),
+        }
+    }
+
+    order.reverse();
+    order
+}
+
+// From "A Simple, Fast Dominance Algorithm" by Cooper, Harvey and Kennedy
+fn dominators(preds: &[Vec<Block>], order: &[Block], count: usize) -> Vec<Option<Block>> {
+    let mut index = vec![usize::MAX; count];
+    for (idx, block) in order.iter().enumerate() {
+        index[block.0 as usize] = idx;
+    }
+
+    let mut idom: Vec<Option<Block>> = vec![None; count];
+    if count == 0 {
+        return idom;
+    }
+    idom[0] = Some(Block(0));
+
+    let intersect = |idom: &[Option<Block>], mut x: Block, mut y: Block| {
+        while x != y {
+            while index[x.0 as usize] > index[y.0 as usize] {
+                x = idom[x.0 as usize].unwrap();
+            }
+            while index[y.0 as usize] > index[x.0 as usize] {
+                y = idom[y.0 as usize].unwrap();
+            }
+        }
+        x
+    };
+
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for &block in &order[1..] {
+            let mut new = None;
+            for &pred in &preds[block.0 as usize] {
+                if idom[pred.0 as usize].is_none() {
+                    continue;
+                }
+                new = Some(match new {
+                    None => pred,
+                    Some(cur) => intersect(&idom, pred, cur),
+                });
+            }
+
+            if new.is_some() && idom[block.0 as usize] != new {
+                idom[block.0 as usize] = new;
+                changed = true;
+            }
+        }
+    }
+
+    // The entry was only its own dominator to keep the walks above from going past it
+    idom[0] = None;
+    idom
+}
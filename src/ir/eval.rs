@@ -0,0 +1,266 @@
+use std::{collections::HashMap, fmt};
+
+use super::{BinOp, Callee, Const, Function, Module, Op, Terminator, Type, UnOp, Value};
+
+// How deep calls can go before it counts as running out of stack, like the interpreter's budget
+pub const MAX_DEPTH: usize = 1000;
+
+// What a value holds while the module runs
+#[derive(Debug, Clone, PartialEq)]
+pub enum Val {
+    Int(i128),
+    Float(f64),
+    Bool(bool),
+    Func(String),
+}
+
+impl From<Const> for Val {
+    fn from(val: Const) -> Val {
+        match val {
+            Const::Int(x) => Val::Int(x),
+            Const::Float(x) => Val::Float(x),
+            Const::Bool(x) => Val::Bool(x),
+        }
+    }
+}
+
+impl fmt::Display for Val {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Val::Int(x) => write!(f, "{}", x),
+            Val::Float(x) => write!(f, "{}", x),
+            Val::Bool(x) => write!(f, "{}", x),
+            Val::Func(x) => write!(f, "func @{}", x),
+        }
+    }
+}
+
+// Runs a module straight from its blocks. It means the same as the interpreter running the
+// program the module came from, which is what lets optimized code be checked against the
+// code it came from
+pub struct Machine<'a> {
+    module: &'a Module,
+    pub globals: HashMap<String, Val>,
+    depth: usize,
+}
+
+impl<'a> Machine<'a> {
+    pub fn new(module: &'a Module) -> Machine<'a> {
+        let globals = module
+            .globals
+            .iter()
+            .map(|x| (x.name.clone(), zero(x.typ)))
+            .collect();
+        Machine {
+            module,
+            globals,
+            depth: 0,
+        }
+    }
+
+    // The error is what went wrong, like dividing by zero
+    pub fn call(&mut self, name: &str, args: Vec<Val>) -> Result<Option<Val>, String> {
+        let Some(func) = self.module.func(name) else {
+            return Err(format!("@{} isn't a function", name));
+        };
+        if self.depth == MAX_DEPTH {
+            return Err(format!("Recursed too deep, after {} calls", MAX_DEPTH));
+        }
+
+        self.depth += 1;
+        let res = self.run(func, args);
+        self.depth -= 1;
+        res
+    }
+
+    fn run(&mut self, func: &Function, args: Vec<Val>) -> Result<Option<Val>, String> {
+        let mut vals: Vec<Option<Val>> = vec![None; func.next_value as usize];
+        for (&(param, _), arg) in func.params.iter().zip(args) {
+            vals[param.0 as usize] = Some(arg);
+        }
+        let types = func.types();
+
+        let mut prev = None;
+        let mut block = super::Block(0);
+        loop {
+            let data = &func[block];
+            let get = |vals: &[Option<Val>], val: Value| vals[val.0 as usize].clone().unwrap();
+
+            // Phis all read the values from before any of them were set
+            let phis: Vec<(Value, Val)> = data
+                .insts
+                .iter()
+                .filter_map(|inst| match &inst.op {
+                    Op::Phi(incoming) => {
+                        let (_, val) = incoming.iter().find(|(x, _)| Some(*x) == prev)?;
+                        Some((inst.dest?.0, get(&vals, *val)))
+                    }
+                    _ => None,
+                })
+                .collect();
+            for (dest, val) in phis {
+                vals[dest.0 as usize] = Some(val);
+            }
+
+            for inst in &data.insts {
+                let val = match &inst.op {
+                    Op::Phi(_) => continue,
+                    Op::Const(x) => Some(Val::from(*x)),
+                    Op::Func(name) => Some(Val::Func(name.clone())),
+                    Op::Binary(op, lhs, rhs) => {
+                        let typ = types[lhs];
+                        Some(binary(*op, typ, &get(&vals, *lhs), &get(&vals, *rhs))?)
+                    }
+                    Op::Unary(op, val) => Some(unary(*op, types[val], &get(&vals, *val))),
+                    Op::Call(callee, args) => {
+                        let name = match callee {
+                            Callee::Direct(name) => name.clone(),
+                            Callee::Indirect(val) => match get(&vals, *val) {
+                                Val::Func(name) => name,
+                                val => unreachable!("called {}, which isn't a function", val),
+                            },
+                        };
+                        let args = args.iter().map(|&x| get(&vals, x)).collect();
+                        self.call(&name, args)?
+                    }
+                    Op::Load(name) => Some(self.globals[name].clone()),
+                    Op::Store(name, val) => {
+                        self.globals.insert(name.clone(), get(&vals, *val));
+                        None
+                    }
+                    Op::Line(..) => None,
+                };
+                if let (Some((dest, _)), Some(val)) = (inst.dest, val) {
+                    vals[dest.0 as usize] = Some(val);
+                }
+            }
+
+            prev = Some(block);
+            block = match data.term {
+                Terminator::Jump(to) => to,
+                Terminator::Branch(cond, yes, no) => match get(&vals, cond) {
+                    Val::Bool(true) => yes,
+                    _ => no,
+                },
+                Terminator::Return(val) => return Ok(val.map(|x| get(&vals, x))),
+            };
+        }
+    }
+}
+
+fn zero(typ: Type) -> Val {
+    match typ {
+        Type::Int(_) | Type::Uint(_) => Val::Int(0),
+        Type::Float(_) => Val::Float(0.0),
+        Type::Bool => Val::Bool(false),
+        // Only ever read after something is stored, like any other variable
+        Type::Func => Val::Func(String::new()),
+    }
+}
+
+// Integers are kept sign or zero extended from their own width, the same as the interpreter
+fn wrap(val: i128, typ: Type) -> i128 {
+    let bits = typ.bits();
+    match typ {
+        _ if bits == 128 => val,
+        Type::Uint(_) => val & ((1 << bits) - 1),
+        _ => (val << (128 - bits)) >> (128 - bits),
+    }
+}
+
+// The operands have the type, and the error is the trap. Constant folding goes through here too,
+// so a fold can never disagree with running the code
+pub fn binary(op: BinOp, typ: Type, lhs: &Val, rhs: &Val) -> Result<Val, String> {
+    let val = match (lhs, rhs) {
+        (&Val::Int(x), &Val::Int(y)) => match op {
+            BinOp::Add => Val::Int(wrap(x.wrapping_add(y), typ)),
+            BinOp::Sub => Val::Int(wrap(x.wrapping_sub(y), typ)),
+            BinOp::Mul => Val::Int(wrap(x.wrapping_mul(y), typ)),
+            BinOp::Div | BinOp::Rem if y == 0 => return Err("Division by zero".to_owned()),
+            BinOp::Div => Val::Int(wrap(x.wrapping_div(y), typ)),
+            BinOp::Rem => Val::Int(wrap(x.wrapping_rem(y), typ)),
+            BinOp::Pow if y < 0 => return Err("Negative exponent".to_owned()),
+            BinOp::Pow => {
+                let exp = u32::try_from(y).unwrap_or(u32::MAX);
+                Val::Int(wrap(x.wrapping_pow(exp), typ))
+            }
+            BinOp::Shl | BinOp::Shr | BinOp::Ushr if y < 0 || y >= typ.bits() as i128 => {
+                return Err("Shifted by more than the width of the type".to_owned())
+            }
+            BinOp::Shl => Val::Int(wrap(x << y, typ)),
+            BinOp::Shr => Val::Int(x >> y),
+            BinOp::Ushr => {
+                let unsigned = match typ.bits() {
+                    128 => x as u128,
+                    bits => (x as u128) & ((1 << bits) - 1),
+                };
+                Val::Int(wrap((unsigned >> y) as i128, typ))
+            }
+            BinOp::And => Val::Int(x & y),
+            BinOp::Or => Val::Int(x | y),
+            BinOp::Xor => Val::Int(x ^ y),
+            BinOp::Nand => Val::Int(wrap(!(x & y), typ)),
+            BinOp::Nor => Val::Int(wrap(!(x | y), typ)),
+            BinOp::Xand => Val::Int(wrap(!(x ^ y), typ)),
+            _ => Val::Bool(compare(op, x.cmp(&y))),
+        },
+        (&Val::Float(x), &Val::Float(y)) => match op {
+            BinOp::Add => Val::Float(x + y),
+            BinOp::Sub => Val::Float(x - y),
+            BinOp::Mul => Val::Float(x * y),
+            BinOp::Div => Val::Float(x / y),
+            BinOp::Rem => Val::Float(x % y),
+            BinOp::Pow => Val::Float(x.powf(y)),
+            _ => match x.partial_cmp(&y) {
+                Some(ord) => Val::Bool(compare(op, ord)),
+                // NaN is only ever not equal
+                None => Val::Bool(op == BinOp::Ne),
+            },
+        },
+        (&Val::Bool(x), &Val::Bool(y)) => match op {
+            BinOp::And => Val::Bool(x & y),
+            BinOp::Or => Val::Bool(x | y),
+            BinOp::Xor => Val::Bool(x ^ y),
+            BinOp::Nand => Val::Bool(!(x & y)),
+            BinOp::Nor => Val::Bool(!(x | y)),
+            BinOp::Xand => Val::Bool(x == y),
+            _ => Val::Bool(compare(op, x.cmp(&y))),
+        },
+        (x, y) => match op {
+            BinOp::Eq => Val::Bool(x == y),
+            BinOp::Ne => Val::Bool(x != y),
+            _ => unreachable!("the verifier doesn't allow {} on {} and {}", op, x, y),
+        },
+    };
+
+    Ok(val)
+}
+
+fn compare(op: BinOp, ord: std::cmp::Ordering) -> bool {
+    match op {
+        BinOp::Eq => ord.is_eq(),
+        BinOp::Ne => ord.is_ne(),
+        BinOp::Lt => ord.is_lt(),
+        BinOp::Le => ord.is_le(),
+        BinOp::Gt => ord.is_gt(),
+        BinOp::Ge => ord.is_ge(),
+        _ => unreachable!("{} is not a comparison", op),
+    }
+}
+
+pub fn unary(op: UnOp, typ: Type, val: &Val) -> Val {
+    match (op, val) {
+        (UnOp::Neg, &Val::Int(x)) => Val::Int(wrap(x.wrapping_neg(), typ)),
+        (UnOp::Neg, &Val::Float(x)) => Val::Float(-x),
+        (UnOp::Not, &Val::Int(x)) => Val::Int(wrap(!x, typ)),
+        (UnOp::Not, &Val::Bool(x)) => Val::Bool(!x),
+        // Only the bits that are part of the type get counted
+        (UnOp::Clz, &Val::Int(x)) => {
+            let bits = typ.bits();
+            let unsigned = (x as u128) << (128 - bits);
+            Val::Int(unsigned.leading_zeros().min(bits) as i128)
+        }
+        (UnOp::Ctz, &Val::Int(x)) => Val::Int((x as u128).trailing_zeros().min(typ.bits()) as i128),
+        (op, val) => unreachable!("the verifier doesn't allow {} on {}", op, val),
+    }
+}
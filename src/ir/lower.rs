@@ -0,0 +1,939 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::{
+    ast::{
+        self,
+        visit::walk::{self, Visitor},
+        Ast, Block as AstBlock, Expr, Ident, Node, NodeId, NodeKind, Param, Path, Pattern, Stmt,
+    },
+    interpreter,
+    module::ModuleGraph,
+    program::{Instance, Program},
+    symbols::{SymbolId, SymbolKind},
+    types::DataType,
+};
+
+use super::{
+    parse::DELIMITERS, BinOp, Block, Callee, Const, Function, Global, Inst, Module, Op, Terminator,
+    Type, UnOp, Value,
+};
+
+// Runs the top level code of every module, dependencies first
+pub const ENTRY: &str = "main";
+
+// Turns a checked program into SSA. Variables are values that get redefined on every assignment,
+// with phis wherever control flow joins. Only variables that some other function or module
+// can see get a global, a function that owns one saves it on entry and puts it back when it
+// returns, so recursive calls still see their own copy
+pub fn lower(graph: &ModuleGraph) -> Result<Module, String> {
+    // Only the interpreter knows what to do with values that don't fit in one IR value, or with
+    // methods. A trait's statement comes after its Self in the arena, so those go first
+    for &id in &graph.order {
+        let prog = &graph.modules[id].prog;
+        for node in prog.ast.ids() {
+            if let NodeKind::Stmt(Stmt::Trait(_) | Stmt::Impl(_)) = prog.ast.kind(node) {
+                return Err(format!(
+                    "traits and impls can't be compiled yet, only interpreted ({})",
+                    graph.reporter.map.format(prog.ast.span(node))
+                ));
+            }
+        }
+    }
+    let mut scan = Scan {
+        prog: &graph.root().prog,
+        module: 0,
+        prefix: String::new(),
+        owner: Owner::Top(0),
+        names: HashSet::from([ENTRY.to_owned()]),
+        funcs: HashMap::new(),
+        order: Vec::new(),
+        owners: HashMap::new(),
+        uses: Vec::new(),
+        generic: false,
+        nested: None,
+    };
+    for &id in &graph.order {
+        scan.module = id;
+        scan.prog = &graph.modules[id].prog;
+        scan.prefix = graph.modules[id].name.clone();
+        scan.owner = Owner::Top(id);
+        scan.visit_block(scan.prog.root);
+        if let Some(stmt) = scan.nested {
+            return Err(format!(
+                "functions inside of generic functions can't be compiled yet, only interpreted ({})",
+                graph.reporter.map.format(scan.prog.ast.span(stmt))
+            ));
+        }
+    }
+
+    // A generic function is only lowered as its instances, which have every type filled in. Those
+    // are in the list of the module that called it, but the code is where it was declared
+    for &id in &graph.order {
+        let prog = &graph.modules[id].prog;
+        let instances = prog.instances.iter().map(|x| {
+            let decl = &graph.modules[scan.funcs[&x.func].module].prog;
+            (decl, &x.types)
+        });
+        for (prog, types) in std::iter::once((prog, &prog.types)).chain(instances) {
+            for node in prog.ast.ids() {
+                match types.get(node) {
+                    Some(typ) if !typ.is_generic() && Type::from(typ).is_none() => {
+                        return Err(format!(
+                            "{} can't be compiled yet, only interpreted ({})",
+                            typ,
+                            graph.reporter.map.format(prog.ast.span(node))
+                        ));
+                    }
+                    _ => (),
+                }
+            }
+        }
+    }
+
+    // Every instance gets its own function, named after the types it was made for
+    let mut instances = HashMap::new();
+    for &id in &graph.order {
+        for (idx, instance) in graph.modules[id].prog.instances.iter().enumerate() {
+            let args: Vec<String> = instance.args.iter().map(|x| x.to_string()).collect();
+            let name = format!("{}.{}", scan.funcs[&instance.func].name, args.join("."));
+            instances.insert((id, idx), unique(&mut scan.names, &name));
+        }
+    }
+
+    // Anything used outside of the function or module that declared it
+    let mut captured: Vec<SymbolId> = Vec::new();
+    for &(id, owner) in &scan.uses {
+        if scan.owners.get(&id).is_some_and(|x| *x != owner) && !captured.contains(&id) {
+            captured.push(id);
+        }
+    }
+
+    let mut globals = HashMap::new();
+    let mut module = Module::default();
+    let symbols = graph.symbols.borrow();
+    captured.sort();
+    for id in captured {
+        let symbol = symbols.get(id);
+        let prefix = match scan.owners[&id] {
+            Owner::Top(module) => graph.modules[module].name.clone(),
+            Owner::Func(func) => scan.funcs[&func].name.clone(),
+        };
+        let name = unique(&mut scan.names, &format!("{}.{}", prefix, symbol.name));
+        let typ = Type::from(symbol.typ.as_ref().unwrap()).unwrap();
+        module.globals.push(Global {
+            name: name.clone(),
+            typ,
+        });
+        globals.insert(id, name);
+    }
+    drop(symbols);
+
+    let info = Info {
+        graph,
+        funcs: &scan.funcs,
+        globals: &globals,
+        owners: &scan.owners,
+        instances: &instances,
+    };
+
+    let mut entry = Builder::new(
+        &info,
+        &graph.root().prog,
+        Function::new(ENTRY.to_owned(), Vec::new(), None),
+    );
+    for &id in &graph.order {
+        entry.module = id;
+        entry.prog = &graph.modules[id].prog;
+        entry.top_level();
+    }
+    module.funcs.push(entry.finish());
+
+    for func in &scan.order {
+        let decl = &scan.funcs[func];
+        let prog = &graph.modules[decl.module].prog;
+        let Stmt::Func(ast_func) = &prog.ast[decl.stmt] else {
+            unreachable!("functions always point at a func statement");
+        };
+        let Some(DataType::Function(typ)) = prog.types.get(ast_func.name) else {
+            unreachable!("the checker gives every function a type");
+        };
+
+        let params = typ.params.iter().map(|x| Type::from(x).unwrap()).collect();
+        let ret = typ.ret.as_ref().map(|x| Type::from(x).unwrap());
+        let mut builder = Builder::new(&info, prog, Function::new(decl.name.clone(), params, ret));
+        builder.module = decl.module;
+        builder.function(*func, ast_func);
+        module.funcs.push(builder.finish());
+    }
+
+    for &id in &graph.order {
+        for (idx, instance) in graph.modules[id].prog.instances.iter().enumerate() {
+            let decl = &scan.funcs[&instance.func];
+            let prog = &graph.modules[decl.module].prog;
+            let Stmt::Func(ast_func) = &prog.ast[decl.stmt] else {
+                unreachable!("functions always point at a func statement");
+            };
+
+            // The instance has the types of the parameters and everything else it declares
+            let typ = |node: NodeId| Type::from(instance.types.get(node).unwrap()).unwrap();
+            let params = ast_func
+                .params
+                .iter()
+                .map(|&x| typ(prog.ast[x].name.into()))
+                .collect();
+            let ret = ast_func.ret.map(|x| typ(x.into()));
+            let name = instances[&(id, idx)].clone();
+            let mut builder = Builder::new(&info, prog, Function::new(name, params, ret));
+            builder.module = id;
+            builder.view = Some(instance);
+            for node in prog.ast.ids() {
+                let NodeKind::Ident(_) = prog.ast.kind(node) else {
+                    continue;
+                };
+                let found = (
+                    prog.names.get(node),
+                    instance.types.get(node).and_then(Type::from),
+                );
+                if let (Some(&var), Some(typ)) = found {
+                    builder.locals.insert(var, typ);
+                }
+            }
+            builder.function(instance.func, ast_func);
+            module.funcs.push(builder.finish());
+        }
+    }
+
+    Ok(module)
+}
+
+// Names end up in the text format, so they can't have anything the parser would stop at
+fn unique(names: &mut HashSet<String>, name: &str) -> String {
+    let name: String = name
+        .chars()
+        .map(|c| match c.is_whitespace() || DELIMITERS.contains(c) {
+            true => '_',
+            false => c,
+        })
+        .collect();
+
+    let mut candidate = name.clone();
+    let mut count = 1;
+    while !names.insert(candidate.clone()) {
+        candidate = format!("{}.{}", name, count);
+        count += 1;
+    }
+    candidate
+}
+
+// Whose code a name is declared or used in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Owner {
+    Top(usize),
+    Func(SymbolId),
+}
+
+struct FuncDecl {
+    name: String,
+    module: usize,
+    stmt: Node<Stmt>,
+}
+
+// Finds every function, and where every variable is declared and used
+struct Scan<'a> {
+    prog: &'a Program,
+    module: usize,
+    prefix: String,
+    owner: Owner,
+    names: HashSet<String>,
+    funcs: HashMap<SymbolId, FuncDecl>,
+    // The functions in the order they were found, so the output doesn't depend on hashing
+    order: Vec<SymbolId>,
+    owners: HashMap<SymbolId, Owner>,
+    uses: Vec<(SymbolId, Owner)>,
+    // Inside a generic function, and the first function found in one
+    generic: bool,
+    nested: Option<Node<Stmt>>,
+}
+
+impl Scan<'_> {
+    fn symbol(&self, node: impl Into<NodeId>) -> Option<SymbolId> {
+        self.prog.names.get(node).copied()
+    }
+
+    fn declare(&mut self, name: Node<Ident>) {
+        if let Some(id) = self.symbol(name) {
+            self.owners.insert(id, self.owner);
+        }
+    }
+
+    fn used(&mut self, node: impl Into<NodeId>) {
+        if let Some(id) = self.symbol(node) {
+            self.uses.push((id, self.owner));
+        }
+    }
+}
+
+impl<'a> Visitor<'a> for Scan<'a> {
+    fn ast(&self) -> &'a Ast {
+        &self.prog.ast
+    }
+
+    fn visit_func(&mut self, func: &'a ast::Func, stmt: Node<Stmt>) {
+        let id = self.symbol(func.name).unwrap();
+        let name = format!("{}.{}", self.prefix, self.prog.ast[func.name].name);
+        let name = unique(&mut self.names, &name);
+        self.funcs.insert(
+            id,
+            FuncDecl {
+                name: name.clone(),
+                module: self.module,
+                stmt,
+            },
+        );
+        if self.generic {
+            self.nested.get_or_insert(stmt);
+        }
+        // Generic functions only get lowered as their instances
+        match func.generics.is_empty() {
+            true => self.order.push(id),
+            false => self.generic = true,
+        }
+
+        let prefix = std::mem::replace(&mut self.prefix, name);
+        let owner = std::mem::replace(&mut self.owner, Owner::Func(id));
+        walk::walk_func(self, func);
+        self.prefix = prefix;
+        self.owner = owner;
+        if !func.generics.is_empty() {
+            self.generic = false;
+        }
+    }
+
+    fn visit_param(&mut self, param: Node<Param>) {
+        self.declare(self.prog.ast[param].name);
+    }
+
+    fn visit_decl(&mut self, decl: &'a ast::Decl, _stmt: Node<Stmt>) {
+        self.visit_expr(decl.val);
+        self.declare(decl.name);
+    }
+
+    fn visit_assign(&mut self, assign: &'a ast::Assign, _stmt: Node<Stmt>) {
+        self.used(assign.name);
+        self.visit_expr(assign.val);
+    }
+
+    fn visit_pattern(&mut self, pattern: Node<Pattern>) {
+        if let Pattern::Binding(name) = self.prog.ast[pattern] {
+            self.declare(name);
+        }
+        walk::walk_pattern(self, pattern);
+    }
+
+    fn visit_ident_expr(&mut self, ident: Node<Ident>, _expr: Node<Expr>) {
+        self.used(ident);
+    }
+
+    fn visit_path_expr(&mut self, path: Node<Path>, _expr: Node<Expr>) {
+        self.used(path);
+    }
+}
+
+// What every function being lowered needs to know about the others
+struct Info<'a> {
+    graph: &'a ModuleGraph,
+    funcs: &'a HashMap<SymbolId, FuncDecl>,
+    globals: &'a HashMap<SymbolId, String>,
+    owners: &'a HashMap<SymbolId, Owner>,
+    // The function of each instance, by the module it's listed in and its index there
+    instances: &'a HashMap<(usize, usize), String>,
+}
+
+// Builds one function a block at a time, putting phis in as variables are read. This is the
+// algorithm from "Simple and Efficient Construction of Static Single Assignment Form" by Braun
+// et al. A block is sealed once every jump into it is known, until then reading a variable
+// there gives a phi that gets its operands when the block is sealed
+struct Builder<'a> {
+    info: &'a Info<'a>,
+    prog: &'a Program,
+    // Which module's list of instances the calls point into, and the instance being lowered,
+    // whose types and calls are used instead of the program's
+    module: usize,
+    view: Option<&'a Instance>,
+    // The types of variables declared in that instance
+    locals: HashMap<SymbolId, Type>,
+    func: Function,
+    cur: Block,
+    terms: Vec<Option<Terminator>>,
+    preds: Vec<Vec<Block>>,
+    sealed: HashSet<Block>,
+    defs: HashMap<(SymbolId, Block), Value>,
+    phis: Vec<Vec<(Value, Type)>>,
+    incoming: HashMap<Value, Vec<(Block, Value)>>,
+    incomplete: HashMap<Block, Vec<(SymbolId, Value)>>,
+    // Where continue goes, the innermost loop is last
+    loops: Vec<Block>,
+    // Top level code has nowhere to return to, so continue outside a loop goes on with the next
+    // statement and return skips the rest of the module, like in the interpreter
+    top: bool,
+    next_stmt: Option<Block>,
+    module_end: Option<Block>,
+    // Globals this function owns and their values from before it was called
+    saved: Vec<(String, Value)>,
+    // The last line given in the current block
+    marked: Option<Op>,
+}
+
+impl<'a> Builder<'a> {
+    fn new(info: &'a Info<'a>, prog: &'a Program, func: Function) -> Builder<'a> {
+        let mut builder = Builder {
+            info,
+            prog,
+            module: 0,
+            view: None,
+            locals: HashMap::new(),
+            func,
+            cur: Block(0),
+            terms: Vec::new(),
+            preds: Vec::new(),
+            sealed: HashSet::new(),
+            defs: HashMap::new(),
+            phis: Vec::new(),
+            incoming: HashMap::new(),
+            incomplete: HashMap::new(),
+            loops: Vec::new(),
+            top: false,
+            next_stmt: None,
+            module_end: None,
+            saved: Vec::new(),
+            marked: None,
+        };
+        let entry = builder.new_block();
+        builder.seal(entry);
+        builder
+    }
+
+    fn new_block(&mut self) -> Block {
+        self.terms.push(None);
+        self.preds.push(Vec::new());
+        self.phis.push(Vec::new());
+        self.func.add_block()
+    }
+
+    fn symbol(&self, node: impl Into<NodeId>) -> SymbolId {
+        *self
+            .prog
+            .names
+            .get(node)
+            .expect("the program wasn't resolved")
+    }
+
+    fn var_type(&self, id: SymbolId) -> Type {
+        if let Some(&typ) = self.locals.get(&id) {
+            return typ;
+        }
+        let symbols = self.info.graph.symbols.borrow();
+        Type::from(symbols.get(id).typ.as_ref().unwrap()).unwrap()
+    }
+
+    // Inside an instance the program's types still have the type parameters in them
+    fn data_type(&self, node: impl Into<NodeId>) -> Option<&'a DataType> {
+        match self.view {
+            Some(view) => view.types.get(node),
+            None => self.prog.types.get(node),
+        }
+    }
+
+    fn expr_type(&self, expr: Node<Expr>) -> Option<Type> {
+        self.data_type(expr).map(|x| Type::from(x).unwrap())
+    }
+
+    // The function a call to a generic function runs, calls to anything else don't have one
+    fn instance(&self, call: Node<Expr>) -> Option<String> {
+        let calls = match self.view {
+            Some(view) => &view.calls,
+            None => &self.prog.calls,
+        };
+        let idx = *calls.get(call)?;
+        Some(self.info.instances[&(self.module, idx)].clone())
+    }
+
+    fn push(&mut self, typ: Option<Type>, op: Op) -> Option<Value> {
+        let dest = typ.map(|typ| (self.func.new_value(), typ));
+        self.func[self.cur].insts.push(Inst::new(dest, op));
+        dest.map(|x| x.0)
+    }
+
+    fn value(&mut self, typ: Type, op: Op) -> Value {
+        self.push(Some(typ), op).unwrap()
+    }
+
+    fn terminate(&mut self, term: Terminator) {
+        if self.terms[self.cur.0 as usize].is_some() {
+            return;
+        }
+        for to in term.successors() {
+            self.preds[to.0 as usize].push(self.cur);
+        }
+        self.terms[self.cur.0 as usize] = Some(term);
+    }
+
+    fn jump(&mut self, to: Block) {
+        self.terminate(Terminator::Jump(to));
+    }
+
+    fn switch_to(&mut self, block: Block) {
+        self.cur = block;
+        self.marked = None;
+    }
+
+    // Says which line the code after this came from, unless the block already did
+    fn mark(&mut self, node: impl Into<NodeId>) {
+        let map = &self.info.graph.reporter.map;
+        let span = self.prog.ast.span(node);
+        let line = Op::Line(map.get(span.file).path.clone(), map.start(span).row + 1);
+        if self.marked.as_ref() == Some(&line) {
+            return;
+        }
+        self.marked = Some(line.clone());
+
+        // A line with no code after it says nothing
+        let insts = &mut self.func[self.cur].insts;
+        match insts.last_mut() {
+            Some(last) if matches!(last.op, Op::Line(..)) => last.op = line,
+            _ => {
+                self.push(None, line);
+            }
+        }
+    }
+
+    // Only made once something jumps there
+    fn lazy_block(&mut self, slot: fn(&mut Self) -> &mut Option<Block>) -> Block {
+        match *slot(self) {
+            Some(block) => block,
+            None => {
+                let block = self.new_block();
+                *slot(self) = Some(block);
+                block
+            }
+        }
+    }
+
+    // Code after a jump can't run, but it still needs a block to go in
+    fn dead_block(&mut self) {
+        let block = self.new_block();
+        self.seal(block);
+        self.switch_to(block);
+    }
+
+    fn seal(&mut self, block: Block) {
+        for (id, phi) in self.incomplete.remove(&block).unwrap_or_default() {
+            self.add_incoming(id, block, phi);
+        }
+        self.sealed.insert(block);
+    }
+
+    fn write(&mut self, id: SymbolId, val: Value) {
+        match self.info.globals.get(&id) {
+            Some(name) => {
+                self.push(None, Op::Store(name.clone(), val));
+            }
+            None => {
+                self.defs.insert((id, self.cur), val);
+            }
+        }
+    }
+
+    fn read(&mut self, id: SymbolId) -> Value {
+        match self.info.globals.get(&id) {
+            Some(name) => self.value(self.var_type(id), Op::Load(name.clone())),
+            None => self.read_in(id, self.cur),
+        }
+    }
+
+    fn read_in(&mut self, id: SymbolId, block: Block) -> Value {
+        if let Some(&val) = self.defs.get(&(id, block)) {
+            return val;
+        }
+
+        let preds = &self.preds[block.0 as usize];
+        let val = if !self.sealed.contains(&block) {
+            let phi = self.phi(id, block);
+            self.incomplete.entry(block).or_default().push((id, phi));
+            phi
+        } else if preds.len() == 1 {
+            self.read_in(id, preds[0])
+        } else if preds.is_empty() {
+            // Only happens in blocks that can't run, which get removed
+            self.func.new_value()
+        } else {
+            let phi = self.phi(id, block);
+            self.defs.insert((id, block), phi);
+            self.add_incoming(id, block, phi);
+            phi
+        };
+
+        self.defs.insert((id, block), val);
+        val
+    }
+
+    fn phi(&mut self, id: SymbolId, block: Block) -> Value {
+        let val = self.func.new_value();
+        let typ = self.var_type(id);
+        self.phis[block.0 as usize].push((val, typ));
+        self.incoming.insert(val, Vec::new());
+        val
+    }
+
+    fn add_incoming(&mut self, id: SymbolId, block: Block, phi: Value) {
+        for pred in self.preds[block.0 as usize].clone() {
+            let val = self.read_in(id, pred);
+            self.incoming.get_mut(&phi).unwrap().push((pred, val));
+        }
+    }
+
+    fn top_level(&mut self) {
+        self.top = true;
+        let stmts = &self.prog.ast[self.prog.root].stmts;
+        for &stmt in stmts {
+            self.stmt(stmt);
+            if let Some(next) = self.next_stmt.take() {
+                self.jump(next);
+                self.seal(next);
+                self.switch_to(next);
+            }
+        }
+
+        if let Some(end) = self.module_end.take() {
+            self.jump(end);
+            self.seal(end);
+            self.switch_to(end);
+        }
+    }
+
+    fn function(&mut self, id: SymbolId, func: &ast::Func) {
+        self.mark(func.name);
+
+        // The old values of everything this function owns go back when it returns
+        let mut owned: Vec<(&SymbolId, &String)> = self
+            .info
+            .globals
+            .iter()
+            .filter(|(x, _)| self.info.owners.get(x) == Some(&Owner::Func(id)))
+            .collect();
+        owned.sort();
+        for (&var, name) in owned {
+            let val = self.value(self.var_type(var), Op::Load(name.clone()));
+            self.saved.push((name.clone(), val));
+        }
+
+        for (idx, &param) in func.params.iter().enumerate() {
+            let id = self.symbol(self.prog.ast[param].name);
+            let val = self.func.params[idx].0;
+            self.write(id, val);
+        }
+
+        self.block(func.body);
+        self.ret(None);
+    }
+
+    // Falling off the end of a function that has a return type gives the zero of that type
+    fn ret(&mut self, val: Option<Value>) {
+        if self.terms[self.cur.0 as usize].is_some() {
+            return;
+        }
+
+        let val = match (val, self.func.ret) {
+            (None, Some(typ)) => Some(self.value(typ, typ.zero())),
+            (val, _) => val,
+        };
+        for (name, old) in self.saved.clone() {
+            self.push(None, Op::Store(name, old));
+        }
+        self.terminate(Terminator::Return(val));
+    }
+
+    fn block(&mut self, block: Node<AstBlock>) {
+        for &stmt in &self.prog.ast[block].stmts {
+            self.stmt(stmt);
+        }
+    }
+
+    fn stmt(&mut self, stmt: Node<Stmt>) {
+        if !matches!(
+            self.prog.ast[stmt],
+            Stmt::Block(_)
+                | Stmt::Func(_)
+                | Stmt::Enum(_)
+                | Stmt::Struct(_)
+                | Stmt::Trait(_)
+                | Stmt::Impl(_)
+                | Stmt::Module(_)
+                | Stmt::Use(_)
+        ) {
+            self.mark(stmt);
+        }
+
+        match &self.prog.ast[stmt] {
+            Stmt::Block(x) => self.block(*x),
+            Stmt::Decl(x) => {
+                let val = self.expr(x.val).unwrap();
+                self.write(self.symbol(x.name), val);
+            }
+            Stmt::Assign(x) => {
+                let id = self.symbol(x.name);
+                let mut val = self.expr(x.val).unwrap();
+                if let Some(op) = &x.op {
+                    let cur = self.read(id);
+                    let op = BinOp::from(&op.src).unwrap();
+                    let typ = match op.is_comparison() {
+                        true => Type::Bool,
+                        false => self.var_type(id),
+                    };
+                    val = self.value(typ, Op::Binary(op, cur, val));
+                }
+                self.write(id, val);
+            }
+            Stmt::IfStmt(x) => {
+                let cond = self.expr(x.cond).unwrap();
+                let body = self.new_block();
+                let merge = self.new_block();
+                self.terminate(Terminator::Branch(cond, body, merge));
+                self.seal(body);
+
+                self.switch_to(body);
+                self.block(x.body);
+                self.jump(merge);
+                self.seal(merge);
+                self.switch_to(merge);
+            }
+            Stmt::WhileLoop(x) => {
+                let header = self.new_block();
+                self.jump(header);
+                self.switch_to(header);
+                self.mark(x.cond);
+                let cond = self.expr(x.cond).unwrap();
+                let body = self.new_block();
+                let exit = self.new_block();
+                self.terminate(Terminator::Branch(cond, body, exit));
+                self.seal(body);
+
+                self.switch_to(body);
+                self.loops.push(header);
+                self.block(x.body);
+                self.loops.pop();
+                self.jump(header);
+                self.seal(header);
+                self.seal(exit);
+                self.switch_to(exit);
+            }
+            Stmt::DoWhileLoop(x) => {
+                let body = self.new_block();
+                let cond_block = self.new_block();
+                let exit = self.new_block();
+                self.jump(body);
+
+                self.switch_to(body);
+                self.loops.push(cond_block);
+                self.block(x.body);
+                self.loops.pop();
+                self.jump(cond_block);
+                self.seal(cond_block);
+
+                self.switch_to(cond_block);
+                self.mark(x.cond);
+                let cond = self.expr(x.cond).unwrap();
+                self.terminate(Terminator::Branch(cond, body, exit));
+                self.seal(body);
+                self.seal(exit);
+                self.switch_to(exit);
+            }
+            Stmt::Continue => {
+                match self.loops.last() {
+                    Some(&target) => self.jump(target),
+                    None if self.top => {
+                        let next = self.lazy_block(|x| &mut x.next_stmt);
+                        self.jump(next);
+                    }
+                    None => self.ret(None),
+                }
+                self.dead_block();
+            }
+            Stmt::Return(x) => {
+                let val = x.val.map(|val| self.expr(val).unwrap());
+                match self.top {
+                    true => {
+                        let end = self.lazy_block(|x| &mut x.module_end);
+                        self.jump(end);
+                    }
+                    false => self.ret(val),
+                }
+                self.dead_block();
+            }
+            Stmt::ExprStmt(x) => {
+                self.expr(x.expr);
+            }
+            Stmt::IfLet(_) => unreachable!("optionals don't make it past the type scan"),
+            Stmt::Trait(_) | Stmt::Impl(_) => unreachable!("methods don't make it past the scan"),
+            // Functions are lowered on their own, types, modules and imports are only names
+            Stmt::Func(_) | Stmt::Enum(_) | Stmt::Struct(_) | Stmt::Module(_) | Stmt::Use(_) => (),
+        }
+    }
+
+    // The function a name points at, if it's one that can be called directly
+    fn func_name(&self, node: impl Into<NodeId>) -> Option<String> {
+        let id = self.symbol(node);
+        let symbols = self.info.graph.symbols.borrow();
+        match symbols.get(id).kind {
+            SymbolKind::Function => self.info.funcs.get(&id).map(|x| x.name.clone()),
+            _ => None,
+        }
+    }
+
+    fn name(&mut self, node: impl Into<NodeId> + Copy) -> Value {
+        match self.func_name(node) {
+            Some(name) => self.value(Type::Func, Op::Func(name)),
+            None => {
+                let id = self.symbol(node);
+                self.read(id)
+            }
+        }
+    }
+
+    // Nothing for calls to functions without a return type
+    fn expr(&mut self, expr: Node<Expr>) -> Option<Value> {
+        let typ = self.expr_type(expr);
+        let val = match &self.prog.ast[expr] {
+            Expr::Ident(x) => self.name(*x),
+            Expr::Path(x) => self.name(*x),
+            Expr::NumLit(x) => {
+                let typ = typ.unwrap();
+                let val = match typ {
+                    Type::Float(_) => Const::Float(x.val as f64),
+                    _ => Const::Int(interpreter::wrap(x.val as i128, self.data_type(expr))),
+                };
+                self.value(typ, Op::Const(val))
+            }
+            Expr::BoolLit(x) => self.value(Type::Bool, Op::Const(Const::Bool(x.val))),
+            Expr::BinaryOp(x) => {
+                let lhs = self.expr(x.lhs).unwrap();
+                let rhs = self.expr(x.rhs).unwrap();
+                let op = BinOp::from(&x.op.src).unwrap();
+                self.value(typ.unwrap(), Op::Binary(op, lhs, rhs))
+            }
+            Expr::UnaryOp(x) => {
+                let val = self.expr(x.val).unwrap();
+                let op = UnOp::from(&x.op.src).unwrap();
+                self.value(typ.unwrap(), Op::Unary(op, val))
+            }
+            Expr::Call(x) => {
+                let direct = self
+                    .instance(expr)
+                    .or_else(|| match &self.prog.ast[x.callee] {
+                        Expr::Ident(x) => self.func_name(*x),
+                        Expr::Path(x) => self.func_name(*x),
+                        _ => None,
+                    });
+                let callee = match direct {
+                    Some(name) => Callee::Direct(name),
+                    None => Callee::Indirect(self.expr(x.callee).unwrap()),
+                };
+                let args = x.args.iter().map(|&arg| self.expr(arg).unwrap()).collect();
+                return self.push(typ, Op::Call(callee, args));
+            }
+            Expr::Match(x) => return self.match_expr(x, typ),
+            Expr::Tuple(_) | Expr::Field(_) => {
+                unreachable!("tuples don't make it past the type scan")
+            }
+            Expr::NoneLit => unreachable!("optionals don't make it past the type scan"),
+        };
+
+        Some(val)
+    }
+
+    // Every arm tests its pattern and guard, and goes on to the next arm when either fails.
+    // Arms with a value hand it to a phi in the block after the match
+    fn match_expr(&mut self, match_expr: &ast::Match, typ: Option<Type>) -> Option<Value> {
+        let val = self.expr(match_expr.val).unwrap();
+        let merge = self.new_block();
+        let mut incoming = Vec::new();
+
+        for &arm in &match_expr.arms {
+            let arm = &self.prog.ast[arm];
+            let body = self.new_block();
+            let next = self.new_block();
+            match self.prog.ast[arm.pat] {
+                Pattern::Literal(lit) => {
+                    let lit = self.expr(lit).unwrap();
+                    let eq = self.value(Type::Bool, Op::Binary(BinOp::Eq, val, lit));
+                    self.terminate(Terminator::Branch(eq, body, next));
+                }
+                Pattern::Binding(name) => {
+                    self.write(self.symbol(name), val);
+                    self.jump(body);
+                }
+                Pattern::Wildcard => self.jump(body),
+                Pattern::Tuple(_) | Pattern::Variant(..) => {
+                    unreachable!("tuples and enums don't make it past the type scan")
+                }
+            }
+            self.seal(body);
+            self.switch_to(body);
+
+            if let Some(guard) = arm.guard {
+                let cond = self.expr(guard).unwrap();
+                let then = self.new_block();
+                self.terminate(Terminator::Branch(cond, then, next));
+                self.seal(then);
+                self.switch_to(then);
+            }
+            self.seal(next);
+
+            match &self.prog.ast[arm.body] {
+                Stmt::ExprStmt(x) if typ.is_some() => {
+                    let val = self.expr(x.expr).unwrap();
+                    incoming.push((self.cur, val));
+                }
+                _ => self.stmt(arm.body),
+            }
+            self.jump(merge);
+            self.switch_to(next);
+        }
+
+        // The checker makes sure some arm always matches, but this block still needs a value
+        if let Some(typ) = typ {
+            let zero = self.value(typ, typ.zero());
+            incoming.push((self.cur, zero));
+        }
+        self.jump(merge);
+        self.seal(merge);
+        self.switch_to(merge);
+
+        let typ = typ?;
+        let phi = self.func.new_value();
+        self.phis[merge.0 as usize].push((phi, typ));
+        self.incoming.insert(phi, incoming);
+        Some(phi)
+    }
+
+    // Drops the blocks that can't run, then the phis that only ever pick one value, then
+    // numbers what's left in order
+    fn finish(mut self) -> Function {
+        self.ret(None);
+        for (idx, phis) in self.phis.iter().enumerate() {
+            let insts = phis.iter().map(|&(val, typ)| {
+                let incoming = self.incoming.remove(&val).unwrap();
+                Inst::new(Some((val, typ)), Op::Phi(incoming))
+            });
+            let block = &mut self.func.blocks[idx];
+            block.insts.splice(0..0, insts.collect::<Vec<_>>());
+            block.term = self.terms[idx].take().unwrap_or(Terminator::Return(None));
+        }
+
+        let mut func = self.func;
+        func.remove_dead_blocks();
+        func.remove_trivial_phis();
+        func.renumber();
+        func
+    }
+}
@@ -0,0 +1,137 @@
+use std::{collections::HashMap, fmt};
+
+use super::{BinOp, Const, Function, Inst, Module, Op, Value};
+
+mod cse;
+mod dce;
+mod inline;
+mod licm;
+mod propagate;
+
+// A pass changes the module in place and says how much it changed, nothing means it's done
+pub struct Pass {
+    pub name: &'static str,
+    // What the count is a count of
+    pub what: &'static str,
+    pub run: fn(&mut Module) -> usize,
+}
+
+// In the order they run. Inlining goes first so the rest get to see through the calls
+pub const PASSES: [Pass; 5] = [
+    Pass {
+        name: "inline",
+        what: "calls inlined",
+        run: inline::run,
+    },
+    Pass {
+        name: "propagate",
+        what: "values folded or forwarded",
+        run: propagate::run,
+    },
+    Pass {
+        name: "cse",
+        what: "repeated values removed",
+        run: cse::run,
+    },
+    Pass {
+        name: "licm",
+        what: "values hoisted out of loops",
+        run: licm::run,
+    },
+    Pass {
+        name: "dce",
+        what: "instructions and blocks removed",
+        run: dce::run,
+    },
+];
+
+// Each pass can open things up for the others, but it has to stop somewhere
+const MAX_ROUNDS: usize = 4;
+
+// How much each pass did over every round
+#[derive(Debug, Clone, Default)]
+pub struct Stats {
+    pub rounds: usize,
+    pub changes: HashMap<&'static str, usize>,
+}
+
+impl fmt::Display for Stats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{} rounds", self.rounds)?;
+        for pass in &PASSES {
+            let count = self.changes.get(pass.name).copied().unwrap_or(0);
+            writeln!(f, "{:>10}: {} {}", pass.name, count, pass.what)?;
+        }
+        Ok(())
+    }
+}
+
+// Runs every pass until a whole round changes nothing. The callback sees the module after each
+// pass, for dumping or checking it
+pub fn optimize(module: &mut Module, mut after: impl FnMut(&Pass, &Module)) -> Stats {
+    let mut stats = Stats::default();
+    while stats.rounds < MAX_ROUNDS {
+        stats.rounds += 1;
+        let mut changed = false;
+        for pass in &PASSES {
+            let count = (pass.run)(module);
+            *stats.changes.entry(pass.name).or_default() += count;
+            changed |= count > 0;
+            after(pass, module);
+        }
+        if !changed {
+            break;
+        }
+    }
+
+    for func in &mut module.funcs {
+        func.renumber();
+    }
+    stats
+}
+
+// The constants in a function, by the value they're assigned to
+fn consts(func: &Function) -> HashMap<Value, Const> {
+    let mut consts = HashMap::new();
+    for block in &func.blocks {
+        for inst in &block.insts {
+            if let (Some((val, _)), Op::Const(x)) = (inst.dest, &inst.op) {
+                consts.insert(val, *x);
+            }
+        }
+    }
+    consts
+}
+
+// Whether the instruction does anything besides giving its value. Traps count, so dividing by
+// something that might be zero can't be dropped or moved to where it might not have run
+fn has_effects(inst: &Inst, consts: &HashMap<Value, Const>) -> bool {
+    let Op::Binary(op, _, rhs) = inst.op else {
+        return matches!(inst.op, Op::Call(..) | Op::Store(..) | Op::Line(..));
+    };
+    let typ = match inst.dest {
+        Some((_, typ)) if typ.is_int() => typ,
+        _ => return false,
+    };
+
+    let rhs = match consts.get(&rhs) {
+        Some(&Const::Int(x)) => Some(x),
+        _ => None,
+    };
+    match op {
+        BinOp::Div | BinOp::Rem => rhs.is_none_or(|x| x == 0),
+        BinOp::Pow => rhs.is_none_or(|x| x < 0),
+        BinOp::Shl | BinOp::Shr | BinOp::Ushr => {
+            rhs.is_none_or(|x| !(0..typ.bits() as i128).contains(&x))
+        }
+        _ => false,
+    }
+}
+
+// Whether the instruction gives the same value for the same operands, wherever it runs
+fn is_pure(inst: &Inst) -> bool {
+    match inst.op {
+        Op::Const(_) | Op::Func(_) | Op::Binary(..) | Op::Unary(..) => true,
+        Op::Phi(_) | Op::Call(..) | Op::Load(_) | Op::Store(..) | Op::Line(..) => false,
+    }
+}
@@ -0,0 +1,104 @@
+use std::collections::HashMap;
+
+use super::super::{BinOp, Block, Cfg, Const, Function, Module, Op, Type, UnOp, Value};
+
+// What makes two pure instructions the same. Operands of ops where order doesn't matter are
+// sorted, and floats are compared by their bits so NaN still matches itself
+#[derive(PartialEq, Eq, Hash)]
+enum Key {
+    Int(Type, i128),
+    Float(Type, u64),
+    Bool(bool),
+    Func(String),
+    Binary(BinOp, Value, Value),
+    Unary(UnOp, Value),
+}
+
+impl Key {
+    fn new(op: &Op, typ: Type) -> Option<Key> {
+        Some(match *op {
+            Op::Const(Const::Int(x)) => Key::Int(typ, x),
+            Op::Const(Const::Float(x)) => Key::Float(typ, x.to_bits()),
+            Op::Const(Const::Bool(x)) => Key::Bool(x),
+            Op::Func(ref name) => Key::Func(name.clone()),
+            Op::Binary(op, lhs, rhs) if commutes(op) => Key::Binary(op, lhs.min(rhs), lhs.max(rhs)),
+            Op::Binary(op, lhs, rhs) => Key::Binary(op, lhs, rhs),
+            Op::Unary(op, val) => Key::Unary(op, val),
+            _ => return None,
+        })
+    }
+}
+
+fn commutes(op: BinOp) -> bool {
+    use BinOp::*;
+    matches!(op, Add | Mul | And | Or | Xor | Nand | Nor | Xand | Eq | Ne)
+}
+
+// Common subexpression elimination. A pure instruction that's already been worked out by
+// something that dominates it is just that value again
+pub fn run(module: &mut Module) -> usize {
+    module.funcs.iter_mut().map(func).sum()
+}
+
+fn func(func: &mut Function) -> usize {
+    let cfg = Cfg::new(func);
+    let mut children: Vec<Vec<Block>> = vec![Vec::new(); func.blocks.len()];
+    for &block in &cfg.order {
+        if let Some(idom) = cfg.idom[block.0 as usize] {
+            children[idom.0 as usize].push(block);
+        }
+    }
+
+    let mut seen = HashMap::new();
+    let mut same = HashMap::new();
+    walk(func, &children, Block(0), &mut seen, &mut same);
+
+    for block in &mut func.blocks {
+        block
+            .insts
+            .retain(|x| !x.dest.is_some_and(|(val, _)| same.contains_key(&val)));
+    }
+    func.replace_uses(&same);
+    same.len()
+}
+
+// Down the dominator tree, so only what dominates a block is in scope for it
+fn walk(
+    func: &Function,
+    children: &[Vec<Block>],
+    block: Block,
+    seen: &mut HashMap<Key, Value>,
+    same: &mut HashMap<Value, Value>,
+) {
+    let mut added = Vec::new();
+    for inst in &func[block].insts {
+        let Some((dest, typ)) = inst.dest else {
+            continue;
+        };
+        // Operands that were repeats have to be looked at as the original
+        let mut op = inst.op.clone();
+        for val in op.operands_mut() {
+            *val = same.get(val).copied().unwrap_or(*val);
+        }
+        let Some(key) = Key::new(&op, typ) else {
+            continue;
+        };
+
+        match seen.get(&key) {
+            Some(&val) => {
+                same.insert(dest, val);
+            }
+            None => {
+                seen.insert(key, dest);
+                added.push(Key::new(&op, typ).unwrap());
+            }
+        }
+    }
+
+    for &child in &children[block.0 as usize] {
+        walk(func, children, child, seen, same);
+    }
+    for key in added {
+        seen.remove(&key);
+    }
+}
@@ -0,0 +1,146 @@
+use std::collections::{HashMap, HashSet};
+
+use super::super::{Block, Cfg, Function, Module, Op, Terminator, Value};
+
+// Dead code elimination. Drops the instructions nothing needs, stores to globals nothing ever
+// loads, blocks that can't run, and jumps to a block that nothing else jumps to
+pub fn run(module: &mut Module) -> usize {
+    let loaded: HashSet<String> = module
+        .funcs
+        .iter()
+        .flat_map(|x| &x.blocks)
+        .flat_map(|x| &x.insts)
+        .filter_map(|x| match &x.op {
+            Op::Load(name) => Some(name.clone()),
+            _ => None,
+        })
+        .collect();
+
+    let mut changes = 0;
+    for func in &mut module.funcs {
+        for block in &mut func.blocks {
+            let before = block.insts.len();
+            block
+                .insts
+                .retain(|x| !matches!(&x.op, Op::Store(name, _) if !loaded.contains(name)));
+            changes += before - block.insts.len();
+        }
+        changes += func.remove_dead_blocks();
+        changes += merge_blocks(func);
+        changes += remove_unused(func);
+        changes += remove_stale_lines(func);
+    }
+
+    // A global nothing loads has nothing storing to it either by now
+    let before = module.globals.len();
+    module.globals.retain(|x| loaded.contains(&x.name));
+    changes + before - module.globals.len()
+}
+
+// Marks what the effects and terminators need, then what those need, and so on. Anything not
+// marked is only needed by other things that aren't, like a phi that only feeds itself
+fn remove_unused(func: &mut Function) -> usize {
+    let consts = super::consts(func);
+    let mut defs = HashMap::new();
+    let mut stack: Vec<Value> = Vec::new();
+    for block in &func.blocks {
+        for inst in &block.insts {
+            if let Some((val, _)) = inst.dest {
+                defs.insert(val, inst);
+            }
+            if super::has_effects(inst, &consts) {
+                stack.extend(inst.op.operands());
+            }
+        }
+        stack.extend(block.term.operands());
+    }
+
+    let mut live = HashSet::new();
+    while let Some(val) = stack.pop() {
+        if live.insert(val) {
+            if let Some(inst) = defs.get(&val) {
+                stack.extend(inst.op.operands());
+            }
+        }
+    }
+
+    let mut removed = 0;
+    for block in &mut func.blocks {
+        let before = block.insts.len();
+        block.insts.retain(|x| match x.dest {
+            _ if super::has_effects(x, &consts) => true,
+            Some((val, _)) => live.contains(&val),
+            None => true,
+        });
+        removed += before - block.insts.len();
+    }
+    removed
+}
+
+// A block that jumps to a block only it jumps to can just carry on with that block's code
+fn merge_blocks(func: &mut Function) -> usize {
+    let mut merged = 0;
+    loop {
+        let cfg = Cfg::new(func);
+        let found = (0..func.blocks.len())
+            .map(|x| Block(x as u32))
+            .find_map(|block| match func[block].term {
+                Terminator::Jump(to) if to != block && cfg.preds(to).len() == 1 => {
+                    Some((block, to))
+                }
+                _ => None,
+            });
+        let Some((block, next)) = found else {
+            break;
+        };
+
+        // With only one way in, the phis can only be one thing
+        let mut aliases = HashMap::new();
+        let mut insts = std::mem::take(&mut func[next].insts);
+        insts.retain(|inst| match (&inst.op, inst.dest) {
+            (Op::Phi(incoming), Some((val, _))) => {
+                aliases.insert(val, incoming[0].1);
+                false
+            }
+            _ => true,
+        });
+        let term = std::mem::replace(&mut func[next].term, Terminator::Return(None));
+        func[block].insts.extend(insts);
+        func[block].term = term;
+
+        for succ in func[block].term.successors() {
+            for inst in &mut func[succ].insts {
+                if let Op::Phi(incoming) = &mut inst.op {
+                    for (from, _) in incoming {
+                        if *from == next {
+                            *from = block;
+                        }
+                    }
+                }
+            }
+        }
+        func.replace_uses(&aliases);
+        func.remove_dead_blocks();
+        merged += 1;
+    }
+    merged
+}
+
+// Once the code between two lines is gone, the first one doesn't say anything
+fn remove_stale_lines(func: &mut Function) -> usize {
+    let mut removed = 0;
+    for block in &mut func.blocks {
+        let before = block.insts.len();
+        let mut insts = std::mem::take(&mut block.insts);
+        insts.dedup_by(|next, prev| {
+            let stale = matches!(prev.op, Op::Line(..)) && matches!(next.op, Op::Line(..));
+            if stale {
+                std::mem::swap(prev, next);
+            }
+            stale
+        });
+        block.insts = insts;
+        removed += before - block.insts.len();
+    }
+    removed
+}
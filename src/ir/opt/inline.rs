@@ -0,0 +1,143 @@
+use std::collections::HashMap;
+
+use super::super::{
+    lower::ENTRY, Block, BlockData, Callee, Function, Inst, Module, Op, Terminator, Value,
+};
+
+// Functions with at most this many instructions get copied into whatever calls them
+const MAX_SIZE: usize = 16;
+// And nothing grows past this from inlining alone
+const MAX_CALLER_SIZE: usize = 1000;
+
+// Replaces direct calls to small functions with the function's body. The call's block is split
+// in two around it, the copy goes in between and every return jumps to the second half
+pub fn run(module: &mut Module) -> usize {
+    let small: HashMap<String, Function> = module
+        .funcs
+        .iter()
+        .filter(|x| x.name != ENTRY && size(x) <= MAX_SIZE)
+        .map(|x| (x.name.clone(), x.clone()))
+        .collect();
+
+    let mut changes = 0;
+    for func in &mut module.funcs {
+        while size(func) < MAX_CALLER_SIZE {
+            let Some((block, idx, callee)) = find_call(func, &small) else {
+                break;
+            };
+            inline(func, block, idx, callee);
+            changes += 1;
+        }
+    }
+    changes
+}
+
+fn size(func: &Function) -> usize {
+    func.blocks.iter().map(|x| x.insts.len() + 1).sum()
+}
+
+// A function is never inlined into itself, so recursion stops at one level
+fn find_call<'a>(
+    func: &Function,
+    small: &'a HashMap<String, Function>,
+) -> Option<(Block, usize, &'a Function)> {
+    for (block, data) in func.blocks.iter().enumerate() {
+        for (idx, inst) in data.insts.iter().enumerate() {
+            let Op::Call(Callee::Direct(name), _) = &inst.op else {
+                continue;
+            };
+            match small.get(name) {
+                Some(callee) if callee.name != func.name => {
+                    return Some((Block(block as u32), idx, callee))
+                }
+                _ => {}
+            }
+        }
+    }
+    None
+}
+
+fn inline(func: &mut Function, block: Block, idx: usize, callee: &Function) {
+    let mut rest = func[block].insts.split_off(idx);
+    let call = rest.remove(0);
+
+    // The copy says where its own lines are, so the rest has to say where it came from again
+    let line = func[block]
+        .insts
+        .iter()
+        .rev()
+        .find(|x| matches!(x.op, Op::Line(..)));
+    if let Some(line) = line {
+        rest.insert(0, line.clone());
+    }
+    let Op::Call(_, args) = call.op else {
+        unreachable!("only calls get inlined")
+    };
+
+    // Whatever came after the call goes in its own block, which the successors now come from
+    let after = func.add_block();
+    let term = std::mem::replace(&mut func[block].term, Terminator::Return(None));
+    func[after] = BlockData { insts: rest, term };
+    for succ in func[after].term.successors() {
+        for inst in &mut func[succ].insts {
+            if let Op::Phi(incoming) = &mut inst.op {
+                for (from, _) in incoming {
+                    if *from == block {
+                        *from = after;
+                    }
+                }
+            }
+        }
+    }
+
+    // Every value in the copy is new, except the parameters which are just the arguments
+    let mut values: HashMap<Value, Value> = callee.params.iter().map(|x| x.0).zip(args).collect();
+    for data in &callee.blocks {
+        for (val, _) in data.insts.iter().filter_map(|x| x.dest) {
+            values.insert(val, func.new_value());
+        }
+    }
+    let first = func.blocks.len() as u32;
+    let moved = |x: Block| Block(x.0 + first);
+
+    let mut returns = Vec::new();
+    for (idx, data) in callee.blocks.iter().enumerate() {
+        let mut data = data.clone();
+        for inst in &mut data.insts {
+            if let Some((val, _)) = &mut inst.dest {
+                *val = values[val];
+            }
+            for val in inst.op.operands_mut() {
+                *val = values[val];
+            }
+            if let Op::Phi(incoming) = &mut inst.op {
+                for (from, _) in incoming {
+                    *from = moved(*from);
+                }
+            }
+        }
+        for val in data.term.operands_mut() {
+            *val = values[val];
+        }
+        for to in data.term.successors_mut() {
+            *to = moved(*to);
+        }
+        if let Terminator::Return(val) = data.term {
+            returns.push((moved(Block(idx as u32)), val));
+            data.term = Terminator::Jump(after);
+        }
+        func.blocks.push(data);
+    }
+    func[block].term = Terminator::Jump(Block(first));
+
+    // What the call gave is whatever got returned, from whichever return it came from
+    if let Some(dest) = call.dest {
+        let incoming = returns
+            .into_iter()
+            .map(|(from, val)| (from, val.unwrap()))
+            .collect();
+        func[after]
+            .insts
+            .insert(0, Inst::new(Some(dest), Op::Phi(incoming)));
+    }
+}
@@ -0,0 +1,143 @@
+use std::collections::HashSet;
+
+use super::super::{Block, Cfg, Function, Inst, Module, Op, Terminator};
+
+// Loop invariant code motion. Pure instructions in a loop whose operands all come from outside it
+// work out the same every time around, so they move to just before the loop starts
+pub fn run(module: &mut Module) -> usize {
+    module.funcs.iter_mut().map(func).sum()
+}
+
+fn func(func: &mut Function) -> usize {
+    // A new preheader is part of any loop around it, so the loops have to be found again
+    while let Some((header, body)) = loops(func)
+        .into_iter()
+        .find(|(header, body)| preheader(func, *header, body) == Some(None))
+    {
+        add_preheader(func, header, &body);
+    }
+
+    let mut changes = 0;
+    for (header, body) in loops(func) {
+        if let Some(Some(preheader)) = preheader(func, header, &body) {
+            changes += hoist(func, preheader, &body);
+        }
+    }
+    changes
+}
+
+// Every loop by its header, with all the blocks in it. A back edge is a jump to a block that
+// dominates where it jumps from, and the loop is everything that can get back to it
+// without going through the header
+fn loops(func: &Function) -> Vec<(Block, HashSet<Block>)> {
+    let cfg = Cfg::new(func);
+    let mut loops: Vec<(Block, HashSet<Block>)> = Vec::new();
+    for &block in &cfg.order {
+        for &to in cfg.succs(block) {
+            if !cfg.dominates(to, block) {
+                continue;
+            }
+
+            let idx = match loops.iter().position(|(x, _)| *x == to) {
+                Some(idx) => idx,
+                None => {
+                    loops.push((to, HashSet::from([to])));
+                    loops.len() - 1
+                }
+            };
+            let body = &mut loops[idx].1;
+            let mut stack = vec![block];
+            while let Some(at) = stack.pop() {
+                if body.insert(at) {
+                    stack.extend(cfg.preds(at));
+                }
+            }
+        }
+    }
+    loops
+}
+
+// The block that comes right before the loop and only goes into it, or nothing inside when it
+// still has to be made. Loops entered from more than one place are left alone
+fn preheader(func: &Function, header: Block, body: &HashSet<Block>) -> Option<Option<Block>> {
+    let pred = outside_pred(func, header, body)?;
+    match Cfg::new(func).succs(pred).len() {
+        1 => Some(Some(pred)),
+        _ => Some(None),
+    }
+}
+
+fn outside_pred(func: &Function, header: Block, body: &HashSet<Block>) -> Option<Block> {
+    let cfg = Cfg::new(func);
+    let outside: Vec<Block> = cfg
+        .preds(header)
+        .iter()
+        .filter(|x| !body.contains(x))
+        .copied()
+        .collect();
+    match outside[..] {
+        [pred] => Some(pred),
+        _ => None,
+    }
+}
+
+// Goes between the loop and the block before it, which also goes somewhere else
+fn add_preheader(func: &mut Function, header: Block, body: &HashSet<Block>) {
+    let pred = outside_pred(func, header, body).unwrap();
+    let new = func.add_block();
+    func[new].term = Terminator::Jump(header);
+    for to in func[pred].term.successors_mut() {
+        if *to == header {
+            *to = new;
+        }
+    }
+    for inst in &mut func[header].insts {
+        if let Op::Phi(incoming) = &mut inst.op {
+            for (from, _) in incoming {
+                if *from == pred {
+                    *from = new;
+                }
+            }
+        }
+    }
+}
+
+fn hoist(func: &mut Function, preheader: Block, body: &HashSet<Block>) -> usize {
+    let consts = super::consts(func);
+    let mut inside = HashSet::new();
+    for &block in body {
+        inside.extend(func[block].insts.iter().filter_map(|x| x.dest.map(|x| x.0)));
+    }
+
+    // Blocks in order so an instruction is usually looked at after its operands
+    let mut blocks: Vec<Block> = body.iter().copied().collect();
+    blocks.sort();
+
+    let mut hoisted: Vec<Inst> = Vec::new();
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for &block in &blocks {
+            let insts = std::mem::take(&mut func[block].insts);
+            let mut kept = Vec::with_capacity(insts.len());
+            for inst in insts {
+                let invariant = super::is_pure(&inst)
+                    && !super::has_effects(&inst, &consts)
+                    && inst.op.operands().iter().all(|x| !inside.contains(x));
+                match invariant {
+                    true => {
+                        inside.remove(&inst.dest.unwrap().0);
+                        hoisted.push(inst);
+                        changed = true;
+                    }
+                    false => kept.push(inst),
+                }
+            }
+            func[block].insts = kept;
+        }
+    }
+
+    let count = hoisted.len();
+    func[preheader].insts.extend(hoisted);
+    count
+}
@@ -0,0 +1,129 @@
+use std::collections::HashMap;
+
+use super::super::{
+    eval::{self, Val},
+    BinOp, Block, Cfg, Const, Function, Module, Op, Terminator, Type, Value,
+};
+
+// Constant and copy propagation. Operations on constants become constants, operations that give
+// back one of their operands become that operand, and branches on a constant become jumps
+pub fn run(module: &mut Module) -> usize {
+    module.funcs.iter_mut().map(func).sum()
+}
+
+fn func(func: &mut Function) -> usize {
+    let mut changes = 0;
+    let mut consts = super::consts(func);
+    let types = func.types();
+    let mut copies: HashMap<Value, Value> = HashMap::new();
+
+    // In reverse postorder the operands are nearly always done before they're used
+    for block in Cfg::new(func).order {
+        for inst in &mut func[block].insts {
+            let Some((dest, _)) = inst.dest else {
+                continue;
+            };
+            let resolve = |val: Value| copies.get(&val).copied().unwrap_or(val);
+            let folded = match inst.op {
+                Op::Binary(op, lhs, rhs) => {
+                    let (lhs, rhs) = (resolve(lhs), resolve(rhs));
+                    match (consts.get(&lhs), consts.get(&rhs)) {
+                        (Some(&x), Some(&y)) => {
+                            eval::binary(op, types[&lhs], &Val::from(x), &Val::from(y)).ok()
+                        }
+                        (x, y) => {
+                            if let Some(val) = identity(op, types[&lhs], lhs, rhs, x, y) {
+                                copies.insert(dest, val);
+                                changes += 1;
+                            }
+                            None
+                        }
+                    }
+                }
+                Op::Unary(op, val) => {
+                    let val = resolve(val);
+                    consts
+                        .get(&val)
+                        .map(|&x| eval::unary(op, types[&val], &Val::from(x)))
+                }
+                _ => None,
+            };
+
+            let folded = match folded {
+                Some(Val::Int(x)) => Const::Int(x),
+                Some(Val::Float(x)) => Const::Float(x),
+                Some(Val::Bool(x)) => Const::Bool(x),
+                _ => continue,
+            };
+            inst.op = Op::Const(folded);
+            consts.insert(dest, folded);
+            changes += 1;
+        }
+    }
+
+    // The copies are gone for good, whatever was left pointing at them goes to the original
+    for block in &mut func.blocks {
+        block
+            .insts
+            .retain(|x| !x.dest.is_some_and(|(val, _)| copies.contains_key(&val)));
+    }
+    func.replace_uses(&copies);
+
+    // Only the side that's taken is left, and the phis on the other side lose this block
+    for idx in 0..func.blocks.len() {
+        let Terminator::Branch(cond, yes, no) = func.blocks[idx].term else {
+            continue;
+        };
+        let (taken, dropped) = match consts.get(&cond) {
+            Some(Const::Bool(true)) => (yes, no),
+            Some(Const::Bool(false)) => (no, yes),
+            _ if yes == no => (yes, no),
+            _ => continue,
+        };
+
+        func.blocks[idx].term = Terminator::Jump(taken);
+        if dropped != taken {
+            let from = Block(idx as u32);
+            for inst in &mut func[dropped].insts {
+                if let Op::Phi(incoming) = &mut inst.op {
+                    incoming.retain(|(pred, _)| *pred != from);
+                }
+            }
+        }
+        changes += 1;
+    }
+
+    changes + func.remove_trivial_phis()
+}
+
+// Operations that give back one side unchanged. Floats are left alone, adding zero to -0.0
+// doesn't give -0.0 back
+fn identity(
+    op: BinOp,
+    typ: Type,
+    lhs: Value,
+    rhs: Value,
+    x: Option<&Const>,
+    y: Option<&Const>,
+) -> Option<Value> {
+    let is = |val: Option<&Const>, want: i128| matches!(val, Some(&Const::Int(x)) if x == want);
+    let is_bool =
+        |val: Option<&Const>, want: bool| matches!(val, Some(&Const::Bool(x)) if x == want);
+    if !typ.is_int() && typ != Type::Bool {
+        return None;
+    }
+
+    match op {
+        BinOp::Add | BinOp::Or | BinOp::Xor if is(y, 0) => Some(lhs),
+        BinOp::Add | BinOp::Or | BinOp::Xor if is(x, 0) => Some(rhs),
+        BinOp::Sub | BinOp::Shl | BinOp::Shr | BinOp::Ushr if is(y, 0) => Some(lhs),
+        BinOp::Mul | BinOp::Div | BinOp::Pow if is(y, 1) => Some(lhs),
+        BinOp::Mul if is(x, 1) => Some(rhs),
+        BinOp::And | BinOp::Or if lhs == rhs => Some(lhs),
+        BinOp::And if is_bool(y, true) => Some(lhs),
+        BinOp::And if is_bool(x, true) => Some(rhs),
+        BinOp::Or | BinOp::Xor if is_bool(y, false) => Some(lhs),
+        BinOp::Or | BinOp::Xor if is_bool(x, false) => Some(rhs),
+        _ => None,
+    }
+}
@@ -0,0 +1,397 @@
+use crate::types::{FloatingSize, IntegralSize};
+
+use super::{
+    BinOp, Block, BlockData, Callee, Const, Function, Global, Inst, Module, Op, Terminator, Type,
+    UnOp, Value,
+};
+
+// Anything that can't be part of a name or a number
+pub(crate) const DELIMITERS: &str = "(),:[]=;";
+
+// Reads back what the printer writes. Blank lines are fine anywhere, and ; outside of a string
+// starts a comment.
+// The result still has to go through the verifier, this only checks the syntax
+pub fn parse(src: &str) -> Result<Module, String> {
+    let lines: Vec<&str> = src.lines().collect();
+    let mut module = Module::default();
+
+    let mut row = 0;
+    while row < lines.len() {
+        let mut line = Line::new(lines[row], row);
+        row += 1;
+        if line.is_empty() {
+            continue;
+        }
+
+        match line.word().as_str() {
+            "global" => {
+                let name = line.name('@')?;
+                line.expect(':')?;
+                let typ = line.typ()?;
+                line.end()?;
+                module.globals.push(Global { name, typ });
+            }
+            "func" => {
+                let func = parse_func(&mut line, &lines, &mut row)?;
+                module.funcs.push(func);
+            }
+            word => return Err(line.error(&format!("expected a global or a func, got '{}'", word))),
+        }
+    }
+
+    Ok(module)
+}
+
+fn parse_func(line: &mut Line, lines: &[&str], row: &mut usize) -> Result<Function, String> {
+    let name = line.name('@')?;
+    let mut func = Function::new(name, Vec::new(), None);
+
+    line.expect('(')?;
+    if !line.eat(')') {
+        loop {
+            let val = line.value()?;
+            line.expect(':')?;
+            func.params.push((val, line.typ()?));
+            if line.eat(')') {
+                break;
+            }
+            line.expect(',')?;
+        }
+    }
+    if line.eat(':') {
+        func.ret = Some(line.typ()?);
+    }
+    line.expect('{')?;
+    line.end()?;
+
+    // Every block has to end with a terminator before the next one can start
+    let mut block: Option<BlockData> = None;
+    loop {
+        let Some(src) = lines.get(*row) else {
+            return Err(format!(
+                "line {}: expected a '}}' to end @{}",
+                *row, func.name
+            ));
+        };
+        let mut line = Line::new(src, *row);
+        *row += 1;
+        if line.is_empty() {
+            continue;
+        }
+
+        if line.eat('}') {
+            line.end()?;
+            if block.is_some() {
+                return Err(line.error("expected the block to end with a terminator"));
+            }
+            break;
+        }
+
+        if line.peek_word().starts_with("bb") && line.rest().contains(':') {
+            let label = line.block()?;
+            line.expect(':')?;
+            line.end()?;
+            if block.is_some() {
+                return Err(line.error("expected the block before to end with a terminator"));
+            }
+            if label.0 as usize != func.blocks.len() {
+                return Err(line.error(&format!(
+                    "expected the blocks in order, this should be bb{}",
+                    func.blocks.len()
+                )));
+            }
+            block = Some(BlockData::default());
+            continue;
+        }
+
+        let Some(data) = block.as_mut() else {
+            return Err(line.error("expected a block label"));
+        };
+        if let Some(term) = line.terminator()? {
+            data.term = term;
+            func.blocks.push(block.take().unwrap());
+        } else {
+            data.insts.push(line.inst()?);
+        }
+        line.end()?;
+    }
+
+    let values = func.params.iter().map(|(val, _)| *val).chain(
+        func.blocks
+            .iter()
+            .flat_map(|x| x.insts.iter().filter_map(|x| x.dest.map(|x| x.0))),
+    );
+    func.next_value = values.map(|x| x.0 + 1).max().unwrap_or(0);
+    Ok(func)
+}
+
+struct Line<'a> {
+    src: &'a str,
+    pos: usize,
+    row: usize,
+}
+
+impl<'a> Line<'a> {
+    fn new(src: &'a str, row: usize) -> Line<'a> {
+        let mut quoted = false;
+        let mut escaped = false;
+        let end = src.char_indices().find(|&(_, c)| {
+            match c {
+                '"' if !escaped => quoted = !quoted,
+                ';' if !quoted => return true,
+                _ => {}
+            }
+            escaped = c == '\\' && !escaped;
+            false
+        });
+        let src = match end {
+            Some((idx, _)) => &src[..idx],
+            None => src,
+        };
+        Line { src, pos: 0, row }
+    }
+
+    fn error(&self, msg: &str) -> String {
+        format!("line {}: {}", self.row + 1, msg)
+    }
+
+    fn rest(&self) -> &'a str {
+        &self.src[self.pos..]
+    }
+
+    fn skip_whitespace(&mut self) {
+        let rest = self.rest();
+        self.pos += rest.len() - rest.trim_start().len();
+    }
+
+    fn is_empty(&mut self) -> bool {
+        self.skip_whitespace();
+        self.rest().is_empty()
+    }
+
+    fn end(&mut self) -> Result<(), String> {
+        match self.is_empty() {
+            true => Ok(()),
+            false => Err(self.error(&format!("unexpected '{}'", self.rest()))),
+        }
+    }
+
+    fn eat(&mut self, chr: char) -> bool {
+        self.skip_whitespace();
+        match self.rest().starts_with(chr) {
+            true => {
+                self.pos += chr.len_utf8();
+                true
+            }
+            false => false,
+        }
+    }
+
+    fn expect(&mut self, chr: char) -> Result<(), String> {
+        match self.eat(chr) {
+            true => Ok(()),
+            false => Err(self.error(&format!("expected '{}'", chr))),
+        }
+    }
+
+    fn peek_word(&mut self) -> &'a str {
+        self.skip_whitespace();
+        let rest = self.rest();
+        let len = rest
+            .find(|c: char| c.is_whitespace() || DELIMITERS.contains(c))
+            .unwrap_or(rest.len());
+        &rest[..len]
+    }
+
+    fn word(&mut self) -> String {
+        let word = self.peek_word();
+        self.pos += word.len();
+        word.to_owned()
+    }
+
+    // Names go up to the next delimiter, so they can have dots and anything else identifiers can
+    fn name(&mut self, sigil: char) -> Result<String, String> {
+        self.expect(sigil)?;
+        match self.word() {
+            word if word.is_empty() => {
+                Err(self.error(&format!("expected a name after '{}'", sigil)))
+            }
+            word => Ok(word),
+        }
+    }
+
+    fn number(&mut self, prefix: &str, what: &str) -> Result<u32, String> {
+        let word = self.word();
+        word.strip_prefix(prefix)
+            .and_then(|x| x.parse().ok())
+            .ok_or_else(|| self.error(&format!("expected {}, got '{}'", what, word)))
+    }
+
+    fn value(&mut self) -> Result<Value, String> {
+        self.expect('%')?;
+        Ok(Value(self.number("", "a value")?))
+    }
+
+    // Only quotes and backslashes are escaped, with a backslash
+    fn string(&mut self) -> Result<String, String> {
+        self.expect('"')?;
+        let mut text = String::new();
+        let mut chars = self.rest().char_indices();
+        while let Some((idx, c)) = chars.next() {
+            match c {
+                '"' => {
+                    self.pos += idx + 1;
+                    return Ok(text);
+                }
+                '\\' => match chars.next() {
+                    Some((_, c @ ('"' | '\\'))) => text.push(c),
+                    _ => return Err(self.error("expected a quote or a backslash after '\\'")),
+                },
+                c => text.push(c),
+            }
+        }
+        Err(self.error("expected a '\"' to end the string"))
+    }
+
+    fn block(&mut self) -> Result<Block, String> {
+        Ok(Block(self.number("bb", "a block")?))
+    }
+
+    fn typ(&mut self) -> Result<Type, String> {
+        let word = self.word();
+        let (kind, size) = match word.is_char_boundary(1) {
+            true => word.split_at(1),
+            false => ("", word.as_str()),
+        };
+        let typ = match kind {
+            _ if word == "bool" => Some(Type::Bool),
+            _ if word == "func" => Some(Type::Func),
+            "i" => IntegralSize::from(size).map(Type::Int),
+            "u" => IntegralSize::from(size).map(Type::Uint),
+            "f" => FloatingSize::from(size).map(Type::Float),
+            _ => None,
+        };
+        typ.ok_or_else(|| self.error(&format!("expected a type, got '{}'", word)))
+    }
+
+    fn values(&mut self, close: char) -> Result<Vec<Value>, String> {
+        let mut values = Vec::new();
+        if self.eat(close) {
+            return Ok(values);
+        }
+        loop {
+            values.push(self.value()?);
+            if self.eat(close) {
+                return Ok(values);
+            }
+            self.expect(',')?;
+        }
+    }
+
+    fn terminator(&mut self) -> Result<Option<Terminator>, String> {
+        let term = match self.peek_word() {
+            "jmp" => {
+                self.word();
+                Terminator::Jump(self.block()?)
+            }
+            "br" => {
+                self.word();
+                let cond = self.value()?;
+                self.expect(',')?;
+                let yes = self.block()?;
+                self.expect(',')?;
+                Terminator::Branch(cond, yes, self.block()?)
+            }
+            "ret" => {
+                self.word();
+                match self.is_empty() {
+                    true => Terminator::Return(None),
+                    false => Terminator::Return(Some(self.value()?)),
+                }
+            }
+            _ => return Ok(None),
+        };
+        Ok(Some(term))
+    }
+
+    fn inst(&mut self) -> Result<Inst, String> {
+        let dest = match self.rest().trim_start().starts_with('%') {
+            true => {
+                let val = self.value()?;
+                self.expect(':')?;
+                let typ = self.typ()?;
+                self.expect('=')?;
+                Some((val, typ))
+            }
+            false => None,
+        };
+
+        let word = self.word();
+        let op = match word.as_str() {
+            "const" => {
+                let Some((_, typ)) = dest else {
+                    return Err(self.error("expected a constant to have a type"));
+                };
+                let word = self.word();
+                let val = match typ {
+                    Type::Int(_) | Type::Uint(_) => word.parse().ok().map(Const::Int),
+                    Type::Float(_) => word.parse().ok().map(Const::Float),
+                    Type::Bool => word.parse().ok().map(Const::Bool),
+                    Type::Func => None,
+                };
+                Op::Const(val.ok_or_else(|| {
+                    self.error(&format!(
+                        "expected a constant of type {}, got '{}'",
+                        typ, word
+                    ))
+                })?)
+            }
+            "func" => Op::Func(self.name('@')?),
+            "phi" => {
+                let mut incoming = Vec::new();
+                loop {
+                    self.expect('[')?;
+                    let block = self.block()?;
+                    self.expect(':')?;
+                    incoming.push((block, self.value()?));
+                    self.expect(']')?;
+                    if !self.eat(',') {
+                        break;
+                    }
+                }
+                Op::Phi(incoming)
+            }
+            "call" => {
+                let callee = match self.rest().trim_start().starts_with('@') {
+                    true => Callee::Direct(self.name('@')?),
+                    false => Callee::Indirect(self.value()?),
+                };
+                self.expect('(')?;
+                Op::Call(callee, self.values(')')?)
+            }
+            "load" => Op::Load(self.name('@')?),
+            "line" => {
+                let file = self.string()?;
+                Op::Line(file, self.number("", "a line")?)
+            }
+            "store" => {
+                let name = self.name('@')?;
+                self.expect(',')?;
+                Op::Store(name, self.value()?)
+            }
+            word => {
+                if let Some(op) = BinOp::VALUES.into_iter().find(|x| x.name() == word) {
+                    let lhs = self.value()?;
+                    self.expect(',')?;
+                    Op::Binary(op, lhs, self.value()?)
+                } else if let Some(op) = UnOp::VALUES.into_iter().find(|x| x.name() == word) {
+                    Op::Unary(op, self.value()?)
+                } else {
+                    return Err(self.error(&format!("unknown instruction '{}'", word)));
+                }
+            }
+        };
+
+        Ok(Inst::new(dest, op))
+    }
+}
@@ -0,0 +1,172 @@
+use core::fmt;
+
+use super::{
+    BinOp, Block, BlockData, Callee, Const, Function, Global, Inst, Module, Op, Terminator, Type,
+    UnOp, Value,
+};
+
+// The text format parse reads back, every value says its type where it's defined:
+//
+//     func @math.double(%0: i32): i32 {
+//     bb0:
+//         %1: i32 = const 2
+//         %2: i32 = mul %0, %1
+//         ret %2
+//     }
+impl fmt::Display for Module {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for global in &self.globals {
+            writeln!(f, "{}", global)?;
+        }
+
+        for (idx, func) in self.funcs.iter().enumerate() {
+            if idx > 0 || !self.globals.is_empty() {
+                writeln!(f)?;
+            }
+            write!(f, "{}", func)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Global {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "global @{}: {}", self.name, self.typ)
+    }
+}
+
+impl fmt::Display for Function {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let params: Vec<String> = self
+            .params
+            .iter()
+            .map(|(val, typ)| format!("{}: {}", val, typ))
+            .collect();
+        write!(f, "func @{}({})", self.name, params.join(", "))?;
+        if let Some(ret) = self.ret {
+            write!(f, ": {}", ret)?;
+        }
+        writeln!(f, " {{")?;
+
+        for (idx, block) in self.blocks.iter().enumerate() {
+            writeln!(f, "{}:", Block(idx as u32))?;
+            write!(f, "{}", block)?;
+        }
+        writeln!(f, "}}")
+    }
+}
+
+impl fmt::Display for BlockData {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for inst in &self.insts {
+            writeln!(f, "    {}", inst)?;
+        }
+        writeln!(f, "    {}", self.term)
+    }
+}
+
+impl fmt::Display for Inst {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some((val, typ)) = self.dest {
+            write!(f, "{}: {} = ", val, typ)?;
+        }
+        write!(f, "{}", self.op)
+    }
+}
+
+impl fmt::Display for Op {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Op::Const(x) => write!(f, "const {}", x),
+            Op::Func(name) => write!(f, "func @{}", name),
+            Op::Binary(op, lhs, rhs) => write!(f, "{} {}, {}", op.name(), lhs, rhs),
+            Op::Unary(op, val) => write!(f, "{} {}", op.name(), val),
+            Op::Phi(incoming) => {
+                let incoming: Vec<String> = incoming
+                    .iter()
+                    .map(|(block, val)| format!("[{}: {}]", block, val))
+                    .collect();
+                write!(f, "phi {}", incoming.join(", "))
+            }
+            Op::Call(callee, args) => {
+                let args: Vec<String> = args.iter().map(|x| x.to_string()).collect();
+                write!(f, "call {}({})", callee, args.join(", "))
+            }
+            Op::Load(name) => write!(f, "load @{}", name),
+            Op::Store(name, val) => write!(f, "store @{}, {}", name, val),
+            Op::Line(file, line) => write!(f, "line {} {}", quote(file), line),
+        }
+    }
+}
+
+impl fmt::Display for Callee {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Callee::Direct(name) => write!(f, "@{}", name),
+            Callee::Indirect(val) => write!(f, "{}", val),
+        }
+    }
+}
+
+impl fmt::Display for Terminator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Terminator::Jump(to) => write!(f, "jmp {}", to),
+            Terminator::Branch(cond, yes, no) => write!(f, "br {}, {}, {}", cond, yes, no),
+            Terminator::Return(Some(val)) => write!(f, "ret {}", val),
+            Terminator::Return(None) => write!(f, "ret"),
+        }
+    }
+}
+
+// Floats use Debug, which always has a dot or an exponent and reads back to the same bits
+impl fmt::Display for Const {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Const::Int(x) => write!(f, "{}", x),
+            Const::Float(x) => write!(f, "{:?}", x),
+            Const::Bool(x) => write!(f, "{}", x),
+        }
+    }
+}
+
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Type::Int(x) => write!(f, "i{}", *x as u32),
+            Type::Uint(x) => write!(f, "u{}", *x as u32),
+            Type::Float(x) => write!(f, "f{}", *x as u32),
+            Type::Bool => write!(f, "bool"),
+            Type::Func => write!(f, "func"),
+        }
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "%{}", self.0)
+    }
+}
+
+impl fmt::Display for Block {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "bb{}", self.0)
+    }
+}
+
+impl fmt::Display for BinOp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+impl fmt::Display for UnOp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+// A string the parser reads back, which is also how C wants it
+pub(crate) fn quote(text: &str) -> String {
+    format!("\"{}\"", text.replace('\\', "\\\\").replace('"', "\\\""))
+}
@@ -0,0 +1,293 @@
+use std::collections::{HashMap, HashSet};
+
+use super::{cfg::Cfg, Block, Callee, Const, Function, Inst, Module, Op, Terminator, Type, Value};
+
+// Everything the backends get to assume about a module. The first problem found is the error
+pub fn verify(module: &Module) -> Result<(), String> {
+    let mut names = HashSet::new();
+    let globals = module.globals.iter().map(|x| &x.name);
+    for name in globals.chain(module.funcs.iter().map(|x| &x.name)) {
+        if !names.insert(name) {
+            return Err(format!("@{} is defined more than once", name));
+        }
+    }
+
+    for func in &module.funcs {
+        verify_func(module, func)?;
+    }
+    Ok(())
+}
+
+pub fn verify_func(module: &Module, func: &Function) -> Result<(), String> {
+    Verifier::new(module, func).verify()
+}
+
+struct Verifier<'a> {
+    module: &'a Module,
+    func: &'a Function,
+    cfg: Cfg,
+    types: HashMap<Value, Type>,
+    // Where each value is defined, parameters are at the very start of the entry
+    defs: HashMap<Value, (Block, usize)>,
+}
+
+impl Verifier<'_> {
+    fn new<'a>(module: &'a Module, func: &'a Function) -> Verifier<'a> {
+        Verifier {
+            module,
+            func,
+            cfg: Cfg::new(func),
+            types: HashMap::new(),
+            defs: HashMap::new(),
+        }
+    }
+
+    fn error(&self, block: Option<Block>, msg: String) -> String {
+        match block {
+            Some(block) => format!("@{} {}: {}", self.func.name, block, msg),
+            None => format!("@{}: {}", self.func.name, msg),
+        }
+    }
+
+    fn verify(mut self) -> Result<(), String> {
+        if self.func.blocks.is_empty() {
+            return Err(self.error(None, "has no blocks".to_owned()));
+        }
+        if !self.cfg.preds(Block(0)).is_empty() {
+            return Err(self.error(Some(Block(0)), "the entry can't be jumped to".to_owned()));
+        }
+
+        // Values are only defined once, and get a type where they are
+        for &(val, typ) in &self.func.params {
+            self.define(val, typ, Block(0), 0)?;
+        }
+        for (idx, block) in self.func.blocks.iter().enumerate() {
+            for (pos, inst) in block.insts.iter().enumerate() {
+                if let Some((val, typ)) = inst.dest {
+                    self.define(val, typ, Block(idx as u32), pos + 1)?;
+                }
+            }
+        }
+        if let Some(max) = self.types.keys().max() {
+            if max.0 >= self.func.next_value {
+                return Err(self.error(None, format!("{} is past the next value", max)));
+            }
+        }
+
+        for idx in 0..self.func.blocks.len() {
+            let block = Block(idx as u32);
+            self.verify_block(block)
+                .map_err(|msg| self.error(Some(block), msg))?;
+        }
+        Ok(())
+    }
+
+    fn define(&mut self, val: Value, typ: Type, block: Block, pos: usize) -> Result<(), String> {
+        if self.types.insert(val, typ).is_some() {
+            return Err(self.error(Some(block), format!("{} is defined more than once", val)));
+        }
+        self.defs.insert(val, (block, pos));
+        Ok(())
+    }
+
+    // The type of a value used at a position, which its definition has to come before
+    fn operand(&self, val: Value, block: Block, pos: usize) -> Result<Type, String> {
+        let Some(&(def, def_pos)) = self.defs.get(&val) else {
+            return Err(format!("{} is never defined", val));
+        };
+
+        // Dominance means nothing in blocks that never run
+        let before = match def == block {
+            true => def_pos <= pos,
+            false => !self.cfg.is_reachable(block) || self.cfg.dominates(def, block),
+        };
+        match before {
+            true => Ok(self.types[&val]),
+            false => Err(format!("{} is used before it's defined", val)),
+        }
+    }
+
+    fn verify_block(&self, block: Block) -> Result<(), String> {
+        let data = &self.func[block];
+        let mut phis = true;
+        for (pos, inst) in data.insts.iter().enumerate() {
+            match &inst.op {
+                Op::Phi(incoming) if phis => self.verify_phi(block, inst, incoming)?,
+                Op::Phi(_) => return Err("phis have to come first".to_owned()),
+                _ => {
+                    phis = false;
+                    self.verify_inst(block, pos, inst)?;
+                }
+            }
+        }
+
+        let end = data.insts.len();
+        match data.term {
+            Terminator::Jump(to) => self.target(to)?,
+            Terminator::Branch(cond, yes, no) => {
+                self.target(yes)?;
+                self.target(no)?;
+                expect("the condition", self.operand(cond, block, end)?, Type::Bool)?;
+            }
+            Terminator::Return(val) => {
+                let typ = match val {
+                    Some(val) => Some(self.operand(val, block, end)?),
+                    None => None,
+                };
+                if typ != self.func.ret {
+                    return Err(format!(
+                        "returns {}, but the function returns {}",
+                        show(typ),
+                        show(self.func.ret)
+                    ));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn target(&self, block: Block) -> Result<(), String> {
+        match (block.0 as usize) < self.func.blocks.len() {
+            true if block.0 == 0 => Err("jumps to the entry".to_owned()),
+            true => Ok(()),
+            false => Err(format!("jumps to {}, which doesn't exist", block)),
+        }
+    }
+
+    // One value for every block control can come from, each defined by the end of that block
+    fn verify_phi(
+        &self,
+        block: Block,
+        inst: &Inst,
+        incoming: &[(Block, Value)],
+    ) -> Result<(), String> {
+        let typ = dest(inst)?;
+        let preds = self.cfg.preds(block);
+        for &(pred, val) in incoming {
+            if !preds.contains(&pred) {
+                return Err(format!(
+                    "phi has a value for {}, which doesn't jump here",
+                    pred
+                ));
+            }
+            if incoming.iter().filter(|(x, _)| *x == pred).count() > 1 {
+                return Err(format!("phi has more than one value for {}", pred));
+            }
+            let end = self.func[pred].insts.len();
+            expect("the phi operand", self.operand(val, pred, end)?, typ)?;
+        }
+        if let Some(pred) = preds.iter().find(|x| incoming.iter().all(|(y, _)| y != *x)) {
+            return Err(format!("phi has no value for {}", pred));
+        }
+        Ok(())
+    }
+
+    fn verify_inst(&self, block: Block, pos: usize, inst: &Inst) -> Result<(), String> {
+        let operand = |val: Value| self.operand(val, block, pos);
+        match &inst.op {
+            Op::Const(val) => {
+                let typ = dest(inst)?;
+                let fits = match (val, typ) {
+                    (Const::Int(x), Type::Int(size)) => {
+                        let bits = size as u32;
+                        bits == 128 || (-(1 << (bits - 1))..1 << (bits - 1)).contains(x)
+                    }
+                    (Const::Int(x), Type::Uint(size)) => {
+                        let bits = size as u32;
+                        bits == 128 || (0..1 << bits).contains(x)
+                    }
+                    (Const::Float(_), Type::Float(_)) | (Const::Bool(_), Type::Bool) => true,
+                    _ => false,
+                };
+                if !fits {
+                    return Err(format!("const {} isn't a {}", val, typ));
+                }
+            }
+            Op::Func(name) => {
+                expect("a function", dest(inst)?, Type::Func)?;
+                if self.module.func(name).is_none() {
+                    return Err(format!("@{} isn't a function", name));
+                }
+            }
+            Op::Binary(op, lhs, rhs) => {
+                let typ = operand(*lhs)?;
+                expect("the right side", operand(*rhs)?, typ)?;
+                if !op.allows(typ) {
+                    return Err(format!("{} can't be used on {}", op, typ));
+                }
+                let result = if op.is_comparison() { Type::Bool } else { typ };
+                expect(&format!("the result of {}", op), dest(inst)?, result)?;
+            }
+            Op::Unary(op, val) => {
+                let typ = operand(*val)?;
+                if !op.allows(typ) {
+                    return Err(format!("{} can't be used on {}", op, typ));
+                }
+                expect(&format!("the result of {}", op), dest(inst)?, typ)?;
+            }
+            Op::Phi(_) => unreachable!("phis are verified on their own"),
+            Op::Call(callee, args) => {
+                let args: Vec<Type> = args.iter().map(|&x| operand(x)).collect::<Result<_, _>>()?;
+                match callee {
+                    Callee::Direct(name) => {
+                        let Some(func) = self.module.func(name) else {
+                            return Err(format!("@{} isn't a function", name));
+                        };
+                        let params: Vec<Type> = func.params.iter().map(|x| x.1).collect();
+                        if args != params {
+                            return Err(format!("the arguments to @{} have the wrong types", name));
+                        }
+                        if inst.dest.is_some() && inst.dest.map(|x| x.1) != func.ret {
+                            return Err(format!(
+                                "@{} returns {}, not {}",
+                                name,
+                                show(func.ret),
+                                show(inst.dest.map(|x| x.1))
+                            ));
+                        }
+                    }
+                    Callee::Indirect(func) => expect("the callee", operand(*func)?, Type::Func)?,
+                }
+            }
+            Op::Load(name) => match self.module.global(name) {
+                Some(global) => expect(&format!("@{}", name), dest(inst)?, global.typ)?,
+                None => return Err(format!("@{} isn't a global", name)),
+            },
+            Op::Line(..) if inst.dest.is_some() => {
+                return Err("line doesn't have a result".to_owned())
+            }
+            Op::Line(..) => {}
+            Op::Store(name, val) => {
+                if inst.dest.is_some() {
+                    return Err("store doesn't have a result".to_owned());
+                }
+                match self.module.global(name) {
+                    Some(global) => expect(&format!("@{}", name), operand(*val)?, global.typ)?,
+                    None => return Err(format!("@{} isn't a global", name)),
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+fn dest(inst: &Inst) -> Result<Type, String> {
+    match inst.dest {
+        Some((_, typ)) => Ok(typ),
+        None => Err(format!("'{}' needs a result", inst.op)),
+    }
+}
+
+fn expect(what: &str, got: Type, expected: Type) -> Result<(), String> {
+    match got == expected {
+        true => Ok(()),
+        false => Err(format!("expected {} to be {}, got {}", what, expected, got)),
+    }
+}
+
+fn show(typ: Option<Type>) -> String {
+    match typ {
+        Some(typ) => typ.to_string(),
+        None => "nothing".to_owned(),
+    }
+}
@@ -0,0 +1,309 @@
+use core::fmt;
+
+// Just enough JSON to talk to other tools, without pulling in a crate
+#[derive(Debug, Clone, PartialEq)]
+pub enum Json {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Json>),
+    Object(Vec<(String, Json)>),
+}
+
+impl Json {
+    pub fn object(fields: Vec<(&str, Json)>) -> Json {
+        Json::Object(
+            fields
+                .into_iter()
+                .map(|(key, val)| (key.to_owned(), val))
+                .collect(),
+        )
+    }
+
+    pub fn str(val: &str) -> Json {
+        Json::String(val.to_owned())
+    }
+
+    pub fn num<T: Into<f64>>(val: T) -> Json {
+        Json::Number(val.into())
+    }
+}
+
+fn escape(f: &mut fmt::Formatter<'_>, src: &str) -> fmt::Result {
+    write!(f, "\"")?;
+    for chr in src.chars() {
+        match chr {
+            '"' => write!(f, "\\\"")?,
+            '\\' => write!(f, "\\\\")?,
+            '\n' => write!(f, "\\n")?,
+            '\r' => write!(f, "\\r")?,
+            '\t' => write!(f, "\\t")?,
+            chr if (chr as u32) < 0x20 => write!(f, "\\u{:04x}", chr as u32)?,
+            chr => write!(f, "{}", chr)?,
+        }
+    }
+    write!(f, "\"")
+}
+
+impl fmt::Display for Json {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Json::Null => write!(f, "null"),
+            Json::Bool(val) => write!(f, "{}", val),
+            Json::Number(val) if val.is_finite() => write!(f, "{}", val),
+            Json::Number(_) => write!(f, "null"),
+            Json::String(val) => escape(f, val),
+            Json::Array(vals) => {
+                write!(f, "[")?;
+                for (idx, val) in vals.iter().enumerate() {
+                    if idx != 0 {
+                        write!(f, ",")?;
+                    }
+                    write!(f, "{}", val)?;
+                }
+                write!(f, "]")
+            }
+            Json::Object(fields) => {
+                write!(f, "{{")?;
+                for (idx, (key, val)) in fields.iter().enumerate() {
+                    if idx != 0 {
+                        write!(f, ",")?;
+                    }
+                    escape(f, key)?;
+                    write!(f, ":{}", val)?;
+                }
+                write!(f, "}}")
+            }
+        }
+    }
+}
+
+impl Json {
+    pub fn parse(src: &str) -> Result<Json, String> {
+        let mut parser = JsonParser {
+            src: src.as_bytes(),
+            idx: 0,
+        };
+        let val = parser.value()?;
+        parser.skip_whitespace();
+        match parser.idx == src.len() {
+            true => Ok(val),
+            false => Err(format!(
+                "Unexpected data after the value at byte {}",
+                parser.idx
+            )),
+        }
+    }
+
+    // Null for anything that isn't there, so lookups can be chained
+    pub fn get(&self, key: &str) -> &Json {
+        match self {
+            Json::Object(fields) => fields
+                .iter()
+                .find(|(name, _)| name == key)
+                .map_or(&Json::Null, |(_, val)| val),
+            _ => &Json::Null,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Json::String(val) => Some(val),
+            _ => None,
+        }
+    }
+
+    pub fn as_u32(&self) -> Option<u32> {
+        match self {
+            Json::Number(val) if *val >= 0.0 && val.fract() == 0.0 => Some(*val as u32),
+            _ => None,
+        }
+    }
+
+    pub fn as_array(&self) -> &[Json] {
+        match self {
+            Json::Array(vals) => vals,
+            _ => &[],
+        }
+    }
+}
+
+struct JsonParser<'a> {
+    src: &'a [u8],
+    idx: usize,
+}
+
+impl JsonParser<'_> {
+    fn skip_whitespace(&mut self) {
+        while self.idx < self.src.len() && self.src[self.idx].is_ascii_whitespace() {
+            self.idx += 1;
+        }
+    }
+
+    fn error<T>(&self, msg: &str) -> Result<T, String> {
+        Err(format!("{} at byte {}", msg, self.idx))
+    }
+
+    fn expect(&mut self, text: &str) -> Result<(), String> {
+        match self.src[self.idx..].starts_with(text.as_bytes()) {
+            true => {
+                self.idx += text.len();
+                Ok(())
+            }
+            false => self.error(&format!("Expected '{}'", text)),
+        }
+    }
+
+    fn value(&mut self) -> Result<Json, String> {
+        self.skip_whitespace();
+        match self.src.get(self.idx) {
+            Some(b'n') => self.expect("null").map(|_| Json::Null),
+            Some(b't') => self.expect("true").map(|_| Json::Bool(true)),
+            Some(b'f') => self.expect("false").map(|_| Json::Bool(false)),
+            Some(b'"') => self.string().map(Json::String),
+            Some(b'[') => self.array(),
+            Some(b'{') => self.object(),
+            Some(b'-' | b'0'..=b'9') => self.number(),
+            Some(_) => self.error("Unexpected character"),
+            None => self.error("Unexpected end of input"),
+        }
+    }
+
+    fn number(&mut self) -> Result<Json, String> {
+        let start = self.idx;
+        while self.idx < self.src.len()
+            && matches!(
+                self.src[self.idx],
+                b'-' | b'+' | b'.' | b'e' | b'E' | b'0'..=b'9'
+            )
+        {
+            self.idx += 1;
+        }
+
+        let text = std::str::from_utf8(&self.src[start..self.idx]).unwrap();
+        match text.parse() {
+            Ok(val) => Ok(Json::Number(val)),
+            Err(_) => self.error(&format!("Invalid number '{}'", text)),
+        }
+    }
+
+    fn hex(&mut self) -> Result<u32, String> {
+        let digits = self.src.get(self.idx..self.idx + 4).unwrap_or_default();
+        let digits = std::str::from_utf8(digits).unwrap_or_default();
+        match u32::from_str_radix(digits, 16) {
+            Ok(val) if digits.len() == 4 => {
+                self.idx += 4;
+                Ok(val)
+            }
+            _ => self.error("Invalid unicode escape"),
+        }
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        self.idx += 1;
+        let mut bytes = Vec::new();
+        loop {
+            let Some(&byte) = self.src.get(self.idx) else {
+                return self.error("Unterminated string");
+            };
+            self.idx += 1;
+
+            let chr = match byte {
+                b'"' => break,
+                b'\\' => {
+                    let Some(&escape) = self.src.get(self.idx) else {
+                        return self.error("Unterminated string");
+                    };
+                    self.idx += 1;
+                    match escape {
+                        b'"' => '"',
+                        b'\\' => '\\',
+                        b'/' => '/',
+                        b'b' => '\u{8}',
+                        b'f' => '\u{c}',
+                        b'n' => '\n',
+                        b'r' => '\r',
+                        b't' => '\t',
+                        b'u' => {
+                            // Characters outside of the BMP come as a pair of surrogates
+                            let mut code = self.hex()?;
+                            if (0xd800..0xdc00).contains(&code) {
+                                self.expect("\\u")?;
+                                let low = self.hex()?;
+                                code =
+                                    0x10000 + ((code - 0xd800) << 10) + (low.wrapping_sub(0xdc00));
+                            }
+                            match char::from_u32(code) {
+                                Some(chr) => chr,
+                                None => return self.error("Invalid unicode escape"),
+                            }
+                        }
+                        _ => return self.error("Invalid escape"),
+                    }
+                }
+                _ => {
+                    bytes.push(byte);
+                    continue;
+                }
+            };
+            bytes.extend(chr.to_string().as_bytes());
+        }
+
+        String::from_utf8(bytes).or_else(|_| self.error("Invalid UTF-8 in string"))
+    }
+
+    fn array(&mut self) -> Result<Json, String> {
+        self.idx += 1;
+        let mut vals = Vec::new();
+        self.skip_whitespace();
+        if self.src.get(self.idx) == Some(&b']') {
+            self.idx += 1;
+            return Ok(Json::Array(vals));
+        }
+
+        loop {
+            vals.push(self.value()?);
+            self.skip_whitespace();
+            match self.src.get(self.idx) {
+                Some(b',') => self.idx += 1,
+                Some(b']') => {
+                    self.idx += 1;
+                    return Ok(Json::Array(vals));
+                }
+                _ => return self.error("Expected ',' or ']'"),
+            }
+        }
+    }
+
+    fn object(&mut self) -> Result<Json, String> {
+        self.idx += 1;
+        let mut fields = Vec::new();
+        self.skip_whitespace();
+        if self.src.get(self.idx) == Some(&b'}') {
+            self.idx += 1;
+            return Ok(Json::Object(fields));
+        }
+
+        loop {
+            self.skip_whitespace();
+            if self.src.get(self.idx) != Some(&b'"') {
+                return self.error("Expected a key");
+            }
+            let key = self.string()?;
+            self.skip_whitespace();
+            self.expect(":")?;
+            fields.push((key, self.value()?));
+
+            self.skip_whitespace();
+            match self.src.get(self.idx) {
+                Some(b',') => self.idx += 1,
+                Some(b'}') => {
+                    self.idx += 1;
+                    return Ok(Json::Object(fields));
+                }
+                _ => return self.error("Expected ',' or '}'"),
+            }
+        }
+    }
+}
@@ -1,63 +1,60 @@
 use std::rc::Rc;
 
 use crate::{
-    location::Location,
+    location::Span,
+    source::FileId,
     tokens::{Token, Type, KEYWORDS, SYMBOLS},
 };
 
 // Lexer
 pub struct Lexer {
     source: Rc<String>,
-    loc: Location,
+    file: FileId,
+    idx: u32,
     tokens: Vec<Token>,
 }
 
 impl Lexer {
-    pub fn new(code: Rc<String>) -> Lexer {
+    pub fn new(code: Rc<String>, file: FileId) -> Lexer {
         Lexer {
             source: code,
-            loc: Location::empty(),
+            file,
+            idx: 0,
             tokens: Vec::new(),
         }
     }
 
-    fn at(&self) -> String {
-        self.source
-            .chars()
-            .nth(self.loc.idx as usize)
-            .unwrap()
-            .to_string()
+    fn rest(&self) -> &str {
+        &self.source[self.idx as usize..]
+    }
+
+    fn at(&self) -> char {
+        self.rest().chars().next().unwrap()
     }
 
     fn ahead(&self, count: usize) -> String {
         // Take caps out at the length, so no need to implement anything there
-        self.source
-            .chars()
-            .skip(self.loc.idx as usize)
-            .take(count)
-            .collect()
+        self.rest().chars().take(count).collect()
     }
 
-    fn push(&mut self, token: Token) {
-        self.loc.idx += token.size;
-        if token.typ == Type::NewLine {
-            self.loc.row += 1;
-            self.loc.col = 0;
-        } else {
-            self.loc.col += token.size;
-        }
+    fn span(&self, lo: u32) -> Span {
+        Span::new(self.file, lo, self.idx)
+    }
 
-        self.tokens
-            .push(Token::new(token.start, self.loc.clone(), token.typ));
+    fn push(&mut self, typ: Type, size: u32) {
+        let lo = self.idx;
+        self.idx += size;
+
+        self.tokens.push(Token::new(self.span(lo), typ));
     }
 
-    fn symbol(&mut self) -> (bool, Token) {
+    fn symbol(&self) -> Option<(Type, u32)> {
         let mut max_len = 0;
         let mut successful_pattern = Type::EOF;
         for pattern in SYMBOLS {
             let sources = pattern.src_strings();
             for src in sources {
-                if self.ahead(src.len()) == src && src.len() > max_len {
+                if self.rest().starts_with(&src) && src.len() > max_len {
                     max_len = src.len();
                     successful_pattern = pattern.clone();
                 }
@@ -65,21 +62,36 @@ impl Lexer {
         }
 
         if max_len == 0 {
-            (false, Token::empty())
+            None
         } else {
-            let token = Token::open(self.loc.clone(), successful_pattern, max_len as u32);
-            (true, token)
+            Some((successful_pattern, max_len as u32))
         }
     }
 
-    fn push_identifier(&mut self, capture: &String, cap_start: Location) -> bool {
-        let size = capture.len();
-        if size != 0 {
-            let value = capture.clone();
-            let typ = match capture.clone().parse::<f32>() {
+    // A dot only belongs to a number when it sits between two digits, like 4.2. Numbers right
+    // after a dot are tuple fields, so pair.0.1 is two of them
+    fn is_decimal_point(&self, capture: &str, symbol: &Type) -> bool {
+        *symbol == Type::Dot
+            && !capture.is_empty()
+            && !matches!(
+                self.tokens.last().map(|x| &x.typ),
+                Some(Type::Dot | Type::QuestionDot)
+            )
+            && capture.chars().all(|c| c.is_ascii_digit())
+            && self
+                .ahead(2)
+                .chars()
+                .nth(1)
+                .is_some_and(|c| c.is_ascii_digit())
+    }
+
+    fn push_identifier(&mut self, capture: &str, cap_start: u32) -> bool {
+        if !capture.is_empty() {
+            let value = capture.to_owned();
+            let typ = match capture.parse::<f32>() {
                 Ok(num) => Type::Number(num), // TODO: Ignore inf, -inf, nan, etc
                 Err(_) => {
-                    let mut ident_typ = Type::Identifier(capture.clone());
+                    let mut ident_typ = Type::Identifier(value.clone());
                     'main: for keyword in KEYWORDS {
                         for src in keyword.src_strings() {
                             if value == src {
@@ -93,7 +105,7 @@ impl Lexer {
                 }
             };
 
-            let token = Token::new(cap_start, self.loc, typ);
+            let token = Token::new(self.span(cap_start), typ);
             self.tokens.push(token);
 
             return true;
@@ -101,30 +113,52 @@ impl Lexer {
         false
     }
 
+    fn push_comment(&mut self) {
+        let len = self.rest().find('\n').unwrap_or(self.rest().len());
+        let text = self.rest()[..len].to_owned();
+        self.push(Type::Comment(text), len as u32);
+    }
+
+    // Every character ends up in exactly one token, so the source can be put back together
     pub fn lex(&mut self) -> Vec<Token> {
         let mut capture = String::new();
-        let mut cap_start = Location::empty();
-        while self.loc.idx < self.source.len() as u32 {
-            let (is_symbol, symbol) = self.symbol();
-            if !is_symbol {
-                if capture.len() == 0 {
-                    cap_start = self.loc.clone();
-                }
-
-                capture += &self.at();
-                self.loc.next();
-            } else {
+        let mut cap_start = self.idx;
+        while self.idx < self.source.len() as u32 {
+            if self.rest().starts_with("//") {
                 if self.push_identifier(&capture, cap_start) {
                     capture.clear();
-                };
+                }
+                self.push_comment();
+                continue;
+            }
+
+            match self.symbol() {
+                Some((typ, _)) if self.is_decimal_point(&capture, &typ) => {
+                    capture.push('.');
+                    self.idx += 1;
+                }
+                Some((typ, size)) => {
+                    if self.push_identifier(&capture, cap_start) {
+                        capture.clear();
+                    };
 
-                self.push(symbol);
+                    self.push(typ, size);
+                }
+                None => {
+                    if capture.is_empty() {
+                        cap_start = self.idx;
+                    }
+
+                    let chr = self.at();
+                    capture.push(chr);
+                    self.idx += chr.len_utf8() as u32;
+                }
             }
         }
         self.push_identifier(&capture, cap_start);
 
         // Add EOF token
-        self.push(Token::new(self.loc, self.loc, Type::EOF));
+        self.push(Type::EOF, 0);
 
         self.tokens.clone()
     }
@@ -133,11 +167,7 @@ impl Lexer {
         self.tokens
             .clone()
             .into_iter()
-            .filter(|tok| tok.typ != Type::Whitespace)
+            .filter(|tok| !matches!(tok.typ, Type::Whitespace | Type::Comment(_)))
             .collect()
     }
 }
-
-pub fn source_lines(source: Rc<String>) -> Rc<Vec<String>> {
-    Rc::new(source.split("\n").map(|s| s.to_owned()).collect())
-}
@@ -0,0 +1,26 @@
+pub mod ast;
+pub mod backend;
+pub mod checker;
+pub mod driver;
+pub mod error;
+pub mod format;
+pub mod interpreter;
+pub mod ir;
+pub mod json;
+pub mod lexer;
+pub mod lint;
+pub mod location;
+pub mod lsp;
+pub mod module;
+pub mod parser;
+pub mod printer;
+pub mod program;
+pub mod read;
+pub mod repl;
+pub mod resolver;
+pub mod scope;
+pub mod source;
+pub mod suggest;
+pub mod symbols;
+pub mod tokens;
+pub mod types;
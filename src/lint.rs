@@ -0,0 +1,140 @@
+use core::fmt;
+
+use crate::location::Span;
+
+// Problems that don't stop the program from compiling, but probably aren't intended
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Lint {
+    UnusedVariables,
+    UnusedFunctions,
+    UnusedParams,
+    Shadowing,
+    RedundantParens,
+    UnneededNewlines,
+    UnreachablePatterns,
+}
+
+impl Lint {
+    pub const VALUES: [Lint; 7] = [
+        Lint::UnusedVariables,
+        Lint::UnusedFunctions,
+        Lint::UnusedParams,
+        Lint::Shadowing,
+        Lint::RedundantParens,
+        Lint::UnneededNewlines,
+        Lint::UnreachablePatterns,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Lint::UnusedVariables => "unused_variables",
+            Lint::UnusedFunctions => "unused_functions",
+            Lint::UnusedParams => "unused_params",
+            Lint::Shadowing => "shadowing",
+            Lint::RedundantParens => "redundant_parens",
+            Lint::UnneededNewlines => "unneeded_newlines",
+            Lint::UnreachablePatterns => "unreachable_patterns",
+        }
+    }
+
+    pub fn from(src: &str) -> Option<Lint> {
+        Lint::VALUES.into_iter().find(|lint| lint.name() == src)
+    }
+
+    // "warnings" stands for every lint at once
+    pub fn group(src: &str) -> Option<Vec<Lint>> {
+        match src {
+            "warnings" => Some(Lint::VALUES.to_vec()),
+            _ => Lint::from(src).map(|lint| vec![lint]),
+        }
+    }
+}
+
+impl fmt::Display for Lint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Level {
+    Allow,
+    Warn,
+    Deny,
+}
+
+impl Level {
+    pub fn from(src: &str) -> Option<Level> {
+        match src {
+            "allow" => Some(Level::Allow),
+            "warn" => Some(Level::Warn),
+            "deny" => Some(Level::Deny),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Level {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Level::Allow => write!(f, "allow"),
+            Level::Warn => write!(f, "warn"),
+            Level::Deny => write!(f, "deny"),
+        }
+    }
+}
+
+// A level set by an attribute, only for the code it's attached to
+#[derive(Debug, Clone)]
+struct Attribute {
+    span: Span,
+    lint: Lint,
+    level: Level,
+}
+
+#[derive(Debug, Clone)]
+pub struct LintLevels {
+    global: [Level; Lint::VALUES.len()],
+    attrs: Vec<Attribute>,
+}
+
+impl Default for LintLevels {
+    fn default() -> LintLevels {
+        LintLevels {
+            global: [Level::Warn; Lint::VALUES.len()],
+            attrs: Vec::new(),
+        }
+    }
+}
+
+impl LintLevels {
+    pub fn set(&mut self, lint: Lint, level: Level) {
+        self.global[lint as usize] = level;
+    }
+
+    // Returns the attribute's index, so the span can be grown once the statement is parsed
+    pub fn attach(&mut self, span: Span, lint: Lint, level: Level) -> usize {
+        self.attrs.push(Attribute { span, lint, level });
+        self.attrs.len() - 1
+    }
+
+    pub fn extend(&mut self, idx: usize, hi: u32) {
+        self.attrs[idx].span.hi = hi;
+    }
+
+    // The closest attribute around the span wins, otherwise whatever was set globally
+    pub fn level(&self, lint: Lint, span: Span) -> Level {
+        self.attrs
+            .iter()
+            .rev()
+            .filter(|attr| {
+                attr.lint == lint
+                    && attr.span.file == span.file
+                    && attr.span.lo <= span.lo
+                    && span.hi <= attr.span.hi
+            })
+            .min_by_key(|attr| attr.span.len())
+            .map(|attr| attr.level)
+            .unwrap_or(self.global[lint as usize])
+    }
+}
@@ -1,7 +1,11 @@
 use core::fmt;
 
+use crate::source::FileId;
+
+// A line and column, only worked out from a Span when it's needed
 #[derive(Debug, Copy, Clone)]
 pub struct Location {
+    pub file: FileId,
     pub row: u32,
     pub col: u32,
     pub idx: u32,
@@ -14,28 +18,49 @@ impl fmt::Display for Location {
 }
 
 impl Location {
-    pub fn new(row: u32, col: u32, idx: u32) -> Location {
-        Location { row, col, idx }
-    }
-
-    pub fn empty() -> Location {
+    pub fn new(file: FileId, row: u32, col: u32, idx: u32) -> Location {
         Location {
-            row: 0,
-            col: 0,
-            idx: 0,
+            file,
+            row,
+            col,
+            idx,
         }
     }
+}
+
+// Byte offsets into a single file, lo is inclusive and hi is exclusive
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct Span {
+    pub file: FileId,
+    pub lo: u32,
+    pub hi: u32,
+}
+
+impl fmt::Display for Span {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "#{}..{}", self.lo, self.hi)
+    }
+}
 
-    pub fn shift(&mut self, count: u32) -> Location {
-        self.idx += count;
-        self.col += count;
+impl Span {
+    pub fn new(file: FileId, lo: u32, hi: u32) -> Span {
+        Span { file, lo, hi }
+    }
 
-        self.clone()
+    pub fn empty(file: FileId) -> Span {
+        Span::new(file, 0, 0)
     }
 
-    pub fn next(&mut self) -> Location {
-        self.shift(1);
+    pub fn len(&self) -> u32 {
+        self.hi - self.lo
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lo == self.hi
+    }
 
-        self.clone()
+    // Covers both spans and everything between them
+    pub fn to(&self, end: Span) -> Span {
+        Span::new(self.file, self.lo.min(end.lo), self.hi.max(end.hi))
     }
 }
@@ -0,0 +1,416 @@
+use std::{
+    collections::HashMap,
+    io::{self, BufRead, Write},
+    path::{Path, PathBuf},
+    rc::Rc,
+};
+
+pub(crate) mod analysis;
+pub(crate) mod position;
+pub(crate) mod rpc;
+
+pub use self::{
+    position::{path_to_uri, uri_to_path},
+    rpc::{read_message, write_message},
+};
+
+use self::{
+    analysis::Outline,
+    position::{offset, range},
+    rpc::{INVALID_PARAMS, INVALID_REQUEST, METHOD_NOT_FOUND, PARSE_ERROR, SERVER_NOT_INITIALIZED},
+};
+use crate::{
+    driver,
+    error::{Error, ErrorFormat, Reporter, Severity},
+    json::Json,
+    location::Span,
+    module::ModuleGraph,
+    read,
+    source::SourceMap,
+    symbols::SymbolKind,
+};
+
+// Symbol and completion kinds from the spec
+const SYMBOL_FUNCTION: u32 = 12;
+const COMPLETION_METHOD: u32 = 2;
+const COMPLETION_FUNCTION: u32 = 3;
+const COMPLETION_VARIABLE: u32 = 6;
+const COMPLETION_INTERFACE: u32 = 8;
+const COMPLETION_ENUM: u32 = 13;
+const COMPLETION_FIELD: u32 = 5;
+const COMPLETION_ENUM_MEMBER: u32 = 20;
+const COMPLETION_STRUCT: u32 = 22;
+const COMPLETION_TYPE_PARAM: u32 = 25;
+
+// Reads messages until the client sends exit or goes away, the result is the exit code
+pub fn run(mut input: impl BufRead, out: impl Write) -> i32 {
+    let mut server = Server::new(out);
+    loop {
+        let msg = match read_message(&mut input) {
+            Ok(Some(msg)) => msg,
+            Ok(None) | Err(_) => return 1,
+        };
+
+        match server.handle(&msg) {
+            Ok(true) => (),
+            Ok(false) => return if server.shutdown { 0 } else { 1 },
+            Err(_) => return 1,
+        }
+    }
+}
+
+struct Document {
+    text: String,
+    // From the last time the document parsed, so a half typed line keeps hover and completion
+    graph: Option<ModuleGraph>,
+    // Every file that got diagnostics from this document, so they can be cleared again
+    published: Vec<String>,
+}
+
+type Response = Result<Json, (i32, String)>;
+
+pub struct Server<W: Write> {
+    out: W,
+    docs: HashMap<String, Document>,
+    initialized: bool,
+    shutdown: bool,
+}
+
+impl<W: Write> Server<W> {
+    pub fn new(out: W) -> Server<W> {
+        Server {
+            out,
+            docs: HashMap::new(),
+            initialized: false,
+            shutdown: false,
+        }
+    }
+
+    // False once the client has asked the server to exit
+    pub fn handle(&mut self, msg: &str) -> io::Result<bool> {
+        let msg = match Json::parse(msg) {
+            Ok(msg) => msg,
+            Err(err) => {
+                return write_message(&mut self.out, &rpc::error(Json::Null, PARSE_ERROR, &err))
+                    .map(|_| true)
+            }
+        };
+
+        let method = msg.get("method").as_str().unwrap_or_default();
+        let params = msg.get("params");
+        let id = msg.get("id");
+
+        // Anything without an id is a notification, and never gets an answer
+        if *id == Json::Null {
+            return self.notification(method, params);
+        }
+
+        let res = match method {
+            "initialize" => {
+                self.initialized = true;
+                Ok(capabilities())
+            }
+            _ if !self.initialized => Err((
+                SERVER_NOT_INITIALIZED,
+                "The server hasn't been initialized".to_owned(),
+            )),
+            _ if self.shutdown => Err((INVALID_REQUEST, "The server is shutting down".to_owned())),
+            "shutdown" => {
+                self.shutdown = true;
+                Ok(Json::Null)
+            }
+            "textDocument/hover" => self.hover(params),
+            "textDocument/definition" => self.definition(params),
+            "textDocument/documentSymbol" => self.document_symbols(params),
+            "textDocument/completion" => self.completion(params),
+            _ => Err((METHOD_NOT_FOUND, format!("Unknown method '{}'", method))),
+        };
+
+        let msg = match res {
+            Ok(result) => rpc::response(id.clone(), result),
+            Err((code, msg)) => rpc::error(id.clone(), code, &msg),
+        };
+        write_message(&mut self.out, &msg)?;
+        Ok(true)
+    }
+
+    fn notification(&mut self, method: &str, params: &Json) -> io::Result<bool> {
+        let uri = params.get("textDocument").get("uri").as_str();
+        match (method, uri) {
+            ("exit", _) => return Ok(false),
+            ("textDocument/didOpen", Some(uri)) => {
+                let text = params.get("textDocument").get("text").as_str();
+                self.docs.insert(
+                    uri.to_owned(),
+                    Document {
+                        text: text.unwrap_or_default().to_owned(),
+                        graph: None,
+                        published: Vec::new(),
+                    },
+                );
+                self.analyze(uri)?;
+            }
+            // Documents are always synced whole, so the last change has all of the text
+            ("textDocument/didChange", Some(uri)) => {
+                let changes = params.get("contentChanges").as_array();
+                let text = changes.last().and_then(|x| x.get("text").as_str());
+                if let (Some(doc), Some(text)) = (self.docs.get_mut(uri), text) {
+                    doc.text = text.to_owned();
+                    self.analyze(uri)?;
+                }
+            }
+            ("textDocument/didClose", Some(uri)) => {
+                if let Some(doc) = self.docs.remove(uri) {
+                    for uri in doc.published {
+                        self.publish(&uri, Vec::new())?;
+                    }
+                }
+            }
+            _ => (),
+        }
+
+        Ok(true)
+    }
+
+    // Open documents are read from the editor, so imports see changes that haven't been saved
+    fn analyze(&mut self, uri: &str) -> io::Result<()> {
+        let Some(root) = uri_to_path(uri) else {
+            return Ok(());
+        };
+
+        let texts: HashMap<PathBuf, String> = self
+            .docs
+            .iter()
+            .filter_map(|(uri, doc)| Some((uri_to_path(uri)?, doc.text.clone())))
+            .collect();
+        let read = |path: &Path| match texts.get(path) {
+            Some(text) => Ok(text.clone()),
+            None => read::read_file(path),
+        };
+
+        let reporter = Reporter::new(SourceMap::new(), false, ErrorFormat::Silent);
+        let graph = driver::analyze(Rc::clone(&reporter), &root, &read);
+
+        let mut diagnostics: Vec<(String, Vec<Json>)> = reporter
+            .map
+            .files()
+            .iter()
+            .map(|file| (path_to_uri(&file.path), Vec::new()))
+            .collect();
+        for err in reporter.diagnostics() {
+            diagnostics[err.span.file.0 as usize]
+                .1
+                .push(diagnostic(&reporter.map, &err));
+        }
+
+        let doc = self.docs.get_mut(uri).unwrap();
+        let stale: Vec<String> = doc
+            .published
+            .drain(..)
+            .filter(|uri| diagnostics.iter().all(|(x, _)| x != uri))
+            .collect();
+        doc.published = diagnostics.iter().map(|(uri, _)| uri.clone()).collect();
+        if graph.is_some() {
+            doc.graph = graph;
+        }
+
+        for uri in stale {
+            self.publish(&uri, Vec::new())?;
+        }
+        for (uri, diagnostics) in diagnostics {
+            self.publish(&uri, diagnostics)?;
+        }
+        Ok(())
+    }
+
+    fn publish(&mut self, uri: &str, diagnostics: Vec<Json>) -> io::Result<()> {
+        let params = Json::object(vec![
+            ("uri", Json::str(uri)),
+            ("diagnostics", Json::Array(diagnostics)),
+        ]);
+        write_message(
+            &mut self.out,
+            &rpc::notification("textDocument/publishDiagnostics", params),
+        )
+    }
+
+    // The analysis of the document a request is about, and the byte offset it points at
+    fn locate(&self, params: &Json) -> Result<Option<(&ModuleGraph, u32)>, (i32, String)> {
+        let uri = params.get("textDocument").get("uri").as_str();
+        let Some(uri) = uri else {
+            return Err((INVALID_PARAMS, "Expected a text document".to_owned()));
+        };
+        let Some(graph) = self.docs.get(uri).and_then(|doc| doc.graph.as_ref()) else {
+            return Ok(None);
+        };
+
+        let file = graph.reporter.map.get(graph.root().file);
+        match offset(&file, params.get("position")) {
+            Some(offset) => Ok(Some((graph, offset))),
+            None => Err((INVALID_PARAMS, "Expected a position".to_owned())),
+        }
+    }
+
+    fn hover(&self, params: &Json) -> Response {
+        let Some((graph, offset)) = self.locate(params)? else {
+            return Ok(Json::Null);
+        };
+
+        Ok(match analysis::hover(graph, offset) {
+            Some((span, text)) => Json::object(vec![
+                (
+                    "contents",
+                    Json::object(vec![
+                        ("kind", Json::str("markdown")),
+                        ("value", Json::str(&format!("```fusion\n{}\n```", text))),
+                    ]),
+                ),
+                ("range", range(&graph.reporter.map, span)),
+            ]),
+            None => Json::Null,
+        })
+    }
+
+    fn definition(&self, params: &Json) -> Response {
+        let Some((graph, offset)) = self.locate(params)? else {
+            return Ok(Json::Null);
+        };
+
+        Ok(match analysis::definition(graph, offset) {
+            Some(span) => location(&graph.reporter.map, span),
+            None => Json::Null,
+        })
+    }
+
+    // Symbols don't need a position, but the document does have to have parsed
+    fn document_symbols(&self, params: &Json) -> Response {
+        let uri = params.get("textDocument").get("uri").as_str();
+        let graph = uri
+            .and_then(|uri| self.docs.get(uri))
+            .and_then(|doc| doc.graph.as_ref());
+
+        Ok(match graph {
+            Some(graph) => {
+                let outline = analysis::outline(&graph.root().prog);
+                Json::Array(symbols(&graph.reporter.map, &outline))
+            }
+            None => Json::Array(Vec::new()),
+        })
+    }
+
+    fn completion(&self, params: &Json) -> Response {
+        let Some((graph, offset)) = self.locate(params)? else {
+            return Ok(Json::Array(Vec::new()));
+        };
+
+        let items = analysis::completions(graph, offset)
+            .into_iter()
+            .map(|sym| {
+                let kind = match sym.kind {
+                    SymbolKind::Function => COMPLETION_FUNCTION,
+                    SymbolKind::Variable | SymbolKind::Param => COMPLETION_VARIABLE,
+                    SymbolKind::Enum => COMPLETION_ENUM,
+                    SymbolKind::Variant => COMPLETION_ENUM_MEMBER,
+                    SymbolKind::Struct => COMPLETION_STRUCT,
+                    SymbolKind::Field => COMPLETION_FIELD,
+                    SymbolKind::TypeParam => COMPLETION_TYPE_PARAM,
+                    SymbolKind::Trait => COMPLETION_INTERFACE,
+                    SymbolKind::Method => COMPLETION_METHOD,
+                };
+                let detail = sym
+                    .typ
+                    .map_or(Json::Null, |typ| Json::str(&typ.to_string()));
+                Json::object(vec![
+                    ("label", Json::str(&sym.name)),
+                    ("kind", Json::num(kind)),
+                    ("detail", detail),
+                ])
+            })
+            .collect();
+        Ok(Json::Array(items))
+    }
+}
+
+fn capabilities() -> Json {
+    Json::object(vec![
+        (
+            "capabilities",
+            Json::object(vec![
+                ("positionEncoding", Json::str("utf-16")),
+                // Full text on every change
+                ("textDocumentSync", Json::num(1)),
+                ("hoverProvider", Json::Bool(true)),
+                ("definitionProvider", Json::Bool(true)),
+                ("documentSymbolProvider", Json::Bool(true)),
+                ("completionProvider", Json::object(Vec::new())),
+            ]),
+        ),
+        (
+            "serverInfo",
+            Json::object(vec![
+                ("name", Json::str("fusion")),
+                ("version", Json::str(env!("CARGO_PKG_VERSION"))),
+            ]),
+        ),
+    ])
+}
+
+fn location(map: &SourceMap, span: Span) -> Json {
+    Json::object(vec![
+        ("uri", Json::str(&path_to_uri(&map.get(span.file).path))),
+        ("range", range(map, span)),
+    ])
+}
+
+// Notes go under the message, since editors only show the message and the labels
+fn diagnostic(map: &SourceMap, err: &Error) -> Json {
+    let severity = match err.severity {
+        Severity::Error => 1,
+        Severity::Warning => 2,
+        Severity::Note => 3,
+    };
+    let code = match err.lint {
+        Some(lint) => lint.name().to_owned(),
+        None => err.id.code(),
+    };
+
+    let mut msg = err.msg.clone();
+    for note in &err.notes {
+        msg.push_str(&format!("\n{}: {}", note.kind, note.msg));
+    }
+
+    let related = err
+        .labels
+        .iter()
+        .map(|label| {
+            Json::object(vec![
+                ("location", location(map, label.span)),
+                ("message", Json::str(&label.msg)),
+            ])
+        })
+        .collect();
+
+    Json::object(vec![
+        ("range", range(map, err.span)),
+        ("severity", Json::num(severity)),
+        ("code", Json::str(&code)),
+        ("source", Json::str("fusion")),
+        ("message", Json::str(&msg)),
+        ("relatedInformation", Json::Array(related)),
+    ])
+}
+
+fn symbols(map: &SourceMap, outline: &[Outline]) -> Vec<Json> {
+    outline
+        .iter()
+        .map(|item| {
+            Json::object(vec![
+                ("name", Json::str(&item.name)),
+                ("kind", Json::num(SYMBOL_FUNCTION)),
+                ("range", range(map, item.span)),
+                ("selectionRange", range(map, item.name_span)),
+                ("children", Json::Array(symbols(map, &item.children))),
+            ])
+        })
+        .collect()
+}
@@ -0,0 +1,127 @@
+use std::rc::Rc;
+
+use crate::{
+    ast::{
+        self,
+        visit::{walk, Visitor},
+        Ast, NodeId, NodeKind, Stmt,
+    },
+    location::Span,
+    module::ModuleGraph,
+    program::Program,
+    symbols::{Symbol, SymbolKind},
+};
+
+// The smallest node around the offset that the filter accepts, a name wins over an expression
+// with the same span
+fn node_at(prog: &Program, offset: u32, filter: impl Fn(NodeId) -> bool) -> Option<NodeId> {
+    prog.ast
+        .ids()
+        .filter(|&id| {
+            let span = prog.ast.span(id);
+            span.lo <= offset && offset <= span.hi && filter(id)
+        })
+        .min_by_key(|&id| (prog.ast.span(id).len(), !prog.names.contains(id)))
+}
+
+pub fn hover(graph: &ModuleGraph, offset: u32) -> Option<(Span, String)> {
+    let prog = &graph.root().prog;
+    let id = node_at(prog, offset, |id| {
+        prog.names.contains(id) || prog.types.contains(id)
+    })?;
+
+    let text = match prog.names.get(id) {
+        Some(&sym) => {
+            let symbols = graph.symbols.borrow();
+            let sym = symbols.get(sym);
+            match (&sym.typ, prog.types.get(id)) {
+                (Some(typ), _) | (None, Some(typ)) => format!("{}: {}", sym.name, typ),
+                (None, None) => sym.name.clone(),
+            }
+        }
+        None => prog.types.get(id)?.to_string(),
+    };
+
+    Some((prog.ast.span(id), text))
+}
+
+// Where the name under the cursor was declared, which can be in another module
+pub fn definition(graph: &ModuleGraph, offset: u32) -> Option<Span> {
+    let prog = &graph.root().prog;
+    let id = node_at(prog, offset, |id| prog.names.contains(id))?;
+    let sym = *prog.names.get(id)?;
+    let span = graph.symbols.borrow().get(sym).span;
+    Some(span)
+}
+
+// Every name that can be used at the offset. Variables only count once they're declared,
+// functions and imports can be used anywhere in their scope
+pub fn completions(graph: &ModuleGraph, offset: u32) -> Vec<Symbol> {
+    let prog = &graph.root().prog;
+    let block = node_at(prog, offset, |id| {
+        matches!(prog.ast.kind(id), NodeKind::Block(_))
+    });
+    let scope = match block.map(|id| prog.ast.kind(id)) {
+        Some(NodeKind::Block(block)) => Rc::clone(&block.scope),
+        _ => prog.scope(),
+    };
+
+    let scope = scope.borrow();
+    let symbols = graph.symbols.borrow();
+    let mut names = scope.names();
+    names.sort();
+    names.dedup();
+
+    names
+        .into_iter()
+        .filter_map(|name| scope.get(&name))
+        .map(|id| symbols.get(id).clone())
+        .filter(|sym| {
+            matches!(sym.kind, SymbolKind::Function | SymbolKind::Enum)
+                || sym.span.file != graph.root().file
+                || sym.span.hi <= offset
+        })
+        .collect()
+}
+
+// A function and the functions declared inside of it
+#[derive(Debug, Clone)]
+pub struct Outline {
+    pub name: String,
+    pub span: Span,
+    pub name_span: Span,
+    pub children: Vec<Outline>,
+}
+
+pub fn outline(prog: &Program) -> Vec<Outline> {
+    let mut visitor = OutlineVisitor {
+        ast: &prog.ast,
+        levels: vec![Vec::new()],
+    };
+    visitor.visit_block(prog.root);
+    visitor.levels.pop().unwrap()
+}
+
+struct OutlineVisitor<'a> {
+    ast: &'a Ast,
+    levels: Vec<Vec<Outline>>,
+}
+
+impl<'a> Visitor<'a> for OutlineVisitor<'a> {
+    fn ast(&self) -> &'a Ast {
+        self.ast
+    }
+
+    fn visit_func(&mut self, func: &'a ast::Func, stmt: ast::Node<Stmt>) {
+        self.levels.push(Vec::new());
+        walk::walk_func(self, func);
+        let children = self.levels.pop().unwrap();
+
+        self.levels.last_mut().unwrap().push(Outline {
+            name: self.ast[func.name].name.clone(),
+            span: self.ast.span(stmt),
+            name_span: self.ast.span(func.name),
+            children,
+        });
+    }
+}
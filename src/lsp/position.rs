@@ -0,0 +1,75 @@
+use std::path::PathBuf;
+
+use crate::{
+    json::Json,
+    location::{Location, Span},
+    source::{SourceFile, SourceMap},
+};
+
+// LSP counts columns in UTF-16 code units, which only matches bytes or chars for ASCII
+pub fn position(file: &SourceFile, loc: Location) -> Json {
+    let start = file.line_start(loc.row) as usize;
+    let col = file.src[start..loc.idx as usize].encode_utf16().count();
+    Json::object(vec![
+        ("line", Json::num(loc.row)),
+        ("character", Json::num(col as u32)),
+    ])
+}
+
+pub fn range(map: &SourceMap, span: Span) -> Json {
+    let file = map.get(span.file);
+    Json::object(vec![
+        ("start", position(&file, map.start(span))),
+        ("end", position(&file, map.end(span))),
+    ])
+}
+
+// The byte offset of a position, anything past the end of a line is the end of that line
+pub fn offset(file: &SourceFile, pos: &Json) -> Option<u32> {
+    let row = pos.get("line").as_u32()?;
+    let col = pos.get("character").as_u32()? as usize;
+    if row >= file.line_count() {
+        return Some(file.src.len() as u32);
+    }
+
+    let start = file.line_start(row);
+    let mut units = 0;
+    for (idx, chr) in file.line(row).char_indices() {
+        if units >= col {
+            return Some(start + idx as u32);
+        }
+        units += chr.len_utf16();
+    }
+    Some(start + file.line(row).len() as u32)
+}
+
+// Only the characters that can't appear in a URI path get escaped
+pub fn path_to_uri(path: &str) -> String {
+    let mut uri = "file://".to_owned();
+    for byte in path.bytes() {
+        match byte {
+            b'a'..=b'z' | b'A'..=b'Z' | b'0'..=b'9' | b'/' | b'-' | b'_' | b'.' | b'~' => {
+                uri.push(byte as char)
+            }
+            _ => uri.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    uri
+}
+
+pub fn uri_to_path(uri: &str) -> Option<PathBuf> {
+    let path = uri.strip_prefix("file://")?.as_bytes();
+    let mut bytes = Vec::new();
+    let mut idx = 0;
+    while idx < path.len() {
+        if path[idx] == b'%' {
+            let hex = std::str::from_utf8(path.get(idx + 1..idx + 3)?).ok()?;
+            bytes.push(u8::from_str_radix(hex, 16).ok()?);
+            idx += 3;
+        } else {
+            bytes.push(path[idx]);
+            idx += 1;
+        }
+    }
+    String::from_utf8(bytes).ok().map(PathBuf::from)
+}
@@ -0,0 +1,78 @@
+use std::io::{self, BufRead, Write};
+
+use crate::json::Json;
+
+// Error codes from the JSON-RPC and LSP specs
+pub const PARSE_ERROR: i32 = -32700;
+pub const INVALID_REQUEST: i32 = -32600;
+pub const METHOD_NOT_FOUND: i32 = -32601;
+pub const INVALID_PARAMS: i32 = -32602;
+pub const SERVER_NOT_INITIALIZED: i32 = -32002;
+
+// Every message is a Content-Length header, a blank line and then that many bytes of JSON.
+// Nothing left to read before the headers is the client going away
+pub fn read_message(input: &mut impl BufRead) -> io::Result<Option<String>> {
+    let mut len = None;
+    loop {
+        let mut line = String::new();
+        if input.read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        if let Some((name, val)) = line.split_once(':') {
+            if name.eq_ignore_ascii_case("Content-Length") {
+                len = val.trim().parse::<usize>().ok();
+            }
+        }
+    }
+
+    let Some(len) = len else {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "Message without a Content-Length header",
+        ));
+    };
+
+    let mut body = vec![0; len];
+    input.read_exact(&mut body)?;
+    String::from_utf8(body)
+        .map(Some)
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+}
+
+pub fn write_message(out: &mut impl Write, msg: &Json) -> io::Result<()> {
+    let body = msg.to_string();
+    write!(out, "Content-Length: {}\r\n\r\n{}", body.len(), body)?;
+    out.flush()
+}
+
+pub fn response(id: Json, result: Json) -> Json {
+    Json::object(vec![
+        ("jsonrpc", Json::str("2.0")),
+        ("id", id),
+        ("result", result),
+    ])
+}
+
+pub fn error(id: Json, code: i32, msg: &str) -> Json {
+    Json::object(vec![
+        ("jsonrpc", Json::str("2.0")),
+        ("id", id),
+        (
+            "error",
+            Json::object(vec![("code", Json::num(code)), ("message", Json::str(msg))]),
+        ),
+    ])
+}
+
+pub fn notification(method: &str, params: Json) -> Json {
+    Json::object(vec![
+        ("jsonrpc", Json::str("2.0")),
+        ("method", Json::str(method)),
+        ("params", params),
+    ])
+}
@@ -1,35 +1,368 @@
-pub mod ast;
-pub mod checker;
-pub mod error;
-pub mod lexer;
-pub mod location;
-pub mod parser;
-pub mod program;
-pub mod read;
-pub mod scope;
-pub mod tokens;
-pub mod types;
-
-use std::rc::Rc;
-
-use checker::Checker;
-use lexer::{source_lines, Lexer};
-use parser::Parser;
+use std::{
+    env, fs,
+    io::{self, IsTerminal},
+    path::{Path, PathBuf},
+    process, thread,
+};
 
-fn main() {
-    let code = Rc::new(read::read_file("code.fn"));
-    let lines = source_lines(Rc::clone(&code));
+use fusion::{
+    backend::{self, x86::Jit},
+    driver,
+    error::{self, ErrorCode, ErrorFormat, Reporter},
+    format, interpreter,
+    ir::{self, Val},
+    lint::{Level, Lint},
+    read, repl,
+    source::SourceMap,
+};
+
+enum Command {
+    Build(PathBuf),
+    Explain(String),
+    Fmt(Vec<PathBuf>),
+    Repl,
+    // The file, then the function to call and its arguments if there is one
+    Run(Vec<String>),
+}
+
+// What build prints once the program checks
+#[derive(Clone, Copy)]
+enum Emit {
+    Ast,
+    Ir,
+    C,
+    Wasm,
+    Wat,
+    Asm,
+    Obj,
+}
+
+struct Options {
+    command: Command,
+    color: bool,
+    format: ErrorFormat,
+    // In the order they were given, so later flags win
+    lints: Vec<(Lint, Level)>,
+    // Only report the files fmt would change
+    check: bool,
+    emit: Emit,
+    // Run the passes over the IR before it's printed
+    optimize: bool,
+    print_after: Option<String>,
+    stats: bool,
+    // Where the IR, C, wasm or x86 goes instead of stdout
+    out: Option<PathBuf>,
+}
+
+fn usage(msg: &str) -> ! {
+    eprintln!("{}", msg);
+    eprintln!("Usage: fusion [--color=auto|always|never] [--error-format=human|json]");
+    eprintln!("              [--allow=<lint>] [--warn=<lint>] [--deny=<lint>]");
+    eprintln!("              [--emit=ast|ir|c|wasm|wat|asm|obj] [--out=<file>] [-O] [--print-after=<pass>] [--stats] [file]");
+    eprintln!("       fusion explain <code>");
+    eprintln!("       fusion fmt [--check] <file>...");
+    eprintln!("       fusion repl");
+    eprintln!("       fusion run [-O] <file> [function [args...]]");
+    process::exit(2);
+}
+
+fn options() -> Options {
+    let mut opts = Options {
+        command: Command::Build(PathBuf::from("text/code.fn")),
+        color: io::stderr().is_terminal(),
+        format: ErrorFormat::Human,
+        lints: Vec::new(),
+        check: false,
+        emit: Emit::Ast,
+        optimize: false,
+        print_after: None,
+        stats: false,
+        out: None,
+    };
+
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.split_once('=') {
+            Some(("--color", "auto")) => opts.color = io::stderr().is_terminal(),
+            Some(("--color", "always")) => opts.color = true,
+            Some(("--color", "never")) => opts.color = false,
+            Some(("--error-format", "human")) => opts.format = ErrorFormat::Human,
+            Some(("--error-format", "json")) => opts.format = ErrorFormat::Json,
+            Some(("--emit", "ast")) => opts.emit = Emit::Ast,
+            Some(("--emit", "ir")) => opts.emit = Emit::Ir,
+            Some(("--emit", "c")) => opts.emit = Emit::C,
+            Some(("--emit", "wasm")) => opts.emit = Emit::Wasm,
+            Some(("--emit", "wat")) => opts.emit = Emit::Wat,
+            Some(("--emit", "asm")) => opts.emit = Emit::Asm,
+            Some(("--emit", "obj")) => opts.emit = Emit::Obj,
+            Some(("--out", path)) => opts.out = Some(PathBuf::from(path)),
+            Some(("--print-after", name)) => match ir::PASSES.iter().any(|x| x.name == name) {
+                true => opts.print_after = Some(name.to_owned()),
+                false => usage(&format!("Unknown pass {}", name)),
+            },
+            Some(("--explain", code)) => opts.command = Command::Explain(code.to_owned()),
+            Some((flag @ ("--allow" | "--warn" | "--deny"), name)) => {
+                let level = Level::from(&flag[2..]).unwrap();
+                match Lint::group(name) {
+                    Some(lints) => opts.lints.extend(lints.into_iter().map(|x| (x, level))),
+                    None => usage(&format!("Unknown lint {}", name)),
+                }
+            }
+            _ if arg == "--check" => opts.check = true,
+            _ if arg == "-O" => opts.optimize = true,
+            _ if arg == "--stats" => opts.stats = true,
+            _ if arg.starts_with("--") => usage(&format!("Unknown option {}", arg)),
+            _ if arg == "explain" => match args.next() {
+                Some(code) => opts.command = Command::Explain(code),
+                None => usage("Expected an error code to explain"),
+            },
+            _ if arg == "fmt" => opts.command = Command::Fmt(Vec::new()),
+            _ if arg == "repl" => opts.command = Command::Repl,
+            _ if arg == "run" => opts.command = Command::Run(Vec::new()),
+            _ => match &mut opts.command {
+                Command::Fmt(paths) => paths.push(PathBuf::from(arg)),
+                Command::Run(args) => args.push(arg),
+                _ => opts.command = Command::Build(PathBuf::from(arg)),
+            },
+        }
+    }
+    // Binary output would only garble a terminal
+    match opts.emit {
+        Emit::Wasm if opts.out.is_none() => usage("--emit=wasm needs --out=<file>"),
+        Emit::Obj if opts.out.is_none() => usage("--emit=obj needs --out=<file>"),
+        _ => {}
+    }
+
+    opts
+}
+
+fn build(opts: &Options, path: &Path) {
+    let reporter = Reporter::new(SourceMap::new(), opts.color, opts.format);
+    for &(lint, level) in &opts.lints {
+        reporter.lints.borrow_mut().set(lint, level);
+    }
+
+    match driver::compile(reporter, path, &read::read_file) {
+        Ok(graph) => match opts.emit {
+            // Split from the diagnostics above it when both end up on a terminal
+            Emit::Ast => {
+                println!("{}.", ". ".to_owned().repeat(60));
+                graph.root().prog.print()
+            }
+            _ => {
+                let mut module = driver::lower(&graph).unwrap_or_else(|err| {
+                    eprintln!("Error lowering: {}", err);
+                    process::exit(1);
+                });
+                if opts.optimize {
+                    let stats = driver::optimize(&mut module, opts.print_after.as_deref());
+                    if opts.stats {
+                        eprint!("{}", stats);
+                    }
+                }
+                let bytes = match opts.emit {
+                    Emit::C => backend::emit_c(&module).into_bytes(),
+                    Emit::Wasm | Emit::Wat => match backend::emit_wasm(&module) {
+                        Ok(wasm) if matches!(opts.emit, Emit::Wasm) => wasm.encode(),
+                        Ok(wasm) => wasm.to_string().into_bytes(),
+                        Err(err) => {
+                            eprintln!("Error emitting wasm: {}", err);
+                            process::exit(1);
+                        }
+                    },
+                    Emit::Asm | Emit::Obj => {
+                        let x86 = match opts.emit {
+                            Emit::Asm => backend::emit_asm(&module).map(String::into_bytes),
+                            _ => backend::emit_object(&module),
+                        };
+                        x86.unwrap_or_else(|err| {
+                            eprintln!("Error emitting x86: {}", err);
+                            process::exit(1);
+                        })
+                    }
+                    _ => module.to_string().into_bytes(),
+                };
+                match &opts.out {
+                    Some(path) => {
+                        if let Err(err) = fs::write(path, bytes) {
+                            eprintln!("Error writing file {}: {}", path.display(), err);
+                            process::exit(1);
+                        }
+                    }
+                    None => print!("{}", String::from_utf8(bytes).unwrap()),
+                }
+            }
+        },
+        // Errors have already been shown by the time they get here
+        Err(_) => process::exit(1),
+    }
+}
+
+// Rewrites the files in place, or with --check only says which ones aren't formatted
+fn fmt(opts: &Options, paths: &[PathBuf]) {
+    if paths.is_empty() {
+        usage("Expected a file to format");
+    }
 
-    let mut lexer = Lexer::new(Rc::clone(&code));
-    lexer.lex();
+    // Style lints are what the formatter is there to fix
+    let reporter = Reporter::new(SourceMap::new(), opts.color, opts.format);
+    for lint in Lint::group("warnings").unwrap() {
+        reporter.lints.borrow_mut().set(lint, Level::Allow);
+    }
 
-    let tokens = lexer.filter();
+    let mut unformatted = false;
+    for path in paths {
+        let code = match read::read_file(path) {
+            Ok(code) => code,
+            Err(err) => {
+                eprintln!("Error reading file {}: {}", path.display(), err);
+                process::exit(1);
+            }
+        };
 
-    let mut parser = Parser::new(Rc::clone(&lines), &tokens);
-    parser.parse();
+        let formatted =
+            match driver::catch_fatal(|| format::format_file(&reporter, path, code.clone())) {
+                Ok(formatted) => formatted,
+                Err(_) => process::exit(1),
+            };
+        if formatted == code {
+            continue;
+        }
 
-    let mut checker = Checker::new(Rc::clone(&lines), parser.prog);
-    checker.check();
+        if opts.check {
+            println!("{} is not formatted", path.display());
+            unformatted = true;
+        } else if let Err(err) = fs::write(path, formatted) {
+            eprintln!("Error writing file {}: {}", path.display(), err);
+            process::exit(1);
+        }
+    }
 
-    checker.prog.print();
+    if unformatted {
+        process::exit(1);
+    }
+}
+
+// Calls nest on the real stack, so programs run on a thread with room for as many as they can make
+fn with_stack<T: Send>(run: impl FnOnce() -> T + Send) -> T {
+    thread::scope(|scope| {
+        thread::Builder::new()
+            .stack_size(interpreter::STACK_SIZE)
+            .spawn_scoped(scope, run)
+            .expect("couldn't start a thread")
+            .join()
+            .unwrap_or_else(|_| process::exit(101))
+    })
+}
+
+fn repl(opts: &Options) {
+    let prompt = io::stdin().is_terminal();
+    let res = with_stack(|| {
+        repl::run(
+            &mut io::stdin().lock(),
+            &mut io::stdout(),
+            opts.color,
+            prompt,
+        )
+    });
+    if let Err(err) = res {
+        eprintln!("Error in the REPL: {}", err);
+        process::exit(1);
+    }
+}
+
+fn arg(src: &str, typ: ir::Type) -> Option<Val> {
+    match typ {
+        ir::Type::Int(_) | ir::Type::Uint(_) => {
+            src.parse().ok().filter(|&x| typ.holds(x)).map(Val::Int)
+        }
+        ir::Type::Float(_) => src.parse().ok().map(Val::Float),
+        ir::Type::Bool => src.parse().ok().map(Val::Bool),
+        ir::Type::Func => Some(Val::Func(src.to_owned())),
+    }
+}
+
+// Runs the top level and then the function, if there is one, and prints what it gives. It's
+// native when the JIT can do the whole module and in the IR machine when it can't
+fn run(opts: &Options, args: &[String]) {
+    let Some((path, call)) = args.split_first() else {
+        usage("Expected a file to run");
+    };
+
+    let reporter = Reporter::new(SourceMap::new(), opts.color, opts.format);
+    for &(lint, level) in &opts.lints {
+        reporter.lints.borrow_mut().set(lint, level);
+    }
+    let Ok(graph) = driver::compile(reporter, Path::new(path), &read::read_file) else {
+        process::exit(1);
+    };
+    let mut module = driver::lower(&graph).unwrap_or_else(|err| {
+        eprintln!("Error lowering: {}", err);
+        process::exit(1);
+    });
+    if opts.optimize {
+        driver::optimize(&mut module, opts.print_after.as_deref());
+    }
+
+    // Functions in the root module don't need its name
+    let call = call.split_first().map(|(name, args)| {
+        let name = match name.contains('.') {
+            true => name.clone(),
+            false => format!("{}.{}", graph.root().name, name),
+        };
+        let Some(func) = module.func(&name) else {
+            usage(&format!("@{} isn't a function", name));
+        };
+        if func.params.len() != args.len() {
+            usage(&format!(
+                "@{} takes {} arguments, but got {}",
+                name,
+                func.params.len(),
+                args.len()
+            ));
+        }
+        let args = args
+            .iter()
+            .zip(&func.params)
+            .map(|(src, &(_, typ))| {
+                arg(src, typ).unwrap_or_else(|| usage(&format!("{} isn't a {}", src, typ)))
+            })
+            .collect();
+        (name, args)
+    });
+
+    let res = with_stack(|| {
+        let mut jit = Jit::new(&module);
+        jit.call(ir::ENTRY, Vec::new())?;
+        match call {
+            Some((name, args)) => jit.call(&name, args),
+            None => Ok(None),
+        }
+    });
+    match res {
+        Ok(Some(val)) => println!("{}", val),
+        Ok(None) => {}
+        Err(err) => {
+            eprintln!("error: {}", err);
+            process::exit(1);
+        }
+    }
+}
+
+fn explain(code: &str) {
+    match ErrorCode::from(code) {
+        Some(code) => print!("{}", error::explain(code).render()),
+        None => usage(&format!("{} is not an error code", code)),
+    }
+}
+
+fn main() {
+    let opts = options();
+    match &opts.command {
+        Command::Build(path) => build(&opts, path),
+        Command::Explain(code) => explain(code),
+        Command::Fmt(paths) => fmt(&opts, paths),
+        Command::Repl => repl(&opts),
+        Command::Run(args) => run(&opts, args),
+    }
 }
@@ -0,0 +1,297 @@
+use std::{
+    cell::RefCell,
+    collections::{HashMap, HashSet},
+    io, panic,
+    path::{Path, PathBuf},
+    rc::Rc,
+};
+
+use crate::{
+    ast::{Stmt, Visibility},
+    checker::Checker,
+    driver,
+    error::{Error, ErrorCode, Reporter},
+    lexer::Lexer,
+    location::Span,
+    parser::Parser,
+    program::Program,
+    read,
+    resolver::Resolver,
+    scope::Scope,
+    source::FileId,
+    symbols::SymbolTable,
+};
+
+#[derive(Debug, Clone, Copy)]
+pub struct Child {
+    pub id: usize,
+    pub vis: Visibility,
+    // The mod statement that declared it
+    pub span: Span,
+}
+
+pub struct Module {
+    pub name: String,
+    pub path: PathBuf,
+    pub file: FileId,
+    pub prog: Program,
+    pub children: HashMap<String, Child>,
+}
+
+// Everything other modules are allowed to look at while checking
+#[derive(Clone)]
+pub struct ModuleInfo {
+    pub name: String,
+    pub scope: Rc<RefCell<Scope>>,
+    pub children: HashMap<String, Child>,
+    pub public: HashSet<String>,
+    // Where each top level item's statement starts
+    pub items: HashMap<String, Span>,
+}
+
+impl Module {
+    pub fn info(&self) -> ModuleInfo {
+        let mut public = HashSet::new();
+        let mut items = HashMap::new();
+        let ast = &self.prog.ast;
+        for &stmt in &ast[self.prog.root].stmts {
+            let (vis, name) = match &ast[stmt] {
+                Stmt::Decl(x) => (x.vis, &ast[x.name].name),
+                Stmt::Func(x) => (x.vis, &ast[x.name].name),
+                Stmt::Enum(x) => (x.vis, &ast[x.name].name),
+                Stmt::Struct(x) => (x.vis, &ast[x.name].name),
+                Stmt::Trait(x) => (x.vis, &ast[x.name].name),
+                _ => continue,
+            };
+
+            if vis == Visibility::Public {
+                public.insert(name.clone());
+            }
+            items.insert(name.clone(), ast.span(stmt));
+        }
+
+        ModuleInfo {
+            name: self.name.clone(),
+            scope: self.prog.scope(),
+            children: self.children.clone(),
+            public,
+            items,
+        }
+    }
+}
+
+pub struct ModuleGraph {
+    pub reporter: Rc<Reporter>,
+    // Shared by every module, so ids from an import mean the same thing everywhere
+    pub symbols: Rc<RefCell<SymbolTable>>,
+    pub modules: Vec<Module>,
+    // Dependencies always come before the modules that declare them
+    pub order: Vec<usize>,
+}
+
+impl ModuleGraph {
+    pub fn load(reporter: Rc<Reporter>, root: &Path) -> ModuleGraph {
+        ModuleGraph::load_with(reporter, root, &read::read_file)
+    }
+
+    // Lets tools hand over files that haven't been saved to disk
+    pub fn load_with(
+        reporter: Rc<Reporter>,
+        root: &Path,
+        read: &dyn Fn(&Path) -> io::Result<String>,
+    ) -> ModuleGraph {
+        let mut loader = Loader {
+            graph: ModuleGraph {
+                reporter,
+                symbols: Rc::new(RefCell::new(SymbolTable::default())),
+                modules: Vec::new(),
+                order: Vec::new(),
+            },
+            ids: HashMap::new(),
+            stack: Vec::new(),
+            read,
+        };
+        loader.load(root.to_path_buf(), None);
+
+        loader.graph
+    }
+
+    pub fn root(&self) -> &Module {
+        &self.modules[0]
+    }
+
+    pub fn resolve(&mut self) {
+        let infos = Rc::new(self.modules.iter().map(Module::info).collect::<Vec<_>>());
+        for &id in &self.order {
+            let prog = &self.modules[id].prog;
+            let mut resolver = Resolver::new(
+                Rc::clone(&self.reporter),
+                Rc::clone(&self.symbols),
+                Rc::clone(&infos),
+                id,
+                prog,
+            );
+            let res = driver::catch_fatal(|| resolver.resolve());
+
+            // Whatever got resolved before an error is kept, so tools can still use it
+            self.modules[id].prog.names = resolver.names;
+            if let Err(err) = res {
+                panic::resume_unwind(Box::new(err));
+            }
+        }
+    }
+
+    pub fn check(&mut self) {
+        for &id in &self.order {
+            let prog = &self.modules[id].prog;
+            let mut checker =
+                Checker::new(Rc::clone(&self.reporter), Rc::clone(&self.symbols), prog);
+            // Generic functions from any module get checked again for the calls made here
+            checker.others = self.modules.iter().map(|x| &x.prog).collect();
+            let res = driver::catch_fatal(|| checker.check());
+
+            let (types, calls) = (checker.types, checker.calls);
+            let (methods, vtables) = (checker.methods, checker.vtables);
+            let instances = checker.instances.take();
+            let prog = &mut self.modules[id].prog;
+            prog.types = types;
+            prog.calls = calls;
+            prog.methods = methods;
+            prog.vtables = vtables;
+            prog.instances = instances;
+            if let Err(err) = res {
+                panic::resume_unwind(Box::new(err));
+            }
+        }
+    }
+}
+
+// The mod statement that asked for a file to be loaded
+#[derive(Clone, Copy)]
+struct Import<'a> {
+    reporter: &'a Rc<Reporter>,
+    name: &'a str,
+    span: Span,
+}
+
+impl Import<'_> {
+    fn panic(&self, message: String, id: ErrorCode) {
+        self.reporter.panic(&Error::new(message, self.span, id));
+    }
+}
+
+struct Loader<'a> {
+    graph: ModuleGraph,
+    ids: HashMap<PathBuf, usize>,
+    stack: Vec<usize>,
+    read: &'a dyn Fn(&Path) -> io::Result<String>,
+}
+
+impl Loader<'_> {
+    fn load(&mut self, path: PathBuf, import: Option<Import>) -> usize {
+        // Every file is only parsed once, no matter how many modules declare it
+        let key = path.canonicalize().unwrap_or_else(|_| path.clone());
+        if let Some(&id) = self.ids.get(&key) {
+            if let Some(pos) = self.stack.iter().position(|x| *x == id) {
+                let mut chain: Vec<String> = self.stack[pos..]
+                    .iter()
+                    .map(|x| self.graph.modules[*x].path.display().to_string())
+                    .collect();
+                chain.push(path.display().to_string());
+
+                import.unwrap().panic(
+                    format!("Import cycle detected: {}", chain.join(" -> ")),
+                    ErrorCode::ImportCycle,
+                );
+            }
+
+            return id;
+        }
+
+        let code = match (self.read)(&path) {
+            Ok(code) => Rc::new(code),
+            Err(err) => match import {
+                Some(import) => {
+                    import.panic(
+                        format!("Could not load the module at {}: {}", path.display(), err),
+                        ErrorCode::ModuleNotFound,
+                    );
+                    panic!();
+                }
+                None => panic!("Error reading file {}: {}", path.display(), err),
+            },
+        };
+
+        let id = self.graph.modules.len();
+        let reporter = Rc::clone(&self.graph.reporter);
+        let file = reporter
+            .map
+            .add(path.display().to_string(), Rc::clone(&code));
+
+        let mut lexer = Lexer::new(code, file);
+        lexer.lex();
+        let tokens = lexer.filter();
+
+        let mut parser = Parser::new(Rc::clone(&reporter), &tokens);
+        let prog = parser.parse();
+
+        // Child modules are loaded once this one is in the graph, so their names are read out first
+        let ast = &prog.ast;
+        let decls: Vec<(String, Visibility, Span)> = ast[prog.root]
+            .stmts
+            .iter()
+            .filter_map(|&stmt| match &ast[stmt] {
+                Stmt::Module(x) => Some((ast[x.name].name.clone(), x.vis, ast.span(stmt))),
+                _ => None,
+            })
+            .collect();
+
+        let name = match import {
+            Some(import) => import.name.to_owned(),
+            None => path
+                .file_stem()
+                .map(|x| x.to_string_lossy().to_string())
+                .unwrap_or_default(),
+        };
+
+        self.graph.modules.push(Module {
+            name,
+            path: path.clone(),
+            file,
+            prog,
+            children: HashMap::new(),
+        });
+        self.ids.insert(key, id);
+        self.stack.push(id);
+
+        let dir = path.parent().unwrap_or(Path::new("")).to_path_buf();
+        for (name, vis, span) in decls {
+            let import = Import {
+                reporter: &reporter,
+                name: &name,
+                span,
+            };
+            if self.graph.modules[id].children.contains_key(&name) {
+                import.panic(
+                    format!("The module '{}' is declared more than once", name),
+                    ErrorCode::InvalidStatement,
+                );
+            }
+
+            let child = self.load(dir.join(format!("{}.fn", name)), Some(import));
+            self.graph.modules[id].children.insert(
+                name,
+                Child {
+                    id: child,
+                    vis,
+                    span,
+                },
+            );
+        }
+
+        self.stack.pop();
+        self.graph.order.push(id);
+
+        id
+    }
+}
@@ -1,4 +1,4 @@
-use std::{cell::RefCell, rc::Rc};
+use std::rc::Rc;
 
 pub(crate) mod expressions;
 pub(crate) mod misc;
@@ -6,34 +6,34 @@ pub(crate) mod raw;
 pub(crate) mod statements;
 
 pub use crate::{
-    ast::{self, Node},
-    error::{Error, ErrorCode},
-    location::Location,
+    ast::{self, Ast, Kind, Node},
+    error::{Error, ErrorCode, Reporter},
+    lint::Lint,
+    location::Span,
     program::Program,
-    scope::Scope,
     tokens::{Token, Type},
 };
 
 pub struct Parser {
-    pub lines: Rc<Vec<String>>,
+    pub reporter: Rc<Reporter>,
     pub tokens: Vec<Token>,
-    pub prog: Program,
-    top: Rc<RefCell<Scope>>,
+    pub ast: Ast,
+    // Any expression can be a statement, not only calls, so the REPL can show values
+    pub expr_stmts: bool,
     idx: usize,
+    nesting: usize,
 }
 
 // Parsing
 impl Parser {
-    pub fn new(lines: Rc<Vec<String>>, tokens: &Vec<Token>) -> Parser {
-        let prog = Program::new(Scope::new(None));
-        let top = Rc::clone(&prog.block.scope);
-
+    pub fn new(reporter: Rc<Reporter>, tokens: &[Token]) -> Parser {
         Parser {
-            lines,
-            tokens: tokens.clone(),
-            prog,
-            top,
+            reporter,
+            tokens: tokens.to_vec(),
+            ast: Ast::default(),
+            expr_stmts: false,
             idx: 0,
+            nesting: 0,
         }
     }
 
@@ -41,67 +41,117 @@ impl Parser {
         self.tokens.get(self.idx - 1).unwrap().clone()
     }
 
+    // Newlines don't end anything inside of parentheses, so they get skipped there
+    fn pos(&self) -> usize {
+        let mut idx = self.idx;
+        if self.nesting > 0 {
+            while self.tokens[idx].typ == Type::NewLine {
+                idx += 1;
+            }
+        }
+        idx
+    }
+
     fn at(&self) -> Token {
-        self.tokens.get(self.idx).unwrap().clone()
+        self.tokens.get(self.pos()).unwrap().clone()
     }
 
-    fn tt(&self) -> Type {
-        self.at().typ
+    fn peek(&self, offset: usize) -> Token {
+        match self.tokens.get(self.pos() + offset) {
+            Some(tok) => tok.clone(),
+            None => self.tokens.last().unwrap().clone(),
+        }
     }
 
-    fn cur_loc(&self) -> Location {
-        self.at().start
+    fn tt(&self) -> Type {
+        self.at().typ
     }
 
-    fn prev_stop(&self) -> Location {
-        self.prev().end
+    fn cur_span(&self) -> Span {
+        self.at().span
     }
 
     fn eat(&mut self) -> Token {
         let tok = self.at();
-        self.idx += 1;
+        self.idx = self.pos() + 1;
         tok
     }
 
     fn expect(&mut self, expected: Type) -> Token {
         let tok = self.at();
-        if !tok.typ.eq(&expected) {
+        if !tok.typ.same_kind(&expected) {
             self.panic(
-                format!("Expected {}, instead got {}", expected, tok.typ.to_string()),
+                format!("Expected {}, instead got {}", expected, tok.typ),
                 ErrorCode::UnexpectedToken,
             );
         }
 
-        self.idx += 1;
+        self.idx = self.pos() + 1;
         tok
     }
 
     fn panic(&self, message: String, id: ErrorCode) {
-        Error::new(
-            Rc::clone(&self.lines),
-            message,
-            self.cur_loc(),
-            self.cur_loc().shift(self.at().size), // TODO: Use Metadata
-            id,
-        )
-        .panic();
+        let err = Error::new(message, self.cur_span(), id);
+        self.reporter.panic(&err);
     }
 
-    fn node<T>(&self, node: T, start: Location) -> Node<T> {
-        Node::new(node, start, self.prev_stop())
+    fn node<T: Kind>(&mut self, node: T, start: Span) -> Node<T> {
+        let span = start.to(self.prev().span);
+        self.ast.alloc(node, span)
     }
 
-    pub fn parse(&mut self) {
-        println!("{}.", ". ".to_owned().repeat(60));
-
-        while self.tt() != Type::EOF {
-            if self.tt().is_line_ending() {
-                self.eat();
-                continue;
+    // One blank line between statements is fine, any more than that isn't needed.
+    // The limit is lower at the edges, where a newline only ends the line before it
+    fn skip_line_endings(&mut self, limit: usize) {
+        let mut newlines: Vec<Span> = Vec::new();
+        while self.tt().is_line_ending() && self.tt() != Type::EOF {
+            let tok = self.eat();
+            if tok.typ == Type::NewLine {
+                newlines.push(tok.span);
             }
+        }
+
+        let limit = if self.tt().is(&[Type::RightBrace, Type::EOF]) {
+            limit.min(1)
+        } else {
+            limit
+        };
+        if newlines.len() <= limit {
+            return;
+        }
+
+        // Whole lines get removed, so the indentation of the next one stays the same
+        let last = newlines.last().unwrap();
+        let lo = match limit {
+            0 => 0,
+            _ => newlines[limit - 1].hi,
+        };
+        let span = Span::new(last.file, lo, last.hi);
+        self.reporter.lint(
+            Error::lint(
+                Lint::UnneededNewlines,
+                "Unneeded blank lines".to_owned(),
+                span,
+            )
+            .suggest(span, String::new(), "remove the blank lines".to_owned()),
+        );
+    }
 
-            let stmt = self.parse_stmt();
-            self.prog.block.stmts.push(stmt);
+    pub fn parse(&mut self) -> Program {
+        self.skip_line_endings(0);
+        while self.tt() == Type::Hash && self.peek(1).typ == Type::Not {
+            self.parse_inner_attribute();
+            self.skip_line_endings(2);
         }
+
+        let mut stmts = Vec::new();
+        while self.tt() != Type::EOF {
+            stmts.push(self.parse_stmt());
+            self.skip_line_endings(2);
+        }
+
+        let end = self.cur_span();
+        let span = Span::new(end.file, 0, end.hi);
+        Program::new(std::mem::take(&mut self.ast), stmts, span)
     }
 }
@@ -1,20 +1,35 @@
 use crate::{
-    ast::{self, Expr, Meta, Node},
+    ast::{self, Expr, Meta, Node, Stmt},
     error::ErrorCode,
+    location::Span,
+    scope::Scope,
     tokens::{Type, ORDERED_BINARY_OPERATORS, ORDERED_UNARY_OPERATORS},
 };
 
-use super::Parser;
+use super::{misc, Parser};
 
 impl Parser {
     pub(crate) fn parse_expr(&mut self) -> Node<Expr> {
         self.parse_binop(None)
     }
 
+    // An expression that stands on its own, so parentheses around all of it do nothing
+    pub(crate) fn parse_value(&mut self) -> Node<Expr> {
+        let start = self.cur_span();
+        let val = self.parse_expr();
+
+        // A group gives back what's inside of it, so the node starts after the parenthesis
+        if self.ast.span(val).lo > start.lo && !misc::is_atom(&self.ast[val]) {
+            self.redundant_parens(start.to(self.prev().span), val);
+        }
+
+        val
+    }
+
     pub(crate) fn parse_binop(&mut self, depth: Option<usize>) -> Node<Expr> {
         let idx = depth.unwrap_or(0);
         if idx < ORDERED_BINARY_OPERATORS.len() {
-            let start = self.cur_loc();
+            let start = self.cur_span();
 
             let mut left = self.parse_binop(Some(idx + 1));
             while self.tt().is(ORDERED_BINARY_OPERATORS[idx]) {
@@ -23,7 +38,7 @@ impl Parser {
 
                 left = self.node(
                     Expr::BinaryOp(ast::BinaryOp {
-                        op: Meta::new(op.typ, op.start, op.end),
+                        op: Meta::new(op.typ, op.span),
                         lhs: left,
                         rhs: binop,
                     }),
@@ -39,31 +54,84 @@ impl Parser {
 
     pub(crate) fn parse_unop(&mut self) -> Node<Expr> {
         if self.tt().is(ORDERED_UNARY_OPERATORS) {
-            let start = self.cur_loc();
+            let start = self.cur_span();
 
             let op = self.eat();
-            let val = self.parse_primary();
+            let val = self.parse_call();
 
             self.node(
                 Expr::UnaryOp(ast::UnaryOp {
-                    op: Meta::new(op.typ, op.start, op.end),
+                    op: Meta::new(op.typ, op.span),
                     val,
                 }),
                 start,
             )
         } else {
-            self.parse_primary()
+            self.parse_call()
+        }
+    }
+
+    pub(crate) fn parse_call(&mut self) -> Node<Expr> {
+        let start = self.cur_span();
+
+        let mut callee = self.parse_primary();
+        loop {
+            callee = match self.tt() {
+                Type::LeftParen => {
+                    let args = self.parse_paren_list(Parser::parse_value);
+                    self.node(Expr::Call(ast::Call { callee, args }), start)
+                }
+                Type::Dot | Type::QuestionDot => self.parse_field(callee, start),
+                _ => break,
+            };
         }
+
+        callee
+    }
+
+    // Tuple fields are numbered, the lexer makes sure pair.0.1 isn't a float
+    pub(crate) fn parse_field(&mut self, val: Node<Expr>, start: Span) -> Node<Expr> {
+        let optional = self.eat().typ == Type::QuestionDot;
+        let tok = self.at();
+        let name = match tok.typ {
+            Type::Number(num) if num >= 0.0 && num.fract() == 0.0 => {
+                self.eat();
+                let name = (num as u32).to_string();
+                self.node(ast::Ident { name }, tok.span)
+            }
+            Type::Identifier(_) => self.parse_ident(),
+            typ => {
+                self.panic(
+                    format!("Expected a field name, instead got {}", typ),
+                    ErrorCode::UnexpectedToken,
+                );
+                panic!();
+            }
+        };
+
+        self.node(
+            Expr::Field(ast::Field {
+                val,
+                name,
+                optional,
+            }),
+            start,
+        )
     }
 
     // Primaries
     pub(crate) fn parse_primary(&mut self) -> Node<Expr> {
         let tok = self.at();
-        let start = self.cur_loc();
+        let start = self.cur_span();
         match tok.typ {
-            // I find it really irritating you can't call a mut method who's parameter is a mut method
             Type::Identifier(_) => {
-                let expr = Expr::Ident(self.parse_raw_ident());
+                let ident = self.parse_ident();
+                let expr = match self.tt() {
+                    Type::Dot if matches!(self.peek(1).typ, Type::Identifier(_)) => {
+                        Expr::Path(self.parse_path_from(ident))
+                    }
+                    _ => Expr::Ident(ident),
+                };
                 self.node(expr, start)
             }
             Type::Number(_) => {
@@ -74,7 +142,12 @@ impl Parser {
                 let expr = Expr::BoolLit(self.parse_raw_bool_lit());
                 self.node(expr, start)
             }
+            Type::None => {
+                self.eat();
+                self.node(Expr::NoneLit, start)
+            }
             Type::LeftParen => self.parse_group(),
+            Type::Match => self.parse_match(),
             _ => {
                 self.panic(
                     format!("Invalid expression {}", tok.typ),
@@ -85,8 +158,52 @@ impl Parser {
         }
     }
 
+    pub(crate) fn parse_match(&mut self) -> Node<Expr> {
+        let start = self.cur_span();
+
+        self.expect(Type::Match);
+        let val = self.parse_value();
+        let arms = self.parse_items(Parser::parse_arm);
+
+        self.node(Expr::Match(ast::Match { val, arms }), start)
+    }
+
+    pub(crate) fn parse_arm(&mut self) -> Node<ast::Arm> {
+        let start = self.cur_span();
+
+        let pat = self.parse_pattern();
+        let guard = match self.tt() {
+            Type::If => {
+                self.eat();
+                Some(self.parse_value())
+            }
+            _ => None,
+        };
+        self.expect(Type::FatArrow);
+
+        let body = match self.tt() {
+            Type::LeftBrace => self.parse_block_stmt(),
+            _ => {
+                let start = self.cur_span();
+                let expr = self.parse_value();
+                self.node(Stmt::ExprStmt(ast::ExprStmt { expr }), start)
+            }
+        };
+
+        let scope = Scope::new(None);
+        self.node(
+            ast::Arm {
+                pat,
+                guard,
+                body,
+                scope,
+            },
+            start,
+        )
+    }
+
     pub(crate) fn parse_ident(&mut self) -> Node<ast::Ident> {
-        let start = self.cur_loc();
+        let start = self.cur_span();
         let raw = self.parse_raw_ident();
 
         self.node(raw, start)
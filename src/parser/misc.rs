@@ -1,64 +1,319 @@
-use std::rc::Rc;
-
 use crate::{
     ast::{self, Expr, Node, Stmt},
+    error::{Error, ErrorCode},
+    lint::Lint,
+    location::Span,
     scope::Scope,
     tokens::Type,
 };
 
 use super::Parser;
 
+// Expressions that parentheses can never change the meaning of
+pub(crate) fn is_atom(expr: &Expr) -> bool {
+    matches!(
+        expr,
+        Expr::Ident(_)
+            | Expr::NumLit(_)
+            | Expr::BoolLit(_)
+            | Expr::NoneLit
+            | Expr::Path(_)
+            | Expr::Call(_)
+            | Expr::Field(_)
+            | Expr::Tuple(_)
+            | Expr::Match(_)
+    )
+}
+
 impl Parser {
+    // A method's self doesn't need a type, it's always Self
     pub(crate) fn parse_param(&mut self) -> Node<ast::Param> {
-        let start = self.cur_loc();
+        let start = self.cur_span();
 
         let name = self.parse_ident();
+        if self.ast[name].name == "self" && self.tt() != Type::Colon {
+            let typ = self.node(
+                ast::Ident {
+                    name: "Self".to_owned(),
+                },
+                start,
+            );
+            let annot = self.node(ast::Annot::Named(typ), start);
+            return self.node(ast::Param { name, annot }, start);
+        }
         self.expect(Type::Colon);
-        let annot = self.parse_ident();
+        let annot = self.parse_annot();
 
         self.node(ast::Param { name, annot }, start)
     }
 
-    pub(crate) fn parse_list<T>(&mut self, parse: fn(&mut Self) -> T) -> Vec<T> {
+    // Every part of a type gets its own node, so an error can point at the part that's wrong
+    pub(crate) fn parse_annot(&mut self) -> Node<ast::Annot> {
+        let start = self.cur_span();
+        let annot = match self.tt() {
+            Type::Dyn => {
+                self.eat();
+                ast::Annot::Dyn(self.parse_ident())
+            }
+            Type::LeftParen => ast::Annot::Tuple(self.parse_paren_list(Parser::parse_annot)),
+            _ => {
+                let name = self.parse_ident();
+                match self.tt() {
+                    Type::LessThan => {
+                        ast::Annot::Generic(name, self.parse_angle_list(Parser::parse_annot))
+                    }
+                    _ => ast::Annot::Named(name),
+                }
+            }
+        };
+        let annot = self.node(annot, start);
+
+        match self.tt() {
+            Type::Question => {
+                self.eat();
+                self.node(ast::Annot::Optional(annot), start)
+            }
+            _ => annot,
+        }
+    }
+
+    // <T, U: Ord + Num> after the name of a function or struct
+    pub(crate) fn parse_generics(&mut self) -> Vec<Node<ast::TypeParam>> {
+        match self.tt() {
+            Type::LessThan => self.parse_angle_list(Parser::parse_type_param),
+            _ => Vec::new(),
+        }
+    }
+
+    fn parse_type_param(&mut self) -> Node<ast::TypeParam> {
+        let start = self.cur_span();
+
+        let name = self.parse_ident();
+        let mut bounds = Vec::new();
+        if self.tt() == Type::Colon {
+            self.eat();
+            bounds.push(self.parse_ident());
+            while self.tt() == Type::Addition {
+                self.eat();
+                bounds.push(self.parse_ident());
+            }
+        }
+
+        self.node(ast::TypeParam { name, bounds }, start)
+    }
+
+    // Like parse_paren_list, but between < and >
+    fn parse_angle_list<T>(&mut self, parse: fn(&mut Self) -> T) -> Vec<T> {
+        self.expect(Type::LessThan);
+        self.nesting += 1;
+        let vals = self.parse_list(Type::GreaterThan, parse);
+        self.expect_angle_close();
+        self.nesting -= 1;
+
+        vals
+    }
+
+    // Type lists can end together, like in Pair<Pair<int32, int32>>, so the lexer's >> gets
+    // split and the first > is used up
+    fn expect_angle_close(&mut self) {
+        let pos = self.pos();
+        let rest = match self.tokens[pos].typ {
+            Type::RightShift => Type::GreaterThan,
+            Type::ZeroFillRightShift => Type::RightShift,
+            Type::GreaterThanOrEqual => Type::Assignment,
+            _ => {
+                self.expect(Type::GreaterThan);
+                return;
+            }
+        };
+
+        let tok = &mut self.tokens[pos];
+        tok.typ = rest;
+        tok.span.lo += 1;
+    }
+
+    pub(crate) fn parse_list<T>(&mut self, end: Type, parse: fn(&mut Self) -> T) -> Vec<T> {
+        if self.tt() == end {
+            return Vec::new();
+        }
+
         let mut vals = vec![parse(self)];
         while self.tt() == Type::Comma {
             self.eat();
+            // Lists spread over multiple lines end with a comma, so adding to them is one line
+            if self.tt() == end {
+                break;
+            }
             vals.push(parse(self));
         }
 
         vals
     }
 
+    pub(crate) fn parse_path(&mut self) -> Node<ast::Path> {
+        let first = self.parse_ident();
+        self.parse_path_from(first)
+    }
+
+    // For when the first name has already been parsed, to tell an identifier and a path apart
+    pub(crate) fn parse_path_from(&mut self, first: Node<ast::Ident>) -> Node<ast::Path> {
+        let start = self.ast.span(first);
+
+        // Anything else after a dot is a field, like the 0 in pair.0
+        let mut segments = vec![first];
+        while self.tt() == Type::Dot && matches!(self.peek(1).typ, Type::Identifier(_)) {
+            self.eat();
+            segments.push(self.parse_ident());
+        }
+
+        self.node(ast::Path { segments }, start)
+    }
+
+    // Parentheses around one value only group it, a comma makes it a tuple, like (1, 2) or (1,)
     pub(crate) fn parse_group(&mut self) -> Node<Expr> {
-        self.eat();
+        let open = self.eat();
+        self.nesting += 1;
         let body = self.parse_expr();
-        self.expect(Type::RightParen);
+        if self.tt() == Type::Comma {
+            self.eat();
+            let mut items = vec![body];
+            items.extend(self.parse_list(Type::RightParen, Parser::parse_value));
+            self.expect(Type::RightParen);
+            self.nesting -= 1;
+
+            return self.node(Expr::Tuple(ast::Tuple { items }), open.span);
+        }
+        let close = self.expect(Type::RightParen);
+        self.nesting -= 1;
+
+        if is_atom(&self.ast[body]) {
+            self.redundant_parens(open.span.to(close.span), body);
+        }
 
         body
     }
 
-    pub(crate) fn parse_block(&mut self) -> Node<ast::Block> {
-        let start = self.cur_loc();
+    // Patterns are written the same way as the values they match
+    pub(crate) fn parse_pattern(&mut self) -> Node<ast::Pattern> {
+        let start = self.cur_span();
+        let pattern = match self.tt() {
+            Type::Identifier(name) if name == "_" => {
+                self.eat();
+                ast::Pattern::Wildcard
+            }
+            Type::Identifier(_) => {
+                let ident = self.parse_ident();
+                match self.tt() {
+                    Type::Dot => {
+                        let path = self.parse_path_from(ident);
+                        let fields = match self.tt() {
+                            Type::LeftParen => self.parse_paren_list(Parser::parse_pattern),
+                            _ => Vec::new(),
+                        };
+                        ast::Pattern::Variant(path, fields)
+                    }
+                    _ => ast::Pattern::Binding(ident),
+                }
+            }
+            Type::Number(_) | Type::Boolean(_) => ast::Pattern::Literal(self.parse_primary()),
+            Type::Subtraction if matches!(self.peek(1).typ, Type::Number(_)) => {
+                ast::Pattern::Literal(self.parse_unop())
+            }
+            Type::LeftParen => {
+                self.eat();
+                self.nesting += 1;
+                let first = self.parse_pattern();
+                let pattern = match self.tt() {
+                    Type::Comma => {
+                        self.eat();
+                        let mut items = vec![first];
+                        items.extend(self.parse_list(Type::RightParen, Parser::parse_pattern));
+                        Some(ast::Pattern::Tuple(items))
+                    }
+                    _ => None,
+                };
+                self.expect(Type::RightParen);
+                self.nesting -= 1;
 
-        let top = Rc::clone(&self.top);
+                match pattern {
+                    Some(pattern) => pattern,
+                    None => return first,
+                }
+            }
+            typ => {
+                self.panic(
+                    format!("Invalid pattern {}", typ),
+                    ErrorCode::InvalidExpression,
+                );
+                panic!();
+            }
+        };
 
-        let scope = Scope::new(Some(Rc::clone(&top)));
-        self.top = Rc::clone(&scope);
+        self.node(pattern, start)
+    }
+
+    // Variants, fields and arms go one per line like statements do, commas between them are optional
+    pub(crate) fn parse_items<T>(&mut self, parse: fn(&mut Self) -> T) -> Vec<T> {
+        let nesting = self.nesting;
+        self.nesting = 0;
 
         self.expect(Type::LeftBrace);
-        let mut stmts: Vec<Node<Stmt>> = Vec::new();
+        self.skip_line_endings(1);
+        let mut items = Vec::new();
         while self.tt() != Type::RightBrace {
-            if self.tt().is_line_ending() {
+            items.push(parse(self));
+            if self.tt() == Type::Comma {
                 self.eat();
-                continue;
             }
+            self.skip_line_endings(2);
+        }
+        self.expect(Type::RightBrace);
+
+        self.nesting = nesting;
+        items
+    }
+
+    pub(crate) fn redundant_parens(&self, span: Span, body: Node<Expr>) {
+        let inner = self.reporter.map.snippet(self.ast.span(body));
+        self.reporter.lint(
+            Error::lint(
+                Lint::RedundantParens,
+                "Unneeded parentheses".to_owned(),
+                span,
+            )
+            .suggest(span, inner, "remove the parentheses".to_owned()),
+        );
+    }
+
+    // Lists between parentheses, which can be spread out over multiple lines
+    pub(crate) fn parse_paren_list<T>(&mut self, parse: fn(&mut Self) -> T) -> Vec<T> {
+        self.expect(Type::LeftParen);
+        self.nesting += 1;
+        let vals = self.parse_list(Type::RightParen, parse);
+        self.expect(Type::RightParen);
+        self.nesting -= 1;
 
+        vals
+    }
+
+    pub(crate) fn parse_block(&mut self) -> Node<ast::Block> {
+        let start = self.cur_span();
+
+        let nesting = self.nesting;
+        self.nesting = 0;
+
+        self.expect(Type::LeftBrace);
+        self.skip_line_endings(1);
+        let mut stmts: Vec<Node<Stmt>> = Vec::new();
+        while self.tt() != Type::RightBrace {
             stmts.push(self.parse_stmt());
+            self.skip_line_endings(2);
         }
         self.expect(Type::RightBrace);
 
-        self.top = Rc::clone(&top);
+        self.nesting = nesting;
 
+        let scope = Scope::new(None);
         self.node(ast::Block { stmts, scope }, start)
     }
 }
@@ -13,7 +13,7 @@ impl Parser {
             self.panic(
                 format!(
                     "Cannot use {} for a name, because it is a reserved keyword",
-                    cur_tok.typ.src_strings().get(0).unwrap(),
+                    cur_tok.typ.src_strings().first().unwrap(),
                 ),
                 ErrorCode::ReservedNameUsed,
             );
@@ -1,16 +1,55 @@
 use crate::{
-    ast::{self, Meta, Node, Stmt},
-    error::ErrorCode,
+    ast::{self, Meta, Node, Stmt, Visibility},
+    error::{Error, ErrorCode},
+    lint::{Level, Lint},
+    location::Span,
+    scope::Scope,
+    suggest,
     tokens::{Type, ORDERED_BINARY_OPERATORS},
 };
 
 use super::Parser;
 
+// Keywords that a statement can start with
+const STATEMENT_KEYWORDS: &[Type] = &[
+    Type::Let,
+    Type::If,
+    Type::While,
+    Type::Do,
+    Type::Continue,
+    Type::Return,
+    Type::Function,
+    Type::Enum,
+    Type::Struct,
+    Type::Trait,
+    Type::Impl,
+    Type::Match,
+    Type::Module,
+    Type::Use,
+    Type::Public,
+    Type::Private,
+];
+
+fn is_binop(typ: &Type) -> bool {
+    ORDERED_BINARY_OPERATORS.iter().any(|ops| typ.is(ops))
+}
+
 impl Parser {
     pub(crate) fn parse_stmt(&mut self) -> Node<Stmt> {
         let tok = self.at();
         match tok.typ {
-            Type::Identifier(_) => self.parse_assign(),
+            Type::Identifier(ref name) => match self.peek(1).typ {
+                Type::LeftParen | Type::Dot => self.parse_expr_stmt(),
+                Type::Assignment => self.parse_assign(),
+                typ if is_binop(&typ) && self.peek(2).typ == Type::Assignment => {
+                    self.parse_assign()
+                }
+                _ if self.expr_stmts => self.parse_expr_stmt(),
+                _ => {
+                    self.misspelled_keyword(name, tok.span);
+                    self.parse_assign()
+                }
+            },
             Type::LeftBrace => self.parse_block_stmt(),
             Type::Let => self.parse_decl(),
             Type::If => self.parse_if_stmt(),
@@ -19,40 +58,182 @@ impl Parser {
             Type::Continue => self.parse_continue(),
             Type::Return => self.parse_return(),
             Type::Function => self.parse_func(),
+            Type::Enum => self.parse_enum(),
+            Type::Struct => self.parse_struct(),
+            Type::Trait => self.parse_trait(),
+            Type::Impl => self.parse_impl(),
+            Type::Match => self.parse_expr_stmt(),
+            Type::Module => self.parse_module(),
+            Type::Use => self.parse_use(),
+            Type::Hash => self.parse_attributed(),
+            Type::Public | Type::Private => match self.peek(1).typ {
+                Type::Let => self.parse_decl(),
+                Type::Function => self.parse_func(),
+                Type::Enum => self.parse_enum(),
+                Type::Struct => self.parse_struct(),
+                Type::Trait => self.parse_trait(),
+                Type::Module => self.parse_module(),
+                _ => {
+                    self.panic(
+                        "Expected let, func, enum, struct, trait or mod after a visibility modifier"
+                            .to_owned(),
+                        ErrorCode::InvalidStatement,
+                    );
+                    panic!();
+                }
+            },
+            _ if self.expr_stmts => self.parse_expr_stmt(),
             _ => {
                 self.panic("Invalid statement".to_owned(), ErrorCode::InvalidStatement);
                 panic!();
             }
         }
         // TODO: Expect Semicolon, NewLine or EOF at the end of each statement
-        // Also, maybe give warning for unneeded newlines after this is implemented
+    }
+
+    // #[level(lint, ...)], the ! of an inner attribute has already been checked for
+    fn parse_attribute(&mut self) -> (Level, Vec<Lint>) {
+        self.expect(Type::Hash);
+        if self.tt() == Type::Not {
+            self.eat();
+        }
+        self.expect(Type::LeftBracket);
+
+        let level = self.parse_ident();
+        let Some(level) = Level::from(&self.ast[level].name) else {
+            self.reporter.panic(&Error::new(
+                format!(
+                    "Unknown lint level '{}', expected allow, warn or deny",
+                    self.ast[level].name
+                ),
+                self.ast.span(level),
+                ErrorCode::InvalidStatement,
+            ));
+            panic!();
+        };
+
+        let mut lints = Vec::new();
+        for name in self.parse_paren_list(Parser::parse_ident) {
+            match Lint::group(&self.ast[name].name) {
+                Some(group) => lints.extend(group),
+                None => self.reporter.panic(&Error::new(
+                    format!("Unknown lint '{}'", self.ast[name].name),
+                    self.ast.span(name),
+                    ErrorCode::InvalidStatement,
+                )),
+            }
+        }
+        self.expect(Type::RightBracket);
+
+        (level, lints)
+    }
+
+    // Applies to the whole file, so these have to come before any statement
+    pub(crate) fn parse_inner_attribute(&mut self) {
+        let file = self.cur_span().file;
+        let (level, lints) = self.parse_attribute();
+
+        let mut levels = self.reporter.lints.borrow_mut();
+        for lint in lints {
+            levels.attach(Span::new(file, 0, u32::MAX), lint, level);
+        }
+    }
+
+    pub(crate) fn parse_attributed(&mut self) -> Node<Stmt> {
+        if self.peek(1).typ == Type::Not {
+            self.panic(
+                "Attributes with a ! can only be at the start of a file".to_owned(),
+                ErrorCode::InvalidStatement,
+            );
+        }
+
+        let (level, lints) = self.parse_attribute();
+        self.skip_line_endings(1);
+
+        // The statement's end isn't known yet, but lints can already come up while parsing it
+        let start = self.cur_span();
+        let attrs: Vec<usize> = lints
+            .into_iter()
+            .map(|lint| {
+                let span = Span::new(start.file, start.lo, u32::MAX);
+                self.reporter.lints.borrow_mut().attach(span, lint, level)
+            })
+            .collect();
+
+        let stmt = self.parse_stmt();
+        for attr in attrs {
+            self.reporter
+                .lints
+                .borrow_mut()
+                .extend(attr, self.ast.span(stmt).hi);
+        }
+
+        stmt
+    }
+
+    // An identifier that isn't followed by an assignment was probably meant to be a keyword
+    fn misspelled_keyword(&self, name: &str, span: Span) {
+        let keywords: Vec<String> = STATEMENT_KEYWORDS
+            .iter()
+            .flat_map(|x| x.src_strings())
+            .collect();
+
+        if let Some(best) = suggest::closest(name, keywords.iter().map(String::as_str)) {
+            self.reporter.panic(
+                &Error::new(
+                    format!("Unknown statement '{}'", name),
+                    span,
+                    ErrorCode::InvalidStatement,
+                )
+                .suggest(
+                    span,
+                    best.to_owned(),
+                    "a keyword with a similar spelling exists".to_owned(),
+                ),
+            );
+        }
     }
 
     pub(crate) fn parse_block_stmt(&mut self) -> Node<Stmt> {
+        let start = self.cur_span();
         let block = self.parse_block();
-        self.node(Stmt::Block(block.src.clone()), block.start)
+        self.node(Stmt::Block(block), start)
+    }
+
+    pub(crate) fn parse_vis(&mut self) -> Visibility {
+        match self.tt() {
+            Type::Public => {
+                self.eat();
+                Visibility::Public
+            }
+            Type::Private => {
+                self.eat();
+                Visibility::Private
+            }
+            _ => Visibility::Private,
+        }
     }
 
     pub(crate) fn parse_decl(&mut self) -> Node<Stmt> {
-        let start = self.cur_loc();
+        let start = self.cur_span();
 
+        let vis = self.parse_vis();
         self.eat();
         let ident = self.parse_ident();
 
         let annotation = if self.tt() == Type::Colon {
             self.eat();
-            Some(self.parse_ident())
+            Some(self.parse_annot())
         } else {
             None
         };
 
         self.expect(Type::Assignment);
-        let value = self.parse_expr();
-
-        self.top.borrow_mut().declare(ident.src.name.clone());
+        let value = self.parse_value();
 
         self.node(
             Stmt::Decl(ast::Decl {
+                vis,
                 name: ident,
                 annot: annotation,
                 val: value,
@@ -62,7 +243,7 @@ impl Parser {
     }
 
     pub(crate) fn parse_assign(&mut self) -> Node<Stmt> {
-        let start = self.cur_loc();
+        let start = self.cur_span();
 
         let name = self.parse_ident();
 
@@ -76,81 +257,95 @@ impl Parser {
 
         let op = if has_op {
             let tok = self.eat();
-            Some(Meta::new(tok.typ, tok.start, tok.end))
+            Some(Meta::new(tok.typ, tok.span))
         } else {
             None
         };
 
         self.expect(Type::Assignment);
-        let val = self.parse_expr();
+        let val = self.parse_value();
 
         self.node(Stmt::Assign(ast::Assign { name, op, val }), start)
     }
 
     pub(crate) fn parse_if_stmt(&mut self) -> Node<Stmt> {
-        let start = self.cur_loc();
+        let start = self.cur_span();
 
         self.eat();
-        let cond = self.parse_expr();
+        if self.tt() == Type::Let {
+            return self.parse_if_let(start);
+        }
+        let cond = self.parse_value();
         let body = self.parse_block();
 
         self.node(Stmt::IfStmt(ast::IfStmt { cond, body }), start)
     }
 
+    // if let x = maybe { }, the if has already been eaten
+    pub(crate) fn parse_if_let(&mut self, start: Span) -> Node<Stmt> {
+        self.expect(Type::Let);
+        let name = self.parse_ident();
+        self.expect(Type::Assignment);
+        let val = self.parse_value();
+        let body = self.parse_block();
+
+        self.node(Stmt::IfLet(ast::IfLet { name, val, body }), start)
+    }
+
     pub(crate) fn parse_while_loop(&mut self) -> Node<Stmt> {
-        let start = self.cur_loc();
+        let start = self.cur_span();
 
         self.eat();
-        let cond = self.parse_expr();
+        let cond = self.parse_value();
         let body = self.parse_block();
 
         self.node(Stmt::WhileLoop(ast::WhileLoop { cond, body }), start)
     }
 
     pub(crate) fn parse_do_while_loop(&mut self) -> Node<Stmt> {
-        let start = self.cur_loc();
+        let start = self.cur_span();
 
         self.eat();
         let body = self.parse_block();
         self.expect(Type::While);
-        let cond = self.parse_expr();
+        let cond = self.parse_value();
 
         self.node(Stmt::DoWhileLoop(ast::DoWhileLoop { body, cond }), start)
     }
 
     pub(crate) fn parse_continue(&mut self) -> Node<Stmt> {
-        let start = self.cur_loc();
+        let start = self.cur_span();
         self.expect(Type::Continue);
 
         self.node(Stmt::Continue, start)
     }
 
     pub(crate) fn parse_return(&mut self) -> Node<Stmt> {
-        let start = self.cur_loc();
+        let start = self.cur_span();
 
         self.eat();
         let val = if self.tt().is_line_ending() {
             None
         } else {
-            Some(self.parse_expr())
+            Some(self.parse_value())
         };
 
         self.node(Stmt::Return(ast::Return { val }), start)
     }
 
     pub(crate) fn parse_func(&mut self) -> Node<Stmt> {
-        let start = self.cur_loc();
+        let start = self.cur_span();
 
+        let vis = self.parse_vis();
         self.eat();
         let name = self.parse_ident();
+        let generics = self.parse_generics();
 
-        self.expect(Type::LeftParen);
-        let params = self.parse_list(Parser::parse_param);
-        self.expect(Type::RightParen);
+        let params = self.parse_paren_list(Parser::parse_param);
 
         let ret = if self.tt() == Type::Colon {
             self.eat();
-            Some(self.parse_ident())
+            Some(self.parse_annot())
         } else {
             None
         };
@@ -158,7 +353,9 @@ impl Parser {
         let body = self.parse_block();
 
         let func = ast::Func {
+            vis,
             name,
+            generics,
             params,
             ret,
             body,
@@ -166,4 +363,166 @@ impl Parser {
 
         self.node(Stmt::Func(func), start)
     }
+
+    pub(crate) fn parse_enum(&mut self) -> Node<Stmt> {
+        let start = self.cur_span();
+
+        let vis = self.parse_vis();
+        self.expect(Type::Enum);
+        let name = self.parse_ident();
+        let variants = self.parse_items(Parser::parse_variant);
+
+        self.node(
+            Stmt::Enum(ast::Enum {
+                vis,
+                name,
+                variants,
+            }),
+            start,
+        )
+    }
+
+    pub(crate) fn parse_struct(&mut self) -> Node<Stmt> {
+        let start = self.cur_span();
+
+        let vis = self.parse_vis();
+        self.expect(Type::Struct);
+        let name = self.parse_ident();
+        let generics = self.parse_generics();
+        let fields = self.parse_items(Parser::parse_param);
+
+        let scope = Scope::new(None);
+        self.node(
+            Stmt::Struct(ast::Struct {
+                vis,
+                name,
+                generics,
+                fields,
+                scope,
+            }),
+            start,
+        )
+    }
+
+    pub(crate) fn parse_trait(&mut self) -> Node<Stmt> {
+        let start = self.cur_span();
+
+        let vis = self.parse_vis();
+        self.expect(Type::Trait);
+        let name = self.parse_ident();
+        let methods = self.parse_items(Parser::parse_signature);
+
+        let scope = Scope::new(None);
+        self.node(
+            Stmt::Trait(ast::Trait {
+                vis,
+                name,
+                methods,
+                scope,
+            }),
+            start,
+        )
+    }
+
+    // A function without a body
+    pub(crate) fn parse_signature(&mut self) -> Node<ast::Signature> {
+        let start = self.cur_span();
+
+        self.expect(Type::Function);
+        let name = self.parse_ident();
+        let params = self.parse_paren_list(Parser::parse_param);
+        let ret = if self.tt() == Type::Colon {
+            self.eat();
+            Some(self.parse_annot())
+        } else {
+            None
+        };
+
+        self.node(ast::Signature { name, params, ret }, start)
+    }
+
+    // impl Shape for Circle { }, or impl Circle { } for methods that aren't from a trait
+    pub(crate) fn parse_impl(&mut self) -> Node<Stmt> {
+        let start = self.cur_span();
+
+        self.expect(Type::Impl);
+        let first = self.parse_annot();
+        let (trait_name, typ) = match self.tt() {
+            Type::For => {
+                // Only a trait can go before the for, and traits don't take types
+                let ast::Annot::Named(trait_name) = self.ast[first] else {
+                    let err = Error::new(
+                        "Expected the name of a trait".to_owned(),
+                        self.ast.span(first),
+                        ErrorCode::UnexpectedToken,
+                    );
+                    self.reporter.panic(&err);
+                    panic!()
+                };
+                self.eat();
+                (Some(trait_name), self.parse_annot())
+            }
+            _ => (None, first),
+        };
+        let methods = self.parse_items(Parser::parse_method);
+
+        let scope = Scope::new(None);
+        self.node(
+            Stmt::Impl(ast::Impl {
+                trait_name,
+                typ,
+                methods,
+                scope,
+            }),
+            start,
+        )
+    }
+
+    fn parse_method(&mut self) -> Node<Stmt> {
+        if !self.tt().is(&[Type::Function, Type::Public, Type::Private]) {
+            self.panic(
+                "Expected a method, impls can only hold functions".to_owned(),
+                ErrorCode::InvalidStatement,
+            );
+        }
+        self.parse_func()
+    }
+
+    pub(crate) fn parse_variant(&mut self) -> Node<ast::Variant> {
+        let start = self.cur_span();
+
+        let name = self.parse_ident();
+        let fields = match self.tt() {
+            Type::LeftParen => self.parse_paren_list(Parser::parse_annot),
+            _ => Vec::new(),
+        };
+
+        self.node(ast::Variant { name, fields }, start)
+    }
+
+    pub(crate) fn parse_module(&mut self) -> Node<Stmt> {
+        let start = self.cur_span();
+
+        let vis = self.parse_vis();
+        self.expect(Type::Module);
+        let name = self.parse_ident();
+
+        self.node(Stmt::Module(ast::Module { vis, name }), start)
+    }
+
+    pub(crate) fn parse_use(&mut self) -> Node<Stmt> {
+        let start = self.cur_span();
+
+        self.expect(Type::Use);
+        let path = self.parse_path();
+
+        self.node(Stmt::Use(ast::Use { path }), start)
+    }
+
+    pub(crate) fn parse_expr_stmt(&mut self) -> Node<Stmt> {
+        let start = self.cur_span();
+        let expr = self.parse_expr();
+
+        self.node(Stmt::ExprStmt(ast::ExprStmt { expr }), start)
+    }
 }
@@ -0,0 +1,415 @@
+use crate::{
+    ast::{self, visit::Visitor, Ast, Expr, Ident, Node, Stmt, Visibility},
+    program::Program,
+    tokens::{Type, ORDERED_BINARY_OPERATORS},
+};
+
+const INDENT: &str = "    ";
+
+// Binary operators bind as tight as their row in the table, unary operators bind tighter than
+// all of them, and calls and everything that can be called bind the tightest
+const UNARY: usize = ORDERED_BINARY_OPERATORS.len();
+const CALL: usize = UNARY + 1;
+
+fn precedence(op: &Type) -> usize {
+    ORDERED_BINARY_OPERATORS
+        .iter()
+        .position(|ops| op.is(ops))
+        .expect("not a binary operator")
+}
+
+fn op_str(op: &Type) -> String {
+    op.src_strings().remove(0)
+}
+
+// Turns a program back into source. Blank lines, redundant parentheses and optional keywords
+// aren't in the AST, so the same AST always comes out the same way
+pub fn print(prog: &Program) -> String {
+    let mut printer = Printer::new(&prog.ast);
+    printer.stmts(&prog.ast[prog.root].stmts);
+    printer.out
+}
+
+pub struct Printer<'a> {
+    ast: &'a Ast,
+    out: String,
+    indent: usize,
+}
+
+impl<'a> Printer<'a> {
+    pub fn new(ast: &'a Ast) -> Printer<'a> {
+        Printer {
+            ast,
+            out: String::new(),
+            indent: 0,
+        }
+    }
+
+    fn write(&mut self, text: &str) {
+        self.out.push_str(text);
+    }
+
+    fn name(&mut self, ident: Node<Ident>) {
+        self.out.push_str(&self.ast[ident].name);
+    }
+
+    fn annot(&mut self, annot: Node<ast::Annot>) {
+        self.out.push_str(&self.ast[annot].display(self.ast));
+    }
+
+    fn vis(&mut self, vis: Visibility) {
+        if vis == Visibility::Public {
+            self.write("pub ");
+        }
+    }
+
+    // Functions and types get a blank line on both sides, everything else sits on the next line
+    fn stmts(&mut self, stmts: &[Node<Stmt>]) {
+        let ast = self.ast;
+        let is_func = |x: Node<Stmt>| {
+            matches!(
+                ast[x],
+                Stmt::Func(_) | Stmt::Enum(_) | Stmt::Struct(_) | Stmt::Trait(_) | Stmt::Impl(_)
+            )
+        };
+        for (idx, &stmt) in stmts.iter().enumerate() {
+            if idx > 0 && (is_func(stmt) || is_func(stmts[idx - 1])) {
+                self.write("\n");
+            }
+
+            self.write(&INDENT.repeat(self.indent));
+            self.visit_stmt(stmt);
+            self.write("\n");
+        }
+    }
+
+    fn block(&mut self, block: Node<ast::Block>) {
+        self.braced(&self.ast()[block].stmts);
+    }
+
+    fn braced(&mut self, stmts: &[Node<Stmt>]) {
+        if stmts.is_empty() {
+            self.write("{}");
+            return;
+        }
+
+        self.write("{\n");
+        self.indent += 1;
+        self.stmts(stmts);
+        self.indent -= 1;
+        self.write(&INDENT.repeat(self.indent));
+        self.write("}");
+    }
+
+    // Variants and arms go one per line, without commas
+    fn items<T: Copy>(&mut self, items: &[T], mut item: impl FnMut(&mut Self, T)) {
+        self.write("{\n");
+        self.indent += 1;
+        for &x in items {
+            self.write(&INDENT.repeat(self.indent));
+            item(self, x);
+            self.write("\n");
+        }
+        self.indent -= 1;
+        self.write(&INDENT.repeat(self.indent));
+        self.write("}");
+    }
+
+    // A method's self is always Self, so its type isn't written
+    fn param(&mut self, param: Node<ast::Param>) {
+        let param = self.ast[param];
+        self.name(param.name);
+        if param.is_self(self.ast) {
+            return;
+        }
+        self.write(": ");
+        self.annot(param.annot);
+    }
+
+    fn generics(&mut self, generics: &[Node<ast::TypeParam>]) {
+        if generics.is_empty() {
+            return;
+        }
+        self.write("<");
+        self.list(generics, |p, param| {
+            let param = &p.ast[param];
+            p.name(param.name);
+            for (idx, &bound) in param.bounds.iter().enumerate() {
+                p.write(if idx == 0 { ": " } else { " + " });
+                p.name(bound);
+            }
+        });
+        self.write(">");
+    }
+
+    fn list<T: Copy>(&mut self, items: &[T], mut item: impl FnMut(&mut Self, T)) {
+        for (idx, &x) in items.iter().enumerate() {
+            if idx > 0 {
+                self.write(", ");
+            }
+            item(self, x);
+        }
+    }
+
+    fn pattern(&mut self, pat: Node<ast::Pattern>) {
+        let ast = self.ast;
+        match &ast[pat] {
+            ast::Pattern::Wildcard => self.write("_"),
+            ast::Pattern::Binding(x) => self.name(*x),
+            ast::Pattern::Literal(x) => self.expr(*x, 0),
+            ast::Pattern::Tuple(items) => {
+                self.write("(");
+                self.list(items, Printer::pattern);
+                if items.len() == 1 {
+                    self.write(",");
+                }
+                self.write(")");
+            }
+            ast::Pattern::Variant(path, fields) => {
+                self.write(&ast[*path].display(ast));
+                if !fields.is_empty() {
+                    self.write("(");
+                    self.list(fields, Printer::pattern);
+                    self.write(")");
+                }
+            }
+        }
+    }
+
+    fn arm(&mut self, arm: Node<ast::Arm>) {
+        let arm = &self.ast[arm];
+        self.pattern(arm.pat);
+        if let Some(guard) = arm.guard {
+            self.write(" if ");
+            self.expr(guard, 0);
+        }
+        self.write(" => ");
+        self.visit_stmt(arm.body);
+    }
+
+    // Parentheses only go around an expression that binds looser than where it's used
+    pub fn expr(&mut self, expr: Node<Expr>, min: usize) {
+        let ast = self.ast;
+        let prec = match &ast[expr] {
+            Expr::BinaryOp(x) => precedence(&x.op.src),
+            Expr::UnaryOp(_) => UNARY,
+            _ => CALL,
+        };
+
+        if prec < min {
+            self.write("(");
+        }
+
+        match &ast[expr] {
+            Expr::Ident(x) => self.name(*x),
+            Expr::NumLit(x) => self.write(&x.val.to_string()),
+            Expr::BoolLit(x) => self.write(&x.val.to_string()),
+            Expr::NoneLit => self.write("none"),
+            // Everything is left associative, so only the right side needs them on a tie
+            Expr::BinaryOp(x) => {
+                self.expr(x.lhs, prec);
+                self.write(&format!(" {} ", op_str(&x.op.src)));
+                self.expr(x.rhs, prec + 1);
+            }
+            Expr::UnaryOp(x) => {
+                self.write(&op_str(&x.op.src));
+                self.expr(x.val, CALL);
+            }
+            Expr::Path(x) => self.write(&ast[*x].display(ast)),
+            Expr::Call(x) => {
+                self.expr(x.callee, CALL);
+                self.write("(");
+                self.list(&x.args, |p, arg| p.expr(arg, 0));
+                self.write(")");
+            }
+            Expr::Field(x) => {
+                self.expr(x.val, CALL);
+                self.write(if x.optional { "?." } else { "." });
+                self.name(x.name);
+            }
+            Expr::Tuple(x) => {
+                self.write("(");
+                self.list(&x.items, |p, item| p.expr(item, 0));
+                if x.items.len() == 1 {
+                    self.write(",");
+                }
+                self.write(")");
+            }
+            Expr::Match(x) => {
+                self.write("match ");
+                self.expr(x.val, 0);
+                self.write(" ");
+                self.items(&x.arms, Printer::arm);
+            }
+        }
+
+        if prec < min {
+            self.write(")");
+        }
+    }
+}
+
+// Each hook writes its statement without indentation or a line ending, stmts adds those
+impl<'a> Visitor<'a> for Printer<'a> {
+    fn ast(&self) -> &'a Ast {
+        self.ast
+    }
+
+    fn visit_block(&mut self, block: Node<ast::Block>) {
+        self.block(block);
+    }
+
+    fn visit_expr(&mut self, expr: Node<Expr>) {
+        self.expr(expr, 0);
+    }
+
+    fn visit_decl(&mut self, decl: &'a ast::Decl, _stmt: Node<Stmt>) {
+        self.vis(decl.vis);
+        self.write("let ");
+        self.name(decl.name);
+        if let Some(annot) = decl.annot {
+            self.write(": ");
+            self.annot(annot);
+        }
+        self.write(" = ");
+        self.visit_expr(decl.val);
+    }
+
+    fn visit_assign(&mut self, assign: &'a ast::Assign, _stmt: Node<Stmt>) {
+        self.name(assign.name);
+        self.write(" ");
+        if let Some(op) = &assign.op {
+            self.write(&op_str(&op.src));
+        }
+        self.write("= ");
+        self.visit_expr(assign.val);
+    }
+
+    fn visit_if_stmt(&mut self, if_stmt: &'a ast::IfStmt, _stmt: Node<Stmt>) {
+        self.write("if ");
+        self.visit_expr(if_stmt.cond);
+        self.write(" ");
+        self.block(if_stmt.body);
+    }
+
+    fn visit_if_let(&mut self, if_let: &'a ast::IfLet, _stmt: Node<Stmt>) {
+        self.write("if let ");
+        self.name(if_let.name);
+        self.write(" = ");
+        self.visit_expr(if_let.val);
+        self.write(" ");
+        self.block(if_let.body);
+    }
+
+    fn visit_while_loop(&mut self, while_loop: &'a ast::WhileLoop, _stmt: Node<Stmt>) {
+        self.write("while ");
+        self.visit_expr(while_loop.cond);
+        self.write(" ");
+        self.block(while_loop.body);
+    }
+
+    fn visit_do_while_loop(&mut self, do_while_loop: &'a ast::DoWhileLoop, _stmt: Node<Stmt>) {
+        self.write("do ");
+        self.block(do_while_loop.body);
+        self.write(" while ");
+        self.visit_expr(do_while_loop.cond);
+    }
+
+    fn visit_continue(&mut self, _stmt: Node<Stmt>) {
+        self.write("continue");
+    }
+
+    fn visit_return(&mut self, ret: &'a ast::Return, _stmt: Node<Stmt>) {
+        self.write("return");
+        if let Some(val) = ret.val {
+            self.write(" ");
+            self.visit_expr(val);
+        }
+    }
+
+    fn visit_func(&mut self, func: &'a ast::Func, _stmt: Node<Stmt>) {
+        self.vis(func.vis);
+        self.write("func ");
+        self.name(func.name);
+        self.generics(&func.generics);
+        self.write("(");
+        self.list(&func.params, Printer::param);
+        self.write(")");
+        if let Some(ret) = func.ret {
+            self.write(": ");
+            self.annot(ret);
+        }
+        self.write(" ");
+        self.block(func.body);
+    }
+
+    fn visit_enum(&mut self, enum_stmt: &'a ast::Enum, _stmt: Node<Stmt>) {
+        self.vis(enum_stmt.vis);
+        self.write("enum ");
+        self.name(enum_stmt.name);
+        self.write(" ");
+        self.items(&enum_stmt.variants, |p, variant| {
+            let variant = &p.ast[variant];
+            p.name(variant.name);
+            if !variant.fields.is_empty() {
+                p.write("(");
+                p.list(&variant.fields, Printer::annot);
+                p.write(")");
+            }
+        });
+    }
+
+    fn visit_struct(&mut self, struct_stmt: &'a ast::Struct, _stmt: Node<Stmt>) {
+        self.vis(struct_stmt.vis);
+        self.write("struct ");
+        self.name(struct_stmt.name);
+        self.generics(&struct_stmt.generics);
+        self.write(" ");
+        self.items(&struct_stmt.fields, Printer::param);
+    }
+
+    fn visit_trait(&mut self, trait_stmt: &'a ast::Trait, _stmt: Node<Stmt>) {
+        self.vis(trait_stmt.vis);
+        self.write("trait ");
+        self.name(trait_stmt.name);
+        self.write(" ");
+        self.items(&trait_stmt.methods, |p, sig| {
+            let sig = &p.ast[sig];
+            p.write("func ");
+            p.name(sig.name);
+            p.write("(");
+            p.list(&sig.params, Printer::param);
+            p.write(")");
+            if let Some(ret) = sig.ret {
+                p.write(": ");
+                p.annot(ret);
+            }
+        });
+    }
+
+    fn visit_impl(&mut self, impl_stmt: &'a ast::Impl, _stmt: Node<Stmt>) {
+        self.write("impl ");
+        if let Some(trait_name) = impl_stmt.trait_name {
+            self.name(trait_name);
+            self.write(" for ");
+        }
+        self.annot(impl_stmt.typ);
+        self.write(" ");
+        self.braced(&impl_stmt.methods);
+    }
+
+    fn visit_module(&mut self, module: &'a ast::Module, _stmt: Node<Stmt>) {
+        self.vis(module.vis);
+        self.write("mod ");
+        self.name(module.name);
+    }
+
+    fn visit_use(&mut self, use_stmt: &'a ast::Use, _stmt: Node<Stmt>) {
+        self.write("use ");
+        self.write(&self.ast[use_stmt.path].display(self.ast));
+    }
+
+    fn visit_expr_stmt(&mut self, expr_stmt: &'a ast::ExprStmt, _stmt: Node<Stmt>) {
+        self.visit_expr(expr_stmt.expr);
+    }
+}
@@ -1,28 +1,146 @@
 use std::{cell::RefCell, rc::Rc};
 
 use crate::{
-    ast::{Block, Func},
+    ast::{
+        self,
+        visit::walk_mut::{self, VisitorMut},
+        Ast, Block, Expr, Ident, Node, NodeKind, SideTable, Stmt,
+    },
+    location::Span,
+    printer,
     scope::Scope,
+    symbols::SymbolId,
+    types::DataType,
 };
 
 #[derive(Debug, Clone)]
 pub struct Program {
-    pub block: Block,
-    pub funcs: Vec<Box<Func>>,
+    pub ast: Ast,
+    pub root: Node<Block>,
+    // The symbol every name refers to, filled in by the resolver
+    pub names: SideTable<SymbolId>,
+    // Filled in by the checker, for expressions, annotations and declared names
+    pub types: SideTable<DataType>,
+    // The instance each call to a generic function runs, as an index into instances
+    pub calls: SideTable<usize>,
+    // The function each method call runs, by call
+    pub methods: SideTable<Method>,
+    // The methods a value gets when it's turned into a dyn, in the order its trait has them
+    pub vtables: SideTable<Vec<SymbolId>>,
+    // Every set of types a generic function gets called with from this program, including calls
+    // made from inside other instances
+    pub instances: Vec<Instance>,
+}
+
+// A generic function checked again for one set of types. Its body's types are kept apart from the
+// ones in the program, which still have the type parameters in them
+#[derive(Debug, Clone)]
+pub struct Instance {
+    pub func: SymbolId,
+    pub args: Vec<DataType>,
+    pub types: SideTable<DataType>,
+    pub calls: SideTable<usize>,
+    pub methods: SideTable<Method>,
+    pub vtables: SideTable<Vec<SymbolId>>,
+}
+
+// Calls on a type that's known go straight to its method, calls on a dyn go through the vtable
+// the value got
+#[derive(Debug, Clone, Copy)]
+pub enum Method {
+    Static(SymbolId),
+    Dyn(usize),
 }
 
 impl Program {
-    pub fn new(scope: Rc<RefCell<Scope>>) -> Program {
-        Program {
-            block: Block {
-                stmts: Vec::new(),
-                scope,
+    pub fn new(mut ast: Ast, stmts: Vec<Node<Stmt>>, span: Span) -> Program {
+        let root = ast.alloc(
+            Block {
+                stmts,
+                scope: Scope::new(None),
             },
-            funcs: Vec::new(),
+            span,
+        );
+
+        Program {
+            ast,
+            root,
+            names: SideTable::default(),
+            types: SideTable::default(),
+            calls: SideTable::default(),
+            methods: SideTable::default(),
+            vtables: SideTable::default(),
+            instances: Vec::new(),
         }
     }
 
+    pub fn scope(&self) -> Rc<RefCell<Scope>> {
+        Rc::clone(&self.ast[self.root].scope)
+    }
+
+    // The function a symbol was declared by, if this program declared it
+    pub fn func(&self, id: SymbolId) -> Option<&ast::Func> {
+        self.ast.ids().find_map(|node| match self.ast.kind(node) {
+            NodeKind::Stmt(Stmt::Func(func)) if self.names.get(func.name) == Some(&id) => {
+                Some(func)
+            }
+            _ => None,
+        })
+    }
+
+    // Gives a symbol a new name everywhere this program declares or uses it. Other symbols with
+    // the same name, like a local in another function, are left alone
+    pub fn rename(&mut self, id: SymbolId, name: &str) {
+        let mut rename = Rename {
+            names: &self.names,
+            id,
+            name,
+        };
+        rename.visit_block(&mut self.ast, self.root);
+    }
+
     pub fn print(&self) {
-        println!("{}", format!("{:#?}", self).replace("  ", " "));
+        print!("{}", printer::print(self));
+    }
+
+    // Every node in the order it was made, so children come before the nodes holding them
+    pub fn dump(&self) -> String {
+        let mut out = String::new();
+        for id in self.ast.ids() {
+            let typ = match self.types.get(id) {
+                Some(typ) => format!(" : {}", typ),
+                None => String::new(),
+            };
+            out.push_str(&format!(
+                "{} {:?}{}, {{ span: {} }}\n",
+                id,
+                self.ast.kind(id),
+                typ,
+                self.ast.span(id)
+            ));
+        }
+        out
+    }
+}
+
+struct Rename<'a> {
+    names: &'a SideTable<SymbolId>,
+    id: SymbolId,
+    name: &'a str,
+}
+
+impl VisitorMut for Rename<'_> {
+    fn visit_ident(&mut self, ast: &mut Ast, ident: Node<Ident>) {
+        if self.names.get(ident) == Some(&self.id) {
+            ast[ident].name = self.name.to_owned();
+        }
+    }
+
+    // Names used as values are only reached through their expression
+    fn visit_expr(&mut self, ast: &mut Ast, expr: Node<Expr>) {
+        if let Expr::Ident(ident) = ast[expr] {
+            self.visit_ident(ast, ident);
+        }
+        walk_mut::walk_expr(self, ast, expr);
     }
 }
@@ -1,6 +1,5 @@
-use std::fs;
+use std::{fs, io, path::Path};
 
-pub fn read_file(location: &str) -> String {
-    let path = format!("text/{}", location);
-    fs::read_to_string(path).expect("Error reading file")
+pub fn read_file(path: &Path) -> io::Result<String> {
+    fs::read_to_string(path)
 }
@@ -0,0 +1,293 @@
+use std::{
+    cell::RefCell,
+    collections::{HashMap, HashSet},
+    io::{self, BufRead, Write},
+    rc::Rc,
+};
+
+use crate::{
+    ast::Stmt,
+    checker::Checker,
+    driver,
+    error::{ErrorFormat, Reporter, Severity},
+    interpreter::Interpreter,
+    lexer::Lexer,
+    lint::{Level, Lint},
+    module::ModuleInfo,
+    parser::Parser,
+    program::Program,
+    resolver::Resolver,
+    scope::Scope,
+    source::{FileId, SourceMap},
+    symbols::SymbolTable,
+    tokens::Type,
+};
+
+const HELP: &str = "Enter statements to run them, the value of an expression gets printed.
+Input with unclosed brackets continues on the next line.
+
+:type <expr>    Show the type of an expression without running it
+:ast <input>    Show the nodes the input parses to
+:tokens <input> Show the tokens the input lexes to
+:help           Show this message
+:quit           Leave, so does the end of the input
+";
+
+// Everything typed so far shares one top level scope, so later input can use earlier names
+pub struct Repl {
+    reporter: Rc<Reporter>,
+    symbols: Rc<RefCell<SymbolTable>>,
+    scope: Rc<RefCell<Scope>>,
+    interpreter: Interpreter,
+    inputs: usize,
+    // Every input that ran, generic functions declared in one are checked again by later ones
+    progs: Vec<Rc<Program>>,
+}
+
+impl Repl {
+    pub fn new(color: bool) -> Repl {
+        let reporter = Reporter::new(SourceMap::new(), color, ErrorFormat::Silent);
+        // Anything declared can still be used by the next input
+        for lint in [
+            Lint::UnusedVariables,
+            Lint::UnusedFunctions,
+            Lint::UnusedParams,
+        ] {
+            reporter.lints.borrow_mut().set(lint, Level::Allow);
+        }
+
+        Repl {
+            reporter,
+            symbols: Rc::new(RefCell::new(SymbolTable::default())),
+            scope: Scope::new(None),
+            interpreter: Interpreter::new(),
+            inputs: 0,
+            progs: Vec::new(),
+        }
+    }
+
+    // Unclosed brackets mean there's more to come
+    pub fn is_complete(input: &str) -> bool {
+        let mut lexer = Lexer::new(Rc::new(input.to_owned()), FileId(0));
+        let depth = lexer.lex().iter().fold(0, |depth, tok| match tok.typ {
+            Type::LeftParen | Type::LeftBrace | Type::LeftBracket => depth + 1,
+            Type::RightParen | Type::RightBrace | Type::RightBracket => depth - 1,
+            _ => depth,
+        });
+        depth <= 0
+    }
+
+    // Whatever a complete input prints, diagnostics included
+    pub fn eval(&mut self, input: &str) -> String {
+        let input = input.trim();
+        let Some(cmd) = input.strip_prefix(':') else {
+            return self.run(input);
+        };
+
+        let (cmd, rest) = cmd.split_once(char::is_whitespace).unwrap_or((cmd, ""));
+        match cmd {
+            "type" => self.type_of(rest),
+            "ast" => self.ast(rest),
+            "tokens" => self.tokens(rest),
+            "help" => HELP.to_owned(),
+            _ => format!("Unknown command :{}, try :help\n", cmd),
+        }
+    }
+
+    fn add_file(&mut self, input: &str) -> (Rc<String>, FileId) {
+        self.inputs += 1;
+        let code = Rc::new(input.to_owned());
+        let file = self
+            .reporter
+            .map
+            .add(format!("<repl:{}>", self.inputs), Rc::clone(&code));
+        (code, file)
+    }
+
+    fn info(&self) -> ModuleInfo {
+        ModuleInfo {
+            name: "repl".to_owned(),
+            scope: Rc::clone(&self.scope),
+            children: HashMap::new(),
+            public: HashSet::new(),
+            items: HashMap::new(),
+        }
+    }
+
+    // Parses, resolves and checks an input against the shared scope. On an error the scope and the
+    // impls go back to how they were, so half of a failed input never becomes visible
+    fn compile(&mut self, input: &str, out: &mut String) -> Option<Program> {
+        let (code, file) = self.add_file(input);
+        let seen = self.reporter.diagnostics().len();
+        let saved = self.scope.borrow().clone();
+        let impls = self.symbols.borrow().impls().len();
+
+        let res = driver::catch_fatal(|| {
+            let mut lexer = Lexer::new(code, file);
+            lexer.lex();
+            let mut parser = Parser::new(Rc::clone(&self.reporter), &lexer.filter());
+            parser.expr_stmts = true;
+            let mut prog = parser.parse();
+            let root = prog.root;
+            prog.ast[root].scope = Rc::clone(&self.scope);
+
+            let mut resolver = Resolver::new(
+                Rc::clone(&self.reporter),
+                Rc::clone(&self.symbols),
+                Rc::new(vec![self.info()]),
+                0,
+                &prog,
+            );
+            resolver.resolve();
+            prog.names = resolver.names;
+
+            let mut checker =
+                Checker::new(Rc::clone(&self.reporter), Rc::clone(&self.symbols), &prog);
+            checker.others = self.progs.iter().map(|x| &**x).collect();
+            checker.check();
+            let (types, calls) = (checker.types, checker.calls);
+            let (methods, vtables) = (checker.methods, checker.vtables);
+            let instances = checker.instances.take();
+            prog.types = types;
+            prog.calls = calls;
+            prog.methods = methods;
+            prog.vtables = vtables;
+            prog.instances = instances;
+            prog
+        });
+
+        // Denied lints don't stop checking, but they still stop the input from running
+        let diagnostics = &self.reporter.diagnostics()[seen..];
+        for err in diagnostics {
+            out.push_str(&self.reporter.render(err));
+            out.push('\n');
+        }
+
+        match res {
+            Ok(prog) if diagnostics.iter().all(|x| x.severity != Severity::Error) => Some(prog),
+            _ => {
+                *self.scope.borrow_mut() = saved;
+                self.symbols.borrow_mut().truncate_impls(impls);
+                None
+            }
+        }
+    }
+
+    // Like compile, but nothing gets declared
+    fn inspect(&mut self, input: &str, out: &mut String) -> Option<Program> {
+        let saved = self.scope.borrow().clone();
+        let impls = self.symbols.borrow().impls().len();
+        let prog = self.compile(input, out);
+        *self.scope.borrow_mut() = saved;
+        self.symbols.borrow_mut().truncate_impls(impls);
+        prog
+    }
+
+    fn run(&mut self, input: &str) -> String {
+        let mut out = String::new();
+        let Some(prog) = self.compile(input, &mut out) else {
+            return out;
+        };
+
+        let prog = Rc::new(prog);
+        self.progs.push(Rc::clone(&prog));
+        match self.interpreter.run(&prog) {
+            Ok(Some(val)) => {
+                let last = *prog.ast[prog.root].stmts.last().unwrap();
+                let typ = match &prog.ast[last] {
+                    Stmt::ExprStmt(x) => prog.types.get(x.expr),
+                    _ => None,
+                };
+                match typ {
+                    Some(typ) => out.push_str(&format!("{}: {}\n", val, typ)),
+                    None => out.push_str(&format!("{}\n", val)),
+                }
+            }
+            Ok(None) => (),
+            Err(trap) => out.push_str(&format!(
+                "error: {} ({})\n",
+                trap.msg,
+                self.reporter.map.format(trap.span)
+            )),
+        }
+
+        out
+    }
+
+    fn type_of(&mut self, input: &str) -> String {
+        let mut out = String::new();
+        let Some(prog) = self.inspect(input, &mut out) else {
+            return out;
+        };
+
+        let stmts = &prog.ast[prog.root].stmts;
+        let typ = match stmts.last().map(|&x| &prog.ast[x]) {
+            Some(Stmt::ExprStmt(x)) if stmts.len() == 1 => prog.types.get(x.expr),
+            _ => {
+                out.push_str("Expected a single expression\n");
+                return out;
+            }
+        };
+
+        match typ {
+            Some(typ) => out.push_str(&format!("{}\n", typ)),
+            None => out.push_str("This expression does not have a value\n"),
+        }
+        out
+    }
+
+    fn ast(&mut self, input: &str) -> String {
+        let mut out = String::new();
+        if let Some(prog) = self.inspect(input, &mut out) {
+            out.push_str(&prog.dump());
+        }
+        out
+    }
+
+    fn tokens(&mut self, input: &str) -> String {
+        let (code, file) = self.add_file(input);
+        let mut lexer = Lexer::new(code, file);
+        lexer.lex();
+
+        lexer
+            .filter()
+            .iter()
+            .filter(|tok| tok.typ != Type::EOF)
+            .map(|tok| format!("{}\n", tok))
+            .collect()
+    }
+}
+
+// Reads input until :quit or the end, a prompt is only shown when there's someone to see it
+pub fn run(
+    input: &mut impl BufRead,
+    out: &mut impl Write,
+    color: bool,
+    prompt: bool,
+) -> io::Result<()> {
+    let mut repl = Repl::new(color);
+    let mut buf = String::new();
+    loop {
+        if prompt {
+            write!(out, "{}", if buf.is_empty() { "> " } else { "... " })?;
+            out.flush()?;
+        }
+
+        let mut line = String::new();
+        if input.read_line(&mut line)? == 0 {
+            return Ok(());
+        }
+
+        buf.push_str(&line);
+        if !Repl::is_complete(&buf) {
+            continue;
+        }
+
+        let text = std::mem::take(&mut buf);
+        match text.trim() {
+            "" => (),
+            ":quit" | ":q" => return Ok(()),
+            text => write!(out, "{}", repl.eval(text))?,
+        }
+    }
+}
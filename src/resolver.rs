@@ -0,0 +1,200 @@
+use std::{cell::RefCell, collections::HashMap, rc::Rc};
+
+pub(crate) mod lints;
+pub(crate) mod modules;
+pub(crate) mod statements;
+
+use crate::{
+    ast::{self, visit::Visitor, Ast, Expr, Ident, Node, SideTable, Stmt},
+    error::{Error, ErrorCode, Reporter},
+    location::Span,
+    module::ModuleInfo,
+    program::Program,
+    scope::Scope,
+    suggest,
+    symbols::{SymbolId, SymbolKind, SymbolTable},
+};
+
+// Works out what every name refers to, between parsing and checking
+pub struct Resolver<'a> {
+    pub reporter: Rc<Reporter>,
+    pub symbols: Rc<RefCell<SymbolTable>>,
+    pub names: SideTable<SymbolId>,
+    prog: &'a Program,
+    root: Rc<RefCell<Scope>>,
+    top: Rc<RefCell<Scope>>,
+    // Variables declared further down in each open scope, so using one early gets a better error
+    pending: Vec<HashMap<String, Span>>,
+    modules: Rc<Vec<ModuleInfo>>,
+    module: usize,
+}
+
+impl<'a> Resolver<'a> {
+    pub fn new(
+        reporter: Rc<Reporter>,
+        symbols: Rc<RefCell<SymbolTable>>,
+        modules: Rc<Vec<ModuleInfo>>,
+        module: usize,
+        prog: &'a Program,
+    ) -> Resolver<'a> {
+        let root = prog.scope();
+        Resolver {
+            reporter,
+            symbols,
+            names: SideTable::default(),
+            prog,
+            top: Rc::clone(&root),
+            root,
+            pending: Vec::new(),
+            modules,
+            module,
+        }
+    }
+
+    fn raise(&self, err: Error) {
+        self.reporter.panic(&err);
+    }
+
+    fn at_top_level(&self) -> bool {
+        Rc::ptr_eq(&self.top, &self.root)
+    }
+
+    // Adds a "did you mean" when a name that can be used here is close to the one that was given
+    fn suggest_name(&self, err: Error, name: &str, span: Span, names: &[String]) -> Error {
+        match suggest::closest(name, names.iter().map(String::as_str)) {
+            Some(best) => err.suggest(span, best.to_owned(), "a similar name exists".to_owned()),
+            None => err,
+        }
+    }
+
+    pub(crate) fn declare(
+        &mut self,
+        scope: &Rc<RefCell<Scope>>,
+        node: Node<Ident>,
+        kind: SymbolKind,
+    ) -> SymbolId {
+        let name = &self.ast()[node].name;
+        let span = self.ast().span(node);
+
+        let prev = scope.borrow().local(name);
+        if let Some(prev) = prev {
+            let prev = self.symbols.borrow().get(prev).span;
+            self.raise(
+                Error::new(
+                    format!("'{}' is already declared in this scope", name),
+                    span,
+                    ErrorCode::DuplicateDeclaration,
+                )
+                .label(prev, "first declared here".to_owned()),
+            );
+        }
+        self.check_shadowing(scope, name, span);
+
+        let id = self.symbols.borrow_mut().add(name.clone(), span, kind);
+        scope.borrow_mut().declare(name.clone(), id);
+        self.names.insert(node, id);
+        id
+    }
+
+    // Finds the symbol a name refers to from the current scope
+    pub(crate) fn lookup(&self, name: &str, span: Span) -> SymbolId {
+        let found = self.top.borrow().get(name);
+        if let Some(id) = found {
+            return id;
+        }
+
+        let later = self.pending.iter().rev().find_map(|x| x.get(name));
+        let err = match later {
+            Some(decl) => Error::new(
+                format!("'{}' is used before it is declared", name),
+                span,
+                ErrorCode::UseBeforeDeclaration,
+            )
+            .label(*decl, "declared here".to_owned()),
+            None => {
+                let names = self.top.borrow().names();
+                let err = Error::new(
+                    format!("The variable '{}' does not exist", name),
+                    span,
+                    ErrorCode::VariableNotFound,
+                );
+                self.suggest_name(err, name, span, &names)
+            }
+        };
+        self.raise(err);
+        panic!()
+    }
+
+    pub fn resolve(&mut self) {
+        self.resolve_stmts(self.prog.root);
+    }
+}
+
+// Anything that doesn't declare or use a name is left to the default walk
+impl<'a> Visitor<'a> for Resolver<'a> {
+    fn ast(&self) -> &'a Ast {
+        &self.prog.ast
+    }
+
+    fn visit_block(&mut self, block: Node<ast::Block>) {
+        self.resolve_block(block);
+    }
+
+    fn visit_decl(&mut self, decl: &'a ast::Decl, _stmt: Node<Stmt>) {
+        self.resolve_decl(decl);
+    }
+
+    fn visit_assign(&mut self, assign: &'a ast::Assign, _stmt: Node<Stmt>) {
+        self.resolve_assign(assign);
+    }
+
+    fn visit_if_let(&mut self, if_let: &'a ast::IfLet, _stmt: Node<Stmt>) {
+        self.resolve_if_let(if_let);
+    }
+
+    fn visit_func(&mut self, func: &'a ast::Func, _stmt: Node<Stmt>) {
+        self.resolve_func(func);
+    }
+
+    fn visit_trait(&mut self, _trait_stmt: &'a ast::Trait, stmt: Node<Stmt>) {
+        if !self.at_top_level() {
+            self.raise(Error::new(
+                "Traits can only be declared at the top level".to_owned(),
+                self.ast().span(stmt),
+                ErrorCode::InvalidStatement,
+            ));
+        }
+    }
+
+    fn visit_impl(&mut self, impl_stmt: &'a ast::Impl, stmt: Node<Stmt>) {
+        self.resolve_impl(self.ast().span(stmt), impl_stmt);
+    }
+
+    fn visit_module(&mut self, _module: &'a ast::Module, stmt: Node<Stmt>) {
+        self.resolve_module_stmt(self.ast().span(stmt));
+    }
+
+    fn visit_use(&mut self, use_stmt: &'a ast::Use, stmt: Node<Stmt>) {
+        self.resolve_use(self.ast().span(stmt), use_stmt);
+    }
+
+    fn visit_ident_expr(&mut self, ident: Node<Ident>, _expr: Node<Expr>) {
+        let id = self.lookup(&self.ast()[ident].name, self.ast().span(ident));
+        self.symbols.borrow_mut().get_mut(id).used = true;
+        self.names.insert(ident, id);
+    }
+
+    fn visit_path_expr(&mut self, path: Node<ast::Path>, _expr: Node<Expr>) {
+        if !self.resolve_value_path(path) {
+            self.resolve_path(path);
+        }
+    }
+
+    fn visit_arm(&mut self, arm: Node<ast::Arm>) {
+        self.resolve_arm(arm);
+    }
+
+    fn visit_pattern(&mut self, pattern: Node<ast::Pattern>) {
+        self.resolve_pattern(pattern);
+    }
+}
@@ -0,0 +1,82 @@
+use std::{cell::RefCell, rc::Rc};
+
+use crate::{error::Error, lint::Lint, location::Span, scope::Scope, symbols::SymbolKind};
+
+use super::Resolver;
+
+impl Resolver<'_> {
+    // Once a scope is done with, nothing else can read what was declared in it
+    pub(crate) fn check_unused(&self, scope: &Rc<RefCell<Scope>>) {
+        let public = &self.modules[self.module].public;
+        let top_level = Rc::ptr_eq(scope, &self.root);
+
+        let symbols = self.symbols.borrow();
+        let mut locals = scope.borrow().locals();
+        locals.sort_by_key(|(_, id)| symbols.get(*id).span.lo);
+
+        for (name, id) in locals {
+            let symbol = symbols.get(id);
+            // Starting a name with an underscore says it's unused on purpose, and a method doesn't
+            // get to choose whether it takes self
+            if symbol.used
+                || name.starts_with('_')
+                || name == "self"
+                || (top_level && public.contains(&name))
+            {
+                continue;
+            }
+
+            let (lint, msg) = match symbol.kind {
+                SymbolKind::Variable => (
+                    Lint::UnusedVariables,
+                    format!("The variable '{}' is never used", name),
+                ),
+                SymbolKind::Function => (
+                    Lint::UnusedFunctions,
+                    format!("The function '{}' is never called", name),
+                ),
+                SymbolKind::Param => (
+                    Lint::UnusedParams,
+                    format!("The parameter '{}' is never used", name),
+                ),
+                // Annotations aren't resolved, so there's no telling whether a type is used
+                SymbolKind::Enum
+                | SymbolKind::Variant
+                | SymbolKind::Struct
+                | SymbolKind::Field
+                | SymbolKind::TypeParam
+                | SymbolKind::Trait
+                | SymbolKind::Method => continue,
+            };
+
+            self.reporter
+                .lint(Error::lint(lint, msg, symbol.span).suggest(
+                    symbol.span,
+                    format!("_{}", name),
+                    "if this is on purpose, start the name with an underscore".to_owned(),
+                ));
+        }
+    }
+
+    // Only names from outer scopes count, the same scope twice is a duplicate instead
+    pub(crate) fn check_shadowing(&self, scope: &Rc<RefCell<Scope>>, name: &str, span: Span) {
+        let outer = match &scope.borrow().parent {
+            Some(parent) => parent.borrow().get(name),
+            None => return,
+        };
+
+        if let Some(outer) = outer {
+            self.reporter.lint(
+                Error::lint(
+                    Lint::Shadowing,
+                    format!("'{}' shadows a name from an outer scope", name),
+                    span,
+                )
+                .label(
+                    self.symbols.borrow().get(outer).span,
+                    "first declared here".to_owned(),
+                ),
+            );
+        }
+    }
+}
@@ -0,0 +1,271 @@
+use crate::{
+    ast::{self, visit::Visitor, Node, Visibility},
+    error::{Error, ErrorCode},
+    location::Span,
+    symbols::{SymbolId, SymbolKind},
+};
+
+use super::Resolver;
+
+impl<'a> Resolver<'a> {
+    // Walks down the module tree, only the first step may go through a private module
+    fn resolve_module(&self, segments: &[Node<ast::Ident>]) -> usize {
+        let mut cur = self.module;
+        let ast = self.ast();
+        for (idx, &seg) in segments.iter().enumerate() {
+            let (name, span) = (&ast[seg].name, ast.span(seg));
+            let info = &self.modules[cur];
+            match info.children.get(name) {
+                Some(child) if idx == 0 || child.vis == Visibility::Public => cur = child.id,
+                Some(child) => {
+                    let decl = Span::new(child.span.file, child.span.lo, child.span.lo);
+                    self.raise(
+                        Error::new(
+                            format!("The module '{}' is private to '{}'", name, info.name),
+                            span,
+                            ErrorCode::PrivateItem,
+                        )
+                        .label(child.span, "module declared here".to_owned())
+                        .suggest(
+                            decl,
+                            "pub ".to_owned(),
+                            "make the module public".to_owned(),
+                        ),
+                    )
+                }
+                None => {
+                    let err = Error::new(
+                        format!("The module '{}' does not exist in '{}'", name, info.name),
+                        span,
+                        ErrorCode::ModuleNotFound,
+                    );
+                    let names: Vec<String> = info.children.keys().cloned().collect();
+                    self.raise(self.suggest_name(err, name, span, &names));
+                }
+            }
+        }
+
+        cur
+    }
+
+    // The path starts at a module, unless its first name is something in scope that has
+    // members, like the enum in Shape.Circle. Whatever comes after the item is one of its
+    // members, and both the last segment and the path itself refer to that
+    pub(crate) fn resolve_path(&mut self, path: Node<ast::Path>) -> SymbolId {
+        let ast = self.ast();
+        let segments = &ast[path].segments;
+
+        let first = &ast[segments[0]].name;
+        let local = match self.modules[self.module].children.contains_key(first) {
+            true => None,
+            false => self.top.borrow().get(first),
+        };
+        let (mut id, members) = match local {
+            Some(id) if self.symbols.borrow().get(id).kind == SymbolKind::Enum => {
+                self.symbols.borrow_mut().get_mut(id).used = true;
+                self.names.insert(segments[0], id);
+                (id, &segments[1..])
+            }
+            _ => {
+                let modules = self.module_prefix(segments);
+                let module = self.resolve_module(&segments[..modules]);
+                (
+                    self.resolve_item(module, segments[modules]),
+                    &segments[modules + 1..],
+                )
+            }
+        };
+
+        for &member in members {
+            id = self.resolve_member(id, member);
+        }
+        self.names.insert(path, id);
+        id
+    }
+
+    // p.x.y when p is a variable, only p is a name and the rest are fields for the checker. A
+    // module with the same name still comes first
+    pub(crate) fn resolve_value_path(&mut self, path: Node<ast::Path>) -> bool {
+        let first = self.ast()[path].segments[0];
+        let name = &self.ast()[first].name;
+        if self.modules[self.module].children.contains_key(name) {
+            return false;
+        }
+
+        let found = self.top.borrow().get(name);
+        match found {
+            Some(id)
+                if matches!(
+                    self.symbols.borrow().get(id).kind,
+                    SymbolKind::Variable | SymbolKind::Param
+                ) =>
+            {
+                self.symbols.borrow_mut().get_mut(id).used = true;
+                self.names.insert(first, id);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    // How many names at the start are modules, every path has at least one before its item
+    fn module_prefix(&self, segments: &[Node<ast::Ident>]) -> usize {
+        let mut cur = self.module;
+        let mut count = 0;
+        for &seg in &segments[..segments.len() - 1] {
+            match self.modules[cur].children.get(&self.ast()[seg].name) {
+                Some(child) => cur = child.id,
+                None => break,
+            }
+            count += 1;
+        }
+        count.max(1)
+    }
+
+    fn resolve_item(&mut self, module: usize, item: Node<ast::Ident>) -> SymbolId {
+        let ast = self.ast();
+        let info = &self.modules[module];
+        let name = &ast[item].name;
+        let span = ast.span(item);
+        let res = info.scope.borrow().get(name);
+        match res {
+            Some(symbol) if module == self.module || info.public.contains(name) => {
+                self.symbols.borrow_mut().get_mut(symbol).used = true;
+                self.names.insert(item, symbol);
+                symbol
+            }
+            Some(symbol) => {
+                let decl = self.symbols.borrow().get(symbol).span;
+                let err = Error::new(
+                    format!("'{}' is private to the module '{}'", name, info.name),
+                    span,
+                    ErrorCode::PrivateItem,
+                )
+                .label(decl, format!("'{}' declared here", name));
+
+                // Suggest putting pub in front of the whole statement
+                let err = match info.items.get(name) {
+                    Some(stmt) => err.suggest(
+                        Span::new(stmt.file, stmt.lo, stmt.lo),
+                        "pub ".to_owned(),
+                        format!(
+                            "mark '{}' as pub to use it outside of '{}'",
+                            name, info.name
+                        ),
+                    ),
+                    None => err,
+                };
+                self.raise(err);
+                panic!()
+            }
+            None => {
+                let err = Error::new(
+                    format!("'{}' does not exist in the module '{}'", name, info.name),
+                    span,
+                    ErrorCode::VariableNotFound,
+                );
+
+                // Private items would only lead to another error
+                let names: Vec<String> = info
+                    .scope
+                    .borrow()
+                    .names()
+                    .into_iter()
+                    .filter(|x| module == self.module || info.public.contains(x))
+                    .collect();
+                self.raise(self.suggest_name(err, name, span, &names));
+                panic!()
+            }
+        }
+    }
+
+    // Members are as public as whatever they belong to
+    fn resolve_member(&mut self, owner: SymbolId, member: Node<ast::Ident>) -> SymbolId {
+        let ast = self.ast();
+        let name = &ast[member].name;
+        let span = ast.span(member);
+        let symbols = self.symbols.borrow();
+        let owner = symbols.get(owner);
+        let Some(members) = owner.members.clone() else {
+            let err = Error::new(
+                format!("'{}' has no members, so it has no '{}'", owner.name, name),
+                span,
+                ErrorCode::VariableNotFound,
+            );
+            drop(symbols);
+            self.raise(err);
+            panic!()
+        };
+
+        let found = members.borrow().get(name);
+        match found {
+            Some(id) => {
+                drop(symbols);
+                self.symbols.borrow_mut().get_mut(id).used = true;
+                self.names.insert(member, id);
+                id
+            }
+            None => {
+                let err = Error::new(
+                    format!("'{}' has no variant '{}'", owner.name, name),
+                    span,
+                    ErrorCode::VariableNotFound,
+                )
+                .label(owner.span, format!("'{}' declared here", owner.name));
+                drop(symbols);
+                let names = members.borrow().names();
+                self.raise(self.suggest_name(err, name, span, &names));
+                panic!()
+            }
+        }
+    }
+
+    pub(crate) fn resolve_module_stmt(&mut self, span: Span) {
+        if !self.at_top_level() {
+            self.raise(Error::new(
+                "Modules can only be declared at the top level".to_owned(),
+                span,
+                ErrorCode::InvalidStatement,
+            ));
+        }
+    }
+
+    pub(crate) fn resolve_use(&mut self, span: Span, use_stmt: &ast::Use) {
+        if !self.at_top_level() {
+            self.raise(Error::new(
+                "Imports can only be used at the top level".to_owned(),
+                span,
+                ErrorCode::InvalidStatement,
+            ));
+        }
+
+        let ast = self.ast();
+        let path = use_stmt.path;
+        let segments = &ast[path].segments;
+        if segments.len() < 2 {
+            self.raise(Error::new(
+                format!(
+                    "Expected a path to an item, like {}.item",
+                    ast[path].display(ast)
+                ),
+                ast.span(path),
+                ErrorCode::InvalidStatement,
+            ));
+        }
+
+        let id = self.resolve_path(path);
+        let name = ast[*segments.last().unwrap()].name.clone();
+        if let Some(prev) = self.top.borrow().local(&name) {
+            let prev = self.symbols.borrow().get(prev).span;
+            self.raise(
+                Error::new(
+                    format!("'{}' is already declared in this scope", name),
+                    ast.span(path),
+                    ErrorCode::DuplicateDeclaration,
+                )
+                .label(prev, "first declared here".to_owned()),
+            );
+        }
+        self.top.borrow_mut().import(name, id);
+    }
+}
@@ -0,0 +1,224 @@
+use std::{cell::RefCell, collections::HashMap, rc::Rc};
+
+use crate::{
+    ast::{
+        self,
+        visit::{walk, Visitor},
+        Node, Stmt,
+    },
+    error::{Error, ErrorCode},
+    location::Span,
+    scope::Scope,
+    symbols::{SymbolId, SymbolKind},
+};
+
+use super::Resolver;
+
+impl<'a> Resolver<'a> {
+    // Everything in a block, once its scope is the current one
+    pub(crate) fn resolve_stmts(&mut self, block: Node<ast::Block>) {
+        let ast = self.ast();
+        let scope = Rc::clone(&ast[block].scope);
+
+        // Functions and types can be used before they are declared, so they get declared up front
+        for &stmt in &ast[block].stmts {
+            match &ast[stmt] {
+                Stmt::Func(func) => {
+                    self.declare(&scope, func.name, SymbolKind::Function);
+                }
+                Stmt::Enum(enum_stmt) => self.declare_enum(&scope, enum_stmt),
+                Stmt::Struct(struct_stmt) => self.declare_struct(&scope, struct_stmt),
+                Stmt::Trait(trait_stmt) => self.declare_trait(&scope, trait_stmt),
+                _ => (),
+            }
+        }
+
+        let mut pending = HashMap::new();
+        for &stmt in &ast[block].stmts {
+            if let Stmt::Decl(decl) = &ast[stmt] {
+                pending
+                    .entry(ast[decl.name].name.clone())
+                    .or_insert(ast.span(decl.name));
+            }
+        }
+        self.pending.push(pending);
+
+        walk::walk_block(self, block);
+
+        self.pending.pop();
+        self.check_unused(&scope);
+    }
+
+    pub(crate) fn resolve_block(&mut self, block: Node<ast::Block>) {
+        let scope = Rc::clone(&self.ast()[block].scope);
+        scope.borrow_mut().parent = Some(Rc::clone(&self.top));
+
+        let top = std::mem::replace(&mut self.top, scope);
+        self.resolve_stmts(block);
+        self.top = top;
+    }
+
+    // The variants only get their own scope, so Shape.Circle doesn't take the name Circle
+    pub(crate) fn declare_enum(&mut self, scope: &Rc<RefCell<Scope>>, enum_stmt: &ast::Enum) {
+        let id = self.declare(scope, enum_stmt.name, SymbolKind::Enum);
+        let members = Scope::new(None);
+        for &variant in &enum_stmt.variants {
+            self.declare(&members, self.ast()[variant].name, SymbolKind::Variant);
+        }
+        self.symbols.borrow_mut().get_mut(id).members = Some(members);
+    }
+
+    // Fields are members like variants are. The type parameters get the struct's own scope, where
+    // the checker can find them while it works out the field types
+    pub(crate) fn declare_struct(&mut self, scope: &Rc<RefCell<Scope>>, struct_stmt: &ast::Struct) {
+        let id = self.declare(scope, struct_stmt.name, SymbolKind::Struct);
+        struct_stmt.scope.borrow_mut().parent = Some(Rc::clone(scope));
+        let generics = self.declare_generics(&struct_stmt.scope, &struct_stmt.generics);
+
+        let members = Scope::new(None);
+        for &field in &struct_stmt.fields {
+            self.declare(&members, self.ast()[field].name, SymbolKind::Field);
+        }
+
+        let mut symbols = self.symbols.borrow_mut();
+        let symbol = symbols.get_mut(id);
+        symbol.members = Some(members);
+        symbol.generics = generics;
+    }
+
+    // The methods are members, and Self is a type only the trait can see. It's a member too, so
+    // impls can fill it in without the trait's scope
+    pub(crate) fn declare_trait(&mut self, scope: &Rc<RefCell<Scope>>, trait_stmt: &ast::Trait) {
+        let id = self.declare(scope, trait_stmt.name, SymbolKind::Trait);
+        trait_stmt.scope.borrow_mut().parent = Some(Rc::clone(scope));
+        let self_id = self.declare_self(&trait_stmt.scope, self.ast().span(trait_stmt.name));
+
+        let members = Scope::new(None);
+        members.borrow_mut().declare("Self".to_owned(), self_id);
+        for &method in &trait_stmt.methods {
+            self.declare(&members, self.ast()[method].name, SymbolKind::Method);
+        }
+        self.symbols.borrow_mut().get_mut(id).members = Some(members);
+    }
+
+    // Self isn't written anywhere, so it gets the span of what it stands for
+    fn declare_self(&mut self, scope: &Rc<RefCell<Scope>>, span: Span) -> SymbolId {
+        let id = self
+            .symbols
+            .borrow_mut()
+            .add("Self".to_owned(), span, SymbolKind::TypeParam);
+        scope.borrow_mut().declare("Self".to_owned(), id);
+        id
+    }
+
+    // Methods only get found through the type they're called on, so they don't go in any scope
+    // a name can be looked up in. Their bodies can see Self
+    pub(crate) fn resolve_impl(&mut self, span: Span, impl_stmt: &ast::Impl) {
+        if !self.at_top_level() {
+            self.raise(Error::new(
+                "Impls can only be at the top level".to_owned(),
+                span,
+                ErrorCode::InvalidStatement,
+            ));
+        }
+
+        let scope = Rc::clone(&impl_stmt.scope);
+        scope.borrow_mut().parent = Some(Rc::clone(&self.top));
+        self.declare_self(&scope, self.ast().span(impl_stmt.typ));
+
+        let members = Scope::new(None);
+        for &method in &impl_stmt.methods {
+            if let Stmt::Func(func) = &self.ast()[method] {
+                self.declare(&members, func.name, SymbolKind::Method);
+            }
+        }
+
+        let top = std::mem::replace(&mut self.top, scope);
+        for &method in &impl_stmt.methods {
+            self.visit_stmt(method);
+        }
+        self.top = top;
+    }
+
+    fn declare_generics(
+        &mut self,
+        scope: &Rc<RefCell<Scope>>,
+        generics: &[Node<ast::TypeParam>],
+    ) -> Vec<SymbolId> {
+        generics
+            .iter()
+            .map(|&param| self.declare(scope, self.ast()[param].name, SymbolKind::TypeParam))
+            .collect()
+    }
+
+    // Every arm gets a scope for its bindings, which only the guard and the body can see
+    pub(crate) fn resolve_arm(&mut self, arm: Node<ast::Arm>) {
+        let scope = Rc::clone(&self.ast()[arm].scope);
+        scope.borrow_mut().parent = Some(Rc::clone(&self.top));
+
+        let top = std::mem::replace(&mut self.top, Rc::clone(&scope));
+        walk::walk_arm(self, arm);
+        self.top = top;
+        self.check_unused(&scope);
+    }
+
+    pub(crate) fn resolve_pattern(&mut self, pattern: Node<ast::Pattern>) {
+        match &self.ast()[pattern] {
+            ast::Pattern::Binding(name) => {
+                let scope = Rc::clone(&self.top);
+                self.declare(&scope, *name, SymbolKind::Variable);
+            }
+            ast::Pattern::Variant(path, _) => {
+                self.resolve_path(*path);
+                walk::walk_pattern(self, pattern);
+            }
+            _ => walk::walk_pattern(self, pattern),
+        }
+    }
+
+    pub(crate) fn resolve_decl(&mut self, decl: &ast::Decl) {
+        // The value comes first, so `let x = x` means the outer x
+        self.visit_expr(decl.val);
+
+        let scope = Rc::clone(&self.top);
+        self.declare(&scope, decl.name, SymbolKind::Variable);
+        if let Some(pending) = self.pending.last_mut() {
+            pending.remove(&self.prog.ast[decl.name].name);
+        }
+    }
+
+    pub(crate) fn resolve_assign(&mut self, assign: &ast::Assign) {
+        self.visit_expr(assign.val);
+
+        // Assigning to a variable doesn't use it, so it isn't marked
+        let ast = self.ast();
+        let id = self.lookup(&ast[assign.name].name, ast.span(assign.name));
+        self.names.insert(assign.name, id);
+    }
+
+    // The name goes in the body's scope like a parameter would, the value can't see it
+    pub(crate) fn resolve_if_let(&mut self, if_let: &ast::IfLet) {
+        self.visit_expr(if_let.val);
+
+        let scope = Rc::clone(&self.ast()[if_let.body].scope);
+        scope.borrow_mut().parent = Some(Rc::clone(&self.top));
+        self.declare(&scope, if_let.name, SymbolKind::Variable);
+
+        self.visit_block(if_let.body);
+    }
+
+    pub(crate) fn resolve_func(&mut self, func: &ast::Func) {
+        let ast = self.ast();
+        let scope = Rc::clone(&ast[func.body].scope);
+        scope.borrow_mut().parent = Some(Rc::clone(&self.top));
+        let generics = self.declare_generics(&scope, &func.generics);
+        let id = *self.names.get(func.name).unwrap();
+        self.symbols.borrow_mut().get_mut(id).generics = generics;
+
+        for &param in &func.params {
+            self.declare(&scope, ast[param].name, SymbolKind::Param);
+        }
+
+        self.visit_block(func.body);
+    }
+}
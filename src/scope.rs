@@ -1,73 +1,80 @@
-use std::{cell::RefCell, collections::HashMap, rc::Rc};
+use core::fmt;
+use std::{
+    cell::RefCell,
+    collections::{HashMap, HashSet},
+    rc::Rc,
+};
 
-use crate::{error::ErrorCode, types::DataType};
-
-// Variable
-#[derive(Debug, Clone)]
-pub struct Variable {
-    pub id: u32,
-    pub typ: Option<DataType>,
-}
-
-pub static mut VARIABLE_ID: u32 = 0;
+use crate::symbols::SymbolId;
 
 // Scop3e
-#[derive(Debug, Clone)]
+#[derive(Clone)]
 pub struct Scope {
     pub parent: Option<Rc<RefCell<Scope>>>,
-    varis: HashMap<String, Rc<RefCell<Variable>>>,
+    symbols: HashMap<String, SymbolId>,
+    imports: HashSet<String>,
 }
 
 impl Scope {
     pub fn new(parent: Option<Rc<RefCell<Scope>>>) -> Rc<RefCell<Scope>> {
         Rc::new(RefCell::new(Scope {
             parent,
-            varis: HashMap::new(),
+            symbols: HashMap::new(),
+            imports: HashSet::new(),
         }))
     }
 
-    pub fn declare(&mut self, name: String) {
-        self.varis.insert(
-            name,
-            Rc::new(RefCell::new(Variable {
-                id: unsafe { VARIABLE_ID.clone() },
-                typ: None,
-            })),
-        );
-        unsafe { VARIABLE_ID += 1 };
+    pub fn declare(&mut self, name: String, id: SymbolId) {
+        self.symbols.insert(name, id);
     }
 
-    pub fn param(&mut self, name: String, typ: DataType) {
-        self.varis.insert(
-            name,
-            Rc::new(RefCell::new(Variable {
-                id: unsafe { VARIABLE_ID.clone() },
-                typ: Some(typ),
-            })),
-        );
-        unsafe { VARIABLE_ID += 1 };
+    pub fn import(&mut self, name: String, id: SymbolId) {
+        self.imports.insert(name.clone());
+        self.symbols.insert(name, id);
     }
 
-    pub fn set(&self, name: &String, typ: DataType) -> Option<ErrorCode> {
-        match self.varis.get(name) {
-            Some(val) => {
-                val.borrow_mut().typ = Some(typ);
-                None
-            }
-            None => match self.parent {
-                Some(ref scope) => scope.as_ref().borrow().set(name, typ),
-                None => Some(ErrorCode::VariableNotFound),
-            },
+    // Only this scope, without looking at its parents
+    pub fn local(&self, name: &str) -> Option<SymbolId> {
+        self.symbols.get(name).copied()
+    }
+
+    // Everything declared in this scope itself, imports don't count
+    pub fn locals(&self) -> Vec<(String, SymbolId)> {
+        let mut locals: Vec<(String, SymbolId)> = self
+            .symbols
+            .iter()
+            .filter(|(name, _)| !self.imports.contains(*name))
+            .map(|(name, id)| (name.clone(), *id))
+            .collect();
+        locals.sort_by_key(|(_, id)| *id);
+        locals
+    }
+
+    // Every name that can be used from here, including the ones in parent scopes
+    pub fn names(&self) -> Vec<String> {
+        let mut names: Vec<String> = self.symbols.keys().cloned().collect();
+        if let Some(ref scope) = self.parent {
+            names.extend(scope.borrow().names());
         }
+        names
     }
 
-    pub fn get(&self, name: &String) -> Result<Rc<RefCell<Variable>>, ErrorCode> {
-        match self.varis.get(name) {
-            Some(val) if val.borrow().typ.is_some() => Ok(Rc::clone(val)),
-            _ => match self.parent {
-                Some(ref scope) => scope.as_ref().borrow().get(name),
-                None => Err(ErrorCode::VariableNotFound),
+    pub fn get(&self, name: &str) -> Option<SymbolId> {
+        match self.symbols.get(name) {
+            Some(id) => Some(*id),
+            None => match self.parent {
+                Some(ref scope) => scope.borrow().get(name),
+                None => None,
             },
         }
     }
 }
+
+// Only the scope's own names and sorted, so dumps come out the same every time
+impl fmt::Debug for Scope {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut symbols: Vec<_> = self.symbols.iter().collect();
+        symbols.sort();
+        f.debug_map().entries(symbols).finish()
+    }
+}
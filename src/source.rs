@@ -0,0 +1,124 @@
+use core::fmt;
+use std::{cell::RefCell, rc::Rc};
+
+use crate::location::{Location, Span};
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct FileId(pub u32);
+
+impl fmt::Display for FileId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+pub struct SourceFile {
+    pub id: FileId,
+    pub path: String,
+    pub src: Rc<String>,
+    // Byte offset of the start of every line
+    line_starts: Vec<u32>,
+}
+
+impl SourceFile {
+    pub fn new(id: FileId, path: String, src: Rc<String>) -> SourceFile {
+        let mut line_starts = vec![0];
+        for (idx, byte) in src.bytes().enumerate() {
+            if byte == b'\n' {
+                line_starts.push(idx as u32 + 1);
+            }
+        }
+
+        SourceFile {
+            id,
+            path,
+            src,
+            line_starts,
+        }
+    }
+
+    pub fn line_count(&self) -> u32 {
+        self.line_starts.len() as u32
+    }
+
+    // The line, without its newline
+    pub fn line(&self, row: u32) -> &str {
+        let start = self.line_starts[row as usize] as usize;
+        let end = match self.line_starts.get(row as usize + 1) {
+            Some(end) => *end as usize - 1,
+            None => self.src.len(),
+        };
+
+        self.src[start..end].trim_end_matches('\r')
+    }
+
+    pub fn line_start(&self, row: u32) -> u32 {
+        self.line_starts[row as usize]
+    }
+
+    pub fn location(&self, idx: u32) -> Location {
+        let idx = idx.min(self.src.len() as u32);
+        let row = match self.line_starts.binary_search(&idx) {
+            Ok(row) => row,
+            Err(row) => row - 1,
+        } as u32;
+
+        let start = self.line_starts[row as usize] as usize;
+        let col = self.src[start..idx as usize].chars().count() as u32;
+
+        Location::new(self.id, row, col, idx)
+    }
+}
+
+// Owns every file that has been loaded, so spans from any of them can be displayed
+pub struct SourceMap {
+    files: RefCell<Vec<Rc<SourceFile>>>,
+}
+
+impl SourceMap {
+    pub fn new() -> Rc<SourceMap> {
+        Rc::new(SourceMap {
+            files: RefCell::new(Vec::new()),
+        })
+    }
+
+    pub fn add(&self, path: String, src: Rc<String>) -> FileId {
+        let mut files = self.files.borrow_mut();
+        let id = FileId(files.len() as u32);
+        files.push(Rc::new(SourceFile::new(id, path, src)));
+
+        id
+    }
+
+    pub fn get(&self, file: FileId) -> Rc<SourceFile> {
+        Rc::clone(&self.files.borrow()[file.0 as usize])
+    }
+
+    pub fn files(&self) -> Vec<Rc<SourceFile>> {
+        self.files.borrow().clone()
+    }
+
+    pub fn start(&self, span: Span) -> Location {
+        self.get(span.file).location(span.lo)
+    }
+
+    pub fn end(&self, span: Span) -> Location {
+        self.get(span.file).location(span.hi)
+    }
+
+    pub fn snippet(&self, span: Span) -> String {
+        let file = self.get(span.file);
+        file.src[span.lo as usize..span.hi as usize].to_owned()
+    }
+
+    // path:line:col, like most other compilers
+    pub fn format(&self, span: Span) -> String {
+        let loc = self.start(span);
+        format!(
+            "{}:{}:{}",
+            self.get(span.file).path,
+            loc.row + 1,
+            loc.col + 1
+        )
+    }
+}
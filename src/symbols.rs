@@ -0,0 +1,105 @@
+use core::fmt;
+
+use std::{cell::RefCell, rc::Rc};
+
+use crate::{location::Span, scope::Scope, types::DataType};
+
+// Stays the same for the whole compilation, no matter which module it came from
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct SymbolId(pub u32);
+
+impl fmt::Display for SymbolId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "${}", self.0)
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum SymbolKind {
+    Variable,
+    Function,
+    Param,
+    Enum,
+    Variant,
+    Struct,
+    Field,
+    TypeParam,
+    Trait,
+    Method,
+}
+
+#[derive(Debug, Clone)]
+pub struct Symbol {
+    pub name: String,
+    pub span: Span,
+    pub kind: SymbolKind,
+    // Filled in by the checker
+    pub typ: Option<DataType>,
+    // Read somewhere, assigning to it doesn't count
+    pub used: bool,
+    // What a path can get from it, like an enum's variants
+    pub members: Option<Rc<RefCell<Scope>>>,
+    // The type parameters of a generic function or struct, in order
+    pub generics: Vec<SymbolId>,
+}
+
+// The methods a type has, from a trait or of its own. Types don't have symbols, so these are kept
+// next to the symbols and found by comparing types
+#[derive(Debug, Clone)]
+pub struct Impl {
+    pub trait_id: Option<SymbolId>,
+    pub typ: DataType,
+    pub span: Span,
+    // In the order they were written
+    pub methods: Vec<(String, SymbolId)>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct SymbolTable {
+    symbols: Vec<Symbol>,
+    impls: Vec<Impl>,
+}
+
+impl SymbolTable {
+    pub fn add(&mut self, name: String, span: Span, kind: SymbolKind) -> SymbolId {
+        self.symbols.push(Symbol {
+            name,
+            span,
+            kind,
+            typ: None,
+            used: false,
+            members: None,
+            generics: Vec::new(),
+        });
+        SymbolId(self.symbols.len() as u32 - 1)
+    }
+
+    pub fn get(&self, id: SymbolId) -> &Symbol {
+        &self.symbols[id.0 as usize]
+    }
+
+    pub fn get_mut(&mut self, id: SymbolId) -> &mut Symbol {
+        &mut self.symbols[id.0 as usize]
+    }
+
+    pub fn add_impl(&mut self, found: Impl) {
+        self.impls.push(found);
+    }
+
+    pub fn impls(&self) -> &[Impl] {
+        &self.impls
+    }
+
+    // Forgets the impls of an input that didn't make it
+    pub fn truncate_impls(&mut self, len: usize) {
+        self.impls.truncate(len);
+    }
+
+    pub fn len(&self) -> usize {
+        self.symbols.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.symbols.is_empty()
+    }
+}
@@ -37,12 +37,20 @@ pub const SYMBOLS: &[Type] = &[
     Type::CountTrailingZeros,
     Type::Colon,
     Type::Comma,
+    Type::Dot,
+    Type::Question,
+    Type::QuestionDot,
+    Type::Coalesce,
+    Type::Hash,
+    Type::FatArrow,
 ];
 
 pub const KEYWORDS: &[Type] = &[
     Type::Module,
+    Type::Use,
     Type::Boolean(true),
     Type::Boolean(false),
+    Type::None,
     Type::Let,
     Type::If,
     Type::For,
@@ -52,6 +60,12 @@ pub const KEYWORDS: &[Type] = &[
     Type::Continue,
     Type::Return,
     Type::Function,
+    Type::Enum,
+    Type::Struct,
+    Type::Trait,
+    Type::Impl,
+    Type::Dyn,
+    Type::Match,
     Type::Class,
     Type::Public,
     Type::Private,
@@ -60,6 +74,7 @@ pub const KEYWORDS: &[Type] = &[
 ];
 
 pub const ORDERED_BINARY_OPERATORS: &[&[Type]] = &[
+    &[Type::Coalesce],
     &[
         Type::Xand,
         Type::Xor,
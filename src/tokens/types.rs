@@ -4,12 +4,16 @@ use std::mem::Discriminant;
 #[derive(PartialEq, Debug, Clone)]
 pub enum Type {
     Identifier(String),
+    // Everything from // up to the end of the line, only kept around for the formatter
+    Comment(String),
     Whitespace,
     NewLine,
     Semicolon,
     Module,
+    Use,
     Number(f32),
     Boolean(bool),
+    None,
     Assignment,
     LeftParen,
     RightParen,
@@ -51,7 +55,19 @@ pub enum Type {
     Continue,
     Return,
     Comma,
+    Dot,
+    Question,
+    QuestionDot,
+    Coalesce,
+    Hash,
     Function,
+    Enum,
+    Struct,
+    Trait,
+    Impl,
+    Dyn,
+    Match,
+    FatArrow,
     Class,
     Public,
     Private,
@@ -76,8 +92,10 @@ impl Type {
                     Type::NewLine => "\n",
                     Type::Semicolon => ";",
                     Type::Module => "mod",
+                    Type::Use => "use",
                     Type::Boolean(true) => "true",
                     Type::Boolean(false) => "false",
+                    Type::None => "none",
                     Type::Assignment => "=",
                     Type::LeftParen => "(",
                     Type::RightParen => ")",
@@ -119,7 +137,19 @@ impl Type {
                     Type::Continue => "continue",
                     Type::Return => "return",
                     Type::Comma => ",",
+                    Type::Dot => ".",
+                    Type::Question => "?",
+                    Type::QuestionDot => "?.",
+                    Type::Coalesce => "??",
+                    Type::Hash => "#",
                     Type::Function => "func",
+                    Type::Enum => "enum",
+                    Type::Struct => "struct",
+                    Type::Trait => "trait",
+                    Type::Impl => "impl",
+                    Type::Dyn => "dyn",
+                    Type::Match => "match",
+                    Type::FatArrow => "=>",
                     Type::Class => "class",
                     Type::Public => "pub",
                     Type::Private => "pri",
@@ -131,12 +161,12 @@ impl Type {
             }
         };
 
-        src.to_vec().into_iter().map(|s| s.to_owned()).collect()
+        src.iter().map(|s| s.to_string()).collect()
     }
 
     pub fn is(&self, types: &[Type]) -> bool {
         for typ in types {
-            if self.eq(typ) {
+            if self.same_kind(typ) {
                 return true;
             }
         }
@@ -147,7 +177,7 @@ impl Type {
         std::mem::discriminant(self)
     }
 
-    pub fn eq(&self, typ: &Type) -> bool {
+    pub fn same_kind(&self, typ: &Type) -> bool {
         self.disc() == typ.disc()
     }
 
@@ -1,3 +1,11 @@
+// Every kind's new gives the DataType wrapping it, since that's what all the callers want
+#![allow(clippy::new_ret_no_self)]
+
+use core::fmt;
+use std::collections::HashMap;
+
+use crate::{symbols::SymbolId, tokens::Type};
+
 #[derive(PartialEq, Clone)]
 pub enum DataType {
     Int(Int),
@@ -5,45 +13,221 @@ pub enum DataType {
     Float(Float),
     Bool(Bool),
     Array(Array),
+    Function(Function),
+    Tuple(Tuple),
+    Enum(Enum),
+    Optional(Optional),
+    // What none is before it's put somewhere that knows what it's missing
+    None,
+    Param(Param),
+    Struct(Struct),
+    Dyn(Dyn),
 }
 
 impl DataType {
-    // TODO: Fix to give a result so if they're too short they don't throw an out of bounds error
-    pub fn from(src: &String) -> Option<DataType> {
+    pub fn from(src: &str) -> Option<DataType> {
         // There HAS to be a better way of doing this
-        if let Some(x) = Int::from(&src) {
+        if let Some(x) = Int::from(src) {
             Some(DataType::Int(x))
-        } else if let Some(x) = Uint::from(&src) {
+        } else if let Some(x) = Uint::from(src) {
             Some(DataType::Uint(x))
-        } else if let Some(x) = Float::from(&src) {
+        } else if let Some(x) = Float::from(src) {
             Some(DataType::Float(x))
-        } else if let Some(x) = Bool::from(&src) {
+        } else if let Some(x) = Bool::from(src) {
             Some(DataType::Bool(x))
-        } else if let Some(x) = Array::from(&src) {
-            Some(DataType::Array(x))
         } else {
-            None
+            Array::from(src).map(DataType::Array)
+        }
+    }
+
+    // Whether a value of this type can go where an expected one is needed. Optionals take
+    // their inner type and none too, nothing else converts
+    pub fn fits(&self, expected: &DataType) -> bool {
+        match expected {
+            DataType::Optional(x) => {
+                self == expected || *self == DataType::None || *self == *x.inner
+            }
+            _ => self == expected,
+        }
+    }
+
+    // The rows of the operator table this type has. Anything that isn't a number or a bool can
+    // only be compared, and only when everything it's made of can be
+    pub fn bounds(&self) -> Vec<Bound> {
+        let parts: Vec<&DataType> = match self {
+            DataType::Int(_) | DataType::Uint(_) => return Bound::VALUES.to_vec(),
+            DataType::Float(_) => return vec![Bound::Eq, Bound::Ord, Bound::Num],
+            DataType::Bool(_) => return vec![Bound::Eq, Bound::Ord, Bound::Logic],
+            DataType::Param(x) => return x.bounds.clone(),
+            // The value behind it could be anything with the trait
+            DataType::Dyn(_) => return Vec::new(),
+            DataType::Tuple(x) => x.items.iter().collect(),
+            DataType::Optional(x) => vec![&x.inner],
+            DataType::Struct(x) => x.args.iter().collect(),
+            _ => Vec::new(),
+        };
+
+        match parts.iter().all(|x| x.has(Bound::Eq)) {
+            true => vec![Bound::Eq],
+            false => Vec::new(),
+        }
+    }
+
+    pub fn has(&self, bound: Bound) -> bool {
+        self.bounds().contains(&bound)
+    }
+
+    // Fills in type parameters, the ones that aren't in the map stay as they are
+    pub fn subst(&self, map: &HashMap<SymbolId, DataType>) -> DataType {
+        match self {
+            DataType::Param(x) => map.get(&x.id).cloned().unwrap_or_else(|| self.clone()),
+            DataType::Array(x) => Array::new(Box::new(x.typ.subst(map))),
+            DataType::Function(x) => Function::new(
+                x.params.iter().map(|x| x.subst(map)).collect(),
+                x.ret.as_ref().map(|x| x.subst(map)),
+            ),
+            DataType::Tuple(x) => Tuple::new(x.items.iter().map(|x| x.subst(map)).collect()),
+            DataType::Optional(x) => Optional::new(x.inner.subst(map)),
+            DataType::Struct(x) => DataType::Struct(Struct {
+                args: x.args.iter().map(|x| x.subst(map)).collect(),
+                ..x.clone()
+            }),
+            _ => self.clone(),
         }
     }
 
-    pub fn eq(&self, x: &DataType) -> bool {
-        match (self, x) {
-            (DataType::Int(x), DataType::Int(y)) => x.eq(y),
-            (DataType::Uint(x), DataType::Uint(y)) => x.eq(y),
-            (DataType::Float(x), DataType::Float(y)) => x.eq(y),
-            (DataType::Bool(_), DataType::Bool(_)) => true,
-            (DataType::Array(x), DataType::Array(y)) => x.eq(y),
+    // Whether there's a type parameter somewhere in it
+    pub fn is_generic(&self) -> bool {
+        match self {
+            DataType::Param(_) => true,
+            DataType::Array(x) => x.typ.is_generic(),
+            DataType::Function(x) => {
+                x.params.iter().any(DataType::is_generic)
+                    || x.ret.as_ref().is_some_and(|x| x.is_generic())
+            }
+            DataType::Tuple(x) => x.items.iter().any(DataType::is_generic),
+            DataType::Optional(x) => x.inner.is_generic(),
+            DataType::Struct(x) => x.args.iter().any(DataType::is_generic),
             _ => false,
         }
     }
+}
+
+// What a type parameter is allowed to do. Numbers and bools have the bounds that go with them,
+// a T only has the ones it was given
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Bound {
+    Eq,
+    Ord,
+    Num,
+    Logic,
+    Bits,
+}
+
+impl Bound {
+    pub const VALUES: [Bound; 5] = [Bound::Eq, Bound::Ord, Bound::Num, Bound::Logic, Bound::Bits];
+
+    pub fn from(src: &str) -> Option<Bound> {
+        Bound::VALUES.into_iter().find(|x| x.to_string() == src)
+    }
+
+    // The bound an operator needs, ?? isn't in the table since it works on any optional
+    pub fn of(op: &Type) -> Option<Bound> {
+        OPERATORS
+            .iter()
+            .find(|(_, ops)| op.is(ops))
+            .map(|(bound, _)| *bound)
+    }
+
+    pub fn ops(&self) -> &'static [Type] {
+        OPERATORS.iter().find(|(x, _)| x == self).unwrap().1
+    }
+}
 
-    pub fn to_string(&self) -> String {
+impl fmt::Display for Bound {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+// Which operators are legal for which types, a type can use an operator when it has the bound
+// of its row. The - of a negative number is in Num along with subtraction
+pub const OPERATORS: &[(Bound, &[Type])] = &[
+    (Bound::Eq, &[Type::Equal, Type::NotEqual]),
+    (
+        Bound::Ord,
+        &[
+            Type::LessThan,
+            Type::LessThanOrEqual,
+            Type::GreaterThan,
+            Type::GreaterThanOrEqual,
+        ],
+    ),
+    (
+        Bound::Num,
+        &[
+            Type::Addition,
+            Type::Subtraction,
+            Type::Multiplication,
+            Type::Division,
+            Type::Modulo,
+            Type::Exponentiation,
+        ],
+    ),
+    (
+        Bound::Logic,
+        &[
+            Type::And,
+            Type::Or,
+            Type::Xor,
+            Type::Nand,
+            Type::Nor,
+            Type::Xand,
+            Type::Not,
+        ],
+    ),
+    (
+        Bound::Bits,
+        &[
+            Type::LeftShift,
+            Type::RightShift,
+            Type::ZeroFillRightShift,
+            Type::CountLeadingZeros,
+            Type::CountTrailingZeros,
+        ],
+    ),
+];
+
+// Every type that can be written in an annotation, without the array suffix
+pub fn names() -> Vec<String> {
+    let mut names: Vec<String> = Vec::new();
+    for size in IntegralSize::VALUES {
+        names.push(Int { size }.to_string());
+        names.push(Uint { size }.to_string());
+    }
+    for size in FloatingSize::VALUES {
+        names.push(Float { size }.to_string());
+    }
+    names.push(Bool {}.to_string());
+    names
+}
+
+impl fmt::Display for DataType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
-            DataType::Int(x) => x.to_string(),
-            DataType::Uint(x) => x.to_string(),
-            DataType::Float(x) => x.to_string(),
-            DataType::Bool(x) => x.to_string(),
-            DataType::Array(x) => x.to_string(),
+            DataType::Int(x) => write!(f, "{}", x),
+            DataType::Uint(x) => write!(f, "{}", x),
+            DataType::Float(x) => write!(f, "{}", x),
+            DataType::Bool(x) => write!(f, "{}", x),
+            DataType::Array(x) => write!(f, "{}", x),
+            DataType::Function(x) => write!(f, "{}", x),
+            DataType::Tuple(x) => write!(f, "{}", x),
+            DataType::Enum(x) => write!(f, "{}", x),
+            DataType::Optional(x) => write!(f, "{}", x),
+            DataType::None => write!(f, "none"),
+            DataType::Param(x) => write!(f, "{}", x),
+            DataType::Struct(x) => write!(f, "{}", x),
+            DataType::Dyn(x) => write!(f, "{}", x),
         }
     }
 }
@@ -57,11 +241,19 @@ impl std::fmt::Debug for DataType {
             DataType::Float(x) => write!(f, "{:#?}", x),
             DataType::Bool(x) => write!(f, "{:#?}", x),
             DataType::Array(x) => write!(f, "{:#?}", x),
+            DataType::Function(x) => write!(f, "{:#?}", x),
+            DataType::Tuple(x) => write!(f, "{:#?}", x),
+            DataType::Enum(x) => write!(f, "{:#?}", x),
+            DataType::Optional(x) => write!(f, "{:#?}", x),
+            DataType::None => write!(f, "None"),
+            DataType::Param(x) => write!(f, "{:#?}", x),
+            DataType::Struct(x) => write!(f, "{:#?}", x),
+            DataType::Dyn(x) => write!(f, "{:#?}", x),
         }
     }
 }
 
-#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
 pub enum IntegralSize {
     Int8 = 8,
     Int16 = 16,
@@ -78,6 +270,12 @@ impl IntegralSize {
         IntegralSize::Int64,
         IntegralSize::Int128,
     ];
+
+    pub fn from(src: &str) -> Option<IntegralSize> {
+        IntegralSize::VALUES
+            .into_iter()
+            .find(|size| (*size as u32).to_string() == src)
+    }
 }
 
 #[derive(Debug, PartialEq, Clone)]
@@ -90,24 +288,15 @@ impl Int {
         DataType::Int(Int { size })
     }
 
-    pub fn from(src: &String) -> Option<Int> {
-        if &src[0..3] == "int" {
-            for size in IntegralSize::VALUES {
-                if (size as u32).to_string() == &src[3..] {
-                    return Some(Int { size });
-                }
-            }
-        }
-
-        None
-    }
-
-    pub fn eq(&self, x: &Int) -> bool {
-        self.size == x.size
+    pub fn from(src: &str) -> Option<Int> {
+        let size = IntegralSize::from(src.strip_prefix("int")?)?;
+        Some(Int { size })
     }
+}
 
-    pub fn to_string(&self) -> String {
-        format!("int{}", self.size as u32)
+impl fmt::Display for Int {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "int{}", self.size as u32)
     }
 }
 
@@ -121,28 +310,19 @@ impl Uint {
         DataType::Uint(Uint { size })
     }
 
-    pub fn from(src: &String) -> Option<Uint> {
-        if &src[0..3] == "int" {
-            for size in IntegralSize::VALUES {
-                if (size as u32).to_string() == &src[3..] {
-                    return Some(Uint { size });
-                }
-            }
-        }
-
-        None
-    }
-
-    pub fn eq(&self, x: &Uint) -> bool {
-        self.size == x.size
+    pub fn from(src: &str) -> Option<Uint> {
+        let size = IntegralSize::from(src.strip_prefix("uint")?)?;
+        Some(Uint { size })
     }
+}
 
-    pub fn to_string(&self) -> String {
-        format!("uint{}", self.size as u32)
+impl fmt::Display for Uint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "uint{}", self.size as u32)
     }
 }
 
-#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
 pub enum FloatingSize {
     Float16 = 16,
     Float32 = 32,
@@ -157,6 +337,12 @@ impl FloatingSize {
         FloatingSize::Float64,
         FloatingSize::Float128,
     ];
+
+    pub fn from(src: &str) -> Option<FloatingSize> {
+        FloatingSize::VALUES
+            .into_iter()
+            .find(|size| (*size as u32).to_string() == src)
+    }
 }
 
 #[derive(Debug, PartialEq, Clone)]
@@ -169,24 +355,15 @@ impl Float {
         DataType::Float(Float { size })
     }
 
-    pub fn from(src: &String) -> Option<Float> {
-        if &src[0..5] == "float" {
-            for size in FloatingSize::VALUES {
-                if (size as u32).to_string() == &src[5..] {
-                    return Some(Float { size });
-                }
-            }
-        }
-
-        None
-    }
-
-    pub fn eq(&self, x: &Float) -> bool {
-        self.size == x.size
+    pub fn from(src: &str) -> Option<Float> {
+        let size = FloatingSize::from(src.strip_prefix("float")?)?;
+        Some(Float { size })
     }
+}
 
-    pub fn to_string(&self) -> String {
-        format!("float{}", self.size as u32)
+impl fmt::Display for Float {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "float{}", self.size as u32)
     }
 }
 
@@ -198,16 +375,18 @@ impl Bool {
         DataType::Bool(Bool {})
     }
 
-    pub fn from(src: &String) -> Option<Bool> {
+    pub fn from(src: &str) -> Option<Bool> {
         if src == "bool" {
             Some(Bool {})
         } else {
             None
         }
     }
+}
 
-    pub fn to_string(&self) -> String {
-        "bool".to_owned()
+impl fmt::Display for Bool {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "bool")
     }
 }
 
@@ -221,20 +400,180 @@ impl Array {
         DataType::Array(Array { typ })
     }
 
-    pub fn from(src: &String) -> Option<Array> {
-        if &src[src.len() - 2..src.len()] == "[]" {
-            println!("{}", &src[0..src.len() - 2]);
+    pub fn from(src: &str) -> Option<Array> {
+        let typ = DataType::from(src.strip_suffix("[]")?)?;
+        Some(Array { typ: Box::new(typ) })
+    }
+}
+
+impl fmt::Display for Array {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}[]", self.typ)
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Function {
+    pub params: Vec<DataType>,
+    pub ret: Option<Box<DataType>>,
+}
+
+impl Function {
+    pub fn new(params: Vec<DataType>, ret: Option<DataType>) -> DataType {
+        DataType::Function(Function {
+            params,
+            ret: ret.map(Box::new),
+        })
+    }
+}
+
+impl fmt::Display for Function {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let params: Vec<String> = self.params.iter().map(|x| x.to_string()).collect();
+        write!(f, "func({})", params.join(", "))?;
+        match &self.ret {
+            Some(ret) => write!(f, ": {}", ret),
+            None => Ok(()),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Tuple {
+    pub items: Vec<DataType>,
+}
+
+impl Tuple {
+    pub fn new(items: Vec<DataType>) -> DataType {
+        DataType::Tuple(Tuple { items })
+    }
+}
+
+// A tuple of one keeps its comma, so it can't be mistaken for parentheses
+impl fmt::Display for Tuple {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let items: Vec<String> = self.items.iter().map(|x| x.to_string()).collect();
+        match items.len() {
+            1 => write!(f, "({},)", items[0]),
+            _ => write!(f, "({})", items.join(", ")),
+        }
+    }
+}
+
+// The variants hang off of the enum's symbol, so an enum can hold itself
+#[derive(Debug, PartialEq, Clone)]
+pub struct Enum {
+    pub name: String,
+    pub id: SymbolId,
+}
+
+impl Enum {
+    pub fn new(name: String, id: SymbolId) -> DataType {
+        DataType::Enum(Enum { name, id })
+    }
+}
+
+impl fmt::Display for Enum {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name)
+    }
+}
+
+// T? is either a T or none. There's only one level of it, so T?? is the same as T?
+#[derive(Debug, PartialEq, Clone)]
+pub struct Optional {
+    pub inner: Box<DataType>,
+}
+
+impl Optional {
+    pub fn new(inner: DataType) -> DataType {
+        match inner {
+            DataType::Optional(_) | DataType::None => inner,
+            _ => DataType::Optional(Optional {
+                inner: Box::new(inner),
+            }),
+        }
+    }
+}
+
+impl fmt::Display for Optional {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self.inner {
+            // func(): int32? would read as a function that returns an optional
+            DataType::Function(_) => write!(f, "({})?", self.inner),
+            _ => write!(f, "{}?", self.inner),
         }
+    }
+}
+
+// A type parameter inside the function or struct that declared it, where it could still be
+// anything that has its bounds. Traits are bounds too, they give it methods instead of operators
+#[derive(Debug, PartialEq, Clone)]
+pub struct Param {
+    pub name: String,
+    pub id: SymbolId,
+    pub bounds: Vec<Bound>,
+    pub traits: Vec<SymbolId>,
+}
 
-        // TODO: Implement
-        todo!()
+impl Param {
+    pub fn new(name: String, id: SymbolId, bounds: Vec<Bound>, traits: Vec<SymbolId>) -> DataType {
+        DataType::Param(Param {
+            name,
+            id,
+            bounds,
+            traits,
+        })
     }
+}
 
-    pub fn eq(&self, x: &Array) -> bool {
-        self.typ.eq(&x.typ)
+impl fmt::Display for Param {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name)
     }
+}
+
+// Like enums, the fields hang off of the struct's symbol. A generic struct has a type for each
+// of its parameters, like Pair<int32, bool>
+#[derive(Debug, PartialEq, Clone)]
+pub struct Struct {
+    pub name: String,
+    pub id: SymbolId,
+    pub args: Vec<DataType>,
+}
+
+impl Struct {
+    pub fn new(name: String, id: SymbolId, args: Vec<DataType>) -> DataType {
+        DataType::Struct(Struct { name, id, args })
+    }
+}
+
+impl fmt::Display for Struct {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name)?;
+        if !self.args.is_empty() {
+            let args: Vec<String> = self.args.iter().map(|x| x.to_string()).collect();
+            write!(f, "<{}>", args.join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+// Any value with the trait, its methods are looked up when they're called
+#[derive(Debug, PartialEq, Clone)]
+pub struct Dyn {
+    pub name: String,
+    pub id: SymbolId,
+}
+
+impl Dyn {
+    pub fn new(name: String, id: SymbolId) -> DataType {
+        DataType::Dyn(Dyn { name, id })
+    }
+}
 
-    pub fn to_string(&self) -> String {
-        format!("{}[]", self.typ.to_string())
+impl fmt::Display for Dyn {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "dyn {}", self.name)
     }
 }
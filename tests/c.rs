@@ -0,0 +1,260 @@
+mod common;
+
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use fusion::{
+    backend, driver,
+    ir::{self, Machine, Type, Val},
+};
+
+use common::{cc, literal, lower, same, PROGRAM, WIDE};
+
+#[test]
+fn emits_c99() {
+    let c = backend::emit_c(&lower(&[PROGRAM, WIDE].concat()));
+    for expected in [
+        "#include <stdint.h>\n",
+        "static int32_t g4main5limit;\n",
+        "int8_t f4main5wrap8(int8_t v0, int8_t v1);\n",
+        "__int128 f4main4wide(__int128 v0, __int128 v1);\n",
+        "double f4main6floats(double v0, double v1);\n",
+        "float f4main5small(float v0);\n",
+        "bool f4main7compare(double v0, double v1);\n",
+        "int main(void) {\n",
+        // Everything from one line of fusion goes on one line of C
+        "#line 5 \"main.fn\"\n    v1 = (int32_t)((uint32_t)v0 * (uint32_t)v0); return v1;\n",
+    ] {
+        assert!(c.contains(expected), "no {:?} in\n{}", expected, c);
+    }
+}
+
+fn mangle(name: &str) -> String {
+    let parts: Vec<String> = name
+        .split('.')
+        .map(|x| format!("{}{}", x.len(), x))
+        .collect();
+    format!("f{}", parts.concat())
+}
+
+// Builds the module with a main that makes one call, picked by the first argument, and prints
+// what it gave
+fn build(module: &ir::Module, cases: &[(&str, Vec<Val>)], dir: &Path, name: &str) -> PathBuf {
+    let mut harness = String::from("#define main fusion_main\n");
+    harness += &backend::emit_c(module);
+    harness += "#undef main\n#include <string.h>\n\n";
+    harness += "static double from_bits(uint64_t bits) {\n";
+    harness += "    double x;\n    memcpy(&x, &bits, sizeof x);\n    return x;\n}\n\n";
+    harness += "int main(int argc, char **argv) {\n    fusion_main();\n";
+    harness += "    switch (atoi(argv[argc - 1])) {\n";
+    for (idx, (func, args)) in cases.iter().enumerate() {
+        let args: Vec<String> = args.iter().map(literal).collect();
+        let call = format!("{}({})", mangle(&format!("main.{}", func)), args.join(", "));
+        let ret = module.func(&format!("main.{}", func)).unwrap().ret.unwrap();
+        let print = match ret {
+            Type::Float(_) => format!(
+                "double r = {}; uint64_t bits; memcpy(&bits, &r, sizeof bits); \
+                 printf(\"f%016llx\\n\", (unsigned long long)bits);",
+                call
+            ),
+            Type::Bool => format!("printf(\"b%d\\n\", {});", call),
+            _ => format!(
+                "unsigned __int128 r = (unsigned __int128)(__int128){}; \
+                 printf(\"i%016llx%016llx\\n\", (unsigned long long)(r >> 64), \
+                 (unsigned long long)r);",
+                call
+            ),
+        };
+        harness += &format!("    case {}: {{ {} break; }}\n", idx, print);
+    }
+    harness += "    }\n    return 0;\n}\n";
+
+    let src = dir.join(format!("{}.c", name));
+    let exe = dir.join(name);
+    fs::write(&src, harness).unwrap();
+    let out = Command::new(cc().unwrap())
+        .args(["-std=c99", "-Wall", "-Werror", "-o"])
+        .arg(&exe)
+        .arg(&src)
+        .arg("-lm")
+        .output()
+        .unwrap();
+    assert!(
+        out.status.success(),
+        "{}",
+        String::from_utf8_lossy(&out.stderr)
+    );
+    exe
+}
+
+fn run(exe: &Path, case: usize) -> Result<Option<Val>, String> {
+    let out = Command::new(exe).arg(case.to_string()).output().unwrap();
+    if !out.status.success() {
+        // Just the message, the interpreter doesn't know about lines
+        let err = String::from_utf8(out.stderr).unwrap();
+        let msg = err.strip_prefix("error: ").unwrap();
+        return Err(msg[..msg.rfind(" (").unwrap()].to_owned());
+    }
+
+    let text = String::from_utf8(out.stdout).unwrap();
+    let (kind, val) = text.trim().split_at(1);
+    Ok(Some(match kind {
+        "f" => Val::Float(f64::from_bits(u64::from_str_radix(val, 16).unwrap())),
+        "b" => Val::Bool(val == "1"),
+        _ => Val::Int(u128::from_str_radix(val, 16).unwrap() as i128),
+    }))
+}
+
+// Every call gives the same value or the same trap compiled as it does in the IR, before and
+// after optimizing
+#[test]
+fn runs_like_the_ir() {
+    if cc().is_none() {
+        eprintln!("no C compiler, skipping");
+        return;
+    }
+
+    let ints: Vec<i128> = vec![-128, -7, -1, 0, 1, 2, 7, 127];
+    let uints: Vec<i128> = vec![0, 1, 3, 7, 8, 200, 255];
+    let floats: Vec<f64> = vec![-1.5, 0.0, 2.0, f64::NAN, f64::INFINITY];
+    let pairs = |xs: &[i128]| -> Vec<Vec<Val>> {
+        xs.iter()
+            .flat_map(|&x| xs.iter().map(move |&y| vec![Val::Int(x), Val::Int(y)]))
+            .collect()
+    };
+
+    let mut cases: Vec<(&str, Vec<Val>)> = Vec::new();
+    for &x in &ints {
+        cases.push(("square", vec![Val::Int(x)]));
+        cases.push(("count", vec![Val::Int(x)]));
+        cases.push(("bits", vec![Val::Int(x & 0xff)]));
+        for &y in &floats {
+            cases.push(("highest", vec![Val::Int(x), Val::Int(7), Val::Float(y)]));
+        }
+        for flag in [true, false] {
+            cases.push(("choose", vec![Val::Bool(flag), Val::Int(x)]));
+        }
+    }
+    for name in ["wrap8", "signed", "divide", "power", "wide"] {
+        let mut ints = ints.clone();
+        if name == "power" || name == "wide" {
+            ints.push(1 << 40);
+        }
+        cases.extend(pairs(&ints).into_iter().map(|x| (name, x)));
+    }
+    cases.extend(pairs(&uints).into_iter().map(|x| ("shifts", x)));
+    for &x in &floats {
+        for &y in &floats {
+            cases.push(("floats", vec![Val::Float(x), Val::Float(y)]));
+            cases.push(("compare", vec![Val::Float(x), Val::Float(y)]));
+        }
+    }
+
+    let dir = env::temp_dir().join(format!("fusion-c-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let module = lower(&[PROGRAM, WIDE].concat());
+    let mut opt = module.clone();
+    driver::optimize(&mut opt, None);
+
+    let mut traps = 0;
+    for (module, name) in [(&module, "plain"), (&opt, "optimized")] {
+        let exe = build(module, &cases, &dir, name);
+        for (idx, (func, args)) in cases.iter().enumerate() {
+            let mut machine = Machine::new(module);
+            machine.call(ir::ENTRY, Vec::new()).unwrap();
+            let expected = machine.call(&format!("main.{}", func), args.clone());
+            let got = run(&exe, idx);
+            assert!(
+                same(&got, &expected),
+                "{} {:?}: {:?} isn't {:?}",
+                func,
+                args,
+                got,
+                expected
+            );
+            traps += expected.is_err() as usize;
+        }
+    }
+    fs::remove_dir_all(&dir).unwrap();
+    assert!(traps > 20);
+}
+
+// Traps say where they happened, in the .fn file
+#[test]
+fn traps_point_at_the_source() {
+    if cc().is_none() {
+        eprintln!("no C compiler, skipping");
+        return;
+    }
+
+    let src = "
+let zero = 0
+
+let _x = 1 / zero
+";
+    let dir = env::temp_dir().join(format!("fusion-c-trap-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let c = dir.join("trap.c");
+    let exe = dir.join("trap");
+    fs::write(&c, backend::emit_c(&lower(src))).unwrap();
+    let status = Command::new(cc().unwrap())
+        .args(["-std=c99", "-o"])
+        .arg(&exe)
+        .arg(&c)
+        .arg("-lm")
+        .status()
+        .unwrap();
+    assert!(status.success());
+
+    let out = Command::new(&exe).output().unwrap();
+    fs::remove_dir_all(&dir).unwrap();
+    assert_eq!(out.status.code(), Some(1));
+    assert_eq!(
+        String::from_utf8(out.stderr).unwrap(),
+        "error: Division by zero (main.fn:4)\n"
+    );
+}
+
+// Warnings go to stderr, so what's on stdout compiles as it is
+#[test]
+fn stdout_is_only_c() {
+    let dir = env::temp_dir().join(format!("fusion-c-stdout-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let src = dir.join("main.fn");
+    fs::write(
+        &src,
+        "func f(): int32 {\n    let unused = 1\n    return 2\n}\n",
+    )
+    .unwrap();
+
+    let out = Command::new(env!("CARGO_BIN_EXE_fusion"))
+        .args(["--color=never", "--emit=c"])
+        .arg(&src)
+        .output()
+        .unwrap();
+    assert!(out.status.success());
+    let stderr = String::from_utf8(out.stderr).unwrap();
+    assert!(
+        stderr.starts_with("warning unused_variables:"),
+        "{}",
+        stderr
+    );
+
+    let c = dir.join("main.c");
+    fs::write(&c, &out.stdout).unwrap();
+    if let Some(cc) = cc() {
+        let status = Command::new(cc)
+            .args(["-std=c99", "-fsyntax-only"])
+            .arg(&c)
+            .status()
+            .unwrap();
+        assert!(status.success());
+    }
+    fs::remove_dir_all(&dir).unwrap();
+    assert!(String::from_utf8(out.stdout)
+        .unwrap()
+        .starts_with("// Generated by fusion\n"));
+}
@@ -0,0 +1,182 @@
+// Every test file only uses some of these
+#![allow(dead_code)]
+
+use std::{collections::HashMap, io, path::Path, process::Command, rc::Rc};
+
+use fusion::{
+    driver,
+    error::{ErrorFormat, Reporter},
+    ir::{self, Val},
+    lexer::Lexer,
+    module::ModuleGraph,
+    parser::Parser,
+    program::Program,
+    source::SourceMap,
+};
+
+pub fn parse(src: &str) -> Program {
+    let reporter = Reporter::new(SourceMap::new(), false, ErrorFormat::Silent);
+    let code = Rc::new(src.to_owned());
+    let file = reporter.map.add("main.fn".to_owned(), Rc::clone(&code));
+
+    let mut lexer = Lexer::new(code, file);
+    lexer.lex();
+    let mut parser = Parser::new(reporter, &lexer.filter());
+    parser.parse()
+}
+
+// Operators keep their span inline, everything else has it in the arena's side table
+fn strip_spans(mut debug: String) -> String {
+    while let Some(start) = debug.find(" { span: ") {
+        let end = start + debug[start..].find('}').unwrap();
+        debug.replace_range(start..=end, "");
+    }
+    debug
+}
+
+// Nodes are allocated in the order they're parsed, so two ASTs with the same shape end up with
+// the same node at every id
+pub fn shape(prog: &Program) -> Vec<String> {
+    prog.ast
+        .ids()
+        .map(|id| strip_spans(format!("{:?}", prog.ast.kind(id))))
+        .collect()
+}
+
+// The first file is the root, the rest are modules next to it
+pub fn compile_files(files: &[(&str, &str)]) -> ModuleGraph {
+    let files: HashMap<&Path, &str> = files.iter().map(|(x, y)| (Path::new(*x), *y)).collect();
+    let read = |path: &Path| match files.get(path) {
+        Some(src) => Ok(src.to_string()),
+        None => Err(io::Error::from(io::ErrorKind::NotFound)),
+    };
+
+    let reporter = Reporter::new(SourceMap::new(), false, ErrorFormat::Silent);
+    driver::compile(reporter, Path::new("main.fn"), &read).expect("doesn't compile")
+}
+
+pub fn compile(src: &str) -> ModuleGraph {
+    compile_files(&[("main.fn", src)])
+}
+
+pub fn lower_files(files: &[(&str, &str)]) -> ir::Module {
+    driver::lower(&compile_files(files)).unwrap()
+}
+
+pub fn lower(src: &str) -> ir::Module {
+    lower_files(&[("main.fn", src)])
+}
+
+// What the backends are run on and compared with the IR machine
+pub const PROGRAM: &str = "
+let limit = 3
+
+func square(x: int32): int32 {
+    return x * x
+}
+
+func negate(x: int32): int32 {
+    return -x
+}
+
+func choose(flag: bool, x: int32): int32 {
+    let f = square
+    if flag {
+        f = negate
+    }
+    return f(x) + limit
+}
+
+func wrap8(a: int8, b: int8): int8 {
+    return a * b + a - -b
+}
+
+func shifts(x: uint8, n: uint8): uint8 {
+    return (x << n) | (x >>> n) | (x >> n)
+}
+
+func signed(x: int16, n: int16): int16 {
+    return (x >> n) ^| (x << n) ^| (x >>> n)
+}
+
+func divide(a: int32, b: int32): int32 {
+    return a / b + a % b
+}
+
+func power(x: int64, y: int64): int64 {
+    return x ^ y
+}
+
+func bits(x: uint32): uint32 {
+    return <..x + >..x
+}
+
+func floats(x: float64, y: float64): float64 {
+    return x / y + x % y - x ^ y
+}
+
+func small(x: float32): float32 {
+    return x * x
+}
+
+func compare(x: float64, y: float64): bool {
+    return (x < y) | (x != y) !& (x == x)
+}
+
+func count(n: int32): int32 {
+    let total = 0
+    let i = 0
+    while i < n {
+        total += i
+        i += 1
+    }
+    return total
+}
+
+func larger<T: Ord>(a: T, b: T): T {
+    if a > b {
+        return a
+    }
+    return b
+}
+
+func highest(x: int32, y: int32, z: float64): float64 {
+    if larger(x, y) == x {
+        return larger(z, z * z)
+    }
+    return z
+}
+";
+
+// Only for the backends with something to put an int128 in
+pub const WIDE: &str = "
+func wide(x: int128, y: int128): int128 {
+    return x * y * y * y - (x ^ y) + !x
+}
+";
+
+// The C compiler on the path, if there is one
+pub fn cc() -> Option<&'static str> {
+    Command::new("cc").arg("--version").output().ok()?;
+    Some("cc")
+}
+
+// A value as C, the harnesses define from_bits for the floats
+pub fn literal(val: &Val) -> String {
+    match val {
+        Val::Int(x) => format!("{}ll", x),
+        Val::Float(x) => format!("from_bits(0x{:016x}ull)", x.to_bits()),
+        Val::Bool(x) => x.to_string(),
+        Val::Func(_) => unreachable!("functions aren't passed in"),
+    }
+}
+
+// Floats are the same when their bits are, and any NaN is the same as any other
+pub fn same(x: &Result<Option<Val>, String>, y: &Result<Option<Val>, String>) -> bool {
+    match (x, y) {
+        (Ok(Some(Val::Float(x))), Ok(Some(Val::Float(y)))) => {
+            x.to_bits() == y.to_bits() || (x.is_nan() && y.is_nan())
+        }
+        _ => x == y,
+    }
+}
@@ -0,0 +1,65 @@
+use std::{io, path::Path};
+
+use fusion::{
+    driver,
+    error::{self, ErrorCode, ErrorFormat, Reporter},
+    source::SourceMap,
+};
+
+// Only these can't be triggered from source code, so they have no examples
+const INTERNAL: [ErrorCode; 2] = [ErrorCode::Unknown, ErrorCode::IncorrectParsingType];
+
+// Compiles an example from memory, the first file is the root
+fn compile(files: &[(&str, &str)]) -> Vec<ErrorCode> {
+    let reporter = Reporter::new(SourceMap::new(), false, ErrorFormat::Silent);
+    let read = |path: &Path| -> io::Result<String> {
+        let name = path.file_name().unwrap().to_str().unwrap();
+        match files.iter().find(|(file, _)| *file == name) {
+            Some((_, src)) => Ok(src.to_string()),
+            None => Err(io::ErrorKind::NotFound.into()),
+        }
+    };
+
+    let _ = driver::compile(reporter.clone(), Path::new(files[0].0), &read);
+    reporter.errors().iter().map(|err| err.id).collect()
+}
+
+#[test]
+fn every_code_is_explained() {
+    for code in ErrorCode::VALUES {
+        let entry = error::explain(code);
+        assert_eq!(entry.code, code);
+        assert!(!entry.render().is_empty());
+
+        if !INTERNAL.contains(&code) {
+            assert!(!entry.erroneous.is_empty(), "{:?} has no example", code);
+            assert!(!entry.corrected.is_empty(), "{:?} has no fix", code);
+        }
+    }
+}
+
+#[test]
+fn erroneous_examples_fail_with_their_code() {
+    for code in ErrorCode::VALUES {
+        let entry = error::explain(code);
+        if entry.erroneous.is_empty() {
+            continue;
+        }
+
+        let errors = compile(entry.erroneous);
+        assert_eq!(errors, [code], "{:?} gave {:?}", code, errors);
+    }
+}
+
+#[test]
+fn corrected_examples_compile() {
+    for code in ErrorCode::VALUES {
+        let entry = error::explain(code);
+        if entry.corrected.is_empty() {
+            continue;
+        }
+
+        let errors = compile(entry.corrected);
+        assert!(errors.is_empty(), "{:?} fix gave {:?}", code, errors);
+    }
+}
@@ -0,0 +1,181 @@
+mod common;
+
+use std::{fs, path::PathBuf, process::Command, rc::Rc};
+
+use common::{parse, shape};
+use fusion::{format, lexer::Lexer, printer, source::SourceMap};
+
+const MESSY: &str = "
+
+#![allow(unused_variables)]
+// leading comment
+mod  math
+pub let  answer:int32=42   // the answer
+
+
+
+func noop(){}
+pub   func add(a:int32,
+    b:int32
+):int32{
+let total=a+b*-2
+        total+=1 // bump
+    if total>10{
+    return total
+    }
+    do{total=total-1}while total>0
+
+
+    return add(1,2,)
+}
+let x = add(
+1,
+2
+)
+";
+
+const FORMATTED: &str = "#![allow(unused_variables)]
+// leading comment
+mod math
+pub let answer: int32 = 42 // the answer
+
+func noop() {}
+pub func add(a: int32,
+    b: int32,
+): int32 {
+    let total = a + b * -2
+    total += 1 // bump
+    if total > 10 {
+        return total
+    }
+    do { total = total - 1 } while total > 0
+
+    return add(1, 2)
+}
+let x = add(
+    1,
+    2,
+)
+";
+
+fn fmt(src: &str) -> String {
+    let code = Rc::new(src.to_owned());
+    let file = SourceMap::new().add("main.fn".to_owned(), Rc::clone(&code));
+    let tokens = Lexer::new(Rc::clone(&code), file).lex();
+    format::format(src, &tokens)
+}
+
+#[test]
+fn tokens_cover_the_whole_source() {
+    let code = Rc::new(MESSY.to_owned());
+    let file = SourceMap::new().add("main.fn".to_owned(), Rc::clone(&code));
+    let tokens = Lexer::new(Rc::clone(&code), file).lex();
+
+    let joined: String = tokens
+        .iter()
+        .map(|tok| &MESSY[tok.span.lo as usize..tok.span.hi as usize])
+        .collect();
+    assert_eq!(joined, MESSY);
+}
+
+#[test]
+fn formats_to_the_canonical_style() {
+    assert_eq!(fmt(MESSY), FORMATTED);
+}
+
+#[test]
+fn formatting_is_idempotent() {
+    for src in [
+        MESSY,
+        FORMATTED,
+        "",
+        "let x = 1",
+        "f(\n\n1 // one\n)\n\n\n",
+        "{ {\n1 }}",
+    ] {
+        let once = fmt(src);
+        assert_eq!(fmt(&once), once, "formatted as:\n{}", once);
+    }
+}
+
+#[test]
+fn type_lists_stay_tight() {
+    assert_eq!(
+        fmt("func max < T : Ord > (a: T, b: T): T {}\nlet p: Pair < int32, Pair<bool, int32> > = Pair(1, Pair(true, 2))\nlet o: Pair<int32, bool> ? = none\nlet c = a < b\n"),
+        "func max<T: Ord>(a: T, b: T): T {}\nlet p: Pair<int32, Pair<bool, int32>> = Pair(1, Pair(true, 2))\nlet o: Pair<int32, bool>? = none\nlet c = a < b\n"
+    );
+}
+
+#[test]
+fn impls_and_dyn_are_types() {
+    assert_eq!(
+        fmt("impl Named for Pair < int32, bool > {}\nimpl Pair<int32,int32> {}\nlet x: (dyn Named, Pair<int32, bool>) = y\n"),
+        "impl Named for Pair<int32, bool> {}\nimpl Pair<int32, int32> {}\nlet x: (dyn Named, Pair<int32, bool>) = y\n"
+    );
+}
+
+#[test]
+fn blocks_over_several_lines_get_their_own_braces() {
+    assert_eq!(
+        fmt("func f(a: int32, b: int32): int32 {\nreturn a*b}\n"),
+        "func f(a: int32, b: int32): int32 {\n    return a * b\n}\n"
+    );
+    assert_eq!(
+        fmt("while x<3 {\nx+=1}\n"),
+        "while x < 3 {\n    x += 1\n}\n"
+    );
+    assert_eq!(fmt("{ let q = 1\n}\n"), "{\n    let q = 1\n}\n");
+    assert_eq!(fmt("func g(){\n}\n"), "func g() {}\n");
+    assert_eq!(
+        fmt("if a { // why\nf() } else { g() }\n"),
+        "if a { // why\n    f()\n} else { g() }\n"
+    );
+}
+
+#[test]
+fn printed_programs_are_already_formatted() {
+    let printed = printer::print(&parse(MESSY));
+    assert_eq!(fmt(&printed), printed);
+}
+
+#[test]
+fn formatting_keeps_the_ast() {
+    assert_eq!(shape(&parse(MESSY)), shape(&parse(FORMATTED)));
+}
+
+fn temp_file(name: &str, src: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("fusion-fmt-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let path = dir.join(name);
+    fs::write(&path, src).unwrap();
+    path
+}
+
+fn fusion_fmt(args: &[&str], path: &PathBuf) -> bool {
+    Command::new(env!("CARGO_BIN_EXE_fusion"))
+        .arg("fmt")
+        .args(args)
+        .arg(path)
+        .output()
+        .unwrap()
+        .status
+        .success()
+}
+
+#[test]
+fn check_fails_on_unformatted_files() {
+    let messy = temp_file("messy.fn", MESSY);
+    assert!(!fusion_fmt(&["--check"], &messy));
+    assert_eq!(fs::read_to_string(&messy).unwrap(), MESSY);
+
+    assert!(fusion_fmt(&[], &messy));
+    assert_eq!(fs::read_to_string(&messy).unwrap(), FORMATTED);
+    assert!(fusion_fmt(&["--check"], &messy));
+}
+
+#[test]
+fn files_that_dont_parse_are_left_alone() {
+    let broken = temp_file("broken.fn", "let x =\n");
+    assert!(!fusion_fmt(&[], &broken));
+    assert_eq!(fs::read_to_string(&broken).unwrap(), "let x =\n");
+}
@@ -0,0 +1,379 @@
+mod common;
+
+use std::{collections::HashSet, path::Path};
+
+use fusion::{
+    driver,
+    error::{ErrorFormat, Reporter},
+    ir::{self, Machine, Op, Val},
+    source::SourceMap,
+};
+
+use common::lower_files;
+
+const LOOPS: &str = "
+func collatz(start: int32): int32 {
+    let n = start
+    let steps = 0
+    while n != 1 {
+        if n % 2 == 0 {
+            n = n / 2
+            steps += 1
+            continue
+        }
+        n = 3 * n + 1
+        steps += 1
+    }
+    return steps
+}
+
+func countdown(from: int32): int32 {
+    let left = from
+    do {
+        left -= 1
+    } while left > 0
+    return left
+}
+
+let _steps = collatz(27)
+let _zero = countdown(10)
+";
+
+#[test]
+fn loops_get_phis() {
+    let module = lower_files(&[("main.fn", LOOPS)]);
+    let collatz = module.func("main.collatz").unwrap();
+
+    // Both variables change in the loop, so both get a phi at the header
+    let header = &collatz.blocks[1];
+    let phis: Vec<_> = header
+        .insts
+        .iter()
+        .filter(|x| matches!(x.op, Op::Phi(_)))
+        .collect();
+    assert_eq!(phis.len(), 2);
+    for phi in phis {
+        let Op::Phi(incoming) = &phi.op else {
+            unreachable!()
+        };
+        // From the entry, the continue and the end of the body
+        assert_eq!(incoming.len(), 3);
+    }
+
+    // Nothing else is assigned to more than once
+    let dests: Vec<_> = collatz
+        .blocks
+        .iter()
+        .flat_map(|x| x.insts.iter().filter_map(|x| x.dest))
+        .map(|x| x.0)
+        .collect();
+    let unique: HashSet<_> = dests.iter().collect();
+    assert_eq!(dests.len(), unique.len());
+    assert!(module.globals.is_empty());
+}
+
+#[test]
+fn matches_become_branches() {
+    let module = lower_files(&[(
+        "main.fn",
+        "func sign(n: int32): int32 {
+    return match n {
+        0 => 0
+        x if x < 0 => -1
+        _ => 1
+    }
+}
+
+let _one = sign(5)
+",
+    )]);
+    let sign = module.func("main.sign").unwrap();
+
+    // The literal and the guard are both tested, and the arms meet at a phi
+    let ops: Vec<&Op> = sign
+        .blocks
+        .iter()
+        .flat_map(|x| &x.insts)
+        .map(|x| &x.op)
+        .collect();
+    assert!(ops
+        .iter()
+        .any(|x| matches!(x, Op::Binary(ir::BinOp::Eq, ..))));
+    assert!(ops
+        .iter()
+        .any(|x| matches!(x, Op::Binary(ir::BinOp::Lt, ..))));
+    assert!(ops
+        .iter()
+        .any(|x| matches!(x, Op::Phi(incoming) if incoming.len() == 3)));
+}
+
+#[test]
+fn enums_are_only_interpreted() {
+    let read = |_: &Path| Ok("enum Light {\n    Red\n}\n\nlet _light = Light.Red\n".to_owned());
+    let reporter = Reporter::new(SourceMap::new(), false, ErrorFormat::Silent);
+    let graph = driver::compile(reporter, Path::new("main.fn"), &read).unwrap();
+
+    let err = driver::lower(&graph).unwrap_err();
+    assert_eq!(
+        err,
+        "Light can't be compiled yet, only interpreted (main.fn:5:5)"
+    );
+}
+
+#[test]
+fn text_round_trips() {
+    let module = lower_files(&[("main.fn", LOOPS)]);
+    let text = module.to_string();
+
+    let parsed = ir::parse(&text).unwrap();
+    assert_eq!(parsed, module);
+    assert_eq!(parsed.to_string(), text);
+    ir::verify(&parsed).unwrap();
+}
+
+#[test]
+fn shared_variables_become_globals() {
+    let module = lower_files(&[
+        (
+            "main.fn",
+            "mod util
+func outer(n: int32): int32 {
+    let seen = n
+    func inner(): int32 {
+        return seen + util.base
+    }
+    return inner()
+}
+let _x = outer(2)
+",
+        ),
+        ("util.fn", "pub let base = 10\n"),
+    ]);
+
+    let globals: Vec<String> = module.globals.iter().map(|x| x.to_string()).collect();
+    assert_eq!(
+        globals,
+        ["global @util.base: i32", "global @main.outer.seen: i32"]
+    );
+
+    // The old value is loaded first and stored back before returning
+    let text = module.func("main.outer").unwrap().to_string();
+    assert!(text.starts_with(
+        "func @main.outer(%0: i32): i32 {
+bb0:
+    line \"main.fn\" 2
+    %1: i32 = load @main.outer.seen
+    line \"main.fn\" 3
+    store @main.outer.seen, %0
+"
+    ));
+    assert!(text.contains("    store @main.outer.seen, %1\n    ret %2\n"));
+
+    // Dependencies run first
+    let main = module.func("main").unwrap().to_string();
+    assert!(main.find("@util.base").unwrap() < main.find("@main.outer").unwrap());
+}
+
+#[test]
+fn generics_get_a_function_for_each_instance() {
+    let module = lower_files(&[
+        (
+            "main.fn",
+            "mod util
+func sum(n: int32): int32 {
+    return util.twice(n) + util.twice(n > 0)
+}
+let _x = util.twice(2)
+",
+        ),
+        (
+            "util.fn",
+            "pub func twice<T: Eq>(a: T): int32 {
+    return same(a) + same(a)
+}
+func same<T: Eq>(a: T): int32 {
+    if a == a {
+        return 1
+    }
+    return 0
+}
+",
+        ),
+    ]);
+
+    // Both calls with an int32 share one instance, which is named after where it was declared
+    let names: Vec<&str> = module.funcs.iter().map(|x| x.name.as_str()).collect();
+    assert_eq!(
+        names,
+        [
+            "main",
+            "main.sum",
+            "util.twice.int32",
+            "util.same.int32",
+            "util.twice.bool",
+            "util.same.bool",
+        ]
+    );
+    let text = module.func("util.twice.bool").unwrap().to_string();
+    assert!(text.starts_with("func @util.twice.bool(%0: bool): i32 {"));
+    assert!(text.contains("call @util.same.bool(%0)"));
+
+    let mut machine = Machine::new(&module);
+    machine.call(ir::ENTRY, Vec::new()).unwrap();
+    assert_eq!(
+        machine.call("main.sum", vec![Val::Int(3)]),
+        Ok(Some(Val::Int(4)))
+    );
+}
+
+#[test]
+fn functions_in_generic_functions_are_only_interpreted() {
+    let src = "func outer<T>(a: T): T {
+    func inner(): int32 {
+        return 1
+    }
+    return a
+}
+
+let _x = outer(inner_value())
+
+func inner_value(): int32 {
+    return 2
+}
+";
+    let read = |_: &Path| Ok(src.to_owned());
+    let reporter = Reporter::new(SourceMap::new(), false, ErrorFormat::Silent);
+    let graph = driver::compile(reporter, Path::new("main.fn"), &read).unwrap();
+
+    let err = driver::lower(&graph).unwrap_err();
+    assert_eq!(
+        err,
+        "functions inside of generic functions can't be compiled yet, only interpreted (main.fn:2:5)"
+    );
+}
+
+#[test]
+fn parses_by_hand() {
+    let src = "
+global @total: u8 ; comments are fine
+
+func @pick(%0: bool, %1: f64): f64 {
+bb0:
+    line \"dir;1/main.fn\" 3 ; the first ; is part of the name
+    br %0, bb1, bb2
+bb1:
+    %2: f64 = const -1.5
+    jmp bb2
+bb2:
+    %3: f64 = phi [bb0: %1], [bb1: %2]
+    %4: func = func @pick
+    %5: f64 = call %4(%0, %3)
+    ret %3
+}
+";
+    let module = ir::parse(src).unwrap();
+    ir::verify(&module).unwrap();
+    assert_eq!(module.funcs[0].next_value, 6);
+    assert_eq!(ir::parse(&module.to_string()).unwrap(), module);
+}
+
+#[test]
+fn parse_errors() {
+    let cases = [
+        (
+            "func @f() {\nbb0:\n    %0: i7 = const 1\n    ret\n}",
+            "line 3: expected a type, got 'i7'",
+        ),
+        (
+            "func @f() {\nbb0:\n    frob %1\n    ret\n}",
+            "line 3: unknown instruction 'frob'",
+        ),
+        (
+            "func @f() {\nbb1:\n    ret\n}",
+            "line 2: expected the blocks in order, this should be bb0",
+        ),
+        (
+            "func @f() {\nbb0:\n    %0: i32 = const 1\n}",
+            "line 4: expected the block to end with a terminator",
+        ),
+        (
+            "func @f() {\nbb0:\n    ret\n",
+            "line 3: expected a '}' to end @f",
+        ),
+        ("global @x: bool extra", "line 1: unexpected 'extra'"),
+    ];
+    for (src, err) in cases {
+        assert_eq!(ir::parse(src).unwrap_err(), err, "{}", src);
+    }
+}
+
+#[test]
+fn verifier_errors() {
+    let func = |body: &str| format!("global @g: i32\n\nfunc @f(%0: i32): i32 {{\n{}}}\n", body);
+    let cases = [
+        (
+            "bb0:\n    %1: i32 = add %0, %2\n    %2: i32 = const 1\n    ret %1\n",
+            "@f bb0: %2 is used before it's defined",
+        ),
+        (
+            "bb0:\n    %1: bool = const true\n    %2: i32 = add %0, %1\n    ret %2\n",
+            "@f bb0: expected the right side to be i32, got bool",
+        ),
+        (
+            "bb0:\n    %1: i32 = lt %0, %0\n    ret %1\n",
+            "@f bb0: expected the result of lt to be bool, got i32",
+        ),
+        (
+            "bb0:\n    %1: bool = const true\n    %2: bool = shl %1, %1\n    ret %0\n",
+            "@f bb0: shl can't be used on bool",
+        ),
+        (
+            "bb0:\n    %1: i32 = const 2147483648\n    ret %1\n",
+            "@f bb0: const 2147483648 isn't a i32",
+        ),
+        (
+            "bb0:\n    %1: bool = eq %0, %0\n    br %1, bb1, bb2\nbb1:\n    %2: i32 = const 1\n    jmp bb2\nbb2:\n    ret %2\n",
+            "@f bb2: %2 is used before it's defined",
+        ),
+        (
+            "bb0:\n    jmp bb3\n",
+            "@f bb0: jumps to bb3, which doesn't exist",
+        ),
+        (
+            "bb0:\n    %1: bool = eq %0, %0\n    br %1, bb1, bb2\nbb1:\n    jmp bb2\nbb2:\n    %2: i32 = phi [bb0: %0]\n    ret %2\n",
+            "@f bb2: phi has no value for bb1",
+        ),
+        (
+            "bb0:\n    %1: i32 = call @missing()\n    ret %1\n",
+            "@f bb0: @missing isn't a function",
+        ),
+        (
+            "bb0:\n    %1: i32 = call @f(%0, %0)\n    ret %1\n",
+            "@f bb0: the arguments to @f have the wrong types",
+        ),
+        (
+            "bb0:\n    %1: bool = const false\n    store @g, %1\n    ret %0\n",
+            "@f bb0: expected @g to be i32, got bool",
+        ),
+        ("bb0:\n    ret\n", "@f bb0: returns nothing, but the function returns i32"),
+        (
+            "bb0:\n    jmp bb1\nbb1:\n    jmp bb0\n",
+            "@f bb0: the entry can't be jumped to",
+        ),
+        (
+            "bb0:\n    %0: i32 = const 1\n    ret %0\n",
+            "@f bb0: %0 is defined more than once",
+        ),
+    ];
+
+    for (body, err) in cases {
+        let module = ir::parse(&func(body)).unwrap();
+        assert_eq!(ir::verify(&module).unwrap_err(), err, "{}", body);
+    }
+
+    let twice = ir::parse("global @f: i32\nfunc @f() {\nbb0:\n    ret\n}\n").unwrap();
+    assert_eq!(
+        ir::verify(&twice).unwrap_err(),
+        "@f is defined more than once"
+    );
+}
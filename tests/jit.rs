@@ -0,0 +1,458 @@
+mod common;
+
+use std::{env, fs, process::Command, rc::Rc};
+
+use fusion::{
+    ast::Stmt,
+    backend::x86::Jit,
+    driver,
+    interpreter::{Func, Interpreter, Value},
+    ir::{self, BinOp, Machine, Type, UnOp, Val, MAX_DEPTH},
+    types::{FloatingSize, IntegralSize},
+};
+
+use common::{compile, lower, same, PROGRAM};
+
+// One function per operator the type allows, named after it
+fn arithmetic(typ: Type) -> ir::Module {
+    let mut src = String::new();
+    for op in BinOp::VALUES.into_iter().filter(|x| x.allows(typ)) {
+        let ret = if op.is_comparison() { Type::Bool } else { typ };
+        src += &format!(
+            "func @{op}(%0: {typ}, %1: {typ}): {ret} {{\nbb0:\n    %2: {ret} = {op} %0, %1\n    ret %2\n}}\n\n",
+        );
+    }
+    for op in UnOp::VALUES.into_iter().filter(|x| x.allows(typ)) {
+        src += &format!(
+            "func @{op}(%0: {typ}): {typ} {{\nbb0:\n    %1: {typ} = {op} %0\n    ret %1\n}}\n\n",
+        );
+    }
+    let module = ir::parse(&src).unwrap();
+    ir::verify(&module).unwrap();
+    module
+}
+
+// The edges of the type, and enough small ones for shifts and powers
+fn values(typ: Type) -> Vec<Val> {
+    let bits = typ.bits() as i128;
+    match typ {
+        Type::Int(_) => {
+            let max = i128::MAX >> (128 - bits);
+            let min = !max;
+            [min, min + 1, -7, -1, 0, 1, 2, 3, 7, bits - 1, bits, max]
+                .map(Val::Int)
+                .to_vec()
+        }
+        Type::Uint(_) => {
+            // The machine keeps uint128 in an i128 too
+            let max = (u128::MAX >> (128 - bits)).min(i128::MAX as u128) as i128;
+            [0, 1, 2, 3, 7, bits - 1, bits, max / 2 + 1, max - 1, max]
+                .map(Val::Int)
+                .to_vec()
+        }
+        Type::Float(_) => [
+            -0.0,
+            0.0,
+            1.0,
+            -1.5,
+            2.0,
+            0.1,
+            1e300,
+            f64::NEG_INFINITY,
+            f64::NAN,
+        ]
+        .map(Val::Float)
+        .to_vec(),
+        _ => [false, true].map(Val::Bool).to_vec(),
+    }
+}
+
+// Every operator on every size gives the same value or the same trap natively as in the
+// machine. Nothing 128 bits wide goes native, so those check the fallback
+#[test]
+fn runs_like_the_machine() {
+    let mut types = vec![Type::Bool];
+    for size in IntegralSize::VALUES {
+        types.extend([Type::Int(size), Type::Uint(size)]);
+    }
+    types.extend(FloatingSize::VALUES.map(Type::Float));
+
+    let mut traps = 0;
+    for typ in types {
+        let module = arithmetic(typ);
+        let mut jit = Jit::new(&module);
+        assert_eq!(jit.fallback().is_some(), typ.bits() == 128, "{}", typ);
+        let mut machine = Machine::new(&module);
+
+        let vals = values(typ);
+        for func in &module.funcs {
+            let cases: Vec<Vec<Val>> = match func.params.len() {
+                1 => vals.iter().map(|x| vec![x.clone()]).collect(),
+                _ => vals
+                    .iter()
+                    .flat_map(|x| vals.iter().map(move |y| vec![x.clone(), y.clone()]))
+                    .collect(),
+            };
+            for args in cases {
+                let expected = machine.call(&func.name, args.clone());
+                let got = jit.call(&func.name, args.clone());
+                assert!(
+                    same(&got, &expected),
+                    "{} on {}, {:?}: {:?} isn't {:?}",
+                    func.name,
+                    typ,
+                    args,
+                    got,
+                    expected
+                );
+                traps += expected.is_err() as usize;
+            }
+        }
+    }
+    assert!(traps > 500);
+}
+
+// Globals stay between calls, functions can be passed around and calls run out after as many
+// as they do in the machine
+#[test]
+fn keeps_state_like_the_machine() {
+    let src = "
+let total = 0
+
+func add(x: int32): int32 {
+    total += x
+    return total
+}
+
+func twice(x: int32): int32 {
+    return x * 2
+}
+
+func pick(doubled: bool, x: int32): int32 {
+    let f = add
+    if doubled {
+        f = twice
+    }
+    return f(x)
+}
+
+func down(n: int32): int32 {
+    if n == 0 {
+        return 0
+    }
+    return down(n - 1) + 1
+}
+
+func many(a: float32, b: float64, c: int8, d: uint16, e: int64, f: int32, g: int32, h: int32, i: float64, j: bool): float64 {
+    if j {
+        if a == a {
+            return b * i
+        }
+    }
+    return b
+}
+";
+    let module = lower(src);
+    let mut jit = Jit::new(&module);
+    assert_eq!(jit.fallback(), None);
+    let mut machine = Machine::new(&module);
+    jit.call(ir::ENTRY, Vec::new()).unwrap();
+    machine.call(ir::ENTRY, Vec::new()).unwrap();
+
+    let depth = MAX_DEPTH as i128;
+    let calls = vec![
+        ("main.add", vec![Val::Int(5)]),
+        ("main.add", vec![Val::Int(-2)]),
+        ("main.pick", vec![Val::Bool(true), Val::Int(7)]),
+        ("main.pick", vec![Val::Bool(false), Val::Int(7)]),
+        ("main.add", vec![Val::Int(0)]),
+        ("main.down", vec![Val::Int(3)]),
+        (
+            "main.many",
+            [
+                Val::Float(0.1),
+                Val::Float(3.0),
+                Val::Int(-1),
+                Val::Int(9),
+                Val::Int(1 << 40),
+                Val::Int(1),
+                Val::Int(2),
+                Val::Int(3),
+                Val::Float(-0.5),
+                Val::Bool(true),
+            ]
+            .to_vec(),
+        ),
+        ("main.missing", Vec::new()),
+    ];
+    for (name, args) in calls {
+        let got = jit.call(name, args.clone());
+        let expected = machine.call(name, args);
+        assert!(
+            same(&got, &expected),
+            "{}: {:?} isn't {:?}",
+            name,
+            got,
+            expected
+        );
+    }
+
+    // Too deep for the machine on a test thread's stack, so only the JIT
+    assert_eq!(
+        jit.call("main.down", vec![Val::Int(depth - 1)]),
+        Ok(Some(Val::Int(depth - 1)))
+    );
+    assert_eq!(
+        jit.call("main.down", vec![Val::Int(depth)]),
+        Err(format!("Recursed too deep, after {} calls", MAX_DEPTH))
+    );
+}
+
+// Native code would take whatever bits it's given, so calls that don't fit the function never
+// get to it
+#[test]
+fn mismatched_calls_are_errors() {
+    let src = "
+func @apply(%0: func, %1: i32): i32 {
+bb0:
+    %2: i32 = call %0(%1)
+    ret %2
+}
+
+func @id(%0: i32): i32 {
+bb0:
+    ret %0
+}
+
+func @byte(%0: u8): u8 {
+bb0:
+    ret %0
+}
+";
+    let module = ir::parse(src).unwrap();
+    let mut jit = Jit::new(&module);
+    assert_eq!(jit.fallback(), None);
+
+    let id = || Val::Func("id".to_owned());
+    assert_eq!(
+        jit.call("apply", vec![id(), Val::Int(4)]),
+        Ok(Some(Val::Int(4)))
+    );
+    for (args, err) in [
+        (Vec::new(), "@apply takes 2 arguments, but got 0"),
+        (
+            vec![id(), Val::Int(4), Val::Int(5)],
+            "@apply takes 2 arguments, but got 3",
+        ),
+        (
+            vec![id(), Val::Bool(true)],
+            "@apply can't take true as a i32",
+        ),
+        (
+            vec![Val::Int(0), Val::Int(4)],
+            "@apply can't take 0 as a func",
+        ),
+        (
+            vec![id(), Val::Int(1 << 31)],
+            "@apply can't take 2147483648 as a i32",
+        ),
+        (
+            vec![Val::Func("nope".to_owned()), Val::Int(4)],
+            "@apply can't take func @nope as a func",
+        ),
+    ] {
+        assert_eq!(jit.call("apply", args), Err(err.to_owned()));
+    }
+
+    // The machine would give back all of 300, the native code only its low byte
+    assert_eq!(
+        jit.call("byte", vec![Val::Int(255)]),
+        Ok(Some(Val::Int(255)))
+    );
+    assert_eq!(
+        jit.call("byte", vec![Val::Int(300)]),
+        Err("@byte can't take 300 as a u8".to_owned())
+    );
+}
+
+// The whole way down against the AST interpreter, which the REPL runs on. The arguments go
+// straight to each function, since the source can't write an int8 or a NaN
+#[test]
+fn runs_like_the_interpreter() {
+    let graph = compile(PROGRAM);
+    let prog = Rc::new(graph.root().prog.clone());
+    let mut interpreter = Interpreter::new();
+    interpreter.run(&prog).unwrap();
+    let module = driver::lower(&graph).unwrap();
+    let mut jit = Jit::new(&module);
+    assert_eq!(jit.fallback(), None);
+    jit.call(ir::ENTRY, Vec::new()).unwrap();
+
+    let func = |name: &str| {
+        let stmts = &prog.ast[prog.root].stmts;
+        let stmt = stmts.iter().copied().find(|&x| match &prog.ast[x] {
+            Stmt::Func(func) => prog.ast[func.name].name == name,
+            _ => false,
+        });
+        Func {
+            prog: Rc::clone(&prog),
+            stmt: stmt.unwrap(),
+            view: None,
+        }
+    };
+
+    let ints = [-128, -7, -1, 0, 1, 2, 7, 127].map(Val::Int);
+    let uints = [0, 1, 3, 7, 8, 200, 255].map(Val::Int);
+    let floats = [-1.5, 0.0, 0.1, 2.0, f64::NAN, f64::INFINITY].map(Val::Float);
+    let mut cases = Vec::new();
+    for x in &ints {
+        for name in ["square", "negate", "count"] {
+            cases.push((name, vec![x.clone()]));
+        }
+        for flag in [true, false] {
+            cases.push(("choose", vec![Val::Bool(flag), x.clone()]));
+        }
+        for y in &floats {
+            cases.push(("highest", vec![x.clone(), Val::Int(7), y.clone()]));
+        }
+        for y in &ints {
+            for name in ["wrap8", "signed", "divide", "power"] {
+                cases.push((name, vec![x.clone(), y.clone()]));
+            }
+        }
+    }
+    for x in &uints {
+        cases.push(("bits", vec![x.clone()]));
+        for y in &uints {
+            cases.push(("shifts", vec![x.clone(), y.clone()]));
+        }
+    }
+    for x in &floats {
+        cases.push(("small", vec![x.clone()]));
+        for y in &floats {
+            for name in ["floats", "compare"] {
+                cases.push((name, vec![x.clone(), y.clone()]));
+            }
+        }
+    }
+
+    let mut traps = 0;
+    for (name, args) in cases {
+        let expected = jit.call(&format!("main.{}", name), args.clone());
+        let vals = args
+            .iter()
+            .map(|x| match *x {
+                Val::Int(x) => Value::Int(x),
+                Val::Float(x) => Value::Float(x),
+                Val::Bool(x) => Value::Bool(x),
+                Val::Func(_) => unreachable!("functions aren't passed in"),
+            })
+            .collect();
+        let span = prog.ast.span(prog.root);
+        let got = match interpreter.call(&func(name), vals, span) {
+            Ok(Some(Value::Int(x))) => Ok(Some(Val::Int(x))),
+            Ok(Some(Value::Float(x))) => Ok(Some(Val::Float(x))),
+            Ok(Some(Value::Bool(x))) => Ok(Some(Val::Bool(x))),
+            Ok(val) => panic!("{} gave {:?}", name, val),
+            Err(trap) => Err(trap.msg),
+        };
+        assert!(
+            same(&got, &expected),
+            "{} {:?}: {:?} isn't {:?}",
+            name,
+            args,
+            got,
+            expected
+        );
+        traps += expected.is_err() as usize;
+    }
+    assert!(traps > 10);
+}
+
+fn fusion_run(args: &[&str]) -> (Option<i32>, String, String) {
+    let out = Command::new(env!("CARGO_BIN_EXE_fusion"))
+        .arg("run")
+        .args(args)
+        .output()
+        .unwrap();
+    let text = |x: Vec<u8>| String::from_utf8(x).unwrap();
+    (out.status.code(), text(out.stdout), text(out.stderr))
+}
+
+// fusion run goes through the JIT, or the machine for what it can't do
+#[test]
+fn run_command() {
+    let dir = env::temp_dir().join(format!("fusion-run-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("main.fn");
+    fs::write(
+        &path,
+        "let base = 1
+
+pub func fib(n: int32): int32 {
+    if n < 2 {
+        return n * base
+    }
+    return fib(n - 1) + fib(n - 2)
+}
+
+pub func wide(x: int128): int128 {
+    return x * x
+}
+
+pub func down(n: int32): int32 {
+    return down(n - 1)
+}
+
+pub func small(x: uint8): uint8 {
+    return x
+}
+
+pub func tiny(x: int8): int8 {
+    return x
+}
+",
+    )
+    .unwrap();
+    let path = path.to_str().unwrap();
+
+    let runs = [
+        (vec![path], (Some(0), "", "")),
+        (vec![path, "fib", "20"], (Some(0), "6765\n", "")),
+        (vec!["-O", path, "main.fib", "-3"], (Some(0), "-3\n", "")),
+        (
+            vec![path, "wide", "10000000000000"],
+            (Some(0), "100000000000000000000000000\n", ""),
+        ),
+        (
+            vec![path, "down", "1"],
+            (Some(1), "", "error: Recursed too deep, after 1000 calls\n"),
+        ),
+        (vec![path, "small", "255"], (Some(0), "255\n", "")),
+        (vec![path, "tiny", "-128"], (Some(0), "-128\n", "")),
+    ];
+    for (args, (code, stdout, stderr)) in runs {
+        assert_eq!(
+            fusion_run(&args),
+            (code, stdout.to_owned(), stderr.to_owned()),
+            "{:?}",
+            args
+        );
+    }
+
+    let (code, _, stderr) = fusion_run(&[path, "fib", "x"]);
+    assert_eq!(code, Some(2));
+    assert!(stderr.starts_with("x isn't a i32\n"), "{}", stderr);
+    for (func, val, typ) in [
+        ("small", "300", "u8"),
+        ("small", "-1", "u8"),
+        ("tiny", "128", "i8"),
+        ("tiny", "99999999999999999999999", "i8"),
+    ] {
+        let (code, _, stderr) = fusion_run(&[path, func, val]);
+        assert_eq!(code, Some(2));
+        let err = format!("{} isn't a {}\n", val, typ);
+        assert!(stderr.starts_with(&err), "{}", stderr);
+    }
+    fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,70 @@
+use std::{env, fs, process::Command};
+
+use fusion::json::Json;
+
+// Where a span is, as row:col:byte for both ends
+fn position(json: &Json) -> String {
+    let at = |key| {
+        let pos = json.get(key);
+        let num = |key| pos.get(key).as_u32().unwrap().to_string();
+        [num("row"), num("col"), num("byte")].join(":")
+    };
+    format!("{}-{}", at("start"), at("end"))
+}
+
+#[test]
+fn diagnostics_are_one_object_per_line() {
+    let dir = env::temp_dir().join(format!("fusion-json-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("main.fn");
+    fs::write(&path, "let count = 1\nlet _x: bool = 5\n").unwrap();
+    let file = path.to_str().unwrap();
+
+    let out = Command::new(env!("CARGO_BIN_EXE_fusion"))
+        .arg("--error-format=json")
+        .arg(&path)
+        .output()
+        .unwrap();
+    fs::remove_dir_all(&dir).unwrap();
+    assert_eq!(out.status.code(), Some(1));
+    assert!(out.stdout.is_empty());
+
+    let stderr = String::from_utf8(out.stderr).unwrap();
+    let errs: Vec<Json> = stderr.lines().map(|x| Json::parse(x).unwrap()).collect();
+    assert_eq!(errs.len(), 2, "{}", stderr);
+
+    let warning = &errs[0];
+    assert_eq!(warning.get("code").as_u32(), Some(13));
+    assert_eq!(warning.get("name").as_str(), Some("Lint"));
+    assert_eq!(warning.get("severity").as_str(), Some("warning"));
+    assert_eq!(warning.get("lint").as_str(), Some("unused_variables"));
+    assert_eq!(
+        warning.get("message").as_str(),
+        Some("The variable 'count' is never used")
+    );
+    assert_eq!(warning.get("file").as_str(), Some(file));
+    assert_eq!(position(warning), "1:5:4-1:10:9");
+    assert_eq!(warning.get("labels"), &Json::Array(Vec::new()));
+    let suggestions = warning.get("suggestions").as_array();
+    assert_eq!(suggestions.len(), 1);
+    assert_eq!(suggestions[0].get("replacement").as_str(), Some("_count"));
+    assert_eq!(suggestions[0].get("file").as_str(), Some(file));
+    assert_eq!(position(&suggestions[0]), "1:5:4-1:10:9");
+
+    let error = &errs[1];
+    assert_eq!(error.get("code").as_u32(), Some(6));
+    assert_eq!(error.get("name").as_str(), Some("TypeMismatch"));
+    assert_eq!(error.get("severity").as_str(), Some("error"));
+    assert_eq!(error.get("lint"), &Json::Null);
+    assert_eq!(position(error), "2:16:29-2:17:30");
+    let labels = error.get("labels").as_array();
+    assert_eq!(labels.len(), 1);
+    assert_eq!(
+        labels[0].get("message").as_str(),
+        Some("expected because of this annotation")
+    );
+    assert_eq!(labels[0].get("file").as_str(), Some(file));
+    assert_eq!(position(&labels[0]), "2:9:22-2:13:26");
+    assert_eq!(error.get("notes"), &Json::Array(Vec::new()));
+    assert_eq!(error.get("suggestions"), &Json::Array(Vec::new()));
+}
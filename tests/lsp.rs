@@ -0,0 +1,299 @@
+use std::{
+    fs,
+    io::BufReader,
+    path::{Path, PathBuf},
+    process::{Child, ChildStdin, ChildStdout, Command, Stdio},
+};
+
+use fusion::{json::Json, lsp};
+
+// Stands in for an editor, talking to the real binary over its stdin and stdout
+struct Client {
+    child: Child,
+    stdin: ChildStdin,
+    stdout: BufReader<ChildStdout>,
+    next_id: u32,
+}
+
+impl Client {
+    fn start() -> Client {
+        let mut child = Command::new(env!("CARGO_BIN_EXE_lsp"))
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .unwrap();
+
+        Client {
+            stdin: child.stdin.take().unwrap(),
+            stdout: BufReader::new(child.stdout.take().unwrap()),
+            child,
+            next_id: 0,
+        }
+    }
+
+    fn initialized() -> Client {
+        let mut client = Client::start();
+        client.request("initialize", Json::object(vec![]));
+        client.notify("initialized", Json::object(vec![]));
+        client
+    }
+
+    fn send(&mut self, msg: Vec<(&str, Json)>) {
+        let mut fields = vec![("jsonrpc", Json::str("2.0"))];
+        fields.extend(msg);
+        lsp::write_message(&mut self.stdin, &Json::object(fields)).unwrap();
+    }
+
+    fn recv(&mut self) -> Json {
+        let msg = lsp::read_message(&mut self.stdout).unwrap().unwrap();
+        Json::parse(&msg).unwrap()
+    }
+
+    // Notifications that come in before the response are skipped
+    fn request(&mut self, method: &str, params: Json) -> Json {
+        self.next_id += 1;
+        let id = Json::num(self.next_id);
+        self.send(vec![
+            ("id", id.clone()),
+            ("method", Json::str(method)),
+            ("params", params),
+        ]);
+
+        loop {
+            let msg = self.recv();
+            if *msg.get("id") == id {
+                return msg;
+            }
+        }
+    }
+
+    fn notify(&mut self, method: &str, params: Json) {
+        self.send(vec![("method", Json::str(method)), ("params", params)]);
+    }
+
+    fn diagnostics(&mut self, uri: &str) -> Vec<Json> {
+        loop {
+            let msg = self.recv();
+            if msg.get("method").as_str() == Some("textDocument/publishDiagnostics")
+                && msg.get("params").get("uri").as_str() == Some(uri)
+            {
+                return msg.get("params").get("diagnostics").as_array().to_vec();
+            }
+        }
+    }
+
+    fn open(&mut self, uri: &str, text: &str) -> Vec<Json> {
+        let doc = Json::object(vec![
+            ("uri", Json::str(uri)),
+            ("languageId", Json::str("fusion")),
+            ("version", Json::num(1)),
+            ("text", Json::str(text)),
+        ]);
+        self.notify(
+            "textDocument/didOpen",
+            Json::object(vec![("textDocument", doc)]),
+        );
+        self.diagnostics(uri)
+    }
+
+    fn change(&mut self, uri: &str, text: &str) -> Vec<Json> {
+        let change = Json::object(vec![("text", Json::str(text))]);
+        self.notify(
+            "textDocument/didChange",
+            Json::object(vec![
+                ("textDocument", Json::object(vec![("uri", Json::str(uri))])),
+                ("contentChanges", Json::Array(vec![change])),
+            ]),
+        );
+        self.diagnostics(uri)
+    }
+
+    fn at(&mut self, method: &str, uri: &str, line: u32, character: u32) -> Json {
+        let params = Json::object(vec![
+            ("textDocument", Json::object(vec![("uri", Json::str(uri))])),
+            (
+                "position",
+                Json::object(vec![
+                    ("line", Json::num(line)),
+                    ("character", Json::num(character)),
+                ]),
+            ),
+        ]);
+        self.request(method, params).get("result").clone()
+    }
+
+    fn exit(mut self) -> i32 {
+        self.notify("exit", Json::Null);
+        self.child.wait().unwrap().code().unwrap()
+    }
+}
+
+fn project(name: &str, files: &[(&str, &str)]) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("fusion-lsp-{}-{}", std::process::id(), name));
+    fs::create_dir_all(&dir).unwrap();
+    for (file, src) in files {
+        fs::write(dir.join(file), src).unwrap();
+    }
+    dir
+}
+
+fn uri(dir: &Path, file: &str) -> String {
+    lsp::path_to_uri(&dir.join(file).display().to_string())
+}
+
+fn position(pos: &Json) -> (u32, u32) {
+    (
+        pos.get("line").as_u32().unwrap(),
+        pos.get("character").as_u32().unwrap(),
+    )
+}
+
+fn start(range: &Json) -> (u32, u32) {
+    position(range.get("start"))
+}
+
+const MATH: &str = "pub func add(a: int32, b: int32): int32 {
+    return a + b
+}
+";
+
+const MAIN: &str = "mod math
+use math.add
+
+let total: int32 = add(1, 2)
+func twice(x: int32): int32 {
+    func one(): int32 {
+        return 1
+    }
+    return x * one()
+}
+let _doubled: int32 = twice(total)
+";
+
+#[test]
+fn lifecycle() {
+    let mut client = Client::start();
+    let res = client.request("textDocument/hover", Json::object(vec![]));
+    assert_eq!(res.get("error").get("code"), &Json::num(-32002));
+
+    let res = client.request("initialize", Json::object(vec![]));
+    let caps = res.get("result").get("capabilities");
+    assert_eq!(caps.get("hoverProvider"), &Json::Bool(true));
+    assert_eq!(caps.get("positionEncoding").as_str(), Some("utf-16"));
+
+    let res = client.request("workspace/symbol", Json::object(vec![]));
+    assert_eq!(res.get("error").get("code"), &Json::num(-32601));
+
+    let res = client.request("shutdown", Json::Null);
+    assert_eq!(res.get("result"), &Json::Null);
+    assert_eq!(client.exit(), 0);
+
+    // Exiting without shutting down first is an error
+    assert_eq!(Client::initialized().exit(), 1);
+}
+
+#[test]
+fn diagnostics_follow_edits() {
+    let dir = project("diagnostics", &[]);
+    let main = uri(&dir, "main.fn");
+    let mut client = Client::initialized();
+
+    let diags = client.open(&main, "let x: int32 = 1\nlet _y: bool = x\n");
+    assert_eq!(diags.len(), 1);
+    assert_eq!(diags[0].get("code").as_str(), Some("E0006"));
+    assert_eq!(diags[0].get("severity"), &Json::num(1));
+    assert_eq!(start(diags[0].get("range")), (1, 15));
+
+    let diags = client.change(&main, "let x: int32 = 1\nlet _y: int32 = x\n");
+    assert!(diags.is_empty());
+
+    // Lints come through as warnings
+    let diags = client.change(&main, "let x: int32 = 1\n\n\n\nlet _y: int32 = x\n");
+    assert_eq!(diags[0].get("severity"), &Json::num(2));
+    assert_eq!(diags[0].get("code").as_str(), Some("unneeded_newlines"));
+}
+
+#[test]
+fn hover_shows_each_part_of_a_type() {
+    let dir = project("annotations", &[]);
+    let main = uri(&dir, "main.fn");
+    let mut client = Client::initialized();
+    assert!(client
+        .open(&main, "let _x: (int32, bool)? = none\n")
+        .is_empty());
+
+    for (col, start_col, typ) in [(10, 9, "int32"), (17, 16, "bool"), (8, 8, "(int32, bool)")] {
+        let hover = client.at("textDocument/hover", &main, 0, col);
+        let value = hover.get("contents").get("value").as_str().unwrap();
+        assert!(value.contains(typ), "{}", value);
+        assert_eq!(start(hover.get("range")), (0, start_col));
+    }
+}
+
+#[test]
+fn navigation() {
+    let dir = project("navigation", &[("math.fn", MATH)]);
+    let main = uri(&dir, "main.fn");
+    let mut client = Client::initialized();
+    assert!(client.open(&main, MAIN).is_empty());
+
+    // The total in twice(total)
+    let hover = client.at("textDocument/hover", &main, 10, 29);
+    let value = hover.get("contents").get("value").as_str().unwrap();
+    assert!(value.contains("total: int32"), "{}", value);
+    assert_eq!(start(hover.get("range")), (10, 28));
+
+    let def = client.at("textDocument/definition", &main, 10, 29);
+    assert_eq!(def.get("uri").as_str(), Some(main.as_str()));
+    assert_eq!(start(def.get("range")), (3, 4));
+
+    // add(1, 2) goes through the use to the other module
+    let def = client.at("textDocument/definition", &main, 3, 20);
+    assert_eq!(def.get("uri").as_str(), Some(uri(&dir, "math.fn").as_str()));
+    assert_eq!(start(def.get("range")), (0, 9));
+
+    let params = Json::object(vec![(
+        "textDocument",
+        Json::object(vec![("uri", Json::str(&main))]),
+    )]);
+    let symbols = client.request("textDocument/documentSymbol", params);
+    let symbols = symbols.get("result").as_array();
+    assert_eq!(symbols.len(), 1);
+    assert_eq!(symbols[0].get("name").as_str(), Some("twice"));
+    assert_eq!(start(symbols[0].get("selectionRange")), (4, 5));
+    let children = symbols[0].get("children").as_array();
+    assert_eq!(children[0].get("name").as_str(), Some("one"));
+
+    // Inside of twice, before doubled is declared
+    let items = client.at("textDocument/completion", &main, 8, 4);
+    let labels: Vec<&str> = items
+        .as_array()
+        .iter()
+        .map(|x| x.get("label").as_str().unwrap())
+        .collect();
+    assert_eq!(labels, ["add", "one", "total", "twice", "x"]);
+
+    // A line that's still being typed keeps what was known before it
+    let diags = client.change(&main, &format!("{}let z = ", MAIN));
+    assert_eq!(diags[0].get("code").as_str(), Some("E0004"));
+    let hover = client.at("textDocument/hover", &main, 10, 29);
+    assert_eq!(start(hover.get("range")), (10, 28));
+}
+
+#[test]
+fn positions_are_utf16() {
+    let dir = project("utf16", &[]);
+    let main = uri(&dir, "main.fn");
+    let mut client = Client::initialized();
+
+    // The emoji is four bytes, one char and two UTF-16 code units
+    let diags = client.open(&main, "let 🎉: int32 = 1\nlet _y: int32 = 🎉 + z\n");
+    assert_eq!(diags.len(), 1);
+    assert_eq!(start(diags[0].get("range")), (1, 21));
+
+    client.change(&main, "let 🎉: int32 = 1\nlet _y: int32 = 🎉 + 🎉\n");
+    let hover = client.at("textDocument/hover", &main, 1, 21);
+    assert_eq!(start(hover.get("range")), (1, 21));
+    let def = client.at("textDocument/definition", &main, 1, 22);
+    assert_eq!(start(def.get("range")), (0, 4));
+}
@@ -0,0 +1,226 @@
+mod common;
+
+use fusion::{
+    driver,
+    ir::{self, Machine, Op, Val},
+};
+
+use common::lower;
+
+fn optimized(src: &str) -> (ir::Module, ir::Module) {
+    let module = lower(src);
+    let mut opt = module.clone();
+    driver::optimize(&mut opt, None);
+    (module, opt)
+}
+
+fn count(func: &ir::Function, what: fn(&Op) -> bool) -> usize {
+    func.blocks
+        .iter()
+        .flat_map(|x| &x.insts)
+        .filter(|x| what(&x.op))
+        .count()
+}
+
+const PROGRAM: &str = "
+let limit = 3
+
+func square(x: int32): int32 {
+    return x * x
+}
+
+func pick(flag: bool, a: int32, b: int32): int32 {
+    if flag {
+        return a
+    }
+    return b
+}
+
+func sum(n: int32): int32 {
+    let total = 0
+    let i = 0
+    while i < n {
+        let step = n * 2 + limit
+        total += square(i) % step
+        i += 1
+    }
+    return total
+}
+
+func divide(a: int32, b: int32): int32 {
+    let _unused = a * 7
+    let _trap = a / b
+    return pick(a > b, a, b) + (2 + 3) * 4
+}
+
+func shifts(x: uint8, n: uint8): uint8 {
+    let a = x << n
+    let b = x << n
+    let c = a ^| b
+    return c | (x >>> n) | (x ^ n)
+}
+
+func bits(x: int32): int32 {
+    let n = 0
+    do {
+        n += !x & 1
+        x = x >> 1
+    } while (x != 0) & (x != -1)
+    return n + x
+}
+
+func floats(x: float64, y: float64): bool {
+    let a = x / y
+    return (a != a) | (x - y == a * y)
+}
+
+func countdown(n: int32): int32 {
+    if n <= 0 {
+        return 0
+    }
+    return countdown(n - 1) + 1
+}
+";
+
+// Every function gets the same arguments before and after, and has to give the same value or
+// the same trap
+#[test]
+fn behaves_the_same() {
+    let (module, opt) = optimized(PROGRAM);
+    let ints: Vec<i128> = vec![-128, -7, -1, 0, 1, 2, 7, 127];
+    let uints: Vec<i128> = vec![0, 1, 3, 7, 8, 200, 255];
+    let floats: Vec<f64> = vec![-1.5, 0.0, -0.0, 2.0, f64::NAN];
+
+    let cases: Vec<(&str, Vec<Vec<Val>>)> = vec![
+        ("square", ints.iter().map(|&x| vec![Val::Int(x)]).collect()),
+        ("sum", ints.iter().map(|&x| vec![Val::Int(x)]).collect()),
+        (
+            "countdown",
+            ints.iter().map(|&x| vec![Val::Int(x)]).collect(),
+        ),
+        ("bits", ints.iter().map(|&x| vec![Val::Int(x)]).collect()),
+        (
+            "divide",
+            ints.iter()
+                .flat_map(|&x| ints.iter().map(move |&y| vec![Val::Int(x), Val::Int(y)]))
+                .collect(),
+        ),
+        (
+            "shifts",
+            uints
+                .iter()
+                .flat_map(|&x| uints.iter().map(move |&y| vec![Val::Int(x), Val::Int(y)]))
+                .collect(),
+        ),
+        (
+            "floats",
+            floats
+                .iter()
+                .flat_map(|&x| {
+                    floats
+                        .iter()
+                        .map(move |&y| vec![Val::Float(x), Val::Float(y)])
+                })
+                .collect(),
+        ),
+    ];
+
+    let mut traps = 0;
+    for (name, calls) in cases {
+        let name = format!("main.{}", name);
+        for args in calls {
+            let mut before = Machine::new(&module);
+            before.call(ir::ENTRY, Vec::new()).unwrap();
+            let mut after = Machine::new(&opt);
+            after.call(ir::ENTRY, Vec::new()).unwrap();
+
+            let expected = before.call(&name, args.clone());
+            let got = after.call(&name, args.clone());
+            assert_eq!(got, expected, "@{} {:?}", name, args);
+            traps += expected.is_err() as usize;
+        }
+    }
+    // Dividing by zero and shifting too far both have to stay
+    assert!(traps > 10);
+}
+
+#[test]
+fn passes_do_their_thing() {
+    let (module, opt) = optimized(PROGRAM);
+    let is_call = |x: &Op| matches!(x, Op::Call(..));
+    let is_binary = |x: &Op| matches!(x, Op::Binary(..));
+
+    // pick is small enough to inline, the recursive call stays
+    let divide = opt.func("main.divide").unwrap();
+    assert_eq!(count(divide, is_call), 0);
+    assert_eq!(count(opt.func("main.countdown").unwrap(), is_call), 1);
+
+    // The unused multiply goes, the division might trap so it stays. What's left is the
+    // comparison for pick, the division and adding the folded 20
+    assert_eq!(count(divide, is_binary), 3);
+    assert!(divide.to_string().contains("const 20"));
+
+    // Both shifts are the same
+    let shifts = opt.func("main.shifts").unwrap().to_string();
+    assert_eq!(shifts.matches("shl").count(), 1);
+
+    // limit is a global so it gets loaded every time round, but n * 2 moves out of the loop
+    let sum = opt.func("main.sum").unwrap();
+    let header = sum
+        .blocks
+        .iter()
+        .position(|x| x.insts.iter().any(|x| matches!(x.op, Op::Phi(_))));
+    let before_loop = &sum.blocks[..header.unwrap()];
+    assert!(before_loop
+        .iter()
+        .flat_map(|x| &x.insts)
+        .any(|x| matches!(x.op, Op::Binary(ir::BinOp::Mul, ..))));
+
+    let size = |module: &ir::Module| {
+        module
+            .funcs
+            .iter()
+            .map(|x| count(x, |_| true))
+            .sum::<usize>()
+    };
+    assert!(size(&opt) < size(&module));
+}
+
+#[test]
+fn traps_survive() {
+    let src = "
+let zero = 0
+let _x = 1 / zero
+let _y = 1 << 40
+";
+    let (module, opt) = optimized(src);
+    let before = Machine::new(&module).call(ir::ENTRY, Vec::new());
+    let after = Machine::new(&opt).call(ir::ENTRY, Vec::new());
+    assert_eq!(before, Err("Division by zero".to_owned()));
+    assert_eq!(after, before);
+}
+
+#[test]
+fn stats_and_dumps() {
+    let mut module = lower(PROGRAM);
+    let mut seen = Vec::new();
+    let stats = ir::optimize(&mut module, |pass, module| {
+        ir::verify(module).unwrap();
+        seen.push(pass.name);
+    });
+
+    assert_eq!(seen.len(), stats.rounds * ir::PASSES.len());
+    assert_eq!(&seen[..5], ["inline", "propagate", "cse", "licm", "dce"]);
+    for pass in &ir::PASSES {
+        assert!(stats.changes[pass.name] > 0, "{} did nothing", pass.name);
+    }
+
+    // Running it again finds nothing left to do
+    let again = ir::optimize(&mut module.clone(), |_, _| {});
+    assert_eq!(again.rounds, 1);
+    assert!(again.changes.values().all(|&x| x == 0), "{}", again);
+
+    let text = stats.to_string();
+    assert!(text.starts_with(&format!("{} rounds\n", stats.rounds)));
+    assert!(text.contains("    inline: "));
+}
@@ -0,0 +1,268 @@
+mod common;
+
+use common::{compile, parse, shape};
+use fusion::{
+    ast::{NodeKind, Stmt},
+    printer,
+};
+
+fn round_trip(src: &str) -> String {
+    let prog = parse(src);
+    let printed = printer::print(&prog);
+
+    let reparsed = parse(&printed);
+    assert_eq!(shape(&prog), shape(&reparsed), "printed as:\n{}", printed);
+    assert_eq!(printer::print(&reparsed), printed);
+
+    printed
+}
+
+#[test]
+fn every_statement_round_trips() {
+    round_trip(
+        "mod math
+pub mod util
+use math.add
+
+pub let  answer:int32=42
+let flag = true
+{
+let inner = 1
+}
+
+func  noop() {}
+pub func add(a:int32,b:int32):int32{
+    let total = a+b
+    total+=1
+    if total>10 {
+        return total
+    }
+    while flag {
+        continue
+    }
+    do {
+        total = total-1
+    } while total > 0
+    return total
+}
+add(1, 2)
+math.add(3,add(4, 5))
+",
+    );
+}
+
+#[test]
+fn enums_and_matches_round_trip() {
+    let printed = round_trip(
+        "enum Shape { Circle(float64), Rect(float64, float64), Empty }
+let pair = (1,true)
+let one = (1,)
+let n = match pair {
+    (0, _) => 0, (-1, true) => 1
+    (x, _) if x>2 => { f(x) }
+    _ => match one { (y,) => y }
+}
+",
+    );
+
+    assert_eq!(
+        printed,
+        "enum Shape {
+    Circle(float64)
+    Rect(float64, float64)
+    Empty
+}
+
+let pair = (1, true)
+let one = (1,)
+let n = match pair {
+    (0, _) => 0
+    (-1, true) => 1
+    (x, _) if x > 2 => {
+        f(x)
+    }
+    _ => match one {
+        (y,) => y
+    }
+}
+"
+    );
+}
+
+#[test]
+fn optionals_round_trip() {
+    let printed = round_trip(
+        "func f(p:(int32,bool)?):int32?{return p?.0}
+let x:int32? = none
+if let y=x { g(y) }
+let z = x??f((1, true))??(2,(3,4)).1.0",
+    );
+
+    assert_eq!(
+        printed,
+        "func f(p: (int32, bool)?): int32? {
+    return p?.0
+}
+
+let x: int32? = none
+if let y = x {
+    g(y)
+}
+let z = x ?? f((1, true)) ?? (2, (3, 4)).1.0
+"
+    );
+}
+
+#[test]
+fn generics_round_trip() {
+    let printed = round_trip(
+        "struct Pair<A,B>{first:A,second:B}
+func max<T:Ord+Num>(a:T,b:T):T{return a}
+let p:Pair<int32,Pair<bool,int32>> = Pair(1,Pair(true,2))
+let q = p.second.first",
+    );
+
+    assert_eq!(
+        printed,
+        "struct Pair<A, B> {
+    first: A
+    second: B
+}
+
+func max<T: Ord + Num>(a: T, b: T): T {
+    return a
+}
+
+let p: Pair<int32, Pair<bool, int32>> = Pair(1, Pair(true, 2))
+let q = p.second.first
+"
+    );
+}
+
+#[test]
+fn traits_round_trip() {
+    let printed = round_trip(
+        "pub trait Shape{func area(self):int32
+func grow(self,by:int32):Self}
+impl Shape for Square{func area(self:Self):int32{return self.side}
+func grow(self,by:int32):Square{return Square(by)}}
+impl Square{}
+func total(s:dyn Shape?):int32{return 0}",
+    );
+
+    assert_eq!(
+        printed,
+        "pub trait Shape {
+    func area(self): int32
+    func grow(self, by: int32): Self
+}
+
+impl Shape for Square {
+    func area(self): int32 {
+        return self.side
+    }
+
+    func grow(self, by: int32): Square {
+        return Square(by)
+    }
+}
+
+impl Square {}
+
+func total(s: dyn Shape?): int32 {
+    return 0
+}
+"
+    );
+}
+
+#[test]
+fn output_is_canonical() {
+    let printed = round_trip(
+        "pub let  x:int32=(1)
+func f(a:int32):int32{return a*2}
+let y=f( x )
+y-=1",
+    );
+
+    assert_eq!(
+        printed,
+        "pub let x: int32 = 1
+
+func f(a: int32): int32 {
+    return a * 2
+}
+
+let y = f(x)
+y -= 1
+"
+    );
+}
+
+#[test]
+fn parentheses_follow_precedence() {
+    let cases = [
+        ("let x = (a + b) * c", "let x = (a + b) * c\n"),
+        ("let x = a + (b * c)", "let x = a + b * c\n"),
+        ("let x = (a - b) - c", "let x = a - b - c\n"),
+        ("let x = a - (b - c)", "let x = a - (b - c)\n"),
+        ("let x = (a ^ b) ^ c", "let x = a ^ b ^ c\n"),
+        ("let x = -(a + b)", "let x = -(a + b)\n"),
+        ("let x = -(-a)", "let x = -(-a)\n"),
+        ("let x = -a * b", "let x = -a * b\n"),
+        ("let x = a == (b < c)", "let x = a == (b < c)\n"),
+        ("let x = (a & b) | c", "let x = a & b | c\n"),
+        ("let x = f(a + b)(c)", "let x = f(a + b)(c)\n"),
+    ];
+
+    for (src, expected) in cases {
+        assert_eq!(round_trip(src), expected);
+    }
+}
+
+#[test]
+fn renames_only_the_symbol_asked_for() {
+    let src = "func count(n: int32): int32 {
+    let total = n
+    total += 1
+    return total
+}
+func other(): int32 {
+    let total = 2
+    return total
+}
+let x = count(other())
+";
+    let mut graph = compile(src);
+    let prog = &mut graph.modules[0].prog;
+
+    // The first total is the one declared in count
+    let total = prog
+        .ast
+        .ids()
+        .find_map(|id| match prog.ast.kind(id) {
+            NodeKind::Stmt(Stmt::Decl(x)) => prog.names.get(x.name).copied(),
+            _ => None,
+        })
+        .unwrap();
+    let count = prog.scope().borrow().get("count").unwrap();
+    prog.rename(total, "sum");
+    prog.rename(count, "tally");
+
+    assert_eq!(
+        printer::print(prog),
+        "func tally(n: int32): int32 {
+    let sum = n
+    sum += 1
+    return sum
+}
+
+func other(): int32 {
+    let total = 2
+    return total
+}
+
+let x = tally(other())
+"
+    );
+}
@@ -0,0 +1,253 @@
+use std::{
+    io::Write,
+    process::{Command, Stdio},
+    thread,
+};
+
+use fusion::{interpreter, repl::Repl};
+
+// On a thread with room for the deepest recursion the interpreter allows
+fn session(inputs: &[&str]) -> Vec<String> {
+    thread::scope(|scope| {
+        thread::Builder::new()
+            .stack_size(interpreter::STACK_SIZE)
+            .spawn_scoped(scope, || {
+                let mut repl = Repl::new(false);
+                inputs.iter().map(|input| repl.eval(input)).collect()
+            })
+            .unwrap()
+            .join()
+            .unwrap()
+    })
+}
+
+#[test]
+fn values_are_printed_with_their_type() {
+    let out = session(&["1 + 2 * 3", "1 < 2", "!true", "-(2 ^ 4)", "7 / 2"]);
+    assert_eq!(
+        out,
+        [
+            "7: int32\n",
+            "true: bool\n",
+            "false: bool\n",
+            "-16: int32\n",
+            "3: int32\n"
+        ]
+    );
+}
+
+#[test]
+fn declarations_persist_between_inputs() {
+    let out = session(&[
+        "let x = 10",
+        "func double(n: int32): int32 {\n    return n * 2\n}",
+        "x += 1",
+        "double(x)",
+        "let total = 0\nwhile total < 100 {\n    total = total + double(x)\n}\ntotal",
+    ]);
+    assert_eq!(out, ["", "", "", "22: int32\n", "110: int32\n"]);
+}
+
+#[test]
+fn errors_dont_end_the_session() {
+    let out = session(&["let y: bool = 1", "y", "let y = 1 + true", "let y = 5", "y"]);
+    assert!(out[0].contains("E0006"), "{}", out[0]);
+    // Nothing from a failed input gets declared
+    assert!(out[1].contains("E0007"), "{}", out[1]);
+    assert!(out[2].contains("E0006"), "{}", out[2]);
+    assert_eq!(out[3], "");
+    assert_eq!(out[4], "5: int32\n");
+}
+
+#[test]
+fn runtime_errors_are_reported() {
+    let out = session(&[
+        "let zero = 0",
+        "1 / zero",
+        "func f(): int32 {\n    return f()\n}",
+        "f()",
+    ]);
+    assert!(out[1].starts_with("error: Division by zero"), "{}", out[1]);
+    assert!(
+        out[3].starts_with("error: Recursed too deep, after 1000 calls"),
+        "{}",
+        out[3]
+    );
+}
+
+#[test]
+fn recursion_is_limited_by_calls() {
+    let out = session(&[
+        "func down(n: int32): int32 {\n    if n == 0 {\n        return 0\n    }\n    return down(n - 1)\n}",
+        "down(999)",
+        "down(1000)",
+    ]);
+    assert_eq!(out[1], "0: int32\n");
+    assert!(out[2].starts_with("error: Recursed"), "{}", out[2]);
+}
+
+#[test]
+fn meta_commands() {
+    let out = session(&[
+        "let x = 1",
+        ":type x == 2",
+        ":type let y = 1",
+        ":tokens x+1",
+        ":ast -x",
+        ":type y",
+        ":nope",
+    ]);
+    assert_eq!(out[1], "bool\n");
+    assert_eq!(out[2], "Expected a single expression\n");
+    assert_eq!(
+        out[3],
+        "Identifier(\"x\") at #0..1\nAddition at #1..2\nNumber(1.0) at #2..3\n"
+    );
+    assert!(
+        out[4].contains("UnaryOp") && out[4].contains(": int32"),
+        "{}",
+        out[4]
+    );
+    // Inspecting an input doesn't run or declare it
+    assert!(out[5].contains("E0007"), "{}", out[5]);
+    assert!(out[6].starts_with("Unknown command :nope"));
+}
+
+#[test]
+fn enums_and_matches() {
+    let out = session(&[
+        "enum Shape {\n    Circle(int32)\n    Rect(int32, int32)\n    Empty\n}",
+        "func area(s: Shape): int32 {\n    match s {\n        Shape.Circle(r) => {\n            return 3 * r * r\n        }\n        Shape.Rect(w, h) if w == h => {\n            return w * w\n        }\n        _ => {}\n    }\n    return -1\n}",
+        "area(Shape.Circle(2))",
+        "area(Shape.Rect(3, 3))",
+        "area(Shape.Rect(2, 3))",
+        "Shape.Rect(2, 3)",
+        "Shape.Circle",
+        "match (area(Shape.Empty), true) {\n    (-1, false) => 0\n    (n, true) => n * 10\n    _ => 1\n}",
+        "(1,)",
+    ]);
+    assert_eq!(
+        out,
+        [
+            "",
+            "",
+            "12: int32\n",
+            "9: int32\n",
+            "-1: int32\n",
+            "Shape.Rect(2, 3): Shape\n",
+            "func Shape.Circle: func(int32): Shape\n",
+            "-10: int32\n",
+            "(1,): (int32,)\n",
+        ]
+    );
+}
+
+#[test]
+fn optionals_and_narrowing() {
+    let out = session(&[
+        "func half(n: int32): int32? {\n    if n % 2 != 0 {\n        return none\n    }\n    return n / 2\n}",
+        "half(3)",
+        "half(4) ?? 0",
+        "let x = half(6)\nlet total = 0\nif x != none {\n    total = x * 2\n}\nif let y = half(8) {\n    total += y\n}\ntotal",
+        "func or_zero(n: int32?): int32 {\n    if n == none {\n        return 0\n    }\n    return n\n}\nor_zero(half(5))",
+        "let pair: (int32, bool)? = none\npair?.1",
+        "x + 1",
+    ]);
+    assert_eq!(out[0], "");
+    assert_eq!(out[1], "none: int32?\n");
+    assert_eq!(out[2], "2: int32\n");
+    assert_eq!(out[3], "10: int32\n");
+    assert_eq!(out[4], "0: int32\n");
+    assert_eq!(out[5], "none: bool?\n");
+    // Nothing narrowed x out here
+    assert!(out[6].contains("E0006"), "{}", out[6]);
+}
+
+#[test]
+fn unclosed_brackets_continue_the_input() {
+    assert!(!Repl::is_complete("func f() {"));
+    assert!(!Repl::is_complete("let x = (1 +"));
+    assert!(Repl::is_complete("func f() {}"));
+    assert!(Repl::is_complete("let x = 1 // {"));
+}
+
+#[test]
+fn repl_command_reads_stdin() {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_fusion"))
+        .args(["repl", "--color=never"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+
+    let input = "func sum(n: int32): int32 {\n    if n == 0 {\n        return 0\n    }\n    return n + sum(n - 1)\n}\nsum(10)\n:quit\n1\n";
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(input.as_bytes())
+        .unwrap();
+    let out = child.wait_with_output().unwrap();
+
+    assert!(out.status.success());
+    assert_eq!(String::from_utf8(out.stdout).unwrap(), "55: int32\n");
+}
+
+#[test]
+fn generics_get_an_instance_per_type() {
+    let out = session(&[
+        "func max<T: Ord>(a: T, b: T): T {\n    if a > b {\n        return a\n    }\n    return b\n}",
+        "max(3, 7)",
+        "max(false, true)",
+        "struct Pair<A, B> {\n    first: A\n    second: B\n}",
+        "func swap<A, B>(pair: Pair<A, B>): Pair<B, A> {\n    return Pair(pair.second, pair.first)\n}\nswap(Pair(1, true))",
+        "swap(Pair(1, true)).second",
+        "func same<T>(a: T, b: T): bool {\n    return a == b\n}",
+        "max((1, 2), (3, 4))",
+    ]);
+    assert_eq!(out[0], "");
+    assert_eq!(out[1], "7: int32\n");
+    assert_eq!(out[2], "true: bool\n");
+    assert_eq!(out[4], "Pair(true, 1): Pair<bool, int32>\n");
+    assert_eq!(out[5], "1: int32\n");
+    assert!(out[6].contains("E0017"), "{}", out[6]);
+    // Tuples can be compared for equality, but not ordered
+    assert!(out[7].contains("E0017"), "{}", out[7]);
+}
+
+#[test]
+fn methods_are_found_through_the_type() {
+    let out = session(&[
+        "trait Shape {\n    func area(self): int32\n}",
+        "struct Square {\n    side: int32\n}\nstruct Rect {\n    w: int32\n    h: int32\n}",
+        "impl Shape for Square {\n    func area(self): int32 {\n        return self.side * self.side\n    }\n}",
+        "impl Shape for Rect {\n    func area(self): int32 {\n        return self.w * self.h\n    }\n}",
+        "impl Square {\n    func grow(self, by: int32): Self {\n        return Square(self.side + by)\n    }\n}",
+        "Square(2).grow(1).area()",
+        "func twice<T: Shape>(shape: T): int32 {\n    return shape.area() * 2\n}\ntwice(Rect(2, 3))",
+        "let shape: dyn Shape = Rect(4, 5)",
+        "shape.area()",
+        "shape = Square(3)\nshape",
+        "shape.area()",
+        "twice(true)",
+    ]);
+    assert_eq!(out[5], "9: int32\n");
+    assert_eq!(out[6], "12: int32\n");
+    assert_eq!(out[8], "20: int32\n");
+    assert_eq!(out[9], "Square(3): dyn Shape\n");
+    assert_eq!(out[10], "9: int32\n");
+    assert!(out[11].contains("E0017"), "{}", out[11]);
+}
+
+#[test]
+fn a_failed_impl_can_be_written_again() {
+    let out = session(&[
+        "trait Named {\n    func name(self): int32\n}",
+        "impl Named for bool {\n    func name(self): int32 {\n        return self\n    }\n}",
+        "impl Named for bool {\n    func name(self): int32 {\n        return 1\n    }\n}",
+        "true.name()",
+    ]);
+    assert!(out[1].contains("E0006"), "{}", out[1]);
+    assert_eq!(out[2], "");
+    assert_eq!(out[3], "1: int32\n");
+}
@@ -0,0 +1,321 @@
+// Snapshot tests over tests/ui/*.fn. Each file is lexed, parsed and checked, the tokens and the
+// AST end up in a .stdout file next to it and the diagnostics in a .stderr file. Diagnostics in
+// the file itself also need a comment on their line saying what they are:
+//
+//     let x: bool = 5 //~ ERROR TypeMismatch
+//     let y = 1 //~ WARNING unused_variables
+//
+// A ^ after the ~ points one line further up, for lines that can't hold a comment themselves.
+// Run `cargo test --test ui -- --bless` to write the snapshots instead of comparing them, any
+// other argument only runs the tests with it in their name
+use std::{
+    collections::HashSet,
+    env, fs, io,
+    path::{Path, PathBuf},
+    process,
+    rc::Rc,
+};
+
+use fusion::{
+    ast::{Expr, NodeKind, Stmt},
+    driver,
+    error::{Error, ErrorCode, ErrorFormat, Reporter, Severity},
+    lexer::Lexer,
+    read,
+    source::{FileId, SourceMap},
+    tokens::Type,
+};
+
+const UI_DIR: &str = "tests/ui";
+
+// Only these can't be triggered from source code
+const INTERNAL: [ErrorCode; 2] = [ErrorCode::Unknown, ErrorCode::IncorrectParsingType];
+
+// Every form the AST has, stmt_name and expr_name won't compile until a new one is added there
+const STMTS: [&str; 17] = [
+    "Block",
+    "Decl",
+    "Assign",
+    "IfStmt",
+    "IfLet",
+    "WhileLoop",
+    "DoWhileLoop",
+    "Func",
+    "Enum",
+    "Struct",
+    "Trait",
+    "Impl",
+    "Continue",
+    "Return",
+    "Module",
+    "Use",
+    "ExprStmt",
+];
+const EXPRS: [&str; 11] = [
+    "Ident", "NumLit", "BoolLit", "NoneLit", "BinaryOp", "UnaryOp", "Path", "Call", "Field",
+    "Tuple", "Match",
+];
+
+fn stmt_name(stmt: &Stmt) -> &'static str {
+    match stmt {
+        Stmt::Block(_) => "Block",
+        Stmt::Decl(_) => "Decl",
+        Stmt::Assign(_) => "Assign",
+        Stmt::IfStmt(_) => "IfStmt",
+        Stmt::IfLet(_) => "IfLet",
+        Stmt::WhileLoop(_) => "WhileLoop",
+        Stmt::DoWhileLoop(_) => "DoWhileLoop",
+        Stmt::Func(_) => "Func",
+        Stmt::Enum(_) => "Enum",
+        Stmt::Struct(_) => "Struct",
+        Stmt::Trait(_) => "Trait",
+        Stmt::Impl(_) => "Impl",
+        Stmt::Continue => "Continue",
+        Stmt::Return(_) => "Return",
+        Stmt::Module(_) => "Module",
+        Stmt::Use(_) => "Use",
+        Stmt::ExprStmt(_) => "ExprStmt",
+    }
+}
+
+fn expr_name(expr: &Expr) -> &'static str {
+    match expr {
+        Expr::Ident(_) => "Ident",
+        Expr::NumLit(_) => "NumLit",
+        Expr::BoolLit(_) => "BoolLit",
+        Expr::NoneLit => "NoneLit",
+        Expr::BinaryOp(_) => "BinaryOp",
+        Expr::UnaryOp(_) => "UnaryOp",
+        Expr::Path(_) => "Path",
+        Expr::Call(_) => "Call",
+        Expr::Field(_) => "Field",
+        Expr::Tuple(_) => "Tuple",
+        Expr::Match(_) => "Match",
+    }
+}
+
+// What a line says it should get, like ERROR TypeMismatch
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+struct Annotation {
+    row: u32,
+    kind: String,
+    name: String,
+}
+
+fn annotations(src: &str) -> Vec<Annotation> {
+    let mut found = Vec::new();
+    for (row, line) in src.lines().enumerate() {
+        let Some((_, rest)) = line.split_once("//~") else {
+            continue;
+        };
+
+        let up = rest.len() - rest.trim_start_matches('^').len();
+        let mut words = rest[up..].split_whitespace();
+        if let (Some(kind), Some(name)) = (words.next(), words.next()) {
+            found.push(Annotation {
+                row: (row - up) as u32,
+                kind: kind.to_owned(),
+                name: name.to_owned(),
+            });
+        }
+    }
+    found
+}
+
+fn annotate(reporter: &Reporter, err: &Error) -> Annotation {
+    let kind = match err.severity {
+        Severity::Error => "ERROR",
+        Severity::Warning => "WARNING",
+        Severity::Note => "NOTE",
+    };
+    let name = match err.lint {
+        Some(lint) => lint.name().to_owned(),
+        None => err.id.to_string(),
+    };
+
+    Annotation {
+        row: reporter.map.start(err.span).row,
+        kind: kind.to_owned(),
+        name,
+    }
+}
+
+struct Outcome {
+    stdout: String,
+    stderr: String,
+    codes: Vec<ErrorCode>,
+    forms: Vec<&'static str>,
+}
+
+fn compile(path: &Path, src: &str) -> Result<Outcome, String> {
+    let mut stdout = "tokens:\n".to_owned();
+    let tokens = Lexer::new(Rc::new(src.to_owned()), FileId(0)).lex();
+    for tok in tokens.iter().filter(|x| x.typ != Type::Whitespace) {
+        stdout += &format!("{}\n", tok);
+    }
+
+    let reporter = Reporter::new(SourceMap::new(), false, ErrorFormat::Silent);
+    let read = |path: &Path| -> io::Result<String> { read::read_file(path) };
+    let graph = driver::analyze(Rc::clone(&reporter), path, &read);
+
+    let mut forms = Vec::new();
+    if let Some(graph) = graph {
+        let prog = &graph.root().prog;
+        stdout += "ast:\n";
+        stdout += &prog.dump();
+
+        for id in prog.ast.ids() {
+            match prog.ast.kind(id) {
+                NodeKind::Stmt(stmt) => forms.push(stmt_name(stmt)),
+                NodeKind::Expr(expr) => forms.push(expr_name(expr)),
+                _ => (),
+            }
+        }
+    }
+
+    let mut stderr = String::new();
+    let mut actual = Vec::new();
+    let diagnostics = reporter.diagnostics();
+    for err in &diagnostics {
+        stderr += &format!("{}\n", reporter.render(err));
+        // The root is always the first file, diagnostics in its modules are their own tests
+        if err.span.file == FileId(0) {
+            actual.push(annotate(&reporter, err));
+        }
+    }
+
+    let mut expected = annotations(src);
+    expected.sort();
+    actual.sort();
+    if expected != actual {
+        let show = |list: &[Annotation]| -> String {
+            list.iter()
+                .map(|x| format!("    line {}: {} {}\n", x.row + 1, x.kind, x.name))
+                .collect()
+        };
+        return Err(format!(
+            "the annotations don't match the diagnostics\nexpected:\n{}got:\n{}",
+            show(&expected),
+            show(&actual)
+        ));
+    }
+
+    Ok(Outcome {
+        stdout,
+        stderr,
+        codes: diagnostics.iter().map(|x| x.id).collect(),
+        forms,
+    })
+}
+
+// Compares with the snapshot, or replaces it when blessing. An empty snapshot has no file
+fn snapshot(path: &Path, actual: &str, bless: bool) -> Result<(), String> {
+    if bless {
+        let res = match actual.is_empty() {
+            true if path.exists() => fs::remove_file(path),
+            true => Ok(()),
+            false => fs::write(path, actual),
+        };
+        return res.map_err(|err| format!("couldn't write {}: {}", path.display(), err));
+    }
+
+    let expected = fs::read_to_string(path).unwrap_or_default();
+    if expected == actual {
+        return Ok(());
+    }
+
+    let line = expected
+        .lines()
+        .zip(actual.lines())
+        .position(|(x, y)| x != y)
+        .unwrap_or_else(|| expected.lines().count().min(actual.lines().count()));
+    Err(format!(
+        "{} doesn't match, first difference on line {}\n- {}\n+ {}\nrun with --bless to update it",
+        path.display(),
+        line + 1,
+        expected.lines().nth(line).unwrap_or("<end of file>"),
+        actual.lines().nth(line).unwrap_or("<end of file>"),
+    ))
+}
+
+fn run(path: &Path, bless: bool) -> Result<Outcome, String> {
+    let src = fs::read_to_string(path).map_err(|err| err.to_string())?;
+    let outcome = compile(path, &src)?;
+    snapshot(&path.with_extension("stdout"), &outcome.stdout, bless)?;
+    snapshot(&path.with_extension("stderr"), &outcome.stderr, bless)?;
+    Ok(outcome)
+}
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let bless = args.iter().any(|x| x == "--bless");
+    let filters: Vec<&String> = args.iter().filter(|x| !x.starts_with('-')).collect();
+
+    let mut paths: Vec<PathBuf> = fs::read_dir(UI_DIR)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|x| x == "fn"))
+        .filter(|path| {
+            let name = path.display().to_string();
+            filters.is_empty() || filters.iter().any(|x| name.contains(x.as_str()))
+        })
+        .collect();
+    paths.sort();
+
+    println!("\nrunning {} ui tests", paths.len());
+    let mut failures = Vec::new();
+    let mut failed = 0;
+    let mut codes = Vec::new();
+    let mut forms = HashSet::new();
+    for path in &paths {
+        match run(path, bless) {
+            Ok(outcome) => {
+                println!("test {} ... ok", path.display());
+                codes.extend(outcome.codes);
+                forms.extend(outcome.forms);
+            }
+            Err(msg) => {
+                println!("test {} ... FAILED", path.display());
+                failed += 1;
+                failures.push(format!("---- {} ----\n{}\n", path.display(), msg));
+            }
+        }
+    }
+
+    // Coverage only means something with every test there
+    if filters.is_empty() {
+        let missing: Vec<String> = ErrorCode::VALUES
+            .iter()
+            .filter(|code| !INTERNAL.contains(code) && !codes.contains(code))
+            .map(|code| code.to_string())
+            .chain(
+                STMTS
+                    .iter()
+                    .chain(&EXPRS)
+                    .filter(|form| !forms.contains(*form))
+                    .map(|form| form.to_string()),
+            )
+            .collect();
+        if !missing.is_empty() {
+            failures.push(format!(
+                "---- coverage ----\nno ui test has {}\n",
+                missing.join(", ")
+            ));
+        }
+    }
+
+    for failure in &failures {
+        println!("\n{}", failure);
+    }
+    let result = if failures.is_empty() { "ok" } else { "FAILED" };
+    println!(
+        "\ntest result: {}. {} passed; {} failed\n",
+        result,
+        paths.len() - failed,
+        failures.len()
+    );
+
+    if !failures.is_empty() {
+        process::exit(1);
+    }
+}
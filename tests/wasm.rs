@@ -0,0 +1,242 @@
+mod common;
+
+use std::{
+    env, fs,
+    path::Path,
+    process::{Command, Stdio},
+};
+
+use fusion::{
+    backend::{self, wasm},
+    driver,
+    ir::{self, Machine, Type, Val},
+};
+
+use common::{lower, PROGRAM};
+
+#[test]
+fn emits_valid_modules() {
+    let module = lower(PROGRAM);
+    let mut opt = module.clone();
+    driver::optimize(&mut opt, None);
+    for module in [&module, &opt] {
+        let wasm = backend::emit_wasm(module).unwrap();
+        wasm::validate(&wasm.encode()).unwrap();
+    }
+
+    let wat = backend::emit_wasm(&module).unwrap().to_string();
+    for expected in [
+        "(import \"fusion\" \"fmod\" (func $fusion.fmod",
+        "(export \"main.square\" (func $main.square))",
+        "(elem (i32.const 0) func $main.square $main.negate)",
+        "    loop\n",
+        "call_indirect (type ",
+        "f32.mul\n",
+    ] {
+        assert!(wat.contains(expected), "no {:?} in\n{}", expected, wat);
+    }
+}
+
+#[test]
+fn int128_is_an_error() {
+    let src = "
+func wide(x: int128): int128 {
+    return x
+}
+";
+    let err = backend::emit_wasm(&lower(src)).unwrap_err();
+    assert_eq!(err, "@main.wide: i128 doesn't fit in any wasm type");
+}
+
+#[test]
+fn rejects_broken_modules() {
+    let bytes = backend::emit_wasm(&lower(PROGRAM)).unwrap().encode();
+    assert_eq!(
+        wasm::validate(b"\0elf\x01\0\0\0"),
+        Err("at byte 0x4: not a wasm module".to_owned())
+    );
+    assert!(wasm::validate(&bytes[..bytes.len() - 3]).is_err());
+
+    // Adding an i64 to an i32
+    let mut module = wasm::Module::default();
+    let typ = module.type_index(wasm::FuncType {
+        params: vec![wasm::ValType::I32],
+        results: vec![wasm::ValType::I32],
+    });
+    module.funcs.push(wasm::Func {
+        name: "bad".to_owned(),
+        typ,
+        locals: Vec::new(),
+        body: vec![
+            wasm::Instr::LocalGet(0),
+            wasm::Instr::I64Const(1),
+            wasm::Instr::Num(wasm::opcode("i32.add")),
+        ],
+        export: true,
+    });
+    let err = wasm::validate(&module.encode()).unwrap_err();
+    assert!(
+        err.ends_with("function 0: expected i32, got i64"),
+        "{}",
+        err
+    );
+
+    // Falling off the end without the result
+    module.funcs[0].body = vec![wasm::Instr::Block(None), wasm::Instr::End];
+    let err = wasm::validate(&module.encode()).unwrap_err();
+    assert!(
+        err.ends_with("expected i32 on the stack, but it's empty"),
+        "{}",
+        err
+    );
+}
+
+fn node() -> bool {
+    Command::new("node").arg("--version").output().is_ok()
+}
+
+fn literal(val: &Val, typ: &Type) -> String {
+    // i64s go over as BigInts
+    match val {
+        Val::Int(x) if typ.bits() == 64 => format!("{}n", x),
+        Val::Int(x) => x.to_string(),
+        Val::Float(x) if x.is_nan() => "NaN".to_owned(),
+        Val::Float(x) if x.is_infinite() => format!("{}Infinity", if *x < 0.0 { "-" } else { "" }),
+        Val::Float(x) => format!("{:?}", x),
+        Val::Bool(x) => (*x as u8).to_string(),
+        Val::Func(_) => unreachable!("functions aren't passed in"),
+    }
+}
+
+// Runs every case in node, one line of output each: the integer, the float's bits or trap.
+// NaNs don't keep their bits through JS so they're just nan
+fn run(bytes: &[u8], module: &ir::Module, cases: &[(&str, Vec<Val>)], dir: &Path) -> Vec<String> {
+    let path = dir.join("main.wasm");
+    fs::write(&path, bytes).unwrap();
+
+    let mut js = format!(
+        "const fs = require('fs');\n\
+         const imports = {{ fusion: {{ fmod: (x, y) => x % y, pow: Math.pow }} }};\n\
+         const wasm = new WebAssembly.Module(fs.readFileSync({:?}));\n\
+         const e = new WebAssembly.Instance(wasm, imports).exports;\n\
+         const bits = x => {{ const v = new DataView(new ArrayBuffer(8)); \
+         v.setFloat64(0, x); return 'f' + v.getBigUint64(0).toString(16); }};\n\
+         const show = (f, float) => {{ try {{ const r = f(); \
+         console.log(!float ? String(r) : Number.isNaN(r) ? 'nan' : bits(r)); }} catch (err) {{ console.log('trap'); }} }};\n\
+         e['main']();\n",
+        path.to_str().unwrap()
+    );
+    for (func, args) in cases {
+        let func = module.func(&format!("main.{}", func)).unwrap();
+        let args: Vec<String> = args
+            .iter()
+            .zip(&func.params)
+            .map(|(x, (_, typ))| literal(x, typ))
+            .collect();
+        js += &format!(
+            "show(() => e['{}']({}), {});\n",
+            func.name,
+            args.join(", "),
+            matches!(func.ret, Some(Type::Float(_)))
+        );
+    }
+
+    let out = Command::new("node")
+        .arg("-e")
+        .arg(js)
+        .stderr(Stdio::inherit())
+        .output()
+        .unwrap();
+    assert!(out.status.success());
+    String::from_utf8(out.stdout)
+        .unwrap()
+        .lines()
+        .map(str::to_owned)
+        .collect()
+}
+
+// What the interpreter's result looks like printed from JS, with unsigned values coming back
+// with their top bit as the sign
+fn show(val: &Result<Option<Val>, String>, typ: Type) -> String {
+    match val {
+        Err(_) => "trap".to_owned(),
+        Ok(Some(Val::Float(x))) if x.is_nan() => "nan".to_owned(),
+        Ok(Some(Val::Float(x))) => format!("f{:x}", x.to_bits()),
+        Ok(Some(Val::Bool(x))) => (*x as u8).to_string(),
+        Ok(Some(Val::Int(x))) => match typ {
+            Type::Uint(_) if typ.bits() == 32 => (*x as u32 as i32).to_string(),
+            Type::Uint(_) if typ.bits() == 64 => (*x as u64 as i64).to_string(),
+            _ => x.to_string(),
+        },
+        _ => unreachable!("every case gives a value"),
+    }
+}
+
+// Every call gives the same value, or traps, in node just like in the IR, before and after
+// optimizing
+#[test]
+fn runs_like_the_ir() {
+    if !node() {
+        eprintln!("no node, skipping");
+        return;
+    }
+
+    let ints: Vec<i128> = vec![-128, -7, -1, 0, 1, 2, 7, 127];
+    let uints: Vec<i128> = vec![0, 1, 3, 7, 8, 200, 255];
+    let floats: Vec<f64> = vec![-1.5, 0.0, 2.0, f64::NAN, f64::INFINITY];
+    let pairs = |xs: &[i128]| -> Vec<Vec<Val>> {
+        xs.iter()
+            .flat_map(|&x| xs.iter().map(move |&y| vec![Val::Int(x), Val::Int(y)]))
+            .collect()
+    };
+
+    let mut cases: Vec<(&str, Vec<Val>)> = Vec::new();
+    for &x in &ints {
+        cases.push(("square", vec![Val::Int(x)]));
+        cases.push(("count", vec![Val::Int(x)]));
+        cases.push(("bits", vec![Val::Int(x & 0xff)]));
+        for flag in [true, false] {
+            cases.push(("choose", vec![Val::Bool(flag), Val::Int(x)]));
+        }
+    }
+    cases.push(("bits", vec![Val::Int(0x8000_0000)]));
+    for name in ["wrap8", "signed", "divide", "power"] {
+        let mut ints = ints.clone();
+        if name == "power" {
+            ints.push(1 << 40);
+        }
+        cases.extend(pairs(&ints).into_iter().map(|x| (name, x)));
+    }
+    cases.extend(pairs(&uints).into_iter().map(|x| ("shifts", x)));
+    for &x in &floats {
+        cases.push(("small", vec![Val::Float(x)]));
+        for &y in &floats {
+            cases.push(("floats", vec![Val::Float(x), Val::Float(y)]));
+            cases.push(("compare", vec![Val::Float(x), Val::Float(y)]));
+        }
+    }
+
+    let dir = env::temp_dir().join(format!("fusion-wasm-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let module = lower(PROGRAM);
+    let mut opt = module.clone();
+    driver::optimize(&mut opt, None);
+
+    let mut traps = 0;
+    for module in [&module, &opt] {
+        let bytes = backend::emit_wasm(module).unwrap().encode();
+        let got = run(&bytes, module, &cases, &dir);
+        assert_eq!(got.len(), cases.len());
+        for ((func, args), got) in cases.iter().zip(got) {
+            let name = format!("main.{}", func);
+            let mut machine = Machine::new(module);
+            machine.call(ir::ENTRY, Vec::new()).unwrap();
+            let expected = machine.call(&name, args.clone());
+            let expected = show(&expected, module.func(&name).unwrap().ret.unwrap());
+            assert_eq!(got, expected, "{} {:?}", func, args);
+            traps += (expected == "trap") as usize;
+        }
+    }
+    fs::remove_dir_all(&dir).unwrap();
+    assert!(traps > 20);
+}
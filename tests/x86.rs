@@ -0,0 +1,326 @@
+mod common;
+
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use fusion::{
+    backend::{self, x86},
+    driver,
+    ir::{self, Machine, Type, Val},
+    types::{FloatingSize, IntegralSize},
+};
+
+use common::{cc, literal, lower, same, PROGRAM};
+
+// On top of the shared program, more arguments than fit in registers and more values than
+// registers
+const SPILLS: &str = "
+func many(a: int32, b: float64, c: int32, d: int32, e: float32, f: int32, g: int32, h: int32, i: int32, j: float64, k: bool): int32 {
+    if e != e {
+        return 0
+    }
+    if k {
+        if b < j {
+            return square(a + h) + c - d + f * g + i * limit
+        }
+    }
+    return a
+}
+
+func spill(x: int32): int32 {
+    let a = x + 1
+    let b = a * x
+    let c = b - a
+    let d = c * 3
+    let e = d + b
+    let f = e - x
+    let g = f * f
+    let h = g + c
+    let i = h - d
+    let j = i * a
+    let k = square(j)
+    return a + b - c + d - e + f - g + h - i + j - k
+}
+
+func fspill(x: float64, y: float64): float64 {
+    let a = x + y
+    let b = a * x
+    let c = b - y
+    let d = c / a
+    let e = d + b
+    let f = floats(e, c)
+    return a + b - c + d - e + f
+}
+";
+
+#[test]
+fn emits_gnu_assembly() {
+    let asm = backend::emit_asm(&lower(&[PROGRAM, SPILLS].concat())).unwrap();
+    for expected in [
+        "\t.globl f4main6square\n",
+        "f4main6square:\n",
+        "\tcall f4main6square\n",
+        "\tcall *",
+        "\tcall fmod@PLT\n",
+        "\tcvtss2sd %xmm0, %xmm0\n",
+        "\t# main.fn:5\n",
+        "\tmovq g4main5limit(%rip), ",
+        "\t.section .note.GNU-stack,\"\",@progbits\n",
+    ] {
+        assert!(asm.contains(expected), "no {:?} in\n{}", expected, asm);
+    }
+    assert_eq!(x86::symbol("main.square"), "f4main6square");
+}
+
+#[test]
+fn int128_is_an_error() {
+    let src = "
+func wide(x: int128): int128 {
+    return x
+}
+";
+    let err = backend::emit_asm(&lower(src)).unwrap_err();
+    assert_eq!(err, "@main.wide: i128 doesn't fit in a register");
+    assert!(backend::emit_object(&lower(src)).is_err());
+}
+
+fn c_type(typ: Type) -> &'static str {
+    match typ {
+        Type::Int(size) => match size {
+            IntegralSize::Int8 => "int8_t",
+            IntegralSize::Int16 => "int16_t",
+            IntegralSize::Int32 => "int32_t",
+            _ => "int64_t",
+        },
+        Type::Uint(size) => match size {
+            IntegralSize::Int8 => "uint8_t",
+            IntegralSize::Int16 => "uint16_t",
+            IntegralSize::Int32 => "uint32_t",
+            _ => "uint64_t",
+        },
+        Type::Float(FloatingSize::Float32) => "float",
+        Type::Float(_) => "double",
+        Type::Bool => "bool",
+        Type::Func => "void *",
+    }
+}
+
+// A main that makes one call, picked by the first argument, and prints what it gave
+fn harness(module: &ir::Module, cases: &[(&str, Vec<Val>)]) -> String {
+    let mut c = String::from("#include <stdbool.h>\n#include <stdint.h>\n#include <stdio.h>\n");
+    c += "#include <stdlib.h>\n#include <string.h>\n\n";
+    for func in &module.funcs {
+        let params: Vec<&str> = func.params.iter().map(|&(_, typ)| c_type(typ)).collect();
+        c += &format!(
+            "{} {}({});\n",
+            func.ret.map_or("void", c_type),
+            x86::symbol(&func.name),
+            match params.is_empty() {
+                true => "void".to_owned(),
+                false => params.join(", "),
+            }
+        );
+    }
+    c += "\nstatic double from_bits(uint64_t bits) {\n";
+    c += "    double x;\n    memcpy(&x, &bits, sizeof x);\n    return x;\n}\n\n";
+    c += &format!(
+        "int main(int argc, char **argv) {{\n    {}();\n",
+        x86::symbol(ir::ENTRY)
+    );
+    c += "    switch (atoi(argv[argc - 1])) {\n";
+    for (idx, (func, args)) in cases.iter().enumerate() {
+        let name = format!("main.{}", func);
+        let args: Vec<String> = args.iter().map(literal).collect();
+        let call = format!("{}({})", x86::symbol(&name), args.join(", "));
+        let print = match module.func(&name).unwrap().ret.unwrap() {
+            Type::Float(_) => format!(
+                "double r = {}; uint64_t bits; memcpy(&bits, &r, sizeof bits); \
+                 printf(\"f%016llx\\n\", (unsigned long long)bits);",
+                call
+            ),
+            Type::Bool => format!("printf(\"b%d\\n\", {});", call),
+            _ => format!(
+                "uint64_t r = (uint64_t)(int64_t){}; printf(\"i%016llx\\n\", (unsigned long long)r);",
+                call
+            ),
+        };
+        c += &format!("    case {}: {{ {} break; }}\n", idx, print);
+    }
+    c + "    }\n    return 0;\n}\n"
+}
+
+// Links the harness with the module, as assembly through cc or as our own object
+fn build(module: &ir::Module, harness: &str, dir: &Path, name: &str, object: bool) -> PathBuf {
+    let main = dir.join(format!("{}-main.c", name));
+    fs::write(&main, harness).unwrap();
+    let code = match object {
+        true => {
+            let path = dir.join(format!("{}.o", name));
+            fs::write(&path, backend::emit_object(module).unwrap()).unwrap();
+            path
+        }
+        false => {
+            let path = dir.join(format!("{}.s", name));
+            fs::write(&path, backend::emit_asm(module).unwrap()).unwrap();
+            path
+        }
+    };
+
+    let exe = dir.join(name);
+    let out = Command::new(cc().unwrap())
+        .args(["-std=c99", "-Wall", "-Werror", "-o"])
+        .arg(&exe)
+        .arg(&main)
+        .arg(&code)
+        .arg("-lm")
+        .output()
+        .unwrap();
+    assert!(
+        out.status.success(),
+        "{}",
+        String::from_utf8_lossy(&out.stderr)
+    );
+    exe
+}
+
+fn run(exe: &Path, case: usize, ret: Type) -> Result<Option<Val>, String> {
+    let out = Command::new(exe).arg(case.to_string()).output().unwrap();
+    if !out.status.success() {
+        let err = String::from_utf8(out.stderr).unwrap();
+        let msg = err.strip_prefix("error: ").unwrap();
+        return Err(msg[..msg.rfind(" (").unwrap()].to_owned());
+    }
+
+    let text = String::from_utf8(out.stdout).unwrap();
+    let (kind, val) = text.trim().split_at(1);
+    let bits = u64::from_str_radix(val, 16).unwrap();
+    Ok(Some(match kind {
+        "f" => Val::Float(f64::from_bits(bits)),
+        "b" => Val::Bool(val == "1"),
+        _ => match ret {
+            Type::Uint(_) => Val::Int(bits as i128),
+            _ => Val::Int(bits as i64 as i128),
+        },
+    }))
+}
+
+// Every call gives the same value or the same trap natively as it does in the IR, before and
+// after optimizing, and whether cc assembles it or we do
+#[test]
+fn runs_like_the_ir() {
+    if cc().is_none() {
+        eprintln!("no C compiler, skipping");
+        return;
+    }
+
+    let ints: Vec<i128> = vec![-128, -7, -1, 0, 1, 2, 7, 127];
+    let uints: Vec<i128> = vec![0, 1, 3, 7, 8, 200, 255];
+    let floats: Vec<f64> = vec![-1.5, 0.0, 2.0, f64::NAN, f64::INFINITY];
+    let pairs = |xs: &[i128]| -> Vec<Vec<Val>> {
+        xs.iter()
+            .flat_map(|&x| xs.iter().map(move |&y| vec![Val::Int(x), Val::Int(y)]))
+            .collect()
+    };
+
+    let mut cases: Vec<(&str, Vec<Val>)> = Vec::new();
+    for &x in &ints {
+        cases.push(("square", vec![Val::Int(x)]));
+        cases.push(("count", vec![Val::Int(x)]));
+        cases.push(("bits", vec![Val::Int(x & 0xff)]));
+        cases.push(("spill", vec![Val::Int(x)]));
+        for &y in &floats {
+            cases.push(("highest", vec![Val::Int(x), Val::Int(7), Val::Float(y)]));
+        }
+        for flag in [true, false] {
+            cases.push(("choose", vec![Val::Bool(flag), Val::Int(x)]));
+            let args = [x, 2, -3, 4, 5, 6, 7].map(Val::Int);
+            let mut args = args.to_vec();
+            args.insert(1, Val::Float(x as f64));
+            args.insert(4, Val::Float(0.5));
+            args.insert(9, Val::Float(1.0));
+            args.push(Val::Bool(flag));
+            cases.push(("many", args));
+        }
+    }
+    cases.push(("bits", vec![Val::Int(0x8000_0000)]));
+    for name in ["wrap8", "signed", "divide", "power"] {
+        let mut ints = ints.clone();
+        if name == "power" {
+            ints.push(1 << 40);
+        }
+        cases.extend(pairs(&ints).into_iter().map(|x| (name, x)));
+    }
+    cases.extend(pairs(&uints).into_iter().map(|x| ("shifts", x)));
+    for &x in &floats {
+        cases.push(("small", vec![Val::Float(x)]));
+        for &y in &floats {
+            cases.push(("floats", vec![Val::Float(x), Val::Float(y)]));
+            cases.push(("compare", vec![Val::Float(x), Val::Float(y)]));
+            cases.push(("fspill", vec![Val::Float(x), Val::Float(y)]));
+        }
+    }
+
+    let dir = env::temp_dir().join(format!("fusion-x86-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let module = lower(&[PROGRAM, SPILLS].concat());
+    let mut opt = module.clone();
+    driver::optimize(&mut opt, None);
+
+    let mut traps = 0;
+    for (module, name) in [(&module, "plain"), (&opt, "optimized")] {
+        let harness = harness(module, &cases);
+        for object in [false, true] {
+            let name = format!("{}-{}", name, if object { "obj" } else { "asm" });
+            let exe = build(module, &harness, &dir, &name, object);
+            for (idx, (func, args)) in cases.iter().enumerate() {
+                let name = format!("main.{}", func);
+                let mut machine = Machine::new(module);
+                machine.call(ir::ENTRY, Vec::new()).unwrap();
+                let expected = machine.call(&name, args.clone());
+                let got = run(&exe, idx, module.func(&name).unwrap().ret.unwrap());
+                assert!(
+                    same(&got, &expected),
+                    "{} {:?}: {:?} isn't {:?}",
+                    func,
+                    args,
+                    got,
+                    expected
+                );
+                traps += expected.is_err() as usize;
+            }
+        }
+    }
+    fs::remove_dir_all(&dir).unwrap();
+    assert!(traps > 40);
+}
+
+// Traps say where they happened, in the .fn file, and exit without libc
+#[test]
+fn traps_point_at_the_source() {
+    if cc().is_none() {
+        eprintln!("no C compiler, skipping");
+        return;
+    }
+
+    let src = "
+let zero = 0
+
+let _x = 1 / zero
+";
+    let dir = env::temp_dir().join(format!("fusion-x86-trap-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let module = lower(src);
+    let main = "void f4main(void);\n\nint main(void) {\n    f4main();\n    return 0;\n}\n";
+    let exe = build(&module, main, &dir, "trap", true);
+
+    let out = Command::new(&exe).output().unwrap();
+    fs::remove_dir_all(&dir).unwrap();
+    assert_eq!(out.status.code(), Some(1));
+    assert_eq!(
+        String::from_utf8(out.stderr).unwrap(),
+        "error: Division by zero (main.fn:4)\n"
+    );
+}